@@ -1,14 +1,22 @@
-use cosmwasm_bignumber::Uint256;
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    attr, to_binary, Addr, BankMsg, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, QueryRequest,
-    ReplyOn, Response, StdResult, SubMsg, Uint128, WasmMsg, WasmQuery,
+    attr, to_binary, Addr, BankMsg, CanonicalAddr, Coin, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, QueryRequest, ReplyOn, Response, StdResult, Storage, SubMsg, Uint128, WasmMsg,
+    WasmQuery,
 };
 
 use crate::contract::{CLAIM_REWARDS_OPERATION, SWAP_TO_STABLE_OPERATION};
 use crate::error::ContractError;
 use crate::external::handle::{RewardContractExecuteMsg, RewardContractQueryMsg};
-use crate::state::{read_config, BETHAccruedRewardsResponse, Config};
+use crate::state::{
+    read_borrower_info, read_config, read_holder_reward, read_holders,
+    read_last_distributed_height, read_reward_migration, read_reward_state,
+    remove_reward_migration, store_holder_reward, store_last_distributed_height,
+    store_reward_migration, store_reward_state, BETHAccruedRewardsResponse, BorrowerInfo, Config,
+    HolderReward, RewardMigration,
+};
 
+use moneymarket::custody::RewardStateResponse;
 use moneymarket::querier::{deduct_tax, query_all_balances, query_balance};
 use terra_cosmwasm::{create_swap_msg, TerraMsgWrapper};
 
@@ -33,6 +41,13 @@ pub fn distribute_rewards(
         return Err(ContractError::Unauthorized {});
     }
 
+    // Skip the distribution until the interval has elapsed
+    if env.block.height
+        < read_last_distributed_height(deps.storage) + config.reward_distribution_interval
+    {
+        return Ok(Response::default());
+    }
+
     let reward_contract = deps.api.addr_humanize(&config.reward_contract)?;
 
     let accrued_rewards =
@@ -41,6 +56,8 @@ pub fn distribute_rewards(
         return Ok(Response::default());
     }
 
+    store_last_distributed_height(deps.storage, env.block.height)?;
+
     // Do not emit the event logs here
     Ok(Response::new().add_submessage(SubMsg::reply_on_success(
         CosmosMsg::Wasm(WasmMsg::Execute {
@@ -52,7 +69,8 @@ pub fn distribute_rewards(
     )))
 }
 
-/// Apply swapped reward to global index
+/// Remit the protocol share of the swapped reward to the overseer
+/// and apply the rest to the global index
 /// Executor: itself
 pub fn distribute_hook(
     deps: DepsMut,
@@ -63,22 +81,31 @@ pub fn distribute_hook(
 
     let overseer_contract = deps.api.addr_humanize(&config.overseer_contract)?;
 
-    // reward_amount = (prev_balance + reward_amount) - prev_balance
-    // = (0 + reward_amount) - 0 = reward_amount = balance
-    let reward_amount: Uint256 = query_balance(
-        deps.as_ref(),
-        contract_addr,
-        config.stable_denom.to_string(),
-    )?;
+    let distribution_denom = distribution_denom(&config);
+
+    // reward_amount = (prev_balance + reward_amount) - prev_balance,
+    // where prev_balance is the holder rewards not claimed yet
+    let mut reward_state = read_reward_state(deps.storage);
+    let balance: Uint256 =
+        query_balance(deps.as_ref(), contract_addr, distribution_denom.to_string())?;
+    let reward_amount = if balance > reward_state.unclaimed_rewards {
+        balance - reward_state.unclaimed_rewards
+    } else {
+        Uint256::zero()
+    };
+
+    let remitted_amount = reward_state.distribute(reward_amount, config.protocol_reward_share);
+    store_reward_state(deps.storage, &reward_state)?;
+
     let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
-    if !reward_amount.is_zero() {
+    if !remitted_amount.is_zero() {
         messages.push(CosmosMsg::Bank(BankMsg::Send {
             to_address: overseer_contract.to_string(),
             amount: vec![deduct_tax(
                 deps.as_ref(),
                 Coin {
-                    denom: config.stable_denom,
-                    amount: reward_amount.into(),
+                    denom: distribution_denom,
+                    amount: remitted_amount.into(),
                 },
             )?],
         }));
@@ -86,10 +113,197 @@ pub fn distribute_hook(
 
     Ok(Response::new().add_messages(messages).add_attributes(vec![
         attr("action", "distribute_rewards"),
-        attr("buffer_rewards", reward_amount),
+        attr("buffer_rewards", remitted_amount),
+        attr("holder_rewards", reward_amount - remitted_amount),
+    ]))
+}
+
+/// Send the holder rewards accrued on the collateral of the borrower
+/// Executor: borrower
+pub fn claim_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    claim_holder_rewards(
+        deps,
+        env.block.height,
+        &config,
+        info.sender.clone(),
+        info.sender,
+    )
+}
+
+/// Send the holder rewards of the borrower to the overseer, or to the
+/// borrower when they are not paid in the stable denom
+/// Executor: overseer
+pub fn claim_rewards_for(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if config.overseer_contract != deps.api.addr_canonicalize(info.sender.as_str())? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let recipient = if distribution_denom(&config) == config.stable_denom {
+        info.sender
+    } else {
+        borrower.clone()
+    };
+    claim_holder_rewards(deps, env.block.height, &config, borrower, recipient)
+}
+
+fn claim_holder_rewards(
+    deps: DepsMut,
+    height: u64,
+    config: &Config,
+    borrower: Addr,
+    recipient: Addr,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let borrower_info = read_borrower_info(deps.storage, &borrower_raw);
+    let mut holder_reward =
+        settle_holder_reward(deps.storage, height, &borrower_raw, &borrower_info)?;
+
+    // The fraction below one unit stays pending
+    let claim_amount = holder_reward.claimable();
+    holder_reward.pending_rewards = holder_reward.pending_rewards
+        - Decimal256::from_uint256(claim_amount - holder_reward.claimable_rewards);
+    holder_reward.claimable_rewards = Uint256::zero();
+    store_holder_reward(deps.storage, &borrower_raw, &holder_reward)?;
+
+    let mut reward_state = read_reward_state(deps.storage);
+    reward_state.unclaimed_rewards = reward_state.unclaimed_rewards - claim_amount;
+    store_reward_state(deps.storage, &reward_state)?;
+
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    if !claim_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: distribution_denom(config),
+                    amount: claim_amount.into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "claim_rewards"),
+        attr("borrower", borrower),
+        attr("amount", claim_amount),
+    ]))
+}
+
+/// Accrue the holder rewards of the borrower on its recorded weight, then
+/// weigh its balance by its current boost. Called after every change to
+/// the balance or the locked amount of the borrower
+pub(crate) fn settle_holder_reward(
+    storage: &mut dyn Storage,
+    height: u64,
+    borrower: &CanonicalAddr,
+    borrower_info: &BorrowerInfo,
+) -> StdResult<HolderReward> {
+    let config: Config = read_config(storage)?;
+    let mut reward_state = read_reward_state(storage);
+    let mut holder_reward = read_holder_reward(storage, borrower);
+    holder_reward.settle(reward_state.global_index);
+
+    // The boost starts over once nothing is locked anymore
+    if borrower_info.spendable == borrower_info.balance {
+        holder_reward.lock_start_height = None;
+    } else if holder_reward.lock_start_height.is_none() {
+        holder_reward.lock_start_height = Some(height);
+    }
+
+    let weight = borrower_info.balance * holder_reward.boost(config.reward_boost.as_ref(), height);
+    reward_state.total_weight = reward_state.total_weight + weight - holder_reward.weight;
+    holder_reward.weight = weight;
+    store_reward_state(storage, &reward_state)?;
+    store_holder_reward(storage, borrower, &holder_reward)?;
+    Ok(holder_reward)
+}
+
+/// Convert the rewards accrued by the next `limit` holders to claimable
+/// balances. The first call restarts the global index from zero, the
+/// holders not converted yet keep referring to the previous one
+/// Executor: owner
+pub fn migrate_reward_indexes(
+    deps: DepsMut,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut reward_migration = match read_reward_migration(deps.storage) {
+        Some(reward_migration) => reward_migration,
+        None => {
+            let mut reward_state = read_reward_state(deps.storage);
+            let reward_migration = RewardMigration {
+                legacy_index: reward_state.global_index,
+                last_holder: None,
+            };
+            reward_state.global_index = Decimal256::zero();
+            reward_state.index_epoch += 1;
+            store_reward_state(deps.storage, &reward_state)?;
+            store_reward_migration(deps.storage, &reward_migration)?;
+            reward_migration
+        }
+    };
+
+    let holders = read_holders(deps.storage, reward_migration.last_holder.clone(), limit)?;
+    for holder in holders.iter() {
+        let holder_reward = read_holder_reward(deps.storage, holder);
+        store_holder_reward(deps.storage, holder, &holder_reward)?;
+    }
+
+    if let Some(last_holder) = holders.last() {
+        reward_migration.last_holder = Some(last_holder.clone());
+    }
+    let completed =
+        read_holders(deps.storage, reward_migration.last_holder.clone(), Some(1))?.is_empty();
+    if completed {
+        remove_reward_migration(deps.storage);
+    } else {
+        store_reward_migration(deps.storage, &reward_migration)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "migrate_reward_indexes"),
+        attr("migrated_holders", holders.len().to_string()),
+        attr("completed", completed.to_string()),
     ]))
 }
 
+pub fn query_reward_state(deps: Deps) -> StdResult<RewardStateResponse> {
+    let reward_state = read_reward_state(deps.storage);
+    Ok(RewardStateResponse {
+        global_index: reward_state.global_index,
+        index_epoch: reward_state.index_epoch,
+        total_weight: reward_state.total_weight,
+        unclaimed_rewards: reward_state.unclaimed_rewards,
+        total_remitted: reward_state.total_remitted,
+    })
+}
+
+/// Unswapped rewards are distributed as they were paid
+fn distribution_denom(config: &Config) -> String {
+    if config.swap_rewards {
+        config.stable_denom.to_string()
+    } else {
+        config.reward_denom.to_string()
+    }
+}
+
 /// Swap all coins to stable_denom
 /// and execute `swap_hook`
 /// Executor: itself
@@ -12,18 +12,36 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("Convert amount cannot exceed locked amount: {0}")]
+    ConvertAmountExceedsLocked(u128),
+
+    #[error("Release amount cannot exceed locked amount: {0}")]
+    ReleaseAmountExceedsLocked(u128),
+
     #[error("Liquidation amount cannot exceed locked amount: {0}")]
     LiquidationAmountExceedsLocked(u128),
 
     #[error("Lock amount cannot excceed the user's spendable amount: {0}")]
     LockAmountExceedsSpendable(u128),
 
+    #[error("Cannot sweep tracked asset: {0}")]
+    SweepTrackedAsset(String),
+
+    #[error("Protocol reward share cannot exceed one")]
+    InvalidProtocolRewardShare {},
+
+    #[error("Max reward boost cannot be less than one")]
+    InvalidRewardBoost {},
+
     #[error("Invalid reply ID")]
     InvalidReplyId {},
 
     #[error("Invalid request: \"deposit collateral\" message not included in request")]
     MissingDepositCollateralHook {},
 
+    #[error("Transfer amount cannot exceed locked amount: {0}")]
+    TransferAmountExceedsLocked(u128),
+
     #[error("Unlock amount cannot exceed locked amount: {0}")]
     UnlockAmountExceedsLocked(u128),
 
@@ -1,10 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_bignumber::Uint256;
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{CanonicalAddr, Deps, Order, StdResult, Storage, Uint128};
 use cosmwasm_storage::{Bucket, ReadonlyBucket, ReadonlySingleton, Singleton};
-use moneymarket::custody::{BAssetInfo, BorrowerResponse};
+use moneymarket::custody::{BAssetInfo, BorrowerResponse, RewardBoost};
 
 //BETHAccruedRewardsResponse the struct that shows the result of accrued_rewards query
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
@@ -13,7 +13,13 @@ pub struct BETHAccruedRewardsResponse {
 }
 
 const KEY_CONFIG: &[u8] = b"config";
+const KEY_TOTAL_BALANCE: &[u8] = b"total_balance";
+const KEY_LAST_DISTRIBUTED_HEIGHT: &[u8] = b"last_distributed_height";
+const KEY_RECONCILIATION: &[u8] = b"reconciliation";
+const KEY_REWARD_STATE: &[u8] = b"reward_state";
+const KEY_REWARD_MIGRATION: &[u8] = b"reward_migration";
 const PREFIX_BORROWER: &[u8] = b"borrower";
+const PREFIX_HOLDER_REWARD: &[u8] = b"holder_reward";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
@@ -25,12 +31,37 @@ pub struct Config {
     pub liquidation_contract: CanonicalAddr,
     pub stable_denom: String,
     pub basset_info: BAssetInfo,
+    pub rebasing: bool,
+    pub reward_denom: String,
+    pub swap_rewards: bool,
+    pub reward_distribution_interval: u64,
+    pub protocol_reward_share: Decimal256,
+    pub reward_boost: Option<RewardBoost>,
 }
 
+/// Config layout before the reward settings were added,
+/// only used to migrate the stored config
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct BorrowerInfo {
-    pub balance: Uint256,
-    pub spendable: Uint256,
+pub struct LegacyConfig {
+    pub owner: CanonicalAddr,
+    pub collateral_token: CanonicalAddr,
+    pub overseer_contract: CanonicalAddr,
+    pub market_contract: CanonicalAddr,
+    pub reward_contract: CanonicalAddr,
+    pub liquidation_contract: CanonicalAddr,
+    pub stable_denom: String,
+    pub basset_info: BAssetInfo,
+}
+
+pub use moneymarket::custody::{BorrowerInfo, HolderReward, Reconciliation, RewardState};
+
+/// Progress of a restart of the reward indexes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardMigration {
+    /// Global index of the previous epoch the unconverted holders refer to
+    pub legacy_index: Decimal256,
+    /// Last converted holder, the conversion goes in key order
+    pub last_holder: Option<CanonicalAddr>,
 }
 
 pub fn store_config(storage: &mut dyn Storage, data: &Config) -> StdResult<()> {
@@ -41,6 +72,119 @@ pub fn read_config(storage: &dyn Storage) -> StdResult<Config> {
     ReadonlySingleton::new(storage, KEY_CONFIG).load()
 }
 
+pub fn read_legacy_config(storage: &dyn Storage) -> StdResult<LegacyConfig> {
+    ReadonlySingleton::new(storage, KEY_CONFIG).load()
+}
+
+pub fn store_total_balance(storage: &mut dyn Storage, total_balance: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_TOTAL_BALANCE).save(total_balance)
+}
+
+/// Sum of all borrower balances, zero until the first deposit
+pub fn read_total_balance(storage: &dyn Storage) -> Uint256 {
+    ReadonlySingleton::new(storage, KEY_TOTAL_BALANCE)
+        .load()
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+pub fn store_last_distributed_height(storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+    Singleton::new(storage, KEY_LAST_DISTRIBUTED_HEIGHT).save(&height)
+}
+
+/// Height of the last reward claim, zero until the first distribution
+pub fn read_last_distributed_height(storage: &dyn Storage) -> u64 {
+    ReadonlySingleton::new(storage, KEY_LAST_DISTRIBUTED_HEIGHT)
+        .load()
+        .unwrap_or(0)
+}
+
+pub fn store_reconciliation(
+    storage: &mut dyn Storage,
+    reconciliation: &Reconciliation,
+) -> StdResult<()> {
+    Singleton::new(storage, KEY_RECONCILIATION).save(reconciliation)
+}
+
+/// Last reconciliation, all zero until the first one
+pub fn read_reconciliation(storage: &dyn Storage) -> Reconciliation {
+    ReadonlySingleton::new(storage, KEY_RECONCILIATION)
+        .load()
+        .unwrap_or_default()
+}
+
+pub fn store_reward_state(storage: &mut dyn Storage, reward_state: &RewardState) -> StdResult<()> {
+    Singleton::new(storage, KEY_REWARD_STATE).save(reward_state)
+}
+
+/// Holder reward accounting, all zero until the first distribution
+pub fn read_reward_state(storage: &dyn Storage) -> RewardState {
+    ReadonlySingleton::new(storage, KEY_REWARD_STATE)
+        .load()
+        .unwrap_or_default()
+}
+
+pub fn store_holder_reward(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    holder_reward: &HolderReward,
+) -> StdResult<()> {
+    Bucket::new(storage, PREFIX_HOLDER_REWARD).save(borrower.as_slice(), holder_reward)
+}
+
+/// Kept when the borrower balance is removed, until the rewards are claimed.
+/// Holders the running index migration has not reached yet are converted
+/// on read, so they settle against the current epoch
+pub fn read_holder_reward(storage: &dyn Storage, borrower: &CanonicalAddr) -> HolderReward {
+    let mut holder_reward: HolderReward = ReadonlyBucket::new(storage, PREFIX_HOLDER_REWARD)
+        .load(borrower.as_slice())
+        .unwrap_or_default();
+
+    let index_epoch = read_reward_state(storage).index_epoch;
+    if holder_reward.index_epoch < index_epoch {
+        let legacy_index = read_reward_migration(storage)
+            .map_or_else(Decimal256::zero, |migration| migration.legacy_index);
+        holder_reward.reset_index(legacy_index, index_epoch);
+    }
+    holder_reward
+}
+
+/// Holders with a reward record, in key order
+pub fn read_holders(
+    storage: &dyn Storage,
+    start_after: Option<CanonicalAddr>,
+    limit: Option<u32>,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let holder_bucket: ReadonlyBucket<HolderReward> =
+        ReadonlyBucket::new(storage, PREFIX_HOLDER_REWARD);
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = calc_range_start(start_after);
+
+    holder_bucket
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| Ok(CanonicalAddr::from(item?.0)))
+        .collect()
+}
+
+pub fn store_reward_migration(
+    storage: &mut dyn Storage,
+    reward_migration: &RewardMigration,
+) -> StdResult<()> {
+    Singleton::new(storage, KEY_REWARD_MIGRATION).save(reward_migration)
+}
+
+pub fn remove_reward_migration(storage: &mut dyn Storage) {
+    Singleton::<RewardMigration>::new(storage, KEY_REWARD_MIGRATION).remove()
+}
+
+/// The running index migration, if any
+pub fn read_reward_migration(storage: &dyn Storage) -> Option<RewardMigration> {
+    ReadonlySingleton::new(storage, KEY_REWARD_MIGRATION)
+        .may_load()
+        .unwrap_or_default()
+}
+
 pub fn store_borrower_info(
     storage: &mut dyn Storage,
     borrower: &CanonicalAddr,
@@ -69,6 +213,49 @@ pub fn read_borrower_info(storage: &dyn Storage, borrower: &CanonicalAddr) -> Bo
     }
 }
 
+/// Sum of the balances of all borrowers, the total balance they back
+pub fn sum_borrower_balances(storage: &dyn Storage) -> StdResult<Uint256> {
+    let position_bucket: ReadonlyBucket<BorrowerInfo> =
+        ReadonlyBucket::new(storage, PREFIX_BORROWER);
+
+    let total_balance = position_bucket
+        .range(None, None, Order::Ascending)
+        .try_fold(Uint256::zero(), |total, item| Ok(total + item?.1.balance));
+    total_balance
+}
+
+/// Record the balances of the borrowers from before the holder rewards
+/// as their weights, returns the total weight of all holders
+pub fn init_holder_weights(storage: &mut dyn Storage) -> StdResult<Uint256> {
+    let borrowers: Vec<(Vec<u8>, BorrowerInfo)> =
+        ReadonlyBucket::<BorrowerInfo>::new(storage, PREFIX_BORROWER)
+            .range(None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+
+    let reward_state = read_reward_state(storage);
+    let mut total_weight = Uint256::zero();
+    for (borrower, borrower_info) in borrowers {
+        let holder_reward: Option<HolderReward> =
+            ReadonlyBucket::new(storage, PREFIX_HOLDER_REWARD).may_load(&borrower)?;
+        let holder_reward = match holder_reward {
+            Some(holder_reward) => holder_reward,
+            None => {
+                let holder_reward = HolderReward {
+                    reward_index: reward_state.global_index,
+                    weight: borrower_info.balance,
+                    index_epoch: reward_state.index_epoch,
+                    ..HolderReward::default()
+                };
+                store_holder_reward(storage, &CanonicalAddr::from(borrower), &holder_reward)?;
+                holder_reward
+            }
+        };
+        total_weight += holder_reward.weight;
+    }
+
+    Ok(total_weight)
+}
+
 // settings for pagination
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
@@ -76,9 +263,12 @@ pub fn read_borrowers(
     deps: Deps,
     start_after: Option<CanonicalAddr>,
     limit: Option<u32>,
+    height: u64,
 ) -> StdResult<Vec<BorrowerResponse>> {
     let position_bucket: ReadonlyBucket<BorrowerInfo> =
         ReadonlyBucket::new(deps.storage, PREFIX_BORROWER);
+    let config: Config = read_config(deps.storage)?;
+    let global_index = read_reward_state(deps.storage).global_index;
 
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start = calc_range_start(start_after);
@@ -89,10 +279,14 @@ pub fn read_borrowers(
         .map(|item| {
             let (k, v) = item?;
             let borrower: CanonicalAddr = CanonicalAddr::from(k);
+            let mut holder_reward = read_holder_reward(deps.storage, &borrower);
+            holder_reward.settle(global_index);
             Ok(BorrowerResponse {
                 borrower: deps.api.addr_humanize(&borrower)?.to_string(),
                 balance: v.balance,
                 spendable: v.spendable,
+                pending_rewards: holder_reward.claimable(),
+                reward_boost: holder_reward.boost(config.reward_boost.as_ref(), height),
             })
         })
         .collect()
@@ -1,5 +1,6 @@
 use crate::external::handle::RewardContractQueryMsg;
 use crate::state::BETHAccruedRewardsResponse;
+use cosmwasm_bignumber::Decimal256;
 use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
     from_binary, from_slice, to_binary, Addr, Api, BalanceResponse, BankQuery, CanonicalAddr, Coin,
@@ -7,7 +8,8 @@ use cosmwasm_std::{
     SystemResult, Uint128, WasmQuery,
 };
 use cosmwasm_storage::to_length_prefixed;
-use cw20::TokenInfoResponse;
+use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
+use moneymarket::custody::{RebaseIndexResponse, RebasingTokenQueryMsg};
 use std::collections::HashMap;
 use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
 
@@ -33,6 +35,7 @@ pub struct WasmMockQuerier {
     reward_balance: Uint128,
     other_balance: Uint128,
     tax_querier: TaxQuerier,
+    rebase_index: Decimal256,
 }
 
 #[derive(Clone, Default)]
@@ -197,16 +200,36 @@ impl WasmMockQuerier {
                     panic!("DO NOT ENTER HERE")
                 }
             }
-            QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: _,
-                msg,
-            }) => match from_binary(msg).unwrap() {
-                RewardContractQueryMsg::AccruedRewards { address: _ } => SystemResult::Ok(
-                    ContractResult::from(to_binary(&BETHAccruedRewardsResponse {
-                        rewards: self.accrued_rewards.rewards,
-                    })),
-                ),
-            },
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
+                if let Ok(Cw20QueryMsg::Balance { address }) = from_binary(msg) {
+                    let balance = self
+                        .token_querier
+                        .balances
+                        .get(contract_addr)
+                        .and_then(|balances| balances.get(&address))
+                        .copied()
+                        .unwrap_or_default();
+                    return SystemResult::Ok(ContractResult::from(to_binary(
+                        &Cw20BalanceResponse { balance },
+                    )));
+                }
+
+                if let Ok(RebasingTokenQueryMsg::RebaseIndex {}) = from_binary(msg) {
+                    return SystemResult::Ok(ContractResult::from(to_binary(
+                        &RebaseIndexResponse {
+                            rebase_index: self.rebase_index,
+                        },
+                    )));
+                }
+
+                match from_binary(msg).unwrap() {
+                    RewardContractQueryMsg::AccruedRewards { address: _ } => SystemResult::Ok(
+                        ContractResult::from(to_binary(&BETHAccruedRewardsResponse {
+                            rewards: self.accrued_rewards.rewards,
+                        })),
+                    ),
+                }
+            }
             QueryRequest::Bank(BankQuery::Balance { address, denom }) => {
                 if address == "reward" && denom == "uusd" {
                     let bank_res = BalanceResponse {
@@ -240,6 +263,7 @@ impl WasmMockQuerier {
             accrued_rewards: BETHAccruedRewardsResponse::default(),
             reward_balance: Uint128::zero(),
             other_balance: Uint128::zero(),
+            rebase_index: Decimal256::one(),
         }
     }
 
@@ -264,4 +288,8 @@ impl WasmMockQuerier {
     pub fn set_other_balances(&mut self, balance: Uint128) {
         self.other_balance = balance
     }
+
+    pub fn with_rebase_index(&mut self, rebase_index: Decimal256) {
+        self.rebase_index = rebase_index
+    }
 }
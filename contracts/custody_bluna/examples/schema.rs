@@ -5,7 +5,8 @@ use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use moneymarket::custody::{
     BorrowerResponse, BorrowersResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg,
-    QueryMsg,
+    LastDistributionResponse, MigrateMsg, QueryMsg, ReconciliationResponse, RewardStateResponse,
+    TotalBalanceResponse,
 };
 
 fn main() {
@@ -18,7 +19,12 @@ fn main() {
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(Cw20HookMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(BorrowerResponse), &out_dir);
     export_schema(&schema_for!(BorrowersResponse), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(TotalBalanceResponse), &out_dir);
+    export_schema(&schema_for!(ReconciliationResponse), &out_dir);
+    export_schema(&schema_for!(RewardStateResponse), &out_dir);
+    export_schema(&schema_for!(LastDistributionResponse), &out_dir);
 }
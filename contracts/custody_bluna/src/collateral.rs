@@ -1,16 +1,22 @@
+use crate::distribution::settle_holder_reward;
 use crate::error::ContractError;
 use crate::state::{
-    read_borrower_info, read_borrowers, read_config, remove_borrower_info, store_borrower_info,
-    BorrowerInfo, Config,
+    read_borrower_info, read_borrowers, read_config, read_holder_reward, read_reconciliation,
+    read_reward_state, read_total_balance, remove_borrower_info, store_borrower_info,
+    store_reconciliation, store_total_balance, BorrowerInfo, Config, Reconciliation,
 };
 
-use cosmwasm_bignumber::Uint256;
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    attr, to_binary, Addr, CanonicalAddr, CosmosMsg, Deps, DepsMut, MessageInfo, Response,
-    StdResult, WasmMsg,
+    attr, to_binary, Addr, Binary, CanonicalAddr, CosmosMsg, Deps, DepsMut, Env, MessageInfo,
+    Response, StdResult, WasmMsg,
 };
-use cw20::Cw20ExecuteMsg;
-use moneymarket::custody::{BorrowerResponse, BorrowersResponse};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
+use moneymarket::custody::{
+    BorrowerResponse, BorrowersResponse, RebaseIndexResponse, RebasingTokenQueryMsg,
+    ReconciliationResponse, TotalBalanceResponse,
+};
+use moneymarket::exchange_rate::ExchangeRateResponse;
 use moneymarket::liquidation::Cw20HookMsg as LiquidationCw20HookMsg;
 use terra_cosmwasm::TerraMsgWrapper;
 
@@ -18,9 +24,13 @@ use terra_cosmwasm::TerraMsgWrapper;
 /// Executor: bAsset token contract
 pub fn deposit_collateral(
     deps: DepsMut,
+    env: Env,
     borrower: Addr,
     amount: Uint256,
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    // Rebasing collaterals are recorded in shares of the token balance
+    let amount = amount / rebase_index(deps.as_ref())?;
+
     let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
     let mut borrower_info: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
 
@@ -28,7 +38,15 @@ pub fn deposit_collateral(
     borrower_info.balance += amount;
     borrower_info.spendable += amount;
 
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
     store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
+    let total_balance = read_total_balance(deps.storage) + amount;
+    store_total_balance(deps.storage, &total_balance)?;
 
     Ok(Response::new().add_attributes(vec![
         attr("action", "deposit_collateral"),
@@ -41,6 +59,7 @@ pub fn deposit_collateral(
 /// Executor: borrower
 pub fn withdraw_collateral(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     amount: Option<Uint256>,
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
@@ -62,11 +81,19 @@ pub fn withdraw_collateral(
     borrower_info.balance = borrower_info.balance - amount;
     borrower_info.spendable = borrower_info.spendable - amount;
 
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
     if borrower_info.balance == Uint256::zero() {
         remove_borrower_info(deps.storage, &borrower_raw);
     } else {
         store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
     }
+    let total_balance = read_total_balance(deps.storage) - amount;
+    store_total_balance(deps.storage, &total_balance)?;
 
     Ok(Response::new()
         .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -77,7 +104,7 @@ pub fn withdraw_collateral(
             funds: vec![],
             msg: to_binary(&Cw20ExecuteMsg::Transfer {
                 recipient: borrower.to_string(),
-                amount: amount.into(),
+                amount: (amount * rebase_index(deps.as_ref())?).into(),
             })?,
         }))
         .add_attributes(vec![
@@ -92,6 +119,7 @@ pub fn withdraw_collateral(
 /// Executor: overseer
 pub fn lock_collateral(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     borrower: Addr,
     amount: Uint256,
@@ -110,6 +138,12 @@ pub fn lock_collateral(
     }
 
     borrower_info.spendable = borrower_info.spendable - amount;
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
     store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
     Ok(Response::new().add_attributes(vec![
         attr("action", "lock_collateral"),
@@ -123,6 +157,7 @@ pub fn lock_collateral(
 /// Executor: overseer
 pub fn unlock_collateral(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     borrower: Addr,
     amount: Uint256,
@@ -142,6 +177,12 @@ pub fn unlock_collateral(
     }
 
     borrower_info.spendable += amount;
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
     store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
 
     Ok(Response::new().add_attributes(vec![
@@ -153,10 +194,12 @@ pub fn unlock_collateral(
 
 pub fn liquidate_collateral(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     liquidator: Addr,
     borrower: Addr,
     amount: Uint256,
+    premium_rate: Option<Decimal256>,
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
     let config: Config = read_config(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.overseer_contract {
@@ -173,7 +216,15 @@ pub fn liquidate_collateral(
     }
 
     borrower_info.balance = borrower_info.balance - amount;
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
     store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
+    let total_balance = read_total_balance(deps.storage) - amount;
+    store_total_balance(deps.storage, &total_balance)?;
 
     Ok(Response::new()
         .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -187,7 +238,7 @@ pub fn liquidate_collateral(
                     .api
                     .addr_humanize(&config.liquidation_contract)?
                     .to_string(),
-                amount: amount.into(),
+                amount: (amount * rebase_index(deps.as_ref())?).into(),
                 msg: to_binary(&LiquidationCw20HookMsg::ExecuteBid {
                     liquidator: liquidator.to_string(),
                     fee_address: Some(
@@ -198,6 +249,8 @@ pub fn liquidate_collateral(
                     repay_address: Some(
                         deps.api.addr_humanize(&config.market_contract)?.to_string(),
                     ),
+                    borrower: Some(borrower.to_string()),
+                    premium_rate,
                 })?,
             })?,
         }))
@@ -209,18 +262,238 @@ pub fn liquidate_collateral(
         ]))
 }
 
-pub fn query_borrower(deps: Deps, borrower: Addr) -> StdResult<BorrowerResponse> {
+/// Remove locked collateral and send it to the converter
+/// Executor: overseer
+pub fn convert_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    amount: Uint256,
+    converter: Addr,
+    msg: Binary,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.overseer_contract {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw: CanonicalAddr = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut borrower_info: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
+    let borrowed_amt = borrower_info.balance - borrower_info.spendable;
+    if amount > borrowed_amt {
+        return Err(ContractError::ConvertAmountExceedsLocked(
+            borrowed_amt.into(),
+        ));
+    }
+
+    borrower_info.balance = borrower_info.balance - amount;
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
+    if borrower_info.balance == Uint256::zero() {
+        remove_borrower_info(deps.storage, &borrower_raw);
+    } else {
+        store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
+    }
+    let total_balance = read_total_balance(deps.storage) - amount;
+    store_total_balance(deps.storage, &total_balance)?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps
+                .api
+                .addr_humanize(&config.collateral_token)?
+                .to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: converter.to_string(),
+                amount: (amount * rebase_index(deps.as_ref())?).into(),
+                msg,
+            })?,
+        }))
+        .add_attributes(vec![
+            attr("action", "convert_collateral"),
+            attr("borrower", borrower),
+            attr("converter", converter),
+            attr("amount", amount),
+        ]))
+}
+
+/// Remove locked collateral and transfer it to the recipient
+/// Executor: overseer
+pub fn release_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    recipient: Addr,
+    amount: Uint256,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.overseer_contract {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw: CanonicalAddr = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut borrower_info: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
+    let locked_amount = borrower_info.balance - borrower_info.spendable;
+    if amount > locked_amount {
+        return Err(ContractError::ReleaseAmountExceedsLocked(
+            locked_amount.into(),
+        ));
+    }
+
+    borrower_info.balance = borrower_info.balance - amount;
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
+    if borrower_info.balance == Uint256::zero() {
+        remove_borrower_info(deps.storage, &borrower_raw);
+    } else {
+        store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
+    }
+    let total_balance = read_total_balance(deps.storage) - amount;
+    store_total_balance(deps.storage, &total_balance)?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps
+                .api
+                .addr_humanize(&config.collateral_token)?
+                .to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: (amount * rebase_index(deps.as_ref())?).into(),
+            })?,
+        }))
+        .add_attributes(vec![
+            attr("action", "release_collateral"),
+            attr("borrower", borrower),
+            attr("recipient", recipient),
+            attr("amount", amount),
+        ]))
+}
+
+/// Move locked collateral to the recipient without unlocking it
+/// Executor: overseer
+pub fn transfer_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    recipient: Addr,
+    amount: Uint256,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.overseer_contract {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw: CanonicalAddr = deps.api.addr_canonicalize(borrower.as_str())?;
+    let recipient_raw: CanonicalAddr = deps.api.addr_canonicalize(recipient.as_str())?;
+    let mut borrower_info: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
+    let mut recipient_info: BorrowerInfo = read_borrower_info(deps.storage, &recipient_raw);
+    borrower_info
+        .transfer_locked(&mut recipient_info, amount)
+        .map_err(|locked_amount| {
+            ContractError::TransferAmountExceedsLocked(locked_amount.into())
+        })?;
+
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &borrower_raw,
+        &borrower_info,
+    )?;
+    if borrower_info.balance == Uint256::zero() {
+        remove_borrower_info(deps.storage, &borrower_raw);
+    } else {
+        store_borrower_info(deps.storage, &borrower_raw, &borrower_info)?;
+    }
+    settle_holder_reward(
+        deps.storage,
+        env.block.height,
+        &recipient_raw,
+        &recipient_info,
+    )?;
+    store_borrower_info(deps.storage, &recipient_raw, &recipient_info)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_collateral"),
+        attr("borrower", borrower),
+        attr("recipient", recipient),
+        attr("amount", amount),
+    ]))
+}
+
+/// Record the collateral balance of the custody above or below
+/// the total balance, valued at the rebase index of the token
+/// Executor: owner
+pub fn reconcile(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let total_balance = read_total_balance(deps.storage) * rebase_index(deps.as_ref())?;
+    let balance: BalanceResponse = deps.querier.query_wasm_smart(
+        deps.api.addr_humanize(&config.collateral_token)?,
+        &Cw20QueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    let balance = Uint256::from(balance.balance);
+    let reconciliation = Reconciliation::new(total_balance, balance, env.block.height);
+    store_reconciliation(deps.storage, &reconciliation)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "reconcile"),
+        attr("total_balance", total_balance),
+        attr("balance", balance),
+        attr("reserve", reconciliation.reserve),
+        attr("deficit", reconciliation.deficit),
+    ]))
+}
+
+pub fn query_reconciliation(deps: Deps) -> StdResult<ReconciliationResponse> {
+    let reconciliation = read_reconciliation(deps.storage);
+    Ok(ReconciliationResponse {
+        reserve: reconciliation.reserve,
+        deficit: reconciliation.deficit,
+        reconciled_height: reconciliation.reconciled_height,
+    })
+}
+
+pub fn query_borrower(deps: Deps, env: Env, borrower: Addr) -> StdResult<BorrowerResponse> {
+    let config: Config = read_config(deps.storage)?;
     let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
     let borrower_info: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
+    let mut holder_reward = read_holder_reward(deps.storage, &borrower_raw);
+    holder_reward.settle(read_reward_state(deps.storage).global_index);
     Ok(BorrowerResponse {
         borrower: borrower.to_string(),
         balance: borrower_info.balance,
         spendable: borrower_info.spendable,
+        pending_rewards: holder_reward.claimable(),
+        reward_boost: holder_reward.boost(config.reward_boost.as_ref(), env.block.height),
     })
 }
 
 pub fn query_borrowers(
     deps: Deps,
+    env: Env,
     start_after: Option<Addr>,
     limit: Option<u32>,
 ) -> StdResult<BorrowersResponse> {
@@ -230,6 +503,33 @@ pub fn query_borrowers(
         None
     };
 
-    let borrowers = read_borrowers(deps, start_after, limit)?;
+    let borrowers = read_borrowers(deps, start_after, limit, env.block.height)?;
     Ok(BorrowersResponse { borrowers })
 }
+
+pub fn query_total_balance(deps: Deps) -> StdResult<TotalBalanceResponse> {
+    let total_balance = read_total_balance(deps.storage);
+    Ok(TotalBalanceResponse { total_balance })
+}
+
+/// Collateral tokens a single recorded share is worth
+pub fn query_exchange_rate(deps: Deps) -> StdResult<ExchangeRateResponse> {
+    Ok(ExchangeRateResponse {
+        exchange_rate: rebase_index(deps)?,
+    })
+}
+
+/// Token amount of a single share of a rebasing collateral, as the
+/// collateral token reports it. One share is one token otherwise
+fn rebase_index(deps: Deps) -> StdResult<Decimal256> {
+    let config: Config = read_config(deps.storage)?;
+    if !config.rebasing {
+        return Ok(Decimal256::one());
+    }
+
+    let res: RebaseIndexResponse = deps.querier.query_wasm_smart(
+        deps.api.addr_humanize(&config.collateral_token)?,
+        &RebasingTokenQueryMsg::RebaseIndex {},
+    )?;
+    Ok(res.rebase_index)
+}
@@ -1,21 +1,34 @@
+use cosmwasm_bignumber::Decimal256;
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, from_binary, to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response,
-    StdResult,
+    attr, from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Reply, Response, StdResult, WasmMsg,
 };
 
 use crate::collateral::{
-    deposit_collateral, liquidate_collateral, lock_collateral, query_borrower, query_borrowers,
-    unlock_collateral, withdraw_collateral,
+    convert_collateral, deposit_collateral, liquidate_collateral, lock_collateral, query_borrower,
+    query_borrowers, query_exchange_rate, query_reconciliation, query_total_balance, reconcile,
+    release_collateral, transfer_collateral, unlock_collateral, withdraw_collateral,
+};
+use crate::distribution::{
+    claim_rewards, claim_rewards_for, distribute_hook, distribute_rewards, migrate_reward_indexes,
+    query_reward_state, swap_to_stable_denom,
 };
-use crate::distribution::{distribute_hook, distribute_rewards, swap_to_stable_denom};
 use crate::error::ContractError;
-use crate::state::{read_config, store_config, Config};
+use crate::state::{
+    init_holder_weights, read_config, read_last_distributed_height, read_legacy_config,
+    read_reward_state, store_config, store_reward_state, store_total_balance,
+    sum_borrower_balances, Config,
+};
 
-use cw20::Cw20ReceiveMsg;
-use moneymarket::common::optional_addr_validate;
-use moneymarket::custody::{ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use moneymarket::common::{optional_addr_validate, AssetInfo};
+use moneymarket::custody::{
+    ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, LastDistributionResponse, MigrateMsg,
+    QueryMsg, RewardBoost,
+};
+use moneymarket::querier::{deduct_tax, query_balance, query_token_balance};
 use terra_cosmwasm::TerraMsgWrapper;
 
 pub const CLAIM_REWARDS_OPERATION: u64 = 1u64;
@@ -27,7 +40,13 @@ pub fn instantiate(
     _env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
+    let protocol_reward_share = msg.protocol_reward_share.unwrap_or_else(Decimal256::one);
+    assert_protocol_reward_share(protocol_reward_share)?;
+    if let Some(reward_boost) = &msg.reward_boost {
+        assert_reward_boost(reward_boost)?;
+    }
+
     let config = Config {
         owner: deps.api.addr_canonicalize(&msg.owner)?,
         overseer_contract: deps.api.addr_canonicalize(&msg.overseer_contract)?,
@@ -35,8 +54,17 @@ pub fn instantiate(
         market_contract: deps.api.addr_canonicalize(&msg.market_contract)?,
         reward_contract: deps.api.addr_canonicalize(&msg.reward_contract)?,
         liquidation_contract: deps.api.addr_canonicalize(&msg.liquidation_contract)?,
+        reward_denom: msg
+            .reward_denom
+            .clone()
+            .unwrap_or_else(|| msg.stable_denom.to_string()),
+        swap_rewards: msg.swap_rewards.unwrap_or(true),
         stable_denom: msg.stable_denom,
         basset_info: msg.basset_info,
+        rebasing: msg.rebasing.unwrap_or(false),
+        reward_distribution_interval: msg.reward_distribution_interval.unwrap_or(0),
+        protocol_reward_share,
+        reward_boost: msg.reward_boost,
     };
 
     store_config(deps.storage, &config)?;
@@ -52,10 +80,13 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
     match msg {
-        ExecuteMsg::Receive(msg) => receive_cw20(deps, info, msg),
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::UpdateConfig {
             owner,
             liquidation_contract,
+            reward_distribution_interval,
+            protocol_reward_share,
+            reward_boost,
         } => {
             let api = deps.api;
             update_config(
@@ -63,26 +94,77 @@ pub fn execute(
                 info,
                 optional_addr_validate(api, owner)?,
                 optional_addr_validate(api, liquidation_contract)?,
+                reward_distribution_interval,
+                protocol_reward_share,
+                reward_boost,
             )
         }
+        ExecuteMsg::SweepTokens { asset, recipient } => {
+            let api = deps.api;
+            sweep_tokens(deps, env, info, asset, api.addr_validate(&recipient)?)
+        }
+        ExecuteMsg::Reconcile {} => reconcile(deps, env, info),
         ExecuteMsg::LockCollateral { borrower, amount } => {
             let borrower_addr = deps.api.addr_validate(&borrower)?;
-            lock_collateral(deps, info, borrower_addr, amount)
+            lock_collateral(deps, env, info, borrower_addr, amount)
         }
         ExecuteMsg::UnlockCollateral { borrower, amount } => {
             let borrower_addr = deps.api.addr_validate(&borrower)?;
-            unlock_collateral(deps, info, borrower_addr, amount)
+            unlock_collateral(deps, env, info, borrower_addr, amount)
+        }
+        ExecuteMsg::ClaimRewardsFor { borrower } => {
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            claim_rewards_for(deps, env, info, borrower_addr)
         }
         ExecuteMsg::DistributeRewards {} => distribute_rewards(deps, env, info),
-        ExecuteMsg::WithdrawCollateral { amount } => withdraw_collateral(deps, info, amount),
+        ExecuteMsg::MigrateRewardIndexes { limit } => migrate_reward_indexes(deps, info, limit),
+        ExecuteMsg::TransferCollateral {
+            borrower,
+            recipient,
+            amount,
+        } => {
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            let recipient_addr = deps.api.addr_validate(&recipient)?;
+            transfer_collateral(deps, env, info, borrower_addr, recipient_addr, amount)
+        }
+        ExecuteMsg::WithdrawCollateral { amount } => withdraw_collateral(deps, env, info, amount),
+        ExecuteMsg::ClaimRewards {} => claim_rewards(deps, env, info),
         ExecuteMsg::LiquidateCollateral {
             liquidator,
             borrower,
             amount,
+            premium_rate,
         } => {
             let liquidator_addr = deps.api.addr_validate(&liquidator)?;
             let borrower_addr = deps.api.addr_validate(&borrower)?;
-            liquidate_collateral(deps, info, liquidator_addr, borrower_addr, amount)
+            liquidate_collateral(
+                deps,
+                env,
+                info,
+                liquidator_addr,
+                borrower_addr,
+                amount,
+                premium_rate,
+            )
+        }
+        ExecuteMsg::ConvertCollateral {
+            borrower,
+            amount,
+            converter,
+            msg,
+        } => {
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            let converter_addr = deps.api.addr_validate(&converter)?;
+            convert_collateral(deps, env, info, borrower_addr, amount, converter_addr, msg)
+        }
+        ExecuteMsg::ReleaseCollateral {
+            borrower,
+            recipient,
+            amount,
+        } => {
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            let recipient_addr = deps.api.addr_validate(&recipient)?;
+            release_collateral(deps, env, info, borrower_addr, recipient_addr, amount)
         }
     }
 }
@@ -95,7 +177,13 @@ pub fn reply(
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
     match msg.id {
         // ClaimRewards callback
-        CLAIM_REWARDS_OPERATION => swap_to_stable_denom(deps, env),
+        CLAIM_REWARDS_OPERATION => {
+            if read_config(deps.storage)?.swap_rewards {
+                swap_to_stable_denom(deps, env)
+            } else {
+                distribute_hook(deps, env)
+            }
+        }
         // Swap to stable callback
         SWAP_TO_STABLE_OPERATION => distribute_hook(deps, env),
         _ => Err(ContractError::InvalidReplyId {}),
@@ -104,6 +192,7 @@ pub fn reply(
 
 pub fn receive_cw20(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
@@ -118,7 +207,17 @@ pub fn receive_cw20(
             }
 
             let cw20_sender_addr = deps.api.addr_validate(&cw20_msg.sender)?;
-            deposit_collateral(deps, cw20_sender_addr, cw20_msg.amount.into())
+            deposit_collateral(deps, env, cw20_sender_addr, cw20_msg.amount.into())
+        }
+        Ok(Cw20HookMsg::DepositCollateralFor { borrower }) => {
+            // only asset contract can execute this message
+            let config: Config = read_config(deps.storage)?;
+            if deps.api.addr_canonicalize(contract_addr.as_str())? != config.collateral_token {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            deposit_collateral(deps, env, borrower_addr, cw20_msg.amount.into())
         }
         _ => Err(ContractError::MissingDepositCollateralHook {}),
     }
@@ -129,6 +228,9 @@ pub fn update_config(
     info: MessageInfo,
     owner: Option<Addr>,
     liquidation_contract: Option<Addr>,
+    reward_distribution_interval: Option<u64>,
+    protocol_reward_share: Option<Decimal256>,
+    reward_boost: Option<Option<RewardBoost>>,
 ) -> Result<Response<TerraMsgWrapper>, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
 
@@ -144,23 +246,164 @@ pub fn update_config(
         config.liquidation_contract = deps.api.addr_canonicalize(liquidation_contract.as_str())?;
     }
 
+    if let Some(reward_distribution_interval) = reward_distribution_interval {
+        config.reward_distribution_interval = reward_distribution_interval;
+    }
+
+    if let Some(protocol_reward_share) = protocol_reward_share {
+        assert_protocol_reward_share(protocol_reward_share)?;
+        config.protocol_reward_share = protocol_reward_share;
+    }
+
+    if let Some(reward_boost) = reward_boost {
+        if let Some(reward_boost) = &reward_boost {
+            assert_reward_boost(reward_boost)?;
+        }
+        config.reward_boost = reward_boost;
+    }
+
     store_config(deps.storage, &config)?;
     Ok(Response::new().add_attributes(vec![attr("action", "update_config")]))
 }
 
+fn assert_protocol_reward_share(protocol_reward_share: Decimal256) -> Result<(), ContractError> {
+    if protocol_reward_share > Decimal256::one() {
+        return Err(ContractError::InvalidProtocolRewardShare {});
+    }
+
+    Ok(())
+}
+
+fn assert_reward_boost(reward_boost: &RewardBoost) -> Result<(), ContractError> {
+    if reward_boost.max_boost < Decimal256::one() {
+        return Err(ContractError::InvalidRewardBoost {});
+    }
+
+    Ok(())
+}
+
+pub fn sweep_tokens(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset: AssetInfo,
+    recipient: Addr,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // Collateral token is held for the borrowers and
+    // stable denom and reward denom rewards are pending distribution
+    let (asset_name, amount, message) = match asset {
+        AssetInfo::NativeToken { denom } => {
+            if denom == config.stable_denom || denom == config.reward_denom {
+                return Err(ContractError::SweepTrackedAsset(denom));
+            }
+
+            let amount = query_balance(deps.as_ref(), env.contract.address, denom.to_string())?;
+            let message = CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: denom.to_string(),
+                        amount: amount.into(),
+                    },
+                )?],
+            });
+            (denom, amount, message)
+        }
+        AssetInfo::Token { contract_addr } => {
+            if deps.api.addr_canonicalize(&contract_addr)? == config.collateral_token {
+                return Err(ContractError::SweepTrackedAsset(contract_addr));
+            }
+
+            let amount = query_token_balance(
+                deps.as_ref(),
+                deps.api.addr_validate(&contract_addr)?,
+                env.contract.address,
+            )?;
+            let message = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: amount.into(),
+                })?,
+            });
+            (contract_addr, amount, message)
+        }
+    };
+
+    Ok(Response::new().add_message(message).add_attributes(vec![
+        attr("action", "sweep"),
+        attr("asset", asset_name),
+        attr("recipient", recipient),
+        attr("amount", amount),
+    ]))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
+    // Configs from before the reward settings keep swapping the rewards
+    // to the stable denom and remitting all of them to the overseer
+    if read_config(deps.storage).is_err() {
+        let legacy_config = read_legacy_config(deps.storage)?;
+        store_config(
+            deps.storage,
+            &Config {
+                owner: legacy_config.owner,
+                collateral_token: legacy_config.collateral_token,
+                overseer_contract: legacy_config.overseer_contract,
+                market_contract: legacy_config.market_contract,
+                reward_contract: legacy_config.reward_contract,
+                liquidation_contract: legacy_config.liquidation_contract,
+                reward_denom: legacy_config.stable_denom.clone(),
+                stable_denom: legacy_config.stable_denom,
+                basset_info: legacy_config.basset_info,
+                rebasing: false,
+                swap_rewards: true,
+                reward_distribution_interval: 0,
+                protocol_reward_share: Decimal256::one(),
+                reward_boost: None,
+            },
+        )?;
+    }
+
+    // Deployments from before the total balance was recorded start
+    // it from the balances of their borrowers
+    let total_balance = sum_borrower_balances(deps.storage)?;
+    store_total_balance(deps.storage, &total_balance)?;
+
+    // and the holders from before the holder rewards weigh their balances
+    let mut reward_state = read_reward_state(deps.storage);
+    reward_state.total_weight = init_holder_weights(deps.storage)?;
+    store_reward_state(deps.storage, &reward_state)?;
+
+    Ok(Response::default())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::Borrower { address } => {
             let addr = deps.api.addr_validate(&address)?;
-            to_binary(&query_borrower(deps, addr)?)
+            to_binary(&query_borrower(deps, env, addr)?)
         }
         QueryMsg::Borrowers { start_after, limit } => to_binary(&query_borrowers(
             deps,
+            env,
             optional_addr_validate(deps.api, start_after)?,
             limit,
         )?),
+        QueryMsg::TotalBalance {} => to_binary(&query_total_balance(deps)?),
+        QueryMsg::LastDistribution {} => to_binary(&query_last_distribution(deps)?),
+        QueryMsg::Reconciliation {} => to_binary(&query_reconciliation(deps)?),
+        QueryMsg::ExchangeRate {} => to_binary(&query_exchange_rate(deps)?),
+        QueryMsg::RewardState {} => to_binary(&query_reward_state(deps)?),
     }
 }
 
@@ -184,5 +427,20 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
             .to_string(),
         stable_denom: config.stable_denom,
         basset_info: config.basset_info,
+        rebasing: config.rebasing,
+        reward_denom: config.reward_denom,
+        swap_rewards: config.swap_rewards,
+        reward_distribution_interval: config.reward_distribution_interval,
+        protocol_reward_share: config.protocol_reward_share,
+        reward_boost: config.reward_boost,
+    })
+}
+
+pub fn query_last_distribution(deps: Deps) -> StdResult<LastDistributionResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let last_distributed_height = read_last_distributed_height(deps.storage);
+    Ok(LastDistributionResponse {
+        last_distributed_height,
+        next_distribution_height: last_distributed_height + config.reward_distribution_interval,
     })
 }
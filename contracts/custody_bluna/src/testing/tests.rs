@@ -1,22 +1,30 @@
-use cosmwasm_bignumber::Uint256;
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
     attr, from_binary, to_binary, Api, Attribute, BankMsg, Coin, ContractResult, CosmosMsg,
-    Decimal, Reply, Response, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
+    Decimal, DepsMut, Env, Reply, Response, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
 };
 
 use crate::contract::{
-    execute, instantiate, query, reply, CLAIM_REWARDS_OPERATION, SWAP_TO_STABLE_OPERATION,
+    execute, instantiate, migrate, query, reply, CLAIM_REWARDS_OPERATION, SWAP_TO_STABLE_OPERATION,
 };
 use crate::error::ContractError;
 use crate::external::handle::RewardContractExecuteMsg;
-use crate::state::{read_borrower_info, BLunaAccruedRewardsResponse};
+use crate::state::{
+    read_borrower_info, store_borrower_info, store_total_balance, BLunaAccruedRewardsResponse,
+    BorrowerInfo, LegacyConfig,
+};
 use crate::testing::mock_querier::mock_dependencies;
 
 use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
+use cosmwasm_storage::Singleton;
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use moneymarket::common::AssetInfo;
 use moneymarket::custody::{
-    BAssetInfo, BorrowerResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg,
+    BAssetInfo, BorrowerResponse, BorrowersResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg,
+    InstantiateMsg, LastDistributionResponse, MigrateMsg, QueryMsg, ReconciliationResponse,
+    RewardBoost, RewardStateResponse, TotalBalanceResponse,
 };
+use moneymarket::exchange_rate::ExchangeRateResponse;
 use moneymarket::liquidation::Cw20HookMsg as LiquidationCw20HookMsg;
 use terra_cosmwasm::create_swap_msg;
 
@@ -37,6 +45,12 @@ fn proper_initialization() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -72,6 +86,12 @@ fn update_config() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -82,6 +102,9 @@ fn update_config() {
     let msg = ExecuteMsg::UpdateConfig {
         owner: Some("owner2".to_string()),
         liquidation_contract: Some("liquidation2".to_string()),
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
     let info = mock_info("owner", &[]);
     execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap();
@@ -104,6 +127,104 @@ fn update_config() {
     }
 }
 
+#[test]
+fn sweep_tokens() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::SweepTokens {
+        asset: AssetInfo::NativeToken {
+            denom: "ukrw".to_string(),
+        },
+        recipient: "recipient".to_string(),
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // tracked collateral cannot be swept
+    let info = mock_info("owner", &[]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::SweepTokens {
+            asset: AssetInfo::Token {
+                contract_addr: "bluna".to_string(),
+            },
+            recipient: "recipient".to_string(),
+        },
+    );
+    match res {
+        Err(ContractError::SweepTrackedAsset(asset)) => assert_eq!(asset, "bluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::SweepTokens {
+            asset: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            recipient: "recipient".to_string(),
+        },
+    );
+    match res {
+        Err(ContractError::SweepTrackedAsset(asset)) => assert_eq!(asset, "uusd"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.set_other_balances(Uint128::from(1000u128));
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "recipient".to_string(),
+            amount: vec![Coin {
+                denom: "ukrw".to_string(),
+                amount: Uint128::from(1000u128),
+            }],
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "sweep"),
+            attr("asset", "ukrw"),
+            attr("recipient", "recipient"),
+            attr("amount", "1000"),
+        ]
+    );
+}
+
 #[test]
 fn deposit_collateral() {
     let mut deps = mock_dependencies(&[]);
@@ -121,6 +242,12 @@ fn deposit_collateral() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -179,6 +306,8 @@ fn deposit_collateral() {
             borrower: "addr0000".to_string(),
             balance: Uint256::from(100u128),
             spendable: Uint256::from(100u128),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
         }
     );
 
@@ -209,6 +338,8 @@ fn deposit_collateral() {
             borrower: "addr0000".to_string(),
             balance: Uint256::from(200u128),
             spendable: Uint256::from(200u128),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
         }
     );
 }
@@ -230,6 +361,12 @@ fn withdraw_collateral() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -303,6 +440,8 @@ fn withdraw_collateral() {
             borrower: "addr0000".to_string(),
             balance: Uint256::from(50u64),
             spendable: Uint256::from(50u64),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
         }
     );
 
@@ -325,6 +464,8 @@ fn withdraw_collateral() {
             borrower: "addr0000".to_string(),
             balance: Uint256::from(10u128),
             spendable: Uint256::from(10u128),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
         }
     );
 
@@ -346,12 +487,14 @@ fn withdraw_collateral() {
             borrower: "addr0000".to_string(),
             balance: Uint256::zero(),
             spendable: Uint256::zero(),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
         }
     );
 }
 
 #[test]
-fn lock_collateral() {
+fn total_balance() {
     let mut deps = mock_dependencies(&[]);
 
     let msg = InstantiateMsg {
@@ -367,194 +510,173 @@ fn lock_collateral() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
-        sender: "addr0000".to_string(),
-        amount: Uint128::from(100u128),
-        msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
-    });
-
-    let info = mock_info("bluna", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "deposit_collateral"),
-            attr("borrower", "addr0000"),
-            attr("amount", "100"),
-        ]
-    );
-
-    let msg = ExecuteMsg::LockCollateral {
-        borrower: "addr0000".to_string(),
-        amount: Uint256::from(50u64),
+    let query_total = |deps: cosmwasm_std::Deps| -> Uint256 {
+        let res: TotalBalanceResponse =
+            from_binary(&query(deps, mock_env(), QueryMsg::TotalBalance {}).unwrap()).unwrap();
+        res.total_balance
     };
-    let info = mock_info("addr0000", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
-    match res {
-        Err(ContractError::Unauthorized {}) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    //locking more than spendable
-    let info2 = mock_info("overseer", &[]);
-    let msg2 = ExecuteMsg::LockCollateral {
-        borrower: "addr0000".to_string(),
-        amount: Uint256::from(200u128),
+    assert_eq!(query_total(deps.as_ref()), Uint256::zero());
+
+    let deposit = |sender: &str, amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+        })
     };
-    let res2 = execute(deps.as_mut(), mock_env(), info2, msg2).unwrap_err();
-
-    assert_eq!(res2, ContractError::LockAmountExceedsSpendable(100));
-
-    let info = mock_info("overseer", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "lock_collateral"),
-            attr("borrower", "addr0000"),
-            attr("amount", "50"),
-        ]
-    );
 
-    //directly checking if spendable is decreased by amount
-    let spend = read_borrower_info(
-        &deps.storage,
-        &deps.api.addr_canonicalize("addr0000").unwrap(),
+    let info = mock_info("bluna", &[]);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        deposit("addr0000", 100),
     )
-    .spendable;
-    assert_eq!(spend, Uint256::from(50u128));
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        deposit("addr0001", 50),
+    )
+    .unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(150u128));
 
     let msg = ExecuteMsg::WithdrawCollateral {
-        amount: Some(Uint256::from(51u64)),
+        amount: Some(Uint256::from(30u128)),
     };
-    let info = mock_info("addr0000", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
-    match res {
-        Err(ContractError::WithdrawAmountExceedsSpendable(50)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(120u128));
+
+    execute(deps.as_mut(), mock_env(), info, deposit("addr0001", 20)).unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(140u128));
+
+    // withdraw everything; the borrower entry is removed
+    let msg = ExecuteMsg::WithdrawCollateral { amount: None };
+    execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(70u128));
 
+    // failed withdrawals leave the counter untouched
     let msg = ExecuteMsg::WithdrawCollateral {
-        amount: Some(Uint256::from(50u64)),
+        amount: Some(Uint256::from(71u128)),
     };
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "withdraw_collateral"),
-            attr("borrower", "addr0000"),
-            attr("amount", "50"),
-        ]
-    );
-
-    let query_res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::Borrower {
-            address: "addr0000".to_string(),
-        },
-    )
-    .unwrap();
-    let borrower_res: BorrowerResponse = from_binary(&query_res).unwrap();
-    assert_eq!(
-        borrower_res,
-        BorrowerResponse {
-            borrower: "addr0000".to_string(),
-            balance: Uint256::from(50u64),
-            spendable: Uint256::from(0u64),
-        }
-    );
+    let _ = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap_err();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(70u128));
 
-    // Unlock partial amount of collateral
-    let msg = ExecuteMsg::UnlockCollateral {
+    // locking does not change the total, liquidation does
+    let info = mock_info("overseer", &[]);
+    let msg = ExecuteMsg::LockCollateral {
         borrower: "addr0000".to_string(),
-        amount: Uint256::from(30u64),
+        amount: Uint256::from(40u128),
     };
+    execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(70u128));
 
-    //unauthorized sender
-    let info2 = mock_info("addr0000", &[]);
-    let res2 = execute(deps.as_mut(), mock_env(), info2, msg.clone());
-    match res2 {
-        Err(ContractError::Unauthorized {}) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    //unlocking more than allowed (which is 50 - 0 = 50)
-    let msg3 = ExecuteMsg::UnlockCollateral {
+    let msg = ExecuteMsg::LiquidateCollateral {
+        liquidator: "liquidator".to_string(),
         borrower: "addr0000".to_string(),
-        amount: Uint256::from(230u128),
+        amount: Uint256::from(25u128),
+        premium_rate: None,
     };
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(45u128));
 
-    let info3 = mock_info("overseer", &[]);
-    let res3 = execute(deps.as_mut(), mock_env(), info3, msg3);
-    match res3 {
-        Err(ContractError::UnlockAmountExceedsLocked(50)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    let info = mock_info("overseer", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "unlock_collateral"),
-            attr("borrower", "addr0000"),
-            attr("amount", "30"),
-        ]
-    );
+    // a deployment from before the counter backfills it on migration
+    store_total_balance(deps.as_mut().storage, &Uint256::zero()).unwrap();
+    let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    assert_eq!(query_total(deps.as_ref()), Uint256::from(45u128));
+}
 
-    //checking if amount is added to spendable
-    let spend = read_borrower_info(
-        &deps.storage,
-        &deps.api.addr_canonicalize("addr0000").unwrap(),
-    )
-    .spendable;
-    assert_eq!(spend, Uint256::from(30u128));
+#[test]
+fn migrate_legacy_config() {
+    let mut deps = mock_dependencies(&[]);
 
-    let msg = ExecuteMsg::WithdrawCollateral {
-        amount: Some(Uint256::from(30u64)),
+    let legacy_config = LegacyConfig {
+        owner: deps.api.addr_canonicalize("owner").unwrap(),
+        collateral_token: deps.api.addr_canonicalize("bluna").unwrap(),
+        overseer_contract: deps.api.addr_canonicalize("overseer").unwrap(),
+        market_contract: deps.api.addr_canonicalize("market").unwrap(),
+        reward_contract: deps.api.addr_canonicalize("reward").unwrap(),
+        liquidation_contract: deps.api.addr_canonicalize("liquidation").unwrap(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
     };
-    let info = mock_info("addr0000", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "withdraw_collateral"),
-            attr("borrower", "addr0000"),
-            attr("amount", "30"),
-        ]
-    );
+    Singleton::new(deps.as_mut().storage, b"config")
+        .save(&legacy_config)
+        .unwrap();
+    for (borrower, balance, spendable) in
+        [("addr0000", 100u128, 40u128), ("addr0001", 50u128, 50u128)]
+    {
+        store_borrower_info(
+            &mut deps.storage,
+            &deps.api.addr_canonicalize(borrower).unwrap(),
+            &BorrowerInfo {
+                balance: Uint256::from(balance),
+                spendable: Uint256::from(spendable),
+            },
+        )
+        .unwrap();
+    }
 
-    let query_res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::Borrower {
-            address: "addr0000".to_string(),
-        },
+    let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+    // the rewards are swapped to uusd and remitted to the overseer as before
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.owner, "owner".to_string());
+    assert_eq!(config_res.collateral_token, "bluna".to_string());
+    assert_eq!(config_res.stable_denom, "uusd".to_string());
+    assert!(!config_res.rebasing);
+    assert_eq!(config_res.reward_denom, "uusd".to_string());
+    assert!(config_res.swap_rewards);
+    assert_eq!(config_res.reward_distribution_interval, 0u64);
+    assert_eq!(config_res.protocol_reward_share, Decimal256::one());
+    assert_eq!(config_res.reward_boost, None);
+
+    let res: TotalBalanceResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::TotalBalance {}).unwrap()).unwrap();
+    assert_eq!(res.total_balance, Uint256::from(150u128));
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(res.total_weight, Uint256::from(150u128));
+    let res: BorrowerResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Borrower {
+                address: "addr0000".to_string(),
+            },
+        )
+        .unwrap(),
     )
     .unwrap();
-    let borrower_res: BorrowerResponse = from_binary(&query_res).unwrap();
-    assert_eq!(
-        borrower_res,
-        BorrowerResponse {
-            borrower: "addr0000".to_string(),
-            balance: Uint256::from(20u64),
-            spendable: Uint256::from(0u64),
-        }
-    );
+    assert_eq!(res.balance, Uint256::from(100u128));
+    assert_eq!(res.spendable, Uint256::from(40u128));
+
+    // a migrated config is kept on the next migration
+    let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    assert_eq!(from_binary::<ConfigResponse>(&res).unwrap(), config_res);
 }
 
 #[test]
-fn distribute_rewards() {
-    let mut deps = mock_dependencies(&[Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(1000000u128),
-    }]);
+fn reconcile() {
+    let mut deps = mock_dependencies(&[]);
 
     let msg = InstantiateMsg {
         owner: "owner".to_string(),
@@ -569,113 +691,99 @@ fn distribute_rewards() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
-    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-
-    let msg = ExecuteMsg::DistributeRewards {};
-    let res = execute(deps.as_mut(), mock_env(), info, msg);
-    match res {
-        Err(ContractError::Unauthorized {}) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-    deps.querier.set_reward_balance(Uint128::new(10000000));
-    deps.querier
-        .set_accrued_rewards(BLunaAccruedRewardsResponse {
-            rewards: Uint128::new(10000000),
-        });
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+    });
+    execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
 
-    let msg = ExecuteMsg::DistributeRewards {};
-    let info = mock_info("overseer", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-    // Do not print logs at this step
-    let empty_vector: Vec<Attribute> = Vec::new();
-    assert_eq!(res.attributes, empty_vector);
-    assert_eq!(
-        res.messages,
-        vec![SubMsg::reply_on_success(
-            CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "reward".to_string(),
-                funds: vec![],
-                msg: to_binary(&RewardContractExecuteMsg::ClaimRewards { recipient: None })
-                    .unwrap(),
-            }),
-            CLAIM_REWARDS_OPERATION
-        )]
+    let msg = ExecuteMsg::Reconcile {};
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
     );
-}
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 
-#[test]
-fn distribute_hook() {
-    let mut deps = mock_dependencies(&[Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(1000000u128),
-    }]);
+    let query_reconciliation = |deps: cosmwasm_std::Deps| -> ReconciliationResponse {
+        from_binary(&query(deps, mock_env(), QueryMsg::Reconciliation {}).unwrap()).unwrap()
+    };
+    let query_balance = |deps: cosmwasm_std::Deps| -> Uint256 {
+        let msg = QueryMsg::Borrower {
+            address: "addr0000".to_string(),
+        };
+        from_binary::<BorrowerResponse>(&query(deps, mock_env(), msg).unwrap())
+            .unwrap()
+            .balance
+    };
 
+    // 30 sent directly to the custody are kept as a reserve
     deps.querier.with_token_balances(&[(
         &"bluna".to_string(),
-        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000u128))],
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(130u128))],
     )]);
-
-    deps.querier.with_tax(
-        Decimal::percent(1),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
-
-    let msg = InstantiateMsg {
-        owner: "owner".to_string(),
-        collateral_token: "bluna".to_string(),
-        overseer_contract: "overseer".to_string(),
-        market_contract: "market".to_string(),
-        reward_contract: "reward".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        stable_denom: "uusd".to_string(),
-        basset_info: BAssetInfo {
-            name: "bluna".to_string(),
-            symbol: "bluna".to_string(),
-            decimals: 6,
-        },
-    };
-
-    let info = mock_info("addr0000", &[]);
-    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
-
-    // Claimed rewards is 1000000uusd
-    // mimic last swap_msg callback to execute distribute_hook
-    deps.querier.set_other_balances(Uint128::new(1000000));
-    let reply_msg = Reply {
-        id: 2,
-        result: ContractResult::Ok(SubMsgExecutionResponse {
-            events: vec![],
-            data: None,
-        }),
-    };
-    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
-
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        msg.clone(),
+    )
+    .unwrap();
     assert_eq!(
         res.attributes,
         vec![
-            attr("action", "distribute_rewards"),
-            attr("buffer_rewards", "1000000"),
+            attr("action", "reconcile"),
+            attr("total_balance", "100"),
+            attr("balance", "130"),
+            attr("reserve", "30"),
+            attr("deficit", "0"),
         ]
     );
+    assert_eq!(
+        query_reconciliation(deps.as_ref()),
+        ReconciliationResponse {
+            reserve: Uint256::from(30u128),
+            deficit: Uint256::zero(),
+            reconciled_height: mock_env().block.height,
+        }
+    );
+    assert_eq!(query_balance(deps.as_ref()), Uint256::from(100u128));
 
+    // a shortfall is flagged without touching the borrower balances
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(80u128))],
+    )]);
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
     assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-            to_address: "overseer".to_string(),
-            amount: vec![Coin {
-                denom: "uusd".to_string(),
-                amount: Uint128::from(990099u128)
-            }],
-        })),],
-    )
+        query_reconciliation(deps.as_ref()),
+        ReconciliationResponse {
+            reserve: Uint256::zero(),
+            deficit: Uint256::from(20u128),
+            reconciled_height: mock_env().block.height,
+        }
+    );
+    assert_eq!(query_balance(deps.as_ref()), Uint256::from(100u128));
 }
 
 #[test]
-fn distribution_hook_zero_rewards() {
+fn rebasing_collateral() {
     let mut deps = mock_dependencies(&[]);
 
     let msg = InstantiateMsg {
@@ -684,111 +792,145 @@ fn distribution_hook_zero_rewards() {
         overseer_contract: "overseer".to_string(),
         market_contract: "market".to_string(),
         reward_contract: "reward".to_string(),
-        liquidation_contract: "terraswap".to_string(),
+        liquidation_contract: "liquidation".to_string(),
         stable_denom: "uusd".to_string(),
         basset_info: BAssetInfo {
             name: "bluna".to_string(),
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: Some(true),
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-    // Claimed rewards is 1000000uusd
-    // mimic last swap_msg callback to execute distribute_hook
-    let reply_msg = Reply {
-        id: 2,
-        result: ContractResult::Ok(SubMsgExecutionResponse {
-            events: vec![],
-            data: None,
-        }),
+    let deposit = |sender: &str, amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+        })
     };
-    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    let query_borrower = |deps: cosmwasm_std::Deps, borrower: &str| -> BorrowerResponse {
+        from_binary(
+            &query(
+                deps,
+                mock_env(),
+                QueryMsg::Borrower {
+                    address: borrower.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+    let query_exchange_rate = |deps: cosmwasm_std::Deps| -> Decimal256 {
+        let res: ExchangeRateResponse =
+            from_binary(&query(deps, mock_env(), QueryMsg::ExchangeRate {}).unwrap()).unwrap();
+        res.exchange_rate
+    };
+    let withdraw_msg = |amount: u128, recipient: &str| {
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::from(amount),
+            })
+            .unwrap(),
+        }))
+    };
+
+    let info = mock_info("bluna", &[]);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        deposit("addr0000", 100),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        deposit("addr0001", 300),
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(50u128),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("overseer", &[]), msg).unwrap();
 
+    // tokens sent to the custody directly are not a rebase
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(500u128))],
+    )]);
+    assert_eq!(query_exchange_rate(deps.as_ref()), Decimal256::one());
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "distribute_rewards"),
-            attr("buffer_rewards", "0"),
-        ]
+        query_borrower(deps.as_ref(), "addr0001").balance,
+        Uint256::from(300u128)
     );
 
-    assert_eq!(res.messages, vec![],)
-}
-
-#[test]
-fn swap_to_stable_denom() {
-    let mut deps = mock_dependencies(&[
-        Coin {
-            denom: "uusd".to_string(),
-            amount: Uint128::from(1000000u128),
-        },
-        Coin {
-            denom: "ukrw".to_string(),
-            amount: Uint128::from(20000000000u128),
-        },
-        Coin {
-            denom: "usdr".to_string(),
-            amount: Uint128::from(2000000u128),
-        },
-    ]);
+    // positive rebase of 10%, the shares are worth more tokens
+    deps.querier.with_rebase_index(Decimal256::percent(110));
+    assert_eq!(query_exchange_rate(deps.as_ref()), Decimal256::percent(110));
+    assert_eq!(
+        query_borrower(deps.as_ref(), "addr0000"),
+        BorrowerResponse {
+            borrower: "addr0000".to_string(),
+            balance: Uint256::from(100u128),
+            spendable: Uint256::from(50u128),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
 
-    let msg = InstantiateMsg {
-        owner: "owner".to_string(),
-        collateral_token: "bluna".to_string(),
-        overseer_contract: "overseer".to_string(),
-        market_contract: "market".to_string(),
-        reward_contract: "reward".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        stable_denom: "uusd".to_string(),
-        basset_info: BAssetInfo {
-            name: "bluna".to_string(),
-            symbol: "bluna".to_string(),
-            decimals: 6,
-        },
+    let msg = ExecuteMsg::WithdrawCollateral {
+        amount: Some(Uint256::from(30u128)),
     };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(res.messages, vec![withdraw_msg(33, "addr0001")]);
+    assert_eq!(
+        read_borrower_info(
+            &deps.storage,
+            &deps.api.addr_canonicalize("addr0001").unwrap()
+        )
+        .balance,
+        Uint256::from(270u128)
+    );
 
-    let info = mock_info("addr0000", &[]);
-    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    // deposits are recorded in shares
+    execute(deps.as_mut(), mock_env(), info, deposit("addr0002", 110)).unwrap();
+    assert_eq!(
+        query_borrower(deps.as_ref(), "addr0002").balance,
+        Uint256::from(100u128)
+    );
 
-    // mimic callback from distribute_rewards to execute swap_to_stable_denom
-    let reply_msg = Reply {
-        id: 1,
-        result: ContractResult::Ok(SubMsgExecutionResponse {
-            events: vec![],
-            data: None,
-        }),
+    // negative rebase of 20%, the locked shares the overseer
+    // recorded can still be unlocked in full
+    deps.querier.with_rebase_index(Decimal256::percent(88));
+    let msg = ExecuteMsg::UnlockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(50u128),
     };
-    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("overseer", &[]), msg).unwrap();
 
-    assert_eq!(
-        res.messages,
-        vec![
-            SubMsg::new(create_swap_msg(
-                Coin {
-                    denom: "ukrw".to_string(),
-                    amount: Uint128::from(20000000000u128),
-                },
-                "uusd".to_string(),
-            )),
-            SubMsg::reply_on_success(
-                create_swap_msg(
-                    Coin {
-                        denom: "usdr".to_string(),
-                        amount: Uint128::from(2000000u128),
-                    },
-                    "uusd".to_string(),
-                ),
-                SWAP_TO_STABLE_OPERATION
-            ),
-        ]
-    );
+    let msg = ExecuteMsg::WithdrawCollateral { amount: None };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(res.messages, vec![withdraw_msg(88, "addr0000")]);
 }
 
 #[test]
-fn liquidate_collateral() {
+fn lock_collateral() {
     let mut deps = mock_dependencies(&[]);
 
     let msg = InstantiateMsg {
@@ -804,6 +946,12 @@ fn liquidate_collateral() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -830,6 +978,23 @@ fn liquidate_collateral() {
         borrower: "addr0000".to_string(),
         amount: Uint256::from(50u64),
     };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    //locking more than spendable
+    let info2 = mock_info("overseer", &[]);
+    let msg2 = ExecuteMsg::LockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(200u128),
+    };
+    let res2 = execute(deps.as_mut(), mock_env(), info2, msg2).unwrap_err();
+
+    assert_eq!(res2, ContractError::LockAmountExceedsSpendable(100));
+
     let info = mock_info("overseer", &[]);
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(
@@ -841,58 +1006,1675 @@ fn liquidate_collateral() {
         ]
     );
 
-    let msg = ExecuteMsg::LiquidateCollateral {
-        liquidator: "addr0001".to_string(),
-        borrower: "addr0000".to_string(),
-        amount: Uint256::from(100u64),
+    //directly checking if spendable is decreased by amount
+    let spend = read_borrower_info(
+        &deps.storage,
+        &deps.api.addr_canonicalize("addr0000").unwrap(),
+    )
+    .spendable;
+    assert_eq!(spend, Uint256::from(50u128));
+
+    let msg = ExecuteMsg::WithdrawCollateral {
+        amount: Some(Uint256::from(51u64)),
     };
     let info = mock_info("addr0000", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
     match res {
-        Err(ContractError::Unauthorized {}) => (),
+        Err(ContractError::WithdrawAmountExceedsSpendable(50)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::WithdrawCollateral {
+        amount: Some(Uint256::from(50u64)),
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "withdraw_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "50"),
+        ]
+    );
+
+    let query_res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Borrower {
+            address: "addr0000".to_string(),
+        },
+    )
+    .unwrap();
+    let borrower_res: BorrowerResponse = from_binary(&query_res).unwrap();
+    assert_eq!(
+        borrower_res,
+        BorrowerResponse {
+            borrower: "addr0000".to_string(),
+            balance: Uint256::from(50u64),
+            spendable: Uint256::from(0u64),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
+
+    // Unlock partial amount of collateral
+    let msg = ExecuteMsg::UnlockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(30u64),
+    };
+
+    //unauthorized sender
+    let info2 = mock_info("addr0000", &[]);
+    let res2 = execute(deps.as_mut(), mock_env(), info2, msg.clone());
+    match res2 {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    //unlocking more than allowed (which is 50 - 0 = 50)
+    let msg3 = ExecuteMsg::UnlockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(230u128),
+    };
+
+    let info3 = mock_info("overseer", &[]);
+    let res3 = execute(deps.as_mut(), mock_env(), info3, msg3);
+    match res3 {
+        Err(ContractError::UnlockAmountExceedsLocked(50)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info("overseer", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "unlock_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "30"),
+        ]
+    );
+
+    //checking if amount is added to spendable
+    let spend = read_borrower_info(
+        &deps.storage,
+        &deps.api.addr_canonicalize("addr0000").unwrap(),
+    )
+    .spendable;
+    assert_eq!(spend, Uint256::from(30u128));
+
+    let msg = ExecuteMsg::WithdrawCollateral {
+        amount: Some(Uint256::from(30u64)),
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "withdraw_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "30"),
+        ]
+    );
+
+    let query_res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Borrower {
+            address: "addr0000".to_string(),
+        },
+    )
+    .unwrap();
+    let borrower_res: BorrowerResponse = from_binary(&query_res).unwrap();
+    assert_eq!(
+        borrower_res,
+        BorrowerResponse {
+            borrower: "addr0000".to_string(),
+            balance: Uint256::from(20u64),
+            spendable: Uint256::from(0u64),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
+}
+
+#[test]
+fn distribute_rewards() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::DistributeRewards {};
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.set_reward_balance(Uint128::new(10000000));
+    deps.querier
+        .set_accrued_rewards(BLunaAccruedRewardsResponse {
+            rewards: Uint128::new(10000000),
+        });
+
+    let msg = ExecuteMsg::DistributeRewards {};
+    let info = mock_info("overseer", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    // Do not print logs at this step
+    let empty_vector: Vec<Attribute> = Vec::new();
+    assert_eq!(res.attributes, empty_vector);
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "reward".to_string(),
+                funds: vec![],
+                msg: to_binary(&RewardContractExecuteMsg::ClaimRewards { recipient: None })
+                    .unwrap(),
+            }),
+            CLAIM_REWARDS_OPERATION
+        )]
+    );
+}
+
+#[test]
+fn reward_distribution_interval() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: Some(100u64),
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    deps.querier.set_reward_balance(Uint128::new(10000000));
+    deps.querier
+        .set_accrued_rewards(BLunaAccruedRewardsResponse {
+            rewards: Uint128::new(10000000),
+        });
+
+    let claim_msg = SubMsg::reply_on_success(
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "reward".to_string(),
+            funds: vec![],
+            msg: to_binary(&RewardContractExecuteMsg::ClaimRewards { recipient: None }).unwrap(),
+        }),
+        CLAIM_REWARDS_OPERATION,
+    );
+
+    let msg = ExecuteMsg::DistributeRewards {};
+    let info = mock_info("overseer", &[]);
+    let mut env = mock_env();
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(res.messages, vec![claim_msg.clone()]);
+
+    let res: LastDistributionResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::LastDistribution {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res,
+        LastDistributionResponse {
+            last_distributed_height: env.block.height,
+            next_distribution_height: env.block.height + 100u64,
+        }
+    );
+
+    // an early call is skipped without error
+    env.block.height += 50u64;
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(res, Response::default());
+
+    env.block.height += 50u64;
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.messages, vec![claim_msg]);
+}
+
+#[test]
+fn distribute_hook() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000u128))],
+    )]);
+
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Claimed rewards is 1000000uusd
+    // mimic last swap_msg callback to execute distribute_hook
+    deps.querier.set_other_balances(Uint128::new(1000000));
+    let reply_msg = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute_rewards"),
+            attr("buffer_rewards", "1000000"),
+            attr("holder_rewards", "0"),
+        ]
+    );
+
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "overseer".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(990099u128)
+            }],
+        })),],
+    )
+}
+
+#[test]
+fn holder_rewards() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: Some(Decimal256::zero()),
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    for (borrower, amount) in [("addr0000", 600u128), ("addr0001", 400u128)] {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: borrower.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+    }
+
+    // Claimed rewards is 1000000uusd, all of it goes to the holders
+    deps.querier.set_other_balances(Uint128::new(1000000));
+    let reply_msg = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute_rewards"),
+            attr("buffer_rewards", "0"),
+            attr("holder_rewards", "1000000"),
+        ]
+    );
+    assert_eq!(res.messages, vec![]);
+
+    // the rewards of a withdrawn balance stay claimable
+    let msg = ExecuteMsg::WithdrawCollateral { amount: None };
+    execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg).unwrap();
+    let res: BorrowerResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Borrower {
+                address: "addr0001".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.pending_rewards, Uint256::from(400000u128));
+
+    let msg = ExecuteMsg::ClaimRewards {};
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_rewards"),
+            attr("borrower", "addr0000"),
+            attr("amount", "600000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(600000u128)
+            }],
+        }))],
+    );
+
+    // nothing is left to claim
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(res.messages, vec![]);
+
+    // the overseer claims the rewards of a borrower for itself
+    let msg = ExecuteMsg::ClaimRewardsFor {
+        borrower: "addr0001".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("overseer", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "overseer".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(400000u128)
+            }],
+        }))],
+    );
+}
+
+#[test]
+fn protocol_reward_share() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: Some(Decimal256::percent(20)),
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        liquidation_contract: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: Some(Decimal256::percent(101)),
+        reward_boost: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::InvalidProtocolRewardShare {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    for (borrower, amount) in [("addr0000", 600u128), ("addr0001", 400u128)] {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: borrower.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+    }
+
+    // Claimed rewards is 1000000uusd, 20% of it goes to the overseer
+    deps.querier.set_other_balances(Uint128::new(1000000));
+    let reply_msg = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute_rewards"),
+            attr("buffer_rewards", "200000"),
+            attr("holder_rewards", "800000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "overseer".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(200000u128)
+            }],
+        }))],
+    );
+
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        RewardStateResponse {
+            global_index: Decimal256::from_uint256(800u128),
+            index_epoch: 0,
+            total_weight: Uint256::from(1000u128),
+            unclaimed_rewards: Uint256::from(800000u128),
+            total_remitted: Uint256::from(200000u128),
+        }
+    );
+
+    // the rewards of a withdrawn balance stay claimable
+    let msg = ExecuteMsg::WithdrawCollateral { amount: None };
+    execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg).unwrap();
+    let res: BorrowerResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Borrower {
+                address: "addr0001".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.pending_rewards, Uint256::from(320000u128));
+
+    let msg = ExecuteMsg::ClaimRewards {};
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_rewards"),
+            attr("borrower", "addr0000"),
+            attr("amount", "480000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(480000u128)
+            }],
+        }))],
+    );
+
+    // nothing is left to claim
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(res.messages, vec![]);
+
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(res.unclaimed_rewards, Uint256::from(320000u128));
+}
+
+#[test]
+fn reward_boost() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: Some(Decimal256::zero()),
+        reward_boost: Some(RewardBoost {
+            boost_per_block: Decimal256::percent(1),
+            max_boost: Decimal256::percent(200),
+        }),
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        liquidation_contract: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: Some(Some(RewardBoost {
+            boost_per_block: Decimal256::percent(1),
+            max_boost: Decimal256::percent(50),
+        })),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::InvalidRewardBoost {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    for borrower in ["addr0000", "addr0001"] {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: borrower.to_string(),
+            amount: Uint128::from(1000u128),
+            msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+    }
+
+    let lock_msg = |borrower: &str| ExecuteMsg::LockCollateral {
+        borrower: borrower.to_string(),
+        amount: Uint256::from(1000u128),
+    };
+    let query_borrower = |deps: cosmwasm_std::Deps, env: Env, borrower: &str| -> BorrowerResponse {
+        from_binary(
+            &query(
+                deps,
+                env,
+                QueryMsg::Borrower {
+                    address: borrower.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+
+    // addr0000 locks 100 blocks before addr0001
+    let info = mock_info("overseer", &[]);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        lock_msg("addr0000"),
+    )
+    .unwrap();
+    let mut env = mock_env();
+    env.block.height += 100;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        lock_msg("addr0001"),
+    )
+    .unwrap();
+
+    // the boost is capped and applies from the next settlement
+    let res = query_borrower(deps.as_ref(), env.clone(), "addr0000");
+    assert_eq!(res.reward_boost, Decimal256::percent(200));
+    let res = query_borrower(deps.as_ref(), env.clone(), "addr0001");
+    assert_eq!(res.reward_boost, Decimal256::one());
+    let msg = ExecuteMsg::ClaimRewards {};
+    execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(res.total_weight, Uint256::from(3000u128));
+
+    // the long-term locker earns twice the share of the fresh one
+    deps.querier.set_other_balances(Uint128::new(3000000));
+    let reply_msg = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), env.clone(), reply_msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute_rewards"),
+            attr("buffer_rewards", "0"),
+            attr("holder_rewards", "3000000"),
+        ]
+    );
+    let res = query_borrower(deps.as_ref(), env.clone(), "addr0000");
+    assert_eq!(res.pending_rewards, Uint256::from(2000000u128));
+    let res = query_borrower(deps.as_ref(), env.clone(), "addr0001");
+    assert_eq!(res.pending_rewards, Uint256::from(1000000u128));
+
+    // unlocking everything starts the boost over
+    let msg = ExecuteMsg::UnlockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(1000u128),
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let res = query_borrower(deps.as_ref(), env.clone(), "addr0000");
+    assert_eq!(res.reward_boost, Decimal256::one());
+    assert_eq!(res.pending_rewards, Uint256::from(2000000u128));
+
+    // a balance from before the holder rewards weighs as much on migration
+    store_borrower_info(
+        &mut deps.storage,
+        &deps.api.addr_canonicalize("addr0002").unwrap(),
+        &BorrowerInfo {
+            balance: Uint256::from(500u128),
+            spendable: Uint256::from(500u128),
+        },
+    )
+    .unwrap();
+    let _res = migrate(deps.as_mut(), env.clone(), MigrateMsg {}).unwrap();
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(res.total_weight, Uint256::from(2500u128));
+}
+
+#[test]
+fn migrate_reward_indexes() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: Some(Decimal256::zero()),
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    for (borrower, amount) in [
+        ("addr0000", 1000u128),
+        ("addr0001", 2000),
+        ("addr0002", 3000),
+    ] {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: borrower.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+    }
+
+    let distribute = |deps: DepsMut| {
+        let reply_msg = Reply {
+            id: 2,
+            result: ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        reply(deps, mock_env(), reply_msg).unwrap();
+    };
+    let claimable = |deps: cosmwasm_std::Deps| -> Vec<Uint256> {
+        let msg = QueryMsg::Borrowers {
+            start_after: None,
+            limit: None,
+        };
+        let res: BorrowersResponse = from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+        res.borrowers
+            .into_iter()
+            .map(|borrower| borrower.pending_rewards)
+            .collect()
+    };
+
+    // 1000000 / 6000 per unit of balance, rounded down
+    deps.querier.set_other_balances(Uint128::new(1000000));
+    distribute(deps.as_mut());
+    let before = claimable(deps.as_ref());
+    assert_eq!(
+        before,
+        vec![
+            Uint256::from(166666u128),
+            Uint256::from(333333u128),
+            Uint256::from(499999u128),
+        ]
+    );
+
+    let msg = ExecuteMsg::MigrateRewardIndexes { limit: Some(2) };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "migrate_reward_indexes"),
+            attr("migrated_holders", "2"),
+            attr("completed", "false"),
+        ]
+    );
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(res.global_index, Decimal256::zero());
+    assert_eq!(res.index_epoch, 1);
+    assert_eq!(claimable(deps.as_ref()), before);
+
+    // distributions keep accruing on the new index while the holders are converted
+    deps.querier.set_other_balances(Uint128::new(1600000));
+    distribute(deps.as_mut());
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "migrate_reward_indexes"),
+            attr("migrated_holders", "1"),
+            attr("completed", "true"),
+        ]
+    );
+    let after = claimable(deps.as_ref());
+    assert_eq!(
+        after,
+        vec![
+            Uint256::from(266666u128),
+            Uint256::from(533333u128),
+            Uint256::from(799999u128),
+        ]
+    );
+    assert_eq!(
+        after.iter().fold(Uint256::zero(), |total, v| total + *v),
+        before.iter().fold(Uint256::zero(), |total, v| total + *v) + Uint256::from(600000u128)
+    );
+
+    // the carried balance is claimed at once with the new rewards
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::ClaimRewards {},
+    )
+    .unwrap();
+    assert_eq!(res.attributes[2], attr("amount", "266666"));
+    assert_eq!(claimable(deps.as_ref())[0], Uint256::zero());
+
+    // the claimed amount leaves the rewards held for the holders
+    let res: RewardStateResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RewardState {}).unwrap()).unwrap();
+    assert_eq!(res.index_epoch, 1);
+    assert_eq!(res.unclaimed_rewards, Uint256::from(1333334u128));
+}
+
+#[test]
+fn distribution_hook_zero_rewards() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "terraswap".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Claimed rewards is 1000000uusd
+    // mimic last swap_msg callback to execute distribute_hook
+    let reply_msg = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute_rewards"),
+            attr("buffer_rewards", "0"),
+            attr("holder_rewards", "0"),
+        ]
+    );
+
+    assert_eq!(res.messages, vec![],)
+}
+
+#[test]
+fn swap_to_stable_denom() {
+    let mut deps = mock_dependencies(&[
+        Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        },
+        Coin {
+            denom: "ukrw".to_string(),
+            amount: Uint128::from(20000000000u128),
+        },
+        Coin {
+            denom: "usdr".to_string(),
+            amount: Uint128::from(2000000u128),
+        },
+    ]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // mimic callback from distribute_rewards to execute swap_to_stable_denom
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(create_swap_msg(
+                Coin {
+                    denom: "ukrw".to_string(),
+                    amount: Uint128::from(20000000000u128),
+                },
+                "uusd".to_string(),
+            )),
+            SubMsg::reply_on_success(
+                create_swap_msg(
+                    Coin {
+                        denom: "usdr".to_string(),
+                        amount: Uint128::from(2000000u128),
+                    },
+                    "uusd".to_string(),
+                ),
+                SWAP_TO_STABLE_OPERATION
+            ),
+        ]
+    );
+}
+
+#[test]
+fn non_stable_reward_denom() {
+    let mut deps = mock_dependencies(&[
+        Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        },
+        Coin {
+            denom: "uluna".to_string(),
+            amount: Uint128::from(2000000u128),
+        },
+    ]);
+
+    let mut msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: Some("uluna".to_string()),
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+
+    let query_res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&query_res).unwrap();
+    assert_eq!("uluna".to_string(), config_res.reward_denom);
+    assert!(config_res.swap_rewards);
+
+    // claimed rewards are swapped to uusd first
+    let reply_msg = Reply {
+        id: CLAIM_REWARDS_OPERATION,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), reply_msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            create_swap_msg(
+                Coin {
+                    denom: "uluna".to_string(),
+                    amount: Uint128::from(2000000u128),
+                },
+                "uusd".to_string(),
+            ),
+            SWAP_TO_STABLE_OPERATION
+        )]
+    );
+
+    // without swap, rewards are sent to the overseer as they were paid
+    msg.swap_rewards = Some(false);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    deps.querier.set_other_balances(Uint128::new(2000000));
+    let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "overseer".to_string(),
+            amount: vec![Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(2000000u128)
+            }],
+        }))]
+    );
+
+    // pending rewards cannot be swept
+    let msg = ExecuteMsg::SweepTokens {
+        asset: AssetInfo::NativeToken {
+            denom: "uluna".to_string(),
+        },
+        recipient: "addr0001".to_string(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::SweepTrackedAsset(denom)) => assert_eq!(denom, "uluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn liquidate_collateral() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+    });
+
+    let info = mock_info("bluna", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "deposit_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "100"),
+        ]
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(50u64),
+    };
+    let info = mock_info("overseer", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "lock_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "50"),
+        ]
+    );
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        liquidator: "addr0001".to_string(),
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(100u64),
+        premium_rate: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info("overseer", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::LiquidationAmountExceedsLocked(50)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let msg = ExecuteMsg::LiquidateCollateral {
+        liquidator: "liquidator".to_string(),
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(10u64),
+        premium_rate: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "liquidate_collateral"),
+            attr("liquidator", "liquidator"),
+            attr("borrower", "addr0000"),
+            attr("amount", "10"),
+        ]
+    );
+
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: "liquidation".to_string(),
+                amount: Uint128::from(10u128),
+                msg: to_binary(&LiquidationCw20HookMsg::ExecuteBid {
+                    liquidator: "liquidator".to_string(),
+                    fee_address: Some("overseer".to_string()),
+                    repay_address: Some("market".to_string()),
+                    borrower: Some("addr0000".to_string()),
+                    premium_rate: None,
+                })
+                .unwrap()
+            })
+            .unwrap(),
+        }))]
+    );
+}
+
+#[test]
+fn convert_collateral() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // converters deposit on behalf of the borrower
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "converter".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::DepositCollateralFor {
+            borrower: "addr0000".to_string(),
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "deposit_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "100"),
+        ]
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(50u64),
+    };
+    let info = mock_info("overseer", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::ConvertCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(100u64),
+        converter: "converter".to_string(),
+        msg: to_binary(&"convert").unwrap(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
         _ => panic!("DO NOT ENTER HERE"),
     }
 
     let info = mock_info("overseer", &[]);
     let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
     match res {
-        Err(ContractError::LiquidationAmountExceedsLocked(50)) => (),
+        Err(ContractError::ConvertAmountExceedsLocked(50)) => (),
         _ => panic!("DO NOT ENTER HERE"),
     }
-    let msg = ExecuteMsg::LiquidateCollateral {
-        liquidator: "liquidator".to_string(),
+
+    let msg = ExecuteMsg::ConvertCollateral {
         borrower: "addr0000".to_string(),
-        amount: Uint256::from(10u64),
+        amount: Uint256::from(50u64),
+        converter: "converter".to_string(),
+        msg: to_binary(&"convert").unwrap(),
     };
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(
         res.attributes,
         vec![
-            attr("action", "liquidate_collateral"),
-            attr("liquidator", "liquidator"),
+            attr("action", "convert_collateral"),
             attr("borrower", "addr0000"),
-            attr("amount", "10"),
+            attr("converter", "converter"),
+            attr("amount", "50"),
         ]
     );
-
     assert_eq!(
         res.messages,
         vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: "bluna".to_string(),
             funds: vec![],
             msg: to_binary(&Cw20ExecuteMsg::Send {
-                contract: "liquidation".to_string(),
-                amount: Uint128::from(10u128),
-                msg: to_binary(&LiquidationCw20HookMsg::ExecuteBid {
-                    liquidator: "liquidator".to_string(),
-                    fee_address: Some("overseer".to_string()),
-                    repay_address: Some("market".to_string()),
-                })
-                .unwrap()
+                contract: "converter".to_string(),
+                amount: Uint128::from(50u128),
+                msg: to_binary(&"convert").unwrap(),
+            })
+            .unwrap(),
+        }))]
+    );
+
+    // only the spendable collateral is left
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Borrower {
+            address: "addr0000".to_string(),
+        },
+    )
+    .unwrap();
+    let borrower_res: BorrowerResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        borrower_res,
+        BorrowerResponse {
+            borrower: "addr0000".to_string(),
+            balance: Uint256::from(50u64),
+            spendable: Uint256::from(50u64),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
+}
+
+#[test]
+fn release_collateral() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::DepositCollateral {}).unwrap(),
+    });
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(50u64),
+    };
+    let info = mock_info("overseer", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::ReleaseCollateral {
+        borrower: "addr0000".to_string(),
+        recipient: "addr0001".to_string(),
+        amount: Uint256::from(100u64),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info("overseer", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::ReleaseAmountExceedsLocked(50)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::ReleaseCollateral {
+        borrower: "addr0000".to_string(),
+        recipient: "addr0001".to_string(),
+        amount: Uint256::from(50u64),
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "release_collateral"),
+            attr("borrower", "addr0000"),
+            attr("recipient", "addr0001"),
+            attr("amount", "50"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0001".to_string(),
+                amount: Uint128::from(50u128),
             })
             .unwrap(),
         }))]
     );
+
+    // only the spendable collateral is left
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Borrower {
+            address: "addr0000".to_string(),
+        },
+    )
+    .unwrap();
+    let borrower_res: BorrowerResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        borrower_res,
+        BorrowerResponse {
+            borrower: "addr0000".to_string(),
+            balance: Uint256::from(50u64),
+            spendable: Uint256::from(50u64),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
+}
+
+#[test]
+fn transfer_collateral() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner".to_string(),
+        collateral_token: "bluna".to_string(),
+        overseer_contract: "overseer".to_string(),
+        market_contract: "market".to_string(),
+        reward_contract: "reward".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        stable_denom: "uusd".to_string(),
+        basset_info: BAssetInfo {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            decimals: 6,
+        },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // converters deposit on behalf of the borrower
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "converter".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::DepositCollateralFor {
+            borrower: "addr0000".to_string(),
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("bluna", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "deposit_collateral"),
+            attr("borrower", "addr0000"),
+            attr("amount", "100"),
+        ]
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        borrower: "addr0000".to_string(),
+        amount: Uint256::from(50u64),
+    };
+    let info = mock_info("overseer", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::TransferCollateral {
+        borrower: "addr0000".to_string(),
+        recipient: "addr0001".to_string(),
+        amount: Uint256::from(50u64),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info("overseer", &[]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::TransferCollateral {
+            borrower: "addr0000".to_string(),
+            recipient: "addr0001".to_string(),
+            amount: Uint256::from(51u64),
+        },
+    );
+    match res {
+        Err(ContractError::TransferAmountExceedsLocked(50)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "transfer_collateral"),
+            attr("borrower", "addr0000"),
+            attr("recipient", "addr0001"),
+            attr("amount", "50"),
+        ]
+    );
+    assert_eq!(res.messages, vec![]);
+
+    // the borrower keeps the spendable collateral,
+    // the recipient receives it locked
+    let borrower = |address: &str| {
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Borrower {
+                address: address.to_string(),
+            },
+        )
+        .unwrap();
+        from_binary::<BorrowerResponse>(&res).unwrap()
+    };
+    assert_eq!(
+        borrower("addr0000"),
+        BorrowerResponse {
+            borrower: "addr0000".to_string(),
+            balance: Uint256::from(50u64),
+            spendable: Uint256::from(50u64),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
+    assert_eq!(
+        borrower("addr0001"),
+        BorrowerResponse {
+            borrower: "addr0001".to_string(),
+            balance: Uint256::from(50u64),
+            spendable: Uint256::zero(),
+            pending_rewards: Uint256::zero(),
+            reward_boost: Decimal256::one(),
+        }
+    );
 }
 
 #[test]
@@ -915,6 +2697,12 @@ fn proper_distribute_rewards_with_no_rewards() {
             symbol: "bluna".to_string(),
             decimals: 6,
         },
+        rebasing: None,
+        reward_denom: None,
+        swap_rewards: None,
+        reward_distribution_interval: None,
+        protocol_reward_share: None,
+        reward_boost: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -1,15 +1,21 @@
 use crate::error::ContractError;
-use crate::state::{read_config, store_config, Config};
+use crate::querier::{query_market_config, query_market_state};
+use crate::state::{
+    read_config, read_smoothed_rate, store_config, store_smoothed_rate, Config, SmoothedRate,
+};
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
 use cosmwasm_bignumber::Decimal256;
 use cosmwasm_bignumber::Uint256;
-use cosmwasm_std::{to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
-use moneymarket::common::optional_addr_validate;
+use cosmwasm_std::{
+    attr, to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
+};
+use moneymarket::common::{optional_addr_validate, round_decimal};
 use moneymarket::interest_model::{
     BorrowRateResponse, ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg,
 };
+use moneymarket::querier::query_balance;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -17,13 +23,23 @@ pub fn instantiate(
     _env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
+    let smoothing_factor = msg.smoothing_factor.unwrap_or_else(Decimal256::one);
+    assert_smoothing_factor(smoothing_factor)?;
+    let market_contract = msg
+        .market_contract
+        .map(|c| deps.api.addr_canonicalize(&c))
+        .transpose()?;
+
     store_config(
         deps.storage,
         &Config {
             owner: deps.api.addr_canonicalize(&msg.owner)?,
             base_rate: msg.base_rate,
             interest_multiplier: msg.interest_multiplier,
+            risk_weight: msg.risk_weight.unwrap_or_else(Decimal256::one),
+            market_contract,
+            smoothing_factor,
         },
     )?;
 
@@ -33,7 +49,7 @@ pub fn instantiate(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
@@ -42,6 +58,9 @@ pub fn execute(
             owner,
             base_rate,
             interest_multiplier,
+            risk_weight,
+            market_contract,
+            smoothing_factor,
         } => {
             let api = deps.api;
             update_config(
@@ -50,17 +69,25 @@ pub fn execute(
                 optional_addr_validate(api, owner)?,
                 base_rate,
                 interest_multiplier,
+                risk_weight,
+                optional_addr_validate(api, market_contract)?,
+                smoothing_factor,
             )
         }
+        ExecuteMsg::UpdateSmoothedRate {} => update_smoothed_rate(deps, env),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_config(
     deps: DepsMut,
     info: MessageInfo,
     owner: Option<Addr>,
     base_rate: Option<Decimal256>,
     interest_multiplier: Option<Decimal256>,
+    risk_weight: Option<Decimal256>,
+    market_contract: Option<Addr>,
+    smoothing_factor: Option<Decimal256>,
 ) -> Result<Response, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
@@ -79,10 +106,80 @@ pub fn update_config(
         config.interest_multiplier = interest_multiplier;
     }
 
+    if let Some(risk_weight) = risk_weight {
+        config.risk_weight = risk_weight;
+    }
+
+    if let Some(market_contract) = market_contract {
+        config.market_contract = Some(deps.api.addr_canonicalize(market_contract.as_str())?);
+    }
+
+    if let Some(smoothing_factor) = smoothing_factor {
+        assert_smoothing_factor(smoothing_factor)?;
+        config.smoothing_factor = smoothing_factor;
+    }
+
     store_config(deps.storage, &config)?;
     Ok(Response::default())
 }
 
+fn assert_smoothing_factor(smoothing_factor: Decimal256) -> Result<(), ContractError> {
+    if smoothing_factor.is_zero() || smoothing_factor > Decimal256::one() {
+        return Err(ContractError::InvalidSmoothingFactor {});
+    }
+
+    Ok(())
+}
+
+/// Anyone can record the rate, the inputs are read from the market itself
+pub fn update_smoothed_rate(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let market = match &config.market_contract {
+        Some(market_contract) => deps.api.addr_humanize(market_contract)?,
+        None => return Err(ContractError::MarketNotRegistered {}),
+    };
+
+    let prev_smoothed_rate = read_smoothed_rate(deps.storage)?;
+    if let Some(prev_smoothed_rate) = &prev_smoothed_rate {
+        if prev_smoothed_rate.last_updated >= env.block.height {
+            return Ok(Response::default());
+        }
+    }
+
+    let market_config = query_market_config(deps.as_ref(), market.clone())?;
+    let market_state = query_market_state(deps.as_ref(), market.clone())?;
+    let market_balance = query_balance(deps.as_ref(), market, market_config.stable_denom)?;
+    let instant_rate = compute_borrow_rate(
+        &config,
+        market_balance,
+        market_state.total_liabilities,
+        market_state.total_reserves,
+    );
+
+    // smoothed_rate = smoothing_factor * instant_rate
+    //     + (1 - smoothing_factor) * prev_smoothed_rate
+    let rate = match prev_smoothed_rate {
+        Some(prev_smoothed_rate) => {
+            instant_rate * config.smoothing_factor
+                + prev_smoothed_rate.rate * (Decimal256::one() - config.smoothing_factor)
+        }
+        None => instant_rate,
+    };
+    store_smoothed_rate(
+        deps.storage,
+        &SmoothedRate {
+            rate,
+            last_updated: env.block.height,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_smoothed_rate"),
+        attr("instant_rate", instant_rate.to_string()),
+        attr("smoothed_rate", rate.to_string()),
+    ]))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
@@ -91,11 +188,25 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             market_balance,
             total_liabilities,
             total_reserves,
+            precision,
         } => to_binary(&query_borrow_rate(
             deps,
             market_balance,
             total_liabilities,
             total_reserves,
+            precision,
+        )?),
+        QueryMsg::InstantBorrowRate {
+            market_balance,
+            total_liabilities,
+            total_reserves,
+            precision,
+        } => to_binary(&query_instant_borrow_rate(
+            deps,
+            market_balance,
+            total_liabilities,
+            total_reserves,
+            precision,
         )?),
     }
 }
@@ -106,6 +217,12 @@ fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
         owner: deps.api.addr_humanize(&state.owner)?.to_string(),
         base_rate: state.base_rate,
         interest_multiplier: state.interest_multiplier,
+        risk_weight: state.risk_weight,
+        market_contract: state
+            .market_contract
+            .map(|c| deps.api.addr_humanize(&c).map(|c| c.to_string()))
+            .transpose()?,
+        smoothing_factor: state.smoothing_factor,
     };
 
     Ok(resp)
@@ -116,9 +233,40 @@ fn query_borrow_rate(
     market_balance: Uint256,
     total_liabilities: Decimal256,
     total_reserves: Decimal256,
+    precision: Option<u32>,
+) -> StdResult<BorrowRateResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let rate = match read_smoothed_rate(deps.storage)? {
+        Some(smoothed_rate) if config.smoothing_factor < Decimal256::one() => smoothed_rate.rate,
+        _ => compute_borrow_rate(&config, market_balance, total_liabilities, total_reserves),
+    };
+
+    Ok(BorrowRateResponse {
+        rate: round_decimal(rate, precision),
+    })
+}
+
+fn query_instant_borrow_rate(
+    deps: Deps,
+    market_balance: Uint256,
+    total_liabilities: Decimal256,
+    total_reserves: Decimal256,
+    precision: Option<u32>,
 ) -> StdResult<BorrowRateResponse> {
     let config: Config = read_config(deps.storage)?;
+    let rate = compute_borrow_rate(&config, market_balance, total_liabilities, total_reserves);
 
+    Ok(BorrowRateResponse {
+        rate: round_decimal(rate, precision),
+    })
+}
+
+fn compute_borrow_rate(
+    config: &Config,
+    market_balance: Uint256,
+    total_liabilities: Decimal256,
+    total_reserves: Decimal256,
+) -> Decimal256 {
     // ignore decimal parts
     let total_value_in_market =
         Decimal256::from_uint256(market_balance) + total_liabilities - total_reserves;
@@ -129,7 +277,9 @@ fn query_borrow_rate(
         total_liabilities / total_value_in_market
     };
 
-    Ok(BorrowRateResponse {
-        rate: utilization_ratio * config.interest_multiplier + config.base_rate,
-    })
+    // weighted_utilization = utilization_ratio * risk_weight
+    // borrow_rate = base_rate + weighted_utilization * interest_multiplier
+    let weighted_utilization = utilization_ratio * config.risk_weight;
+
+    weighted_utilization * config.interest_multiplier + config.base_rate
 }
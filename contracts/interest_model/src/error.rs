@@ -11,4 +11,10 @@ pub enum ContractError {
 
     #[error("Unauthorized")]
     Unauthorized {},
+
+    #[error("Smoothing factor must be greater than 0 and at most 1")]
+    InvalidSmoothingFactor {},
+
+    #[error("No market contract is registered to record the borrow rate of")]
+    MarketNotRegistered {},
 }
@@ -0,0 +1,18 @@
+use cosmwasm_std::{to_binary, Addr, Deps, QueryRequest, StdResult, WasmQuery};
+use moneymarket::market::{
+    ConfigResponse as MarketConfigResponse, QueryMsg as MarketQueryMsg, StateResponse,
+};
+
+pub fn query_market_config(deps: Deps, market_addr: Addr) -> StdResult<MarketConfigResponse> {
+    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: market_addr.to_string(),
+        msg: to_binary(&MarketQueryMsg::Config {})?,
+    }))
+}
+
+pub fn query_market_state(deps: Deps, market_addr: Addr) -> StdResult<StateResponse> {
+    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: market_addr.to_string(),
+        msg: to_binary(&MarketQueryMsg::State { block_height: None })?,
+    }))
+}
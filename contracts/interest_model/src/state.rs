@@ -6,12 +6,22 @@ use cosmwasm_std::{CanonicalAddr, StdResult, Storage};
 use cosmwasm_storage::{singleton, singleton_read};
 
 static KEY_CONFIG: &[u8] = b"config";
+static KEY_SMOOTHED_RATE: &[u8] = b"smoothed_rate";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub owner: CanonicalAddr,
     pub base_rate: Decimal256,
     pub interest_multiplier: Decimal256,
+    pub risk_weight: Decimal256,
+    pub market_contract: Option<CanonicalAddr>,
+    pub smoothing_factor: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SmoothedRate {
+    pub rate: Decimal256,
+    pub last_updated: u64,
 }
 
 pub fn store_config(storage: &mut dyn Storage, config: &Config) -> StdResult<()> {
@@ -21,3 +31,14 @@ pub fn store_config(storage: &mut dyn Storage, config: &Config) -> StdResult<()>
 pub fn read_config(storage: &dyn Storage) -> StdResult<Config> {
     singleton_read(storage, KEY_CONFIG).load()
 }
+
+pub fn store_smoothed_rate(
+    storage: &mut dyn Storage,
+    smoothed_rate: &SmoothedRate,
+) -> StdResult<()> {
+    singleton(storage, KEY_SMOOTHED_RATE).save(smoothed_rate)
+}
+
+pub fn read_smoothed_rate(storage: &dyn Storage) -> StdResult<Option<SmoothedRate>> {
+    singleton_read(storage, KEY_SMOOTHED_RATE).may_load()
+}
@@ -0,0 +1,131 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_binary, from_slice, to_binary, Coin, ContractResult, Empty, OwnedDeps, Querier,
+    QuerierResult, QueryRequest, SystemError, SystemResult, WasmQuery,
+};
+
+use moneymarket::market::{ConfigResponse, QueryMsg, SameBlockRepayPolicy, StateResponse};
+
+/// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
+/// this uses our CustomQuerier.
+pub fn mock_dependencies(
+    contract_balance: &[Coin],
+) -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+    let custom_querier: WasmMockQuerier =
+        WasmMockQuerier::new(MockQuerier::new(&[(MOCK_CONTRACT_ADDR, contract_balance)]));
+
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: custom_querier,
+    }
+}
+
+pub struct WasmMockQuerier {
+    base: MockQuerier<Empty>,
+    market_querier: MarketQuerier,
+}
+
+#[derive(Clone, Default)]
+pub struct MarketQuerier {
+    // (total_liabilities, total_reserves) of the market contract
+    market_state: Option<(Decimal256, Decimal256)>,
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_slice(bin_request) {
+            Ok(v) => v,
+            Err(e) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        self.handle_query(&request)
+    }
+}
+
+impl WasmMockQuerier {
+    pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
+        match &request {
+            QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: _,
+                msg,
+            }) => match (from_binary(msg).unwrap(), self.market_querier.market_state) {
+                (QueryMsg::Config {}, Some(_)) => {
+                    SystemResult::Ok(ContractResult::from(to_binary(&ConfigResponse {
+                        owner_addr: "owner0000".to_string(),
+                        aterra_contract: "AT-uusd".to_string(),
+                        interest_model: MOCK_CONTRACT_ADDR.to_string(),
+                        distribution_model: "distribution".to_string(),
+                        overseer_contract: "overseer".to_string(),
+                        collector_contract: "collector".to_string(),
+                        distributor_contract: "distributor".to_string(),
+                        stable_denom: "uusd".to_string(),
+                        max_borrow_factor: Decimal256::one(),
+                        min_liquidity_ratio: Decimal256::zero(),
+                        repay_dust_epsilon: Uint256::zero(),
+                        blocks_per_year: 4656810,
+                        borrow_fee: Decimal256::zero(),
+                        accrual_block_threshold: 0,
+                        accrue_by_time: false,
+                        reserve_route: None,
+                        flash_fee: Decimal256::zero(),
+                        accepted_repay_denoms: vec!["uusd".to_string()],
+                        yield_strategy: None,
+                        max_deployed_ratio: Decimal256::zero(),
+                        rate_discount_source: None,
+                        rate_discount_tiers: vec![],
+                        min_repay_amount: Uint256::zero(),
+                        repay_protocol_fee: Decimal256::zero(),
+                        commitment_fee: Decimal256::zero(),
+                        max_total_borrow: None,
+                        same_block_repay_policy: SameBlockRepayPolicy::Allow,
+                    })))
+                }
+                (
+                    QueryMsg::State { block_height: _ },
+                    Some((total_liabilities, total_reserves)),
+                ) => SystemResult::Ok(ContractResult::from(to_binary(&StateResponse {
+                    total_liabilities,
+                    total_reserves,
+                    last_interest_updated: 0,
+                    last_reward_updated: 0,
+                    global_interest_index: Decimal256::one(),
+                    global_reward_index: Decimal256::zero(),
+                    anc_emission_rate: Decimal256::zero(),
+                    prev_aterra_supply: Uint256::zero(),
+                    prev_exchange_rate: Decimal256::one(),
+                    liquidity_ratio: Decimal256::one(),
+                    total_deployed: Uint256::zero(),
+                    max_total_borrow: None,
+                }))),
+                _ => SystemResult::Err(SystemError::InvalidRequest {
+                    error: "No market state exists".to_string(),
+                    request: msg.as_slice().into(),
+                }),
+            },
+            _ => self.base.handle_query(request),
+        }
+    }
+}
+
+impl WasmMockQuerier {
+    pub fn new(base: MockQuerier<Empty>) -> Self {
+        WasmMockQuerier {
+            base,
+            market_querier: MarketQuerier::default(),
+        }
+    }
+
+    pub fn with_market_state(&mut self, total_liabilities: Decimal256, total_reserves: Decimal256) {
+        self.market_querier.market_state = Some((total_liabilities, total_reserves));
+    }
+
+    pub fn update_balance(&mut self, addr: &str, balance: Vec<Coin>) {
+        self.base.update_balance(addr, balance);
+    }
+}
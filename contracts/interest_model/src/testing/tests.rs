@@ -0,0 +1,370 @@
+use crate::contract::{execute, instantiate, query};
+use crate::error::ContractError;
+use crate::testing::mock_querier::mock_dependencies;
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::testing::{mock_env, mock_info};
+use cosmwasm_std::{attr, from_binary, Coin, Deps, Uint128};
+use moneymarket::interest_model::{
+    BorrowRateResponse, ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg,
+};
+
+#[test]
+fn proper_initialization() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_rate: Decimal256::percent(10),
+        interest_multiplier: Decimal256::percent(10),
+        risk_weight: None,
+        market_contract: None,
+        smoothing_factor: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+
+    // we can just call .unwrap() to assert this was a success
+    let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(0, res.messages.len());
+
+    // it worked, let's query the state
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let value: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!("owner0000", value.owner.as_str());
+    assert_eq!("0.1", &value.base_rate.to_string());
+    assert_eq!("0.1", &value.interest_multiplier.to_string());
+    assert_eq!("1", &value.risk_weight.to_string());
+
+    let query_msg = QueryMsg::BorrowRate {
+        market_balance: Uint256::from(1000000u128),
+        total_liabilities: Decimal256::from_uint256(500000u128),
+        total_reserves: Decimal256::from_uint256(100000u128),
+        precision: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let value: BorrowRateResponse = from_binary(&res).unwrap();
+    // utilization_ratio = 0.35714285714285714
+    // borrow_rate = 0.035714285 + 0.1
+    assert_eq!("0.135714285714285714", &value.rate.to_string());
+
+    let query_msg = QueryMsg::BorrowRate {
+        market_balance: Uint256::zero(),
+        total_liabilities: Decimal256::zero(),
+        total_reserves: Decimal256::zero(),
+        precision: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let value: BorrowRateResponse = from_binary(&res).unwrap();
+    assert_eq!("0.1", &value.rate.to_string());
+}
+
+#[test]
+fn borrow_rate_precision() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_rate: Decimal256::percent(10),
+        interest_multiplier: Decimal256::percent(10),
+        risk_weight: None,
+        market_contract: None,
+        smoothing_factor: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // borrow_rate = 0.135714285714285714
+    let borrow_rate = |precision: Option<u32>| {
+        let query_msg = QueryMsg::BorrowRate {
+            market_balance: Uint256::from(1000000u128),
+            total_liabilities: Decimal256::from_uint256(500000u128),
+            total_reserves: Decimal256::from_uint256(100000u128),
+            precision,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        from_binary::<BorrowRateResponse>(&res)
+            .unwrap()
+            .rate
+            .to_string()
+    };
+    assert_eq!(borrow_rate(None), "0.135714285714285714");
+    assert_eq!(borrow_rate(Some(18)), "0.135714285714285714");
+    assert_eq!(borrow_rate(Some(9)), "0.135714286");
+    assert_eq!(borrow_rate(Some(4)), "0.1357");
+    assert_eq!(borrow_rate(Some(1)), "0.1");
+    assert_eq!(borrow_rate(Some(0)), "0");
+}
+
+#[test]
+fn update_config() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_rate: Decimal256::percent(10),
+        interest_multiplier: Decimal256::percent(10),
+        risk_weight: None,
+        market_contract: None,
+        smoothing_factor: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // update owner
+    let info = mock_info("owner0000", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: Some("owner0001".to_string()),
+        base_rate: None,
+        interest_multiplier: None,
+        risk_weight: None,
+        market_contract: None,
+        smoothing_factor: None,
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(0, res.messages.len());
+
+    // it worked, let's query the state
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let value: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!("owner0001", value.owner.as_str());
+    assert_eq!("0.1", &value.base_rate.to_string());
+    assert_eq!("0.1", &value.interest_multiplier.to_string());
+
+    // Unauthorized err
+    let info = mock_info("owner0000", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        base_rate: Some(Decimal256::percent(1)),
+        interest_multiplier: Some(Decimal256::percent(1)),
+        risk_weight: None,
+        market_contract: None,
+        smoothing_factor: None,
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("Must return unauthorized error"),
+    }
+}
+
+#[test]
+fn weighted_borrow_rate() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_rate: Decimal256::percent(10),
+        interest_multiplier: Decimal256::percent(10),
+        risk_weight: Some(Decimal256::percent(200)),
+        market_contract: None,
+        smoothing_factor: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let value: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!("2", &value.risk_weight.to_string());
+
+    let query_msg = QueryMsg::BorrowRate {
+        market_balance: Uint256::from(1000000u128),
+        total_liabilities: Decimal256::from_uint256(500000u128),
+        total_reserves: Decimal256::from_uint256(100000u128),
+        precision: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+    let weighted: BorrowRateResponse = from_binary(&res).unwrap();
+    // utilization_ratio = 0.35714285714285714
+    // weighted_utilization = 0.71428571428571428
+    // borrow_rate = 0.071428571 + 0.1
+    assert_eq!("0.171428571428571428", &weighted.rate.to_string());
+
+    // reset the weight to compare against the raw utilization
+    let info = mock_info("owner0000", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        base_rate: None,
+        interest_multiplier: None,
+        risk_weight: Some(Decimal256::one()),
+        market_contract: None,
+        smoothing_factor: None,
+    };
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let unweighted: BorrowRateResponse = from_binary(&res).unwrap();
+    assert_eq!("0.135714285714285714", &unweighted.rate.to_string());
+    assert!(weighted.rate > unweighted.rate);
+}
+
+#[test]
+fn smoothed_borrow_rate() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_rate: Decimal256::percent(10),
+        interest_multiplier: Decimal256::percent(10),
+        risk_weight: None,
+        market_contract: None,
+        smoothing_factor: Some(Decimal256::percent(50)),
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let mut env = mock_env();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::UpdateSmoothedRate {},
+    );
+    match res {
+        Err(ContractError::MarketNotRegistered {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let update_config = |smoothing_factor: Decimal256| ExecuteMsg::UpdateConfig {
+        owner: None,
+        base_rate: None,
+        interest_multiplier: None,
+        risk_weight: None,
+        market_contract: Some("market".to_string()),
+        smoothing_factor: Some(smoothing_factor),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner0000", &[]),
+        update_config(Decimal256::zero()),
+    );
+    match res {
+        Err(ContractError::InvalidSmoothingFactor {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner0000", &[]),
+        update_config(Decimal256::percent(50)),
+    )
+    .unwrap();
+
+    deps.querier.update_balance(
+        "market",
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    deps.querier
+        .with_market_state(Decimal256::zero(), Decimal256::zero());
+
+    let borrow_rate = |deps: Deps, instant: bool| {
+        let market_balance = Uint256::from(1000000u128);
+        let total_liabilities = Decimal256::from_uint256(1000000u128);
+        let total_reserves = Decimal256::zero();
+        let query_msg = if instant {
+            QueryMsg::InstantBorrowRate {
+                market_balance,
+                total_liabilities,
+                total_reserves,
+                precision: None,
+            }
+        } else {
+            QueryMsg::BorrowRate {
+                market_balance,
+                total_liabilities,
+                total_reserves,
+                precision: None,
+            }
+        };
+        let res = query(deps, mock_env(), query_msg).unwrap();
+        from_binary::<BorrowRateResponse>(&res)
+            .unwrap()
+            .rate
+            .to_string()
+    };
+
+    // the instantaneous rate is reported until a rate is recorded
+    // utilization_ratio = 0.5
+    // borrow_rate = 0.05 + 0.1
+    assert_eq!(borrow_rate(deps.as_ref(), false), "0.15");
+
+    // the first recorded rate starts the average
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::UpdateSmoothedRate {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_smoothed_rate"),
+            attr("instant_rate", "0.1"),
+            attr("smoothed_rate", "0.1"),
+        ]
+    );
+    assert_eq!(borrow_rate(deps.as_ref(), false), "0.1");
+
+    // a step change of the utilization is followed halfway per record
+    deps.querier
+        .with_market_state(Decimal256::from_uint256(1000000u128), Decimal256::zero());
+    env.block.height += 1;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::UpdateSmoothedRate {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_smoothed_rate"),
+            attr("instant_rate", "0.15"),
+            attr("smoothed_rate", "0.125"),
+        ]
+    );
+    assert_eq!(borrow_rate(deps.as_ref(), false), "0.125");
+    assert_eq!(borrow_rate(deps.as_ref(), true), "0.15");
+
+    // recorded at most once per block
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::UpdateSmoothedRate {},
+    )
+    .unwrap();
+    assert!(res.attributes.is_empty());
+    assert_eq!(borrow_rate(deps.as_ref(), false), "0.125");
+
+    env.block.height += 1;
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info("addr0000", &[]),
+        ExecuteMsg::UpdateSmoothedRate {},
+    )
+    .unwrap();
+    assert_eq!(borrow_rate(deps.as_ref(), false), "0.1375");
+
+    // without smoothing the instantaneous rate is reported again
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner0000", &[]),
+        update_config(Decimal256::one()),
+    )
+    .unwrap();
+    assert_eq!(borrow_rate(deps.as_ref(), false), "0.15");
+}
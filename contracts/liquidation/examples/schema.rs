@@ -4,8 +4,9 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use moneymarket::liquidation::{
-    BidResponse, BidsResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg,
-    LiquidationAmountResponse, QueryMsg,
+    AuctionResponse, BidResponse, BidsResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg,
+    InstantiateMsg, KeeperFundsResponse, LiquidationAmountResponse, LiquidationConfigResponse,
+    QueryMsg,
 };
 
 fn main() {
@@ -22,4 +23,7 @@ fn main() {
     export_schema(&schema_for!(BidResponse), &out_dir);
     export_schema(&schema_for!(BidsResponse), &out_dir);
     export_schema(&schema_for!(LiquidationAmountResponse), &out_dir);
+    export_schema(&schema_for!(LiquidationConfigResponse), &out_dir);
+    export_schema(&schema_for!(KeeperFundsResponse), &out_dir);
+    export_schema(&schema_for!(AuctionResponse), &out_dir);
 }
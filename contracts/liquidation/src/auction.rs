@@ -0,0 +1,409 @@
+use crate::error::ContractError;
+use crate::state::{
+    read_auction, read_config, read_liquidation_proceeds, read_native_denom, remove_auction,
+    store_auction, store_liquidation_proceeds, Auction, Config,
+};
+
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{
+    attr, to_binary, Addr, BankMsg, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, QueryRequest,
+    Response, StdError, StdResult, WasmMsg, WasmQuery,
+};
+use cw20::Cw20ExecuteMsg;
+use moneymarket::common::mul_ceil;
+use moneymarket::custody::Cw20HookMsg as CustodyCw20HookMsg;
+use moneymarket::liquidation::AuctionResponse;
+use moneymarket::oracle::PriceResponse;
+use moneymarket::overseer::{
+    ExecuteMsg as OverseerExecuteMsg, QueryMsg as OverseerQueryMsg, WhitelistResponse,
+};
+use moneymarket::querier::{deduct_tax, query_price, TimeConstraints};
+use moneymarket::tokens::{TokensHuman, TokensMath, TokensToHuman, TokensToRaw};
+
+pub fn start_auction(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    sub_account: Option<String>,
+    collaterals: TokensHuman,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let duration = config
+        .auction_duration
+        .ok_or(ContractError::AuctionsDisabled {})?;
+    if config.overseer_contract != Some(deps.api.addr_canonicalize(info.sender.as_str())?) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    if read_auction(deps.storage, &borrower_raw)?.is_some() {
+        return Err(ContractError::AuctionAlreadyActive(borrower.to_string()));
+    }
+
+    let collaterals = collaterals.to_raw(deps.as_ref())?;
+    store_auction(
+        deps.storage,
+        &borrower_raw,
+        &Auction {
+            sub_account,
+            collaterals,
+            escrowed: vec![],
+            escrow_sources: vec![],
+            start_height: env.block.height,
+            duration,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "start_auction"),
+        attr("borrower", borrower),
+        attr("start_height", env.block.height.to_string()),
+    ]))
+}
+
+/// Collateral sent by a custody contract for the auction of the borrower,
+/// up to the amount the overseer seized. The sender is the overseer for
+/// native collaterals and a whitelisted custody contract otherwise
+pub fn escrow_auction_collateral(
+    deps: DepsMut,
+    sender: Addr,
+    borrower: Addr,
+    collateral_token: Addr,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let overseer_contract = match &config.overseer_contract {
+        Some(overseer_contract) => deps.api.addr_humanize(overseer_contract)?,
+        None => return Err(ContractError::AuctionsDisabled {}),
+    };
+    if sender != overseer_contract
+        && !query_custody_contracts(deps.as_ref(), &overseer_contract, &collateral_token)?
+            .contains(&sender.to_string())
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut auction: Auction = read_auction(deps.storage, &borrower_raw)?
+        .ok_or_else(|| ContractError::NoActiveAuction(borrower.to_string()))?;
+
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let sender_raw = deps.api.addr_canonicalize(sender.as_str())?;
+    match auction
+        .escrow_sources
+        .iter_mut()
+        .find(|s| s.0 == collateral_token_raw && s.1 == sender_raw)
+    {
+        Some(source) => source.2 += amount,
+        None => auction
+            .escrow_sources
+            .push((collateral_token_raw.clone(), sender_raw, amount)),
+    }
+    auction
+        .escrowed
+        .add(vec![(collateral_token_raw.clone(), amount)]);
+
+    let seized_amount = auction
+        .collaterals
+        .iter()
+        .find(|c| c.0 == collateral_token_raw)
+        .map(|c| c.1)
+        .unwrap_or_else(Uint256::zero);
+    let escrowed_amount = auction
+        .escrowed
+        .iter()
+        .find(|c| c.0 == collateral_token_raw)
+        .map(|c| c.1)
+        .unwrap_or_else(Uint256::zero);
+    if escrowed_amount > seized_amount {
+        return Err(ContractError::EscrowExceedsSeized(
+            collateral_token.to_string(),
+        ));
+    }
+
+    store_auction(deps.storage, &borrower_raw, &auction)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "escrow_auction_collateral"),
+        attr("borrower", borrower),
+        attr("collateral_token", collateral_token),
+        attr("amount", amount),
+    ]))
+}
+
+/// Custody contracts the overseer whitelists for the collateral
+fn query_custody_contracts(
+    deps: Deps,
+    overseer_contract: &Addr,
+    collateral_token: &Addr,
+) -> StdResult<Vec<String>> {
+    let whitelist: WhitelistResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: overseer_contract.to_string(),
+            msg: to_binary(&OverseerQueryMsg::Whitelist {
+                collateral_token: Some(collateral_token.to_string()),
+                start_after: None,
+                limit: None,
+            })?,
+        }))?;
+
+    Ok(whitelist
+        .elems
+        .into_iter()
+        .flat_map(|elem| elem.custody_contracts.into_iter().map(|c| c.0))
+        .collect())
+}
+
+/// Discount of the auction at the block height, growing linearly
+/// from zero to the max premium rate over the auction duration
+fn auction_discount(config: &Config, auction: &Auction, block_height: u64) -> Decimal256 {
+    let elapsed = std::cmp::min(block_height - auction.start_height, auction.duration);
+    config.max_premium_rate * Decimal256::from_ratio(elapsed, auction.duration)
+}
+
+/// Stable amount buying the escrowed collaterals of the auction at the discount
+fn auction_price(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    auction: &Auction,
+    discount: Decimal256,
+) -> StdResult<Uint256> {
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+    let mut collateral_value = Uint256::zero();
+    for (collateral_token, amount) in auction.escrowed.iter() {
+        let price: PriceResponse = query_price(
+            deps,
+            oracle_contract.clone(),
+            deps.api.addr_humanize(collateral_token)?.to_string(),
+            config.stable_denom.clone(),
+            Some(TimeConstraints {
+                block_time: env.block.time.seconds(),
+                valid_timeframe: config.price_timeframe,
+            }),
+        )?;
+        collateral_value += *amount * price.rate;
+    }
+
+    Ok(collateral_value * (Decimal256::one() - discount))
+}
+
+pub fn bid_auction(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let auction: Auction = read_auction(deps.storage, &borrower_raw)?
+        .ok_or_else(|| ContractError::NoActiveAuction(borrower.to_string()))?;
+    if env.block.height > auction.start_height + auction.duration {
+        return Err(ContractError::AuctionExpired(borrower.to_string()));
+    }
+
+    let overseer_contract = match &config.overseer_contract {
+        Some(overseer_contract) => deps.api.addr_humanize(overseer_contract)?,
+        None => return Err(ContractError::AuctionsDisabled {}),
+    };
+
+    let discount = auction_discount(&config, &auction, env.block.height);
+    let required_stable = auction_price(deps.as_ref(), &env, &config, &auction, discount)?;
+    let amount: Uint256 = Uint256::from(
+        info.funds
+            .iter()
+            .find(|c| c.denom == config.stable_denom)
+            .map(|c| c.amount)
+            .ok_or_else(|| ContractError::AssetNotProvided(config.stable_denom.clone()))?,
+    );
+    if amount < required_stable {
+        return Err(ContractError::InsufficientBidBalance(
+            required_stable.into(),
+        ));
+    }
+
+    let bid_fee = if config.conservative_rounding {
+        std::cmp::min(mul_ceil(required_stable, config.bid_fee), required_stable)
+    } else {
+        required_stable * config.bid_fee
+    };
+    let repay_amount = required_stable - bid_fee;
+
+    // The won collaterals stay in escrow until the bidder claims them
+    remove_auction(deps.storage, &borrower_raw);
+    let bidder_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    for (collateral_token, collateral_amount) in auction.escrowed.iter() {
+        let proceeds = read_liquidation_proceeds(deps.storage, &bidder_raw, collateral_token);
+        store_liquidation_proceeds(
+            deps.storage,
+            &bidder_raw,
+            collateral_token,
+            proceeds + *collateral_amount,
+        )?;
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: overseer_contract.to_string(),
+        funds: vec![deduct_tax(
+            deps.as_ref(),
+            Coin {
+                denom: config.stable_denom.clone(),
+                amount: repay_amount.into(),
+            },
+        )?],
+        msg: to_binary(&OverseerExecuteMsg::RepayAuctionProceeds {
+            borrower: borrower.to_string(),
+            sub_account: auction.sub_account,
+        })?,
+    })];
+
+    let fee_address = match config.liquidation_fee_recipient {
+        Some(recipient) => deps.api.addr_humanize(&recipient)?,
+        None => overseer_contract,
+    };
+    if !bid_fee.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: fee_address.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom.clone(),
+                    amount: bid_fee.into(),
+                },
+            )?],
+        }));
+    }
+
+    // Refund the funds above the auction price
+    if amount > required_stable {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom.clone(),
+                    amount: (amount - required_stable).into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "bid_auction"),
+        attr("borrower", borrower),
+        attr("bidder", info.sender),
+        attr("discount", discount.to_string()),
+        attr("repay_amount", repay_amount),
+        attr("bid_fee", bid_fee),
+    ]))
+}
+
+/// Returns the collaterals of an expired auction nobody bid on to the
+/// position, the custody contracts take the cw20 collaterals back before
+/// the overseer locks them again
+pub fn settle_auction(deps: DepsMut, env: Env, borrower: Addr) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let auction: Auction = read_auction(deps.storage, &borrower_raw)?
+        .ok_or_else(|| ContractError::NoActiveAuction(borrower.to_string()))?;
+    if env.block.height <= auction.start_height + auction.duration {
+        return Err(ContractError::AuctionNotExpired(borrower.to_string()));
+    }
+
+    let overseer_contract = match &config.overseer_contract {
+        Some(overseer_contract) => deps.api.addr_humanize(overseer_contract)?,
+        None => return Err(ContractError::AuctionsDisabled {}),
+    };
+
+    remove_auction(deps.storage, &borrower_raw);
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut native_funds: Vec<Coin> = vec![];
+    let mut collaterals: Vec<(String, String, Uint256)> = vec![];
+    for (collateral_token, source, amount) in auction.escrow_sources.iter() {
+        let collateral_token_human = deps.api.addr_humanize(collateral_token)?;
+        let source_human = deps.api.addr_humanize(source)?;
+        match read_native_denom(deps.storage, collateral_token)? {
+            Some(denom) => native_funds.push(Coin {
+                denom,
+                amount: (*amount).into(),
+            }),
+            None => messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: collateral_token_human.to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: source_human.to_string(),
+                    amount: (*amount).into(),
+                    msg: to_binary(&CustodyCw20HookMsg::DepositCollateralFor {
+                        borrower: borrower.to_string(),
+                    })?,
+                })?,
+            })),
+        }
+        collaterals.push((
+            collateral_token_human.to_string(),
+            source_human.to_string(),
+            *amount,
+        ));
+    }
+
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: overseer_contract.to_string(),
+        funds: native_funds,
+        msg: to_binary(&OverseerExecuteMsg::ReturnAuctionCollateral {
+            borrower: borrower.to_string(),
+            sub_account: auction.sub_account,
+            collaterals,
+        })?,
+    }));
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "settle_auction"),
+        attr("borrower", borrower),
+        attr("recipient", overseer_contract),
+    ]))
+}
+
+pub fn query_auction(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    bid_amount: Option<Uint256>,
+) -> StdResult<AuctionResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let auction: Auction = read_auction(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+    )?
+    .ok_or_else(|| StdError::generic_err(format!("No auction of {} is active", borrower)))?;
+
+    let blocks_elapsed = env.block.height - auction.start_height;
+    let blocks_remaining = auction.duration.saturating_sub(blocks_elapsed);
+    let discount = auction_discount(&config, &auction, env.block.height);
+    let auction_price = auction_price(deps, &env, &config, &auction, discount)?;
+
+    // The bid amount buys the same share of every escrowed collateral
+    let mut implied_collaterals = auction.escrowed.clone();
+    if let Some(bid_amount) = bid_amount {
+        if bid_amount < auction_price {
+            let share =
+                Decimal256::from_uint256(bid_amount) / Decimal256::from_uint256(auction_price);
+            for collateral in implied_collaterals.iter_mut() {
+                collateral.1 = collateral.1 * share;
+            }
+        }
+    }
+
+    Ok(AuctionResponse {
+        borrower: borrower.to_string(),
+        sub_account: auction.sub_account,
+        collaterals: auction.escrowed.to_human(deps)?,
+        start_height: auction.start_height,
+        discount,
+        blocks_elapsed,
+        blocks_remaining,
+        auction_price,
+        implied_collaterals: implied_collaterals.to_human(deps)?,
+    })
+}
@@ -1,16 +1,19 @@
 use crate::error::ContractError;
 use crate::state::{
-    read_bid, read_bids_by_collateral, read_bids_by_user, read_config, remove_bid, store_bid, Bid,
+    read_all_liquidation_proceeds, read_bid, read_bids_by_collateral, read_bids_by_user,
+    read_config, read_keeper_funds, read_liquidation_proceeds, read_native_denom, remove_bid,
+    remove_liquidation_proceeds, store_bid, store_keeper_funds, store_liquidation_proceeds, Bid,
     Config,
 };
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    attr, to_binary, Addr, BankMsg, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response,
-    StdResult, WasmMsg,
+    attr, to_binary, Addr, BankMsg, CanonicalAddr, Coin, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Response, StdResult, WasmMsg,
 };
 use cw20::Cw20ExecuteMsg;
-use moneymarket::liquidation::{BidResponse, BidsResponse};
+use moneymarket::common::mul_ceil;
+use moneymarket::liquidation::{BidResponse, BidsResponse, KeeperFundsResponse};
 use moneymarket::oracle::PriceResponse;
 use moneymarket::querier::{deduct_tax, query_price, TimeConstraints};
 
@@ -107,6 +110,7 @@ pub fn retract_bid(
         ]))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute_bid(
     deps: DepsMut,
     env: Env,
@@ -115,11 +119,22 @@ pub fn execute_bid(
     fee_address: Addr,
     collateral_token: Addr,
     amount: Uint256,
+    premium_rate: Option<Decimal256>,
+    self_liquidation: bool,
 ) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
     let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
     let bidder_raw = deps.api.addr_canonicalize(liquidator.as_str())?;
-    let bid: Bid = read_bid(deps.storage, &bidder_raw, &collateral_token_raw)?;
+    // The premium rate of the collateral passed by the overseer
+    // overrides the max premium rate
+    let max_premium_rate = premium_rate.unwrap_or(config.max_premium_rate);
+    // Without a bid for the collateral, the keeper funds
+    // of the liquidator pay at the max premium rate
+    let bid: Option<Bid> = read_bid(deps.storage, &bidder_raw, &collateral_token_raw).ok();
+    let premium_rate = bid
+        .as_ref()
+        .map(|bid| std::cmp::min(bid.premium_rate, max_premium_rate))
+        .unwrap_or(max_premium_rate);
 
     let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
     let price: PriceResponse = query_price(
@@ -134,53 +149,83 @@ pub fn execute_bid(
     )?;
 
     let collateral_value = amount * price.rate;
-    let required_stable = collateral_value
-        * (Decimal256::one() - std::cmp::min(bid.premium_rate, config.max_premium_rate));
-    if required_stable > bid.amount {
-        return Err(ContractError::InsufficientBidBalance(
-            required_stable.into(),
-        ));
-    }
+    let required_stable = collateral_value * (Decimal256::one() - premium_rate);
+    match bid {
+        Some(bid) => {
+            if required_stable > bid.amount {
+                return Err(ContractError::InsufficientBidBalance(
+                    required_stable.into(),
+                ));
+            }
 
-    // Update bid
-    if bid.amount == required_stable {
-        remove_bid(deps.storage, &bidder_raw, &collateral_token_raw);
-    } else {
-        store_bid(
-            deps.storage,
-            &bidder_raw,
-            &collateral_token_raw,
-            Bid {
-                amount: bid.amount - required_stable,
-                ..bid
-            },
-        )?;
+            // Update bid
+            if bid.amount == required_stable {
+                remove_bid(deps.storage, &bidder_raw, &collateral_token_raw);
+            } else {
+                store_bid(
+                    deps.storage,
+                    &bidder_raw,
+                    &collateral_token_raw,
+                    Bid {
+                        amount: bid.amount - required_stable,
+                        ..bid
+                    },
+                )?;
+            }
+        }
+        None => {
+            let keeper_funds = read_keeper_funds(deps.storage, &bidder_raw);
+            if required_stable > keeper_funds {
+                return Err(ContractError::InsufficientKeeperFunds(
+                    required_stable.into(),
+                ));
+            }
+
+            store_keeper_funds(deps.storage, &bidder_raw, keeper_funds - required_stable)?;
+        }
     }
 
-    let bid_fee = required_stable * config.bid_fee;
+    // A borrower bidding for its own collateral keeps the premium, the
+    // bid fee is all the liquidation costs it and may be reduced
+    let bid_fee_rate = match config.self_liquidation_fee {
+        Some(self_liquidation_fee) if self_liquidation => self_liquidation_fee,
+        _ => config.bid_fee,
+    };
+
+    // Under conservative rounding the fee rounds up,
+    // so the amount credited to the loan rounds down
+    let bid_fee = if config.conservative_rounding {
+        std::cmp::min(mul_ceil(required_stable, bid_fee_rate), required_stable)
+    } else {
+        required_stable * bid_fee_rate
+    };
     let repay_amount = required_stable - bid_fee;
 
-    let mut messages: Vec<CosmosMsg> = vec![
-        CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: collateral_token.to_string(),
-            funds: vec![],
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: liquidator.to_string(),
-                amount: amount.into(),
-            })?,
-        }),
-        CosmosMsg::Bank(BankMsg::Send {
-            to_address: repay_address.to_string(),
-            amount: vec![deduct_tax(
-                deps.as_ref(),
-                Coin {
-                    denom: config.stable_denom.clone(),
-                    amount: repay_amount.into(),
-                },
-            )?],
-        }),
-    ];
+    // The won collateral stays in escrow until the bidder claims it
+    let proceeds = read_liquidation_proceeds(deps.storage, &bidder_raw, &collateral_token_raw);
+    store_liquidation_proceeds(
+        deps.storage,
+        &bidder_raw,
+        &collateral_token_raw,
+        proceeds + amount,
+    )?;
 
+    let mut messages: Vec<CosmosMsg> = vec![CosmosMsg::Bank(BankMsg::Send {
+        to_address: repay_address.to_string(),
+        amount: vec![deduct_tax(
+            deps.as_ref(),
+            Coin {
+                denom: config.stable_denom.clone(),
+                amount: repay_amount.into(),
+            },
+        )?],
+    })];
+
+    // The configured recipient takes precedence over the fee address of the bid
+    let fee_address = match config.liquidation_fee_recipient {
+        Some(recipient) => deps.api.addr_humanize(&recipient)?,
+        None => fee_address,
+    };
     if !bid_fee.is_zero() {
         messages.push(CosmosMsg::Bank(BankMsg::Send {
             to_address: fee_address.to_string(),
@@ -204,6 +249,138 @@ pub fn execute_bid(
     ]))
 }
 
+pub fn deposit_keeper_funds(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let amount: Uint256 = Uint256::from(
+        info.funds
+            .iter()
+            .find(|c| c.denom == config.stable_denom)
+            .map(|c| c.amount)
+            .ok_or(ContractError::AssetNotProvided(config.stable_denom))?,
+    );
+
+    let keeper_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let keeper_funds = read_keeper_funds(deps.storage, &keeper_raw) + amount;
+    store_keeper_funds(deps.storage, &keeper_raw, keeper_funds)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "deposit_keeper_funds"),
+        attr("keeper", info.sender),
+        attr("amount", amount),
+        attr("keeper_funds", keeper_funds),
+    ]))
+}
+
+pub fn withdraw_keeper_funds(
+    deps: DepsMut,
+    info: MessageInfo,
+    amount: Option<Uint256>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let keeper_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let keeper_funds = read_keeper_funds(deps.storage, &keeper_raw);
+
+    let amount = amount.unwrap_or(keeper_funds);
+    if amount > keeper_funds {
+        return Err(ContractError::WithdrawExceedsKeeperFunds(
+            keeper_funds.into(),
+        ));
+    }
+    store_keeper_funds(deps.storage, &keeper_raw, keeper_funds - amount)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: amount.into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "withdraw_keeper_funds"),
+        attr("keeper", info.sender),
+        attr("amount", amount),
+    ]))
+}
+
+pub fn claim_liquidation_proceeds(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let bidder_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let proceeds = read_all_liquidation_proceeds(deps.storage, &bidder_raw)?;
+    if proceeds.is_empty() {
+        return Err(ContractError::NoLiquidationProceeds {});
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut claimed_logs: Vec<String> = vec![];
+    for (collateral_token_raw, amount) in proceeds {
+        remove_liquidation_proceeds(deps.storage, &bidder_raw, &collateral_token_raw);
+
+        let collateral_token = deps.api.addr_humanize(&collateral_token_raw)?;
+        claimed_logs.push(format!("{}{}", amount, collateral_token));
+        messages.push(collateral_transfer_msg(
+            deps.as_ref(),
+            &collateral_token_raw,
+            &info.sender,
+            amount,
+        )?);
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "claim_liquidation_proceeds"),
+        attr("liquidator", info.sender),
+        attr("claimed_amounts", claimed_logs.join(",")),
+    ]))
+}
+
+/// Transfer of a collateral out of the contract, native collaterals
+/// are paid out in the denom the overseer sent them in
+pub(crate) fn collateral_transfer_msg(
+    deps: Deps,
+    collateral_token: &CanonicalAddr,
+    recipient: &Addr,
+    amount: Uint256,
+) -> StdResult<CosmosMsg> {
+    if let Some(denom) = read_native_denom(deps.storage, collateral_token)? {
+        return Ok(CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![deduct_tax(
+                deps,
+                Coin {
+                    denom,
+                    amount: amount.into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: deps.api.addr_humanize(collateral_token)?.to_string(),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: recipient.to_string(),
+            amount: amount.into(),
+        })?,
+    }))
+}
+
+pub fn query_keeper_funds(deps: Deps, keeper: Addr) -> StdResult<KeeperFundsResponse> {
+    let amount = read_keeper_funds(deps.storage, &deps.api.addr_canonicalize(keeper.as_str())?);
+
+    Ok(KeeperFundsResponse {
+        keeper: keeper.to_string(),
+        amount,
+    })
+}
+
 pub fn query_bid(deps: Deps, collateral_token: Addr, bidder: Addr) -> StdResult<BidResponse> {
     let bid: Bid = read_bid(
         deps.storage,
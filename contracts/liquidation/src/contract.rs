@@ -1,20 +1,27 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
+use crate::auction::{
+    bid_auction, escrow_auction_collateral, query_auction, settle_auction, start_auction,
+};
 use crate::bid::{
-    execute_bid, query_bid, query_bids_by_collateral, query_bids_by_user, retract_bid, submit_bid,
+    claim_liquidation_proceeds, deposit_keeper_funds, execute_bid, query_bid,
+    query_bids_by_collateral, query_bids_by_user, query_keeper_funds, retract_bid, submit_bid,
+    withdraw_keeper_funds,
 };
 use crate::error::ContractError;
-use crate::state::{read_config, store_config, Config};
+use crate::state::{read_config, store_config, store_native_denom, Config};
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
+    from_binary, to_binary, Addr, Api, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError,
+    StdResult,
 };
 use cw20::Cw20ReceiveMsg;
-use moneymarket::common::optional_addr_validate;
+use moneymarket::common::{mul_ceil, optional_addr_validate};
 use moneymarket::liquidation::{
-    ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, LiquidationAmountResponse, QueryMsg,
+    ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, LiquidationAmountResponse,
+    LiquidationConfigResponse, QueryMsg, UpdateConfigMsg,
 };
 use moneymarket::querier::query_tax_rate;
 use moneymarket::tokens::TokensHuman;
@@ -25,7 +32,25 @@ pub fn instantiate(
     _env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
+    let liquidation_fee_recipient = match msg.liquidation_fee_recipient {
+        Some(recipient) => Some(
+            deps.api
+                .addr_canonicalize(assert_fee_recipient(deps.api, recipient)?.as_str())?,
+        ),
+        None => None,
+    };
+    if let Some(post_liquidation_safe_ltv) = msg.post_liquidation_safe_ltv {
+        assert_post_liquidation_safe_ltv(post_liquidation_safe_ltv)?;
+    }
+    if let Some(auction_duration) = msg.auction_duration {
+        assert_auction_duration(auction_duration)?;
+    }
+    assert_self_liquidation_fee(msg.self_liquidation_fee, msg.bid_fee)?;
+    let overseer_contract = msg
+        .overseer_contract
+        .map(|c| deps.api.addr_canonicalize(&c))
+        .transpose()?;
     store_config(
         deps.storage,
         &Config {
@@ -37,6 +62,13 @@ pub fn instantiate(
             max_premium_rate: msg.max_premium_rate,
             liquidation_threshold: msg.liquidation_threshold,
             price_timeframe: msg.price_timeframe,
+            liquidation_fee_recipient,
+            max_liquidation_value: msg.max_liquidation_value,
+            conservative_rounding: msg.conservative_rounding.unwrap_or(true),
+            post_liquidation_safe_ltv: msg.post_liquidation_safe_ltv,
+            overseer_contract,
+            auction_duration: msg.auction_duration,
+            self_liquidation_fee: msg.self_liquidation_fee,
         },
     )?;
 
@@ -52,17 +84,32 @@ pub fn execute(
 ) -> Result<Response, ContractError> {
     match msg {
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
-        ExecuteMsg::UpdateConfig {
-            owner,
-            oracle_contract,
-            stable_denom,
-            safe_ratio,
-            bid_fee,
-            max_premium_rate,
-            liquidation_threshold,
-            price_timeframe,
-        } => {
+        ExecuteMsg::UpdateConfig(msg) => {
+            let UpdateConfigMsg {
+                owner,
+                oracle_contract,
+                stable_denom,
+                safe_ratio,
+                bid_fee,
+                max_premium_rate,
+                liquidation_threshold,
+                price_timeframe,
+                liquidation_fee_recipient,
+                max_liquidation_value,
+                conservative_rounding,
+                post_liquidation_safe_ltv,
+                overseer_contract,
+                auction_duration,
+                self_liquidation_fee,
+            } = *msg;
             let api = deps.api;
+            let liquidation_fee_recipient = liquidation_fee_recipient
+                .map(|recipient| {
+                    recipient
+                        .map(|recipient| assert_fee_recipient(api, recipient))
+                        .transpose()
+                })
+                .transpose()?;
             update_config(
                 deps,
                 info,
@@ -74,6 +121,13 @@ pub fn execute(
                 max_premium_rate,
                 liquidation_threshold,
                 price_timeframe,
+                liquidation_fee_recipient,
+                max_liquidation_value,
+                conservative_rounding,
+                post_liquidation_safe_ltv,
+                optional_addr_validate(api, overseer_contract)?,
+                auction_duration,
+                self_liquidation_fee,
             )
         }
         ExecuteMsg::SubmitBid {
@@ -95,6 +149,55 @@ pub fn execute(
             let api = deps.api;
             retract_bid(deps, info, api.addr_validate(&collateral_token)?, amount)
         }
+        ExecuteMsg::ClaimLiquidationProceeds {} => claim_liquidation_proceeds(deps, info),
+        ExecuteMsg::DepositKeeperFunds {} => deposit_keeper_funds(deps, info),
+        ExecuteMsg::WithdrawKeeperFunds { amount } => withdraw_keeper_funds(deps, info, amount),
+        ExecuteMsg::StartAuction {
+            borrower,
+            sub_account,
+            collaterals,
+        } => {
+            let api = deps.api;
+            start_auction(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                sub_account,
+                collaterals,
+            )
+        }
+        ExecuteMsg::Bid { borrower } => {
+            let api = deps.api;
+            bid_auction(deps, env, info, api.addr_validate(&borrower)?)
+        }
+        ExecuteMsg::ExecuteBid {
+            collateral_token,
+            liquidator,
+            fee_address,
+            repay_address,
+            borrower,
+            premium_rate,
+        } => {
+            let api = deps.api;
+            execute_native_bid(
+                deps,
+                env,
+                info,
+                api.addr_validate(&collateral_token)?,
+                Cw20HookMsg::ExecuteBid {
+                    liquidator,
+                    fee_address,
+                    repay_address,
+                    borrower,
+                    premium_rate,
+                },
+            )
+        }
+        ExecuteMsg::SettleAuction { borrower } => {
+            let api = deps.api;
+            settle_auction(deps, env, api.addr_validate(&borrower)?)
+        }
     }
 }
 
@@ -106,29 +209,133 @@ pub fn receive_cw20(
 ) -> Result<Response, ContractError> {
     let contract_addr = info.sender;
     match from_binary(&cw20_msg.msg) {
-        Ok(Cw20HookMsg::ExecuteBid {
-            liquidator,
-            repay_address,
-            fee_address,
-        }) => {
-            let collateral_token = contract_addr.to_string();
-            let repay_address = repay_address.unwrap_or_else(|| cw20_msg.sender.clone());
-            let fee_address = fee_address.unwrap_or_else(|| cw20_msg.sender.clone());
+        Ok(hook_msg) => execute_bid_hook(
+            deps,
+            env,
+            cw20_msg.sender,
+            contract_addr.to_string(),
+            cw20_msg.amount.into(),
+            hook_msg,
+        ),
+        _ => Err(ContractError::MissingExecuteBidHook {}),
+    }
+}
 
-            let api = deps.api;
+/// Native collaterals held by the overseer are sent with the bid message
+/// instead of a CW20 hook, their denom is kept to pay the proceeds out
+pub fn execute_native_bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral_token: Addr,
+    hook_msg: Cw20HookMsg,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if config.overseer_contract != Some(deps.api.addr_canonicalize(info.sender.as_str())?) {
+        return Err(ContractError::Unauthorized {});
+    }
 
-            execute_bid(
-                deps,
-                env,
-                api.addr_validate(&liquidator)?,
-                api.addr_validate(&repay_address)?,
-                api.addr_validate(&fee_address)?,
-                api.addr_validate(&collateral_token)?,
-                cw20_msg.amount.into(),
-            )
-        }
-        _ => Err(ContractError::MissingExecuteBidHook {}),
+    if info.funds.len() != 1 {
+        return Err(ContractError::NativeCollateralNotProvided {});
     }
+    let collateral = &info.funds[0];
+    store_native_denom(
+        deps.storage,
+        &deps.api.addr_canonicalize(collateral_token.as_str())?,
+        &collateral.denom,
+    )?;
+
+    execute_bid_hook(
+        deps,
+        env,
+        info.sender.to_string(),
+        collateral_token.to_string(),
+        collateral.amount.into(),
+        hook_msg,
+    )
+}
+
+fn execute_bid_hook(
+    deps: DepsMut,
+    env: Env,
+    sender: String,
+    collateral_token: String,
+    amount: Uint256,
+    hook_msg: Cw20HookMsg,
+) -> Result<Response, ContractError> {
+    let Cw20HookMsg::ExecuteBid {
+        liquidator,
+        repay_address,
+        fee_address,
+        borrower,
+        premium_rate,
+    } = hook_msg;
+
+    // Collaterals seized for an auction are escrowed under it
+    if liquidator == env.contract.address.as_str() {
+        let borrower = borrower.ok_or(ContractError::AuctionBorrowerNotProvided {})?;
+        let api = deps.api;
+        return escrow_auction_collateral(
+            deps,
+            api.addr_validate(&sender)?,
+            api.addr_validate(&borrower)?,
+            api.addr_validate(&collateral_token)?,
+            amount,
+        );
+    }
+
+    let repay_address = repay_address.unwrap_or_else(|| sender.clone());
+    let fee_address = fee_address.unwrap_or(sender);
+    let self_liquidation = borrower.as_deref() == Some(liquidator.as_str());
+
+    let api = deps.api;
+
+    execute_bid(
+        deps,
+        env,
+        api.addr_validate(&liquidator)?,
+        api.addr_validate(&repay_address)?,
+        api.addr_validate(&fee_address)?,
+        api.addr_validate(&collateral_token)?,
+        amount,
+        premium_rate,
+        self_liquidation,
+    )
+}
+
+fn assert_fee_recipient(api: &dyn Api, recipient: String) -> Result<Addr, ContractError> {
+    if recipient.trim().is_empty() {
+        return Err(ContractError::EmptyFeeRecipient {});
+    }
+
+    Ok(api.addr_validate(&recipient)?)
+}
+
+fn assert_post_liquidation_safe_ltv(safe_ltv: Decimal256) -> Result<(), ContractError> {
+    if safe_ltv >= Decimal256::one() {
+        return Err(ContractError::InvalidPostLiquidationSafeLtv {});
+    }
+
+    Ok(())
+}
+
+fn assert_auction_duration(auction_duration: u64) -> Result<(), ContractError> {
+    if auction_duration == 0 {
+        return Err(ContractError::InvalidAuctionDuration {});
+    }
+
+    Ok(())
+}
+
+fn assert_self_liquidation_fee(
+    self_liquidation_fee: Option<Decimal256>,
+    bid_fee: Decimal256,
+) -> Result<(), ContractError> {
+    if matches!(self_liquidation_fee, Some(fee) if fee > bid_fee) {
+        return Err(ContractError::InvalidSelfLiquidationFee {});
+    }
+
+    Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -143,6 +350,13 @@ pub fn update_config(
     max_premium_rate: Option<Decimal256>,
     liquidation_threshold: Option<Uint256>,
     price_timeframe: Option<u64>,
+    liquidation_fee_recipient: Option<Option<Addr>>,
+    max_liquidation_value: Option<Uint256>,
+    conservative_rounding: Option<bool>,
+    post_liquidation_safe_ltv: Option<Decimal256>,
+    overseer_contract: Option<Addr>,
+    auction_duration: Option<u64>,
+    self_liquidation_fee: Option<Decimal256>,
 ) -> Result<Response, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
@@ -181,12 +395,45 @@ pub fn update_config(
         config.price_timeframe = price_timeframe;
     }
 
+    if let Some(liquidation_fee_recipient) = liquidation_fee_recipient {
+        config.liquidation_fee_recipient = liquidation_fee_recipient
+            .map(|recipient| deps.api.addr_canonicalize(recipient.as_str()))
+            .transpose()?;
+    }
+
+    if let Some(max_liquidation_value) = max_liquidation_value {
+        config.max_liquidation_value = Some(max_liquidation_value);
+    }
+
+    if let Some(conservative_rounding) = conservative_rounding {
+        config.conservative_rounding = conservative_rounding;
+    }
+
+    if let Some(post_liquidation_safe_ltv) = post_liquidation_safe_ltv {
+        assert_post_liquidation_safe_ltv(post_liquidation_safe_ltv)?;
+        config.post_liquidation_safe_ltv = Some(post_liquidation_safe_ltv);
+    }
+
+    if let Some(overseer_contract) = overseer_contract {
+        config.overseer_contract = Some(deps.api.addr_canonicalize(overseer_contract.as_str())?);
+    }
+
+    if let Some(auction_duration) = auction_duration {
+        assert_auction_duration(auction_duration)?;
+        config.auction_duration = Some(auction_duration);
+    }
+
+    if let Some(self_liquidation_fee) = self_liquidation_fee {
+        config.self_liquidation_fee = Some(self_liquidation_fee);
+    }
+    assert_self_liquidation_fee(config.self_liquidation_fee, config.bid_fee)?;
+
     store_config(deps.storage, &config)?;
     Ok(Response::default())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::LiquidationAmount {
@@ -194,12 +441,14 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             borrow_limit,
             collaterals,
             collateral_prices,
+            premium_rates,
         } => to_binary(&query_liquidation_amount(
             deps,
             borrow_amount,
             borrow_limit,
             collaterals,
             collateral_prices,
+            premium_rates,
         )?),
         QueryMsg::Bid {
             collateral_token,
@@ -237,6 +486,18 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
                 limit,
             )?)
         }
+        QueryMsg::KeeperFunds { keeper } => {
+            let keeper_addr = deps.api.addr_validate(&keeper)?;
+            to_binary(&query_keeper_funds(deps, keeper_addr)?)
+        }
+        QueryMsg::LiquidationConfig {} => to_binary(&query_liquidation_config(deps)?),
+        QueryMsg::Auction {
+            borrower,
+            bid_amount,
+        } => {
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            to_binary(&query_auction(deps, env, borrower_addr, bid_amount)?)
+        }
     }
 }
 
@@ -251,6 +512,41 @@ fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
         max_premium_rate: config.max_premium_rate,
         liquidation_threshold: config.liquidation_threshold,
         price_timeframe: config.price_timeframe,
+        liquidation_fee_recipient: config
+            .liquidation_fee_recipient
+            .map(|r| deps.api.addr_humanize(&r).map(|r| r.to_string()))
+            .transpose()?,
+        max_liquidation_value: config.max_liquidation_value,
+        conservative_rounding: config.conservative_rounding,
+        post_liquidation_safe_ltv: config.post_liquidation_safe_ltv,
+        overseer_contract: config
+            .overseer_contract
+            .map(|c| deps.api.addr_humanize(&c).map(|c| c.to_string()))
+            .transpose()?,
+        auction_duration: config.auction_duration,
+        self_liquidation_fee: config.self_liquidation_fee,
+    };
+
+    Ok(resp)
+}
+
+fn query_liquidation_config(deps: Deps) -> StdResult<LiquidationConfigResponse> {
+    let config = read_config(deps.storage)?;
+    let resp = LiquidationConfigResponse {
+        stable_denom: config.stable_denom,
+        safe_ratio: config.safe_ratio,
+        bid_fee: config.bid_fee,
+        max_premium_rate: config.max_premium_rate,
+        liquidation_threshold: config.liquidation_threshold,
+        price_timeframe: config.price_timeframe,
+        liquidation_fee_recipient: config
+            .liquidation_fee_recipient
+            .map(|r| deps.api.addr_humanize(&r).map(|r| r.to_string()))
+            .transpose()?,
+        max_liquidation_value: config.max_liquidation_value,
+        conservative_rounding: config.conservative_rounding,
+        post_liquidation_safe_ltv: config.post_liquidation_safe_ltv,
+        auction_duration: config.auction_duration,
     };
 
     Ok(resp)
@@ -262,6 +558,7 @@ fn query_liquidation_amount(
     borrow_limit: Uint256,
     collaterals: TokensHuman,
     collateral_prices: Vec<Decimal256>,
+    premium_rates: Option<Vec<Decimal256>>,
 ) -> StdResult<LiquidationAmountResponse> {
     let config: Config = read_config(deps.storage)?;
 
@@ -281,29 +578,71 @@ fn query_liquidation_amount(
 
     let tax_rate = query_tax_rate(deps)?;
 
-    let fee_deductor = (Decimal256::one() - config.max_premium_rate)
-        * (Decimal256::one() - config.bid_fee)
-        * (Decimal256::one() - tax_rate);
+    let fee_deductor = |premium_rate: Decimal256| {
+        (Decimal256::one() - premium_rate)
+            * (Decimal256::one() - config.bid_fee)
+            * (Decimal256::one() - tax_rate)
+    };
 
     // expected_repay_amount must be bigger than borrow_amount
     // else force liquidate all collaterals
-    let expected_repay_amount = collaterals_value * fee_deductor;
-    if expected_repay_amount <= borrow_amount {
-        return Ok(LiquidationAmountResponse { collaterals });
-    }
+    let expected_repay_amount = match premium_rates {
+        None => collaterals_value * fee_deductor(config.max_premium_rate),
+        Some(premium_rates) => {
+            if premium_rates.len() != collaterals.len() {
+                return Err(StdError::generic_err(
+                    "premium_rates must match the collaterals",
+                ));
+            }
 
-    // When collaterals_value is smaller than liquidation_threshold,
-    // liquidate all collaterals
-    let safe_borrow_amount = borrow_limit * config.safe_ratio;
-    let liquidation_ratio = if collaterals_value < config.liquidation_threshold {
-        Decimal256::from_uint256(borrow_amount) / Decimal256::from_uint256(expected_repay_amount)
+            collaterals
+                .iter()
+                .zip(collateral_prices.iter())
+                .zip(premium_rates)
+                .fold(
+                    Uint256::zero(),
+                    |acc, ((collateral, price), premium_rate)| {
+                        acc + collateral.1 * *price * fee_deductor(premium_rate)
+                    },
+                )
+        }
+    };
+    let liquidation_ratio = if expected_repay_amount <= borrow_amount {
+        Decimal256::one()
     } else {
-        Decimal256::from_uint256(borrow_amount - safe_borrow_amount)
-            / Decimal256::from_uint256(expected_repay_amount - safe_borrow_amount)
+        // When collaterals_value is smaller than liquidation_threshold,
+        // liquidate all collaterals
+        // A safe LTV is only a target while it stays below the
+        // liquidation LTV of the position
+        let safe_borrow_amount = match config.post_liquidation_safe_ltv {
+            Some(safe_ltv) if collaterals_value * safe_ltv < borrow_limit => {
+                collaterals_value * safe_ltv
+            }
+            _ => borrow_limit * config.safe_ratio,
+        };
+        let liquidation_ratio = if collaterals_value < config.liquidation_threshold {
+            Decimal256::from_uint256(borrow_amount)
+                / Decimal256::from_uint256(expected_repay_amount)
+        } else {
+            Decimal256::from_uint256(borrow_amount - safe_borrow_amount)
+                / Decimal256::from_uint256(expected_repay_amount - safe_borrow_amount)
+        };
+
+        // Cap the liquidation_ratio to 1
+        std::cmp::min(Decimal256::one(), liquidation_ratio)
     };
 
-    // Cap the liquidation_ratio to 1
-    let liquidation_ratio = std::cmp::min(Decimal256::one(), liquidation_ratio);
+    // Cap the value liquidated at once, the rest of the position
+    // is left to subsequent liquidations
+    let liquidation_ratio = match config.max_liquidation_value {
+        Some(max_liquidation_value)
+            if collaterals_value * liquidation_ratio > max_liquidation_value =>
+        {
+            Decimal256::from_uint256(max_liquidation_value)
+                / Decimal256::from_uint256(collaterals_value)
+        }
+        _ => liquidation_ratio,
+    };
     Ok(LiquidationAmountResponse {
         collaterals: collaterals
             .iter()
@@ -312,7 +651,12 @@ fn query_liquidation_amount(
                 let (collateral, _) = c;
                 let mut collateral = collateral.clone();
 
-                collateral.1 = collateral.1 * liquidation_ratio;
+                // Seize the rounding residue rather than leave it unbacked
+                collateral.1 = if config.conservative_rounding {
+                    std::cmp::min(mul_ceil(collateral.1, liquidation_ratio), collateral.1)
+                } else {
+                    collateral.1 * liquidation_ratio
+                };
                 collateral
             })
             .filter(|c| c.1 > Uint256::zero())
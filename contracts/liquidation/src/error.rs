@@ -15,9 +15,21 @@ pub enum ContractError {
     #[error("User already has bid for specified collateral: {0}")]
     AlreadyBidForCollateral(Addr),
 
+    #[error("Liquidation fee recipient cannot be empty")]
+    EmptyFeeRecipient {},
+
+    #[error("Post liquidation safe LTV must be lower than 1")]
+    InvalidPostLiquidationSafeLtv {},
+
+    #[error("Self liquidation fee cannot exceed the bid fee")]
+    InvalidSelfLiquidationFee {},
+
     #[error("No {0} assets have been provided")]
     AssetNotProvided(String),
 
+    #[error("A single native collateral must be attached")]
+    NativeCollateralNotProvided {},
+
     #[error("Premium rate cannot exceed the max premium rate: {0}")]
     PremiumExceedsMaxPremium(String),
 
@@ -32,4 +44,37 @@ pub enum ContractError {
 
     #[error("Retract amount cannot exceed bid balance: {0}")]
     RetractExceedsBid(u128),
+
+    #[error("Insufficient keeper funds; Required balance: {0}")]
+    InsufficientKeeperFunds(u128),
+
+    #[error("Withdraw amount cannot exceed keeper funds: {0}")]
+    WithdrawExceedsKeeperFunds(u128),
+
+    #[error("No liquidation proceeds to claim")]
+    NoLiquidationProceeds {},
+
+    #[error("Auction duration must be greater than 0")]
+    InvalidAuctionDuration {},
+
+    #[error("Liquidation auctions are not enabled")]
+    AuctionsDisabled {},
+
+    #[error("An auction of {0} is already active")]
+    AuctionAlreadyActive(String),
+
+    #[error("No auction of {0} is active")]
+    NoActiveAuction(String),
+
+    #[error("Borrower of the escrowed auction collateral is not provided")]
+    AuctionBorrowerNotProvided {},
+
+    #[error("Escrowed collateral exceeds the amount seized for the auction: {0}")]
+    EscrowExceedsSeized(String),
+
+    #[error("The auction of {0} has expired")]
+    AuctionExpired(String),
+
+    #[error("The auction of {0} has not expired yet")]
+    AuctionNotExpired(String),
 }
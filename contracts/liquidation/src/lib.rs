@@ -1,3 +1,4 @@
+mod auction;
 mod bid;
 pub mod contract;
 pub mod error;
@@ -5,12 +5,17 @@ use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{CanonicalAddr, Deps, Order, StdError, StdResult, Storage};
 use cosmwasm_storage::{singleton, singleton_read, Bucket, ReadonlyBucket};
 use moneymarket::liquidation::BidResponse;
+use moneymarket::tokens::Tokens;
 
 static KEY_CONFIG: &[u8] = b"config";
 
 static PREFIX_BID: &[u8] = b"bid";
 static PREFIX_BID_BY_USER: &[u8] = b"bid_by_user";
 static PREFIX_BID_BY_COLLATERAL: &[u8] = b"bid_by_collateral";
+static PREFIX_LIQUIDATION_PROCEEDS: &[u8] = b"liquidation_proceeds";
+static PREFIX_AUCTION: &[u8] = b"auction";
+static PREFIX_NATIVE_DENOM: &[u8] = b"native_denom";
+static PREFIX_KEEPER_FUNDS: &[u8] = b"keeper_funds";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
@@ -22,6 +27,25 @@ pub struct Config {
     pub max_premium_rate: Decimal256,
     pub liquidation_threshold: Uint256,
     pub price_timeframe: u64,
+    // The fields below default for configs stored before they existed
+    #[serde(default)]
+    pub liquidation_fee_recipient: Option<CanonicalAddr>,
+    #[serde(default)]
+    pub max_liquidation_value: Option<Uint256>,
+    #[serde(default = "default_conservative_rounding")]
+    pub conservative_rounding: bool,
+    #[serde(default)]
+    pub post_liquidation_safe_ltv: Option<Decimal256>,
+    #[serde(default)]
+    pub overseer_contract: Option<CanonicalAddr>,
+    #[serde(default)]
+    pub auction_duration: Option<u64>,
+    #[serde(default)]
+    pub self_liquidation_fee: Option<Decimal256>,
+}
+
+fn default_conservative_rounding() -> bool {
+    true
 }
 
 pub fn store_config(storage: &mut dyn Storage, config: &Config) -> StdResult<()> {
@@ -94,6 +118,131 @@ pub fn read_bid<'a>(
         .map_err(|_| StdError::generic_err("No bids with the specified information exist"))
 }
 
+/// Collateral won by a bidder, held in escrow until claimed
+pub fn store_liquidation_proceeds(
+    storage: &mut dyn Storage,
+    bidder: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+    amount: Uint256,
+) -> StdResult<()> {
+    let mut proceeds_bucket: Bucket<Uint256> =
+        Bucket::multilevel(storage, &[PREFIX_LIQUIDATION_PROCEEDS, bidder.as_slice()]);
+    proceeds_bucket.save(collateral_token.as_slice(), &amount)
+}
+
+pub fn read_liquidation_proceeds(
+    storage: &dyn Storage,
+    bidder: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+) -> Uint256 {
+    let proceeds_bucket: ReadonlyBucket<Uint256> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_LIQUIDATION_PROCEEDS, bidder.as_slice()]);
+    proceeds_bucket
+        .load(collateral_token.as_slice())
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+pub fn read_all_liquidation_proceeds(
+    storage: &dyn Storage,
+    bidder: &CanonicalAddr,
+) -> StdResult<Vec<(CanonicalAddr, Uint256)>> {
+    let proceeds_bucket: ReadonlyBucket<Uint256> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_LIQUIDATION_PROCEEDS, bidder.as_slice()]);
+    proceeds_bucket
+        .range(None, None, Order::Ascending)
+        .map(|elem| {
+            let (k, v) = elem?;
+            Ok((CanonicalAddr::from(k), v))
+        })
+        .collect()
+}
+
+pub fn remove_liquidation_proceeds(
+    storage: &mut dyn Storage,
+    bidder: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+) {
+    let mut proceeds_bucket: Bucket<Uint256> =
+        Bucket::multilevel(storage, &[PREFIX_LIQUIDATION_PROCEEDS, bidder.as_slice()]);
+    proceeds_bucket.remove(collateral_token.as_slice());
+}
+
+/// Stable balance a keeper deposited to pay for liquidations
+pub fn store_keeper_funds(
+    storage: &mut dyn Storage,
+    keeper: &CanonicalAddr,
+    amount: Uint256,
+) -> StdResult<()> {
+    let mut keeper_funds_bucket: Bucket<Uint256> = Bucket::new(storage, PREFIX_KEEPER_FUNDS);
+    if amount.is_zero() {
+        keeper_funds_bucket.remove(keeper.as_slice());
+        return Ok(());
+    }
+
+    keeper_funds_bucket.save(keeper.as_slice(), &amount)
+}
+
+pub fn read_keeper_funds(storage: &dyn Storage, keeper: &CanonicalAddr) -> Uint256 {
+    let keeper_funds_bucket: ReadonlyBucket<Uint256> =
+        ReadonlyBucket::new(storage, PREFIX_KEEPER_FUNDS);
+    keeper_funds_bucket
+        .load(keeper.as_slice())
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+/// Dutch auction over the collaterals seized from a borrower
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Auction {
+    pub sub_account: Option<String>,
+    /// Collaterals seized by the overseer for the auction
+    pub collaterals: Tokens,
+    /// Collaterals escrowed by the custody contracts so far
+    pub escrowed: Tokens,
+    /// Custody contract each escrowed amount came from, the overseer
+    /// for native collaterals, to return the collaterals of an expired auction
+    #[serde(default)]
+    pub escrow_sources: Vec<(CanonicalAddr, CanonicalAddr, Uint256)>,
+    pub start_height: u64,
+    pub duration: u64,
+}
+
+pub fn store_auction(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    auction: &Auction,
+) -> StdResult<()> {
+    let mut auction_bucket: Bucket<Auction> = Bucket::new(storage, PREFIX_AUCTION);
+    auction_bucket.save(borrower.as_slice(), auction)
+}
+
+pub fn read_auction(storage: &dyn Storage, borrower: &CanonicalAddr) -> StdResult<Option<Auction>> {
+    let auction_bucket: ReadonlyBucket<Auction> = ReadonlyBucket::new(storage, PREFIX_AUCTION);
+    auction_bucket.may_load(borrower.as_slice())
+}
+
+pub fn remove_auction(storage: &mut dyn Storage, borrower: &CanonicalAddr) {
+    let mut auction_bucket: Bucket<Auction> = Bucket::new(storage, PREFIX_AUCTION);
+    auction_bucket.remove(borrower.as_slice());
+}
+
+/// Denom of a native collateral, recorded when the overseer sends it
+pub fn store_native_denom(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    denom: &str,
+) -> StdResult<()> {
+    let mut denom_bucket: Bucket<String> = Bucket::new(storage, PREFIX_NATIVE_DENOM);
+    denom_bucket.save(collateral_token.as_slice(), &denom.to_string())
+}
+
+pub fn read_native_denom(
+    storage: &dyn Storage,
+    collateral_token: &CanonicalAddr,
+) -> StdResult<Option<String>> {
+    let denom_bucket: ReadonlyBucket<String> = ReadonlyBucket::new(storage, PREFIX_NATIVE_DENOM);
+    denom_bucket.may_load(collateral_token.as_slice())
+}
+
 // settings for pagination
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
@@ -10,6 +10,7 @@ use cosmwasm_std::{
 use std::collections::HashMap;
 
 use moneymarket::oracle::PriceResponse;
+use moneymarket::overseer::{TokenType, WhitelistResponse, WhitelistResponseElem};
 use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -17,6 +18,12 @@ use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrap
 pub enum QueryMsg {
     /// Query oracle price to oracle contract
     Price { base: String, quote: String },
+    /// Query collateral whitelist to overseer contract
+    Whitelist {
+        collateral_token: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 /// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
@@ -38,6 +45,7 @@ pub struct WasmMockQuerier {
     base: MockQuerier<TerraQueryWrapper>,
     tax_querier: TaxQuerier,
     oracle_price_querier: OraclePriceQuerier,
+    custody_querier: CustodyQuerier,
 }
 
 #[derive(Clone, Default)]
@@ -91,6 +99,28 @@ pub(crate) fn oracle_price_to_map(
     oracle_price_map
 }
 
+#[derive(Clone, Default)]
+pub struct CustodyQuerier {
+    // collateral token to its whitelisted custody contracts
+    custody_contracts: HashMap<String, Vec<String>>,
+}
+
+impl CustodyQuerier {
+    pub fn new(custody_contracts: &[(&String, &String)]) -> Self {
+        let mut custody_contracts_map: HashMap<String, Vec<String>> = HashMap::new();
+        for (collateral_token, custody_contract) in custody_contracts.iter() {
+            custody_contracts_map
+                .entry(collateral_token.to_string())
+                .or_default()
+                .push(custody_contract.to_string());
+        }
+
+        CustodyQuerier {
+            custody_contracts: custody_contracts_map,
+        }
+    }
+}
+
 impl Querier for WasmMockQuerier {
     fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
         // MockQuerier doesn't support Custom, so we ignore it completely here
@@ -146,6 +176,7 @@ impl WasmMockQuerier {
                                 rate: v.0,
                                 last_updated_base: v.1,
                                 last_updated_quote: v.2,
+                                confidence_spread: None,
                             })))
                         }
                         None => SystemResult::Err(SystemError::InvalidRequest {
@@ -154,6 +185,44 @@ impl WasmMockQuerier {
                         }),
                     }
                 }
+                QueryMsg::Whitelist {
+                    collateral_token, ..
+                } => {
+                    let collateral_token = collateral_token.unwrap();
+                    let custody_contracts = self
+                        .custody_querier
+                        .custody_contracts
+                        .get(&collateral_token)
+                        .cloned()
+                        .unwrap_or_default();
+                    SystemResult::Ok(ContractResult::from(to_binary(&WhitelistResponse {
+                        elems: vec![WhitelistResponseElem {
+                            name: "name".to_string(),
+                            symbol: "symbol".to_string(),
+                            max_ltv: Decimal256::percent(50),
+                            custody_contract: custody_contracts
+                                .first()
+                                .cloned()
+                                .unwrap_or_default(),
+                            custody_contracts: custody_contracts
+                                .into_iter()
+                                .map(|c| (c, Decimal256::one()))
+                                .collect(),
+                            collateral_token,
+                            quote_denom: None,
+                            liquidation_premium: None,
+                            correlation_group: None,
+                            min_lock_amount: None,
+                            borrow_enabled_from: None,
+                            borrow_disabled_from: None,
+                            liquidation_contract: None,
+                            decimals: 6,
+                            exchange_rate_source: None,
+                            borrow_rate_premium: None,
+                            token_type: TokenType::Cw20,
+                        }],
+                    })))
+                }
             },
             _ => self.base.handle_query(request),
         }
@@ -166,6 +235,7 @@ impl WasmMockQuerier {
             base,
             tax_querier: TaxQuerier::default(),
             oracle_price_querier: OraclePriceQuerier::default(),
+            custody_querier: CustodyQuerier::default(),
         }
     }
 
@@ -181,4 +251,8 @@ impl WasmMockQuerier {
     ) {
         self.oracle_price_querier = OraclePriceQuerier::new(oracle_price);
     }
+
+    pub fn with_custody_contracts(&mut self, custody_contracts: &[(&String, &String)]) {
+        self.custody_querier = CustodyQuerier::new(custody_contracts);
+    }
 }
@@ -3,15 +3,22 @@ use crate::error::ContractError;
 use crate::testing::mock_querier::mock_dependencies;
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
-use cosmwasm_std::testing::{mock_env, mock_info};
+use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_binary, to_binary, BankMsg, Coin, CosmosMsg, Decimal, SubMsg, Uint128, WasmMsg,
+    attr, from_binary, to_binary, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, Decimal, Deps,
+    StdError, SubMsg, Uint128, WasmMsg,
 };
+use cosmwasm_storage::singleton;
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use moneymarket::custody::Cw20HookMsg as CustodyCw20HookMsg;
 use moneymarket::liquidation::{
-    BidResponse, BidsResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg,
-    LiquidationAmountResponse, QueryMsg,
+    AuctionResponse, BidResponse, BidsResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg,
+    InstantiateMsg, KeeperFundsResponse, LiquidationAmountResponse, LiquidationConfigResponse,
+    QueryMsg, UpdateConfigMsg,
 };
+use moneymarket::overseer::ExecuteMsg as OverseerExecuteMsg;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[test]
 fn proper_initialization() {
@@ -26,6 +33,13 @@ fn proper_initialization() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -48,10 +62,58 @@ fn proper_initialization() {
             max_premium_rate: Decimal256::percent(5),
             liquidation_threshold: Uint256::from(100000000u64),
             price_timeframe: 60u64,
+            liquidation_fee_recipient: None,
+            max_liquidation_value: None,
+            conservative_rounding: true,
+            post_liquidation_safe_ltv: None,
+            overseer_contract: None,
+            auction_duration: None,
+            self_liquidation_fee: None,
         }
     );
 }
 
+#[test]
+fn legacy_config() {
+    let mut deps = mock_dependencies(&[]);
+
+    #[derive(Serialize, Deserialize)]
+    struct LegacyConfig {
+        owner: CanonicalAddr,
+        oracle_contract: CanonicalAddr,
+        stable_denom: String,
+        safe_ratio: Decimal256,
+        bid_fee: Decimal256,
+        max_premium_rate: Decimal256,
+        liquidation_threshold: Uint256,
+        price_timeframe: u64,
+    }
+    let legacy_config = LegacyConfig {
+        owner: deps.api.addr_canonicalize("owner0000").unwrap(),
+        oracle_contract: deps.api.addr_canonicalize("oracle0000").unwrap(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+    };
+    singleton(deps.as_mut().storage, b"config")
+        .save(&legacy_config)
+        .unwrap();
+
+    // configs stored before the fee recipient, the rounding mode and the
+    // self liquidation fee existed keep sending the fee with the bid
+    // and round in favor of the protocol
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.owner, "owner0000".to_string());
+    assert_eq!(config_res.price_timeframe, 60u64);
+    assert_eq!(config_res.liquidation_fee_recipient, None);
+    assert!(config_res.conservative_rounding);
+    assert_eq!(config_res.self_liquidation_fee, None);
+}
+
 #[test]
 fn update_config() {
     let mut deps = mock_dependencies(&[]);
@@ -65,6 +127,13 @@ fn update_config() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -72,7 +141,7 @@ fn update_config() {
 
     // update owner
     let info = mock_info("owner0000", &[]);
-    let msg = ExecuteMsg::UpdateConfig {
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
         owner: Some("owner0001".to_string()),
         oracle_contract: None,
         stable_denom: None,
@@ -81,7 +150,14 @@ fn update_config() {
         max_premium_rate: None,
         liquidation_threshold: None,
         price_timeframe: None,
-    };
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(0, res.messages.len());
@@ -100,12 +176,19 @@ fn update_config() {
             max_premium_rate: Decimal256::percent(5),
             liquidation_threshold: Uint256::from(100000000u64),
             price_timeframe: 60u64,
+            liquidation_fee_recipient: None,
+            max_liquidation_value: None,
+            conservative_rounding: true,
+            post_liquidation_safe_ltv: None,
+            overseer_contract: None,
+            auction_duration: None,
+            self_liquidation_fee: None,
         }
     );
 
     // Update left items
     let info = mock_info("owner0001", &[]);
-    let msg = ExecuteMsg::UpdateConfig {
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
         owner: None,
         oracle_contract: Some("oracle0001".to_string()),
         stable_denom: Some("ukrw".to_string()),
@@ -114,7 +197,14 @@ fn update_config() {
         max_premium_rate: Some(Decimal256::percent(7)),
         liquidation_threshold: Some(Uint256::from(150000000u64)),
         price_timeframe: Some(120u64),
-    };
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(0, res.messages.len());
@@ -133,12 +223,118 @@ fn update_config() {
             max_premium_rate: Decimal256::percent(7),
             liquidation_threshold: Uint256::from(150000000u64),
             price_timeframe: 120u64,
+            liquidation_fee_recipient: None,
+            max_liquidation_value: None,
+            conservative_rounding: true,
+            post_liquidation_safe_ltv: None,
+            overseer_contract: None,
+            auction_duration: None,
+            self_liquidation_fee: None,
         }
     );
 
+    // set the fee recipient
+    let info = mock_info("owner0001", &[]);
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: Some(Some("insurance0000".to_string())),
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let value: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        value.liquidation_fee_recipient,
+        Some("insurance0000".to_string())
+    );
+
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: Some(Some("".to_string())),
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::EmptyFeeRecipient {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // clear the fee recipient
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: Some(None),
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let value: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(value.liquidation_fee_recipient, None);
+
+    // the self liquidation fee cannot exceed the bid fee
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: Some(Decimal256::percent(3)),
+    }));
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner0001", &[]), msg);
+    match res {
+        Err(ContractError::InvalidSelfLiquidationFee {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
     // Unauthorized err
     let info = mock_info("owner0000", &[]);
-    let msg = ExecuteMsg::UpdateConfig {
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
         owner: None,
         oracle_contract: Some("oracle0001".to_string()),
         stable_denom: Some("ukrw".to_string()),
@@ -147,7 +343,14 @@ fn update_config() {
         max_premium_rate: Some(Decimal256::percent(7)),
         liquidation_threshold: Some(Uint256::from(150000000u64)),
         price_timeframe: Some(100u64),
-    };
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
 
     let res = execute(deps.as_mut(), mock_env(), info, msg);
     match res {
@@ -156,6 +359,92 @@ fn update_config() {
     }
 }
 
+#[test]
+fn query_liquidation_config() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let value: LiquidationConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::LiquidationConfig {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        value,
+        LiquidationConfigResponse {
+            stable_denom: "uusd".to_string(),
+            safe_ratio: Decimal256::percent(10),
+            bid_fee: Decimal256::percent(1),
+            max_premium_rate: Decimal256::percent(5),
+            liquidation_threshold: Uint256::from(100000000u64),
+            price_timeframe: 60u64,
+            liquidation_fee_recipient: None,
+            max_liquidation_value: None,
+            conservative_rounding: true,
+            post_liquidation_safe_ltv: None,
+            auction_duration: None,
+        }
+    );
+
+    let info = mock_info("owner0000", &[]);
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: Some("ukrw".to_string()),
+        safe_ratio: Some(Decimal256::percent(15)),
+        bid_fee: Some(Decimal256::percent(2)),
+        max_premium_rate: Some(Decimal256::percent(7)),
+        liquidation_threshold: Some(Uint256::from(150000000u64)),
+        price_timeframe: Some(120u64),
+        liquidation_fee_recipient: Some(Some("insurance0000".to_string())),
+        max_liquidation_value: Some(Uint256::from(5000000000u64)),
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let value: LiquidationConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::LiquidationConfig {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        value,
+        LiquidationConfigResponse {
+            stable_denom: "ukrw".to_string(),
+            safe_ratio: Decimal256::percent(15),
+            bid_fee: Decimal256::percent(2),
+            max_premium_rate: Decimal256::percent(7),
+            liquidation_threshold: Uint256::from(150000000u64),
+            price_timeframe: 120u64,
+            liquidation_fee_recipient: Some("insurance0000".to_string()),
+            max_liquidation_value: Some(Uint256::from(5000000000u64)),
+            conservative_rounding: true,
+            post_liquidation_safe_ltv: None,
+            auction_duration: None,
+        }
+    );
+}
+
 #[test]
 fn submit_bid() {
     let mut deps = mock_dependencies(&[]);
@@ -169,6 +458,13 @@ fn submit_bid() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -241,6 +537,13 @@ fn retract_bid() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -319,6 +622,13 @@ fn execute_bid() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -354,6 +664,8 @@ fn execute_bid() {
             liquidator: "addr0000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: None,
         })
         .unwrap(),
     });
@@ -373,6 +685,8 @@ fn execute_bid() {
             liquidator: "addr0000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: None,
         })
         .unwrap(),
     });
@@ -380,15 +694,6 @@ fn execute_bid() {
     assert_eq!(
         res.messages,
         vec![
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "asset0000".to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                    recipient: "addr0000".to_string(),
-                    amount: Uint128::from(1000000u128),
-                })
-                .unwrap(),
-            })),
             SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
                 to_address: "repay0000".to_string(),
                 amount: vec![Coin {
@@ -413,6 +718,8 @@ fn execute_bid() {
             liquidator: "addr0000".to_string(),
             fee_address: None,
             repay_address: None,
+            borrower: None,
+            premium_rate: None,
         })
         .unwrap(),
     });
@@ -420,15 +727,6 @@ fn execute_bid() {
     assert_eq!(
         res.messages,
         vec![
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "asset0000".to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                    recipient: "addr0000".to_string(),
-                    amount: Uint128::from(1000000u128),
-                })
-                .unwrap(),
-            })),
             SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
                 to_address: "addr0001".to_string(),
                 amount: vec![Coin {
@@ -445,16 +743,105 @@ fn execute_bid() {
             })),
         ]
     );
+
+    // bid fees are routed to the configured recipient
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: Some(Some("insurance0000".to_string())),
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+
+    // required_stable 9,900
+    // bid_fee         99
+    // repay_amount    9,801
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0001".to_string(),
+        amount: Uint128::from(20000u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "addr0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "insurance0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(98u128), // 99 / (1 + tax_rate)
+            }]
+        }))
+    );
+
+    // the borrower liquidating its own position pays the self liquidation fee
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: Some(Decimal256::zero()),
+    }));
+    execute(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+
+    // required_stable 99
+    // bid_fee         0
+    // repay_amount    99
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0001".to_string(),
+        amount: Uint128::from(200u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "addr0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: Some("addr0000".to_string()),
+            premium_rate: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "repay0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(98u128), // 99 / (1 + tax_rate)
+            }]
+        }))]
+    );
 }
 
 #[test]
-fn query_liquidation_amount() {
+fn liquidation_fee_recipient() {
     let mut deps = mock_dependencies(&[]);
-    deps.querier.with_tax(
-        Decimal::percent(1),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
-
     let msg = InstantiateMsg {
         owner: "owner0000".to_string(),
         oracle_contract: "oracle0000".to_string(),
@@ -464,46 +851,1467 @@ fn query_liquidation_amount() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: Some("insurance0000".to_string()),
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
-    let info = mock_info("addr0000", &[]);
-    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
 
-    // fee_deductor = 0.931095
-    // expected_repay_amount = 931,095
-    let msg = QueryMsg::LiquidationAmount {
-        borrow_amount: Uint256::from(931095u64),
-        borrow_limit: Uint256::from(900000u64),
-        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
-        collateral_prices: vec![Decimal256::percent(10)],
-    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
 
-    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
-    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
-    assert_eq!(
-        res,
-        LiquidationAmountResponse {
-            collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
-        }
+    let msg = ExecuteMsg::SubmitBid {
+        collateral_token: "asset0000".to_string(),
+        premium_rate: Decimal256::percent(1),
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
     );
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-    let msg = QueryMsg::LiquidationAmount {
-        borrow_amount: Uint256::from(100000u64),
-        borrow_limit: Uint256::from(1000000u64),
-        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
-        collateral_prices: vec![Decimal256::one()],
-    };
+    // required_stable 495,000
+    // bid_fee         4,950
+    // repay_amount    490,050
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0001".to_string(),
+        amount: Uint128::from(1000000u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "addr0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: None,
+        })
+        .unwrap(),
+    });
 
-    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
-    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    // the configured recipient takes the fee over the fee address of the bid
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("asset0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
     assert_eq!(
-        res,
-        LiquidationAmountResponse {
-            collaterals: vec![],
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "repay0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(490050u128),
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "insurance0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(4950u128),
+                }]
+            })),
+        ]
+    );
+
+    // once cleared, the fee goes back to the fee address of the bid
+    let update_msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: Some(None),
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner0000", &[]),
+        update_msg,
+    )
+    .unwrap();
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "fee0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(4950u128),
+            }]
+        }))
+    );
+}
+
+#[test]
+fn collateral_liquidation_premium() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::SubmitBid {
+        collateral_token: "asset0000".to_string(),
+        premium_rate: Decimal256::percent(5),
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let execute_bid_msg = |premium_rate: Decimal256| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "custody0000".to_string(),
+            amount: Uint128::from(1000000u128),
+            msg: to_binary(&Cw20HookMsg::ExecuteBid {
+                liquidator: "addr0000".to_string(),
+                fee_address: Some("fee0000".to_string()),
+                repay_address: Some("repay0000".to_string()),
+                borrower: None,
+                premium_rate: Some(premium_rate),
+            })
+            .unwrap(),
+        })
+    };
+
+    // the premium of the collateral caps the premium of the bid
+    // required_stable 490,000
+    // bid_fee         4,900
+    // repay_amount    485,100
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("asset0000", &[]),
+        execute_bid_msg(Decimal256::percent(2)),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "repay0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(485100u128),
+            }]
+        }))
+    );
+
+    // a lower bid premium is kept
+    // required_stable 475,000
+    // bid_fee         4,750
+    // repay_amount    470,250
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("asset0000", &[]),
+        execute_bid_msg(Decimal256::percent(10)),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "repay0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(470250u128),
+            }]
+        }))
+    );
+
+    let bid: BidResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Bid {
+                collateral_token: "asset0000".to_string(),
+                bidder: "addr0000".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(bid.amount, Uint256::from(35000u64));
+}
+
+#[test]
+fn keeper_funds() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("keeper0000", &[]),
+        ExecuteMsg::DepositKeeperFunds {},
+    );
+    match res {
+        Err(ContractError::AssetNotProvided(denom)) => assert_eq!(denom, "uusd"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info(
+        "keeper0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(500000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::DepositKeeperFunds {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "deposit_keeper_funds"),
+            attr("keeper", "keeper0000"),
+            attr("amount", "500000"),
+            attr("keeper_funds", "500000"),
+        ]
+    );
+
+    // without a bid, the keeper funds pay at the max premium rate
+    // required_stable 475,000
+    // bid_fee         4,750
+    // repay_amount    470,250
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "custody0000".to_string(),
+        amount: Uint128::from(1000000u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "keeper0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("asset0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "repay0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(470250u128),
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "fee0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(4750u128),
+                }]
+            })),
+        ]
+    );
+
+    let query_keeper_funds = |deps: Deps| -> Uint256 {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::KeeperFunds {
+                keeper: "keeper0000".to_string(),
+            },
+        )
+        .unwrap();
+        let keeper_funds: KeeperFundsResponse = from_binary(&res).unwrap();
+        keeper_funds.amount
+    };
+    assert_eq!(query_keeper_funds(deps.as_ref()), Uint256::from(25000u128));
+
+    // the remaining funds cannot cover another liquidation
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg);
+    match res {
+        Err(ContractError::InsufficientKeeperFunds(475000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    assert_eq!(query_keeper_funds(deps.as_ref()), Uint256::from(25000u128));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("keeper0000", &[]),
+        ExecuteMsg::WithdrawKeeperFunds {
+            amount: Some(Uint256::from(30000u128)),
+        },
+    );
+    match res {
+        Err(ContractError::WithdrawExceedsKeeperFunds(25000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("keeper0000", &[]),
+        ExecuteMsg::WithdrawKeeperFunds { amount: None },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "keeper0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(25000u128),
+            }]
+        }))]
+    );
+    assert_eq!(query_keeper_funds(deps.as_ref()), Uint256::zero());
+
+    // the premium rate of the collateral overrides the max premium rate
+    // required_stable 18,000
+    // bid_fee         180
+    // repay_amount    17,820
+    let info = mock_info(
+        "keeper0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(18000u128),
+        }],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::DepositKeeperFunds {},
+    )
+    .unwrap();
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "custody0000".to_string(),
+        amount: Uint128::from(40000u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "keeper0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: Some(Decimal256::percent(10)),
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "repay0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(17820u128),
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "fee0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(180u128),
+                }]
+            })),
+        ]
+    );
+    assert_eq!(query_keeper_funds(deps.as_ref()), Uint256::zero());
+}
+
+#[test]
+fn claim_liquidation_proceeds() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[
+        (
+            &("asset0000".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("asset0001".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(100),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    for (bidder, collateral_token) in [
+        ("addr0000", "asset0000"),
+        ("addr0000", "asset0001"),
+        ("addr0001", "asset0000"),
+    ] {
+        let msg = ExecuteMsg::SubmitBid {
+            collateral_token: collateral_token.to_string(),
+            premium_rate: Decimal256::percent(1),
+        };
+        let info = mock_info(
+            bidder,
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(1000000u128),
+            }],
+        );
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    let execute_bid = |liquidator: &str, collateral_token: &str, amount: u128| {
+        (
+            mock_info(collateral_token, &[]),
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: "custody0000".to_string(),
+                amount: Uint128::from(amount),
+                msg: to_binary(&Cw20HookMsg::ExecuteBid {
+                    liquidator: liquidator.to_string(),
+                    fee_address: None,
+                    repay_address: None,
+                    borrower: None,
+                    premium_rate: None,
+                })
+                .unwrap(),
+            }),
+        )
+    };
+    for (liquidator, collateral_token, amount) in [
+        ("addr0000", "asset0000", 100000u128),
+        ("addr0000", "asset0000", 200000u128),
+        ("addr0000", "asset0001", 50000u128),
+        ("addr0001", "asset0000", 400000u128),
+    ] {
+        let (info, msg) = execute_bid(liquidator, collateral_token, amount);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    let msg = ExecuteMsg::ClaimLiquidationProceeds {};
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "asset0000".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0000".to_string(),
+                    amount: Uint128::from(300000u128),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "asset0001".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0000".to_string(),
+                    amount: Uint128::from(50000u128),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_liquidation_proceeds"),
+            attr("liquidator", "addr0000"),
+            attr("claimed_amounts", "300000asset0000,50000asset0001"),
+        ]
+    );
+
+    // the escrow of addr0000 is empty now
+    let msg = ExecuteMsg::ClaimLiquidationProceeds {};
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::NoLiquidationProceeds {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // while addr0001 still claims its own proceeds
+    let msg = ExecuteMsg::ClaimLiquidationProceeds {};
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0001".to_string(),
+                amount: Uint128::from(400000u128),
+            })
+            .unwrap(),
+        }))]
+    );
+}
+
+#[test]
+fn execute_native_bid() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: Some("overseer0000".to_string()),
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::SubmitBid {
+        collateral_token: "asset0000".to_string(),
+        premium_rate: Decimal256::percent(1),
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::ExecuteBid {
+        collateral_token: "asset0000".to_string(),
+        liquidator: "addr0000".to_string(),
+        fee_address: Some("fee0000".to_string()),
+        repay_address: Some("repay0000".to_string()),
+        borrower: Some("borrower0000".to_string()),
+        premium_rate: None,
+    };
+    let native_collateral = [Coin {
+        denom: "uluna".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+
+    // only the overseer sends native collaterals
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("custody0000", &native_collateral),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("overseer0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::NativeCollateralNotProvided {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("overseer0000", &native_collateral),
+        msg,
+    )
+    .unwrap();
+    assert!(res
+        .attributes
+        .contains(&attr("collateral_amount", "100000")));
+
+    // the proceeds are paid out in the native denom
+    let msg = ExecuteMsg::ClaimLiquidationProceeds {};
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(100000u128),
+            }],
+        }))]
+    );
+}
+
+#[test]
+fn auction_bid() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: Some("overseer0000".to_string()),
+        auction_duration: Some(100u64),
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let mut env = mock_env();
+    deps.querier
+        .with_custody_contracts(&[(&"asset0000".to_string(), &"custody0000".to_string())]);
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // only the overseer can start an auction
+    let msg = ExecuteMsg::StartAuction {
+        borrower: "borrower0000".to_string(),
+        sub_account: None,
+        collaterals: vec![("asset0000".to_string(), Uint256::from(20000u64))],
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("overseer0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("overseer0000", &[]),
+        msg,
+    );
+    match res {
+        Err(ContractError::AuctionAlreadyActive(borrower)) => {
+            assert_eq!(borrower, "borrower0000")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the custody contract escrows the seized collateral
+    let escrow_msg = |sender: &str, amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::ExecuteBid {
+                liquidator: MOCK_CONTRACT_ADDR.to_string(),
+                fee_address: Some("overseer0000".to_string()),
+                repay_address: Some("market0000".to_string()),
+                borrower: Some("borrower0000".to_string()),
+                premium_rate: None,
+            })
+            .unwrap(),
+        })
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        escrow_msg("addr0000", 20000u128),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        escrow_msg("custody0000", 20000u128),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        escrow_msg("custody0000", 1u128),
+    );
+    match res {
+        Err(ContractError::EscrowExceedsSeized(collateral_token)) => {
+            assert_eq!(collateral_token, "asset0000")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // 40 of 100 blocks elapsed, discount = 5% * 40 / 100 = 2%
+    // collateral value 10,000 sells for 9,800
+    env.block.height += 40;
+    let msg = ExecuteMsg::Bid {
+        borrower: "borrower0000".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0001",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(9000u128),
+            }],
+        ),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::InsufficientBidBalance(amount)) => assert_eq!(amount, 9800u128),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0001",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(10000u128),
+            }],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "overseer0000".to_string(),
+                funds: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(9702u128), // 9,800 - bid fee
+                }],
+                msg: to_binary(&OverseerExecuteMsg::RepayAuctionProceeds {
+                    borrower: "borrower0000".to_string(),
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "overseer0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(98u128),
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0001".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(200u128),
+                }]
+            })),
+        ]
+    );
+
+    // the won collateral is claimed out of escrow
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("addr0001", &[]),
+        ExecuteMsg::ClaimLiquidationProceeds {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0001".to_string(),
+                amount: Uint128::from(20000u128),
+            })
+            .unwrap(),
+        }))]
+    );
+}
+
+#[test]
+fn auction_expiry() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: Some("overseer0000".to_string()),
+        auction_duration: Some(0u64),
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let mut env = mock_env();
+    deps.querier
+        .with_custody_contracts(&[(&"asset0000".to_string(), &"custody0000".to_string())]);
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::InvalidAuctionDuration {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = InstantiateMsg {
+        auction_duration: Some(100u64),
+        self_liquidation_fee: None,
+        ..msg
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("overseer0000", &[]),
+        ExecuteMsg::StartAuction {
+            borrower: "borrower0000".to_string(),
+            sub_account: None,
+            collaterals: vec![("asset0000".to_string(), Uint256::from(20000u64))],
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "custody0000".to_string(),
+            amount: Uint128::from(20000u128),
+            msg: to_binary(&Cw20HookMsg::ExecuteBid {
+                liquidator: MOCK_CONTRACT_ADDR.to_string(),
+                fee_address: Some("overseer0000".to_string()),
+                repay_address: Some("market0000".to_string()),
+                borrower: Some("borrower0000".to_string()),
+                premium_rate: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    // the auction is open through its last block
+    env.block.height += 100;
+    let settle_msg = ExecuteMsg::SettleAuction {
+        borrower: "borrower0000".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        settle_msg.clone(),
+    );
+    match res {
+        Err(ContractError::AuctionNotExpired(borrower)) => assert_eq!(borrower, "borrower0000"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    env.block.height += 1;
+    let bid_msg = ExecuteMsg::Bid {
+        borrower: "borrower0000".to_string(),
+    };
+    let bid_info = mock_info(
+        "addr0001",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(10000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        bid_info.clone(),
+        bid_msg.clone(),
+    );
+    match res {
+        Err(ContractError::AuctionExpired(borrower)) => assert_eq!(borrower, "borrower0000"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the collateral of an expired auction goes back to the position
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        settle_msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "asset0000".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "custody0000".to_string(),
+                    amount: Uint128::from(20000u128),
+                    msg: to_binary(&CustodyCw20HookMsg::DepositCollateralFor {
+                        borrower: "borrower0000".to_string(),
+                    })
+                    .unwrap(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "overseer0000".to_string(),
+                funds: vec![],
+                msg: to_binary(&OverseerExecuteMsg::ReturnAuctionCollateral {
+                    borrower: "borrower0000".to_string(),
+                    sub_account: None,
+                    collaterals: vec![(
+                        "asset0000".to_string(),
+                        "custody0000".to_string(),
+                        Uint256::from(20000u64),
+                    )],
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    let res = execute(deps.as_mut(), env, bid_info, bid_msg);
+    match res {
+        Err(ContractError::NoActiveAuction(borrower)) => assert_eq!(borrower, "borrower0000"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn query_auction() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: Some("overseer0000".to_string()),
+        auction_duration: Some(100u64),
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let mut env = mock_env();
+    deps.querier
+        .with_custody_contracts(&[(&"asset0000".to_string(), &"custody0000".to_string())]);
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("overseer0000", &[]),
+        ExecuteMsg::StartAuction {
+            borrower: "borrower0000".to_string(),
+            sub_account: None,
+            collaterals: vec![("asset0000".to_string(), Uint256::from(20000u64))],
+        },
+    )
+    .unwrap();
+
+    // the custody contract escrows the seized collateral
+    let escrow_msg = |sender: &str, amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_binary(&Cw20HookMsg::ExecuteBid {
+                liquidator: MOCK_CONTRACT_ADDR.to_string(),
+                fee_address: Some("overseer0000".to_string()),
+                repay_address: Some("market0000".to_string()),
+                borrower: Some("borrower0000".to_string()),
+                premium_rate: None,
+            })
+            .unwrap(),
+        })
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        escrow_msg("custody0000", 20000u128),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![]);
+
+    let query_msg = |bid_amount: Option<Uint256>| QueryMsg::Auction {
+        borrower: "borrower0000".to_string(),
+        bid_amount,
+    };
+    let res: AuctionResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), query_msg(None)).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        AuctionResponse {
+            borrower: "borrower0000".to_string(),
+            sub_account: None,
+            collaterals: vec![("asset0000".to_string(), Uint256::from(20000u64))],
+            start_height: env.block.height,
+            discount: Decimal256::zero(),
+            blocks_elapsed: 0u64,
+            blocks_remaining: 100u64,
+            auction_price: Uint256::from(10000u64),
+            implied_collaterals: vec![("asset0000".to_string(), Uint256::from(20000u64))],
+        }
+    );
+
+    // discount = 5% * 25 / 100 = 1.25%, the auction price is 9,875
+    // and 3,950 of stable buy 40% of the collateral
+    let start_height = env.block.height;
+    env.block.height += 25;
+    let res: AuctionResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            query_msg(Some(Uint256::from(3950u64))),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        AuctionResponse {
+            borrower: "borrower0000".to_string(),
+            sub_account: None,
+            collaterals: vec![("asset0000".to_string(), Uint256::from(20000u64))],
+            start_height,
+            discount: Decimal256::from_str("0.0125").unwrap(),
+            blocks_elapsed: 25u64,
+            blocks_remaining: 75u64,
+            auction_price: Uint256::from(9875u64),
+            implied_collaterals: vec![("asset0000".to_string(), Uint256::from(8000u64))],
+        }
+    );
+
+    // the discount stops at the max premium rate
+    env.block.height += 125;
+    let res: AuctionResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            query_msg(Some(Uint256::from(20000u64))),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.discount, Decimal256::percent(5));
+    assert_eq!(res.blocks_elapsed, 150u64);
+    assert_eq!(res.blocks_remaining, 0u64);
+    assert_eq!(res.auction_price, Uint256::from(9500u64));
+    assert_eq!(
+        res.implied_collaterals,
+        vec![("asset0000".to_string(), Uint256::from(20000u64))]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Auction {
+            borrower: "borrower0001".to_string(),
+            bid_amount: None,
+        },
+    );
+    match res {
+        Err(StdError::GenericErr { msg, .. }) => {
+            assert_eq!(msg, "No auction of borrower0001 is active")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn query_liquidation_amount() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // fee_deductor = 0.931095
+    // expected_repay_amount = 931,095
+    let msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(931095u64),
+        borrow_limit: Uint256::from(900000u64),
+        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        collateral_prices: vec![Decimal256::percent(10)],
+        premium_rates: None,
+    };
+
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res,
+        LiquidationAmountResponse {
+            collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        }
+    );
+
+    let msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(100000u64),
+        borrow_limit: Uint256::from(1000000u64),
+        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        collateral_prices: vec![Decimal256::one()],
+        premium_rates: None,
+    };
+
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res,
+        LiquidationAmountResponse {
+            collaterals: vec![],
+        }
+    );
+
+    let query_msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(1000000u64),
+        borrow_limit: Uint256::from(99999u64),
+        collaterals: vec![
+            ("token0000".to_string(), Uint256::from(1000000u64)),
+            ("token0001".to_string(), Uint256::from(2000000u64)),
+            ("token0002".to_string(), Uint256::from(3000000u64)),
+        ],
+        collateral_prices: vec![
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+        ],
+        premium_rates: None,
+    };
+
+    // fee_deductor = 0.931095
+    // liquidation_ratio = 0.3580014213
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res,
+        LiquidationAmountResponse {
+            collaterals: vec![
+                ("token0000".to_string(), Uint256::from(358002u64)),
+                ("token0001".to_string(), Uint256::from(716003u64)),
+                ("token0002".to_string(), Uint256::from(1074005u64)),
+            ],
+        }
+    );
+
+    // the lower premium of token0000 raises the expected repay amount
+    // expected_repay_amount = 500,000 * 0.970299 + 2,500,000 * 0.931095 = 2,812,886
+    // liquidation_ratio = 0.3555067642
+    let query_msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(1000000u64),
+        borrow_limit: Uint256::from(99999u64),
+        collaterals: vec![
+            ("token0000".to_string(), Uint256::from(1000000u64)),
+            ("token0001".to_string(), Uint256::from(2000000u64)),
+            ("token0002".to_string(), Uint256::from(3000000u64)),
+        ],
+        collateral_prices: vec![
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+        ],
+        premium_rates: Some(vec![
+            Decimal256::percent(1),
+            Decimal256::percent(5),
+            Decimal256::percent(5),
+        ]),
+    };
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res,
+        LiquidationAmountResponse {
+            collaterals: vec![
+                ("token0000".to_string(), Uint256::from(355507u64)),
+                ("token0001".to_string(), Uint256::from(711014u64)),
+                ("token0002".to_string(), Uint256::from(1066521u64)),
+            ],
         }
     );
 
     let query_msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(1000000u64),
+        borrow_limit: Uint256::from(99999u64),
+        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        collateral_prices: vec![Decimal256::percent(50)],
+        premium_rates: Some(vec![]),
+    };
+    match query(deps.as_ref(), mock_env(), query_msg) {
+        Err(StdError::GenericErr { msg, .. }) => {
+            assert_eq!(msg, "premium_rates must match the collaterals")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn max_liquidation_value() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: Some(Uint256::from(500000u64)),
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        config_res.max_liquidation_value,
+        Some(Uint256::from(500000u64))
+    );
+
+    // positions below the cap are liquidated as before
+    let msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(931095u64),
+        borrow_limit: Uint256::from(900000u64),
+        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        collateral_prices: vec![Decimal256::percent(10)],
+        premium_rates: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res,
+        LiquidationAmountResponse {
+            collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        }
+    );
+
+    // collaterals_value = 3,000,000
+    // liquidation_ratio = 0.3580014213 without the cap
+    // 500,000 / 3,000,000 with the cap
+    let msg = QueryMsg::LiquidationAmount {
         borrow_amount: Uint256::from(1000000u64),
         borrow_limit: Uint256::from(99999u64),
         collaterals: vec![
@@ -516,24 +2324,335 @@ fn query_liquidation_amount() {
             Decimal256::percent(50),
             Decimal256::percent(50),
         ],
+        premium_rates: None,
     };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res,
+        LiquidationAmountResponse {
+            collaterals: vec![
+                ("token0000".to_string(), Uint256::from(166667u64)),
+                ("token0001".to_string(), Uint256::from(333334u64)),
+                ("token0002".to_string(), Uint256::from(500000u64)),
+            ],
+        }
+    );
 
-    // fee_deductor = 0.931095
-    // liquidation_ratio = 0.3580014213
-    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    // the next call on the remaining position is capped again
+    // collaterals_value = 2,500,000
+    // liquidation_ratio = 0.2268 without the cap
+    let msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(534453u64),
+        borrow_limit: Uint256::from(83332u64),
+        collaterals: vec![
+            ("token0000".to_string(), Uint256::from(833334u64)),
+            ("token0001".to_string(), Uint256::from(1666667u64)),
+            ("token0002".to_string(), Uint256::from(2500000u64)),
+        ],
+        collateral_prices: vec![
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+        ],
+        premium_rates: None,
+    };
+    let res = query(deps.as_ref(), mock_env(), msg).unwrap();
     let res: LiquidationAmountResponse = from_binary(&res).unwrap();
     assert_eq!(
         res,
         LiquidationAmountResponse {
             collaterals: vec![
-                ("token0000".to_string(), Uint256::from(358001u64)),
-                ("token0001".to_string(), Uint256::from(716002u64)),
-                ("token0002".to_string(), Uint256::from(1074004u64)),
+                ("token0000".to_string(), Uint256::from(166667u64)),
+                ("token0001".to_string(), Uint256::from(333334u64)),
+                ("token0002".to_string(), Uint256::from(500000u64)),
             ],
         }
     );
 }
 
+#[test]
+fn conservative_rounding() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        max_premium_rate: Decimal256::percent(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::SubmitBid {
+        collateral_token: "asset0000".to_string(),
+        premium_rate: Decimal256::percent(1),
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // fee_deductor = 0.931095
+    // liquidation_ratio = 0.3580014213
+    let query_msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(1000000u64),
+        borrow_limit: Uint256::from(99999u64),
+        collaterals: vec![
+            ("token0000".to_string(), Uint256::from(1000000u64)),
+            ("token0001".to_string(), Uint256::from(2000000u64)),
+            ("token0002".to_string(), Uint256::from(3000000u64)),
+        ],
+        collateral_prices: vec![
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+            Decimal256::percent(50),
+        ],
+        premium_rates: None,
+    };
+
+    // the seized collaterals round up, covering the exact
+    // 358001.42, 716002.84 and 1074004.26 needed to restore the loan
+    let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![
+            ("token0000".to_string(), Uint256::from(358002u64)),
+            ("token0001".to_string(), Uint256::from(716003u64)),
+            ("token0002".to_string(), Uint256::from(1074005u64)),
+        ]
+    );
+
+    // required_stable = 20202 * 0.5 * 0.99 = 9999.99 => 9999
+    // bid_fee         = 99.99 rounded up to 100
+    // repay_amount    = 9899, below the exact 9899.01 backed by the bid
+    let bid_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0001".to_string(),
+        amount: Uint128::from(20202u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "addr0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: None,
+            premium_rate: None,
+        })
+        .unwrap(),
+    });
+    let info = mock_info("asset0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), bid_msg.clone()).unwrap();
+    assert_eq!(res.attributes[2], attr("repay_amount", "9899"));
+    assert_eq!(res.attributes[3], attr("bid_fee", "100"));
+
+    // without conservative rounding the residue favors the borrower
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: Some(false),
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![
+            ("token0000".to_string(), Uint256::from(358001u64)),
+            ("token0001".to_string(), Uint256::from(716002u64)),
+            ("token0002".to_string(), Uint256::from(1074004u64)),
+        ]
+    );
+
+    let res = execute(deps.as_mut(), mock_env(), info, bid_msg).unwrap();
+    assert_eq!(res.attributes[2], attr("repay_amount", "9900"));
+    assert_eq!(res.attributes[3], attr("bid_fee", "99"));
+}
+
+#[test]
+fn post_liquidation_safe_ltv() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(80),
+        bid_fee: Decimal256::zero(),
+        max_premium_rate: Decimal256::zero(),
+        liquidation_threshold: Uint256::zero(),
+        price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: Some(false),
+        post_liquidation_safe_ltv: Some(Decimal256::one()),
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    };
+
+    let info = mock_info("owner0000", &[]);
+    let res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::InvalidPostLiquidationSafeLtv {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = InstantiateMsg {
+        post_liquidation_safe_ltv: None,
+        ..msg
+    };
+    instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    // the position sits at 70% LTV with a 60% liquidation LTV
+    let query_msg = QueryMsg::LiquidationAmount {
+        borrow_amount: Uint256::from(700000u64),
+        borrow_limit: Uint256::from(600000u64),
+        collaterals: vec![("token0000".to_string(), Uint256::from(1000000u64))],
+        collateral_prices: vec![Decimal256::one()],
+        premium_rates: None,
+    };
+
+    // without a safe LTV the loan is brought to the safe ratio
+    // of the borrow limit, 276924 / 576924 = 48%
+    let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![("token0000".to_string(), Uint256::from(423076u64))]
+    );
+
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: Some(Decimal256::one()),
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::InvalidPostLiquidationSafeLtv {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: Some(Decimal256::percent(50)),
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    // 400000 seized and repaid leaves a 300000 loan against
+    // 600000 of collaterals, at the 50% safe LTV rather than
+    // at the 60% edge
+    let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![("token0000".to_string(), Uint256::from(400000u64))]
+    );
+    let seized = res.collaterals[0].1;
+    assert_eq!(
+        Decimal256::from_ratio(
+            Uint256::from(700000u64) - seized,
+            Uint256::from(1000000u64) - seized
+        ),
+        Decimal256::percent(50)
+    );
+
+    // a safe LTV above the liquidation LTV falls back to the safe ratio
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner: None,
+        oracle_contract: None,
+        stable_denom: None,
+        safe_ratio: None,
+        bid_fee: None,
+        max_premium_rate: None,
+        liquidation_threshold: None,
+        price_timeframe: None,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: Some(Decimal256::percent(65)),
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
+    }));
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    let res: LiquidationAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![("token0000".to_string(), Uint256::from(423076u64))]
+    );
+}
+
 #[test]
 fn query_bids_by_user() {
     let mut deps = mock_dependencies(&[]);
@@ -551,6 +2670,13 @@ fn query_bids_by_user() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -723,6 +2849,13 @@ fn query_bids_by_collateral() {
         max_premium_rate: Decimal256::percent(5),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
+        liquidation_fee_recipient: None,
+        max_liquidation_value: None,
+        conservative_rounding: None,
+        post_liquidation_safe_ltv: None,
+        overseer_contract: None,
+        auction_duration: None,
+        self_liquidation_fee: None,
     };
 
     let info = mock_info("addr0000", &[]);
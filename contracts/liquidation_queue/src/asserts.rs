@@ -62,6 +62,18 @@ pub fn assert_fees(fees: Decimal256) -> StdResult<()> {
     Ok(())
 }
 
+pub fn assert_self_liquidation_bonus(
+    self_liquidation_bonus: Decimal256,
+    liquidator_fee: Decimal256,
+) -> StdResult<()> {
+    if self_liquidation_bonus > liquidator_fee {
+        return Err(StdError::generic_err(
+            "self_liquidation_bonus can not be greater than liquidator_fee",
+        ));
+    }
+    Ok(())
+}
+
 pub fn assert_max_slot_premium(max_slot: u8, premium_rate_per_slot: Decimal256) -> StdResult<()> {
     let max_slot_premium =
         premium_rate_per_slot * Decimal256::from_uint256(Uint256::from(max_slot as u128));
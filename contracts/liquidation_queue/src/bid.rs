@@ -13,6 +13,7 @@ use cosmwasm_std::{
 };
 use cw20::Cw20ExecuteMsg;
 use moneymarket::oracle::PriceResponse;
+use moneymarket::overseer::TokenType;
 use moneymarket::querier::{deduct_tax, query_price, TimeConstraints};
 
 /// Stable asset is submitted to create a bid record. If available bids for the collateral is under
@@ -296,6 +297,7 @@ pub fn execute_liquidation(
     liquidator: String,
     repay_address: String,
     fee_address: String,
+    borrower: Option<String>,
     collateral_token: String,
     amount: Uint256,
 ) -> StdResult<Response> {
@@ -305,15 +307,22 @@ pub fn execute_liquidation(
         read_collateral_info(deps.storage, &collateral_token_raw)?;
     let available_bids: Uint256 = read_total_bids(deps.storage, &collateral_token_raw)?;
 
-    // only collateral token custody can execute liquidations
+    // only collateral token custody, or the overseer holding a native
+    // collateral, can execute liquidations
     let overseer = deps.api.addr_humanize(&config.overseer)?;
-    let custody_contract = query_collateral_whitelist_info(
+    let whitelist_elem = query_collateral_whitelist_info(
         &deps.querier,
         overseer.to_string(),
         collateral_token.to_string(),
-    )?
-    .custody_contract;
-    if sender != custody_contract {
+    )?;
+    let authorized = match whitelist_elem.token_type {
+        TokenType::Native { .. } => sender == overseer.as_str(),
+        TokenType::Cw20 => whitelist_elem
+            .custody_contracts
+            .iter()
+            .any(|c| c.0 == sender),
+    };
+    if !authorized {
         return Err(StdError::generic_err(
             "Unauthorized: only custody contract can execute liquidations",
         ));
@@ -379,7 +388,13 @@ pub fn execute_liquidation(
     )?;
 
     let bid_fee = repay_amount * config.bid_fee;
-    let liquidator_fee = repay_amount * config.liquidator_fee;
+    // Borrowers unwinding their own position pay the reduced bonus
+    let liquidator_fee_rate = if borrower.as_ref() == Some(&liquidator) {
+        config.self_liquidation_bonus
+    } else {
+        config.liquidator_fee
+    };
+    let liquidator_fee = repay_amount * liquidator_fee_rate;
     let repay_amount = repay_amount - bid_fee - liquidator_fee;
 
     let mut messages: Vec<CosmosMsg> = vec![CosmosMsg::Bank(BankMsg::Send {
@@ -511,14 +526,33 @@ pub fn claim_liquidations(
 
     let mut messages: Vec<CosmosMsg> = vec![];
     if !claim_amount.is_zero() {
-        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: collateral_token.clone(),
-            funds: vec![],
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: info.sender.to_string(),
-                amount: claim_amount.into(),
-            })?,
-        }));
+        let config: Config = read_config(deps.storage)?;
+        let token_type = query_collateral_whitelist_info(
+            &deps.querier,
+            deps.api.addr_humanize(&config.overseer)?.to_string(),
+            collateral_token.clone(),
+        )?
+        .token_type;
+        messages.push(match token_type {
+            TokenType::Native { denom } => CosmosMsg::Bank(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom,
+                        amount: claim_amount.into(),
+                    },
+                )?],
+            }),
+            TokenType::Cw20 => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: collateral_token.clone(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: info.sender.to_string(),
+                    amount: claim_amount.into(),
+                })?,
+            }),
+        });
     }
 
     Ok(Response::new().add_messages(messages).add_attributes(vec![
@@ -1,7 +1,9 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
-use crate::asserts::{assert_fees, assert_max_slot, assert_max_slot_premium};
+use crate::asserts::{
+    assert_fees, assert_max_slot, assert_max_slot_premium, assert_self_liquidation_bonus,
+};
 use crate::bid::{activate_bids, claim_liquidations, execute_liquidation, retract_bid, submit_bid};
 use crate::querier::query_collateral_whitelist_info;
 use crate::query::{
@@ -18,6 +20,7 @@ use cosmwasm_std::{
 };
 use cw20::Cw20ReceiveMsg;
 use moneymarket::liquidation_queue::{Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg};
+use moneymarket::overseer::TokenType;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -27,6 +30,7 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
     assert_fees(msg.liquidator_fee + msg.bid_fee)?;
+    assert_self_liquidation_bonus(msg.self_liquidation_bonus, msg.liquidator_fee)?;
 
     store_config(
         deps.storage,
@@ -37,6 +41,7 @@ pub fn instantiate(
             safe_ratio: msg.safe_ratio,
             bid_fee: msg.bid_fee,
             liquidator_fee: msg.liquidator_fee,
+            self_liquidation_bonus: msg.self_liquidation_bonus,
             liquidation_threshold: msg.liquidation_threshold,
             price_timeframe: msg.price_timeframe,
             waiting_period: msg.waiting_period,
@@ -57,6 +62,7 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             safe_ratio,
             bid_fee,
             liquidator_fee,
+            self_liquidation_bonus,
             liquidation_threshold,
             price_timeframe,
             waiting_period,
@@ -69,6 +75,7 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             safe_ratio,
             bid_fee,
             liquidator_fee,
+            self_liquidation_bonus,
             liquidation_threshold,
             price_timeframe,
             waiting_period,
@@ -105,6 +112,22 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             collateral_token,
             bids_idx,
         } => claim_liquidations(deps, env, info, collateral_token, bids_idx),
+        ExecuteMsg::ExecuteBid {
+            collateral_token,
+            liquidator,
+            fee_address,
+            repay_address,
+            borrower,
+        } => execute_native_bid(
+            deps,
+            env,
+            info,
+            collateral_token,
+            liquidator,
+            fee_address,
+            repay_address,
+            borrower,
+        ),
     }
 }
 
@@ -120,6 +143,7 @@ pub fn receive_cw20(
             liquidator,
             repay_address,
             fee_address,
+            borrower,
         } => {
             let collateral_token = contract_addr.to_string();
             let repay_address = repay_address.unwrap_or_else(|| cw20_msg.sender.clone());
@@ -132,6 +156,7 @@ pub fn receive_cw20(
                 liquidator,
                 repay_address,
                 fee_address,
+                borrower,
                 collateral_token,
                 cw20_msg.amount.into(),
             )
@@ -139,6 +164,59 @@ pub fn receive_cw20(
     }
 }
 
+/// Native collaterals held by the overseer are sent with the bid message
+/// instead of a CW20 hook, in the denom they are whitelisted with
+#[allow(clippy::too_many_arguments)]
+pub fn execute_native_bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral_token: String,
+    liquidator: String,
+    fee_address: Option<String>,
+    repay_address: Option<String>,
+    borrower: Option<String>,
+) -> StdResult<Response> {
+    let config: Config = read_config(deps.storage)?;
+    let overseer = deps.api.addr_humanize(&config.overseer)?;
+    let denom = match query_collateral_whitelist_info(
+        &deps.querier,
+        overseer.to_string(),
+        collateral_token.clone(),
+    )?
+    .token_type
+    {
+        TokenType::Native { denom } => denom,
+        TokenType::Cw20 => {
+            return Err(StdError::generic_err(
+                "CW20 collaterals must be sent through the custody contract",
+            ))
+        }
+    };
+    if info.funds.len() != 1 || info.funds[0].denom != denom {
+        return Err(StdError::generic_err(format!(
+            "Native collateral must be sent in {}",
+            denom
+        )));
+    }
+
+    let sender = info.sender.to_string();
+    let repay_address = repay_address.unwrap_or_else(|| sender.clone());
+    let fee_address = fee_address.unwrap_or_else(|| sender.clone());
+
+    execute_liquidation(
+        deps,
+        env,
+        sender,
+        liquidator,
+        repay_address,
+        fee_address,
+        borrower,
+        collateral_token,
+        info.funds[0].amount.into(),
+    )
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn update_config(
     deps: DepsMut,
@@ -148,6 +226,7 @@ pub fn update_config(
     safe_ratio: Option<Decimal256>,
     bid_fee: Option<Decimal256>,
     liquidator_fee: Option<Decimal256>,
+    self_liquidation_bonus: Option<Decimal256>,
     liquidation_threshold: Option<Uint256>,
     price_timeframe: Option<u64>,
     waiting_period: Option<u64>,
@@ -180,6 +259,11 @@ pub fn update_config(
         config.liquidator_fee = liquidator_fee;
     }
 
+    if let Some(self_liquidation_bonus) = self_liquidation_bonus {
+        config.self_liquidation_bonus = self_liquidation_bonus;
+    }
+    assert_self_liquidation_bonus(config.self_liquidation_bonus, config.liquidator_fee)?;
+
     if let Some(liquidation_threshold) = liquidation_threshold {
         config.liquidation_threshold = liquidation_threshold;
     }
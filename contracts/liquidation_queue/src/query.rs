@@ -22,6 +22,7 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
         safe_ratio: config.safe_ratio,
         bid_fee: config.bid_fee,
         liquidator_fee: config.liquidator_fee,
+        self_liquidation_bonus: config.self_liquidation_bonus,
         liquidation_threshold: config.liquidation_threshold,
         price_timeframe: config.price_timeframe,
         waiting_period: config.waiting_period,
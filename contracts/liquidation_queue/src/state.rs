@@ -26,6 +26,7 @@ pub struct Config {
     pub safe_ratio: Decimal256,
     pub bid_fee: Decimal256,
     pub liquidator_fee: Decimal256,
+    pub self_liquidation_bonus: Decimal256,
     pub liquidation_threshold: Uint256,
     pub price_timeframe: u64,
     pub waiting_period: u64,
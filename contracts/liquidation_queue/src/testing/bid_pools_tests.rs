@@ -25,6 +25,7 @@ fn one_bidder_distribution() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -87,6 +88,7 @@ fn one_bidder_distribution() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -140,6 +142,7 @@ fn two_bidder_distribution() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -202,6 +205,7 @@ fn two_bidder_distribution() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -250,6 +254,7 @@ fn two_bidder_distribution() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -326,6 +331,7 @@ fn two_bidder_distribution_big_numbers() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -388,6 +394,7 @@ fn two_bidder_distribution_big_numbers() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -436,6 +443,7 @@ fn two_bidder_distribution_big_numbers() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -512,6 +520,7 @@ fn one_user_two_bid_slots() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 10u64,
         waiting_period: 60u64,
@@ -588,6 +597,7 @@ fn one_user_two_bid_slots() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -618,6 +628,7 @@ fn one_user_two_bid_slots() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -678,6 +689,7 @@ fn partial_withdraw_after_execution() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -740,6 +752,7 @@ fn partial_withdraw_after_execution() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -784,6 +797,7 @@ fn partial_withdraw_after_execution() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -879,6 +893,7 @@ fn completely_empty_pool() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -930,6 +945,7 @@ fn completely_empty_pool() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -997,6 +1013,7 @@ fn completely_empty_pool() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1057,6 +1074,7 @@ fn product_truncated_to_zero() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -1110,6 +1128,7 @@ fn product_truncated_to_zero() {
                 liquidator: "liquidator00000".to_string(),
                 fee_address: Some("fee0000".to_string()),
                 repay_address: Some("repay0000".to_string()),
+                borrower: None,
             })
             .unwrap(),
         });
@@ -1179,6 +1198,7 @@ fn two_bidder_reward_distribution_common_slot() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -1269,6 +1289,7 @@ fn two_bidder_reward_distribution_common_slot() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1356,6 +1377,7 @@ fn two_bidder_distribution_multiple_common_slots() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1501,6 +1523,7 @@ fn two_bidder_distribution_multiple_common_slots() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1516,6 +1539,7 @@ fn two_bidder_distribution_multiple_common_slots() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1671,6 +1695,7 @@ fn two_bidder_unequal_deposit_reward_distribution() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -1761,6 +1786,7 @@ fn two_bidder_unequal_deposit_reward_distribution() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1855,6 +1881,7 @@ fn scalable_reward_distribution_after_multiple_liquidations() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1976,6 +2003,7 @@ fn scalable_reward_distribution_after_multiple_liquidations() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2049,6 +2077,7 @@ fn scalable_reward_distribution_after_multiple_liquidations() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2110,6 +2139,7 @@ fn not_enough_bid_for_collateral() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -2198,6 +2228,7 @@ fn not_enough_bid_for_collateral() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2226,6 +2257,7 @@ fn two_bidder_reward_distribution_common_slot_large_numbers() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -2314,6 +2346,7 @@ fn two_bidder_reward_distribution_common_slot_large_numbers() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
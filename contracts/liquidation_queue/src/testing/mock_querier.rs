@@ -1,4 +1,4 @@
-use moneymarket::overseer::{WhitelistResponse, WhitelistResponseElem};
+use moneymarket::overseer::{TokenType, WhitelistResponse, WhitelistResponseElem};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -51,12 +51,14 @@ pub struct WasmMockQuerier {
 #[derive(Clone, Default)]
 pub struct CollateralQuerier {
     collaterals: HashMap<String, Decimal256>,
+    native_denoms: HashMap<String, String>,
 }
 
 impl CollateralQuerier {
     pub fn new(collaterals: &[(&String, &Decimal256)]) -> Self {
         CollateralQuerier {
             collaterals: collaterals_to_map(collaterals),
+            native_denoms: HashMap::new(),
         }
     }
 }
@@ -177,6 +179,7 @@ impl WasmMockQuerier {
                                 rate: v.0,
                                 last_updated_base: v.1,
                                 last_updated_quote: v.2,
+                                confidence_spread: None,
                             })))
                         }
                         None => SystemResult::Err(SystemError::InvalidRequest {
@@ -193,7 +196,7 @@ impl WasmMockQuerier {
                     match self
                         .collateral_querier
                         .collaterals
-                        .get(&collateral_token.unwrap())
+                        .get(collateral_token.as_ref().unwrap())
                     {
                         Some(v) => {
                             SystemResult::Ok(ContractResult::from(to_binary(&WhitelistResponse {
@@ -202,7 +205,31 @@ impl WasmMockQuerier {
                                     symbol: "symbol".to_string(),
                                     max_ltv: *v,
                                     custody_contract: "custody0000".to_string(),
+                                    custody_contracts: vec![(
+                                        "custody0000".to_string(),
+                                        Decimal256::one(),
+                                    )],
                                     collateral_token: "token0000".to_string(),
+                                    quote_denom: None,
+                                    liquidation_premium: None,
+                                    correlation_group: None,
+                                    min_lock_amount: None,
+                                    borrow_enabled_from: None,
+                                    borrow_disabled_from: None,
+                                    liquidation_contract: None,
+                                    decimals: 6,
+                                    token_type: match self
+                                        .collateral_querier
+                                        .native_denoms
+                                        .get(collateral_token.as_ref().unwrap())
+                                    {
+                                        Some(denom) => TokenType::Native {
+                                            denom: denom.clone(),
+                                        },
+                                        None => TokenType::Cw20,
+                                    },
+                                    exchange_rate_source: None,
+                                    borrow_rate_premium: None,
                                 }],
                             })))
                         }
@@ -244,4 +271,10 @@ impl WasmMockQuerier {
     pub fn with_collateral_max_ltv(&mut self, collaterals: &[(&String, &Decimal256)]) {
         self.collateral_querier = CollateralQuerier::new(collaterals);
     }
+
+    pub fn with_native_collateral(&mut self, collateral_token: &str, denom: &str) {
+        self.collateral_querier
+            .native_denoms
+            .insert(collateral_token.to_string(), denom.to_string());
+    }
 }
@@ -109,6 +109,7 @@ fn instantiate_and_whitelist(deps: &mut OwnedDeps<MemoryStorage, MockApi, WasmMo
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 101u64,
         waiting_period: 60u64,
@@ -175,6 +176,7 @@ fn simulate_bids_with_2_liq_amounts(
                     liquidator: "liquidator00000".to_string(),
                     fee_address: Some("fee0000".to_string()),
                     repay_address: Some("repay0000".to_string()),
+                    borrower: None,
                 })
                 .unwrap(),
             });
@@ -191,6 +193,7 @@ fn simulate_bids_with_2_liq_amounts(
                     liquidator: "liquidator00000".to_string(),
                     fee_address: Some("fee0000".to_string()),
                     repay_address: Some("repay0000".to_string()),
+                    borrower: None,
                 })
                 .unwrap(),
             });
@@ -25,6 +25,7 @@ fn partial_one_collateral_one_slot_high_ltv() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -97,6 +98,7 @@ fn partial_one_collateral_one_slot_high_ltv() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -131,6 +133,7 @@ fn partial_one_collateral_one_slot() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -203,6 +206,7 @@ fn partial_one_collateral_one_slot() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -237,6 +241,7 @@ fn partial_one_collateral_one_slot_with_fees() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -309,6 +314,7 @@ fn partial_one_collateral_one_slot_with_fees() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -352,6 +358,7 @@ fn partial_one_collateral_one_slot_with_fees_all() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(1),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -424,6 +431,7 @@ fn partial_one_collateral_one_slot_with_fees_all() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -474,6 +482,7 @@ fn partial_one_collateral_two_slots() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -553,6 +562,7 @@ fn partial_one_collateral_two_slots() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -587,6 +597,7 @@ fn partial_one_collateral_two_slots_with_fees() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -666,6 +677,7 @@ fn partial_one_collateral_two_slots_with_fees() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -709,6 +721,7 @@ fn non_partial_liquidation() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(1000000u128),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -774,6 +787,7 @@ fn non_partial_liquidation() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -808,6 +822,7 @@ fn non_partial_liquidation_two_slots() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(1000000u128),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -878,6 +893,7 @@ fn non_partial_liquidation_two_slots() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -912,6 +928,7 @@ fn non_partial_liquidation_with_fees() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(1000000u128),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -977,6 +994,7 @@ fn non_partial_liquidation_with_fees() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1020,6 +1038,7 @@ fn non_partial_liquidation_two_slots_with_fees() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(1000000u128),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1090,6 +1109,7 @@ fn non_partial_liquidation_two_slots_with_fees() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1133,6 +1153,7 @@ fn non_partial_liquidation_two_slots_with_fees_big_nums() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(2000000000u128),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1203,6 +1224,7 @@ fn non_partial_liquidation_two_slots_with_fees_big_nums() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1248,6 +1270,7 @@ fn partial_two_collaterals_ltv_diff() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1352,6 +1375,7 @@ fn partial_two_collaterals_ltv_diff() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1376,6 +1400,7 @@ fn partial_two_collaterals_ltv_diff() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1413,6 +1438,7 @@ fn partial_two_collaterals_multi_slots_per_col() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1527,6 +1553,7 @@ fn partial_two_collaterals_multi_slots_per_col() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1551,6 +1578,7 @@ fn partial_two_collaterals_multi_slots_per_col() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1588,6 +1616,7 @@ fn partial_two_collaterals_one_slot_diff_ltv() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1692,6 +1721,7 @@ fn partial_two_collaterals_one_slot_diff_ltv() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1716,6 +1746,7 @@ fn partial_two_collaterals_one_slot_diff_ltv() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1754,6 +1785,7 @@ fn partial_three_collaterals_one_slot_diff_ltv() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -1886,6 +1918,7 @@ fn partial_three_collaterals_one_slot_diff_ltv() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1910,6 +1943,7 @@ fn partial_three_collaterals_one_slot_diff_ltv() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1934,6 +1968,7 @@ fn partial_three_collaterals_one_slot_diff_ltv() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -1972,6 +2007,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -2110,6 +2146,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2134,6 +2171,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2158,6 +2196,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2196,6 +2235,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts_2() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -2334,6 +2374,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts_2() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2358,6 +2399,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts_2() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2382,6 +2424,7 @@ fn partial_three_collaterals_one_slot_diff_ltv_big_amounts_2() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2419,6 +2462,7 @@ fn not_enough_bids_for_one_of_two_col() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -2530,6 +2574,7 @@ fn not_enough_bids_for_one_of_two_col() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2563,6 +2608,7 @@ fn not_enough_bids_for_one_of_two_col() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -2609,6 +2655,7 @@ fn integration_test_simul() {
         safe_ratio: Decimal256::percent(80),
         bid_fee: Decimal256::percent(0),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::zero(),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -2697,6 +2744,7 @@ fn integration_test_simul() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -28,6 +28,7 @@ fn query_liquidation_amount() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -165,6 +166,7 @@ fn query_bids() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -346,6 +348,7 @@ fn query_bid_pools() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -501,6 +504,7 @@ fn query_collateral_info() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -23,6 +23,7 @@ fn proper_initialization() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -47,6 +48,7 @@ fn proper_initialization() {
             safe_ratio: Decimal256::percent(10),
             bid_fee: Decimal256::percent(1),
             liquidator_fee: Decimal256::percent(0),
+            self_liquidation_bonus: Decimal256::zero(),
             liquidation_threshold: Uint256::from(100000000u64),
             price_timeframe: 60u64,
             waiting_period: 60u64,
@@ -66,6 +68,7 @@ fn update_config() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -83,6 +86,7 @@ fn update_config() {
         safe_ratio: None,
         bid_fee: None,
         liquidator_fee: None,
+        self_liquidation_bonus: None,
         liquidation_threshold: None,
         price_timeframe: None,
         waiting_period: None,
@@ -104,6 +108,7 @@ fn update_config() {
             safe_ratio: Decimal256::percent(10),
             bid_fee: Decimal256::percent(1),
             liquidator_fee: Decimal256::percent(0),
+            self_liquidation_bonus: Decimal256::zero(),
             liquidation_threshold: Uint256::from(100000000u64),
             price_timeframe: 60u64,
             waiting_period: 60u64,
@@ -119,6 +124,7 @@ fn update_config() {
         safe_ratio: Some(Decimal256::percent(15)),
         bid_fee: Some(Decimal256::percent(2)),
         liquidator_fee: Some(Decimal256::percent(1)),
+        self_liquidation_bonus: None,
         liquidation_threshold: Some(Uint256::from(150000000u64)),
         price_timeframe: Some(120u64),
         waiting_period: Some(100u64),
@@ -140,6 +146,7 @@ fn update_config() {
             safe_ratio: Decimal256::percent(15),
             bid_fee: Decimal256::percent(2),
             liquidator_fee: Decimal256::percent(1),
+            self_liquidation_bonus: Decimal256::zero(),
             liquidation_threshold: Uint256::from(150000000u64),
             price_timeframe: 120u64,
             waiting_period: 100u64,
@@ -147,6 +154,26 @@ fn update_config() {
         }
     );
 
+    // self_liquidation_bonus can not exceed liquidator_fee
+    let info = mock_info("owner0001", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        oracle_contract: None,
+        safe_ratio: None,
+        bid_fee: None,
+        liquidator_fee: None,
+        self_liquidation_bonus: Some(Decimal256::percent(2)),
+        liquidation_threshold: None,
+        price_timeframe: None,
+        waiting_period: None,
+        overseer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err("self_liquidation_bonus can not be greater than liquidator_fee")
+    );
+
     // Unauthorized err
     let info = mock_info("owner0000", &[]);
     let msg = ExecuteMsg::UpdateConfig {
@@ -155,6 +182,7 @@ fn update_config() {
         safe_ratio: Some(Decimal256::percent(1)),
         bid_fee: Some(Decimal256::percent(2)),
         liquidator_fee: Some(Decimal256::percent(1)),
+        self_liquidation_bonus: None,
         liquidation_threshold: Some(Uint256::from(150000000u64)),
         price_timeframe: Some(100u64),
         waiting_period: Some(100u64),
@@ -178,6 +206,7 @@ fn submit_bid() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -292,6 +321,7 @@ fn activate_bid() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -407,6 +437,7 @@ fn retract_bid() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -480,6 +511,7 @@ fn retract_unactive_bid() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
@@ -559,6 +591,7 @@ fn execute_bid() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(1),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 100000u64,
         waiting_period: 60u64,
@@ -622,6 +655,7 @@ fn execute_bid() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -642,6 +676,7 @@ fn execute_bid() {
             liquidator: "liquidator0000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -681,6 +716,7 @@ fn execute_bid() {
             liquidator: "liquidator0000".to_string(),
             fee_address: None,
             repay_address: None,
+            borrower: None,
         })
         .unwrap(),
     });
@@ -719,6 +755,7 @@ fn execute_bid() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -729,6 +766,162 @@ fn execute_bid() {
     );
 }
 
+#[test]
+fn execute_self_liquidation() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier
+        .with_collateral_max_ltv(&[(&"asset0000".to_string(), &Decimal256::percent(90))]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        liquidator_fee: Decimal256::percent(1),
+        self_liquidation_bonus: Decimal256::permille(5),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 100000u64,
+        waiting_period: 60u64,
+        overseer: "overseer0000".to_string(),
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::WhitelistCollateral {
+        collateral_token: "asset0000".to_string(),
+        max_slot: 30u8,
+        bid_threshold: Uint256::zero(),
+        premium_rate_per_slot: Decimal256::percent(1),
+    };
+    let info = mock_info("owner0000", &[]);
+    execute(deps.as_mut(), env, info, msg).unwrap();
+
+    let msg = ExecuteMsg::SubmitBid {
+        collateral_token: "asset0000".to_string(),
+        premium_slot: 1u8,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    let env = mock_env();
+    let wait_end = env.block.time.plus_seconds(60u64);
+    execute(deps.as_mut(), env, info, msg).unwrap();
+
+    let msg = ExecuteMsg::ActivateBids {
+        collateral_token: "asset0000".to_string(),
+        bids_idx: Some(vec![Uint128::from(1u128)]),
+    };
+    let mut env = mock_env();
+    env.block.time = wait_end;
+    let info = mock_info("addr0000", &[]);
+    execute(deps.as_mut(), env, info, msg).unwrap();
+
+    // required_stable 495,000
+    // bid_fee         4,950
+    // self_bonus      2,475
+    // repay_amount    487,575
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "custody0000".to_string(),
+        amount: Uint128::from(1000000u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "addr0001".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: Some("addr0001".to_string()),
+        })
+        .unwrap(),
+    });
+    let info = mock_info("asset0000", &[]);
+    let env = mock_env();
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "repay0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(482747u128), // 487575 / (1 + tax_rate)
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "fee0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(4900u128), // 4950 / (1 + tax_rate)
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0001".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(2450u128), // 2475 / (1 + tax_rate)
+                }]
+            })),
+        ]
+    );
+
+    // a third party liquidating the same borrower still gets the full liquidator_fee
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "custody0000".to_string(),
+        amount: Uint128::from(1000000u128),
+        msg: to_binary(&Cw20HookMsg::ExecuteBid {
+            liquidator: "liquidator0000".to_string(),
+            fee_address: Some("fee0000".to_string()),
+            repay_address: Some("repay0000".to_string()),
+            borrower: Some("addr0001".to_string()),
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "repay0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(480297u128), // 485100 / (1 + tax_rate)
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "fee0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(4900u128), // 4950 / (1 + tax_rate)
+                }]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "liquidator0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(4900u128), // 4950 / (1 + tax_rate)
+                }]
+            })),
+        ]
+    );
+}
+
 #[test]
 fn claim_liquidations() {
     let mut deps = mock_dependencies(&[]);
@@ -745,6 +938,7 @@ fn claim_liquidations() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 1000000u64,
         waiting_period: 60u64,
@@ -807,6 +1001,7 @@ fn claim_liquidations() {
             liquidator: "liquidator00000".to_string(),
             fee_address: Some("fee0000".to_string()),
             repay_address: Some("repay0000".to_string()),
+            borrower: None,
         })
         .unwrap(),
     });
@@ -828,6 +1023,151 @@ fn claim_liquidations() {
     );
 }
 
+#[test]
+fn execute_native_bid() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier
+        .with_collateral_max_ltv(&[(&"asset0000".to_string(), &Decimal256::percent(90))]);
+    deps.querier.with_native_collateral("asset0000", "uluna");
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        oracle_contract: "oracle0000".to_string(),
+        stable_denom: "uusd".to_string(),
+        safe_ratio: Decimal256::percent(10),
+        bid_fee: Decimal256::percent(1),
+        liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
+        liquidation_threshold: Uint256::from(100000000u64),
+        price_timeframe: 1000000u64,
+        waiting_period: 60u64,
+        overseer: "overseer0000".to_string(),
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let env = mock_env();
+    deps.querier.with_oracle_price(&[(
+        &("asset0000".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+    let msg = ExecuteMsg::WhitelistCollateral {
+        collateral_token: "asset0000".to_string(),
+        max_slot: 30u8,
+        bid_threshold: Uint256::zero(),
+        premium_rate_per_slot: Decimal256::percent(1),
+    };
+    let info = mock_info("owner0000", &[]);
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::SubmitBid {
+        collateral_token: "asset0000".to_string(),
+        premium_slot: 1u8,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    let env = mock_env();
+    let wait_end = env.block.time.plus_seconds(60u64);
+    execute(deps.as_mut(), env, info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::ActivateBids {
+        collateral_token: "asset0000".to_string(),
+        bids_idx: Some(vec![Uint128::from(1u128)]),
+    };
+    let mut env = mock_env();
+    env.block.time = wait_end;
+    execute(deps.as_mut(), env, info, msg).unwrap();
+
+    let msg = ExecuteMsg::ExecuteBid {
+        collateral_token: "asset0000".to_string(),
+        liquidator: "liquidator00000".to_string(),
+        fee_address: Some("fee0000".to_string()),
+        repay_address: Some("repay0000".to_string()),
+        borrower: None,
+    };
+
+    // only the overseer holds native collaterals
+    let info = mock_info(
+        "custody0000",
+        &[Coin {
+            denom: "uluna".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+    assert_eq!(
+        res,
+        StdError::generic_err("Unauthorized: only custody contract can execute liquidations")
+    );
+
+    let info = mock_info(
+        "overseer0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+    assert_eq!(
+        res,
+        StdError::generic_err("Native collateral must be sent in uluna")
+    );
+
+    // required_stable 495,000
+    // bid_fee         4,950
+    // repay_amount    490,050
+    let info = mock_info(
+        "overseer0000",
+        &[Coin {
+            denom: "uluna".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "repay0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(485198u128),
+            }]
+        }))
+    );
+
+    // the liquidated collateral is claimed in its native denom
+    let msg = ExecuteMsg::ClaimLiquidations {
+        collateral_token: "asset0000".to_string(),
+        bids_idx: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(1000000u128),
+            }]
+        }))]
+    );
+}
+
 #[test]
 fn update_collateral_info() {
     let mut deps = mock_dependencies(&[]);
@@ -841,6 +1181,7 @@ fn update_collateral_info() {
         safe_ratio: Decimal256::percent(10),
         bid_fee: Decimal256::percent(1),
         liquidator_fee: Decimal256::percent(0),
+        self_liquidation_bonus: Decimal256::zero(),
         liquidation_threshold: Uint256::from(100000000u64),
         price_timeframe: 60u64,
         waiting_period: 60u64,
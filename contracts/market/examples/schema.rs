@@ -4,8 +4,11 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use moneymarket::market::{
-    BorrowerInfoResponse, BorrowerInfosResponse, ConfigResponse, Cw20HookMsg, EpochStateResponse,
-    ExecuteMsg, InstantiateMsg, QueryMsg,
+    AccrualParamsResponse, AccruedInterestResponse, BorrowAprResponse, BorrowerInfoResponse,
+    BorrowerInfosResponse, CanBorrowResponse, ConfigResponse, CreditLineResponse, Cw20HookMsg,
+    DepositApyResponse, EpochStateResponse, ExchangeRateBetweenResponse, ExecuteMsg,
+    InstantiateMsg, InterestIndexesResponse, LastAccrualResponse, MaxRedeemableResponse, QueryMsg,
+    ReserveCoverageResponse,
 };
 use moneymarket_market::state::State;
 
@@ -24,4 +27,15 @@ fn main() {
     export_schema(&schema_for!(EpochStateResponse), &out_dir);
     export_schema(&schema_for!(BorrowerInfoResponse), &out_dir);
     export_schema(&schema_for!(BorrowerInfosResponse), &out_dir);
+    export_schema(&schema_for!(AccruedInterestResponse), &out_dir);
+    export_schema(&schema_for!(ExchangeRateBetweenResponse), &out_dir);
+    export_schema(&schema_for!(AccrualParamsResponse), &out_dir);
+    export_schema(&schema_for!(InterestIndexesResponse), &out_dir);
+    export_schema(&schema_for!(CanBorrowResponse), &out_dir);
+    export_schema(&schema_for!(BorrowAprResponse), &out_dir);
+    export_schema(&schema_for!(DepositApyResponse), &out_dir);
+    export_schema(&schema_for!(LastAccrualResponse), &out_dir);
+    export_schema(&schema_for!(MaxRedeemableResponse), &out_dir);
+    export_schema(&schema_for!(CreditLineResponse), &out_dir);
+    export_schema(&schema_for!(ReserveCoverageResponse), &out_dir);
 }
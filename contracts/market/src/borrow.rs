@@ -1,89 +1,250 @@
 use anchor_token::distributor::ExecuteMsg as FaucetExecuteMsg;
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    attr, to_binary, Addr, BankMsg, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response,
-    StdResult, WasmMsg,
+    attr, to_binary, Addr, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Response, StdResult, Storage, SubMsg, WasmMsg,
 };
+use cw20::Cw20ExecuteMsg;
+use moneymarket::converter::ExecuteMsg as ConverterExecuteMsg;
+use moneymarket::custody::Cw20HookMsg as CustodyCw20HookMsg;
 use moneymarket::interest_model::BorrowRateResponse;
-use moneymarket::market::{BorrowerInfoResponse, BorrowerInfosResponse};
-use moneymarket::overseer::BorrowLimitResponse;
-use moneymarket::querier::{deduct_tax, query_balance, query_supply};
+use moneymarket::market::{
+    BorrowerInfoResponse, BorrowerInfosResponse, CanBorrowResponse, CreditLineResponse, ExecuteMsg,
+    InterestIndexesResponse, OverpayAction, SameBlockRepayPolicy,
+};
+use moneymarket::overseer::{BorrowLimitResponse, ExecuteMsg as OverseerExecuteMsg};
+use moneymarket::querier::{deduct_tax, query_balance, query_supply, query_token_balance};
+
+use crate::contract::{split_custody_amount, LEVERAGE_STEP_REPLY_ID};
 
-use crate::deposit::compute_exchange_rate_raw;
+use crate::deposit::{compute_exchange_rate_raw, query_market_balance, repay_denom_rate};
 use crate::error::ContractError;
-use crate::querier::{query_borrow_limit, query_borrow_rate, query_target_deposit_rate};
+use crate::querier::{
+    query_borrow_limit, query_borrow_rate, query_custody_contracts, query_position,
+    query_staker_tier, query_target_deposit_rate,
+};
 use crate::state::{
-    read_borrower_info, read_borrower_infos, read_config, read_state, store_borrower_info,
-    store_state, BorrowerInfo, Config, State,
+    read_borrow_rate_premium, read_borrower_info, read_borrower_infos, read_config,
+    read_credit_line, read_flash_deleverage, read_interest_free_allowance, read_interest_free_loan,
+    read_last_borrow_height, read_leverage_step, read_loan_principal, read_repay_credit,
+    read_state, read_total_committed, read_total_interest_free, read_total_repay_credit,
+    remove_credit_line, remove_flash_deleverage, remove_interest_free_allowance,
+    remove_leverage_step, store_borrow_rate_premium, store_borrower_info, store_credit_line,
+    store_flash_deleverage, store_interest_free_allowance, store_interest_free_loan,
+    store_last_borrow_height, store_leverage_step, store_loan_principal, store_repay_credit,
+    store_state, store_total_committed, store_total_interest_free, store_total_repay_credit,
+    update_borrow_rate_premiums, BorrowRatePremium, BorrowerInfo, Config, CreditLine,
+    PendingFlashDeleverage, PendingLeverageStep, State,
 };
 
+const SECONDS_PER_YEAR: u64 = 31_536_000;
+
 pub fn borrow_stable(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     borrow_amount: Uint256,
     to: Option<Addr>,
+    sub_account: Option<String>,
 ) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
 
+    let borrower = info.sender;
+    let borrow_fee = apply_borrow(
+        deps.branch(),
+        &env,
+        &config,
+        &borrower,
+        borrow_amount,
+        sub_account,
+    )?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.unwrap_or_else(|| borrower.clone()).to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: (borrow_amount - borrow_fee).into(),
+                },
+            )?],
+        }))
+        .add_attributes(vec![
+            attr("action", "borrow_stable"),
+            attr("borrower", borrower),
+            attr("borrow_amount", borrow_amount),
+            attr("borrow_fee", borrow_fee),
+        ]))
+}
+
+/// Adds the borrow to the position loan after the borrow checks,
+/// the premium of the position is refreshed from the collaterals backing it.
+/// Returns the borrow fee kept as reserves
+fn apply_borrow(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    borrower: &Addr,
+    borrow_amount: Uint256,
+    sub_account: Option<String>,
+) -> Result<Uint256, ContractError> {
     let mut state: State = read_state(deps.storage)?;
 
-    let borrower = info.sender;
     let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
-    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
+    let mut liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, sub_account.as_deref());
 
     // Compute interest
-    compute_interest(deps.as_ref(), &config, &mut state, env.block.height, None)?;
-    compute_borrower_interest(&state, &mut liability);
+    compute_interest_throttled(
+        deps.as_ref(),
+        config,
+        &mut state,
+        interest_clock(config, env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, sub_account.as_deref()),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            config,
+            &mut state,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
 
     // Compute ANC reward
     compute_reward(&mut state, env.block.height);
     compute_borrower_reward(&state, &mut liability);
 
+    let borrow_rate_premium = assert_borrow(
+        deps.as_ref(),
+        env,
+        config,
+        &state,
+        borrower,
+        liability.loan_amount,
+        borrow_amount,
+        sub_account.clone(),
+    )?;
+    store_borrow_rate_premium(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &BorrowRatePremium {
+            rate: borrow_rate_premium,
+            last_accrued: state.last_interest_updated,
+        },
+    )?;
+
+    // The fee stays in the market as reserves
+    let borrow_fee = borrow_amount * config.borrow_fee;
+    add_loan_principal(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        liability.loan_amount,
+        borrow_amount,
+    )?;
+    liability.loan_amount += borrow_amount;
+    state.total_liabilities += Decimal256::from_uint256(borrow_amount);
+    state.total_reserves += Decimal256::from_uint256(borrow_fee);
+    store_last_borrow_height(deps.storage, &borrower_raw, env.block.height)?;
+    update_interest_free_loan(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &liability,
+    )?;
+    store_state(deps.storage, &state)?;
+    store_borrower_info(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &liability,
+    )?;
+
+    Ok(borrow_fee)
+}
+
+/// Checks a borrow must pass, in the order they are enforced
+#[allow(clippy::too_many_arguments)]
+fn assert_borrow(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    state: &State,
+    borrower: &Addr,
+    loan_amount: Uint256,
+    borrow_amount: Uint256,
+    sub_account: Option<String>,
+) -> Result<Decimal256, ContractError> {
+    // Protocol-wide debt ceiling, on top of the per-borrower limits
+    if let Some(max_total_borrow) = config.max_total_borrow {
+        if Decimal256::from_uint256(borrow_amount) + state.total_liabilities
+            > Decimal256::from_uint256(max_total_borrow)
+        {
+            return Err(ContractError::MaxTotalBorrowReached(
+                max_total_borrow.into(),
+            ));
+        }
+    }
+
     let overseer = deps.api.addr_humanize(&config.overseer_contract)?;
     let borrow_limit_res: BorrowLimitResponse = query_borrow_limit(
-        deps.as_ref(),
+        deps,
         overseer,
         borrower.clone(),
         Some(env.block.time.seconds()),
+        sub_account,
     )?;
 
-    if borrow_limit_res.borrow_limit < borrow_amount + liability.loan_amount {
+    if borrow_limit_res.borrow_limit < borrow_amount + loan_amount {
         return Err(ContractError::BorrowExceedsLimit(
             borrow_limit_res.borrow_limit.into(),
         ));
     }
 
     let current_balance = query_balance(
-        deps.as_ref(),
-        env.contract.address,
+        deps,
+        env.contract.address.clone(),
         config.stable_denom.to_string(),
-    )?;
+    )? - read_total_repay_credit(deps.storage);
 
-    // Assert borrow amount
-    assert_max_borrow_factor(&config, &state, current_balance, borrow_amount)?;
+    // Liquidity circuit breaker, repays and redeems are not affected
+    if compute_liquidity_ratio(state, current_balance) < config.min_liquidity_ratio {
+        return Err(ContractError::LiquidityBelowMinimum {});
+    }
 
-    liability.loan_amount += borrow_amount;
-    state.total_liabilities += Decimal256::from_uint256(borrow_amount);
-    store_state(deps.storage, &state)?;
-    store_borrower_info(deps.storage, &borrower_raw, &liability)?;
+    // Assert borrow amount, the undrawn credit line commitments are reserved
+    assert_max_borrow_factor(
+        config,
+        state,
+        current_balance,
+        borrow_amount + read_total_committed(deps.storage),
+    )?;
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Bank(BankMsg::Send {
-            to_address: to.unwrap_or_else(|| borrower.clone()).to_string(),
-            amount: vec![deduct_tax(
-                deps.as_ref(),
-                Coin {
-                    denom: config.stable_denom,
-                    amount: borrow_amount.into(),
-                },
-            )?],
-        }))
-        .add_attributes(vec![
-            attr("action", "borrow_stable"),
-            attr("borrower", borrower),
-            attr("borrow_amount", borrow_amount),
-        ]))
+    Ok(borrow_limit_res.borrow_rate_premium)
 }
 
 pub fn repay_stable_from_liquidation(
@@ -92,6 +253,7 @@ pub fn repay_stable_from_liquidation(
     info: MessageInfo,
     borrower: Addr,
     prev_balance: Uint256,
+    sub_account: Option<String>,
 ) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
     if config.overseer_contract != deps.api.addr_canonicalize(info.sender.as_str())? {
@@ -113,130 +275,1664 @@ pub fn repay_stable_from_liquidation(
         amount: (cur_balance - prev_balance).into(),
     }];
 
-    repay_stable(deps, env, info)
+    execute_repay(
+        deps,
+        env,
+        info,
+        false,
+        sub_account,
+        OverpayAction::Refund,
+        true,
+    )
+}
+
+pub fn repay_stable_on_behalf(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if config.overseer_contract != deps.api.addr_canonicalize(info.sender.as_str())? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // override env, the attached funds are kept
+    let mut info = info;
+    info.sender = borrower;
+
+    execute_repay(
+        deps,
+        env,
+        info,
+        false,
+        sub_account,
+        OverpayAction::Refund,
+        false,
+    )
+}
+
+pub fn repay_stable(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    auto_unlock: bool,
+    sub_account: Option<String>,
+    overpay_action: OverpayAction,
+) -> Result<Response, ContractError> {
+    execute_repay(
+        deps,
+        env,
+        info,
+        auto_unlock,
+        sub_account,
+        overpay_action,
+        false,
+    )
+}
+
+/// Splits a stable denom valued overpayment back into the denoms paid,
+/// the stable denom first, then the accepted repay denoms at the rate
+/// they were paid at
+fn overpay_refund(
+    deps: Deps,
+    config: &Config,
+    paid: &[(Coin, Decimal256)],
+    overpaid: Uint256,
+) -> StdResult<Vec<Coin>> {
+    let mut left = overpaid;
+    let mut ordered: Vec<&(Coin, Decimal256)> = paid
+        .iter()
+        .filter(|(coin, _)| coin.denom == config.stable_denom)
+        .collect();
+    ordered.extend(
+        paid.iter()
+            .filter(|(coin, _)| coin.denom != config.stable_denom),
+    );
+
+    let mut refund: Vec<Coin> = vec![];
+    for (coin, rate) in ordered {
+        if left.is_zero() {
+            break;
+        }
+
+        // A coin priced at zero added nothing to the repayment
+        if rate.is_zero() {
+            continue;
+        }
+
+        let units = std::cmp::min(Uint256::from(coin.amount), left / *rate);
+        if units.is_zero() {
+            continue;
+        }
+
+        let value = units * *rate;
+        left = if value > left {
+            Uint256::zero()
+        } else {
+            left - value
+        };
+
+        let coin = deduct_tax(
+            deps,
+            Coin {
+                denom: coin.denom.clone(),
+                amount: units.into(),
+            },
+        )?;
+        if !coin.amount.is_zero() {
+            refund.push(coin);
+        }
+    }
+
+    Ok(refund)
+}
+
+/// Shared repay accounting, a repayment settled by the overseer on behalf
+/// of a liquidated borrower is exempt from the same block policy and the
+/// minimum repay amount
+fn execute_repay(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    auto_unlock: bool,
+    sub_account: Option<String>,
+    overpay_action: OverpayAction,
+    from_liquidation: bool,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+
+    // Sum the accepted repay denoms, valued in the stable denom
+    if let Some(coin) = info
+        .funds
+        .iter()
+        .find(|c| !config.accepted_repay_denoms.contains(&c.denom))
+    {
+        return Err(ContractError::UnacceptedRepayDenom(coin.denom.clone()));
+    }
+    let mut amount = Uint256::zero();
+    let mut paid: Vec<(Coin, Decimal256)> = vec![];
+    for coin in info.funds.iter() {
+        let rate = repay_denom_rate(deps.as_ref(), &config, &coin.denom)
+            .map_err(|_| ContractError::UnpricedRepayDenom(coin.denom.clone()))?;
+        amount += Uint256::from(coin.amount) * rate;
+        paid.push((coin.clone(), rate));
+    }
+
+    // Cannot deposit zero amount
+    if amount.is_zero() {
+        return Err(ContractError::ZeroRepay(config.stable_denom));
+    }
+
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+
+    let mut same_block_fee = Uint256::zero();
+    if !from_liquidation {
+        same_block_fee = same_block_repay_fee(deps.as_ref(), &config, &env, &borrower_raw, amount)?;
+        amount = amount - same_block_fee;
+        state.total_reserves += Decimal256::from_uint256(same_block_fee);
+    }
+
+    let mut liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    // Compute interest
+    compute_interest_throttled(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        Some(amount),
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, sub_account.as_deref()),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+
+    // Compute ANC reward
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+
+    let prev_loan_amount: Uint256 = liability.loan_amount;
+    let repay_amount: Uint256 = std::cmp::min(liability.loan_amount, amount);
+    let mut credited_amount = Uint256::zero();
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if repay_amount < amount {
+        match overpay_action {
+            OverpayAction::Refund => {
+                // Payback left repay amount to sender, in the denoms paid
+                let refund = overpay_refund(deps.as_ref(), &config, &paid, amount - repay_amount)?;
+                if !refund.is_empty() {
+                    messages.push(CosmosMsg::Bank(BankMsg::Send {
+                        to_address: borrower.to_string(),
+                        amount: refund,
+                    }));
+                }
+            }
+            OverpayAction::Credit => {
+                credited_amount = amount - repay_amount;
+                let credit = read_repay_credit(deps.storage, &borrower_raw);
+                store_repay_credit(deps.storage, &borrower_raw, &(credit + credited_amount))?;
+                let total_credit = read_total_repay_credit(deps.storage);
+                store_total_repay_credit(deps.storage, &(total_credit + credited_amount))?;
+            }
+        }
+    }
+
+    // The overseer repays what the liquidation raised, however small
+    let min_repay_amount = if from_liquidation {
+        Uint256::zero()
+    } else {
+        config.min_repay_amount
+    };
+    let (protocol_fee, rounding_closed) = apply_repayment(
+        deps.storage,
+        &config,
+        &mut state,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &mut liability,
+        repay_amount,
+        amount,
+        min_repay_amount,
+    )?;
+    store_borrower_info(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &liability,
+    )?;
+    store_state(deps.storage, &state)?;
+
+    if auto_unlock && !repay_amount.is_zero() {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps
+                .api
+                .addr_humanize(&config.overseer_contract)?
+                .to_string(),
+            funds: vec![],
+            msg: to_binary(&OverseerExecuteMsg::UnlockCollateralFromRepay {
+                borrower: borrower.to_string(),
+                prev_loan_amount,
+                repay_amount,
+                sub_account,
+            })?,
+        }));
+    }
+
+    let mut attributes = vec![
+        attr("action", "repay_stable"),
+        attr("borrower", borrower),
+        attr("repay_amount", repay_amount),
+    ];
+    if !credited_amount.is_zero() {
+        attributes.push(attr("credited_amount", credited_amount));
+    }
+    if !protocol_fee.is_zero() {
+        attributes.push(attr("protocol_fee", protocol_fee));
+    }
+    if !same_block_fee.is_zero() {
+        attributes.push(attr("same_block_fee", same_block_fee));
+    }
+    if rounding_closed {
+        attributes.push(attr("rounding_closed", "true"));
+    }
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attributes))
+}
+
+/// Lowers the position loan by `repay_amount`, of the `amount` paid, and
+/// returns the protocol fee skimmed off it and whether the rounding dust
+/// left behind was forgiven. A partial repayment must pay `min_repay_amount`
+#[allow(clippy::too_many_arguments)]
+fn apply_repayment(
+    storage: &mut dyn Storage,
+    config: &Config,
+    state: &mut State,
+    borrower_raw: &CanonicalAddr,
+    sub_account: Option<&str>,
+    liability: &mut BorrowerInfo,
+    repay_amount: Uint256,
+    amount: Uint256,
+    min_repay_amount: Uint256,
+) -> Result<(Uint256, bool), ContractError> {
+    let prev_loan_amount = liability.loan_amount;
+    liability.loan_amount = liability.loan_amount - repay_amount;
+    state.total_liabilities = state.total_liabilities - Decimal256::from_uint256(repay_amount);
+
+    // The fee is skimmed off the interest part of the repayment alone,
+    // the repaid amount still lowers the loan in full
+    let interest_repaid = split_repayment(
+        storage,
+        borrower_raw,
+        sub_account,
+        prev_loan_amount,
+        repay_amount,
+    )?;
+    let protocol_fee = interest_repaid * config.repay_protocol_fee;
+    state.total_reserves += Decimal256::from_uint256(protocol_fee);
+
+    // Forgive the rounding dust left by a repayment meant to close the loan
+    let rounding_closed =
+        !liability.loan_amount.is_zero() && liability.loan_amount <= config.repay_dust_epsilon;
+    if rounding_closed {
+        let dust = std::cmp::min(
+            Decimal256::from_uint256(liability.loan_amount),
+            state.total_liabilities,
+        );
+        state.total_liabilities = state.total_liabilities - dust;
+        liability.loan_amount = Uint256::zero();
+    }
+
+    // Only a repayment closing the loan can go below the minimum
+    if !liability.loan_amount.is_zero() && amount < min_repay_amount {
+        return Err(ContractError::RepayBelowMinimum(min_repay_amount.into()));
+    }
+
+    update_interest_free_loan(storage, borrower_raw, sub_account, liability)?;
+    Ok((protocol_fee, rounding_closed))
+}
+
+/// A borrow repaid within its own block can move the indexes at no cost,
+/// returns the fee the policy keeps out of the repaid amount
+fn same_block_repay_fee(
+    deps: Deps,
+    config: &Config,
+    env: &Env,
+    borrower_raw: &CanonicalAddr,
+    amount: Uint256,
+) -> Result<Uint256, ContractError> {
+    if read_last_borrow_height(deps.storage, borrower_raw)? != Some(env.block.height) {
+        return Ok(Uint256::zero());
+    }
+
+    match config.same_block_repay_policy {
+        SameBlockRepayPolicy::Allow => Ok(Uint256::zero()),
+        SameBlockRepayPolicy::Fee { rate } => Ok(amount * rate),
+        SameBlockRepayPolicy::Block => Err(ContractError::SameBlockRepay {}),
+    }
 }
 
-pub fn repay_stable(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+/// Repays with the attached funds and borrows again in one step,
+/// the limit is only checked against the resulting loan
+pub fn refinance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    repay_amount: Uint256,
+    new_borrow_amount: Uint256,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
 
-    // Check stable denom deposit
+    // The attached funds must cover exactly the repaid portion
     let amount: Uint256 = info
         .funds
         .iter()
         .find(|c| c.denom == config.stable_denom)
         .map(|c| Uint256::from(c.amount))
         .unwrap_or_else(Uint256::zero);
+    if amount != repay_amount {
+        return Err(ContractError::InvalidRefinanceFunds(
+            repay_amount.into(),
+            config.stable_denom,
+        ));
+    }
 
-    // Cannot deposit zero amount
-    if amount.is_zero() {
-        return Err(ContractError::ZeroRepay(config.stable_denom));
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    // Compute interest
+    compute_interest_throttled(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        Some(repay_amount),
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, sub_account.as_deref()),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+
+    // Compute ANC reward
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+
+    let prev_loan_amount: Uint256 = liability.loan_amount;
+    if prev_loan_amount < repay_amount {
+        return Err(ContractError::RepayExceedsLoan(prev_loan_amount.into()));
+    }
+
+    split_repayment(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        prev_loan_amount,
+        repay_amount,
+    )?;
+    liability.loan_amount = liability.loan_amount - repay_amount;
+    state.total_liabilities = state.total_liabilities - Decimal256::from_uint256(repay_amount);
+
+    // A pure repayment only lowers the loan
+    if !new_borrow_amount.is_zero() {
+        let borrow_rate_premium = assert_borrow(
+            deps.as_ref(),
+            &env,
+            &config,
+            &state,
+            &borrower,
+            liability.loan_amount,
+            new_borrow_amount,
+            sub_account.clone(),
+        )?;
+        store_borrow_rate_premium(
+            deps.storage,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &BorrowRatePremium {
+                rate: borrow_rate_premium,
+                last_accrued: state.last_interest_updated,
+            },
+        )?;
+    }
+
+    let borrow_fee = new_borrow_amount * config.borrow_fee;
+    add_loan_principal(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        liability.loan_amount,
+        new_borrow_amount,
+    )?;
+    liability.loan_amount += new_borrow_amount;
+    state.total_liabilities += Decimal256::from_uint256(new_borrow_amount);
+    state.total_reserves += Decimal256::from_uint256(borrow_fee);
+    update_interest_free_loan(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &liability,
+    )?;
+    store_state(deps.storage, &state)?;
+    store_borrower_info(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &liability,
+    )?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !new_borrow_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: borrower.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: (new_borrow_amount - borrow_fee).into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "refinance"),
+        attr("borrower", borrower),
+        attr("repay_amount", repay_amount),
+        attr("borrow_amount", new_borrow_amount),
+        attr("borrow_fee", borrow_fee),
+        attr("loan_amount_before", prev_loan_amount),
+        attr("loan_amount_after", liability.loan_amount),
+    ]))
+}
+
+/// Repays the loan with stable coins lent for the rest of the transaction.
+/// The lent amount never leaves the market, so settling it only needs
+/// the balance to have grown by the amount plus the fee
+pub fn flash_deleverage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrow_amount: Uint256,
+    callback: Binary,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if borrow_amount.is_zero() {
+        return Err(ContractError::ZeroRepay(config.stable_denom));
+    }
+
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw, None);
+
+    // The same block fee is owed on top of the flash fee
+    let same_block_fee =
+        same_block_repay_fee(deps.as_ref(), &config, &env, &borrower_raw, borrow_amount)?;
+
+    // Compute interest
+    compute_interest_throttled(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, None),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            None,
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+
+    // Compute ANC reward
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+
+    let prev_loan_amount: Uint256 = liability.loan_amount;
+    if prev_loan_amount < borrow_amount {
+        return Err(ContractError::RepayExceedsLoan(prev_loan_amount.into()));
+    }
+
+    let (protocol_fee, rounding_closed) = apply_repayment(
+        deps.storage,
+        &config,
+        &mut state,
+        &borrower_raw,
+        None,
+        &mut liability,
+        borrow_amount,
+        borrow_amount,
+        config.min_repay_amount,
+    )?;
+    store_borrower_info(deps.storage, &borrower_raw, None, &liability)?;
+    store_state(deps.storage, &state)?;
+
+    let fee = borrow_amount * config.flash_fee + same_block_fee;
+    let prev_balance: Uint256 = query_balance(
+        deps.as_ref(),
+        env.contract.address.clone(),
+        config.stable_denom,
+    )?;
+    store_flash_deleverage(
+        deps.storage,
+        &PendingFlashDeleverage {
+            borrower: borrower_raw,
+            prev_balance,
+            borrow_amount,
+            fee,
+        },
+    )?;
+
+    let mut attributes = vec![
+        attr("action", "flash_deleverage"),
+        attr("borrower", borrower.clone()),
+        attr("borrow_amount", borrow_amount),
+        attr("flash_fee", fee),
+        attr("loan_amount_before", prev_loan_amount),
+        attr("loan_amount_after", liability.loan_amount),
+    ];
+    if !protocol_fee.is_zero() {
+        attributes.push(attr("protocol_fee", protocol_fee));
+    }
+    if rounding_closed {
+        attributes.push(attr("rounding_closed", "true"));
+    }
+
+    Ok(Response::new()
+        .add_messages(vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: borrower.to_string(),
+                funds: vec![],
+                msg: callback,
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::SettleFlashDeleverage {})?,
+            }),
+        ])
+        .add_attributes(attributes))
+}
+
+/// Fails the whole transaction when the callback did not pay back
+/// the flash amount and its fee
+pub fn settle_flash_deleverage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let pending = match read_flash_deleverage(deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(ContractError::Unauthorized {}),
+    };
+
+    let config: Config = read_config(deps.storage)?;
+    let cur_balance: Uint256 = query_balance(
+        deps.as_ref(),
+        env.contract.address,
+        config.stable_denom.to_string(),
+    )?;
+    let required_balance = pending.prev_balance + pending.borrow_amount + pending.fee;
+    if cur_balance < required_balance {
+        return Err(ContractError::FlashDeleverageShortfall(
+            (required_balance - cur_balance).into(),
+        ));
+    }
+
+    let mut state: State = read_state(deps.storage)?;
+    state.total_reserves += Decimal256::from_uint256(pending.fee);
+    store_state(deps.storage, &state)?;
+    remove_flash_deleverage(deps.storage);
+
+    let borrower = deps.api.addr_humanize(&pending.borrower)?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let excess = cur_balance - required_balance;
+    if !excess.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: borrower.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: excess.into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "settle_flash_deleverage"),
+        attr("borrower", borrower),
+        attr("flash_fee", pending.fee),
+        attr("refund_amount", excess),
+    ]))
+}
+
+pub fn set_leverage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral_token: Addr,
+    target_leverage: Decimal256,
+    max_iterations: u32,
+    slippage: Decimal256,
+) -> Result<Response, ContractError> {
+    if target_leverage <= Decimal256::one() {
+        return Err(ContractError::InvalidTargetLeverage {});
+    }
+
+    if slippage >= Decimal256::one() {
+        return Err(ContractError::InvalidSlippage {});
+    }
+
+    leverage_step(
+        deps,
+        env,
+        info.sender,
+        collateral_token,
+        target_leverage,
+        max_iterations,
+        slippage,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn continue_leverage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    collateral_token: Addr,
+    target_leverage: Decimal256,
+    iterations_left: u32,
+    slippage: Decimal256,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    leverage_step(
+        deps,
+        env,
+        borrower,
+        collateral_token,
+        target_leverage,
+        iterations_left,
+        slippage,
+    )
+}
+
+/// Measures the position and either finishes the adjustment with the
+/// achieved leverage or borrows toward the target, converts the loan into
+/// the collateral, locks it and continues with the next step
+fn leverage_step(
+    mut deps: DepsMut,
+    env: Env,
+    borrower: Addr,
+    collateral_token: Addr,
+    target_leverage: Decimal256,
+    iterations_left: u32,
+    slippage: Decimal256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let overseer_contract = deps.api.addr_humanize(&config.overseer_contract)?;
+    let position = query_position(deps.as_ref(), overseer_contract.clone(), borrower.clone())?;
+    let collateral_price = position
+        .collaterals
+        .iter()
+        .find(|c| c.collateral_token == collateral_token.as_str())
+        .map(|c| c.price)
+        .ok_or_else(|| ContractError::LeverageCollateralNotLocked(collateral_token.to_string()))?;
+
+    let loan_amount =
+        query_borrower_info(deps.as_ref(), env.clone(), borrower.clone(), None, None)?.loan_amount;
+    if position.collateral_value <= loan_amount {
+        return Err(ContractError::LeverageUnderwater {});
+    }
+
+    // leverage = collateral_value / (collateral_value - loan_amount)
+    let equity = position.collateral_value - loan_amount;
+    let leverage = Decimal256::from_ratio(position.collateral_value, equity);
+
+    // Borrowing b adds b of collateral value, reaching the target at
+    // b = target_leverage * equity - collateral_value
+    let target_value = equity * target_leverage;
+    let borrow_amount = if iterations_left == 0
+        || leverage >= target_leverage
+        || target_value <= position.collateral_value
+        || position.borrow_limit <= loan_amount
+    {
+        Uint256::zero()
+    } else {
+        std::cmp::min(
+            target_value - position.collateral_value,
+            position.borrow_limit - loan_amount,
+        )
+    };
+
+    if borrow_amount.is_zero() {
+        return Ok(Response::new().add_attributes(vec![
+            attr("action", "set_leverage"),
+            attr("borrower", borrower),
+            attr("leverage", leverage.to_string()),
+        ]));
+    }
+
+    let reserve_route = match &config.reserve_route {
+        Some(reserve_route) => deps.api.addr_humanize(reserve_route)?,
+        None => return Err(ContractError::NoReserveRoute {}),
+    };
+
+    let borrow_fee = apply_borrow(deps.branch(), &env, &config, &borrower, borrow_amount, None)?;
+
+    let offer_coin = deduct_tax(
+        deps.as_ref(),
+        Coin {
+            denom: config.stable_denom.clone(),
+            amount: (borrow_amount - borrow_fee).into(),
+        },
+    )?;
+
+    // At the oracle price the collateral must be worth the borrow net of the slippage
+    let min_receive =
+        Uint256::from(offer_coin.amount) * (Decimal256::one() - slippage) / collateral_price;
+
+    // The route sends the collateral to the market, its reply
+    // locks what was actually delivered
+    let prev_balance = query_token_balance(
+        deps.as_ref(),
+        collateral_token.clone(),
+        env.contract.address.clone(),
+    )?;
+    store_leverage_step(
+        deps.storage,
+        &PendingLeverageStep {
+            borrower: deps.api.addr_canonicalize(borrower.as_str())?,
+            collateral_token: deps.api.addr_canonicalize(collateral_token.as_str())?,
+            prev_balance,
+            min_receive,
+            target_leverage,
+            iterations_left,
+            slippage,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: reserve_route.to_string(),
+                funds: vec![offer_coin],
+                msg: to_binary(&ConverterExecuteMsg::ConvertNative {
+                    ask_token: collateral_token.to_string(),
+                    recipient: env.contract.address.to_string(),
+                    msg: None,
+                    min_receive: Some(min_receive),
+                })?,
+            }),
+            LEVERAGE_STEP_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr("action", "set_leverage_step"),
+            attr("borrower", borrower),
+            attr("leverage", leverage.to_string()),
+            attr("borrow_amount", borrow_amount),
+            attr("borrow_fee", borrow_fee),
+            attr("min_receive", min_receive),
+        ]))
+}
+
+/// Deposit the collateral the reserve route delivered for the borrower
+/// across the custody contracts, lock it and continue with the next step
+pub fn leverage_step_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = match read_leverage_step(deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(ContractError::InvalidReplyId {}),
+    };
+    remove_leverage_step(deps.storage);
+
+    let config: Config = read_config(deps.storage)?;
+    let borrower = deps.api.addr_humanize(&pending.borrower)?;
+    let collateral_token = deps.api.addr_humanize(&pending.collateral_token)?;
+    let acquired_amount = query_token_balance(
+        deps.as_ref(),
+        collateral_token.clone(),
+        env.contract.address.clone(),
+    )? - pending.prev_balance;
+    if acquired_amount < pending.min_receive {
+        return Err(ContractError::LeverageSlippageExceeded(
+            acquired_amount.into(),
+        ));
+    }
+
+    let overseer_contract = deps.api.addr_humanize(&config.overseer_contract)?;
+    let custody_contracts = query_custody_contracts(
+        deps.as_ref(),
+        overseer_contract.clone(),
+        collateral_token.clone(),
+    )?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (custody_contract, custody_amount) in
+        split_custody_amount(&custody_contracts, acquired_amount)
+    {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: collateral_token.to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: custody_contract.to_string(),
+                amount: custody_amount.into(),
+                msg: to_binary(&CustodyCw20HookMsg::DepositCollateralFor {
+                    borrower: borrower.to_string(),
+                })?,
+            })?,
+        }));
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: overseer_contract.to_string(),
+        funds: vec![],
+        msg: to_binary(&OverseerExecuteMsg::LockCollateralFor {
+            borrower: borrower.to_string(),
+            collaterals: vec![(collateral_token.to_string(), acquired_amount)],
+        })?,
+    }));
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::ContinueLeverage {
+            borrower: borrower.to_string(),
+            collateral_token: collateral_token.to_string(),
+            target_leverage: pending.target_leverage,
+            iterations_left: pending.iterations_left - 1,
+            slippage: pending.slippage,
+        })?,
+    }));
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "lock_leverage_step"),
+        attr("borrower", borrower),
+        attr("acquired_amount", acquired_amount),
+    ]))
+}
+
+/// Grants the allowance when `amount` is given, revokes it otherwise
+pub fn update_interest_free_allowance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    collateral_token: Addr,
+    amount: Option<Uint256>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw, None);
+
+    // Settle the interest accrued under the previous allowance
+    compute_interest(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_interest_free_loan(deps.storage, &borrower_raw),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            None,
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+
+    let action = if let Some(amount) = amount {
+        store_interest_free_allowance(deps.storage, &borrower_raw, &collateral_token_raw, &amount)?;
+        "grant_interest_free_allowance"
+    } else {
+        remove_interest_free_allowance(deps.storage, &borrower_raw, &collateral_token_raw);
+        "revoke_interest_free_allowance"
+    };
+
+    update_interest_free_loan(deps.storage, &borrower_raw, None, &liability)?;
+    store_borrower_info(deps.storage, &borrower_raw, None, &liability)?;
+    store_state(deps.storage, &state)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", action),
+        attr("borrower", borrower),
+        attr("collateral_token", collateral_token),
+        attr("amount", amount.unwrap_or_else(Uint256::zero)),
+    ]))
+}
+
+/// Rewards accrued so far are kept by the borrower,
+/// the recipient accrues them on the whole loan from now on
+pub fn transfer_loan(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if config.overseer_contract != deps.api.addr_canonicalize(info.sender.as_str())? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let recipient_raw = deps.api.addr_canonicalize(recipient.as_str())?;
+    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw, None);
+    let mut recipient_liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &recipient_raw, None);
+
+    // Settle both loans before the loan amount moves
+    compute_interest(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_interest_free_loan(deps.storage, &borrower_raw),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            None,
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    let recipient_interest = compute_borrower_interest(
+        &state,
+        &mut recipient_liability,
+        read_interest_free_loan(deps.storage, &recipient_raw),
+    );
+    let recipient_interest = recipient_interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &recipient_raw,
+            None,
+            &mut recipient_liability,
+        )?;
+    let recipient_interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &recipient_raw,
+        &mut recipient_liability,
+        recipient_interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &recipient_raw,
+        &mut recipient_liability,
+        recipient_interest,
+    )?;
+
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+    compute_borrower_reward(&state, &mut recipient_liability);
+
+    let loan_amount = liability.loan_amount;
+    let principal = loan_principal(deps.storage, &borrower_raw, None, loan_amount)?;
+    add_loan_principal(
+        deps.storage,
+        &recipient_raw,
+        None,
+        recipient_liability.loan_amount,
+        principal,
+    )?;
+    store_loan_principal(deps.storage, &borrower_raw, None, &Uint256::zero())?;
+    recipient_liability.loan_amount += loan_amount;
+    liability.loan_amount = Uint256::zero();
+
+    update_interest_free_loan(deps.storage, &borrower_raw, None, &liability)?;
+    update_interest_free_loan(deps.storage, &recipient_raw, None, &recipient_liability)?;
+    store_borrower_info(deps.storage, &borrower_raw, None, &liability)?;
+    store_borrower_info(deps.storage, &recipient_raw, None, &recipient_liability)?;
+    store_state(deps.storage, &state)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_loan"),
+        attr("borrower", borrower),
+        attr("recipient", recipient),
+        attr("loan_amount", loan_amount),
+    ]))
+}
+
+/// Move `fraction` of the loan and of its principal between two
+/// positions of the borrower, the overseer checked both limits
+#[allow(clippy::too_many_arguments)]
+pub fn split_loan(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    from_sub_account: Option<String>,
+    to_sub_account_name: String,
+    fraction: Decimal256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if config.overseer_contract != deps.api.addr_canonicalize(info.sender.as_str())? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let from_sub_account = from_sub_account.as_deref();
+    let to_sub_account = Some(to_sub_account_name.as_str());
+    let mut liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, from_sub_account);
+    let mut to_liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, to_sub_account);
+
+    // Settle both loans before the loan amount moves
+    compute_interest(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    for (sub_account, liability) in [
+        (from_sub_account, &mut liability),
+        (to_sub_account, &mut to_liability),
+    ] {
+        let interest = compute_borrower_interest(
+            &state,
+            liability,
+            read_position_interest_free(deps.storage, &borrower_raw, sub_account),
+        );
+        let interest = interest
+            + accrue_borrow_rate_premium(
+                deps.storage,
+                &config,
+                &mut state,
+                &borrower_raw,
+                sub_account,
+                liability,
+            )?;
+        let interest = apply_rate_discount(
+            deps.as_ref(),
+            &config,
+            &mut state,
+            &borrower_raw,
+            liability,
+            interest,
+        )?;
+        settle_repay_credit(deps.storage, &mut state, &borrower_raw, liability, interest)?;
+    }
+
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+    compute_borrower_reward(&state, &mut to_liability);
+
+    let loan_amount = liability.loan_amount * fraction;
+    let principal = loan_principal(
+        deps.storage,
+        &borrower_raw,
+        from_sub_account,
+        liability.loan_amount,
+    )?;
+    let split_principal = principal * fraction;
+    add_loan_principal(
+        deps.storage,
+        &borrower_raw,
+        to_sub_account,
+        to_liability.loan_amount,
+        split_principal,
+    )?;
+    store_loan_principal(
+        deps.storage,
+        &borrower_raw,
+        from_sub_account,
+        &(principal - split_principal),
+    )?;
+    to_liability.loan_amount += loan_amount;
+    liability.loan_amount = liability.loan_amount - loan_amount;
+
+    update_interest_free_loan(deps.storage, &borrower_raw, from_sub_account, &liability)?;
+    store_borrower_info(deps.storage, &borrower_raw, from_sub_account, &liability)?;
+    store_borrower_info(deps.storage, &borrower_raw, to_sub_account, &to_liability)?;
+    store_state(deps.storage, &state)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "split_loan"),
+        attr("borrower", borrower),
+        attr("from_sub_account", from_sub_account.unwrap_or_default()),
+        attr("to_sub_account", to_sub_account_name.as_str()),
+        attr("loan_amount", loan_amount),
+    ]))
+}
+
+pub fn claim_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    to: Option<Addr>,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    // Compute interest
+    compute_interest_throttled(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, sub_account.as_deref()),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+
+    // Compute ANC reward
+    compute_reward(&mut state, env.block.height);
+    compute_borrower_reward(&state, &mut liability);
+
+    let claim_amount = liability.pending_rewards * Uint256::one();
+    liability.pending_rewards = liability.pending_rewards - Decimal256::from_uint256(claim_amount);
+
+    store_state(deps.storage, &state)?;
+    store_borrower_info(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &liability,
+    )?;
+
+    let messages: Vec<CosmosMsg> = if !claim_amount.is_zero() {
+        vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps
+                .api
+                .addr_humanize(&config.distributor_contract)?
+                .to_string(),
+            funds: vec![],
+            msg: to_binary(&FaucetExecuteMsg::Spend {
+                recipient: if let Some(to) = to {
+                    to.to_string()
+                } else {
+                    borrower.to_string()
+                },
+                amount: claim_amount.into(),
+            })?,
+        })]
+    } else {
+        vec![]
+    };
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "claim_rewards"),
+        attr("claim_amount", claim_amount),
+    ]))
+}
+
+/// Accrues the commitment fee on the undrawn commitment up to the block height
+fn accrue_commitment_fee(config: &Config, credit_line: &mut CreditLine, block_height: u64) {
+    let blocks = block_height - credit_line.last_accrued_height;
+    credit_line.accrued_fee += credit_line.committed
+        * (config.commitment_fee * Decimal256::from_ratio(blocks, config.blocks_per_year));
+    credit_line.last_accrued_height = block_height;
+}
+
+pub fn open_credit_line(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    // A free commitment would reserve the capacity of other borrowers at no
+    // cost, an open credit line can still be closed once the fee is unset
+    if !amount.is_zero() && config.commitment_fee.is_zero() {
+        return Err(ContractError::CreditLinesDisabled {});
+    }
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut credit_line = read_credit_line(deps.storage, &borrower_raw)?.unwrap_or(CreditLine {
+        committed: Uint256::zero(),
+        accrued_fee: Uint256::zero(),
+        last_accrued_height: env.block.height,
+    });
+    accrue_commitment_fee(&config, &mut credit_line, env.block.height);
+
+    // The fee accrued on the previous commitment is paid with the attached funds
+    let paid_amount: Uint256 = info
+        .funds
+        .iter()
+        .find(|c| c.denom == config.stable_denom)
+        .map(|c| Uint256::from(c.amount))
+        .unwrap_or_else(Uint256::zero);
+    if paid_amount < credit_line.accrued_fee {
+        return Err(ContractError::CommitmentFeeUnpaid(
+            credit_line.accrued_fee.into(),
+        ));
     }
 
-    let mut state: State = read_state(deps.storage)?;
-
-    let borrower = info.sender;
-    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
-    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
+    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw, None);
 
     // Compute interest
-    compute_interest(
+    compute_interest_throttled(
         deps.as_ref(),
         &config,
         &mut state,
-        env.block.height,
-        Some(amount),
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, None),
+    );
+    let interest = interest
+        + accrue_borrow_rate_premium(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            None,
+            &mut liability,
+        )?;
+    let interest = apply_rate_discount(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    settle_repay_credit(
+        deps.storage,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
     )?;
-    compute_borrower_interest(&state, &mut liability);
 
     // Compute ANC reward
     compute_reward(&mut state, env.block.height);
     compute_borrower_reward(&state, &mut liability);
 
-    let repay_amount: Uint256;
-    let mut messages: Vec<CosmosMsg> = vec![];
-    if liability.loan_amount < amount {
-        repay_amount = liability.loan_amount;
-        liability.loan_amount = Uint256::zero();
+    let commitment_fee = credit_line.accrued_fee;
+    state.total_reserves += Decimal256::from_uint256(commitment_fee);
+
+    // The own commitment is replaced, only the others stay reserved
+    let other_committed = read_total_committed(deps.storage) - credit_line.committed;
+    store_total_committed(deps.storage, &other_committed)?;
+    if !amount.is_zero() {
+        assert_borrow(
+            deps.as_ref(),
+            &env,
+            &config,
+            &state,
+            &borrower,
+            liability.loan_amount,
+            amount,
+            None,
+        )?;
+    }
+
+    store_total_committed(deps.storage, &(other_committed + amount))?;
+    if amount.is_zero() {
+        remove_credit_line(deps.storage, &borrower_raw);
+    } else {
+        store_credit_line(
+            deps.storage,
+            &borrower_raw,
+            &CreditLine {
+                committed: amount,
+                accrued_fee: Uint256::zero(),
+                last_accrued_height: env.block.height,
+            },
+        )?;
+    }
+    store_state(deps.storage, &state)?;
+    store_borrower_info(deps.storage, &borrower_raw, None, &liability)?;
 
-        // Payback left repay amount to sender
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if paid_amount > commitment_fee {
         messages.push(CosmosMsg::Bank(BankMsg::Send {
             to_address: borrower.to_string(),
             amount: vec![deduct_tax(
                 deps.as_ref(),
                 Coin {
                     denom: config.stable_denom,
-                    amount: (amount - repay_amount).into(),
+                    amount: (paid_amount - commitment_fee).into(),
                 },
             )?],
         }));
-    } else {
-        repay_amount = amount;
-        liability.loan_amount = liability.loan_amount - repay_amount;
     }
 
-    state.total_liabilities = state.total_liabilities - Decimal256::from_uint256(repay_amount);
-
-    store_borrower_info(deps.storage, &borrower_raw, &liability)?;
-    store_state(deps.storage, &state)?;
-
     Ok(Response::new().add_messages(messages).add_attributes(vec![
-        attr("action", "repay_stable"),
+        attr("action", "open_credit_line"),
         attr("borrower", borrower),
-        attr("repay_amount", repay_amount),
+        attr("committed", amount),
+        attr("commitment_fee", commitment_fee),
     ]))
 }
 
-pub fn claim_rewards(
-    deps: DepsMut,
+pub fn draw_credit(
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    amount: Uint256,
     to: Option<Addr>,
 ) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
-    let mut state: State = read_state(deps.storage)?;
 
     let borrower = info.sender;
     let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
-    let mut liability: BorrowerInfo = read_borrower_info(deps.storage, &borrower_raw);
-
-    // Compute interest
-    compute_interest(deps.as_ref(), &config, &mut state, env.block.height, None)?;
-    compute_borrower_interest(&state, &mut liability);
-
-    // Compute ANC reward
-    compute_reward(&mut state, env.block.height);
-    compute_borrower_reward(&state, &mut liability);
+    let mut credit_line: CreditLine =
+        read_credit_line(deps.storage, &borrower_raw)?.ok_or(ContractError::NoCreditLine {})?;
+    accrue_commitment_fee(&config, &mut credit_line, env.block.height);
+    if amount > credit_line.committed {
+        return Err(ContractError::DrawExceedsCreditLine(
+            credit_line.committed.into(),
+        ));
+    }
 
-    let claim_amount = liability.pending_rewards * Uint256::one();
-    liability.pending_rewards = liability.pending_rewards - Decimal256::from_uint256(claim_amount);
+    // The drawn amount is no longer reserved when the borrow is checked
+    let total_committed = read_total_committed(deps.storage);
+    store_total_committed(deps.storage, &(total_committed - amount))?;
+    let borrow_fee = apply_borrow(deps.branch(), &env, &config, &borrower, amount, None)?;
 
+    // The accrued commitment fee is kept from the drawn funds as reserves
+    let commitment_fee = credit_line.accrued_fee;
+    if borrow_fee + commitment_fee > amount {
+        return Err(ContractError::CreditDrawBelowFee(commitment_fee.into()));
+    }
+    let mut state: State = read_state(deps.storage)?;
+    state.total_reserves += Decimal256::from_uint256(commitment_fee);
     store_state(deps.storage, &state)?;
-    store_borrower_info(deps.storage, &borrower_raw, &liability)?;
 
-    let messages: Vec<CosmosMsg> = if !claim_amount.is_zero() {
-        vec![CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: deps
-                .api
-                .addr_humanize(&config.distributor_contract)?
-                .to_string(),
-            funds: vec![],
-            msg: to_binary(&FaucetExecuteMsg::Spend {
-                recipient: if let Some(to) = to {
-                    to.to_string()
-                } else {
-                    borrower.to_string()
+    credit_line.committed = credit_line.committed - amount;
+    credit_line.accrued_fee = Uint256::zero();
+    if credit_line.committed.is_zero() {
+        remove_credit_line(deps.storage, &borrower_raw);
+    } else {
+        store_credit_line(deps.storage, &borrower_raw, &credit_line)?;
+    }
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.unwrap_or_else(|| borrower.clone()).to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: (amount - borrow_fee - commitment_fee).into(),
                 },
-                amount: claim_amount.into(),
-            })?,
-        })]
+            )?],
+        }))
+        .add_attributes(vec![
+            attr("action", "draw_credit"),
+            attr("borrower", borrower),
+            attr("borrow_amount", amount),
+            attr("borrow_fee", borrow_fee),
+            attr("commitment_fee", commitment_fee),
+        ]))
+}
+
+/// Point in time interest accrues up to: the block time in seconds
+/// when `accrue_by_time` is set, the given block height otherwise.
+/// The block time of another height than the current one is projected
+/// at the average block time of `blocks_per_year`
+pub fn interest_clock(config: &Config, env: &Env, block_height: u64) -> u64 {
+    if config.accrue_by_time {
+        let seconds = |blocks: u64| {
+            (blocks as u128 * SECONDS_PER_YEAR as u128 / config.blocks_per_year as u128) as u64
+        };
+
+        if block_height >= env.block.height {
+            env.block.time.seconds() + seconds(block_height - env.block.height)
+        } else {
+            env.block
+                .time
+                .seconds()
+                .saturating_sub(seconds(env.block.height - block_height))
+        }
     } else {
-        vec![]
+        block_height
+    }
+}
+
+/// Rebase the premiums accrued on the old interest clock onto the new one
+/// when `accrue_by_time` switches, keeping the premium interest pending
+/// since their last accrual
+pub fn rebase_borrow_rate_premiums(
+    storage: &mut dyn Storage,
+    config: &Config,
+    prev_clock: u64,
+    clock: u64,
+) -> StdResult<()> {
+    let blocks_per_year = config.blocks_per_year as u128;
+    let seconds_per_year = SECONDS_PER_YEAR as u128;
+    let accrue_by_time = config.accrue_by_time;
+    update_borrow_rate_premiums(storage, |premium| {
+        let elapsed = prev_clock.saturating_sub(premium.last_accrued) as u128;
+        let elapsed = if accrue_by_time {
+            elapsed * seconds_per_year / blocks_per_year
+        } else {
+            elapsed * blocks_per_year / seconds_per_year
+        };
+
+        premium.last_accrued = clock.saturating_sub(elapsed as u64);
+    })
+}
+
+/// Converts a per-block rate into a rate per unit of the interest clock
+pub fn rate_per_clock_unit(config: &Config, rate: Decimal256) -> Decimal256 {
+    if config.accrue_by_time {
+        rate * Decimal256::from_uint256(config.blocks_per_year)
+            / Decimal256::from_uint256(SECONDS_PER_YEAR)
+    } else {
+        rate
+    }
+}
+
+/// Compute interest like `compute_interest` for loan operations,
+/// reusing the stored index until `accrual_block_threshold` blocks
+/// passed since the last accrual. Deposits, redemptions, queries and
+/// owner operations price against the index and keep accruing fresh
+pub fn compute_interest_throttled(
+    deps: Deps,
+    config: &Config,
+    state: &mut State,
+    block_height: u64,
+    deposit_amount: Option<Uint256>,
+) -> StdResult<()> {
+    // The threshold is set in blocks, the interest clock may count seconds
+    let threshold = if config.accrue_by_time {
+        (config.accrual_block_threshold as u128 * SECONDS_PER_YEAR as u128
+            / config.blocks_per_year as u128) as u64
+    } else {
+        config.accrual_block_threshold
     };
+    if block_height < state.last_interest_updated.saturating_add(threshold) {
+        return Ok(());
+    }
 
-    Ok(Response::new().add_messages(messages).add_attributes(vec![
-        attr("action", "claim_rewards"),
-        attr("claim_amount", claim_amount),
-    ]))
+    compute_interest(deps, config, state, block_height, deposit_amount)
 }
 
 /// Compute interest and update state
@@ -253,11 +1949,9 @@ pub fn compute_interest(
     }
 
     let aterra_supply = query_supply(deps, deps.api.addr_humanize(&config.aterra_contract)?)?;
-    let balance: Uint256 = query_balance(
-        deps,
-        deps.api.addr_humanize(&config.contract_addr)?,
-        config.stable_denom.to_string(),
-    )? - deposit_amount.unwrap_or_else(Uint256::zero);
+    let balance: Uint256 = query_market_balance(deps, config)?
+        - deposit_amount.unwrap_or_else(Uint256::zero)
+        - read_total_repay_credit(deps.storage);
 
     let borrow_rate_res: BorrowRateResponse = query_borrow_rate(
         deps,
@@ -275,8 +1969,9 @@ pub fn compute_interest(
         block_height,
         balance,
         aterra_supply,
-        borrow_rate_res.rate,
-        target_deposit_rate,
+        rate_per_clock_unit(config, borrow_rate_res.rate),
+        rate_per_clock_unit(config, target_deposit_rate),
+        read_total_interest_free(deps.storage),
     );
 
     Ok(())
@@ -294,6 +1989,7 @@ pub fn compute_interest_raw(
     aterra_supply: Uint256,
     borrow_rate: Decimal256,
     target_deposit_rate: Decimal256,
+    interest_free_liabilities: Decimal256,
 ) {
     if state.last_interest_updated >= block_height {
         return;
@@ -302,7 +1998,12 @@ pub fn compute_interest_raw(
     let passed_blocks = Decimal256::from_uint256(block_height - state.last_interest_updated);
 
     let interest_factor = passed_blocks * borrow_rate;
-    let interest_accrued = state.total_liabilities * interest_factor;
+    let interest_bearing = if state.total_liabilities > interest_free_liabilities {
+        state.total_liabilities - interest_free_liabilities
+    } else {
+        Decimal256::zero()
+    };
+    let interest_accrued = interest_bearing * interest_factor;
 
     state.global_interest_index =
         state.global_interest_index * (Decimal256::one() + interest_factor);
@@ -330,11 +2031,280 @@ pub fn compute_interest_raw(
     state.last_interest_updated = block_height;
 }
 
-/// Compute new interest and apply to liability
-pub(crate) fn compute_borrower_interest(state: &State, liability: &mut BorrowerInfo) {
-    liability.loan_amount =
-        liability.loan_amount * state.global_interest_index / liability.interest_index;
+/// Compute new interest and apply to liability,
+/// the interest-free portion of the loan does not grow.
+/// Returns the interest accrued on the loan
+pub(crate) fn compute_borrower_interest(
+    state: &State,
+    liability: &mut BorrowerInfo,
+    interest_free_amount: Uint256,
+) -> Uint256 {
+    let prev_loan_amount = liability.loan_amount;
+    let interest_free_amount = std::cmp::min(interest_free_amount, liability.loan_amount);
+    liability.loan_amount = interest_free_amount
+        + (liability.loan_amount - interest_free_amount) * state.global_interest_index
+            / liability.interest_index;
     liability.interest_index = state.global_interest_index;
+
+    if liability.loan_amount > prev_loan_amount {
+        liability.loan_amount - prev_loan_amount
+    } else {
+        Uint256::zero()
+    }
+}
+
+/// Apply the borrow rate premium of the position accrued on its interest-bearing
+/// loan since the last accrual of the position, up to the interest clock of the state.
+/// Returns the premium interest with the updated premium of the position
+fn compute_premium_interest(
+    storage: &dyn Storage,
+    config: &Config,
+    state: &mut State,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    liability: &mut BorrowerInfo,
+) -> StdResult<(Uint256, Option<BorrowRatePremium>)> {
+    let mut premium = match read_borrow_rate_premium(storage, borrower, sub_account)? {
+        Some(premium) => premium,
+        None => return Ok((Uint256::zero(), None)),
+    };
+
+    let elapsed = state
+        .last_interest_updated
+        .saturating_sub(premium.last_accrued);
+    let interest_free_amount = std::cmp::min(
+        read_position_interest_free(storage, borrower, sub_account),
+        liability.loan_amount,
+    );
+    let interest = (liability.loan_amount - interest_free_amount)
+        * (rate_per_clock_unit(config, premium.rate) * Decimal256::from_uint256(elapsed));
+    liability.loan_amount += interest;
+    state.total_liabilities += Decimal256::from_uint256(interest);
+    premium.last_accrued = std::cmp::max(premium.last_accrued, state.last_interest_updated);
+
+    Ok((interest, Some(premium)))
+}
+
+/// Accrue the borrow rate premium of the position into its loan and the
+/// total liabilities, returns the premium interest
+fn accrue_borrow_rate_premium(
+    storage: &mut dyn Storage,
+    config: &Config,
+    state: &mut State,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    liability: &mut BorrowerInfo,
+) -> StdResult<Uint256> {
+    let (interest, premium) =
+        compute_premium_interest(storage, config, state, borrower, sub_account, liability)?;
+    if let Some(premium) = premium {
+        store_borrow_rate_premium(storage, borrower, sub_account, &premium)?;
+    }
+
+    Ok(interest)
+}
+
+/// Rebate the rate discount of the staker tier of the borrower on the accrued interest,
+/// the rebate comes out of the reserves so the exchange rate stays unchanged.
+/// Returns the interest left after the rebate
+fn apply_rate_discount(
+    deps: Deps,
+    config: &Config,
+    state: &mut State,
+    borrower: &CanonicalAddr,
+    liability: &mut BorrowerInfo,
+    interest: Uint256,
+) -> StdResult<Uint256> {
+    let rate_discount_source = match &config.rate_discount_source {
+        Some(rate_discount_source) if !interest.is_zero() => rate_discount_source,
+        _ => return Ok(interest),
+    };
+
+    let tier = query_staker_tier(
+        deps,
+        deps.api.addr_humanize(rate_discount_source)?,
+        deps.api.addr_humanize(borrower)?,
+    )?;
+    let discount = config
+        .rate_discount_tiers
+        .iter()
+        .find(|t| t.0 == tier)
+        .map(|t| t.1)
+        .unwrap_or_else(Decimal256::zero);
+
+    let rebate = std::cmp::min(interest * discount, state.total_reserves * Uint256::one());
+    if rebate.is_zero() {
+        return Ok(interest);
+    }
+
+    let rebate_dec = Decimal256::from_uint256(rebate);
+    liability.loan_amount = liability.loan_amount - rebate;
+    state.total_liabilities =
+        state.total_liabilities - std::cmp::min(rebate_dec, state.total_liabilities);
+    state.total_reserves = state.total_reserves - rebate_dec;
+
+    Ok(interest - rebate)
+}
+
+/// Send the repay credit of the borrower back,
+/// a credit left after the loan is repaid is not spent otherwise
+pub fn withdraw_repay_credit(
+    deps: DepsMut,
+    info: MessageInfo,
+    amount: Option<Uint256>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let credit = read_repay_credit(deps.storage, &borrower_raw);
+    let amount = amount.unwrap_or(credit);
+    if amount.is_zero() || amount > credit {
+        return Err(ContractError::InvalidRepayCreditWithdraw(credit.into()));
+    }
+
+    store_repay_credit(deps.storage, &borrower_raw, &(credit - amount))?;
+    let total_credit = read_total_repay_credit(deps.storage);
+    store_total_repay_credit(deps.storage, &(total_credit - amount))?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: amount.into(),
+                },
+            )?],
+        }))
+        .add_attributes(vec![
+            attr("action", "withdraw_repay_credit"),
+            attr("borrower", info.sender),
+            attr("amount", amount),
+        ]))
+}
+
+/// Pay the accrued interest out of the repay credit first,
+/// returns the spent credit
+pub(crate) fn apply_repay_credit(
+    liability: &mut BorrowerInfo,
+    interest: Uint256,
+    credit: Uint256,
+) -> Uint256 {
+    let spent = std::cmp::min(interest, credit);
+    liability.loan_amount = liability.loan_amount - spent;
+
+    spent
+}
+
+/// The spent credit settles liabilities the interest accrual already counted,
+/// so it leaves the total liabilities together with the credit balance
+fn settle_repay_credit(
+    storage: &mut dyn Storage,
+    state: &mut State,
+    borrower: &CanonicalAddr,
+    liability: &mut BorrowerInfo,
+    interest: Uint256,
+) -> StdResult<()> {
+    let credit = read_repay_credit(storage, borrower);
+    let spent = apply_repay_credit(liability, interest, credit);
+    if spent.is_zero() {
+        return Ok(());
+    }
+
+    state.total_liabilities = state.total_liabilities
+        - std::cmp::min(Decimal256::from_uint256(spent), state.total_liabilities);
+    store_repay_credit(storage, borrower, &(credit - spent))?;
+    let total_credit = read_total_repay_credit(storage);
+    store_total_repay_credit(storage, &(total_credit - spent))?;
+
+    Ok(())
+}
+
+/// Principal of the position loan, at most the loan itself.
+/// Loans borrowed before the principal was recorded count as principal alone
+fn loan_principal(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    loan_amount: Uint256,
+) -> StdResult<Uint256> {
+    Ok(read_loan_principal(storage, borrower, sub_account)?
+        .unwrap_or(loan_amount)
+        .min(loan_amount))
+}
+
+fn add_loan_principal(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    loan_amount: Uint256,
+    amount: Uint256,
+) -> StdResult<()> {
+    let principal = loan_principal(storage, borrower, sub_account, loan_amount)?;
+    store_loan_principal(storage, borrower, sub_account, &(principal + amount))
+}
+
+/// A repayment pays the accrued interest before the principal,
+/// returns the interest part of it
+fn split_repayment(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    loan_amount: Uint256,
+    repay_amount: Uint256,
+) -> StdResult<Uint256> {
+    let principal = loan_principal(storage, borrower, sub_account, loan_amount)?;
+    let interest_repaid = std::cmp::min(repay_amount, loan_amount - principal);
+    store_loan_principal(
+        storage,
+        borrower,
+        sub_account,
+        &(principal - (repay_amount - interest_repaid)),
+    )?;
+
+    Ok(interest_repaid)
+}
+
+/// Interest-free portion of the position loan,
+/// allowances only apply to default positions
+fn read_position_interest_free(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+) -> Uint256 {
+    match sub_account {
+        Some(_) => Uint256::zero(),
+        None => read_interest_free_loan(storage, borrower),
+    }
+}
+
+/// Cover the loan by the borrower allowance again after it changed,
+/// keeping the interest-free total in sync
+fn update_interest_free_loan(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    liability: &BorrowerInfo,
+) -> StdResult<()> {
+    if sub_account.is_some() {
+        return Ok(());
+    }
+
+    let prev_interest_free = read_interest_free_loan(storage, borrower);
+    let interest_free = std::cmp::min(
+        read_interest_free_allowance(storage, borrower)?,
+        liability.loan_amount,
+    );
+    if interest_free == prev_interest_free {
+        return Ok(());
+    }
+
+    let total_interest_free = read_total_interest_free(storage)
+        + Decimal256::from_uint256(interest_free)
+        - Decimal256::from_uint256(prev_interest_free);
+    store_total_interest_free(storage, &total_interest_free)?;
+    store_interest_free_loan(storage, borrower, &interest_free)
 }
 
 /// Compute distributed reward and update global index
@@ -367,11 +2337,11 @@ pub fn query_borrower_info(
     env: Env,
     borrower: Addr,
     block_height: Option<u64>,
+    sub_account: Option<String>,
 ) -> StdResult<BorrowerInfoResponse> {
-    let mut borrower_info: BorrowerInfo = read_borrower_info(
-        deps.storage,
-        &deps.api.addr_canonicalize(borrower.as_str())?,
-    );
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut borrower_info: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, sub_account.as_deref());
 
     let block_height = if let Some(block_height) = block_height {
         block_height
@@ -382,18 +2352,160 @@ pub fn query_borrower_info(
     let config: Config = read_config(deps.storage)?;
     let mut state: State = read_state(deps.storage)?;
 
-    compute_interest(deps, &config, &mut state, block_height, None)?;
-    compute_borrower_interest(&state, &mut borrower_info);
+    compute_interest(
+        deps,
+        &config,
+        &mut state,
+        interest_clock(&config, &env, block_height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut borrower_info,
+        read_position_interest_free(deps.storage, &borrower_raw, sub_account.as_deref()),
+    );
+    let interest = interest
+        + compute_premium_interest(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &mut borrower_info,
+        )?
+        .0;
+    let interest = apply_rate_discount(
+        deps,
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut borrower_info,
+        interest,
+    )?;
+    let repay_credit = read_repay_credit(deps.storage, &borrower_raw);
+    let spent_credit = apply_repay_credit(&mut borrower_info, interest, repay_credit);
 
     compute_reward(&mut state, block_height);
     compute_borrower_reward(&state, &mut borrower_info);
 
     Ok(BorrowerInfoResponse {
         borrower: borrower.to_string(),
+        sub_account,
         interest_index: borrower_info.interest_index,
         reward_index: borrower_info.reward_index,
         loan_amount: borrower_info.loan_amount,
         pending_rewards: borrower_info.pending_rewards,
+        interest_free_allowance: read_interest_free_allowance(deps.storage, &borrower_raw)?,
+        repay_credit: repay_credit - spent_credit,
+    })
+}
+
+/// Dry run of a borrow, the reason is the error the borrow would fail with
+pub fn query_can_borrow(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    amount: Uint256,
+    sub_account: Option<String>,
+) -> StdResult<CanBorrowResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let mut state: State = read_state(deps.storage)?;
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut liability: BorrowerInfo =
+        read_borrower_info(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    compute_interest(
+        deps,
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    let interest = compute_borrower_interest(
+        &state,
+        &mut liability,
+        read_position_interest_free(deps.storage, &borrower_raw, sub_account.as_deref()),
+    );
+    let interest = interest
+        + compute_premium_interest(
+            deps.storage,
+            &config,
+            &mut state,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &mut liability,
+        )?
+        .0;
+    let interest = apply_rate_discount(
+        deps,
+        &config,
+        &mut state,
+        &borrower_raw,
+        &mut liability,
+        interest,
+    )?;
+    apply_repay_credit(
+        &mut liability,
+        interest,
+        read_repay_credit(deps.storage, &borrower_raw),
+    );
+
+    let reason = assert_borrow(
+        deps,
+        &env,
+        &config,
+        &state,
+        &borrower,
+        liability.loan_amount,
+        amount,
+        sub_account,
+    )
+    .err()
+    .map(|err| err.to_string());
+
+    Ok(CanBorrowResponse {
+        allowed: reason.is_none(),
+        reason,
+    })
+}
+
+pub fn query_credit_line(deps: Deps, env: Env, borrower: Addr) -> StdResult<CreditLineResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut credit_line = read_credit_line(deps.storage, &borrower_raw)?.unwrap_or(CreditLine {
+        committed: Uint256::zero(),
+        accrued_fee: Uint256::zero(),
+        last_accrued_height: env.block.height,
+    });
+    accrue_commitment_fee(&config, &mut credit_line, env.block.height);
+
+    Ok(CreditLineResponse {
+        borrower: borrower.to_string(),
+        committed: credit_line.committed,
+        accrued_fee: credit_line.accrued_fee,
+        total_committed: read_total_committed(deps.storage),
+    })
+}
+
+pub fn query_interest_indexes(
+    deps: Deps,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> StdResult<InterestIndexesResponse> {
+    let borrower_info: BorrowerInfo = read_borrower_info(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        sub_account.as_deref(),
+    );
+    let state: State = read_state(deps.storage)?;
+
+    Ok(InterestIndexesResponse {
+        borrower: borrower.to_string(),
+        global_interest_index: state.global_interest_index,
+        last_interest_updated: state.last_interest_updated,
+        interest_index: borrower_info.interest_index,
+        loan_amount: borrower_info.loan_amount,
     })
 }
 
@@ -412,6 +2524,18 @@ pub fn query_borrower_infos(
     Ok(BorrowerInfosResponse { borrower_infos })
 }
 
+/// Ratio of the stable balance available for redeems and borrows
+/// over the total deposits (balance + liabilities - reserves)
+pub fn compute_liquidity_ratio(state: &State, balance: Uint256) -> Decimal256 {
+    let balance = Decimal256::from_uint256(balance);
+    if balance <= state.total_reserves {
+        return Decimal256::zero();
+    }
+
+    let available = balance - state.total_reserves;
+    available / (available + state.total_liabilities)
+}
+
 fn assert_max_borrow_factor(
     config: &Config,
     state: &State,
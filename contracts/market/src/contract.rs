@@ -2,33 +2,68 @@
 use cosmwasm_std::entry_point;
 
 use crate::borrow::{
-    borrow_stable, claim_rewards, compute_interest, compute_interest_raw, compute_reward,
-    query_borrower_info, query_borrower_infos, repay_stable, repay_stable_from_liquidation,
+    borrow_stable, claim_rewards, compute_interest, compute_interest_raw, compute_liquidity_ratio,
+    compute_reward, continue_leverage, draw_credit, flash_deleverage, interest_clock,
+    leverage_step_reply, open_credit_line, query_borrower_info, query_borrower_infos,
+    query_can_borrow, query_credit_line, query_interest_indexes, rate_per_clock_unit,
+    rebase_borrow_rate_premiums, refinance, repay_stable, repay_stable_from_liquidation,
+    repay_stable_on_behalf, set_leverage, settle_flash_deleverage, split_loan, transfer_loan,
+    update_interest_free_allowance, withdraw_repay_credit,
+};
+use crate::deposit::{
+    compute_exchange_rate, compute_exchange_rate_raw, deploy_to_strategy, deposit_stable,
+    query_deployed_value, query_market_balance, query_max_redeemable, recall_from_strategy,
+    recall_from_strategy_reply, redeem_stable,
 };
-use crate::deposit::{compute_exchange_rate_raw, deposit_stable, redeem_stable};
 use crate::error::ContractError;
-use crate::querier::{query_anc_emission_rate, query_borrow_rate, query_target_deposit_rate};
+use crate::querier::{
+    query_anc_emission_rate, query_borrow_rate, query_custody_balance, query_custody_contracts,
+    query_target_deposit_rate,
+};
 use crate::response::MsgInstantiateContractResponse;
-use crate::state::{read_config, read_state, store_config, store_state, Config, State};
+use crate::state::{
+    read_config, read_epoch_interest, read_exchange_rate_snapshot, read_flash_deleverage,
+    read_reserve_conversion, read_state, read_total_deployed, read_total_interest_free,
+    read_total_repay_credit, remove_reserve_conversion, store_config, store_epoch_interest,
+    store_exchange_rate_snapshot, store_reserve_conversion, store_state, Config, EpochInterest,
+    ExchangeRateSnapshot, PendingReserveConversion, State,
+};
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
     attr, from_binary, to_binary, Addr, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg, Deps,
     DepsMut, Env, MessageInfo, Reply, Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
 };
-use cw20::{Cw20Coin, Cw20ReceiveMsg, MinterResponse};
+use cw20::{Cw20Coin, Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
 
 use moneymarket::common::optional_addr_validate;
+use moneymarket::converter::{
+    Cw20HookMsg as ConverterCw20HookMsg, ExecuteMsg as ConverterExecuteMsg,
+};
+use moneymarket::custody::{Cw20HookMsg as CustodyCw20HookMsg, ExecuteMsg as CustodyExecuteMsg};
 use moneymarket::interest_model::BorrowRateResponse;
 use moneymarket::market::{
-    ConfigResponse, Cw20HookMsg, EpochStateResponse, ExecuteMsg, InstantiateMsg, QueryMsg,
-    StateResponse,
+    AccrualParamsResponse, AccruedInterestResponse, BorrowAprResponse, ConfigResponse, Cw20HookMsg,
+    DepositApyResponse, EpochStateResponse, ExchangeRateBetweenResponse, ExecuteMsg,
+    InstantiateMsg, LastAccrualResponse, OverpayAction, QueryMsg, ReserveCoverageResponse,
+    SameBlockRepayPolicy, StateResponse, UpdateConfigMsg,
 };
-use moneymarket::querier::{deduct_tax, query_balance, query_supply};
+use moneymarket::overseer::ExecuteMsg as OverseerExecuteMsg;
+use moneymarket::querier::{deduct_tax, query_balance, query_supply, query_token_balance};
 use protobuf::Message;
 use terraswap::token::InstantiateMsg as TokenInstantiateMsg;
 
 pub const INITIAL_DEPOSIT_AMOUNT: u128 = 1000000;
+const SECONDS_PER_YEAR: u64 = 31536000;
+
+// Reply ids of the reserve route conversions and strategy recalls
+const DEPLOY_RESERVES_REPLY_ID: u64 = 2;
+const RECALL_RESERVES_REPLY_ID: u64 = 3;
+pub(crate) const STRATEGY_RECALL_REPLY_ID: u64 = 4;
+pub(crate) const LEVERAGE_STEP_REPLY_ID: u64 = 5;
+/// Rounding leaves at most a few units of dust, a larger epsilon
+/// would forgive real debt
+const MAX_REPAY_DUST_EPSILON: u128 = 1000;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -51,6 +86,49 @@ pub fn instantiate(
         ));
     }
 
+    if msg.blocks_per_year == 0 {
+        return Err(ContractError::InvalidBlocksPerYear {});
+    }
+
+    assert_repay_dust_epsilon(msg.repay_dust_epsilon)?;
+    let borrow_fee = msg.borrow_fee.unwrap_or_else(Decimal256::zero);
+    assert_borrow_fee(borrow_fee)?;
+    let repay_protocol_fee = msg.repay_protocol_fee.unwrap_or_else(Decimal256::zero);
+    assert_repay_protocol_fee(repay_protocol_fee)?;
+    let commitment_fee = msg.commitment_fee.unwrap_or_else(Decimal256::zero);
+    assert_commitment_fee(commitment_fee)?;
+    let same_block_repay_policy = msg
+        .same_block_repay_policy
+        .unwrap_or(SameBlockRepayPolicy::Allow);
+    assert_same_block_repay_policy(&same_block_repay_policy)?;
+
+    let flash_fee = msg.flash_fee.unwrap_or_else(Decimal256::zero);
+    assert_flash_fee(flash_fee)?;
+
+    let stable_denom = msg.stable_denom.clone();
+    let accepted_repay_denoms = msg
+        .accepted_repay_denoms
+        .unwrap_or_else(|| vec![stable_denom]);
+    assert_accepted_repay_denoms(&msg.stable_denom, &accepted_repay_denoms)?;
+
+    let reserve_route = msg
+        .reserve_route
+        .map(|r| deps.api.addr_canonicalize(&r))
+        .transpose()?;
+
+    let yield_strategy = msg
+        .yield_strategy
+        .map(|s| deps.api.addr_canonicalize(&s))
+        .transpose()?;
+    let max_deployed_ratio = msg.max_deployed_ratio.unwrap_or_else(Decimal256::zero);
+    assert_max_deployed_ratio(max_deployed_ratio)?;
+
+    let rate_discount_source = msg
+        .rate_discount_source
+        .map(|s| deps.api.addr_canonicalize(&s))
+        .transpose()?;
+    let rate_discount_tiers = msg.rate_discount_tiers.unwrap_or_default();
+    assert_rate_discount_tiers(&rate_discount_tiers)?;
     store_config(
         deps.storage,
         &Config {
@@ -64,15 +142,34 @@ pub fn instantiate(
             distributor_contract: CanonicalAddr::from(vec![]),
             stable_denom: msg.stable_denom.clone(),
             max_borrow_factor: msg.max_borrow_factor,
+            min_liquidity_ratio: msg.min_liquidity_ratio,
+            repay_dust_epsilon: msg.repay_dust_epsilon,
+            blocks_per_year: msg.blocks_per_year,
+            borrow_fee,
+            accrual_block_threshold: msg.accrual_block_threshold.unwrap_or(0),
+            accrue_by_time: msg.accrue_by_time.unwrap_or(false),
+            reserve_route,
+            flash_fee,
+            accepted_repay_denoms,
+            yield_strategy,
+            max_deployed_ratio,
+            rate_discount_source,
+            rate_discount_tiers,
+            min_repay_amount: msg.min_repay_amount.unwrap_or_else(Uint256::zero),
+            repay_protocol_fee,
+            commitment_fee,
+            max_total_borrow: msg.max_total_borrow,
+            same_block_repay_policy,
         },
     )?;
+    let config: Config = read_config(deps.storage)?;
 
     store_state(
         deps.storage,
         &State {
             total_liabilities: Decimal256::zero(),
             total_reserves: Decimal256::zero(),
-            last_interest_updated: env.block.height,
+            last_interest_updated: interest_clock(&config, &env, env.block.height),
             last_reward_updated: env.block.height,
             global_interest_index: Decimal256::one(),
             global_reward_index: Decimal256::zero(),
@@ -82,6 +179,15 @@ pub fn instantiate(
         },
     )?;
 
+    store_exchange_rate_snapshot(
+        deps.storage,
+        env.block.height,
+        &ExchangeRateSnapshot {
+            exchange_rate: Decimal256::one(),
+            block_time: env.block.time.seconds(),
+        },
+    )?;
+
     Ok(
         Response::new().add_submessages(vec![SubMsg::reply_on_success(
             CosmosMsg::Wasm(WasmMsg::Instantiate {
@@ -118,6 +224,13 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    // Nothing may move the market balance before the flash deleverage is settled
+    if !matches!(msg, ExecuteMsg::SettleFlashDeleverage {})
+        && read_flash_deleverage(deps.storage)?.is_some()
+    {
+        return Err(ContractError::FlashDeleverageInProgress {});
+    }
+
     match msg {
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::RegisterContracts {
@@ -137,12 +250,31 @@ pub fn execute(
                 api.addr_validate(&distributor_contract)?,
             )
         }
-        ExecuteMsg::UpdateConfig {
-            owner_addr,
-            interest_model,
-            distribution_model,
-            max_borrow_factor,
-        } => {
+        ExecuteMsg::UpdateConfig(msg) => {
+            let UpdateConfigMsg {
+                owner_addr,
+                interest_model,
+                distribution_model,
+                max_borrow_factor,
+                min_liquidity_ratio,
+                repay_dust_epsilon,
+                blocks_per_year,
+                borrow_fee,
+                accrual_block_threshold,
+                accrue_by_time,
+                reserve_route,
+                flash_fee,
+                accepted_repay_denoms,
+                yield_strategy,
+                max_deployed_ratio,
+                rate_discount_source,
+                rate_discount_tiers,
+                min_repay_amount,
+                repay_protocol_fee,
+                commitment_fee,
+                max_total_borrow,
+                same_block_repay_policy,
+            } = *msg;
             let api = deps.api;
             update_config(
                 deps,
@@ -152,6 +284,53 @@ pub fn execute(
                 optional_addr_validate(api, interest_model)?,
                 optional_addr_validate(api, distribution_model)?,
                 max_borrow_factor,
+                min_liquidity_ratio,
+                repay_dust_epsilon,
+                blocks_per_year,
+                borrow_fee,
+                accrual_block_threshold,
+                accrue_by_time,
+                optional_addr_validate(api, reserve_route)?,
+                flash_fee,
+                accepted_repay_denoms,
+                optional_addr_validate(api, yield_strategy)?,
+                max_deployed_ratio,
+                optional_addr_validate(api, rate_discount_source)?,
+                rate_discount_tiers,
+                min_repay_amount,
+                repay_protocol_fee,
+                commitment_fee,
+                max_total_borrow,
+                same_block_repay_policy,
+            )
+        }
+        ExecuteMsg::GrantInterestFreeAllowance {
+            borrower,
+            collateral_token,
+            amount,
+        } => {
+            let api = deps.api;
+            update_interest_free_allowance(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                api.addr_validate(&collateral_token)?,
+                Some(amount),
+            )
+        }
+        ExecuteMsg::RevokeInterestFreeAllowance {
+            borrower,
+            collateral_token,
+        } => {
+            let api = deps.api;
+            update_interest_free_allowance(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                api.addr_validate(&collateral_token)?,
+                None,
             )
         }
         ExecuteMsg::ExecuteEpochOperations {
@@ -168,8 +347,73 @@ pub fn execute(
             threshold_deposit_rate,
             distributed_interest,
         ),
+        ExecuteMsg::DeployReserves {
+            collateral_token,
+            amount,
+        } => {
+            let api = deps.api;
+            deploy_reserves(
+                deps,
+                env,
+                info,
+                api.addr_validate(&collateral_token)?,
+                amount,
+            )
+        }
+        ExecuteMsg::RecallReserves {
+            collateral_token,
+            amount,
+        } => {
+            let api = deps.api;
+            recall_reserves(
+                deps,
+                env,
+                info,
+                api.addr_validate(&collateral_token)?,
+                amount,
+            )
+        }
+        ExecuteMsg::ConvertRecalledReserves {
+            collateral_token,
+            prev_balance,
+        } => {
+            let api = deps.api;
+            convert_recalled_reserves(
+                deps,
+                env,
+                info,
+                api.addr_validate(&collateral_token)?,
+                prev_balance,
+            )
+        }
+        ExecuteMsg::DeployToStrategy { amount } => deploy_to_strategy(deps, info, amount),
+        ExecuteMsg::RecallFromStrategy { amount } => recall_from_strategy(deps, env, info, amount),
+        ExecuteMsg::SettleFlashDeleverage {} => settle_flash_deleverage(deps, env, info),
+        ExecuteMsg::ContinueLeverage {
+            borrower,
+            collateral_token,
+            target_leverage,
+            iterations_left,
+            slippage,
+        } => {
+            let api = deps.api;
+            continue_leverage(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                api.addr_validate(&collateral_token)?,
+                target_leverage,
+                iterations_left,
+                slippage,
+            )
+        }
         ExecuteMsg::DepositStable {} => deposit_stable(deps, env, info),
-        ExecuteMsg::BorrowStable { borrow_amount, to } => {
+        ExecuteMsg::BorrowStable {
+            borrow_amount,
+            to,
+            sub_account,
+        } => {
             let api = deps.api;
             borrow_stable(
                 deps,
@@ -177,12 +421,38 @@ pub fn execute(
                 info,
                 borrow_amount,
                 optional_addr_validate(api, to)?,
+                sub_account,
             )
         }
-        ExecuteMsg::RepayStable {} => repay_stable(deps, env, info),
+        ExecuteMsg::RepayStable {
+            auto_unlock,
+            sub_account,
+            overpay_action,
+        } => repay_stable(
+            deps,
+            env,
+            info,
+            auto_unlock.unwrap_or(false),
+            sub_account,
+            overpay_action.unwrap_or(OverpayAction::Refund),
+        ),
+        ExecuteMsg::WithdrawRepayCredit { amount } => withdraw_repay_credit(deps, info, amount),
+        ExecuteMsg::Refinance {
+            repay_amount,
+            new_borrow_amount,
+            sub_account,
+        } => refinance(
+            deps,
+            env,
+            info,
+            repay_amount,
+            new_borrow_amount,
+            sub_account,
+        ),
         ExecuteMsg::RepayStableFromLiquidation {
             borrower,
             prev_balance,
+            sub_account,
         } => {
             let api = deps.api;
             repay_stable_from_liquidation(
@@ -191,17 +461,87 @@ pub fn execute(
                 info,
                 api.addr_validate(&borrower)?,
                 prev_balance,
+                sub_account,
+            )
+        }
+        ExecuteMsg::RepayStableOnBehalf {
+            borrower,
+            sub_account,
+        } => {
+            let api = deps.api;
+            repay_stable_on_behalf(deps, env, info, api.addr_validate(&borrower)?, sub_account)
+        }
+        ExecuteMsg::TransferLoan {
+            borrower,
+            recipient,
+        } => {
+            let api = deps.api;
+            transfer_loan(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                api.addr_validate(&recipient)?,
+            )
+        }
+        ExecuteMsg::SplitLoan {
+            borrower,
+            from_sub_account,
+            to_sub_account,
+            fraction,
+        } => {
+            let api = deps.api;
+            split_loan(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                from_sub_account,
+                to_sub_account,
+                fraction,
+            )
+        }
+        ExecuteMsg::FlashDeleverage {
+            borrow_amount,
+            callback,
+        } => flash_deleverage(deps, env, info, borrow_amount, callback),
+        ExecuteMsg::SetLeverage {
+            collateral_token,
+            target_leverage,
+            max_iterations,
+            slippage,
+        } => {
+            let api = deps.api;
+            set_leverage(
+                deps,
+                env,
+                info,
+                api.addr_validate(&collateral_token)?,
+                target_leverage,
+                max_iterations,
+                slippage,
             )
         }
-        ExecuteMsg::ClaimRewards { to } => {
+        ExecuteMsg::OpenCreditLine { amount } => open_credit_line(deps, env, info, amount),
+        ExecuteMsg::DrawCredit { amount, to } => {
             let api = deps.api;
-            claim_rewards(deps, env, info, optional_addr_validate(api, to)?)
+            draw_credit(deps, env, info, amount, optional_addr_validate(api, to)?)
+        }
+        ExecuteMsg::ClaimRewards { to, sub_account } => {
+            let api = deps.api;
+            claim_rewards(
+                deps,
+                env,
+                info,
+                optional_addr_validate(api, to)?,
+                sub_account,
+            )
         }
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     match msg.id {
         1 => {
             // get new token's contract address
@@ -218,6 +558,10 @@ pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractE
 
             register_aterra(deps, token_addr)
         }
+        DEPLOY_RESERVES_REPLY_ID => deploy_reserves_reply(deps, env),
+        RECALL_RESERVES_REPLY_ID => recall_reserves_reply(deps, env),
+        STRATEGY_RECALL_REPLY_ID => recall_from_strategy_reply(deps, env),
+        LEVERAGE_STEP_REPLY_ID => leverage_step_reply(deps, env),
         _ => Err(ContractError::InvalidReplyId {}),
     }
 }
@@ -284,6 +628,7 @@ pub fn register_contracts(
     Ok(Response::default())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_config(
     deps: DepsMut,
     env: Env,
@@ -292,6 +637,24 @@ pub fn update_config(
     interest_model: Option<Addr>,
     distribution_model: Option<Addr>,
     max_borrow_factor: Option<Decimal256>,
+    min_liquidity_ratio: Option<Decimal256>,
+    repay_dust_epsilon: Option<Uint256>,
+    blocks_per_year: Option<u64>,
+    borrow_fee: Option<Decimal256>,
+    accrual_block_threshold: Option<u64>,
+    accrue_by_time: Option<bool>,
+    reserve_route: Option<Addr>,
+    flash_fee: Option<Decimal256>,
+    accepted_repay_denoms: Option<Vec<String>>,
+    yield_strategy: Option<Addr>,
+    max_deployed_ratio: Option<Decimal256>,
+    rate_discount_source: Option<Addr>,
+    rate_discount_tiers: Option<Vec<(u32, Decimal256)>>,
+    min_repay_amount: Option<Uint256>,
+    repay_protocol_fee: Option<Decimal256>,
+    commitment_fee: Option<Decimal256>,
+    max_total_borrow: Option<Uint256>,
+    same_block_repay_policy: Option<SameBlockRepayPolicy>,
 ) -> Result<Response, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
 
@@ -306,7 +669,13 @@ pub fn update_config(
 
     if interest_model.is_some() {
         let mut state: State = read_state(deps.storage)?;
-        compute_interest(deps.as_ref(), &config, &mut state, env.block.height, None)?;
+        compute_interest(
+            deps.as_ref(),
+            &config,
+            &mut state,
+            interest_clock(&config, &env, env.block.height),
+            None,
+        )?;
         store_state(deps.storage, &state)?;
 
         if let Some(interest_model) = interest_model {
@@ -322,10 +691,529 @@ pub fn update_config(
         config.max_borrow_factor = max_borrow_factor;
     }
 
+    if let Some(min_liquidity_ratio) = min_liquidity_ratio {
+        config.min_liquidity_ratio = min_liquidity_ratio;
+    }
+
+    if let Some(repay_dust_epsilon) = repay_dust_epsilon {
+        assert_repay_dust_epsilon(repay_dust_epsilon)?;
+        config.repay_dust_epsilon = repay_dust_epsilon;
+    }
+
+    if let Some(blocks_per_year) = blocks_per_year {
+        if blocks_per_year == 0 {
+            return Err(ContractError::InvalidBlocksPerYear {});
+        }
+
+        config.blocks_per_year = blocks_per_year;
+    }
+
+    if let Some(borrow_fee) = borrow_fee {
+        assert_borrow_fee(borrow_fee)?;
+        config.borrow_fee = borrow_fee;
+    }
+
+    if let Some(accrual_block_threshold) = accrual_block_threshold {
+        config.accrual_block_threshold = accrual_block_threshold;
+    }
+
+    if let Some(accrue_by_time) = accrue_by_time {
+        if accrue_by_time != config.accrue_by_time {
+            // Accrue up to now on the old clock before switching units
+            let mut state: State = read_state(deps.storage)?;
+            compute_interest(
+                deps.as_ref(),
+                &config,
+                &mut state,
+                interest_clock(&config, &env, env.block.height),
+                None,
+            )?;
+
+            let prev_clock = state.last_interest_updated;
+            config.accrue_by_time = accrue_by_time;
+            state.last_interest_updated = interest_clock(&config, &env, env.block.height);
+            store_state(deps.storage, &state)?;
+            rebase_borrow_rate_premiums(
+                deps.storage,
+                &config,
+                prev_clock,
+                state.last_interest_updated,
+            )?;
+        }
+    }
+
+    if let Some(reserve_route) = reserve_route {
+        config.reserve_route = Some(deps.api.addr_canonicalize(reserve_route.as_str())?);
+    }
+
+    if let Some(flash_fee) = flash_fee {
+        assert_flash_fee(flash_fee)?;
+        config.flash_fee = flash_fee;
+    }
+
+    if let Some(accepted_repay_denoms) = accepted_repay_denoms {
+        assert_accepted_repay_denoms(&config.stable_denom, &accepted_repay_denoms)?;
+        config.accepted_repay_denoms = accepted_repay_denoms;
+    }
+
+    if let Some(yield_strategy) = yield_strategy {
+        config.yield_strategy = Some(deps.api.addr_canonicalize(yield_strategy.as_str())?);
+    }
+
+    if let Some(max_deployed_ratio) = max_deployed_ratio {
+        assert_max_deployed_ratio(max_deployed_ratio)?;
+        config.max_deployed_ratio = max_deployed_ratio;
+    }
+
+    if let Some(rate_discount_source) = rate_discount_source {
+        config.rate_discount_source =
+            Some(deps.api.addr_canonicalize(rate_discount_source.as_str())?);
+    }
+
+    if let Some(rate_discount_tiers) = rate_discount_tiers {
+        assert_rate_discount_tiers(&rate_discount_tiers)?;
+        config.rate_discount_tiers = rate_discount_tiers;
+    }
+
+    if let Some(min_repay_amount) = min_repay_amount {
+        config.min_repay_amount = min_repay_amount;
+    }
+
+    if let Some(repay_protocol_fee) = repay_protocol_fee {
+        assert_repay_protocol_fee(repay_protocol_fee)?;
+        config.repay_protocol_fee = repay_protocol_fee;
+    }
+
+    if let Some(commitment_fee) = commitment_fee {
+        assert_commitment_fee(commitment_fee)?;
+        config.commitment_fee = commitment_fee;
+    }
+
+    if let Some(max_total_borrow) = max_total_borrow {
+        config.max_total_borrow = Some(max_total_borrow);
+    }
+
+    if let Some(same_block_repay_policy) = same_block_repay_policy {
+        assert_same_block_repay_policy(&same_block_repay_policy)?;
+        config.same_block_repay_policy = same_block_repay_policy;
+    }
+
     store_config(deps.storage, &config)?;
     Ok(Response::new().add_attributes(vec![attr("action", "update_config")]))
 }
 
+pub fn deploy_reserves(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral_token: Addr,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let reserve_route = match &config.reserve_route {
+        Some(reserve_route) => deps.api.addr_humanize(reserve_route)?,
+        None => return Err(ContractError::NoReserveRoute {}),
+    };
+
+    let mut state: State = read_state(deps.storage)?;
+    compute_interest(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+
+    let reserves = state.total_reserves * Uint256::one();
+    if amount > reserves {
+        return Err(ContractError::ReservesOverdrawn(reserves.into()));
+    }
+
+    state.total_reserves = state.total_reserves - Decimal256::from_uint256(amount);
+    store_state(deps.storage, &state)?;
+
+    let offer_coin = deduct_tax(
+        deps.as_ref(),
+        Coin {
+            denom: config.stable_denom.clone(),
+            amount: amount.into(),
+        },
+    )?;
+
+    // The route sends the collateral to the market, its reply
+    // locks what was actually delivered
+    let prev_balance = query_token_balance(
+        deps.as_ref(),
+        collateral_token.clone(),
+        env.contract.address.clone(),
+    )?;
+    store_reserve_conversion(
+        deps.storage,
+        &PendingReserveConversion {
+            collateral_token: deps.api.addr_canonicalize(collateral_token.as_str())?,
+            prev_balance,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: reserve_route.to_string(),
+                funds: vec![offer_coin],
+                msg: to_binary(&ConverterExecuteMsg::ConvertNative {
+                    ask_token: collateral_token.to_string(),
+                    recipient: env.contract.address.to_string(),
+                    msg: None,
+                    min_receive: None,
+                })?,
+            }),
+            DEPLOY_RESERVES_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr("action", "deploy_reserves"),
+            attr("collateral_token", collateral_token),
+            attr("amount", amount),
+        ]))
+}
+
+/// Deposit the collateral the reserve route delivered across the custody
+/// contracts by the weights the overseer splits its lock with, and lock it
+fn deploy_reserves_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = match read_reserve_conversion(deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(ContractError::InvalidReplyId {}),
+    };
+    remove_reserve_conversion(deps.storage);
+
+    let config: Config = read_config(deps.storage)?;
+    let collateral_token = deps.api.addr_humanize(&pending.collateral_token)?;
+    let acquired_amount = query_token_balance(
+        deps.as_ref(),
+        collateral_token.clone(),
+        env.contract.address,
+    )? - pending.prev_balance;
+    if acquired_amount.is_zero() {
+        return Err(ContractError::NoReservesConverted {});
+    }
+
+    let overseer_contract = deps.api.addr_humanize(&config.overseer_contract)?;
+    let custody_contracts = query_custody_contracts(
+        deps.as_ref(),
+        overseer_contract.clone(),
+        collateral_token.clone(),
+    )?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (custody_contract, custody_amount) in
+        split_custody_amount(&custody_contracts, acquired_amount)
+    {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: collateral_token.to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: custody_contract.to_string(),
+                amount: custody_amount.into(),
+                msg: to_binary(&CustodyCw20HookMsg::DepositCollateral {})?,
+            })?,
+        }));
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: overseer_contract.to_string(),
+        funds: vec![],
+        msg: to_binary(&OverseerExecuteMsg::LockCollateral {
+            collaterals: vec![(collateral_token.to_string(), acquired_amount)],
+            sub_account: None,
+        })?,
+    }));
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "lock_deployed_reserves"),
+        attr("collateral_token", collateral_token),
+        attr("acquired_amount", acquired_amount),
+    ]))
+}
+
+/// Split the amount across the custody contracts proportionally to their
+/// weights, the last custody contract takes the rounding remainder
+pub(crate) fn split_custody_amount(
+    custody_contracts: &[(Addr, Decimal256)],
+    amount: Uint256,
+) -> Vec<(Addr, Uint256)> {
+    let total_weight: Decimal256 = custody_contracts
+        .iter()
+        .fold(Decimal256::zero(), |acc, c| acc + c.1);
+
+    let last_index = custody_contracts.len().saturating_sub(1);
+    let mut left_amount = amount;
+    let mut amounts: Vec<(Addr, Uint256)> = vec![];
+    for (index, (custody_contract, weight)) in custody_contracts.iter().enumerate() {
+        let custody_amount = if index == last_index {
+            left_amount
+        } else {
+            amount * (*weight / total_weight)
+        };
+
+        left_amount = left_amount - custody_amount;
+        if !custody_amount.is_zero() {
+            amounts.push((custody_contract.clone(), custody_amount));
+        }
+    }
+
+    amounts
+}
+
+pub fn recall_reserves(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral_token: Addr,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if config.reserve_route.is_none() {
+        return Err(ContractError::NoReserveRoute {});
+    }
+
+    let overseer_contract = deps.api.addr_humanize(&config.overseer_contract)?;
+    let mut messages: Vec<CosmosMsg> = vec![CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: overseer_contract.to_string(),
+        funds: vec![],
+        msg: to_binary(&OverseerExecuteMsg::UnlockCollateral {
+            collaterals: vec![(collateral_token.to_string(), amount)],
+            sub_account: None,
+        })?,
+    })];
+
+    // Withdraw everything unlocked from the custody contracts holding
+    // collateral of the market
+    for (custody_contract, _) in
+        query_custody_contracts(deps.as_ref(), overseer_contract, collateral_token.clone())?
+    {
+        let custody_balance = query_custody_balance(
+            deps.as_ref(),
+            custody_contract.clone(),
+            env.contract.address.clone(),
+        )?;
+        if !custody_balance.is_zero() {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: custody_contract.to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::WithdrawCollateral { amount: None })?,
+            }));
+        }
+    }
+
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::ConvertRecalledReserves {
+            collateral_token: collateral_token.to_string(),
+            prev_balance: query_token_balance(
+                deps.as_ref(),
+                collateral_token.clone(),
+                env.contract.address.clone(),
+            )?,
+        })?,
+    }));
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "recall_reserves"),
+        attr("collateral_token", collateral_token),
+        attr("amount", amount),
+    ]))
+}
+
+pub fn convert_recalled_reserves(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral_token: Addr,
+    prev_balance: Uint256,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let reserve_route = match &config.reserve_route {
+        Some(reserve_route) => deps.api.addr_humanize(reserve_route)?,
+        None => return Err(ContractError::NoReserveRoute {}),
+    };
+
+    let recalled_amount = query_token_balance(
+        deps.as_ref(),
+        collateral_token.clone(),
+        env.contract.address.clone(),
+    )? - prev_balance;
+    if recalled_amount.is_zero() {
+        return Err(ContractError::NoReservesConverted {});
+    }
+
+    // The reply returns what the route delivered to the reserves
+    let prev_balance = query_balance(
+        deps.as_ref(),
+        env.contract.address.clone(),
+        config.stable_denom.clone(),
+    )?;
+    store_reserve_conversion(
+        deps.storage,
+        &PendingReserveConversion {
+            collateral_token: deps.api.addr_canonicalize(collateral_token.as_str())?,
+            prev_balance,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: collateral_token.to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: reserve_route.to_string(),
+                    amount: recalled_amount.into(),
+                    msg: to_binary(&ConverterCw20HookMsg::Convert {
+                        ask_token: config.stable_denom,
+                        recipient: env.contract.address.to_string(),
+                        msg: None,
+                    })?,
+                })?,
+            }),
+            RECALL_RESERVES_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr("action", "convert_recalled_reserves"),
+            attr("collateral_token", collateral_token),
+            attr("recalled_amount", recalled_amount),
+        ]))
+}
+
+fn recall_reserves_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = match read_reserve_conversion(deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(ContractError::InvalidReplyId {}),
+    };
+    remove_reserve_conversion(deps.storage);
+
+    let config: Config = read_config(deps.storage)?;
+    let received_amount = query_balance(
+        deps.as_ref(),
+        env.contract.address.clone(),
+        config.stable_denom.clone(),
+    )? - pending.prev_balance;
+
+    let mut state: State = read_state(deps.storage)?;
+    compute_interest(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+    state.total_reserves += Decimal256::from_uint256(received_amount);
+    store_state(deps.storage, &state)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "return_recalled_reserves"),
+        attr(
+            "collateral_token",
+            deps.api.addr_humanize(&pending.collateral_token)?,
+        ),
+        attr("received_amount", received_amount),
+    ]))
+}
+
+fn assert_repay_dust_epsilon(repay_dust_epsilon: Uint256) -> Result<(), ContractError> {
+    if repay_dust_epsilon > Uint256::from(MAX_REPAY_DUST_EPSILON) {
+        return Err(ContractError::InvalidRepayDustEpsilon(
+            MAX_REPAY_DUST_EPSILON,
+        ));
+    }
+
+    Ok(())
+}
+
+fn assert_borrow_fee(borrow_fee: Decimal256) -> Result<(), ContractError> {
+    if borrow_fee >= Decimal256::one() {
+        return Err(ContractError::InvalidBorrowFee {});
+    }
+
+    Ok(())
+}
+
+fn assert_flash_fee(flash_fee: Decimal256) -> Result<(), ContractError> {
+    if flash_fee >= Decimal256::one() {
+        return Err(ContractError::InvalidFlashFee {});
+    }
+
+    Ok(())
+}
+
+fn assert_repay_protocol_fee(repay_protocol_fee: Decimal256) -> Result<(), ContractError> {
+    if repay_protocol_fee > Decimal256::one() {
+        return Err(ContractError::InvalidRepayProtocolFee {});
+    }
+
+    Ok(())
+}
+
+fn assert_same_block_repay_policy(policy: &SameBlockRepayPolicy) -> Result<(), ContractError> {
+    if let SameBlockRepayPolicy::Fee { rate } = policy {
+        if *rate > Decimal256::one() {
+            return Err(ContractError::InvalidSameBlockRepayFee {});
+        }
+    }
+
+    Ok(())
+}
+
+fn assert_commitment_fee(commitment_fee: Decimal256) -> Result<(), ContractError> {
+    if commitment_fee >= Decimal256::one() {
+        return Err(ContractError::InvalidCommitmentFee {});
+    }
+
+    Ok(())
+}
+
+fn assert_max_deployed_ratio(max_deployed_ratio: Decimal256) -> Result<(), ContractError> {
+    if max_deployed_ratio > Decimal256::one() {
+        return Err(ContractError::InvalidMaxDeployedRatio {});
+    }
+
+    Ok(())
+}
+
+fn assert_rate_discount_tiers(
+    rate_discount_tiers: &[(u32, Decimal256)],
+) -> Result<(), ContractError> {
+    if rate_discount_tiers
+        .iter()
+        .any(|(_, discount)| *discount > Decimal256::one())
+    {
+        return Err(ContractError::InvalidRateDiscount {});
+    }
+
+    Ok(())
+}
+
+fn assert_accepted_repay_denoms(
+    stable_denom: &str,
+    accepted_repay_denoms: &[String],
+) -> Result<(), ContractError> {
+    if !accepted_repay_denoms.iter().any(|d| d == stable_denom) {
+        return Err(ContractError::InvalidAcceptedRepayDenoms {});
+    }
+
+    Ok(())
+}
+
 pub fn execute_epoch_operations(
     deps: DepsMut,
     env: Env,
@@ -342,16 +1230,23 @@ pub fn execute_epoch_operations(
 
     let mut state: State = read_state(deps.storage)?;
 
+    // Reserve top-up sent by the overseer
+    let reserve_topup: Uint256 = info
+        .funds
+        .iter()
+        .find(|c| c.denom == config.stable_denom)
+        .map(|c| Uint256::from(c.amount))
+        .unwrap_or_else(Uint256::zero);
+
     // Compute interest and reward before updating anc_emission_rate
     let aterra_supply = query_supply(
         deps.as_ref(),
         deps.api.addr_humanize(&config.aterra_contract)?,
     )?;
-    let balance: Uint256 = query_balance(
-        deps.as_ref(),
-        deps.api.addr_humanize(&config.contract_addr)?,
-        config.stable_denom.to_string(),
-    )? - distributed_interest;
+    let balance: Uint256 = query_market_balance(deps.as_ref(), &config)?
+        - distributed_interest
+        - reserve_topup
+        - read_total_repay_credit(deps.storage);
 
     let borrow_rate_res: BorrowRateResponse = query_borrow_rate(
         deps.as_ref(),
@@ -363,13 +1258,19 @@ pub fn execute_epoch_operations(
 
     compute_interest_raw(
         &mut state,
-        env.block.height,
+        interest_clock(&config, &env, env.block.height),
         balance,
         aterra_supply,
-        borrow_rate_res.rate,
-        target_deposit_rate,
+        rate_per_clock_unit(&config, borrow_rate_res.rate),
+        rate_per_clock_unit(&config, target_deposit_rate),
+        read_total_interest_free(deps.storage),
     );
 
+    // The top-up is added to both the balance and the reserves,
+    // leaving the exchange rate unchanged
+    state.total_reserves += Decimal256::from_uint256(reserve_topup);
+    let balance = balance + reserve_topup;
+
     // recompute prev_exchange_rate with distributed_interest
     state.prev_exchange_rate =
         compute_exchange_rate_raw(&state, aterra_supply, balance + distributed_interest);
@@ -380,7 +1281,8 @@ pub fn execute_epoch_operations(
     // Update total_reserves and send it to collector contract
     // only when there is enough balance
     let total_reserves = state.total_reserves * Uint256::one();
-    let messages: Vec<CosmosMsg> = if !total_reserves.is_zero() && balance > total_reserves {
+    let held_balance = balance - query_deployed_value(deps.as_ref(), &config)?;
+    let messages: Vec<CosmosMsg> = if !total_reserves.is_zero() && held_balance > total_reserves {
         state.total_reserves = state.total_reserves - Decimal256::from_uint256(total_reserves);
 
         vec![CosmosMsg::Bank(BankMsg::Send {
@@ -412,10 +1314,26 @@ pub fn execute_epoch_operations(
     .emission_rate;
 
     store_state(deps.storage, &state)?;
+    store_epoch_interest(
+        deps.storage,
+        &EpochInterest {
+            exchange_rate: state.prev_exchange_rate,
+            block_height: env.block.height,
+        },
+    )?;
+    store_exchange_rate_snapshot(
+        deps.storage,
+        env.block.height,
+        &ExchangeRateSnapshot {
+            exchange_rate: state.prev_exchange_rate,
+            block_time: env.block.time.seconds(),
+        },
+    )?;
 
     Ok(Response::new().add_messages(messages).add_attributes(vec![
         attr("action", "execute_epoch_operations"),
         attr("total_reserves", total_reserves),
+        attr("reserve_topup", reserve_topup),
         attr("anc_emission_rate", state.anc_emission_rate.to_string()),
     ]))
 }
@@ -430,23 +1348,71 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             distributed_interest,
         } => to_binary(&query_epoch_state(
             deps,
+            env,
             block_height,
             distributed_interest,
         )?),
         QueryMsg::BorrowerInfo {
             borrower,
             block_height,
+            sub_account,
         } => to_binary(&query_borrower_info(
             deps,
             env,
             deps.api.addr_validate(&borrower)?,
             block_height,
+            sub_account,
         )?),
         QueryMsg::BorrowerInfos { start_after, limit } => to_binary(&query_borrower_infos(
             deps,
             optional_addr_validate(deps.api, start_after)?,
             limit,
         )?),
+        QueryMsg::AccruedInterest {} => to_binary(&query_accrued_interest(deps, env)?),
+        QueryMsg::ExchangeRateBetween {
+            start_height,
+            end_height,
+        } => to_binary(&query_exchange_rate_between(
+            deps,
+            start_height,
+            end_height,
+        )?),
+        QueryMsg::AccrualParams {} => to_binary(&query_accrual_params(deps)?),
+        QueryMsg::InterestIndexes {
+            borrower,
+            sub_account,
+        } => to_binary(&query_interest_indexes(
+            deps,
+            deps.api.addr_validate(&borrower)?,
+            sub_account,
+        )?),
+        QueryMsg::CanBorrow {
+            borrower,
+            amount,
+            sub_account,
+        } => to_binary(&query_can_borrow(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            amount,
+            sub_account,
+        )?),
+        QueryMsg::BorrowApr {} => to_binary(&query_borrow_apr(deps)?),
+        QueryMsg::DepositApy {
+            compounding_periods,
+        } => to_binary(&query_deposit_apy(deps, compounding_periods)?),
+        QueryMsg::LastAccrual {} => to_binary(&query_last_accrual(deps)?),
+        QueryMsg::MaxRedeemable { depositor } => to_binary(&query_max_redeemable(
+            deps,
+            env,
+            deps.api.addr_validate(&depositor)?,
+        )?),
+        QueryMsg::CreditLine { borrower } => to_binary(&query_credit_line(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+        )?),
+        QueryMsg::ReserveCoverage {} => to_binary(&query_reserve_coverage(deps, env)?),
     }
 }
 
@@ -474,10 +1440,38 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
             .to_string(),
         stable_denom: config.stable_denom,
         max_borrow_factor: config.max_borrow_factor,
+        min_liquidity_ratio: config.min_liquidity_ratio,
+        repay_dust_epsilon: config.repay_dust_epsilon,
+        blocks_per_year: config.blocks_per_year,
+        borrow_fee: config.borrow_fee,
+        accrual_block_threshold: config.accrual_block_threshold,
+        accrue_by_time: config.accrue_by_time,
+        reserve_route: config
+            .reserve_route
+            .map(|r| deps.api.addr_humanize(&r).map(|r| r.to_string()))
+            .transpose()?,
+        flash_fee: config.flash_fee,
+        accepted_repay_denoms: config.accepted_repay_denoms,
+        yield_strategy: config
+            .yield_strategy
+            .map(|s| deps.api.addr_humanize(&s).map(|s| s.to_string()))
+            .transpose()?,
+        max_deployed_ratio: config.max_deployed_ratio,
+        rate_discount_source: config
+            .rate_discount_source
+            .map(|s| deps.api.addr_humanize(&s).map(|s| s.to_string()))
+            .transpose()?,
+        rate_discount_tiers: config.rate_discount_tiers,
+        min_repay_amount: config.min_repay_amount,
+        repay_protocol_fee: config.repay_protocol_fee,
+        commitment_fee: config.commitment_fee,
+        max_total_borrow: config.max_total_borrow,
+        same_block_repay_policy: config.same_block_repay_policy,
     })
 }
 
 pub fn query_state(deps: Deps, env: Env, block_height: Option<u64>) -> StdResult<StateResponse> {
+    let config: Config = read_config(deps.storage)?;
     let mut state: State = read_state(deps.storage)?;
 
     let block_height = if let Some(block_height) = block_height {
@@ -485,8 +1479,9 @@ pub fn query_state(deps: Deps, env: Env, block_height: Option<u64>) -> StdResult
     } else {
         env.block.height
     };
+    let interest_clock = interest_clock(&config, &env, block_height);
 
-    if block_height < state.last_interest_updated {
+    if interest_clock < state.last_interest_updated {
         return Err(StdError::generic_err(
             "block_height must bigger than last_interest_updated",
         ));
@@ -498,14 +1493,16 @@ pub fn query_state(deps: Deps, env: Env, block_height: Option<u64>) -> StdResult
         ));
     }
 
-    let config: Config = read_config(deps.storage)?;
-
     // Compute interest rate with given block height
-    compute_interest(deps, &config, &mut state, block_height, None)?;
+    compute_interest(deps, &config, &mut state, interest_clock, None)?;
 
     // Compute reward rate with given block height
     compute_reward(&mut state, block_height);
 
+    let balance: Uint256 =
+        query_market_balance(deps, &config)? - read_total_repay_credit(deps.storage);
+    let total_deployed = read_total_deployed(deps.storage);
+
     Ok(StateResponse {
         total_liabilities: state.total_liabilities,
         total_reserves: state.total_reserves,
@@ -516,11 +1513,122 @@ pub fn query_state(deps: Deps, env: Env, block_height: Option<u64>) -> StdResult
         anc_emission_rate: state.anc_emission_rate,
         prev_aterra_supply: state.prev_aterra_supply,
         prev_exchange_rate: state.prev_exchange_rate,
+        liquidity_ratio: compute_liquidity_ratio(
+            &state,
+            balance - query_deployed_value(deps, &config)?,
+        ),
+        total_deployed,
+        max_total_borrow: config.max_total_borrow,
+    })
+}
+
+pub fn query_reserve_coverage(deps: Deps, env: Env) -> StdResult<ReserveCoverageResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let mut state: State = read_state(deps.storage)?;
+
+    compute_interest(
+        deps,
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+
+    let coverage_ratio = if state.total_liabilities.is_zero() {
+        Decimal256::zero()
+    } else {
+        state.total_reserves / state.total_liabilities
+    };
+
+    Ok(ReserveCoverageResponse {
+        total_reserves: state.total_reserves,
+        total_liabilities: state.total_liabilities,
+        coverage_ratio,
+    })
+}
+
+/// Interest the depositors accrued since the last epoch operation, the
+/// aTerra value growth with the deposit rate capped at the target as the
+/// epoch caps it. None accrues before the first epoch operation
+pub fn query_accrued_interest(deps: Deps, env: Env) -> StdResult<AccruedInterestResponse> {
+    let epoch_interest: EpochInterest = match read_epoch_interest(deps.storage)? {
+        Some(epoch_interest) => epoch_interest,
+        None => {
+            return Ok(AccruedInterestResponse {
+                accrued_interest: Uint256::zero(),
+                last_epoch_height: None,
+            })
+        }
+    };
+
+    let config: Config = read_config(deps.storage)?;
+    let mut state: State = read_state(deps.storage)?;
+    compute_interest(
+        deps,
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+
+    let exchange_rate = compute_exchange_rate(deps, &config, &state, None)?;
+    let accrued_interest = if exchange_rate > epoch_interest.exchange_rate {
+        query_supply(deps, deps.api.addr_humanize(&config.aterra_contract)?)?
+            * (exchange_rate - epoch_interest.exchange_rate)
+    } else {
+        Uint256::zero()
+    };
+
+    Ok(AccruedInterestResponse {
+        accrued_interest,
+        last_epoch_height: Some(epoch_interest.block_height),
+    })
+}
+
+/// Exchange rates come from the snapshots recorded by epoch operations,
+/// so each height resolves to the latest epoch at or before it
+pub fn query_exchange_rate_between(
+    deps: Deps,
+    start_height: u64,
+    end_height: u64,
+) -> StdResult<ExchangeRateBetweenResponse> {
+    if start_height > end_height {
+        return Err(StdError::generic_err(
+            "start_height cannot be greater than end_height",
+        ));
+    }
+
+    let read_snapshot = |height: u64| {
+        read_exchange_rate_snapshot(deps.storage, height)?.ok_or_else(|| {
+            StdError::generic_err(format!(
+                "No exchange rate recorded at or before height {}",
+                height
+            ))
+        })
+    };
+    let (start_snapshot_height, start) = read_snapshot(start_height)?;
+    let (end_snapshot_height, end) = read_snapshot(end_height)?;
+
+    let elapsed = end.block_time - start.block_time;
+    let apr = if elapsed == 0 || end.exchange_rate <= start.exchange_rate {
+        Decimal256::zero()
+    } else {
+        (end.exchange_rate - start.exchange_rate) / start.exchange_rate
+            * Decimal256::from_ratio(SECONDS_PER_YEAR, elapsed)
+    };
+
+    Ok(ExchangeRateBetweenResponse {
+        start_exchange_rate: start.exchange_rate,
+        start_snapshot_height,
+        end_exchange_rate: end.exchange_rate,
+        end_snapshot_height,
+        apr,
     })
 }
 
 pub fn query_epoch_state(
     deps: Deps,
+    env: Env,
     block_height: Option<u64>,
     distributed_interest: Option<Uint256>,
 ) -> StdResult<EpochStateResponse> {
@@ -529,14 +1637,13 @@ pub fn query_epoch_state(
 
     let distributed_interest = distributed_interest.unwrap_or_else(Uint256::zero);
     let aterra_supply = query_supply(deps, deps.api.addr_humanize(&config.aterra_contract)?)?;
-    let balance = query_balance(
-        deps,
-        deps.api.addr_humanize(&config.contract_addr)?,
-        config.stable_denom.to_string(),
-    )? - distributed_interest;
+    let balance = query_market_balance(deps, &config)?
+        - distributed_interest
+        - read_total_repay_credit(deps.storage);
 
     if let Some(block_height) = block_height {
-        if block_height < state.last_interest_updated {
+        let interest_clock = interest_clock(&config, &env, block_height);
+        if interest_clock < state.last_interest_updated {
             return Err(StdError::generic_err(
                 "block_height must bigger than last_interest_updated",
             ));
@@ -556,11 +1663,12 @@ pub fn query_epoch_state(
         // Compute interest rate to return latest epoch state
         compute_interest_raw(
             &mut state,
-            block_height,
+            interest_clock,
             balance,
             aterra_supply,
-            borrow_rate_res.rate,
-            target_deposit_rate,
+            rate_per_clock_unit(&config, borrow_rate_res.rate),
+            rate_per_clock_unit(&config, target_deposit_rate),
+            read_total_interest_free(deps.storage),
         );
     }
 
@@ -574,3 +1682,141 @@ pub fn query_epoch_state(
         aterra_supply,
     })
 }
+
+pub fn query_accrual_params(deps: Deps) -> StdResult<AccrualParamsResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let state: State = read_state(deps.storage)?;
+
+    let aterra_supply = query_supply(deps, deps.api.addr_humanize(&config.aterra_contract)?)?;
+    let balance: Uint256 =
+        query_market_balance(deps, &config)? - read_total_repay_credit(deps.storage);
+    let borrow_rate_res: BorrowRateResponse = query_borrow_rate(
+        deps,
+        deps.api.addr_humanize(&config.interest_model)?,
+        balance,
+        state.total_liabilities,
+        state.total_reserves,
+    )?;
+
+    Ok(AccrualParamsResponse {
+        global_interest_index: state.global_interest_index,
+        last_interest_updated: state.last_interest_updated,
+        total_liabilities: state.total_liabilities,
+        total_reserves: state.total_reserves,
+        aterra_supply,
+        borrow_rate: borrow_rate_res.rate,
+    })
+}
+
+pub fn query_last_accrual(deps: Deps) -> StdResult<LastAccrualResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let state: State = read_state(deps.storage)?;
+    Ok(LastAccrualResponse {
+        last_interest_clock: state.last_interest_updated,
+        accrue_by_time: config.accrue_by_time,
+        last_reward_updated: state.last_reward_updated,
+    })
+}
+
+pub fn query_borrow_apr(deps: Deps) -> StdResult<BorrowAprResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let state: State = read_state(deps.storage)?;
+
+    let balance: Uint256 =
+        query_market_balance(deps, &config)? - read_total_repay_credit(deps.storage);
+    let borrow_rate_res: BorrowRateResponse = query_borrow_rate(
+        deps,
+        deps.api.addr_humanize(&config.interest_model)?,
+        balance,
+        state.total_liabilities,
+        state.total_reserves,
+    )?;
+
+    Ok(BorrowAprResponse {
+        borrow_rate: borrow_rate_res.rate,
+        blocks_per_year: config.blocks_per_year,
+        apr: borrow_rate_res.rate * Decimal256::from_uint256(config.blocks_per_year),
+    })
+}
+
+/// Deposit rate is the interest of the interest bearing liabilities spread
+/// over the deposits, capped at the target deposit rate like the accrual
+pub fn query_deposit_apy(
+    deps: Deps,
+    compounding_periods: Option<u64>,
+) -> StdResult<DepositApyResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let state: State = read_state(deps.storage)?;
+
+    let compounding_periods = compounding_periods.unwrap_or(config.blocks_per_year);
+    if compounding_periods == 0 || compounding_periods > config.blocks_per_year {
+        return Err(StdError::generic_err(format!(
+            "compounding_periods must be between 1 and {}",
+            config.blocks_per_year
+        )));
+    }
+
+    let balance: Uint256 =
+        query_market_balance(deps, &config)? - read_total_repay_credit(deps.storage);
+    let borrow_rate_res: BorrowRateResponse = query_borrow_rate(
+        deps,
+        deps.api.addr_humanize(&config.interest_model)?,
+        balance,
+        state.total_liabilities,
+        state.total_reserves,
+    )?;
+    let target_deposit_rate: Decimal256 =
+        query_target_deposit_rate(deps, deps.api.addr_humanize(&config.overseer_contract)?)?;
+
+    let interest_free_liabilities = read_total_interest_free(deps.storage);
+    let interest_bearing = if state.total_liabilities > interest_free_liabilities {
+        state.total_liabilities - interest_free_liabilities
+    } else {
+        Decimal256::zero()
+    };
+    let deposits = Decimal256::from_uint256(balance) + state.total_liabilities;
+    let deposit_rate = if deposits <= state.total_reserves {
+        Decimal256::zero()
+    } else {
+        std::cmp::min(
+            borrow_rate_res.rate * interest_bearing / (deposits - state.total_reserves),
+            target_deposit_rate,
+        )
+    };
+
+    let period_rate = deposit_rate * Decimal256::from_uint256(config.blocks_per_year)
+        / Decimal256::from_uint256(compounding_periods);
+    let apy =
+        checked_pow(Decimal256::one() + period_rate, compounding_periods)? - Decimal256::one();
+
+    Ok(DepositApyResponse {
+        deposit_rate,
+        compounding_periods,
+        apy,
+    })
+}
+
+/// Exponentiation by squaring, failing before a product
+/// could overflow the 256 bits of the decimal
+fn checked_pow(base: Decimal256, exp: u64) -> StdResult<Decimal256> {
+    let max_factor = Decimal256::from_uint256(Uint256::from(10u64.pow(18)));
+    let overflow = || StdError::generic_err("Compounded rate overflows");
+
+    let mut result = Decimal256::one();
+    let mut base = base;
+    let mut exp = exp;
+    while exp > 0 {
+        if base > max_factor || result > max_factor {
+            return Err(overflow());
+        }
+        if exp % 2 == 1 {
+            result = result * base;
+        }
+        exp /= 2;
+        if exp > 0 {
+            base = base * base;
+        }
+    }
+
+    Ok(result)
+}
@@ -1,15 +1,26 @@
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    attr, to_binary, Addr, BankMsg, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response,
-    StdResult, Uint128, WasmMsg,
+    attr, to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo,
+    Response, StdResult, Storage, SubMsg, Uint128, WasmMsg,
 };
 
-use crate::borrow::{compute_interest, compute_reward};
+use crate::borrow::{compute_interest, compute_reward, interest_clock};
+use crate::contract::STRATEGY_RECALL_REPLY_ID;
 use crate::error::ContractError;
-use crate::state::{read_config, read_state, store_state, Config, State};
+use crate::querier::{query_overseer_config, query_strategy_balance};
+use crate::state::{
+    read_config, read_state, read_strategy_recall, read_total_deployed, read_total_repay_credit,
+    remove_strategy_recall, store_state, store_strategy_recall, store_total_deployed, Config,
+    State,
+};
 
 use cw20::Cw20ExecuteMsg;
-use moneymarket::querier::{deduct_tax, query_balance, query_supply};
+use moneymarket::common::mul_ceil;
+use moneymarket::market::MaxRedeemableResponse;
+use moneymarket::querier::{
+    deduct_tax, query_balance, query_price, query_supply, query_token_balance,
+};
+use moneymarket::strategy::ExecuteMsg as StrategyExecuteMsg;
 
 pub fn deposit_stable(
     deps: DepsMut,
@@ -37,7 +48,7 @@ pub fn deposit_stable(
         deps.as_ref(),
         &config,
         &mut state,
-        env.block.height,
+        interest_clock(&config, &env, env.block.height),
         Some(deposit_amount),
     )?;
     compute_reward(&mut state, env.block.height);
@@ -76,25 +87,54 @@ pub fn redeem_stable(
 
     // Update interest related state
     let mut state: State = read_state(deps.storage)?;
-    compute_interest(deps.as_ref(), &config, &mut state, env.block.height, None)?;
+    compute_interest(
+        deps.as_ref(),
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
     compute_reward(&mut state, env.block.height);
 
     // Load anchor token exchange rate with updated state
     let exchange_rate = compute_exchange_rate(deps.as_ref(), &config, &state, None)?;
     let redeem_amount = Uint256::from(burn_amount) * exchange_rate;
 
-    let current_balance = query_balance(
+    let balance = query_balance(
         deps.as_ref(),
-        env.contract.address,
+        env.contract.address.clone(),
         config.stable_denom.to_string(),
     )?;
+    let current_balance = balance - read_total_repay_credit(deps.storage);
 
     // Assert redeem amount
-    assert_redeem_amount(&config, &state, current_balance, redeem_amount)?;
+    let deployed_value = query_deployed_value(deps.as_ref(), &config)?;
+    let recall_amount = assert_redeem_amount(
+        &config,
+        &state,
+        current_balance,
+        deployed_value,
+        redeem_amount,
+    )?;
 
     state.prev_aterra_supply = state.prev_aterra_supply - Uint256::from(burn_amount);
     store_state(deps.storage, &state)?;
+
+    // The shortfall of the balance is recalled from the yield strategy
+    // before the redeemed amount is sent
+    let mut messages: Vec<SubMsg> = vec![];
+    if !recall_amount.is_zero() {
+        messages.push(recall_message(
+            deps.storage,
+            deps.api,
+            &config,
+            balance,
+            recall_amount,
+        )?);
+    }
+
     Ok(Response::new()
+        .add_submessages(messages)
         .add_messages(vec![
             CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: deps.api.addr_humanize(&config.aterra_contract)?.to_string(),
@@ -118,24 +158,254 @@ pub fn redeem_stable(
             attr("action", "redeem_stable"),
             attr("burn_amount", burn_amount),
             attr("redeem_amount", redeem_amount),
+            attr("recall_amount", recall_amount),
         ]))
 }
 
+/// Returns the amount to recall from the yield strategy
+/// when the balance cannot cover the redemption
 fn assert_redeem_amount(
     config: &Config,
     state: &State,
     current_balance: Uint256,
+    deployed_value: Uint256,
     redeem_amount: Uint256,
-) -> Result<(), ContractError> {
-    let current_balance = Decimal256::from_uint256(current_balance);
-    let redeem_amount = Decimal256::from_uint256(redeem_amount);
-    if redeem_amount + state.total_reserves > current_balance {
+) -> Result<Uint256, ContractError> {
+    let required_balance = Decimal256::from_uint256(redeem_amount) + state.total_reserves;
+    if required_balance > Decimal256::from_uint256(current_balance + deployed_value) {
         return Err(ContractError::NoStableAvailable(
             config.stable_denom.clone(),
         ));
     }
 
-    Ok(())
+    if required_balance <= Decimal256::from_uint256(current_balance) {
+        return Ok(Uint256::zero());
+    }
+
+    let shortfall = required_balance - Decimal256::from_uint256(current_balance);
+    Ok(std::cmp::min(
+        mul_ceil(Uint256::one(), shortfall),
+        deployed_value,
+    ))
+}
+
+pub fn deploy_to_strategy(
+    deps: DepsMut,
+    info: MessageInfo,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let yield_strategy = match &config.yield_strategy {
+        Some(yield_strategy) => deps.api.addr_humanize(yield_strategy)?,
+        None => return Err(ContractError::NoYieldStrategy {}),
+    };
+
+    // The deployed liquidity stays within max_deployed_ratio of the
+    // liquidity, the reserves are never deployed
+    let state: State = read_state(deps.storage)?;
+    let current_balance = query_balance(
+        deps.as_ref(),
+        deps.api.addr_humanize(&config.contract_addr)?,
+        config.stable_denom.to_string(),
+    )? - read_total_repay_credit(deps.storage);
+    let deployed_value = query_strategy_balance(
+        deps.as_ref(),
+        yield_strategy.clone(),
+        deps.api.addr_humanize(&config.contract_addr)?,
+    )?;
+    let max_deployed = (current_balance + deployed_value) * config.max_deployed_ratio;
+    let deployable = std::cmp::min(
+        if max_deployed > deployed_value {
+            max_deployed - deployed_value
+        } else {
+            Uint256::zero()
+        },
+        if current_balance > state.total_reserves * Uint256::one() {
+            current_balance - state.total_reserves * Uint256::one()
+        } else {
+            Uint256::zero()
+        },
+    );
+    if amount > deployable {
+        return Err(ContractError::DeployedRatioExceeded(deployable.into()));
+    }
+
+    // Only the amount the strategy receives after tax is deployed
+    let deposit_coin = deduct_tax(
+        deps.as_ref(),
+        Coin {
+            denom: config.stable_denom,
+            amount: amount.into(),
+        },
+    )?;
+    let deposit_amount = Uint256::from(deposit_coin.amount);
+    let total_deployed = read_total_deployed(deps.storage);
+    store_total_deployed(deps.storage, &(total_deployed + deposit_amount))?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: yield_strategy.to_string(),
+            funds: vec![deposit_coin],
+            msg: to_binary(&StrategyExecuteMsg::Deposit {})?,
+        }))
+        .add_attributes(vec![
+            attr("action", "deploy_to_strategy"),
+            attr("amount", amount),
+            attr("deployed_amount", deposit_amount),
+        ]))
+}
+
+pub fn recall_from_strategy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let deployed_value = query_deployed_value(deps.as_ref(), &config)?;
+    if amount > deployed_value {
+        return Err(ContractError::RecallExceedsDeployed(deployed_value.into()));
+    }
+
+    let balance = query_balance(
+        deps.as_ref(),
+        env.contract.address,
+        config.stable_denom.to_string(),
+    )?;
+    Ok(Response::new()
+        .add_submessage(recall_message(
+            deps.storage,
+            deps.api,
+            &config,
+            balance,
+            amount,
+        )?)
+        .add_attributes(vec![
+            attr("action", "recall_from_strategy"),
+            attr("amount", amount),
+        ]))
+}
+
+/// Withdraws `amount` of the deployed liquidity back to the market,
+/// the reply settles what the strategy sent above `balance`
+fn recall_message(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    config: &Config,
+    balance: Uint256,
+    amount: Uint256,
+) -> Result<SubMsg, ContractError> {
+    let yield_strategy = match &config.yield_strategy {
+        Some(yield_strategy) => api.addr_humanize(yield_strategy)?,
+        None => return Err(ContractError::NoYieldStrategy {}),
+    };
+
+    store_strategy_recall(storage, &balance)?;
+    Ok(SubMsg::reply_on_success(
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: yield_strategy.to_string(),
+            funds: vec![],
+            msg: to_binary(&StrategyExecuteMsg::Withdraw { amount })?,
+        }),
+        STRATEGY_RECALL_REPLY_ID,
+    ))
+}
+
+/// The recalled liquidity is what the strategy actually sent,
+/// it is taken off the deposited liquidity, yield first recalled last
+pub fn recall_from_strategy_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let prev_balance = match read_strategy_recall(deps.storage)? {
+        Some(prev_balance) => prev_balance,
+        None => return Err(ContractError::InvalidReplyId {}),
+    };
+    remove_strategy_recall(deps.storage);
+
+    let config: Config = read_config(deps.storage)?;
+    let recalled_amount = query_balance(
+        deps.as_ref(),
+        env.contract.address,
+        config.stable_denom.to_string(),
+    )? - prev_balance;
+    let total_deployed = read_total_deployed(deps.storage);
+    store_total_deployed(
+        deps.storage,
+        &(total_deployed - std::cmp::min(recalled_amount, total_deployed)),
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "settle_strategy_recall"),
+        attr("recalled_amount", recalled_amount),
+    ]))
+}
+
+/// Value of the liquidity deployed to the yield strategy
+/// as the strategy reports it
+pub(crate) fn query_deployed_value(deps: Deps, config: &Config) -> StdResult<Uint256> {
+    match &config.yield_strategy {
+        Some(yield_strategy) => query_strategy_balance(
+            deps,
+            deps.api.addr_humanize(yield_strategy)?,
+            deps.api.addr_humanize(&config.contract_addr)?,
+        ),
+        None => Ok(Uint256::zero()),
+    }
+}
+
+/// Redeemable amount of the depositor, limited by the balance of the
+/// market after reserves and the liquidity deployed to the yield strategy
+pub fn query_max_redeemable(
+    deps: Deps,
+    env: Env,
+    depositor: Addr,
+) -> StdResult<MaxRedeemableResponse> {
+    let config: Config = read_config(deps.storage)?;
+
+    // Update interest related state as the redemption would
+    let mut state: State = read_state(deps.storage)?;
+    compute_interest(
+        deps,
+        &config,
+        &mut state,
+        interest_clock(&config, &env, env.block.height),
+        None,
+    )?;
+
+    let exchange_rate = compute_exchange_rate(deps, &config, &state, None)?;
+    let aterra_balance = query_token_balance(
+        deps,
+        deps.api.addr_humanize(&config.aterra_contract)?,
+        depositor,
+    )?;
+
+    let current_balance =
+        query_balance(deps, env.contract.address, config.stable_denom.to_string())?
+            - read_total_repay_credit(deps.storage);
+    let liquidity =
+        Decimal256::from_uint256(current_balance + query_deployed_value(deps, &config)?);
+    let available = if liquidity > state.total_reserves {
+        (liquidity - state.total_reserves) * Uint256::one()
+    } else {
+        Uint256::zero()
+    };
+
+    let aterra_amount = if aterra_balance * exchange_rate > available {
+        available / exchange_rate
+    } else {
+        aterra_balance
+    };
+
+    Ok(MaxRedeemableResponse {
+        aterra_amount,
+        stable_amount: aterra_amount * exchange_rate,
+    })
 }
 
 pub(crate) fn compute_exchange_rate(
@@ -145,13 +415,59 @@ pub(crate) fn compute_exchange_rate(
     deposit_amount: Option<Uint256>,
 ) -> StdResult<Decimal256> {
     let aterra_supply = query_supply(deps, deps.api.addr_humanize(&config.aterra_contract)?)?;
-    let balance = query_balance(
+    let balance = query_market_balance(deps, config)?
+        - deposit_amount.unwrap_or_else(Uint256::zero)
+        - read_total_repay_credit(deps.storage);
+
+    Ok(compute_exchange_rate_raw(state, aterra_supply, balance))
+}
+
+/// Stable denom value of one unit of the given accepted repay denom,
+/// at the price of the oracle the overseer uses
+pub(crate) fn repay_denom_rate(deps: Deps, config: &Config, denom: &str) -> StdResult<Decimal256> {
+    if denom == config.stable_denom {
+        return Ok(Decimal256::one());
+    }
+
+    let overseer_config =
+        query_overseer_config(deps, deps.api.addr_humanize(&config.overseer_contract)?)?;
+    let price = query_price(
         deps,
-        deps.api.addr_humanize(&config.contract_addr)?,
+        deps.api.addr_validate(&overseer_config.oracle_contract)?,
+        denom.to_string(),
         config.stable_denom.to_string(),
-    )? - deposit_amount.unwrap_or_else(Uint256::zero);
+        None,
+    )?;
 
-    Ok(compute_exchange_rate_raw(state, aterra_supply, balance))
+    Ok(price.rate)
+}
+
+/// Market balance valued in the stable denom, including the
+/// accepted repay denoms received from repayments and the value
+/// of the liquidity deployed to the yield strategy. A held repay
+/// denom the oracle cannot currently price is left out rather than
+/// failing every balance dependent query
+pub(crate) fn query_market_balance(deps: Deps, config: &Config) -> StdResult<Uint256> {
+    let contract_addr = deps.api.addr_humanize(&config.contract_addr)?;
+    let mut balance = query_balance(deps, contract_addr.clone(), config.stable_denom.to_string())?
+        + query_deployed_value(deps, config)?;
+
+    for denom in config
+        .accepted_repay_denoms
+        .iter()
+        .filter(|d| **d != config.stable_denom)
+    {
+        let denom_balance = query_balance(deps, contract_addr.clone(), denom.to_string())?;
+        if denom_balance.is_zero() {
+            continue;
+        }
+
+        if let Ok(rate) = repay_denom_rate(deps, config, denom) {
+            balance += denom_balance * rate;
+        }
+    }
+
+    Ok(balance)
 }
 
 pub fn compute_exchange_rate_raw(
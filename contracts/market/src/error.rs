@@ -18,6 +18,33 @@ pub enum ContractError {
     #[error("Must deposit initial funds {0}{0}")]
     InitialFundsNotDeposited(u128, String),
 
+    #[error("Repay dust epsilon cannot exceed {0}")]
+    InvalidRepayDustEpsilon(u128),
+
+    #[error("Borrow fee must be lower than 1")]
+    InvalidBorrowFee {},
+
+    #[error("Flash fee must be lower than 1")]
+    InvalidFlashFee {},
+
+    #[error("Repay protocol fee cannot exceed 1")]
+    InvalidRepayProtocolFee {},
+
+    #[error("Commitment fee must be lower than 1")]
+    InvalidCommitmentFee {},
+
+    #[error("Accepted repay denoms must include the stable denom")]
+    InvalidAcceptedRepayDenoms {},
+
+    #[error("Repay denom has no oracle price: {0}")]
+    UnpricedRepayDenom(String),
+
+    #[error("Repay denom is not accepted: {0}")]
+    UnacceptedRepayDenom(String),
+
+    #[error("Blocks per year must be greater than 0")]
+    InvalidBlocksPerYear {},
+
     #[error("Invalid reply ID")]
     InvalidReplyId {},
 
@@ -27,6 +54,9 @@ pub enum ContractError {
     #[error("Invalid request: \"redeem stable\" message not included in request")]
     MissingRedeemStableHook {},
 
+    #[error("Market liquidity is below the minimum ratio; borrows are disabled")]
+    LiquidityBelowMinimum {},
+
     #[error("Not enough {0} available; borrow demand too high")]
     NoStableAvailable(String),
 
@@ -35,4 +65,85 @@ pub enum ContractError {
 
     #[error("Repay amount must be greater than 0 {0}")]
     ZeroRepay(String),
+
+    #[error("Withdraw amount must be positive and within the repay credit {0}")]
+    InvalidRepayCreditWithdraw(u128),
+
+    #[error("Refinance funds must match the repay amount {0}{1}")]
+    InvalidRefinanceFunds(u128, String),
+
+    #[error("Repay amount exceeds the loan amount {0}")]
+    RepayExceedsLoan(u128),
+
+    #[error("Repay amount is below the minimum {0} and does not close the loan")]
+    RepayBelowMinimum(u128),
+
+    #[error("Deploy amount exceeds the reserves {0}")]
+    ReservesOverdrawn(u128),
+
+    #[error("Reserve route is not configured")]
+    NoReserveRoute {},
+
+    #[error("The reserve route delivered nothing")]
+    NoReservesConverted {},
+
+    #[error("A flash deleverage is in progress")]
+    FlashDeleverageInProgress {},
+
+    #[error("Flash deleverage was not paid back; short by {0}")]
+    FlashDeleverageShortfall(u128),
+
+    #[error("Target leverage must be greater than 1")]
+    InvalidTargetLeverage {},
+
+    #[error("Slippage must be lower than 1")]
+    InvalidSlippage {},
+
+    #[error("Collateral {0} must be locked in the position to lever it")]
+    LeverageCollateralNotLocked(String),
+
+    #[error("Position has no collateral value left over its loan")]
+    LeverageUnderwater {},
+
+    #[error("Collateral conversion exceeds the slippage; received {0}")]
+    LeverageSlippageExceeded(u128),
+
+    #[error("Yield strategy is not configured")]
+    NoYieldStrategy {},
+
+    #[error("Max deployed ratio cannot exceed 1")]
+    InvalidMaxDeployedRatio {},
+
+    #[error("Rate discount cannot exceed 1")]
+    InvalidRateDiscount {},
+
+    #[error("Deploy amount exceeds the max deployed ratio; deployable amount: {0}")]
+    DeployedRatioExceeded(u128),
+
+    #[error("Recall amount exceeds the deployed amount {0}")]
+    RecallExceedsDeployed(u128),
+
+    #[error("No credit line is open")]
+    NoCreditLine {},
+
+    #[error("Credit lines cannot be opened without a commitment fee")]
+    CreditLinesDisabled {},
+
+    #[error("Accrued commitment fee of {0} must be paid")]
+    CommitmentFeeUnpaid(u128),
+
+    #[error("Draw amount exceeds the undrawn commitment {0}")]
+    DrawExceedsCreditLine(u128),
+
+    #[error("Draw amount cannot cover the accrued commitment fee {0}")]
+    CreditDrawBelowFee(u128),
+
+    #[error("Borrow exceeds the {0} ceiling of the total liabilities")]
+    MaxTotalBorrowReached(u128),
+
+    #[error("Same block repay fee cannot exceed 1")]
+    InvalidSameBlockRepayFee {},
+
+    #[error("Cannot repay in the block of the last borrow")]
+    SameBlockRepay {},
 }
@@ -1,9 +1,18 @@
 use cosmwasm_bignumber::{Decimal256, Uint256};
-use cosmwasm_std::{to_binary, Addr, Deps, QueryRequest, StdResult, WasmQuery};
+use cosmwasm_std::{to_binary, Addr, Deps, QueryRequest, StdError, StdResult, WasmQuery};
 
+use moneymarket::converter::{QueryMsg as ConverterQueryMsg, SimulationResponse};
+use moneymarket::custody::{
+    BorrowerResponse as CustodyBorrowerResponse, QueryMsg as CustodyQueryMsg,
+};
 use moneymarket::distribution_model::{AncEmissionRateResponse, QueryMsg as DistributionQueryMsg};
 use moneymarket::interest_model::{BorrowRateResponse, QueryMsg as InterestQueryMsg};
-use moneymarket::overseer::{BorrowLimitResponse, ConfigResponse, QueryMsg as OverseerQueryMsg};
+use moneymarket::overseer::{
+    BorrowLimitResponse, ConfigResponse, PositionResponse, QueryMsg as OverseerQueryMsg,
+    WhitelistResponse,
+};
+use moneymarket::staking::{QueryMsg as StakingQueryMsg, StakerTierResponse};
+use moneymarket::strategy::{DepositorBalanceResponse, QueryMsg as StrategyQueryMsg};
 
 pub fn query_borrow_rate(
     deps: Deps,
@@ -19,6 +28,7 @@ pub fn query_borrow_rate(
                 market_balance,
                 total_liabilities,
                 total_reserves,
+                precision: None,
             })?,
         }))?;
 
@@ -30,6 +40,7 @@ pub fn query_borrow_limit(
     overseer_addr: Addr,
     borrower: Addr,
     block_time: Option<u64>,
+    sub_account: Option<String>,
 ) -> StdResult<BorrowLimitResponse> {
     let borrow_limit: BorrowLimitResponse =
         deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
@@ -37,12 +48,31 @@ pub fn query_borrow_limit(
             msg: to_binary(&OverseerQueryMsg::BorrowLimit {
                 borrower: borrower.to_string(),
                 block_time,
+                sub_account,
+                price_mode: None,
+                quote_denom: None,
             })?,
         }))?;
 
     Ok(borrow_limit)
 }
 
+pub fn query_position(
+    deps: Deps,
+    overseer_addr: Addr,
+    borrower: Addr,
+) -> StdResult<PositionResponse> {
+    let position: PositionResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: overseer_addr.to_string(),
+        msg: to_binary(&OverseerQueryMsg::Position {
+            borrower: borrower.to_string(),
+            sub_account: None,
+        })?,
+    }))?;
+
+    Ok(position)
+}
+
 pub fn query_anc_emission_rate(
     deps: Deps,
     distribution_model: Addr,
@@ -65,12 +95,136 @@ pub fn query_anc_emission_rate(
     Ok(anc_emission_rate)
 }
 
+pub fn query_overseer_config(deps: Deps, overseer_contract: Addr) -> StdResult<ConfigResponse> {
+    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: overseer_contract.to_string(),
+        msg: to_binary(&OverseerQueryMsg::Config {})?,
+    }))
+}
+
 pub fn query_target_deposit_rate(deps: Deps, overseer_contract: Addr) -> StdResult<Decimal256> {
-    let overseer_config: ConfigResponse =
+    let overseer_config: ConfigResponse = query_overseer_config(deps, overseer_contract)?;
+
+    Ok(overseer_config.target_deposit_rate)
+}
+
+pub fn query_custody_contract(
+    deps: Deps,
+    overseer_contract: Addr,
+    collateral_token: Addr,
+) -> StdResult<Addr> {
+    let whitelist: WhitelistResponse =
         deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
             contract_addr: overseer_contract.to_string(),
-            msg: to_binary(&OverseerQueryMsg::Config {})?,
+            msg: to_binary(&OverseerQueryMsg::Whitelist {
+                collateral_token: Some(collateral_token.to_string()),
+                start_after: None,
+                limit: Some(1),
+            })?,
         }))?;
 
-    Ok(overseer_config.target_deposit_rate)
+    match whitelist.elems.first() {
+        Some(elem) => deps.api.addr_validate(&elem.custody_contract),
+        None => Err(StdError::generic_err("Collateral is not whitelisted")),
+    }
+}
+
+/// Custody contracts of the collateral with their weights,
+/// in the order the overseer splits the locked amounts
+pub fn query_custody_contracts(
+    deps: Deps,
+    overseer_contract: Addr,
+    collateral_token: Addr,
+) -> StdResult<Vec<(Addr, Decimal256)>> {
+    let whitelist: WhitelistResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: overseer_contract.to_string(),
+            msg: to_binary(&OverseerQueryMsg::Whitelist {
+                collateral_token: Some(collateral_token.to_string()),
+                start_after: None,
+                limit: Some(1),
+            })?,
+        }))?;
+
+    match whitelist.elems.first() {
+        Some(elem) => elem
+            .custody_contracts
+            .iter()
+            .map(|(custody_contract, weight)| {
+                Ok((deps.api.addr_validate(custody_contract)?, *weight))
+            })
+            .collect(),
+        None => Err(StdError::generic_err("Collateral is not whitelisted")),
+    }
+}
+
+/// Collateral the custody contract holds for the borrower
+pub fn query_custody_balance(
+    deps: Deps,
+    custody_contract: Addr,
+    borrower: Addr,
+) -> StdResult<Uint256> {
+    let borrower: CustodyBorrowerResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: custody_contract.to_string(),
+            msg: to_binary(&CustodyQueryMsg::Borrower {
+                address: borrower.to_string(),
+            })?,
+        }))?;
+
+    Ok(borrower.balance)
+}
+
+/// Value of the liquidity the market deployed to the yield strategy
+pub fn query_strategy_balance(
+    deps: Deps,
+    yield_strategy: Addr,
+    depositor: Addr,
+) -> StdResult<Uint256> {
+    let res: DepositorBalanceResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: yield_strategy.to_string(),
+            msg: to_binary(&StrategyQueryMsg::DepositorBalance {
+                depositor: depositor.to_string(),
+            })?,
+        }))?;
+
+    Ok(res.balance)
+}
+
+pub fn query_route_simulation(
+    deps: Deps,
+    route: Addr,
+    offer_token: String,
+    ask_token: Addr,
+    amount: Uint256,
+) -> StdResult<Uint256> {
+    let simulation: SimulationResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: route.to_string(),
+            msg: to_binary(&ConverterQueryMsg::Simulation {
+                offer_token,
+                ask_token: ask_token.to_string(),
+                amount,
+            })?,
+        }))?;
+
+    Ok(simulation.amount)
+}
+
+/// A failing staking contract grants no discount,
+/// so it cannot block repayments and liquidations
+pub fn query_staker_tier(deps: Deps, staking_addr: Addr, staker: Addr) -> StdResult<u32> {
+    let tier = deps
+        .querier
+        .query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: staking_addr.to_string(),
+            msg: to_binary(&StakingQueryMsg::StakerTier {
+                staker: staker.to_string(),
+            })?,
+        }))
+        .map(|res: StakerTierResponse| res.tier)
+        .unwrap_or(0);
+
+    Ok(tier)
 }
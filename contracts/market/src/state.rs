@@ -2,15 +2,37 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
-use cosmwasm_std::{CanonicalAddr, Deps, Order, StdResult, Storage};
-use cosmwasm_storage::{bucket, bucket_read, ReadonlyBucket, ReadonlySingleton, Singleton};
+use cosmwasm_std::{CanonicalAddr, Deps, Order, StdError, StdResult, Storage};
+use cosmwasm_storage::{
+    bucket, bucket_read, to_length_prefixed, Bucket, ReadonlyBucket, ReadonlySingleton, Singleton,
+};
 
-use moneymarket::market::BorrowerInfoResponse;
+use moneymarket::market::{BorrowerInfoResponse, SameBlockRepayPolicy};
 
 pub const KEY_CONFIG: &[u8] = b"config";
 pub const KEY_STATE: &[u8] = b"state";
+const KEY_EPOCH_INTEREST: &[u8] = b"epoch_interest";
+const KEY_TOTAL_INTEREST_FREE: &[u8] = b"total_interest_free";
+const KEY_TOTAL_REPAY_CREDIT: &[u8] = b"total_repay_credit";
+const KEY_FLASH_DELEVERAGE: &[u8] = b"flash_deleverage";
+const KEY_TOTAL_DEPLOYED: &[u8] = b"total_deployed";
+const KEY_TOTAL_COMMITTED: &[u8] = b"total_committed";
+const KEY_RESERVE_CONVERSION: &[u8] = b"reserve_conversion";
+const KEY_STRATEGY_RECALL: &[u8] = b"strategy_recall";
+const KEY_LEVERAGE_STEP: &[u8] = b"leverage_step";
 
 const PREFIX_LIABILITY: &[u8] = b"liability";
+const PREFIX_SUB_ACCOUNT_LIABILITY: &[u8] = b"sub_account_liability";
+const PREFIX_EXCHANGE_RATE_HISTORY: &[u8] = b"exchange_rate_history";
+const PREFIX_INTEREST_FREE_ALLOWANCE: &[u8] = b"interest_free_allowance";
+const PREFIX_INTEREST_FREE_LOAN: &[u8] = b"interest_free_loan";
+const PREFIX_REPAY_CREDIT: &[u8] = b"repay_credit";
+const PREFIX_LOAN_PRINCIPAL: &[u8] = b"loan_principal";
+const PREFIX_SUB_ACCOUNT_LOAN_PRINCIPAL: &[u8] = b"sub_account_loan_principal";
+const PREFIX_CREDIT_LINE: &[u8] = b"credit_line";
+const PREFIX_LAST_BORROW_HEIGHT: &[u8] = b"last_borrow_height";
+const PREFIX_BORROW_RATE_PREMIUM: &[u8] = b"borrow_rate_premium";
+const PREFIX_SUB_ACCOUNT_BORROW_RATE_PREMIUM: &[u8] = b"sub_account_borrow_rate_premium";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
@@ -24,6 +46,24 @@ pub struct Config {
     pub distributor_contract: CanonicalAddr,
     pub stable_denom: String,
     pub max_borrow_factor: Decimal256,
+    pub min_liquidity_ratio: Decimal256,
+    pub repay_dust_epsilon: Uint256,
+    pub blocks_per_year: u64,
+    pub borrow_fee: Decimal256,
+    pub accrual_block_threshold: u64,
+    pub accrue_by_time: bool,
+    pub reserve_route: Option<CanonicalAddr>,
+    pub flash_fee: Decimal256,
+    pub accepted_repay_denoms: Vec<String>,
+    pub yield_strategy: Option<CanonicalAddr>,
+    pub max_deployed_ratio: Decimal256,
+    pub rate_discount_source: Option<CanonicalAddr>,
+    pub rate_discount_tiers: Vec<(u32, Decimal256)>,
+    pub min_repay_amount: Uint256,
+    pub repay_protocol_fee: Decimal256,
+    pub commitment_fee: Decimal256,
+    pub max_total_borrow: Option<Uint256>,
+    pub same_block_repay_policy: SameBlockRepayPolicy,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -39,6 +79,50 @@ pub struct State {
     pub prev_exchange_rate: Decimal256,
 }
 
+/// Exchange rate the last epoch operation left the depositors with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochInterest {
+    pub exchange_rate: Decimal256,
+    pub block_height: u64,
+}
+
+/// Exchange rate recorded by an epoch operation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateSnapshot {
+    pub exchange_rate: Decimal256,
+    pub block_time: u64,
+}
+
+/// Flash deleverage waiting for its settlement, only ever
+/// stored within the transaction that opened it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingFlashDeleverage {
+    pub borrower: CanonicalAddr,
+    pub prev_balance: Uint256,
+    pub borrow_amount: Uint256,
+    pub fee: Uint256,
+}
+
+/// Reserves sent through the reserve route, waiting for the reply measuring
+/// what the route delivered above `prev_balance`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingReserveConversion {
+    pub collateral_token: CanonicalAddr,
+    pub prev_balance: Uint256,
+}
+
+/// Leverage step waiting for the reserve route to deliver the collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingLeverageStep {
+    pub borrower: CanonicalAddr,
+    pub collateral_token: CanonicalAddr,
+    pub prev_balance: Uint256,
+    pub min_receive: Uint256,
+    pub target_leverage: Decimal256,
+    pub iterations_left: u32,
+    pub slippage: Decimal256,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct BorrowerInfo {
     pub interest_index: Decimal256,
@@ -63,16 +147,78 @@ pub fn read_state(storage: &dyn Storage) -> StdResult<State> {
     ReadonlySingleton::new(storage, KEY_STATE).load()
 }
 
+pub fn store_epoch_interest(storage: &mut dyn Storage, data: &EpochInterest) -> StdResult<()> {
+    Singleton::new(storage, KEY_EPOCH_INTEREST).save(data)
+}
+
+pub fn read_epoch_interest(storage: &dyn Storage) -> StdResult<Option<EpochInterest>> {
+    ReadonlySingleton::new(storage, KEY_EPOCH_INTEREST).may_load()
+}
+
+pub fn store_exchange_rate_snapshot(
+    storage: &mut dyn Storage,
+    block_height: u64,
+    snapshot: &ExchangeRateSnapshot,
+) -> StdResult<()> {
+    bucket(storage, PREFIX_EXCHANGE_RATE_HISTORY).save(&block_height.to_be_bytes(), snapshot)
+}
+
+/// Latest snapshot recorded at or before the given height
+pub fn read_exchange_rate_snapshot(
+    storage: &dyn Storage,
+    block_height: u64,
+) -> StdResult<Option<(u64, ExchangeRateSnapshot)>> {
+    let history_bucket: ReadonlyBucket<ExchangeRateSnapshot> =
+        bucket_read(storage, PREFIX_EXCHANGE_RATE_HISTORY);
+
+    let end = (block_height + 1).to_be_bytes();
+    let snapshot = history_bucket
+        .range(None, Some(&end), Order::Descending)
+        .next()
+        .map(|elem| {
+            let (k, v) = elem?;
+            let mut height = [0u8; 8];
+            height.copy_from_slice(&k);
+            Ok((u64::from_be_bytes(height), v))
+        })
+        .transpose();
+
+    snapshot
+}
+
 pub fn store_borrower_info(
     storage: &mut dyn Storage,
     borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
     liability: &BorrowerInfo,
 ) -> StdResult<()> {
-    bucket(storage, PREFIX_LIABILITY).save(borrower.as_slice(), liability)
+    match sub_account {
+        Some(sub_account) => Bucket::multilevel(
+            storage,
+            &[PREFIX_SUB_ACCOUNT_LIABILITY, borrower.as_slice()],
+        )
+        .save(sub_account.as_bytes(), liability),
+        None => bucket(storage, PREFIX_LIABILITY).save(borrower.as_slice(), liability),
+    }
 }
 
-pub fn read_borrower_info(storage: &dyn Storage, borrower: &CanonicalAddr) -> BorrowerInfo {
-    match bucket_read(storage, PREFIX_LIABILITY).load(borrower.as_slice()) {
+/// Sub-account liabilities are kept out of the default liability bucket,
+/// in one bucket per borrower keyed by the sub-account name
+pub fn read_borrower_info(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+) -> BorrowerInfo {
+    let liability = match sub_account {
+        Some(sub_account) => ReadonlyBucket::multilevel(
+            storage,
+            &[PREFIX_SUB_ACCOUNT_LIABILITY, borrower.as_slice()],
+        )
+        .load(sub_account.as_bytes()),
+        None => bucket_read(storage, PREFIX_LIABILITY).load(borrower.as_slice()),
+    };
+
+    match liability {
         Ok(v) => v,
         _ => BorrowerInfo {
             interest_index: Decimal256::one(),
@@ -83,35 +229,429 @@ pub fn read_borrower_info(storage: &dyn Storage, borrower: &CanonicalAddr) -> Bo
     }
 }
 
+pub fn store_interest_free_allowance(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+    amount: &Uint256,
+) -> StdResult<()> {
+    Bucket::multilevel(
+        storage,
+        &[PREFIX_INTEREST_FREE_ALLOWANCE, borrower.as_slice()],
+    )
+    .save(collateral_token.as_slice(), amount)
+}
+
+pub fn remove_interest_free_allowance(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+) {
+    let mut allowance_bucket: Bucket<Uint256> = Bucket::multilevel(
+        storage,
+        &[PREFIX_INTEREST_FREE_ALLOWANCE, borrower.as_slice()],
+    );
+    allowance_bucket.remove(collateral_token.as_slice());
+}
+
+/// Sum of the allowances granted to the borrower over all collaterals
+pub fn read_interest_free_allowance(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+) -> StdResult<Uint256> {
+    let allowance_bucket: ReadonlyBucket<Uint256> = ReadonlyBucket::multilevel(
+        storage,
+        &[PREFIX_INTEREST_FREE_ALLOWANCE, borrower.as_slice()],
+    );
+
+    let allowance = allowance_bucket
+        .range(None, None, Order::Ascending)
+        .try_fold(Uint256::zero(), |acc, elem| {
+            let (_, amount) = elem?;
+            Ok(acc + amount)
+        });
+
+    allowance
+}
+
+/// Portion of the borrower loan currently covered by its allowance
+pub fn store_interest_free_loan(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    amount: &Uint256,
+) -> StdResult<()> {
+    bucket(storage, PREFIX_INTEREST_FREE_LOAN).save(borrower.as_slice(), amount)
+}
+
+pub fn read_interest_free_loan(storage: &dyn Storage, borrower: &CanonicalAddr) -> Uint256 {
+    bucket_read(storage, PREFIX_INTEREST_FREE_LOAN)
+        .load(borrower.as_slice())
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+/// Liabilities excluded from the interest accrual
+pub fn store_total_interest_free(storage: &mut dyn Storage, data: &Decimal256) -> StdResult<()> {
+    Singleton::new(storage, KEY_TOTAL_INTEREST_FREE).save(data)
+}
+
+pub fn read_total_interest_free(storage: &dyn Storage) -> Decimal256 {
+    ReadonlySingleton::new(storage, KEY_TOTAL_INTEREST_FREE)
+        .load()
+        .unwrap_or_else(|_| Decimal256::zero())
+}
+
+/// Borrowed amount of the position not repaid yet, its loan is
+/// this principal plus the accrued interest
+pub fn store_loan_principal(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    amount: &Uint256,
+) -> StdResult<()> {
+    match sub_account {
+        Some(sub_account) => Bucket::multilevel(
+            storage,
+            &[PREFIX_SUB_ACCOUNT_LOAN_PRINCIPAL, borrower.as_slice()],
+        )
+        .save(sub_account.as_bytes(), amount),
+        None => bucket(storage, PREFIX_LOAN_PRINCIPAL).save(borrower.as_slice(), amount),
+    }
+}
+
+/// None for loans borrowed before the principal was recorded
+pub fn read_loan_principal(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+) -> StdResult<Option<Uint256>> {
+    match sub_account {
+        Some(sub_account) => ReadonlyBucket::multilevel(
+            storage,
+            &[PREFIX_SUB_ACCOUNT_LOAN_PRINCIPAL, borrower.as_slice()],
+        )
+        .may_load(sub_account.as_bytes()),
+        None => bucket_read(storage, PREFIX_LOAN_PRINCIPAL).may_load(borrower.as_slice()),
+    }
+}
+
+/// Borrow rate premium of the collaterals backing a position,
+/// refreshed on every borrow of the position
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BorrowRatePremium {
+    /// Per-block rate added to the borrow rate of the loan
+    pub rate: Decimal256,
+    /// Interest clock the premium was last accrued at
+    pub last_accrued: u64,
+}
+
+pub fn store_borrow_rate_premium(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    premium: &BorrowRatePremium,
+) -> StdResult<()> {
+    match sub_account {
+        Some(sub_account) => Bucket::multilevel(
+            storage,
+            &[PREFIX_SUB_ACCOUNT_BORROW_RATE_PREMIUM, borrower.as_slice()],
+        )
+        .save(sub_account.as_bytes(), premium),
+        None => bucket(storage, PREFIX_BORROW_RATE_PREMIUM).save(borrower.as_slice(), premium),
+    }
+}
+
+pub fn read_borrow_rate_premium(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+) -> StdResult<Option<BorrowRatePremium>> {
+    match sub_account {
+        Some(sub_account) => ReadonlyBucket::multilevel(
+            storage,
+            &[PREFIX_SUB_ACCOUNT_BORROW_RATE_PREMIUM, borrower.as_slice()],
+        )
+        .may_load(sub_account.as_bytes()),
+        None => bucket_read(storage, PREFIX_BORROW_RATE_PREMIUM).may_load(borrower.as_slice()),
+    }
+}
+
+/// Applies `update` to every stored borrow rate premium, sub-account ones included
+pub fn update_borrow_rate_premiums(
+    storage: &mut dyn Storage,
+    update: impl Fn(&mut BorrowRatePremium),
+) -> StdResult<()> {
+    for prefix in [
+        PREFIX_BORROW_RATE_PREMIUM,
+        PREFIX_SUB_ACCOUNT_BORROW_RATE_PREMIUM,
+    ] {
+        // Nested sub-account keys range under the first level of their namespace
+        let premiums: Vec<(Vec<u8>, BorrowRatePremium)> = ReadonlyBucket::new(storage, prefix)
+            .range(None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+
+        let mut premium_bucket: Bucket<BorrowRatePremium> = Bucket::new(storage, prefix);
+        for (key, mut premium) in premiums {
+            update(&mut premium);
+            premium_bucket.save(&key, &premium)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Block height of the last borrow of the borrower in any of its positions
+pub fn store_last_borrow_height(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    block_height: u64,
+) -> StdResult<()> {
+    bucket(storage, PREFIX_LAST_BORROW_HEIGHT).save(borrower.as_slice(), &block_height)
+}
+
+pub fn read_last_borrow_height(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+) -> StdResult<Option<u64>> {
+    bucket_read(storage, PREFIX_LAST_BORROW_HEIGHT).may_load(borrower.as_slice())
+}
+
+/// Over-repaid amount held for the borrower to pay its future interest
+pub fn store_repay_credit(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    amount: &Uint256,
+) -> StdResult<()> {
+    bucket(storage, PREFIX_REPAY_CREDIT).save(borrower.as_slice(), amount)
+}
+
+pub fn read_repay_credit(storage: &dyn Storage, borrower: &CanonicalAddr) -> Uint256 {
+    bucket_read(storage, PREFIX_REPAY_CREDIT)
+        .load(borrower.as_slice())
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+/// Stable balance of the contract held as repay credits,
+/// which is not part of the market liquidity
+pub fn store_total_repay_credit(storage: &mut dyn Storage, data: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_TOTAL_REPAY_CREDIT).save(data)
+}
+
+pub fn read_total_repay_credit(storage: &dyn Storage) -> Uint256 {
+    ReadonlySingleton::new(storage, KEY_TOTAL_REPAY_CREDIT)
+        .load()
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+/// Liquidity deposited in the yield strategy and not recalled yet,
+/// the strategy reports what the deployment is worth
+pub fn store_total_deployed(storage: &mut dyn Storage, data: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_TOTAL_DEPLOYED).save(data)
+}
+
+pub fn read_total_deployed(storage: &dyn Storage) -> Uint256 {
+    ReadonlySingleton::new(storage, KEY_TOTAL_DEPLOYED)
+        .load()
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+/// Borrow capacity committed to a borrower and not drawn yet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreditLine {
+    pub committed: Uint256,
+    /// Commitment fee accrued on the undrawn commitment, not paid yet
+    pub accrued_fee: Uint256,
+    pub last_accrued_height: u64,
+}
+
+pub fn store_credit_line(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    credit_line: &CreditLine,
+) -> StdResult<()> {
+    bucket(storage, PREFIX_CREDIT_LINE).save(borrower.as_slice(), credit_line)
+}
+
+pub fn read_credit_line(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+) -> StdResult<Option<CreditLine>> {
+    bucket_read(storage, PREFIX_CREDIT_LINE).may_load(borrower.as_slice())
+}
+
+pub fn remove_credit_line(storage: &mut dyn Storage, borrower: &CanonicalAddr) {
+    let mut credit_lines: Bucket<CreditLine> = bucket(storage, PREFIX_CREDIT_LINE);
+    credit_lines.remove(borrower.as_slice());
+}
+
+/// Undrawn commitment of all credit lines, reserved from the borrow capacity
+pub fn store_total_committed(storage: &mut dyn Storage, data: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_TOTAL_COMMITTED).save(data)
+}
+
+pub fn read_total_committed(storage: &dyn Storage) -> Uint256 {
+    ReadonlySingleton::new(storage, KEY_TOTAL_COMMITTED)
+        .load()
+        .unwrap_or_else(|_| Uint256::zero())
+}
+
+pub fn store_flash_deleverage(
+    storage: &mut dyn Storage,
+    data: &PendingFlashDeleverage,
+) -> StdResult<()> {
+    Singleton::new(storage, KEY_FLASH_DELEVERAGE).save(data)
+}
+
+pub fn read_flash_deleverage(storage: &dyn Storage) -> StdResult<Option<PendingFlashDeleverage>> {
+    ReadonlySingleton::new(storage, KEY_FLASH_DELEVERAGE).may_load()
+}
+
+pub fn remove_flash_deleverage(storage: &mut dyn Storage) {
+    Singleton::<PendingFlashDeleverage>::new(storage, KEY_FLASH_DELEVERAGE).remove()
+}
+
+pub fn store_reserve_conversion(
+    storage: &mut dyn Storage,
+    data: &PendingReserveConversion,
+) -> StdResult<()> {
+    Singleton::new(storage, KEY_RESERVE_CONVERSION).save(data)
+}
+
+pub fn read_reserve_conversion(
+    storage: &dyn Storage,
+) -> StdResult<Option<PendingReserveConversion>> {
+    ReadonlySingleton::new(storage, KEY_RESERVE_CONVERSION).may_load()
+}
+
+pub fn remove_reserve_conversion(storage: &mut dyn Storage) {
+    Singleton::<PendingReserveConversion>::new(storage, KEY_RESERVE_CONVERSION).remove()
+}
+
+/// Balance of the market before a recall from the yield strategy,
+/// the recall reply settles what the strategy sent above it
+pub fn store_strategy_recall(storage: &mut dyn Storage, prev_balance: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_STRATEGY_RECALL).save(prev_balance)
+}
+
+pub fn read_strategy_recall(storage: &dyn Storage) -> StdResult<Option<Uint256>> {
+    ReadonlySingleton::new(storage, KEY_STRATEGY_RECALL).may_load()
+}
+
+pub fn remove_strategy_recall(storage: &mut dyn Storage) {
+    Singleton::<Uint256>::new(storage, KEY_STRATEGY_RECALL).remove()
+}
+
+pub fn store_leverage_step(storage: &mut dyn Storage, data: &PendingLeverageStep) -> StdResult<()> {
+    Singleton::new(storage, KEY_LEVERAGE_STEP).save(data)
+}
+
+pub fn read_leverage_step(storage: &dyn Storage) -> StdResult<Option<PendingLeverageStep>> {
+    ReadonlySingleton::new(storage, KEY_LEVERAGE_STEP).may_load()
+}
+
+pub fn remove_leverage_step(storage: &mut dyn Storage) {
+    Singleton::<PendingLeverageStep>::new(storage, KEY_LEVERAGE_STEP).remove()
+}
+
 // settings for pagination
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
+/// Pages over the borrowers with a default or a sub-account liability,
+/// each listed with its default position first
 pub fn read_borrower_infos(
     deps: Deps,
     start_after: Option<CanonicalAddr>,
     limit: Option<u32>,
 ) -> StdResult<Vec<BorrowerInfoResponse>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let liability_bucket: ReadonlyBucket<BorrowerInfo> =
         bucket_read(deps.storage, PREFIX_LIABILITY);
 
-    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let start = calc_range_start(start_after);
+    let mut borrower_infos: Vec<BorrowerInfoResponse> = vec![];
+    for borrower_raw in read_liability_borrowers(deps.storage, start_after, limit)? {
+        let borrower = deps.api.addr_humanize(&borrower_raw)?.to_string();
+        let interest_free_allowance = read_interest_free_allowance(deps.storage, &borrower_raw)?;
+        let repay_credit = read_repay_credit(deps.storage, &borrower_raw);
 
-    liability_bucket
-        .range(start.as_deref(), None, Order::Ascending)
-        .take(limit)
-        .map(|elem| {
+        let mut positions: Vec<(Option<String>, BorrowerInfo)> = liability_bucket
+            .may_load(borrower_raw.as_slice())?
+            .map(|v| (None, v))
+            .into_iter()
+            .collect();
+        let sub_account_bucket: ReadonlyBucket<BorrowerInfo> = ReadonlyBucket::multilevel(
+            deps.storage,
+            &[PREFIX_SUB_ACCOUNT_LIABILITY, borrower_raw.as_slice()],
+        );
+        for elem in sub_account_bucket.range(None, None, Order::Ascending) {
             let (k, v) = elem?;
-            let borrower = deps.api.addr_humanize(&CanonicalAddr::from(k))?.to_string();
-            Ok(BorrowerInfoResponse {
-                borrower,
-                interest_index: v.interest_index,
-                reward_index: v.reward_index,
-                loan_amount: v.loan_amount,
-                pending_rewards: v.pending_rewards,
-            })
-        })
-        .collect()
+            let sub_account = String::from_utf8(k)
+                .map_err(|_| StdError::generic_err("Invalid sub account key"))?;
+            positions.push((Some(sub_account), v));
+        }
+
+        borrower_infos.extend(
+            positions
+                .into_iter()
+                .map(|(sub_account, v)| BorrowerInfoResponse {
+                    borrower: borrower.clone(),
+                    sub_account,
+                    interest_index: v.interest_index,
+                    reward_index: v.reward_index,
+                    loan_amount: v.loan_amount,
+                    pending_rewards: v.pending_rewards,
+                    interest_free_allowance,
+                    repay_credit,
+                }),
+        );
+    }
+
+    Ok(borrower_infos)
+}
+
+/// Reads a page of the borrowers with a default or a sub-account liability,
+/// ordered by address. Sub-account keys start with the length-prefixed borrower
+fn read_liability_borrowers(
+    storage: &dyn Storage,
+    start_after: Option<CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let liability_bucket: ReadonlyBucket<BorrowerInfo> = bucket_read(storage, PREFIX_LIABILITY);
+    let mut borrowers: Vec<CanonicalAddr> = liability_bucket
+        .range(
+            calc_range_start(start_after.clone()).as_deref(),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|elem| Ok(CanonicalAddr::from(elem?.0)))
+        .collect::<StdResult<Vec<CanonicalAddr>>>()?;
+
+    let sub_account_bucket: ReadonlyBucket<BorrowerInfo> =
+        bucket_read(storage, PREFIX_SUB_ACCOUNT_LIABILITY);
+    let sub_account_start = start_after
+        .as_ref()
+        .map(|borrower| to_length_prefixed(borrower.as_slice()));
+    let mut sub_account_borrowers: Vec<CanonicalAddr> = vec![];
+    for elem in sub_account_bucket.range(sub_account_start.as_deref(), None, Order::Ascending) {
+        let (k, _) = elem?;
+        let len = u16::from_be_bytes([k[0], k[1]]) as usize;
+        let borrower = CanonicalAddr::from(k[2..2 + len].to_vec());
+        if start_after.as_ref() == Some(&borrower)
+            || sub_account_borrowers.last() == Some(&borrower)
+        {
+            continue;
+        }
+        if sub_account_borrowers.len() == limit {
+            break;
+        }
+
+        sub_account_borrowers.push(borrower);
+    }
+
+    borrowers.extend(sub_account_borrowers);
+    borrowers.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+    borrowers.dedup();
+    borrowers.truncate(limit);
+    Ok(borrowers)
 }
 
 // this will set the first key after the provided key, by appending a 1 byte
@@ -4,6 +4,7 @@ use crate::testing::mock_querier::mock_dependencies;
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::testing::{mock_env, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{Api, Coin, Uint128};
+use moneymarket::market::SameBlockRepayPolicy;
 
 #[test]
 fn proper_compute_borrower_interest() {
@@ -25,7 +26,7 @@ fn proper_compute_borrower_interest() {
         loan_amount: Uint256::zero(),
         pending_rewards: Decimal256::zero(),
     };
-    compute_borrower_interest(&mock_state, &mut liability1);
+    compute_borrower_interest(&mock_state, &mut liability1, Uint256::zero());
     let liability2 = BorrowerInfo {
         interest_index: Decimal256::one(),
         reward_index: Decimal256::zero(),
@@ -51,7 +52,7 @@ fn proper_compute_borrower_interest() {
         loan_amount: Uint256::from(80u128),
         pending_rewards: Decimal256::zero(),
     };
-    compute_borrower_interest(&mock_state2, &mut liability3);
+    compute_borrower_interest(&mock_state2, &mut liability3, Uint256::zero());
     let liability4 = BorrowerInfo {
         interest_index: Decimal256::from_uint256(2u128),
         reward_index: Decimal256::zero(),
@@ -59,6 +60,22 @@ fn proper_compute_borrower_interest() {
         pending_rewards: Decimal256::zero(),
     };
     assert_eq!(liability3, liability4);
+
+    // interest-free amount does not grow
+    let mut liability5 = BorrowerInfo {
+        interest_index: Decimal256::one(),
+        reward_index: Decimal256::zero(),
+        loan_amount: Uint256::from(100u128),
+        pending_rewards: Decimal256::zero(),
+    };
+    compute_borrower_interest(&mock_state2, &mut liability5, Uint256::from(40u128));
+    let liability6 = BorrowerInfo {
+        interest_index: Decimal256::from_uint256(2u128),
+        reward_index: Decimal256::zero(),
+        loan_amount: Uint256::from(160u128),
+        pending_rewards: Decimal256::zero(),
+    };
+    assert_eq!(liability5, liability6);
 }
 
 #[test]
@@ -86,6 +103,24 @@ fn proper_compute_interest() {
         overseer_contract: deps.api.addr_canonicalize("overseer").unwrap(),
         stable_denom: "uusd".to_string(),
         max_borrow_factor: Decimal256::one(),
+        min_liquidity_ratio: Decimal256::zero(),
+        repay_dust_epsilon: Uint256::zero(),
+        blocks_per_year: 4656810,
+        borrow_fee: Decimal256::zero(),
+        accrual_block_threshold: 0,
+        accrue_by_time: false,
+        reserve_route: None,
+        flash_fee: Decimal256::zero(),
+        accepted_repay_denoms: vec!["uusd".to_string()],
+        yield_strategy: None,
+        max_deployed_ratio: Decimal256::zero(),
+        rate_discount_source: None,
+        rate_discount_tiers: vec![],
+        min_repay_amount: Uint256::zero(),
+        repay_protocol_fee: Decimal256::zero(),
+        commitment_fee: Decimal256::zero(),
+        max_total_borrow: None,
+        same_block_repay_policy: SameBlockRepayPolicy::Allow,
     };
 
     deps.querier
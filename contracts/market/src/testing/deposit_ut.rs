@@ -4,6 +4,7 @@ use crate::testing::mock_querier::mock_dependencies;
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::testing::{mock_env, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{Api, Coin, Uint128};
+use moneymarket::market::SameBlockRepayPolicy;
 
 #[test]
 fn proper_compute_exchange_rate() {
@@ -24,6 +25,24 @@ fn proper_compute_exchange_rate() {
         overseer_contract: deps.api.addr_canonicalize("overseer").unwrap(),
         stable_denom: "uusd".to_string(),
         max_borrow_factor: Decimal256::one(),
+        min_liquidity_ratio: Decimal256::zero(),
+        repay_dust_epsilon: Uint256::zero(),
+        blocks_per_year: 4656810,
+        borrow_fee: Decimal256::zero(),
+        accrual_block_threshold: 0,
+        accrue_by_time: false,
+        reserve_route: None,
+        flash_fee: Decimal256::zero(),
+        accepted_repay_denoms: vec!["uusd".to_string()],
+        yield_strategy: None,
+        max_deployed_ratio: Decimal256::zero(),
+        rate_discount_source: None,
+        rate_discount_tiers: vec![],
+        min_repay_amount: Uint256::zero(),
+        repay_protocol_fee: Decimal256::zero(),
+        commitment_fee: Decimal256::zero(),
+        max_total_borrow: None,
+        same_block_repay_policy: SameBlockRepayPolicy::Allow,
     };
     deps.querier.with_token_balances(&[(
         &"AT-uusd".to_string(),
@@ -11,9 +11,17 @@ use cosmwasm_storage::to_length_prefixed;
 use std::collections::HashMap;
 
 use cw20::TokenInfoResponse;
+use moneymarket::converter::SimulationResponse;
+use moneymarket::custody::BorrowerResponse;
 use moneymarket::distribution_model::AncEmissionRateResponse;
 use moneymarket::interest_model::BorrowRateResponse;
-use moneymarket::overseer::{BorrowLimitResponse, ConfigResponse};
+use moneymarket::oracle::PriceResponse;
+use moneymarket::overseer::{
+    BorrowLimitResponse, ConfigResponse, PositionCollateralElem, PositionResponse, TokenType,
+    ValuationMode, WhitelistResponse, WhitelistResponseElem,
+};
+use moneymarket::staking::StakerTierResponse;
+use moneymarket::strategy::DepositorBalanceResponse;
 use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -41,6 +49,29 @@ pub enum QueryMsg {
     Config {},
     /// Query cw20 Token Info
     TokenInfo {},
+    /// Query cw20 Token Balance
+    Balance { address: String },
+    /// Query collateral acquired through the reserve route
+    Simulation {
+        offer_token: String,
+        ask_token: String,
+        amount: Uint256,
+    },
+    /// Query the custody contract of a collateral to overseer contract
+    Whitelist { collateral_token: Option<String> },
+    /// Query the position of a borrower to overseer contract
+    Position {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// Query the staker tier of a borrower to staking contract
+    StakerTier { staker: String },
+    /// Query oracle price to oracle contract
+    Price { base: String, quote: String },
+    /// Query the collateral of a borrower to custody contract
+    Borrower { address: String },
+    /// Query the deployed liquidity to yield strategy contract
+    DepositorBalance { depositor: String },
 }
 
 /// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
@@ -64,6 +95,11 @@ pub struct WasmMockQuerier {
     tax_querier: TaxQuerier,
     borrow_rate_querier: BorrowRateQuerier,
     borrow_limit_querier: BorrowLimitQuerier,
+    position_querier: PositionQuerier,
+    staker_tier_querier: StakerTierQuerier,
+    oracle_price_querier: OraclePriceQuerier,
+    custody_querier: CustodyQuerier,
+    strategy_balances: HashMap<String, Uint256>,
 }
 
 #[derive(Clone, Default)]
@@ -147,12 +183,14 @@ pub(crate) fn borrower_rate_to_map(
 pub struct BorrowLimitQuerier {
     // this lets us iterate over all pairs that match the first string
     borrow_limit: HashMap<String, Uint256>,
+    borrow_rate_premium: HashMap<String, Decimal256>,
 }
 
 impl BorrowLimitQuerier {
     pub fn new(borrow_limit: &[(&String, &Uint256)]) -> Self {
         BorrowLimitQuerier {
             borrow_limit: borrow_limit_to_map(borrow_limit),
+            borrow_rate_premium: HashMap::new(),
         }
     }
 }
@@ -167,6 +205,67 @@ pub(crate) fn borrow_limit_to_map(
     borrow_limit_map
 }
 
+// (collateral token, amount, price) locked by a borrower
+pub type PositionCollaterals = Vec<(String, Uint256, Decimal256)>;
+
+#[derive(Clone, Default)]
+pub struct PositionQuerier {
+    collaterals: HashMap<String, PositionCollaterals>,
+}
+
+impl PositionQuerier {
+    pub fn new(collaterals: &[(&String, &PositionCollaterals)]) -> Self {
+        let mut collaterals_map: HashMap<String, PositionCollaterals> = HashMap::new();
+        for (borrower, borrower_collaterals) in collaterals.iter() {
+            collaterals_map.insert((*borrower).clone(), (*borrower_collaterals).clone());
+        }
+        PositionQuerier {
+            collaterals: collaterals_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct StakerTierQuerier {
+    tiers: HashMap<String, u32>,
+}
+
+impl StakerTierQuerier {
+    pub fn new(tiers: &[(&String, u32)]) -> Self {
+        let mut tiers_map: HashMap<String, u32> = HashMap::new();
+        for (staker, tier) in tiers.iter() {
+            tiers_map.insert((*staker).clone(), *tier);
+        }
+        StakerTierQuerier { tiers: tiers_map }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CustodyQuerier {
+    // custody contracts with their weights by collateral token
+    custody_contracts: HashMap<String, Vec<(String, Decimal256)>>,
+    // collateral balances by custody contract and borrower
+    balances: HashMap<(String, String), Uint256>,
+}
+
+#[derive(Clone, Default)]
+pub struct OraclePriceQuerier {
+    oracle_price: HashMap<(String, String), Decimal256>,
+}
+
+impl OraclePriceQuerier {
+    pub fn new(oracle_price: &[(&(String, String), &Decimal256)]) -> Self {
+        let mut oracle_price_map: HashMap<(String, String), Decimal256> = HashMap::new();
+        for (base_quote, price) in oracle_price.iter() {
+            oracle_price_map.insert((*base_quote).clone(), **price);
+        }
+
+        OraclePriceQuerier {
+            oracle_price: oracle_price_map,
+        }
+    }
+}
+
 impl Querier for WasmMockQuerier {
     fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
         // MockQuerier doesn't support Custom, so we ignore it completely here
@@ -234,8 +333,15 @@ impl WasmMockQuerier {
                     } => match self.borrow_limit_querier.borrow_limit.get(&borrower) {
                         Some(v) => SystemResult::Ok(ContractResult::from(to_binary(
                             &BorrowLimitResponse {
-                                borrower,
                                 borrow_limit: *v,
+                                health_factor: Decimal256::MAX,
+                                borrow_rate_premium: self
+                                    .borrow_limit_querier
+                                    .borrow_rate_premium
+                                    .get(&borrower)
+                                    .cloned()
+                                    .unwrap_or_else(Decimal256::zero),
+                                borrower,
                             },
                         ))),
                         None => SystemResult::Err(SystemError::InvalidRequest {
@@ -253,10 +359,92 @@ impl WasmMockQuerier {
                             emission_rate: Decimal256::from_uint256(5u64),
                         },
                     ))),
+                    QueryMsg::Simulation {
+                        offer_token: _,
+                        ask_token: _,
+                        amount,
+                    } => {
+                        // The route prices the collateral at 2 stable coins
+                        SystemResult::Ok(ContractResult::from(to_binary(&SimulationResponse {
+                            amount: amount * Decimal256::percent(50),
+                        })))
+                    }
+                    QueryMsg::Whitelist { collateral_token } => {
+                        let collateral_token = collateral_token.unwrap();
+                        SystemResult::Ok(ContractResult::from(to_binary(&WhitelistResponse {
+                            elems: vec![WhitelistResponseElem {
+                                name: collateral_token.clone(),
+                                symbol: collateral_token.clone(),
+                                max_ltv: Decimal256::percent(60),
+                                custody_contract: format!("custody_{}", collateral_token),
+                                custody_contracts: self
+                                    .custody_querier
+                                    .custody_contracts
+                                    .get(&collateral_token)
+                                    .cloned()
+                                    .unwrap_or_else(|| {
+                                        vec![(
+                                            format!("custody_{}", collateral_token),
+                                            Decimal256::one(),
+                                        )]
+                                    }),
+                                collateral_token,
+                                quote_denom: None,
+                                liquidation_premium: None,
+                                correlation_group: None,
+                                min_lock_amount: None,
+                                borrow_enabled_from: None,
+                                borrow_disabled_from: None,
+                                liquidation_contract: None,
+                                decimals: 6,
+                                token_type: TokenType::Cw20,
+                                exchange_rate_source: None,
+                                borrow_rate_premium: None,
+                            }],
+                        })))
+                    }
+                    QueryMsg::Position {
+                        borrower,
+                        sub_account: _,
+                    } => {
+                        let collaterals: Vec<PositionCollateralElem> = self
+                            .position_querier
+                            .collaterals
+                            .get(&borrower)
+                            .cloned()
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|(collateral_token, amount, price)| PositionCollateralElem {
+                                collateral_token,
+                                amount,
+                                price,
+                                value: amount * price,
+                                liquidation_price: None,
+                            })
+                            .collect();
+                        let collateral_value = collaterals
+                            .iter()
+                            .fold(Uint256::zero(), |sum, c| sum + c.value);
+                        let borrow_limit = self
+                            .borrow_limit_querier
+                            .borrow_limit
+                            .get(&borrower)
+                            .copied()
+                            .unwrap_or_default();
+                        SystemResult::Ok(ContractResult::from(to_binary(&PositionResponse {
+                            borrower,
+                            collaterals,
+                            collateral_value,
+                            borrow_limit,
+                            loan_amount: Uint256::zero(),
+                            ltv: Decimal256::zero(),
+                            health_factor: Decimal256::MAX,
+                        })))
+                    }
                     QueryMsg::Config {} => {
                         SystemResult::Ok(ContractResult::from(to_binary(&ConfigResponse {
                             owner_addr: "".to_string(),
-                            oracle_contract: "".to_string(),
+                            oracle_contract: "oracle".to_string(),
                             market_contract: "".to_string(),
                             liquidation_contract: "".to_string(),
                             collector_contract: "".to_string(),
@@ -266,7 +454,44 @@ impl WasmMockQuerier {
                             anc_purchase_factor: Decimal256::one(),
                             stable_denom: "uusd".to_string(),
                             epoch_period: 100u64,
-                            price_timeframe: 100u64,
+                            borrow_price_timeframe: 100u64,
+                            liquidation_price_timeframe: 100u64,
+                            max_anc_price: None,
+                            buffer_target: Uint256::zero(),
+                            surplus_recipient: "".to_string(),
+                            stable_decimals: 6,
+                            price_deviation_threshold: Decimal256::percent(50),
+                            max_collaterals_per_borrower: 10,
+                            ordered_epoch_operations: false,
+                            dust_threshold: Uint256::zero(),
+                            dust_tolerance: Decimal256::zero(),
+                            collateral_converter: None,
+                            soft_liquidation: false,
+                            buffer_backstop_limit: Uint256::zero(),
+                            max_confidence_spread: None,
+                            reserve_target: Uint256::zero(),
+                            reserve_topup_factor: Decimal256::zero(),
+                            epoch_requires_fresh_prices: false,
+                            fallback_oracle_contract: None,
+                            min_custody_quorum: Decimal256::zero(),
+                            same_block_collateral_delay: false,
+                            max_collateral_value_per_borrower: None,
+                            risk_admin: None,
+                            epoch_custody_batch_size: None,
+                            anc_buyback_amm: None,
+                            min_lock_amount: Uint256::zero(),
+                            solvency_margin: Decimal256::zero(),
+                            max_block_price_drop: None,
+                            price_drop_cooldown: 0,
+                            dispute_window: 0,
+                            valuation_mode: ValuationMode::Spot,
+                            default_limit: 10,
+                            bad_debt_budget: Uint256::zero(),
+                            deposit_rate_smoothing_epochs: 1,
+                            skip_paused_collaterals: false,
+                            max_ltv_delta_per_epoch: None,
+                            liquidation_frozen: false,
+                            max_anc_purchase_per_epoch: None,
                         })))
                     }
                     QueryMsg::TokenInfo {} => {
@@ -289,6 +514,70 @@ impl WasmMockQuerier {
                             total_supply,
                         })))
                     }
+                    QueryMsg::Balance { address } => {
+                        let balance = self
+                            .token_querier
+                            .balances
+                            .get(contract_addr)
+                            .and_then(|balances| balances.get(&address))
+                            .copied()
+                            .unwrap_or_default();
+
+                        SystemResult::Ok(ContractResult::from(to_binary(&balance)))
+                    }
+                    QueryMsg::StakerTier { staker } => {
+                        let tier = self
+                            .staker_tier_querier
+                            .tiers
+                            .get(&staker)
+                            .copied()
+                            .unwrap_or_default();
+
+                        SystemResult::Ok(ContractResult::from(to_binary(&StakerTierResponse {
+                            tier,
+                        })))
+                    }
+                    QueryMsg::Price { base, quote } => {
+                        match self.oracle_price_querier.oracle_price.get(&(base, quote)) {
+                            Some(rate) => {
+                                SystemResult::Ok(ContractResult::from(to_binary(&PriceResponse {
+                                    rate: *rate,
+                                    last_updated_base: 0,
+                                    last_updated_quote: 0,
+                                    confidence_spread: None,
+                                })))
+                            }
+                            None => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No oracle price exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
+                    QueryMsg::Borrower { address } => {
+                        let balance = self
+                            .custody_querier
+                            .balances
+                            .get(&(contract_addr.to_string(), address.clone()))
+                            .copied()
+                            .unwrap_or_default();
+                        SystemResult::Ok(ContractResult::from(to_binary(&BorrowerResponse {
+                            borrower: address,
+                            balance,
+                            spendable: Uint256::zero(),
+                            pending_rewards: Uint256::zero(),
+                            reward_boost: Decimal256::one(),
+                        })))
+                    }
+                    QueryMsg::DepositorBalance { depositor: _ } => {
+                        let balance = self
+                            .strategy_balances
+                            .get(contract_addr)
+                            .copied()
+                            .unwrap_or_default();
+                        SystemResult::Ok(ContractResult::from(to_binary(
+                            &DepositorBalanceResponse { balance },
+                        )))
+                    }
                 }
             }
             QueryRequest::Wasm(WasmQuery::Raw { contract_addr, key }) => {
@@ -342,6 +631,11 @@ impl WasmMockQuerier {
             tax_querier: TaxQuerier::default(),
             borrow_rate_querier: BorrowRateQuerier::default(),
             borrow_limit_querier: BorrowLimitQuerier::default(),
+            position_querier: PositionQuerier::default(),
+            staker_tier_querier: StakerTierQuerier::default(),
+            oracle_price_querier: OraclePriceQuerier::default(),
+            custody_querier: CustodyQuerier::default(),
+            strategy_balances: HashMap::new(),
         }
     }
 
@@ -371,4 +665,55 @@ impl WasmMockQuerier {
     pub fn with_borrow_limit(&mut self, borrow_limit: &[(&String, &Uint256)]) {
         self.borrow_limit_querier = BorrowLimitQuerier::new(borrow_limit);
     }
+
+    pub fn with_borrow_rate_premium(&mut self, premiums: &[(&String, &Decimal256)]) {
+        for (borrower, premium) in premiums.iter() {
+            self.borrow_limit_querier
+                .borrow_rate_premium
+                .insert((*borrower).clone(), **premium);
+        }
+    }
+
+    pub fn with_position_collaterals(&mut self, collaterals: &[(&String, &PositionCollaterals)]) {
+        self.position_querier = PositionQuerier::new(collaterals);
+    }
+
+    pub fn with_staker_tiers(&mut self, tiers: &[(&String, u32)]) {
+        self.staker_tier_querier = StakerTierQuerier::new(tiers);
+    }
+
+    pub fn with_oracle_price(&mut self, oracle_price: &[(&(String, String), &Decimal256)]) {
+        self.oracle_price_querier = OraclePriceQuerier::new(oracle_price);
+    }
+
+    pub fn with_custody_contracts(
+        &mut self,
+        collateral_token: &str,
+        custody_contracts: &[(&str, Decimal256)],
+    ) {
+        self.custody_querier.custody_contracts.insert(
+            collateral_token.to_string(),
+            custody_contracts
+                .iter()
+                .map(|(custody_contract, weight)| (custody_contract.to_string(), *weight))
+                .collect(),
+        );
+    }
+
+    pub fn with_strategy_balance(&mut self, yield_strategy: &str, balance: Uint256) {
+        self.strategy_balances
+            .insert(yield_strategy.to_string(), balance);
+    }
+
+    pub fn with_custody_balance(
+        &mut self,
+        custody_contract: &str,
+        borrower: &str,
+        balance: Uint256,
+    ) {
+        self.custody_querier.balances.insert(
+            (custody_contract.to_string(), borrower.to_string()),
+            balance,
+        );
+    }
 }
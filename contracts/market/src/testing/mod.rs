@@ -2,3 +2,36 @@ mod borrow_ut;
 mod deposit_ut;
 mod mock_querier;
 mod tests;
+
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use moneymarket::market::InstantiateMsg;
+
+/// Instantiate message the tests start from,
+/// each test overrides the fields it exercises
+pub(crate) fn instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        owner_addr: "owner".to_string(),
+        stable_denom: "uusd".to_string(),
+        aterra_code_id: 123u64,
+        anc_emission_rate: Decimal256::one(),
+        max_borrow_factor: Decimal256::one(),
+        min_liquidity_ratio: Decimal256::zero(),
+        repay_dust_epsilon: Uint256::zero(),
+        blocks_per_year: 4656810,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }
+}
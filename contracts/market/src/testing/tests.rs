@@ -1,22 +1,38 @@
 use crate::contract::{execute, instantiate, query, reply, INITIAL_DEPOSIT_AMOUNT};
+use crate::deposit::query_market_balance;
 use crate::error::ContractError;
 use crate::response::MsgInstantiateContractResponse;
-use crate::state::{read_borrower_infos, read_state, store_state, State};
+use crate::state::{
+    read_borrower_infos, read_config, read_loan_principal, read_state, read_total_deployed,
+    read_total_repay_credit, store_config, store_exchange_rate_snapshot, store_state,
+    store_total_deployed, ExchangeRateSnapshot, State,
+};
+use crate::testing::instantiate_msg;
 use crate::testing::mock_querier::mock_dependencies;
 
 use anchor_token::distributor::ExecuteMsg as FaucetExecuteMsg;
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    attr, from_binary, to_binary, Addr, BankMsg, Coin, ContractResult, CosmosMsg, Decimal, Reply,
-    SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
+    attr, from_binary, to_binary, Addr, Api, BankMsg, Coin, ContractResult, CosmosMsg, Decimal,
+    Deps, DepsMut, Env, MessageInfo, Reply, StdError, SubMsg, SubMsgExecutionResponse, Uint128,
+    WasmMsg,
 };
 use cw20::{Cw20Coin, Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
+use moneymarket::converter::{
+    Cw20HookMsg as ConverterCw20HookMsg, ExecuteMsg as ConverterExecuteMsg,
+};
+use moneymarket::custody::{Cw20HookMsg as CustodyCw20HookMsg, ExecuteMsg as CustodyExecuteMsg};
 use moneymarket::market::{
-    BorrowerInfoResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg,
-    StateResponse,
+    AccrualParamsResponse, AccruedInterestResponse, BorrowAprResponse, BorrowerInfoResponse,
+    CanBorrowResponse, ConfigResponse, CreditLineResponse, Cw20HookMsg, DepositApyResponse,
+    ExchangeRateBetweenResponse, ExecuteMsg, InstantiateMsg, InterestIndexesResponse,
+    LastAccrualResponse, MaxRedeemableResponse, OverpayAction, QueryMsg, ReserveCoverageResponse,
+    SameBlockRepayPolicy, StateResponse, UpdateConfigMsg,
 };
+use moneymarket::overseer::ExecuteMsg as OverseerExecuteMsg;
 use moneymarket::querier::deduct_tax;
+use moneymarket::strategy::ExecuteMsg as StrategyExecuteMsg;
 use protobuf::Message;
 use std::str::FromStr;
 use terraswap::token::InstantiateMsg as TokenInstantiateMsg;
@@ -28,13 +44,7 @@ fn proper_initialization() {
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
     }]);
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -136,6 +146,7 @@ fn proper_initialization() {
     assert_eq!(Decimal256::one(), state.anc_emission_rate);
     assert_eq!(Uint256::zero(), state.prev_aterra_supply);
     assert_eq!(Decimal256::one(), state.prev_exchange_rate);
+    assert_eq!(Decimal256::one(), state.liquidity_ratio);
 }
 
 #[test]
@@ -147,13 +158,7 @@ fn update_config() {
     deps.querier
         .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -191,12 +196,30 @@ fn update_config() {
 
     // update owner
     let info = mock_info("owner", &[]);
-    let msg = ExecuteMsg::UpdateConfig {
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
         owner_addr: Some("owner1".to_string()),
         interest_model: None,
         distribution_model: None,
         max_borrow_factor: None,
-    };
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(0, res.messages.len());
@@ -208,12 +231,30 @@ fn update_config() {
 
     // update left items
     let info = mock_info("owner1", &[]);
-    let msg = ExecuteMsg::UpdateConfig {
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
         owner_addr: None,
         interest_model: Some("interest2".to_string()),
         distribution_model: Some("distribution2".to_string()),
         max_borrow_factor: Some(Decimal256::percent(100)),
-    };
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: Some(10),
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(0, res.messages.len());
@@ -225,15 +266,66 @@ fn update_config() {
     assert_eq!("interest2".to_string(), config_res.interest_model);
     assert_eq!("distribution2".to_string(), config_res.distribution_model);
     assert_eq!(Decimal256::percent(100), config_res.max_borrow_factor);
+    assert_eq!(10u64, config_res.accrual_block_threshold);
+
+    // dust epsilon large enough to forgive real debt
+    let info = mock_info("owner1", &[]);
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: Some(Uint256::from(1001u64)),
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::InvalidRepayDustEpsilon(1000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 
     // Unauthorized err
     let info = mock_info("owner", &[]);
-    let msg = ExecuteMsg::UpdateConfig {
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
         owner_addr: None,
         interest_model: None,
         distribution_model: None,
         max_borrow_factor: None,
-    };
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
 
     let res = execute(deps.as_mut(), mock_env(), info, msg);
     match res {
@@ -249,13 +341,7 @@ fn deposit_stable_huge_amount() {
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
     }]);
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -405,13 +491,7 @@ fn deposit_stable() {
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
     }]);
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -648,13 +728,7 @@ fn redeem_stable() {
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
     }]);
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -830,22 +904,16 @@ fn redeem_stable() {
 }
 
 #[test]
-fn borrow_stable() {
+fn redeem_stable_recall() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
     }]);
-    deps.querier.with_tax(
-        Decimal::percent(1),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
 
     let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
+        yield_strategy: Some("strategy".to_string()),
+        max_deployed_ratio: Some(Decimal256::percent(80)),
+        ..instantiate_msg()
     };
 
     let info = mock_info(
@@ -879,226 +947,167 @@ fn borrow_stable() {
         collector_contract: "collector".to_string(),
         distributor_contract: "distributor".to_string(),
     };
-    let mut env = mock_env();
     let info = mock_info("addr0000", &[]);
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
     deps.querier
         .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
-    deps.querier
-        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
-
+    deps.querier.with_token_balances(&[(
+        &"AT-uusd".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(2000000u128))],
+    )]);
     store_state(
         deps.as_mut().storage,
         &State {
-            total_liabilities: Decimal256::from_uint256(1000000u128),
-            total_reserves: Decimal256::zero(),
-            last_interest_updated: env.block.height,
-            last_reward_updated: env.block.height,
+            total_liabilities: Decimal256::zero(),
+            total_reserves: Decimal256::from_uint256(100000u128),
+            last_interest_updated: mock_env().block.height,
+            last_reward_updated: mock_env().block.height,
             global_interest_index: Decimal256::one(),
             global_reward_index: Decimal256::zero(),
             anc_emission_rate: Decimal256::one(),
-            prev_aterra_supply: Uint256::zero(),
+            prev_aterra_supply: Uint256::from(2000000u64),
             prev_exchange_rate: Decimal256::one(),
         },
     )
     .unwrap();
 
-    let msg = ExecuteMsg::BorrowStable {
-        borrow_amount: Uint256::from(500000u64),
-        to: None,
-    };
-
-    env.block.height += 100;
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    // 1500000 of the 2100000 liquidity is deployed, 100000 of it is yield
+    store_total_deployed(deps.as_mut().storage, &Uint256::from(1400000u64)).unwrap();
+    deps.querier
+        .with_strategy_balance("strategy", Uint256::from(1500000u64));
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(600000u128),
+        }],
+    );
 
-    // interest_factor = 1% * 100blocks = 1
-    // interest_accrued = 1000000
-    // global_interest_index = 2
-    // total_liabilities = 2500000
-    // total_reserves = 3000
-    // last_interest_updated = 100
-    // reward_accrued = 100
-    // global_reward_index = 0.00002
-    // last_rewards_updated = 100
+    // Redeem 1000000, which needs 500000 more than the balance after reserves
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(1000000u128),
+        msg: to_binary(&Cw20HookMsg::RedeemStable {}).unwrap(),
+    });
+    let info = mock_info("AT-uusd", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
     assert_eq!(
-        res.attributes,
+        res.messages,
         vec![
-            attr("action", "borrow_stable"),
-            attr("borrower", "addr0000"),
-            attr("borrow_amount", "500000")
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "strategy".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&StrategyExecuteMsg::Withdraw {
+                        amount: Uint256::from(500000u64),
+                    })
+                    .unwrap(),
+                }),
+                4
+            ),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "AT-uusd".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Burn {
+                    amount: Uint128::from(1000000u128),
+                })
+                .unwrap()
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(1000000u128),
+                    }
+                )
+                .unwrap(),]
+            }))
         ]
     );
-
     assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-            to_address: "addr0000".to_string(),
-            amount: vec![deduct_tax(
-                deps.as_ref(),
-                Coin {
-                    denom: "uusd".to_string(),
-                    amount: Uint128::from(500000u128),
-                }
-            )
-            .unwrap()],
-        }))]
+        read_total_deployed(deps.as_ref().storage),
+        Uint256::from(1400000u64)
     );
 
+    // the recall is settled from what the strategy sent
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1090000u128),
+        }],
+    );
+    deps.querier
+        .with_strategy_balance("strategy", Uint256::from(1000000u64));
+    let recall_reply = Reply {
+        id: 4,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), recall_reply.clone()).unwrap();
     assert_eq!(
-        from_binary::<State>(
-            &query(
-                deps.as_ref(),
-                env.clone(),
-                QueryMsg::State { block_height: None }
-            )
-            .unwrap()
-        )
-        .unwrap(),
-        State {
-            total_liabilities: Decimal256::from_uint256(2500000u128),
-            total_reserves: Decimal256::zero(),
-            last_interest_updated: env.block.height,
-            last_reward_updated: env.block.height,
-            global_interest_index: Decimal256::from_uint256(2u128),
-            global_reward_index: Decimal256::from_str("0.0001").unwrap(),
-            anc_emission_rate: Decimal256::one(),
-            prev_aterra_supply: Uint256::zero(),
-            prev_exchange_rate: Decimal256::one(),
-        }
+        res.attributes,
+        vec![
+            attr("action", "settle_strategy_recall"),
+            attr("recalled_amount", "490000"),
+        ]
     );
-
-    // after 1 block state
     assert_eq!(
-        from_binary::<State>(
-            &query(
-                deps.as_ref(),
-                mock_env(),
-                QueryMsg::State {
-                    block_height: Some(env.block.height + 1u64)
-                }
-            )
-            .unwrap()
-        )
-        .unwrap(),
-        State {
-            total_liabilities: Decimal256::from_uint256(2525000u128),
-            total_reserves: Decimal256::from_uint256(0u128),
-            last_interest_updated: env.block.height + 1u64,
-            last_reward_updated: env.block.height + 1u64,
-            global_interest_index: Decimal256::from_str("2.02").unwrap(),
-            global_reward_index: Decimal256::from_str("0.0001008").unwrap(),
-            anc_emission_rate: Decimal256::one(),
-            prev_aterra_supply: Uint256::zero(),
-            prev_exchange_rate: Decimal256::one(),
-        }
+        read_total_deployed(deps.as_ref().storage),
+        Uint256::from(910000u64)
     );
+    let res = reply(deps.as_mut(), mock_env(), recall_reply).unwrap_err();
+    assert_eq!(res, ContractError::InvalidReplyId {});
 
-    let res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::BorrowerInfo {
-            borrower: "addr0000".to_string(),
-            block_height: None,
+    // the redemption cannot exceed the balance and the deployed liquidity
+    deps.querier.with_token_balances(&[(
+        &"AT-uusd".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000000u128))],
+    )]);
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(500000u128),
+            total_reserves: Decimal256::from_uint256(100000u128),
+            last_interest_updated: mock_env().block.height,
+            last_reward_updated: mock_env().block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::from(1000000u64),
+            prev_exchange_rate: Decimal256::one(),
         },
     )
     .unwrap();
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(1000000u128),
+        msg: to_binary(&Cw20HookMsg::RedeemStable {}).unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(res, ContractError::NoStableAvailable("uusd".to_string()));
+}
 
-    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
-    assert_eq!(
-        liability,
-        BorrowerInfoResponse {
-            borrower: "addr0000".to_string(),
-            interest_index: Decimal256::from_uint256(2u128),
-            reward_index: Decimal256::from_str("0.0001").unwrap(),
-            loan_amount: Uint256::from(500000u64),
-            pending_rewards: Decimal256::zero(),
-        }
-    );
+#[test]
+fn max_redeemable() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
 
-    let res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::BorrowerInfo {
-            borrower: "addr0000".to_string(),
-            block_height: Some(env.block.height),
-        },
-    )
-    .unwrap();
+    let msg = instantiate_msg();
 
-    let borrower_info: BorrowerInfoResponse = from_binary(&res).unwrap();
-    assert_eq!(
-        borrower_info,
-        BorrowerInfoResponse {
-            borrower: "addr0000".to_string(),
-            interest_index: Decimal256::from_uint256(2u128),
-            reward_index: Decimal256::from_str("0.0001").unwrap(),
-            loan_amount: Uint256::from(500000u64),
-            pending_rewards: Decimal256::zero(),
-        }
-    );
-
-    // Query to future blocks
-    // interest_factor is 100%
-    let res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::BorrowerInfo {
-            borrower: "addr0000".to_string(),
-            block_height: Some(env.block.height + 100),
-        },
-    )
-    .unwrap();
-
-    let borrower_info: BorrowerInfoResponse = from_binary(&res).unwrap();
-    assert_eq!(
-        borrower_info,
-        BorrowerInfoResponse {
-            borrower: "addr0000".to_string(),
-            interest_index: Decimal256::from_uint256(4u128),
-            reward_index: Decimal256::from_str("0.00018").unwrap(),
-            loan_amount: Uint256::from(1000000u64),
-            pending_rewards: Decimal256::from_uint256(20u64),
-        }
-    );
-
-    // Cannot borrow more than borrow limit
-    let msg = ExecuteMsg::BorrowStable {
-        borrow_amount: Uint256::from(500001u64),
-        to: None,
-    };
-    let res = execute(deps.as_mut(), env, info, msg);
-    match res {
-        Err(ContractError::BorrowExceedsLimit(1000000)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-}
-
-#[test]
-fn assert_max_borrow_factor() {
-    let mut deps = mock_dependencies(&[Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
-    }]);
-    deps.querier.with_tax(
-        Decimal::percent(1),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
-
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::percent(1),
-    };
-
-    let info = mock_info(
-        "addr0000",
-        &[Coin {
-            denom: "uusd".to_string(),
-            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
-        }],
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
     );
 
     // we can just call .unwrap() to assert this was a success
@@ -1125,67 +1134,102 @@ fn assert_max_borrow_factor() {
         distributor_contract: "distributor".to_string(),
     };
     let info = mock_info("addr0000", &[]);
-    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
     deps.querier
         .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
-    deps.querier
-        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+    deps.querier.with_token_balances(&[(
+        &"AT-uusd".to_string(),
+        &[
+            (&"addr0000".to_string(), &Uint128::from(1800000u128)),
+            (&"addr0001".to_string(), &Uint128::from(200000u128)),
+        ],
+    )]);
 
+    // exchange rate stays at 1 with most of the deposits borrowed
     store_state(
         deps.as_mut().storage,
         &State {
-            total_liabilities: Decimal256::zero(),
-            total_reserves: Decimal256::zero(),
+            total_liabilities: Decimal256::from_uint256(1500000u128),
+            total_reserves: Decimal256::from_uint256(100000u128),
             last_interest_updated: mock_env().block.height,
             last_reward_updated: mock_env().block.height,
             global_interest_index: Decimal256::one(),
             global_reward_index: Decimal256::zero(),
             anc_emission_rate: Decimal256::one(),
-            prev_aterra_supply: Uint256::zero(),
+            prev_aterra_supply: Uint256::from(2000000u64),
             prev_exchange_rate: Decimal256::one(),
         },
     )
     .unwrap();
-
-    let msg = ExecuteMsg::BorrowStable {
-        borrow_amount: Uint256::from(10000u64),
-        to: None,
-    };
-
-    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "borrow_stable"),
-            attr("borrower", "addr0000"),
-            attr("borrow_amount", "10000")
-        ]
-    );
-
-    // subtract borrow amount
     deps.querier.update_balance(
-        MOCK_CONTRACT_ADDR,
+        MOCK_CONTRACT_ADDR.to_string(),
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT - 10000u128),
+            amount: Uint128::from(600000u128),
         }],
     );
 
-    let msg = ExecuteMsg::BorrowStable {
-        borrow_amount: Uint256::from(1u64),
-        to: None,
-    };
-    let res = execute(deps.as_mut(), mock_env(), info, msg);
-    let _uusd_string = "uusd";
-    match res {
-        Err(ContractError::MaxBorrowFactorReached(_uusd_string)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    // the balance after reserves limits the redemption
+    let res: MaxRedeemableResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::MaxRedeemable {
+                depositor: "addr0000".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        MaxRedeemableResponse {
+            aterra_amount: Uint256::from(500000u64),
+            stable_amount: Uint256::from(500000u64),
+        }
+    );
+
+    // the aTerra balance limits the redemption
+    let res: MaxRedeemableResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::MaxRedeemable {
+                depositor: "addr0001".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        MaxRedeemableResponse {
+            aterra_amount: Uint256::from(200000u64),
+            stable_amount: Uint256::from(200000u64),
+        }
+    );
+
+    // the redeemable amount can be redeemed
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(500000u128),
+        msg: to_binary(&Cw20HookMsg::RedeemStable {}).unwrap(),
+    });
+    let info = mock_info("AT-uusd", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(500001u128),
+        msg: to_binary(&Cw20HookMsg::RedeemStable {}).unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(res, ContractError::NoStableAvailable("uusd".to_string()));
 }
 
 #[test]
-fn repay_stable() {
+fn borrow_fee() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
@@ -1196,11 +1240,8 @@ fn repay_stable() {
     );
 
     let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
+        borrow_fee: Some(Decimal256::percent(1)),
+        ..instantiate_msg()
     };
 
     let info = mock_info(
@@ -1234,8 +1275,8 @@ fn repay_stable() {
         collector_contract: "collector".to_string(),
         distributor_contract: "distributor".to_string(),
     };
-    let mut env = mock_env();
-    let mut info = mock_info("addr0000", &[]);
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
     let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
     deps.querier
@@ -1259,98 +1300,14 @@ fn repay_stable() {
     )
     .unwrap();
 
+    // 500000 * 1% stays as reserves
     let msg = ExecuteMsg::BorrowStable {
         borrow_amount: Uint256::from(500000u64),
         to: None,
+        sub_account: None,
     };
-
-    env.block.height += 100;
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-
-    let msg = ExecuteMsg::RepayStable {};
-    info.funds = vec![Coin {
-        denom: "ukrw".to_string(),
-        amount: Uint128::from(100000u128),
-    }];
-
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
-    let _uusd_string = "uusd";
-    match res {
-        Err(ContractError::ZeroRepay(_uusd_string)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    info.funds = vec![Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::zero(),
-    }];
-
-    let res2 = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
-    match res2 {
-        Err(ContractError::ZeroRepay(_uusd_string)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    deps.querier.update_balance(
-        MOCK_CONTRACT_ADDR.to_string(),
-        vec![Coin {
-            denom: "uusd".to_string(),
-            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
-        }],
-    );
-
-    info.funds = vec![Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(100000u128),
-    }];
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "repay_stable"),
-            attr("borrower", "addr0000"),
-            attr("repay_amount", "100000"),
-        ]
-    );
-
-    //Loan amount and Total liability have decreased according to the repayment
-    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
-        .unwrap()
-        .get(0)
-        .unwrap()
-        .loan_amount;
-    assert_eq!(res_loan, Uint256::from(400000u128));
-    assert_eq!(
-        read_state(deps.as_ref().storage).unwrap().total_liabilities,
-        Decimal256::from_uint256(2400000u128)
-    );
-
-    info.funds = vec![Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(500000u128),
-    }];
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "repay_stable"),
-            attr("borrower", "addr0000"),
-            attr("repay_amount", "400000"),
-        ]
-    );
-
-    //Loan amount and Total liability have decreased according to the repayment
-    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
-        .unwrap()
-        .get(0)
-        .unwrap()
-        .loan_amount;
-    assert_eq!(res_loan, Uint256::zero());
-    assert_eq!(
-        read_state(deps.as_ref().storage).unwrap().total_liabilities,
-        Decimal256::from_uint256(2000000u128)
-    );
-
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.attributes[3], attr("borrow_fee", "5000"));
     assert_eq!(
         res.messages,
         vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
@@ -1359,16 +1316,72 @@ fn repay_stable() {
                 deps.as_ref(),
                 Coin {
                     denom: "uusd".to_string(),
-                    amount: Uint128::from(100000u128),
+                    amount: Uint128::from(495000u128),
                 }
             )
-            .unwrap()]
+            .unwrap()],
         }))]
     );
+
+    // the borrower owes the gross amount
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(liability.loan_amount, Uint256::from(500000u64));
+
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.total_reserves, Decimal256::from_uint256(5000u64));
+    assert_eq!(
+        state.total_liabilities,
+        Decimal256::from_uint256(1500000u64)
+    );
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.borrow_fee, Decimal256::percent(1));
+
+    // the fee cannot take the whole borrow
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: Some(Decimal256::one()),
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    let res = execute(deps.as_mut(), env, mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::InvalidBorrowFee {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 }
 
 #[test]
-fn repay_stable_from_liquidation() {
+fn borrow_stable() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
@@ -1378,13 +1391,7 @@ fn repay_stable_from_liquidation() {
         &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
     );
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -1445,101 +1452,6191 @@ fn repay_stable_from_liquidation() {
     let msg = ExecuteMsg::BorrowStable {
         borrow_amount: Uint256::from(500000u64),
         to: None,
+        sub_account: None,
     };
 
     env.block.height += 100;
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
-    // update balance to make repay
-    deps.querier.update_balance(
-        MOCK_CONTRACT_ADDR.to_string(),
-        vec![
-            Coin {
+    // interest_factor = 1% * 100blocks = 1
+    // interest_accrued = 1000000
+    // global_interest_index = 2
+    // total_liabilities = 2500000
+    // total_reserves = 3000
+    // last_interest_updated = 100
+    // reward_accrued = 100
+    // global_reward_index = 0.00002
+    // last_rewards_updated = 100
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "borrow_stable"),
+            attr("borrower", "addr0000"),
+            attr("borrow_amount", "500000"),
+            attr("borrow_fee", "0"),
+        ]
+    );
+
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(500000u128),
+                }
+            )
+            .unwrap()],
+        }))]
+    );
+
+    assert_eq!(
+        from_binary::<State>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::State { block_height: None }
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        State {
+            total_liabilities: Decimal256::from_uint256(2500000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::from_uint256(2u128),
+            global_reward_index: Decimal256::from_str("0.0001").unwrap(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        }
+    );
+
+    // after 1 block state
+    assert_eq!(
+        from_binary::<State>(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::State {
+                    block_height: Some(env.block.height + 1u64)
+                }
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        State {
+            total_liabilities: Decimal256::from_uint256(2525000u128),
+            total_reserves: Decimal256::from_uint256(0u128),
+            last_interest_updated: env.block.height + 1u64,
+            last_reward_updated: env.block.height + 1u64,
+            global_interest_index: Decimal256::from_str("2.02").unwrap(),
+            global_reward_index: Decimal256::from_str("0.0001008").unwrap(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        }
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        liability,
+        BorrowerInfoResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            interest_index: Decimal256::from_uint256(2u128),
+            reward_index: Decimal256::from_str("0.0001").unwrap(),
+            loan_amount: Uint256::from(500000u64),
+            pending_rewards: Decimal256::zero(),
+            interest_free_allowance: Uint256::zero(),
+            repay_credit: Uint256::zero(),
+        }
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: Some(env.block.height),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+
+    let borrower_info: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        borrower_info,
+        BorrowerInfoResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            interest_index: Decimal256::from_uint256(2u128),
+            reward_index: Decimal256::from_str("0.0001").unwrap(),
+            loan_amount: Uint256::from(500000u64),
+            pending_rewards: Decimal256::zero(),
+            interest_free_allowance: Uint256::zero(),
+            repay_credit: Uint256::zero(),
+        }
+    );
+
+    // Query to future blocks
+    // interest_factor is 100%
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: Some(env.block.height + 100),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+
+    let borrower_info: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        borrower_info,
+        BorrowerInfoResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            interest_index: Decimal256::from_uint256(4u128),
+            reward_index: Decimal256::from_str("0.00018").unwrap(),
+            loan_amount: Uint256::from(1000000u64),
+            pending_rewards: Decimal256::from_uint256(20u64),
+            interest_free_allowance: Uint256::zero(),
+            repay_credit: Uint256::zero(),
+        }
+    );
+
+    // Cannot borrow more than borrow limit
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500001u64),
+        to: None,
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg);
+    match res {
+        Err(ContractError::BorrowExceedsLimit(1000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn accrual_block_threshold() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        accrual_block_threshold: Some(100),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(10000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let last_interest_updated = env.block.height;
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    // within the threshold the stored index is reused
+    env.block.height += 50;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.last_interest_updated, last_interest_updated);
+    assert_eq!(state.global_interest_index, Decimal256::one());
+    assert_eq!(
+        state.total_liabilities,
+        Decimal256::from_uint256(1500000u128)
+    );
+
+    // queries still accrue up to the requested block
+    // interest_factor = 1% * 50blocks = 0.5
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::percent(150));
+
+    // interest_factor = 1% * 100blocks = 1
+    // global_interest_index = 2
+    // total_liabilities = 1500000 * 2 = 3000000
+    env.block.height += 50;
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::from_uint256(2u128));
+    assert_eq!(res.total_liabilities, Decimal256::from_uint256(3000000u128));
+
+    // at the threshold the accrual runs and matches the queried state
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.last_interest_updated, env.block.height);
+    assert_eq!(state.global_interest_index, res.global_interest_index);
+    assert_eq!(
+        state.total_liabilities,
+        res.total_liabilities + Decimal256::from_uint256(500000u128)
+    );
+}
+
+#[test]
+fn accrual_block_threshold_by_time() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        blocks_per_year: 5256000,
+        accrual_block_threshold: Some(100),
+        accrue_by_time: Some(true),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(10000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.time.seconds(),
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let last_interest_updated = env.block.time.seconds();
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    // 100 blocks at 6 seconds a block is a threshold of 600 seconds
+    env.block.height += 50;
+    env.block.time = env.block.time.plus_seconds(300);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.last_interest_updated, last_interest_updated);
+    assert_eq!(state.global_interest_index, Decimal256::one());
+
+    // interest_factor = 1% / 6 * 600seconds ~= 1,
+    // less the rounding of the per second rate
+    env.block.height += 50;
+    env.block.time = env.block.time.plus_seconds(300);
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.global_interest_index,
+        Decimal256::from_str("1.9999999999999996").unwrap()
+    );
+
+    // at the threshold the accrual runs and matches the queried state
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.last_interest_updated, env.block.time.seconds());
+    assert_eq!(state.global_interest_index, res.global_interest_index);
+    assert_eq!(
+        state.total_liabilities,
+        res.total_liabilities + Decimal256::from_uint256(500000u128)
+    );
+}
+
+#[test]
+fn deploy_reserves() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        reserve_route: Some("route".to_string()),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(10000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(1000000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::DeployReserves {
+        collateral_token: "bluna".to_string(),
+        amount: Uint256::from(1000001u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the reserves cannot be overdrawn
+    let info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::ReservesOverdrawn(1000000u128));
+
+    let msg = ExecuteMsg::DeployReserves {
+        collateral_token: "bluna".to_string(),
+        amount: Uint256::from(1000000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "route".to_string(),
+                funds: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(1000000u128),
+                }],
+                msg: to_binary(&ConverterExecuteMsg::ConvertNative {
+                    ask_token: "bluna".to_string(),
+                    recipient: MOCK_CONTRACT_ADDR.to_string(),
+                    msg: None,
+                    min_receive: None,
+                })
+                .unwrap(),
+            }),
+            2
+        )]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "deploy_reserves"),
+            attr("collateral_token", "bluna"),
+            attr("amount", "1000000"),
+        ]
+    );
+
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.total_reserves, Decimal256::zero());
+
+    // the route delivers less than its simulation, the received
+    // amount is deposited across the custody contracts by weight
+    deps.querier.with_custody_contracts(
+        "bluna",
+        &[
+            ("custody_bluna", Decimal256::one()),
+            ("custody_bluna2", Decimal256::from_uint256(3u64)),
+        ],
+    );
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(400001u128))],
+    )]);
+    let route_reply = |id: u64| Reply {
+        id,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), env.clone(), route_reply(2)).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "custody_bluna".to_string(),
+                    amount: Uint128::from(100000u128),
+                    msg: to_binary(&CustodyCw20HookMsg::DepositCollateral {}).unwrap(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "custody_bluna2".to_string(),
+                    amount: Uint128::from(300001u128),
+                    msg: to_binary(&CustodyCw20HookMsg::DepositCollateral {}).unwrap(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "overseer".to_string(),
+                funds: vec![],
+                msg: to_binary(&OverseerExecuteMsg::LockCollateral {
+                    collaterals: vec![("bluna".to_string(), Uint256::from(400001u64))],
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "lock_deployed_reserves"),
+            attr("collateral_token", "bluna"),
+            attr("acquired_amount", "400001"),
+        ]
+    );
+
+    let res = reply(deps.as_mut(), env.clone(), route_reply(2)).unwrap_err();
+    assert_eq!(res, ContractError::InvalidReplyId {});
+
+    // the recall withdraws from the custody contracts holding collateral of the market
+    let msg = ExecuteMsg::RecallReserves {
+        collateral_token: "bluna".to_string(),
+        amount: Uint256::from(100000u64),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    deps.querier.with_custody_balance(
+        "custody_bluna2",
+        MOCK_CONTRACT_ADDR,
+        Uint256::from(300001u64),
+    );
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "overseer".to_string(),
+                funds: vec![],
+                msg: to_binary(&OverseerExecuteMsg::UnlockCollateral {
+                    collaterals: vec![("bluna".to_string(), Uint256::from(100000u64))],
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna2".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::WithdrawCollateral { amount: None }).unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::ConvertRecalledReserves {
+                    collateral_token: "bluna".to_string(),
+                    prev_balance: Uint256::from(400001u64),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    let msg = ExecuteMsg::ConvertRecalledReserves {
+        collateral_token: "bluna".to_string(),
+        prev_balance: Uint256::from(400001u64),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(500001u128))],
+    )]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "route".to_string(),
+                    amount: Uint128::from(100000u128),
+                    msg: to_binary(&ConverterCw20HookMsg::Convert {
+                        ask_token: "uusd".to_string(),
+                        recipient: MOCK_CONTRACT_ADDR.to_string(),
+                        msg: None,
+                    })
+                    .unwrap(),
+                })
+                .unwrap(),
+            }),
+            3
+        )]
+    );
+
+    // the stable coins the route delivered return to the reserves
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 150000u128),
+        }],
+    );
+    let res = reply(deps.as_mut(), env, route_reply(3)).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "return_recalled_reserves"),
+            attr("collateral_token", "bluna"),
+            attr("received_amount", "150000"),
+        ]
+    );
+
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.total_reserves, Decimal256::from_uint256(150000u64));
+}
+
+#[test]
+fn deploy_to_strategy() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000u128),
+    }]);
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        yield_strategy: Some("strategy".to_string()),
+        max_deployed_ratio: Some(Decimal256::percent(50)),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(10000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(1000000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::DeployToStrategy {
+        amount: Uint256::from(5000001u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // at most half of the liquidity can be deployed
+    let info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::DeployedRatioExceeded(5000000u128));
+
+    let msg = ExecuteMsg::DeployToStrategy {
+        amount: Uint256::from(4000000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "strategy".to_string(),
+            funds: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(4000000u128),
+            }],
+            msg: to_binary(&StrategyExecuteMsg::Deposit {}).unwrap(),
+        }))]
+    );
+    assert_eq!(
+        read_total_deployed(deps.as_ref().storage),
+        4000000u64.into()
+    );
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(6000000u128),
+        }],
+    );
+
+    // the deployment counts toward the cap at the value the strategy reports
+    deps.querier
+        .with_strategy_balance("strategy", Uint256::from(4400000u64));
+    let msg = ExecuteMsg::DeployToStrategy {
+        amount: Uint256::from(800001u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::DeployedRatioExceeded(800000u128));
+
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.total_deployed, Uint256::from(4000000u64));
+
+    let msg = ExecuteMsg::RecallFromStrategy {
+        amount: Uint256::from(4400001u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::RecallExceedsDeployed(4400000u128));
+
+    let msg = ExecuteMsg::RecallFromStrategy {
+        amount: Uint256::from(1000000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "strategy".to_string(),
+                funds: vec![],
+                msg: to_binary(&StrategyExecuteMsg::Withdraw {
+                    amount: Uint256::from(1000000u64),
+                })
+                .unwrap(),
+            }),
+            4
+        )]
+    );
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(7000000u128),
+        }],
+    );
+    let recall_reply = Reply {
+        id: 4,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    reply(deps.as_mut(), env, recall_reply).unwrap();
+    assert_eq!(
+        read_total_deployed(deps.as_ref().storage),
+        3000000u64.into()
+    );
+}
+
+#[test]
+fn accrue_by_time() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        blocks_per_year: 5256000,
+        accrue_by_time: Some(true),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(6))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(10000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.time.seconds(),
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    // blocks passing without time passing accrue nothing
+    env.block.height += 500;
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::one());
+
+    // rate_per_second = 6% * 5256000blocks / 31536000seconds = 0.01
+    // interest_factor = 0.01 * 100seconds = 1
+    env.block.height += 1;
+    env.block.time = env.block.time.plus_seconds(100);
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::from_uint256(2u128));
+    assert_eq!(res.total_liabilities, Decimal256::from_uint256(2000000u128));
+
+    // the same interval over many more blocks accrues the same interest
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.last_interest_updated, env.block.time.seconds());
+
+    env.block.height += 1000;
+    env.block.time = env.block.time.plus_seconds(100);
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::from_uint256(4u128));
+    assert_eq!(res.total_liabilities, Decimal256::from_uint256(5000000u128));
+
+    // a future height is projected at 6 seconds per block
+    // interest_factor = 0.01 * (100seconds + 50blocks * 6seconds) = 4
+    let res: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::State {
+                block_height: Some(env.block.height + 50),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::from_uint256(10u128));
+
+    let res: LastAccrualResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::LastAccrual {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        LastAccrualResponse {
+            last_interest_clock: env.block.time.seconds() - 100,
+            accrue_by_time: true,
+            last_reward_updated: env.block.height - 1000,
+        }
+    );
+}
+
+#[test]
+fn assert_max_borrow_factor() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        max_borrow_factor: Decimal256::percent(1),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::zero(),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: mock_env().block.height,
+            last_reward_updated: mock_env().block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(10000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "borrow_stable"),
+            attr("borrower", "addr0000"),
+            attr("borrow_amount", "10000"),
+            attr("borrow_fee", "0"),
+        ]
+    );
+
+    // subtract borrow amount
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT - 10000u128),
+        }],
+    );
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(1u64),
+        to: None,
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    let _uusd_string = "uusd";
+    match res {
+        Err(ContractError::MaxBorrowFactorReached(_uusd_string)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn min_liquidity_ratio() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        min_liquidity_ratio: Decimal256::percent(60),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    let borrow_msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(100000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    // liquidity_ratio = 1000000 / (1000000 + 0) = 1
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), borrow_msg.clone()).unwrap();
+
+    // liquidity_ratio = 500000 / (500000 + 100000) = 0.833
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(500000u128),
+        }],
+    );
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), borrow_msg.clone()).unwrap();
+
+    // liquidity_ratio = 250000 / (250000 + 200000) = 0.555
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(250000u128),
+        }],
+    );
+    let state: StateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::State { block_height: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        state.liquidity_ratio,
+        Decimal256::from_ratio(250000u64, 450000u64)
+    );
+
+    let res = execute(deps.as_mut(), mock_env(), info, borrow_msg.clone());
+    match res {
+        Err(ContractError::LiquidityBelowMinimum {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // repays are still allowed
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100000u128),
+        }],
+    );
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // liquidity_ratio = 350000 / (350000 + 100000) = 0.777
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(350000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        borrow_msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "borrow_stable"),
+            attr("borrower", "addr0000"),
+            attr("borrow_amount", "100000"),
+            attr("borrow_fee", "0"),
+        ]
+    );
+}
+
+#[test]
+fn can_borrow() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = instantiate_msg();
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    let can_borrow = |deps: Deps, amount: u64| -> CanBorrowResponse {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::CanBorrow {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(amount),
+                sub_account: None,
+            },
+        )
+        .unwrap();
+        from_binary(&res).unwrap()
+    };
+    let update_config = |deps: DepsMut, max_borrow_factor: u64, min_liquidity_ratio: u64| {
+        let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+            owner_addr: None,
+            max_borrow_factor: Some(Decimal256::percent(max_borrow_factor)),
+            interest_model: None,
+            distribution_model: None,
+            min_liquidity_ratio: Some(Decimal256::percent(min_liquidity_ratio)),
+            repay_dust_epsilon: None,
+            blocks_per_year: None,
+            borrow_fee: None,
+            accrual_block_threshold: None,
+            accrue_by_time: None,
+            reserve_route: None,
+            flash_fee: None,
+            accepted_repay_denoms: None,
+            yield_strategy: None,
+            max_deployed_ratio: None,
+            rate_discount_source: None,
+            rate_discount_tiers: None,
+            min_repay_amount: None,
+            repay_protocol_fee: None,
+            commitment_fee: None,
+            max_total_borrow: None,
+            same_block_repay_policy: None,
+        }));
+        execute(deps, mock_env(), mock_info("owner", &[]), msg).unwrap();
+    };
+
+    assert_eq!(
+        can_borrow(deps.as_ref(), 100000),
+        CanBorrowResponse {
+            allowed: true,
+            reason: None,
+        }
+    );
+
+    // same result as the borrow itself
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(1000001u64),
+        to: None,
+        sub_account: None,
+    };
+    let err = execute(deps.as_mut(), env, info, msg).unwrap_err();
+    assert_eq!(
+        can_borrow(deps.as_ref(), 1000001),
+        CanBorrowResponse {
+            allowed: false,
+            reason: Some(err.to_string()),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        ContractError::BorrowExceedsLimit(1000000u128).to_string()
+    );
+
+    // liquidity_ratio = 1000000 / (1000000 + 1000000) = 0.5
+    update_config(deps.as_mut(), 100, 60);
+    let mut state = read_state(deps.as_ref().storage).unwrap();
+    state.total_liabilities = Decimal256::from_uint256(1000000u64);
+    store_state(deps.as_mut().storage, &state).unwrap();
+    assert_eq!(
+        can_borrow(deps.as_ref(), 100000).reason,
+        Some(ContractError::LiquidityBelowMinimum {}.to_string())
+    );
+
+    state.total_liabilities = Decimal256::zero();
+    store_state(deps.as_mut().storage, &state).unwrap();
+    update_config(deps.as_mut(), 1, 0);
+    assert_eq!(
+        can_borrow(deps.as_ref(), 100000).reason,
+        Some(ContractError::MaxBorrowFactorReached("uusd".to_string()).to_string())
+    );
+
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(2000000u64))]);
+    update_config(deps.as_mut(), 200, 0);
+    assert_eq!(
+        can_borrow(deps.as_ref(), 1000001).reason,
+        Some(ContractError::NoStableAvailable("uusd".to_string()).to_string())
+    );
+}
+
+#[test]
+fn repay_stable() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    info.funds = vec![Coin {
+        denom: "ukrw".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::UnacceptedRepayDenom(denom)) => assert_eq!(denom, "ukrw"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let _uusd_string = "uusd";
+
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::zero(),
+    }];
+
+    let res2 = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res2 {
+        Err(ContractError::ZeroRepay(_uusd_string)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+        }],
+    );
+
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "100000"),
+        ]
+    );
+
+    //Loan amount and Total liability have decreased according to the repayment
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .get(0)
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::from(400000u128));
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_liabilities,
+        Decimal256::from_uint256(2400000u128)
+    );
+
+    // collateral is unlocked by the repaid ratio through the overseer
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::RepayStable {
+            auto_unlock: Some(true),
+            sub_account: None,
+            overpay_action: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "overseer".to_string(),
+            funds: vec![],
+            msg: to_binary(&OverseerExecuteMsg::UnlockCollateralFromRepay {
+                borrower: "addr0000".to_string(),
+                prev_loan_amount: Uint256::from(400000u128),
+                repay_amount: Uint256::from(100000u128),
+                sub_account: None,
+            })
+            .unwrap(),
+        }))]
+    );
+
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(500000u128),
+    }];
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "300000"),
+        ]
+    );
+
+    //Loan amount and Total liability have decreased according to the repayment
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .get(0)
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::zero());
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_liabilities,
+        Decimal256::from_uint256(2000000u128)
+    );
+
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(200000u128),
+                }
+            )
+            .unwrap()]
+        }))]
+    );
+}
+
+#[test]
+fn repay_stable_overpay_action() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // the excess is refunded
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 600000u128),
+        }],
+    );
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(600000u128),
+    }];
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: Some(OverpayAction::Refund),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "500000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(100000u128),
+                }
+            )
+            .unwrap()]
+        }))]
+    );
+    assert_eq!(
+        read_total_repay_credit(deps.as_ref().storage),
+        Uint256::zero()
+    );
+
+    // the excess is kept as credit
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 600000u128),
+        }],
+    );
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: Some(OverpayAction::Credit),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "500000"),
+            attr("credited_amount", "100000"),
+        ]
+    );
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_liabilities,
+        Decimal256::from_uint256(2000000u128)
+    );
+    assert_eq!(
+        read_total_repay_credit(deps.as_ref().storage),
+        Uint256::from(100000u64)
+    );
+
+    // a repaid borrower can take the credit back
+    let msg = ExecuteMsg::WithdrawRepayCredit {
+        amount: Some(Uint256::from(100001u64)),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::InvalidRepayCreditWithdraw(100000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::WithdrawRepayCredit {
+        amount: Some(Uint256::from(40000u64)),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(39603u128),
+            }]
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "withdraw_repay_credit"),
+            attr("borrower", "addr0000"),
+            attr("amount", "40000"),
+        ]
+    );
+    assert_eq!(
+        read_total_repay_credit(deps.as_ref().storage),
+        Uint256::from(60000u64)
+    );
+
+    // the rest is kept for the next loan
+    let msg = ExecuteMsg::WithdrawRepayCredit { amount: None };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        read_total_repay_credit(deps.as_ref().storage),
+        Uint256::zero()
+    );
+    let msg = ExecuteMsg::WithdrawRepayCredit { amount: None };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::InvalidRepayCreditWithdraw(0)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // a repayment without a loan is all credit
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: Some(OverpayAction::Credit),
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(100000u128),
+            }],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        read_total_repay_credit(deps.as_ref().storage),
+        Uint256::from(100000u64)
+    );
+
+    // the credit pays the interest of the next loan first
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+        }],
+    );
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // 10% interest over 10 blocks
+    env.block.height += 10;
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let borrower_info: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(borrower_info.loan_amount, Uint256::from(500000u64));
+    assert_eq!(borrower_info.repay_credit, Uint256::from(50000u64));
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 110000u128),
+        }],
+    );
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000u128),
+    }];
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let borrower_info = read_borrower_infos(deps.as_ref(), None, None).unwrap()[0].clone();
+    assert_eq!(borrower_info.loan_amount, Uint256::from(490000u64));
+    assert_eq!(borrower_info.repay_credit, Uint256::from(50000u64));
+
+    // the spent credit settled accrued liabilities
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_liabilities,
+        Decimal256::from_uint256(2690000u128)
+    );
+    assert_eq!(
+        read_total_repay_credit(deps.as_ref().storage),
+        Uint256::from(50000u64)
+    );
+
+    // an exhausted credit leaves the rest of the interest on the loan
+    env.block.height += 20;
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let borrower_info: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(borrower_info.loan_amount, Uint256::from(538000u64));
+    assert_eq!(borrower_info.repay_credit, Uint256::zero());
+}
+
+#[test]
+fn refinance() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+        }],
+    );
+
+    // attached funds must match the repay amount
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(50000u128),
+    }];
+    let msg = ExecuteMsg::Refinance {
+        repay_amount: Uint256::from(100000u64),
+        new_borrow_amount: Uint256::from(500000u64),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::InvalidRefinanceFunds(100000, _)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the resulting loan of 1,100,000 exceeds the borrow limit
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+    let over_limit_msg = ExecuteMsg::Refinance {
+        repay_amount: Uint256::from(100000u64),
+        new_borrow_amount: Uint256::from(700000u64),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), over_limit_msg);
+    match res {
+        Err(ContractError::BorrowExceedsLimit(1000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "refinance"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "100000"),
+            attr("borrow_amount", "500000"),
+            attr("borrow_fee", "0"),
+            attr("loan_amount_before", "500000"),
+            attr("loan_amount_after", "900000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(500000u128),
+                }
+            )
+            .unwrap()],
+        }))]
+    );
+
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::from(900000u128));
+}
+
+#[test]
+fn flash_deleverage() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        flash_fee: Some(Decimal256::percent(1)),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+        }],
+    );
+
+    let callback = to_binary(&"rescue").unwrap();
+    let msg = ExecuteMsg::FlashDeleverage {
+        borrow_amount: Uint256::from(200000u64),
+        callback: callback.clone(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "addr0000".to_string(),
+                funds: vec![],
+                msg: callback,
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::SettleFlashDeleverage {}).unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "flash_deleverage"),
+            attr("borrower", "addr0000"),
+            attr("borrow_amount", "200000"),
+            attr("flash_fee", "2000"),
+            attr("loan_amount_before", "500000"),
+            attr("loan_amount_after", "300000"),
+        ]
+    );
+
+    // the loan is already repaid while the callback runs
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::from(300000u128));
+    let borrower_raw = deps.api.addr_canonicalize("addr0000").unwrap();
+    assert_eq!(
+        read_loan_principal(deps.as_ref().storage, &borrower_raw, None).unwrap(),
+        Some(Uint256::from(300000u128))
+    );
+
+    // no other operation until it is settled
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::FlashDeleverageInProgress {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let deposit_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(202000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        deposit_info,
+        ExecuteMsg::DepositStable {},
+    );
+    match res {
+        Err(ContractError::FlashDeleverageInProgress {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // only the market itself can settle
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        ExecuteMsg::SettleFlashDeleverage {},
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the callback paid back the flash amount without the fee
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128 + 201000u128),
+        }],
+    );
+    let contract_info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        contract_info.clone(),
+        ExecuteMsg::SettleFlashDeleverage {},
+    );
+    match res {
+        Err(ContractError::FlashDeleverageShortfall(1000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the excess over the flash amount and fee is refunded
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128 + 212000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        contract_info,
+        ExecuteMsg::SettleFlashDeleverage {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(10000u128),
+                }
+            )
+            .unwrap()],
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "settle_flash_deleverage"),
+            attr("borrower", "addr0000"),
+            attr("flash_fee", "2000"),
+            attr("refund_amount", "10000"),
+        ]
+    );
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_reserves,
+        Decimal256::from_uint256(2000u64)
+    );
+
+    // the market is usable again
+    let deposit_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(202000u128),
+        }],
+    );
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        deposit_info,
+        ExecuteMsg::DepositStable {},
+    )
+    .unwrap();
+
+    // a partial flash repayment is held to the minimum repay amount
+    let mut config = read_config(deps.as_ref().storage).unwrap();
+    config.min_repay_amount = Uint256::from(150000u64);
+    store_config(deps.as_mut().storage, &config).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("addr0000", &[]),
+        ExecuteMsg::FlashDeleverage {
+            borrow_amount: Uint256::from(100000u64),
+            callback: to_binary(&"rescue").unwrap(),
+        },
+    );
+    match res {
+        Err(ContractError::RepayBelowMinimum(150000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn set_leverage() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = InstantiateMsg {
+        reserve_route: Some("route".to_string()),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(900u64))]);
+    // The route acquires 1 bluna for 2 stable coins, its oracle price
+    deps.querier.with_position_collaterals(&[(
+        &"addr0000".to_string(),
+        &vec![(
+            "bluna".to_string(),
+            Uint256::from(500u64),
+            Decimal256::from_uint256(2u64),
+        )],
+    )]);
+
+    let msg = ExecuteMsg::SetLeverage {
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::one(),
+        max_iterations: 5,
+        slippage: Decimal256::percent(1),
+    };
+    match execute(deps.as_mut(), env.clone(), info.clone(), msg) {
+        Err(ContractError::InvalidTargetLeverage {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::ContinueLeverage {
+        borrower: "addr0000".to_string(),
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::from_uint256(3u64),
+        iterations_left: 5,
+        slippage: Decimal256::percent(1),
+    };
+    match execute(deps.as_mut(), env.clone(), info.clone(), msg) {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // 1000 of collateral value at 1x, capped by the 900 borrow limit
+    let msg = ExecuteMsg::SetLeverage {
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::from_uint256(3u64),
+        max_iterations: 5,
+        slippage: Decimal256::percent(1),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "route".to_string(),
+                funds: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(900u128),
+                }],
+                msg: to_binary(&ConverterExecuteMsg::ConvertNative {
+                    ask_token: "bluna".to_string(),
+                    recipient: MOCK_CONTRACT_ADDR.to_string(),
+                    msg: None,
+                    min_receive: Some(Uint256::from(445u64)),
+                })
+                .unwrap(),
+            }),
+            5
+        )]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "set_leverage_step"),
+            attr("borrower", "addr0000"),
+            attr("leverage", "1"),
+            attr("borrow_amount", "900"),
+            attr("borrow_fee", "0"),
+            attr("min_receive", "445"),
+        ]
+    );
+
+    // The delivered collateral is locked, not the simulated one
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(448u128))],
+    )]);
+    let route_reply = Reply {
+        id: 5,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), env.clone(), route_reply.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "custody_bluna".to_string(),
+                    amount: Uint128::from(448u128),
+                    msg: to_binary(&CustodyCw20HookMsg::DepositCollateralFor {
+                        borrower: "addr0000".to_string(),
+                    })
+                    .unwrap(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "overseer".to_string(),
+                funds: vec![],
+                msg: to_binary(&OverseerExecuteMsg::LockCollateralFor {
+                    borrower: "addr0000".to_string(),
+                    collaterals: vec![("bluna".to_string(), Uint256::from(448u64))],
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::ContinueLeverage {
+                    borrower: "addr0000".to_string(),
+                    collateral_token: "bluna".to_string(),
+                    target_leverage: Decimal256::from_uint256(3u64),
+                    iterations_left: 4,
+                    slippage: Decimal256::percent(1),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "lock_leverage_step"),
+            attr("borrower", "addr0000"),
+            attr("acquired_amount", "448"),
+        ]
+    );
+    let res = reply(deps.as_mut(), env.clone(), route_reply.clone()).unwrap_err();
+    assert_eq!(res, ContractError::InvalidReplyId {});
+
+    // Each step relocks the acquired collateral, raising the borrow limit
+    let self_info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+    for (collateral_amount, borrow_limit, iterations_left, leverage, borrow_amount) in [
+        (950u64, 1710u64, 4u32, "1.9", "810"),
+        (1355u64, 2439u64, 3u32, "2.71", "290"),
+    ] {
+        deps.querier
+            .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(borrow_limit))]);
+        deps.querier.with_position_collaterals(&[(
+            &"addr0000".to_string(),
+            &vec![(
+                "bluna".to_string(),
+                Uint256::from(collateral_amount),
+                Decimal256::from_uint256(2u64),
+            )],
+        )]);
+        let msg = ExecuteMsg::ContinueLeverage {
+            borrower: "addr0000".to_string(),
+            collateral_token: "bluna".to_string(),
+            target_leverage: Decimal256::from_uint256(3u64),
+            iterations_left,
+            slippage: Decimal256::percent(1),
+        };
+        let res = execute(deps.as_mut(), env.clone(), self_info.clone(), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.attributes[2], attr("leverage", leverage));
+        assert_eq!(res.attributes[3], attr("borrow_amount", borrow_amount));
+    }
+
+    // The target is reached, the adjustment finishes
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(2700u64))]);
+    deps.querier.with_position_collaterals(&[(
+        &"addr0000".to_string(),
+        &vec![(
+            "bluna".to_string(),
+            Uint256::from(1500u64),
+            Decimal256::from_uint256(2u64),
+        )],
+    )]);
+    let msg = ExecuteMsg::ContinueLeverage {
+        borrower: "addr0000".to_string(),
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::from_uint256(3u64),
+        iterations_left: 2,
+        slippage: Decimal256::percent(1),
+    };
+    let res = execute(deps.as_mut(), env.clone(), self_info.clone(), msg).unwrap();
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "set_leverage"),
+            attr("borrower", "addr0000"),
+            attr("leverage", "3"),
+        ]
+    );
+    let res: BorrowerInfoResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::BorrowerInfo {
+                borrower: "addr0000".to_string(),
+                block_height: None,
+                sub_account: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.loan_amount, Uint256::from(2000u64));
+
+    // A single iteration stops short of the target
+    deps.querier
+        .with_borrow_limit(&[(&"addr0001".to_string(), &Uint256::from(900u64))]);
+    deps.querier.with_position_collaterals(&[(
+        &"addr0001".to_string(),
+        &vec![(
+            "bluna".to_string(),
+            Uint256::from(500u64),
+            Decimal256::from_uint256(2u64),
+        )],
+    )]);
+    let info = mock_info("addr0001", &[]);
+    let msg = ExecuteMsg::SetLeverage {
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::from_uint256(3u64),
+        max_iterations: 1,
+        slippage: Decimal256::percent(1),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.attributes[3], attr("borrow_amount", "900"));
+
+    deps.querier
+        .with_borrow_limit(&[(&"addr0001".to_string(), &Uint256::from(1710u64))]);
+    deps.querier.with_position_collaterals(&[(
+        &"addr0001".to_string(),
+        &vec![(
+            "bluna".to_string(),
+            Uint256::from(950u64),
+            Decimal256::from_uint256(2u64),
+        )],
+    )]);
+    let msg = ExecuteMsg::ContinueLeverage {
+        borrower: "addr0001".to_string(),
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::from_uint256(3u64),
+        iterations_left: 0,
+        slippage: Decimal256::percent(1),
+    };
+    let res = execute(deps.as_mut(), env.clone(), self_info, msg).unwrap();
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(res.attributes[2], attr("leverage", "1.9"));
+
+    // A delivery worth less than the borrow net of the slippage fails
+    deps.querier
+        .with_borrow_limit(&[(&"addr0002".to_string(), &Uint256::from(900u64))]);
+    deps.querier.with_position_collaterals(&[(
+        &"addr0002".to_string(),
+        &vec![(
+            "bluna".to_string(),
+            Uint256::from(500u64),
+            Decimal256::percent(150),
+        )],
+    )]);
+    let info = mock_info("addr0002", &[]);
+    let msg = ExecuteMsg::SetLeverage {
+        collateral_token: "bluna".to_string(),
+        target_leverage: Decimal256::from_uint256(3u64),
+        max_iterations: 5,
+        slippage: Decimal256::percent(1),
+    };
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(res.attributes[5], attr("min_receive", "594"));
+
+    deps.querier.with_token_balances(&[(
+        &"bluna".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1041u128))],
+    )]);
+    match reply(deps.as_mut(), mock_env(), route_reply) {
+        Err(ContractError::LeverageSlippageExceeded(593)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn accepted_repay_denoms() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        accepted_repay_denoms: Some(vec![
+            "uusd".to_string(),
+            "uwusd".to_string(),
+            "ueur".to_string(),
+        ]),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let config_res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        config_res.accepted_repay_denoms,
+        vec!["uusd".to_string(), "uwusd".to_string(), "ueur".to_string()]
+    );
+
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+
+    // Unlisted denoms are rejected
+    let info = mock_info(
+        "addr0000",
+        &[
+            Coin {
+                denom: "uwusd".to_string(),
+                amount: Uint128::from(100000u128),
+            },
+            Coin {
+                denom: "ukrw".to_string(),
+                amount: Uint128::from(100000u128),
+            },
+        ],
+    );
+    match execute(deps.as_mut(), env.clone(), info, msg.clone()) {
+        Err(ContractError::UnacceptedRepayDenom(denom)) => assert_eq!(denom, "ukrw"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Listed denoms the oracle does not price are rejected
+    deps.querier.with_oracle_price(&[(
+        &("uwusd".to_string(), "uusd".to_string()),
+        &Decimal256::percent(80),
+    )]);
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "ueur".to_string(),
+            amount: Uint128::from(100000u128),
+        }],
+    );
+    match execute(deps.as_mut(), env.clone(), info, msg.clone()) {
+        Err(ContractError::UnpricedRepayDenom(denom)) => assert_eq!(denom, "ueur"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // The wrapped denom repays at its oracle price
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uwusd".to_string(),
+            amount: Uint128::from(100000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "80000"),
+        ]
+    );
+
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::from(420000u128));
+
+    // Held wrapped denoms count toward the market balance
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![
+            Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+            },
+            Coin {
+                denom: "uwusd".to_string(),
+                amount: Uint128::from(100000u128),
+            },
+            Coin {
+                denom: "ueur".to_string(),
+                amount: Uint128::from(3u128),
+            },
+        ],
+    );
+
+    // while an unpriced dust balance is left out instead of failing
+    let config = read_config(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        query_market_balance(deps.as_ref(), &config).unwrap(),
+        Uint256::from(INITIAL_DEPOSIT_AMOUNT + 80000u128)
+    );
+
+    // An overpayment in the wrapped denom is refunded in that denom
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uwusd".to_string(),
+            amount: Uint128::from(600000u128),
+        }],
+    );
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uwusd".to_string(),
+                amount: Uint128::from(75000u128),
+            }],
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "420000"),
+        ]
+    );
+
+    // The stable denom cannot be removed from the list
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: Some(vec!["uwusd".to_string()]),
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    let info = mock_info("owner", &[]);
+    match execute(deps.as_mut(), env, info, msg) {
+        Err(ContractError::InvalidAcceptedRepayDenoms {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn repay_stable_rounding_dust() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = InstantiateMsg {
+        repay_dust_epsilon: Uint256::from(1u64),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // a repayment outside of the epsilon keeps the loan open
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "100000"),
+        ]
+    );
+
+    // leaves 1 uusd of dust, which is forgiven
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(399999u128),
+    }];
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "399999"),
+            attr("rounding_closed", "true"),
+        ]
+    );
+    assert_eq!(res.messages, vec![]);
+
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::zero());
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_liabilities,
+        Decimal256::from_uint256(1000000u128)
+    );
+}
+
+#[test]
+fn min_repay_amount() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        min_repay_amount: Some(Uint256::from(50000u64)),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(30000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 30000u128),
+        }],
+    );
+
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+
+    // a partial repayment below the minimum is rejected
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000u128),
+    }];
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::RepayBelowMinimum(min_repay_amount)) => {
+            assert_eq!(min_repay_amount, 50000u128)
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the overseer repays the liquidation proceeds below the minimum
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("overseer", &[]),
+        ExecuteMsg::RepayStableFromLiquidation {
+            borrower: "addr0000".to_string(),
+            prev_balance: Uint256::from(INITIAL_DEPOSIT_AMOUNT + 20000u128),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.attributes[2], attr("repay_amount", "10000"));
+
+    // closing the loan is accepted below the minimum
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(20000u128),
+    }];
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.attributes[2], attr("repay_amount", "20000"));
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::zero());
+
+    let res = query(deps.as_ref(), env, QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.min_repay_amount, Uint256::from(50000u64));
+}
+
+#[test]
+fn repay_protocol_fee() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        repay_protocol_fee: Some(Decimal256::percent(10)),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT - 500000u128),
+        }],
+    );
+
+    // the 500000 principal accrues 50000 of interest over 10 blocks
+    env.block.height += 10;
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    let repay = |deps: DepsMut, info: &mut MessageInfo, amount: u128| {
+        info.funds = vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(amount),
+        }];
+        let prev_reserves = read_state(deps.storage).unwrap().total_reserves;
+        let res = execute(deps, env.clone(), info.clone(), msg.clone()).unwrap();
+        (res, prev_reserves)
+    };
+
+    // repaying interest alone is taxed in full
+    let (res, prev_reserves) = repay(deps.as_mut(), &mut info, 30000u128);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "30000"),
+            attr("protocol_fee", "3000"),
+        ]
+    );
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_reserves - prev_reserves,
+        Decimal256::from_uint256(3000u64)
+    );
+    let loan_amount = |deps: Deps| read_borrower_infos(deps, None, None).unwrap()[0].loan_amount;
+    assert_eq!(loan_amount(deps.as_ref()), Uint256::from(520000u64));
+
+    // the left 20000 of interest is taxed, the principal part is not,
+    // and the whole repayment lowers the loan
+    let (res, prev_reserves) = repay(deps.as_mut(), &mut info, 100000u128);
+    assert_eq!(res.attributes[3], attr("protocol_fee", "2000"));
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_reserves - prev_reserves,
+        Decimal256::from_uint256(2000u64)
+    );
+    assert_eq!(loan_amount(deps.as_ref()), Uint256::from(420000u64));
+
+    // a principal only repayment pays no fee
+    let (res, prev_reserves) = repay(deps.as_mut(), &mut info, 100000u128);
+    assert_eq!(res.attributes.len(), 3);
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_reserves,
+        prev_reserves
+    );
+    assert_eq!(loan_amount(deps.as_ref()), Uint256::from(320000u64));
+
+    let res = query(deps.as_ref(), env, QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.repay_protocol_fee, Decimal256::percent(10));
+}
+
+#[test]
+fn credit_line() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        blocks_per_year: 1000,
+        commitment_fee: Some(Decimal256::percent(10)),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::zero())]);
+    deps.querier.with_borrow_limit(&[
+        (&"addr0000".to_string(), &Uint256::from(1000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(1000000u64)),
+    ]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::zero(),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    // credit lines are disabled without a commitment fee
+    let mut config = read_config(deps.as_ref().storage).unwrap();
+    config.commitment_fee = Decimal256::zero();
+    store_config(deps.as_mut().storage, &config).unwrap();
+    let msg = ExecuteMsg::OpenCreditLine {
+        amount: Uint256::from(600000u64),
+    };
+    match execute(deps.as_mut(), env.clone(), info.clone(), msg) {
+        Err(ContractError::CreditLinesDisabled {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    config.commitment_fee = Decimal256::percent(10);
+    store_config(deps.as_mut().storage, &config).unwrap();
+
+    // the commitment cannot exceed the borrow limit
+    let msg = ExecuteMsg::OpenCreditLine {
+        amount: Uint256::from(1000001u64),
+    };
+    match execute(deps.as_mut(), env.clone(), info.clone(), msg) {
+        Err(ContractError::BorrowExceedsLimit(limit)) => assert_eq!(limit, 1000000u128),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::OpenCreditLine {
+        amount: Uint256::from(600000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "open_credit_line"),
+            attr("borrower", "addr0000"),
+            attr("committed", "600000"),
+            attr("commitment_fee", "0"),
+        ]
+    );
+
+    // the commitment is reserved from the capacity of other borrowers
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res {
+        Err(ContractError::MaxBorrowFactorReached(denom)) => assert_eq!(denom, "uusd"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // 10% a year over 100 of the 1000 blocks of a year
+    env.block.height += 100;
+    let query_credit_line = |deps: Deps, env: Env| -> CreditLineResponse {
+        let msg = QueryMsg::CreditLine {
+            borrower: "addr0000".to_string(),
+        };
+        from_binary(&query(deps, env, msg).unwrap()).unwrap()
+    };
+    assert_eq!(
+        query_credit_line(deps.as_ref(), env.clone()),
+        CreditLineResponse {
+            borrower: "addr0000".to_string(),
+            committed: Uint256::from(600000u64),
+            accrued_fee: Uint256::from(6000u64),
+            total_committed: Uint256::from(600000u64),
+        }
+    );
+
+    let msg = ExecuteMsg::DrawCredit {
+        amount: Uint256::from(600001u64),
+        to: None,
+    };
+    match execute(deps.as_mut(), env.clone(), info.clone(), msg) {
+        Err(ContractError::DrawExceedsCreditLine(committed)) => {
+            assert_eq!(committed, 600000u128)
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the accrued fee is kept from the drawn funds
+    let msg = ExecuteMsg::DrawCredit {
+        amount: Uint256::from(200000u64),
+        to: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "draw_credit"),
+            attr("borrower", "addr0000"),
+            attr("borrow_amount", "200000"),
+            attr("borrow_fee", "0"),
+            attr("commitment_fee", "6000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(194000u128),
+                }
+            )
+            .unwrap()],
+        }))]
+    );
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.total_liabilities, Decimal256::from_uint256(200000u64));
+    assert_eq!(state.total_reserves, Decimal256::from_uint256(6000u64));
+
+    // only the undrawn 400000 accrues the fee
+    env.block.height += 50;
+    assert_eq!(
+        query_credit_line(deps.as_ref(), env.clone()),
+        CreditLineResponse {
+            borrower: "addr0000".to_string(),
+            committed: Uint256::from(400000u64),
+            accrued_fee: Uint256::from(2000u64),
+            total_committed: Uint256::from(400000u64),
+        }
+    );
+
+    // resizing the commitment pays the accrued fee first
+    let msg = ExecuteMsg::OpenCreditLine {
+        amount: Uint256::from(100000u64),
+    };
+    match execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()) {
+        Err(ContractError::CommitmentFeeUnpaid(fee)) => assert_eq!(fee, 2000u128),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(3000u128),
+    }];
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.attributes[3], attr("commitment_fee", "2000"));
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(1000u128),
+                }
+            )
+            .unwrap()],
+        }))]
+    );
+    assert_eq!(
+        query_credit_line(deps.as_ref(), env).total_committed,
+        Uint256::from(100000u64)
+    );
+    assert_eq!(
+        read_state(deps.as_ref().storage).unwrap().total_reserves,
+        Decimal256::from_uint256(8000u64)
+    );
+}
+
+#[test]
+fn max_total_borrow() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        blocks_per_year: 1000,
+        max_total_borrow: Some(Uint256::from(800000u64)),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::zero())]);
+    deps.querier.with_borrow_limit(&[
+        (&"addr0000".to_string(), &Uint256::from(1000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(1000000u64)),
+    ]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::zero(),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // the borrow is within the limit of the borrower but breaches the ceiling
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(300001u64),
+        to: None,
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res {
+        Err(ContractError::MaxTotalBorrowReached(ceiling)) => assert_eq!(ceiling, 800000u128),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(300000u64),
+        to: None,
+        sub_account: None,
+    };
+    execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    let res = query(deps.as_ref(), env, QueryMsg::State { block_height: None }).unwrap();
+    let state_res: StateResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        state_res.total_liabilities,
+        Decimal256::from_uint256(800000u64)
+    );
+    assert_eq!(state_res.max_total_borrow, Some(Uint256::from(800000u64)));
+}
+
+#[test]
+fn borrow_rate_premium() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        blocks_per_year: 1000,
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::zero())]);
+    deps.querier.with_borrow_limit(&[
+        (&"addr0000".to_string(), &Uint256::from(1000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(1000000u64)),
+    ]);
+    // the collaterals of addr0000 carry a premium of 1% per block
+    deps.querier
+        .with_borrow_rate_premium(&[(&"addr0000".to_string(), &Decimal256::percent(1))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::zero(),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    for borrower in ["addr0000", "addr0001"] {
+        let msg = ExecuteMsg::BorrowStable {
+            borrow_amount: Uint256::from(100000u64),
+            to: None,
+            sub_account: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(borrower, &[]), msg).unwrap();
+    }
+
+    // only the position backed by the premium collaterals accrues the premium
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: Some(env.block.height + 10u64),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(liability.loan_amount, Uint256::from(110000u64));
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0001".to_string(),
+            block_height: Some(env.block.height + 10u64),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(liability.loan_amount, Uint256::from(100000u64));
+
+    // the premium accrues into the loan and the total liabilities on the next borrow
+    let mut env = env;
+    env.block.height += 10;
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(10000u64),
+        to: None,
+        sub_account: None,
+    };
+    execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(liability.loan_amount, Uint256::from(120000u64));
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.total_liabilities, Decimal256::from_uint256(220000u64));
+
+    // switching to time accrual keeps the 5 blocks of premium pending
+    env.block.height += 5;
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: Some(true),
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    // 6000 of premium, less the rounding of the per-second rate
+    assert_eq!(liability.loan_amount, Uint256::from(125999u64));
+}
+
+#[test]
+fn same_block_repay_policy() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        blocks_per_year: 1000,
+        same_block_repay_policy: Some(SameBlockRepayPolicy::Block),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::zero())]);
+    deps.querier.with_borrow_limit(&[
+        (&"addr0000".to_string(), &Uint256::from(1000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(1000000u64)),
+    ]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::zero(),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        sub_account: None,
+        to: None,
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let repay_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100000u64),
+        }],
+    );
+    let repay_msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+
+    // the repayment in the block of the borrow is rejected
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        repay_info.clone(),
+        repay_msg.clone(),
+    );
+    match res {
+        Err(ContractError::SameBlockRepay {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // a flash deleverage in the block of the borrow is rejected as well
+    let msg = ExecuteMsg::FlashDeleverage {
+        borrow_amount: Uint256::from(100000u64),
+        callback: to_binary(&"rescue").unwrap(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::SameBlockRepay {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the overseer settles a liquidation in the same block regardless
+    let msg = ExecuteMsg::RepayStableFromLiquidation {
+        borrower: "addr0000".to_string(),
+        prev_balance: Uint256::from(INITIAL_DEPOSIT_AMOUNT - 100000u128),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("overseer", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "100000"),
+        ]
+    );
+
+    // the next block repays as usual
+    env.block.height += 1;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        repay_info.clone(),
+        repay_msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "100000"),
+        ]
+    );
+
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        same_block_repay_policy: Some(SameBlockRepayPolicy::Fee {
+            rate: Decimal256::percent(101),
+        }),
+        accepted_repay_denoms: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        commitment_fee: None,
+        distribution_model: None,
+        flash_fee: None,
+        interest_model: None,
+        max_borrow_factor: None,
+        max_deployed_ratio: None,
+        max_total_borrow: None,
+        min_liquidity_ratio: None,
+        min_repay_amount: None,
+        owner_addr: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        repay_dust_epsilon: None,
+        repay_protocol_fee: None,
+        reserve_route: None,
+        yield_strategy: None,
+    }));
+    let res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::InvalidSameBlockRepayFee {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        same_block_repay_policy: Some(SameBlockRepayPolicy::Fee {
+            rate: Decimal256::percent(1),
+        }),
+        accepted_repay_denoms: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        commitment_fee: None,
+        distribution_model: None,
+        flash_fee: None,
+        interest_model: None,
+        max_borrow_factor: None,
+        max_deployed_ratio: None,
+        max_total_borrow: None,
+        min_liquidity_ratio: None,
+        min_repay_amount: None,
+        owner_addr: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        repay_dust_epsilon: None,
+        repay_protocol_fee: None,
+        reserve_route: None,
+        yield_strategy: None,
+    }));
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+
+    // a fee is kept as reserves out of a same block repayment
+    env.block.height += 1;
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(100000u64),
+        sub_account: None,
+        to: None,
+    };
+    execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), repay_info, repay_msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "99000"),
+            attr("same_block_fee", "1000"),
+        ]
+    );
+
+    let res = query(deps.as_ref(), env, QueryMsg::State { block_height: None }).unwrap();
+    let state_res: StateResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        state_res.total_liabilities,
+        Decimal256::from_uint256(301000u64)
+    );
+    assert_eq!(state_res.total_reserves, Decimal256::from_uint256(1000u64));
+}
+
+#[test]
+fn repay_stable_from_liquidation() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // update balance to make repay
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![
+            Coin {
                 denom: "ukrw".to_string(),
                 amount: Uint128::from(100000u128),
             },
-            Coin {
+            Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+            },
+        ],
+    );
+
+    let msg = ExecuteMsg::RepayStableFromLiquidation {
+        borrower: "addr0000".to_string(),
+        prev_balance: Uint256::from(INITIAL_DEPOSIT_AMOUNT),
+        sub_account: None,
+    };
+
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info("overseer", &[]);
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    let _uusd_string = "uusd";
+    match res {
+        Err(ContractError::ZeroRepay(__uusd_string)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // update balance to make repay
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+        }],
+    );
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "100000"),
+        ]
+    );
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 500000u128),
+        }],
+    );
+
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "400000"),
+        ]
+    );
+
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(100000u128),
+                }
+            )
+            .unwrap()]
+        }))]
+    );
+}
+
+#[test]
+fn repay_stable_on_behalf() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::RepayStableOnBehalf {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let funds = [Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(600000u128),
+    }];
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &funds),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // The attached funds repay the borrower loan and the excess goes back to the borrower
+    let res = execute(deps.as_mut(), env, mock_info("overseer", &funds), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_stable"),
+            attr("borrower", "addr0000"),
+            attr("repay_amount", "500000"),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(100000u128),
+                }
+            )
+            .unwrap()]
+        }))]
+    );
+
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::zero());
+}
+
+#[test]
+fn rate_discount() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = InstantiateMsg {
+        rate_discount_source: Some("staking".to_string()),
+        rate_discount_tiers: Some(vec![(1, Decimal256::percent(50))]),
+        ..instantiate_msg()
+    };
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(300000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // a borrower without a tier pays the full interest
+    env.block.height += 100;
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(liability.loan_amount, Uint256::from(1000000u64));
+
+    // tier 1 gets half of the 500000 interest rebated
+    deps.querier
+        .with_staker_tiers(&[(&"addr0000".to_string(), 1)]);
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowerInfo {
+            borrower: "addr0000".to_string(),
+            block_height: None,
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let liability: BorrowerInfoResponse = from_binary(&res).unwrap();
+    assert_eq!(liability.loan_amount, Uint256::from(750000u64));
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+        }],
+    );
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: None,
+        sub_account: None,
+        overpay_action: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // the rebate leaves the liabilities together with the reserves
+    let res_loan = read_borrower_infos(deps.as_ref(), None, None)
+        .unwrap()
+        .first()
+        .unwrap()
+        .loan_amount;
+    assert_eq!(res_loan, Uint256::from(650000u128));
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        state.total_liabilities,
+        Decimal256::from_uint256(2650000u128)
+    );
+    assert_eq!(state.total_reserves, Decimal256::from_uint256(50000u128));
+
+    // discounts cannot exceed the interest
+    info.sender = Addr::unchecked("owner");
+    info.funds = vec![];
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: None,
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: Some(vec![(1, Decimal256::percent(101))]),
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    let res = execute(deps.as_mut(), env, info, msg);
+    match res {
+        Err(ContractError::InvalidRateDiscount {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn transfer_loan() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::TransferLoan {
+        borrower: "addr0000".to_string(),
+        recipient: "addr0001".to_string(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the loan accrues interest until it moves
+    env.block.height += 100;
+    let res = execute(deps.as_mut(), env.clone(), mock_info("overseer", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "transfer_loan"),
+            attr("borrower", "addr0000"),
+            attr("recipient", "addr0001"),
+            attr("loan_amount", "1000000"),
+        ]
+    );
+    assert_eq!(res.messages, vec![]);
+
+    let loan_amount = |borrower: &str| {
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::BorrowerInfo {
+                borrower: borrower.to_string(),
+                block_height: None,
+                sub_account: None,
+            },
+        )
+        .unwrap();
+        from_binary::<BorrowerInfoResponse>(&res)
+            .unwrap()
+            .loan_amount
+    };
+    assert_eq!(loan_amount("addr0000"), Uint256::zero());
+    assert_eq!(loan_amount("addr0001"), Uint256::from(1000000u64));
+}
+
+#[test]
+fn split_loan() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+
+    env.block.height += 100;
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let loan_amount = |deps: Deps, env: Env, sub_account: Option<&str>| {
+        let res = query(
+            deps,
+            env,
+            QueryMsg::BorrowerInfo {
+                borrower: "addr0000".to_string(),
+                block_height: None,
+                sub_account: sub_account.map(|s| s.to_string()),
+            },
+        )
+        .unwrap();
+        from_binary::<BorrowerInfoResponse>(&res)
+            .unwrap()
+            .loan_amount
+    };
+
+    let msg = ExecuteMsg::SplitLoan {
+        borrower: "addr0000".to_string(),
+        from_sub_account: None,
+        to_sub_account: "split".to_string(),
+        fraction: Decimal256::percent(40),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the loan accrues interest until it is split
+    env.block.height += 100;
+    let total_loan_amount = loan_amount(deps.as_ref(), env.clone(), None);
+    let res = execute(deps.as_mut(), env.clone(), mock_info("overseer", &[]), msg).unwrap();
+    let split_loan_amount = total_loan_amount * Decimal256::percent(40);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "split_loan"),
+            attr("borrower", "addr0000"),
+            attr("from_sub_account", ""),
+            attr("to_sub_account", "split"),
+            attr("loan_amount", split_loan_amount.to_string()),
+        ]
+    );
+
+    // the positions together owe the loan before the split
+    assert_eq!(
+        loan_amount(deps.as_ref(), env.clone(), Some("split")),
+        split_loan_amount
+    );
+    assert_eq!(
+        loan_amount(deps.as_ref(), env.clone(), None)
+            + loan_amount(deps.as_ref(), env, Some("split")),
+        total_loan_amount
+    );
+}
+
+#[test]
+fn sub_account_liabilities() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = instantiate_msg();
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: Some("hedge".to_string()),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let query_loan_amount = |deps: Deps, sub_account: Option<&str>| -> Uint256 {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowerInfo {
+                borrower: "addr0000".to_string(),
+                block_height: None,
+                sub_account: sub_account.map(|s| s.to_string()),
+            },
+        )
+        .unwrap();
+        let borrower_info_res: BorrowerInfoResponse = from_binary(&res).unwrap();
+        borrower_info_res.loan_amount
+    };
+    assert_eq!(query_loan_amount(deps.as_ref(), None), Uint256::zero());
+    assert_eq!(
+        query_loan_amount(deps.as_ref(), Some("hedge")),
+        Uint256::from(500000u64)
+    );
+
+    // sub-account positions are paged along with the default positions
+    assert_eq!(
+        read_borrower_infos(deps.as_ref(), None, None).unwrap(),
+        vec![BorrowerInfoResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: Some("hedge".to_string()),
+            interest_index: Decimal256::one(),
+            reward_index: Decimal256::zero(),
+            loan_amount: Uint256::from(500000u64),
+            pending_rewards: Decimal256::zero(),
+            interest_free_allowance: Uint256::zero(),
+            repay_credit: Uint256::zero(),
+        }]
+    );
+    let addr0000_raw = deps.api.addr_canonicalize("addr0000").unwrap();
+    assert_eq!(
+        read_borrower_infos(deps.as_ref(), Some(addr0000_raw), None).unwrap(),
+        vec![]
+    );
+
+    info.funds = vec![Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100000u128),
+    }];
+    let msg = ExecuteMsg::RepayStable {
+        auto_unlock: Some(true),
+        sub_account: Some("hedge".to_string()),
+        overpay_action: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "overseer".to_string(),
+            funds: vec![],
+            msg: to_binary(&OverseerExecuteMsg::UnlockCollateralFromRepay {
+                borrower: "addr0000".to_string(),
+                prev_loan_amount: Uint256::from(500000u128),
+                repay_amount: Uint256::from(100000u128),
+                sub_account: Some("hedge".to_string()),
+            })
+            .unwrap(),
+        }))]
+    );
+    assert_eq!(
+        query_loan_amount(deps.as_ref(), Some("hedge")),
+        Uint256::from(400000u64)
+    );
+}
+
+#[test]
+fn interest_free_allowance() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = instantiate_msg();
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    let msg = ExecuteMsg::GrantInterestFreeAllowance {
+        borrower: "addr0000".to_string(),
+        collateral_token: "bluna".to_string(),
+        amount: Uint256::from(200000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let owner_info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), env.clone(), owner_info.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "grant_interest_free_allowance"),
+            attr("borrower", "addr0000"),
+            attr("collateral_token", "bluna"),
+            attr("amount", "200000"),
+        ]
+    );
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let query_borrower_info = |deps: Deps, block_height: u64| -> BorrowerInfoResponse {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowerInfo {
+                borrower: "addr0000".to_string(),
+                block_height: Some(block_height),
+                sub_account: None,
+            },
+        )
+        .unwrap();
+        from_binary(&res).unwrap()
+    };
+
+    // interest_factor = 1% * 10blocks = 0.1
+    // loan_amount = 200000 + 300000 * 1.1 = 530000
+    let borrower_info = query_borrower_info(deps.as_ref(), env.block.height + 10);
+    assert_eq!(borrower_info.loan_amount, Uint256::from(530000u64));
+    assert_eq!(
+        borrower_info.interest_free_allowance,
+        Uint256::from(200000u64)
+    );
+
+    let state: State = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::State {
+                block_height: Some(env.block.height + 10),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(state.total_liabilities, Decimal256::from_uint256(530000u64));
+
+    // the whole loan accrues interest once revoked
+    let mut env = env;
+    env.block.height += 10;
+    let msg = ExecuteMsg::RevokeInterestFreeAllowance {
+        borrower: "addr0000".to_string(),
+        collateral_token: "bluna".to_string(),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), owner_info, msg).unwrap();
+
+    // loan_amount = 530000 * 1.21 / 1.1 = 583000
+    let borrower_info = query_borrower_info(deps.as_ref(), env.block.height + 10);
+    assert_eq!(borrower_info.loan_amount, Uint256::from(583000u64));
+    assert_eq!(borrower_info.interest_free_allowance, Uint256::zero());
+}
+
+#[test]
+fn claim_rewards() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let info = mock_info("addr0000", &[]);
+    let mut env = mock_env();
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    // zero loan claim, will return empty messages
+    let msg = ExecuteMsg::ClaimRewards {
+        to: None,
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(res.messages.len(), 0);
+
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // zero block passed
+    let msg = ExecuteMsg::ClaimRewards {
+        to: Some("addr0001".to_string()),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(res.messages.len(), 0);
+
+    // 100 blocks passed
+    env.block.height += 100;
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "distributor".to_string(),
+            funds: vec![],
+            msg: to_binary(&FaucetExecuteMsg::Spend {
+                recipient: "addr0001".to_string(),
+                amount: Uint128::from(33u128),
+            })
+            .unwrap(),
+        }))]
+    );
+
+    let res: BorrowerInfoResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::BorrowerInfo {
+                borrower: "addr0000".to_string(),
+                block_height: None,
+                sub_account: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.pending_rewards,
+        Decimal256::from_str("0.333333333333").unwrap()
+    );
+    assert_eq!(
+        res.reward_index,
+        Decimal256::from_str("0.000066666666666666").unwrap()
+    );
+}
+
+#[test]
+fn execute_epoch_operations() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let mut info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    env.block.height += 100;
+
+    // reserve == 3000
+    let msg = ExecuteMsg::ExecuteEpochOperations {
+        deposit_rate: Decimal256::one(),
+        target_deposit_rate: Decimal256::one(),
+        threshold_deposit_rate: Decimal256::one(),
+        distributed_interest: Uint256::zero(),
+    };
+
+    // only overseer can execute this
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    info.sender = Addr::unchecked("overseer");
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "collector".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(2970u128), // 1% tax
+            }],
+        }))]
+    );
+
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        state,
+        State {
+            total_liabilities: Decimal256::from_uint256(2000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::from_uint256(2u64),
+            global_reward_index: Decimal256::from_str("0.0001").unwrap(),
+            anc_emission_rate: Decimal256::from_uint256(5u64),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        }
+    );
+
+    // When there is not enough balance to cover reserve
+    // no message will be sent and reserve will be left as same
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(2999u128),
+        }],
+    );
+
+    let mut env = mock_env();
+    let info = mock_info("overseer", &[]);
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    env.block.height += 100;
+
+    // reserve == 3000
+    let msg = ExecuteMsg::ExecuteEpochOperations {
+        deposit_rate: Decimal256::one(),
+        target_deposit_rate: Decimal256::one(),
+        threshold_deposit_rate: Decimal256::one(),
+        distributed_interest: Uint256::zero(),
+    };
+
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.messages.len(), 0);
+
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        state,
+        State {
+            total_liabilities: Decimal256::from_uint256(2000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::from_uint256(2u64),
+            global_reward_index: Decimal256::from_str("0.0001").unwrap(),
+            anc_emission_rate: Decimal256::from_uint256(5u64),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        }
+    );
+
+    // Stable coins sent by the overseer top up the reserves
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(5000u128),
+        }],
+    );
+
+    let mut env = mock_env();
+    let info = mock_info(
+        "overseer",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1001u128),
+        }],
+    );
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    env.block.height += 100;
+
+    // reserve == 3000 + 1001
+    let msg = ExecuteMsg::ExecuteEpochOperations {
+        deposit_rate: Decimal256::one(),
+        target_deposit_rate: Decimal256::one(),
+        threshold_deposit_rate: Decimal256::one(),
+        distributed_interest: Uint256::zero(),
+    };
+
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.attributes[1], attr("total_reserves", "4001"));
+    assert_eq!(res.attributes[2], attr("reserve_topup", "1001"));
+
+    let state = read_state(deps.as_ref().storage).unwrap();
+    assert_eq!(state.total_reserves, Decimal256::zero());
+}
+
+#[test]
+fn reserve_coverage() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::zero())]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(500u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let reserve_coverage = |deps: Deps, env: Env| -> ReserveCoverageResponse {
+        from_binary(&query(deps, env, QueryMsg::ReserveCoverage {}).unwrap()).unwrap()
+    };
+    assert_eq!(
+        reserve_coverage(deps.as_ref(), env.clone()),
+        ReserveCoverageResponse {
+            total_reserves: Decimal256::from_uint256(500u128),
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            coverage_ratio: Decimal256::from_str("0.0005").unwrap(),
+        }
+    );
+
+    // The balance is too low to send the reserves to the collector,
+    // the top-up stays in the reserves
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1400u128),
+        }],
+    );
+    env.block.height += 100;
+    let msg = ExecuteMsg::ExecuteEpochOperations {
+        deposit_rate: Decimal256::one(),
+        target_deposit_rate: Decimal256::one(),
+        threshold_deposit_rate: Decimal256::one(),
+        distributed_interest: Uint256::zero(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "overseer",
+            &[Coin {
                 denom: "uusd".to_string(),
-                amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
-            },
-        ],
+                amount: Uint128::from(1000u128),
+            }],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 0);
+    assert_eq!(
+        reserve_coverage(deps.as_ref(), env.clone()).coverage_ratio,
+        Decimal256::from_str("0.0015").unwrap()
+    );
+
+    // The liabilities are accrued up to the current block
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    env.block.height += 100;
+    assert_eq!(
+        reserve_coverage(deps.as_ref(), env),
+        ReserveCoverageResponse {
+            total_reserves: Decimal256::from_uint256(1500u128),
+            total_liabilities: Decimal256::from_uint256(2000000u128),
+            coverage_ratio: Decimal256::from_str("0.00075").unwrap(),
+        }
+    );
+}
+
+#[test]
+fn accrued_interest() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::zero(),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::one(),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    // no interest accrued before the first epoch
+    env.block.height += 100;
+    let res: AccruedInterestResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::AccruedInterest {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res,
+        AccruedInterestResponse {
+            accrued_interest: Uint256::zero(),
+            last_epoch_height: None,
+        }
     );
 
-    let msg = ExecuteMsg::RepayStableFromLiquidation {
-        borrower: "addr0000".to_string(),
-        prev_balance: Uint256::from(INITIAL_DEPOSIT_AMOUNT),
+    // 100,000 aTerra worth 21 each after the epoch
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100000u128),
+        }],
+    );
+    deps.querier.with_token_balances(&[(
+        &"AT-uusd".to_string(),
+        &[(&"addr0000".to_string(), &Uint128::from(100000u128))],
+    )]);
+    let msg = ExecuteMsg::ExecuteEpochOperations {
+        deposit_rate: Decimal256::one(),
+        target_deposit_rate: Decimal256::one(),
+        threshold_deposit_rate: Decimal256::one(),
+        distributed_interest: Uint256::zero(),
     };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("overseer", &[]), msg).unwrap();
 
-    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
-    match res {
-        Err(ContractError::Unauthorized {}) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    let res: AccruedInterestResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::AccruedInterest {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res,
+        AccruedInterestResponse {
+            accrued_interest: Uint256::zero(),
+            last_epoch_height: Some(env.block.height),
+        }
+    );
 
-    let info = mock_info("overseer", &[]);
+    // liabilities 2,000,000 => 4,000,000 since the epoch, the depositors
+    // earn the 1% target deposit rate on their 2,100,000 only:
+    // 2,100,000 * 1% * 50 = 1,050,000, the rest goes to the reserves
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(2))]);
+    let epoch_height = env.block.height;
+    env.block.height += 50;
+    let res: AccruedInterestResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::AccruedInterest {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        AccruedInterestResponse {
+            accrued_interest: Uint256::from(1050000u64),
+            last_epoch_height: Some(epoch_height),
+        }
+    );
+}
 
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
-    let _uusd_string = "uusd";
+#[test]
+fn borrow_apr() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // 0.000000047 per block
+    deps.querier.with_borrow_rate(&[(
+        &"interest".to_string(),
+        &Decimal256::from_ratio(47u64, 1000000000u64),
+    )]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::BorrowApr {}).unwrap();
+    let apr_res: BorrowAprResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        apr_res,
+        BorrowAprResponse {
+            borrow_rate: Decimal256::from_ratio(47u64, 1000000000u64),
+            blocks_per_year: 4656810,
+            // 0.000000047 * 4656810
+            apr: Decimal256::from_ratio(21887007u64, 100000000u64),
+        }
+    );
+
+    // zero blocks per year is rejected
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: Some(0),
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg);
     match res {
-        Err(ContractError::ZeroRepay(__uusd_string)) => (),
+        Err(ContractError::InvalidBlocksPerYear {}) => (),
         _ => panic!("DO NOT ENTER HERE"),
     }
 
-    // update balance to make repay
+    let msg = ExecuteMsg::UpdateConfig(Box::new(UpdateConfigMsg {
+        owner_addr: None,
+        interest_model: None,
+        distribution_model: None,
+        max_borrow_factor: None,
+        min_liquidity_ratio: None,
+        repay_dust_epsilon: None,
+        blocks_per_year: Some(5256000),
+        borrow_fee: None,
+        accrual_block_threshold: None,
+        accrue_by_time: None,
+        reserve_route: None,
+        flash_fee: None,
+        accepted_repay_denoms: None,
+        yield_strategy: None,
+        max_deployed_ratio: None,
+        rate_discount_source: None,
+        rate_discount_tiers: None,
+        min_repay_amount: None,
+        repay_protocol_fee: None,
+        commitment_fee: None,
+        max_total_borrow: None,
+        same_block_repay_policy: None,
+    }));
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(5256000, config_res.blocks_per_year);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::BorrowApr {}).unwrap();
+    let apr_res: BorrowAprResponse = from_binary(&res).unwrap();
+    // 0.000000047 * 5256000
+    assert_eq!(apr_res.apr, Decimal256::from_ratio(247032u64, 1000000u64));
+}
+
+#[test]
+fn deposit_apy() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // half of the deposits are borrowed
     deps.querier.update_balance(
-        MOCK_CONTRACT_ADDR.to_string(),
+        MOCK_CONTRACT_ADDR,
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 100000u128),
+            amount: Uint128::from(1000000u128),
         }],
     );
+    let mut state = read_state(deps.as_ref().storage).unwrap();
+    state.total_liabilities = Decimal256::from_uint256(1000000u64);
+    state.total_reserves = Decimal256::zero();
+    store_state(deps.as_mut().storage, &state).unwrap();
+
+    // 0.00000004 per block
+    deps.querier.with_borrow_rate(&[(
+        &"interest".to_string(),
+        &Decimal256::from_ratio(4u64, 100000000u64),
+    )]);
 
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    // (1 + 0.00000002 * 4656810 / 2) ^ 2 - 1
+    // = 1.0465681 ^ 2 - 1
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::DepositApy {
+            compounding_periods: Some(2),
+        },
+    )
+    .unwrap();
+    let apy_res: DepositApyResponse = from_binary(&res).unwrap();
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "repay_stable"),
-            attr("borrower", "addr0000"),
-            attr("repay_amount", "100000"),
-        ]
+        apy_res,
+        DepositApyResponse {
+            deposit_rate: Decimal256::from_ratio(2u64, 100000000u64),
+            compounding_periods: 2,
+            apy: Decimal256::from_str("0.09530478793761").unwrap(),
+        }
+    );
+
+    // (1 + 0.00000002) ^ 4656810 - 1 = 0.097611218713...
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::DepositApy {
+            compounding_periods: None,
+        },
+    )
+    .unwrap();
+    let apy_res: DepositApyResponse = from_binary(&res).unwrap();
+    assert_eq!(apy_res.compounding_periods, 4656810);
+    assert!(apy_res.apy > Decimal256::from_str("0.0976112186").unwrap());
+    assert!(apy_res.apy < Decimal256::from_str("0.0976112188").unwrap());
+
+    // compounding more often than every block is rejected
+    for compounding_periods in [0u64, 4656811u64] {
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::DepositApy {
+                compounding_periods: Some(compounding_periods),
+            },
+        );
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "compounding_periods must be between 1 and 4656810")
+            }
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+    }
+}
+
+#[test]
+fn accrual_params() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
+
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+        }],
+    );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let env = mock_env();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier.with_token_balances(&[(
+        &"AT-uusd".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(2000000u128))],
+    )]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::percent(150),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let res: AccrualParamsResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::AccrualParams {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res,
+        AccrualParamsResponse {
+            global_interest_index: Decimal256::percent(150),
+            last_interest_updated: env.block.height,
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            aterra_supply: Uint256::from(2000000u64),
+            borrow_rate: Decimal256::percent(1),
+        }
     );
+}
+
+#[test]
+fn last_accrual() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
+    }]);
 
-    deps.querier.update_balance(
-        MOCK_CONTRACT_ADDR.to_string(),
-        vec![Coin {
+    let msg = instantiate_msg();
+
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT + 500000u128),
+            amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
         }],
     );
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    // Register anchor token contract
+    let mut token_inst_res = MsgInstantiateContractResponse::new();
+    token_inst_res.set_contract_address("AT-uusd".to_string());
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(token_inst_res.write_to_bytes().unwrap().into()),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // Register overseer contract
+    let msg = ExecuteMsg::RegisterContracts {
+        overseer_contract: "overseer".to_string(),
+        interest_model: "interest".to_string(),
+        distribution_model: "distribution".to_string(),
+        collector_contract: "collector".to_string(),
+        distributor_contract: "distributor".to_string(),
+    };
+    let mut env = mock_env();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier
+        .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
+    deps.querier.with_token_balances(&[(
+        &"AT-uusd".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(2000000u128))],
+    )]);
+
+    store_state(
+        deps.as_mut().storage,
+        &State {
+            total_liabilities: Decimal256::from_uint256(1000000u128),
+            total_reserves: Decimal256::from_uint256(3000u128),
+            last_interest_updated: env.block.height,
+            last_reward_updated: env.block.height,
+            global_interest_index: Decimal256::percent(150),
+            global_reward_index: Decimal256::zero(),
+            anc_emission_rate: Decimal256::one(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+        },
+    )
+    .unwrap();
+
+    let res: LastAccrualResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::LastAccrual {}).unwrap()).unwrap();
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "repay_stable"),
-            attr("borrower", "addr0000"),
-            attr("repay_amount", "400000"),
-        ]
+        res,
+        LastAccrualResponse {
+            last_interest_clock: env.block.height,
+            accrue_by_time: false,
+            last_reward_updated: env.block.height,
+        }
     );
 
+    // borrowing accrues the interest and the rewards
+    deps.querier
+        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+    env.block.height += 100;
+    let msg = ExecuteMsg::BorrowStable {
+        borrow_amount: Uint256::from(500000u64),
+        to: None,
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res: LastAccrualResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::LastAccrual {}).unwrap()).unwrap();
     assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-            to_address: "addr0000".to_string(),
-            amount: vec![deduct_tax(
-                deps.as_ref(),
-                Coin {
-                    denom: "uusd".to_string(),
-                    amount: Uint128::from(100000u128),
-                }
-            )
-            .unwrap()]
-        }))]
+        res,
+        LastAccrualResponse {
+            last_interest_clock: env.block.height,
+            accrue_by_time: false,
+            last_reward_updated: env.block.height,
+        }
     );
+    assert_eq!(res.last_interest_clock, mock_env().block.height + 100);
 }
 
 #[test]
-fn claim_rewards() {
+fn interest_indexes() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
@@ -1549,13 +7646,7 @@ fn claim_rewards() {
         &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
     );
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -1588,8 +7679,8 @@ fn claim_rewards() {
         collector_contract: "collector".to_string(),
         distributor_contract: "distributor".to_string(),
     };
-    let info = mock_info("addr0000", &[]);
     let mut env = mock_env();
+    let info = mock_info("addr0000", &[]);
     let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
     deps.querier
@@ -1613,64 +7704,58 @@ fn claim_rewards() {
     )
     .unwrap();
 
-    // zero loan claim, will return empty messages
-    let msg = ExecuteMsg::ClaimRewards { to: None };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-    assert_eq!(res.messages.len(), 0);
-
     let msg = ExecuteMsg::BorrowStable {
         borrow_amount: Uint256::from(500000u64),
         to: None,
+        sub_account: None,
     };
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-
-    // zero block passed
-    let msg = ExecuteMsg::ClaimRewards {
-        to: Some("addr0001".to_string()),
-    };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
-    assert_eq!(res.messages.len(), 0);
 
-    // 100 blocks passed
     env.block.height += 100;
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // global_interest_index = 2 after accruing 100 blocks at 1%
+    let indexes_query = QueryMsg::InterestIndexes {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res: InterestIndexesResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), indexes_query.clone()).unwrap()).unwrap();
     assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "distributor".to_string(),
-            funds: vec![],
-            msg: to_binary(&FaucetExecuteMsg::Spend {
-                recipient: "addr0001".to_string(),
-                amount: Uint128::from(33u128),
-            })
-            .unwrap(),
-        }))]
+        res,
+        InterestIndexesResponse {
+            borrower: "addr0000".to_string(),
+            global_interest_index: Decimal256::from_uint256(2u128),
+            last_interest_updated: env.block.height,
+            interest_index: Decimal256::from_uint256(2u128),
+            loan_amount: Uint256::from(500000u64),
+        }
     );
 
-    let res: BorrowerInfoResponse = from_binary(
+    // the stored indexes do not accrue on read, unlike the borrower info
+    env.block.height += 100;
+    let res: InterestIndexesResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), indexes_query).unwrap()).unwrap();
+    assert_eq!(res.global_interest_index, Decimal256::from_uint256(2u128));
+    assert_eq!(res.last_interest_updated, env.block.height - 100);
+
+    let borrower_info: BorrowerInfoResponse = from_binary(
         &query(
             deps.as_ref(),
-            mock_env(),
+            env,
             QueryMsg::BorrowerInfo {
                 borrower: "addr0000".to_string(),
                 block_height: None,
+                sub_account: None,
             },
         )
         .unwrap(),
     )
     .unwrap();
-    assert_eq!(
-        res.pending_rewards,
-        Decimal256::from_str("0.333333333333").unwrap()
-    );
-    assert_eq!(
-        res.reward_index,
-        Decimal256::from_str("0.000066666666666666").unwrap()
-    );
+    assert!(borrower_info.interest_index > res.global_interest_index);
 }
 
 #[test]
-fn execute_epoch_operations() {
+fn exchange_rate_between() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
         amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
@@ -1680,13 +7765,7 @@ fn execute_epoch_operations() {
         &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
     );
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        stable_denom: "uusd".to_string(),
-        aterra_code_id: 123u64,
-        anc_emission_rate: Decimal256::one(),
-        max_borrow_factor: Decimal256::one(),
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info(
         "addr0000",
@@ -1695,8 +7774,6 @@ fn execute_epoch_operations() {
             amount: Uint128::from(INITIAL_DEPOSIT_AMOUNT),
         }],
     );
-
-    // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
     // Register anchor token contract
@@ -1720,132 +7797,76 @@ fn execute_epoch_operations() {
         distributor_contract: "distributor".to_string(),
     };
     let mut env = mock_env();
-    let mut info = mock_info("addr0000", &[]);
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
 
     deps.querier
         .with_borrow_rate(&[(&"interest".to_string(), &Decimal256::percent(1))]);
-    deps.querier
-        .with_borrow_limit(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
-
-    store_state(
-        deps.as_mut().storage,
-        &State {
-            total_liabilities: Decimal256::from_uint256(1000000u128),
-            total_reserves: Decimal256::from_uint256(3000u128),
-            last_interest_updated: env.block.height,
-            last_reward_updated: env.block.height,
-            global_interest_index: Decimal256::one(),
-            global_reward_index: Decimal256::zero(),
-            anc_emission_rate: Decimal256::one(),
-            prev_aterra_supply: Uint256::zero(),
-            prev_exchange_rate: Decimal256::one(),
-        },
-    )
-    .unwrap();
-
-    env.block.height += 100;
 
-    // reserve == 3000
-    let msg = ExecuteMsg::ExecuteEpochOperations {
-        deposit_rate: Decimal256::one(),
-        target_deposit_rate: Decimal256::one(),
-        threshold_deposit_rate: Decimal256::one(),
-        distributed_interest: Uint256::zero(),
+    let start_height = env.block.height;
+    let query_exchange_rate_between = |deps: Deps, start_height: u64, end_height: u64| {
+        query(
+            deps,
+            mock_env(),
+            QueryMsg::ExchangeRateBetween {
+                start_height,
+                end_height,
+            },
+        )
+        .map(|res| from_binary::<ExchangeRateBetweenResponse>(&res).unwrap())
     };
 
-    // only overseer can execute this
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
-    match res {
-        Err(ContractError::Unauthorized {}) => (),
+    match query_exchange_rate_between(deps.as_ref(), start_height - 1, start_height) {
+        Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+            msg,
+            format!(
+                "No exchange rate recorded at or before height {}",
+                start_height - 1
+            )
+        ),
         _ => panic!("DO NOT ENTER HERE"),
     }
 
-    info.sender = Addr::unchecked("overseer");
-    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-    assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-            to_address: "collector".to_string(),
-            amount: vec![Coin {
-                denom: "uusd".to_string(),
-                amount: Uint128::from(2970u128), // 1% tax
-            }],
-        }))]
-    );
-
-    let state = read_state(deps.as_ref().storage).unwrap();
-    assert_eq!(
-        state,
-        State {
-            total_liabilities: Decimal256::from_uint256(2000000u128),
-            total_reserves: Decimal256::zero(),
-            last_interest_updated: env.block.height,
-            last_reward_updated: env.block.height,
-            global_interest_index: Decimal256::from_uint256(2u64),
-            global_reward_index: Decimal256::from_str("0.0001").unwrap(),
-            anc_emission_rate: Decimal256::from_uint256(5u64),
-            prev_aterra_supply: Uint256::zero(),
-            prev_exchange_rate: Decimal256::one(),
-        }
-    );
-
-    // When there is not enough balance to cover reserve
-    // no message will be sent and reserve will be left as same
-    deps.querier.update_balance(
-        MOCK_CONTRACT_ADDR.to_string(),
-        vec![Coin {
-            denom: "uusd".to_string(),
-            amount: Uint128::from(2999u128),
-        }],
-    );
-
-    let mut env = mock_env();
-    let info = mock_info("overseer", &[]);
-    store_state(
+    // half a year later the exchange rate is up 5%
+    store_exchange_rate_snapshot(
         deps.as_mut().storage,
-        &State {
-            total_liabilities: Decimal256::from_uint256(1000000u128),
-            total_reserves: Decimal256::from_uint256(3000u128),
-            last_interest_updated: env.block.height,
-            last_reward_updated: env.block.height,
-            global_interest_index: Decimal256::one(),
-            global_reward_index: Decimal256::zero(),
-            anc_emission_rate: Decimal256::one(),
-            prev_aterra_supply: Uint256::zero(),
-            prev_exchange_rate: Decimal256::one(),
+        start_height + 100,
+        &ExchangeRateSnapshot {
+            exchange_rate: Decimal256::percent(105),
+            block_time: env.block.time.seconds() + 15768000,
         },
     )
     .unwrap();
+    assert_eq!(
+        query_exchange_rate_between(deps.as_ref(), start_height, start_height + 150).unwrap(),
+        ExchangeRateBetweenResponse {
+            start_exchange_rate: Decimal256::one(),
+            start_snapshot_height: start_height,
+            end_exchange_rate: Decimal256::percent(105),
+            end_snapshot_height: start_height + 100,
+            apr: Decimal256::percent(10),
+        }
+    );
 
-    env.block.height += 100;
-
-    // reserve == 3000
+    // epoch operations record the exchange rate
+    env.block.height += 200;
     let msg = ExecuteMsg::ExecuteEpochOperations {
         deposit_rate: Decimal256::one(),
         target_deposit_rate: Decimal256::one(),
         threshold_deposit_rate: Decimal256::one(),
         distributed_interest: Uint256::zero(),
     };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("overseer", &[]), msg).unwrap();
 
-    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-    assert_eq!(res.messages.len(), 0);
-
-    let state = read_state(deps.as_ref().storage).unwrap();
+    let res =
+        query_exchange_rate_between(deps.as_ref(), env.block.height, env.block.height).unwrap();
     assert_eq!(
-        state,
-        State {
-            total_liabilities: Decimal256::from_uint256(2000000u128),
-            total_reserves: Decimal256::from_uint256(3000u128),
-            last_interest_updated: env.block.height,
-            last_reward_updated: env.block.height,
-            global_interest_index: Decimal256::from_uint256(2u64),
-            global_reward_index: Decimal256::from_str("0.0001").unwrap(),
-            anc_emission_rate: Decimal256::from_uint256(5u64),
-            prev_aterra_supply: Uint256::zero(),
-            prev_exchange_rate: Decimal256::one(),
-        }
+        res.end_exchange_rate,
+        read_state(deps.as_ref().storage)
+            .unwrap()
+            .prev_exchange_rate
     );
+    assert_eq!(res.end_snapshot_height, env.block.height);
+    assert_eq!(res.apr, Decimal256::zero());
 }
 
 // #[test]
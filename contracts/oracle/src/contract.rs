@@ -1,15 +1,15 @@
 use crate::error::ContractError;
 use crate::state::{
-    read_config, read_feeder, read_price, read_prices, store_config, store_feeder, store_price,
-    Config, PriceInfo,
+    read_config, read_feeder, read_feeder_submission, read_price, read_price_history, read_prices,
+    store_config, store_feeder, store_feeder_submission, store_price, Config, PriceInfo,
 };
-use cosmwasm_bignumber::Decimal256;
+use cosmwasm_bignumber::{Decimal256, Uint256};
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{attr, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
 use moneymarket::oracle::{
-    ConfigResponse, ExecuteMsg, FeederResponse, InstantiateMsg, PriceResponse, PricesResponse,
-    PricesResponseElem, QueryMsg,
+    ConfigResponse, ExecuteMsg, FeederResponse, FeedersResponse, FeedersResponseElem,
+    InstantiateMsg, PriceResponse, PricesResponse, PricesResponseElem, QueryMsg,
 };
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -24,6 +24,7 @@ pub fn instantiate(
         &Config {
             owner: deps.api.addr_canonicalize(&msg.owner)?,
             base_asset: msg.base_asset,
+            price_timeframe: msg.price_timeframe,
         },
     )?;
 
@@ -38,9 +39,15 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::UpdateConfig { owner } => update_config(deps, info, owner),
+        ExecuteMsg::UpdateConfig {
+            owner,
+            price_timeframe,
+        } => update_config(deps, info, owner, price_timeframe),
         ExecuteMsg::RegisterFeeder { asset, feeder } => register_feeder(deps, info, asset, feeder),
-        ExecuteMsg::FeedPrice { prices } => feed_prices(deps, env, info, prices),
+        ExecuteMsg::FeedPrice { prices } | ExecuteMsg::FeedPrices { prices } => {
+            feed_prices(deps, env, info, prices)
+        }
+        ExecuteMsg::FeedPriceAt { prices } => feed_prices_at(deps, env, info, prices),
     }
 }
 
@@ -48,6 +55,7 @@ pub fn update_config(
     deps: DepsMut,
     info: MessageInfo,
     owner: Option<String>,
+    price_timeframe: Option<u64>,
 ) -> Result<Response, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
@@ -58,6 +66,10 @@ pub fn update_config(
         config.owner = deps.api.addr_canonicalize(&owner)?;
     }
 
+    if let Some(price_timeframe) = price_timeframe {
+        config.price_timeframe = price_timeframe;
+    }
+
     store_config(deps.storage, &config)?;
     Ok(Response::default())
 }
@@ -90,8 +102,54 @@ pub fn feed_prices(
 ) -> Result<Response, ContractError> {
     let mut attributes = vec![attr("action", "feed_prices")];
     let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    // Check feeder permission of every asset before any price is recorded
+    for (asset, _) in prices.iter() {
+        let feeder = read_feeder(deps.storage, asset)?;
+        if feeder != sender_raw {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+
+    let block_time = env.block.time.seconds();
+    for price in prices {
+        let asset: String = price.0;
+        let price: Decimal256 = price.1;
+
+        attributes.push(attr("asset", asset.to_string()));
+        attributes.push(attr("price", price.to_string()));
+
+        store_price(
+            deps.storage,
+            &asset,
+            &PriceInfo {
+                last_updated_time: block_time,
+                price,
+            },
+        )?;
+        store_feeder_submission(deps.storage, &asset, &sender_raw, block_time)?;
+    }
+
+    Ok(Response::new().add_attributes(attributes))
+}
+
+/// Feeders can submit prices with the time they were observed off-chain.
+/// An observation must fall within the price timeframe of the current block
+/// and be strictly newer than the stored one, so stale or replayed
+/// submissions cannot overwrite a fresher price
+pub fn feed_prices_at(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    prices: Vec<(String, Decimal256, u64)>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let mut attributes = vec![attr("action", "feed_prices_at")];
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let block_time = env.block.time.seconds();
     for price in prices {
         let asset: String = price.0;
+        let observed_at: u64 = price.2;
         let price: Decimal256 = price.1;
 
         // Check feeder permission
@@ -100,31 +158,53 @@ pub fn feed_prices(
             return Err(ContractError::Unauthorized {});
         }
 
+        // Observation cannot be in the future or older than the valid timeframe
+        if observed_at > block_time
+            || observed_at < block_time.saturating_sub(config.price_timeframe)
+        {
+            return Err(ContractError::InvalidObservationTime(observed_at));
+        }
+
+        // Reject out-of-order and replayed observations
+        if let Ok(last_price) = read_price(deps.storage, &asset) {
+            if observed_at <= last_price.last_updated_time {
+                return Err(ContractError::OutdatedPriceObservation(asset));
+            }
+        }
+
         attributes.push(attr("asset", asset.to_string()));
         attributes.push(attr("price", price.to_string()));
+        attributes.push(attr("observed_at", observed_at.to_string()));
 
         store_price(
             deps.storage,
             &asset,
             &PriceInfo {
-                last_updated_time: env.block.time.seconds(),
+                last_updated_time: observed_at,
                 price,
             },
         )?;
+        store_feeder_submission(deps.storage, &asset, &sender_raw, block_time)?;
     }
 
     Ok(Response::new().add_attributes(attributes))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::Feeder { asset } => to_binary(&query_feeder(deps, asset)?),
+        QueryMsg::Feeders { asset } => to_binary(&query_feeders(deps, asset)?),
         QueryMsg::Price { base, quote } => to_binary(&query_price(deps, base, quote)?),
         QueryMsg::Prices { start_after, limit } => {
             to_binary(&query_prices(deps, start_after, limit)?)
         }
+        QueryMsg::TwapPrice {
+            base,
+            quote,
+            window,
+        } => to_binary(&query_twap_price(deps, env, base, quote, window)?),
     }
 }
 
@@ -133,6 +213,7 @@ fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
     let resp = ConfigResponse {
         owner: deps.api.addr_humanize(&state.owner)?.to_string(),
         base_asset: state.base_asset,
+        price_timeframe: state.price_timeframe,
     };
 
     Ok(resp)
@@ -148,6 +229,18 @@ fn query_feeder(deps: Deps, asset: String) -> StdResult<FeederResponse> {
     Ok(resp)
 }
 
+/// A single feeder is registered per asset, a replaced feeder
+/// is no longer listed but keeps its submission time
+fn query_feeders(deps: Deps, asset: String) -> StdResult<FeedersResponse> {
+    let feeder = read_feeder(deps.storage, &asset)?;
+    let feeders = vec![FeedersResponseElem {
+        feeder: deps.api.addr_humanize(&feeder)?.to_string(),
+        last_submission_time: read_feeder_submission(deps.storage, &asset, &feeder)?,
+    }];
+
+    Ok(FeedersResponse { asset, feeders })
+}
+
 fn query_price(deps: Deps, base: String, quote: String) -> StdResult<PriceResponse> {
     let config: Config = read_config(deps.storage)?;
     let quote_price = if config.base_asset == quote {
@@ -172,6 +265,76 @@ fn query_price(deps: Deps, base: String, quote: String) -> StdResult<PriceRespon
         rate: base_price.price / quote_price.price,
         last_updated_base: base_price.last_updated_time,
         last_updated_quote: quote_price.last_updated_time,
+        confidence_spread: None,
+    })
+}
+
+fn query_twap_price(
+    deps: Deps,
+    env: Env,
+    base: String,
+    quote: String,
+    window: u64,
+) -> StdResult<PriceResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let block_time = env.block.time.seconds();
+    let quote_price = if config.base_asset == quote {
+        PriceInfo {
+            price: Decimal256::one(),
+            last_updated_time: 9999999999,
+        }
+    } else {
+        compute_twap(deps, &quote, block_time, window)?
+    };
+
+    let base_price = if config.base_asset == base {
+        PriceInfo {
+            price: Decimal256::one(),
+            last_updated_time: 9999999999,
+        }
+    } else {
+        compute_twap(deps, &base, block_time, window)?
+    };
+
+    Ok(PriceResponse {
+        rate: base_price.price / quote_price.price,
+        last_updated_base: base_price.last_updated_time,
+        last_updated_quote: quote_price.last_updated_time,
+        confidence_spread: None,
+    })
+}
+
+/// Each price of the history holds until the next one is fed. The average
+/// is taken over the part of the window the history covers, falling back
+/// to the latest price when it covers none of it
+fn compute_twap(deps: Deps, asset: &str, block_time: u64, window: u64) -> StdResult<PriceInfo> {
+    let latest_price = read_price(deps.storage, asset)?;
+    let window_start = block_time.saturating_sub(window);
+
+    let mut weighted_sum = Decimal256::zero();
+    let mut covered_time = 0u64;
+    let mut period_end = block_time;
+    for price in read_price_history(deps.storage, asset)?.iter().rev() {
+        let period_start = price.last_updated_time.max(window_start);
+        if period_start < period_end {
+            let period = period_end - period_start;
+            weighted_sum += price.price * Decimal256::from_uint256(Uint256::from(period));
+            covered_time += period;
+        }
+
+        period_end = price.last_updated_time.min(period_end);
+        if period_end <= window_start {
+            break;
+        }
+    }
+
+    if covered_time == 0 {
+        return Ok(latest_price);
+    }
+
+    Ok(PriceInfo {
+        price: weighted_sum / Decimal256::from_uint256(Uint256::from(covered_time)),
+        last_updated_time: latest_price.last_updated_time,
     })
 }
 
@@ -11,4 +11,10 @@ pub enum ContractError {
 
     #[error("Unauthorized")]
     Unauthorized {},
+
+    #[error("Price observation time is out of the valid timeframe: {0}")]
+    InvalidObservationTime(u64),
+
+    #[error("Price observation is not newer than the last observation for {0}")]
+    OutdatedPriceObservation(String),
 }
@@ -9,13 +9,28 @@ use moneymarket::oracle::PricesResponseElem;
 
 static PREFIX_PRICE: &[u8] = b"price";
 static PREFIX_FEEDER: &[u8] = b"feeder";
+static PREFIX_PRICE_HISTORY: &[u8] = b"price_history";
+static PREFIX_FEEDER_SUBMISSION: &[u8] = b"feeder_submission";
 
 static KEY_CONFIG: &[u8] = b"config";
 
+/// # of the most recent prices of an asset kept for its TWAP
+pub const MAX_PRICE_HISTORY: usize = 30;
+
+/// Seconds an observation stays valid for configs stored
+/// before the price timeframe was configurable
+pub const DEFAULT_PRICE_TIMEFRAME: u64 = 60;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub owner: CanonicalAddr,
     pub base_asset: String,
+    #[serde(default = "default_price_timeframe")]
+    pub price_timeframe: u64,
+}
+
+fn default_price_timeframe() -> u64 {
+    DEFAULT_PRICE_TIMEFRAME
 }
 
 pub fn store_config(storage: &mut dyn Storage, config: &Config) -> StdResult<()> {
@@ -32,9 +47,30 @@ pub struct PriceInfo {
     pub last_updated_time: u64,
 }
 
+/// Stores the latest price of the asset and appends it to its history,
+/// replacing a price fed at the same time
 pub fn store_price(storage: &mut dyn Storage, asset: &str, price: &PriceInfo) -> StdResult<()> {
     let mut price_bucket: Bucket<PriceInfo> = Bucket::new(storage, PREFIX_PRICE);
-    price_bucket.save(asset.as_bytes(), price)
+    price_bucket.save(asset.as_bytes(), price)?;
+
+    let mut history = read_price_history(storage, asset)?;
+    history.retain(|p| p.last_updated_time < price.last_updated_time);
+    history.push(price.clone());
+    if history.len() > MAX_PRICE_HISTORY {
+        history.drain(..history.len() - MAX_PRICE_HISTORY);
+    }
+
+    let mut history_bucket: Bucket<Vec<PriceInfo>> = Bucket::new(storage, PREFIX_PRICE_HISTORY);
+    history_bucket.save(asset.as_bytes(), &history)
+}
+
+/// Most recent prices of the asset, oldest first
+pub fn read_price_history(storage: &dyn Storage, asset: &str) -> StdResult<Vec<PriceInfo>> {
+    let history_bucket: ReadonlyBucket<Vec<PriceInfo>> =
+        ReadonlyBucket::new(storage, PREFIX_PRICE_HISTORY);
+    Ok(history_bucket
+        .may_load(asset.as_bytes())?
+        .unwrap_or_default())
 }
 
 pub fn read_price(storage: &dyn Storage, asset: &str) -> StdResult<PriceInfo> {
@@ -97,6 +133,28 @@ pub fn read_feeder(storage: &dyn Storage, asset: &str) -> StdResult<CanonicalAdd
     }
 }
 
+/// Block time of the most recent prices the feeder submitted for the asset
+pub fn store_feeder_submission(
+    storage: &mut dyn Storage,
+    asset: &str,
+    feeder: &CanonicalAddr,
+    submitted_at: u64,
+) -> StdResult<()> {
+    let mut submission_bucket: Bucket<u64> =
+        Bucket::multilevel(storage, &[PREFIX_FEEDER_SUBMISSION, asset.as_bytes()]);
+    submission_bucket.save(feeder.as_slice(), &submitted_at)
+}
+
+pub fn read_feeder_submission(
+    storage: &dyn Storage,
+    asset: &str,
+    feeder: &CanonicalAddr,
+) -> StdResult<Option<u64>> {
+    let submission_bucket: ReadonlyBucket<u64> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_FEEDER_SUBMISSION, asset.as_bytes()]);
+    submission_bucket.may_load(feeder.as_slice())
+}
+
 // this will set the first key after the provided key, by appending a 1 byte
 fn calc_range_start(start_after: Option<String>) -> Option<Vec<u8>> {
     start_after.map(|idx| {
@@ -1,12 +1,15 @@
 use crate::contract::{execute, instantiate, query};
 use crate::error::ContractError;
+use crate::state::DEFAULT_PRICE_TIMEFRAME;
 use cosmwasm_bignumber::Decimal256;
-use cosmwasm_std::from_binary;
 use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+use cosmwasm_std::{attr, from_binary, Api, CanonicalAddr, Deps};
+use cosmwasm_storage::singleton;
 use moneymarket::oracle::{
-    ConfigResponse, ExecuteMsg, FeederResponse, InstantiateMsg, PriceResponse, PricesResponse,
-    PricesResponseElem, QueryMsg,
+    ConfigResponse, ExecuteMsg, FeederResponse, FeedersResponse, FeedersResponseElem,
+    InstantiateMsg, PriceResponse, PricesResponse, PricesResponseElem, QueryMsg,
 };
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
 #[test]
@@ -16,6 +19,7 @@ fn proper_initialization() {
     let msg = InstantiateMsg {
         owner: "owner0000".to_string(),
         base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -29,6 +33,7 @@ fn proper_initialization() {
     let value: ConfigResponse = from_binary(&res).unwrap();
     assert_eq!("owner0000", value.owner.as_str());
     assert_eq!("base0000", &value.base_asset);
+    assert_eq!(60u64, value.price_timeframe);
 }
 
 #[test]
@@ -38,6 +43,7 @@ fn update_config() {
     let msg = InstantiateMsg {
         owner: "owner0000".to_string(),
         base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -47,6 +53,7 @@ fn update_config() {
     let info = mock_info("owner0000", &[]);
     let msg = ExecuteMsg::UpdateConfig {
         owner: Some("owner0001".to_string()),
+        price_timeframe: Some(120u64),
     };
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -57,10 +64,14 @@ fn update_config() {
     let value: ConfigResponse = from_binary(&res).unwrap();
     assert_eq!("owner0001", value.owner.as_str());
     assert_eq!("base0000", &value.base_asset);
+    assert_eq!(120u64, value.price_timeframe);
 
     // Unauthorized err
     let info = mock_info("owner0000", &[]);
-    let msg = ExecuteMsg::UpdateConfig { owner: None };
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        price_timeframe: None,
+    };
 
     let res = execute(deps.as_mut(), mock_env(), info, msg);
     match res {
@@ -69,6 +80,30 @@ fn update_config() {
     }
 }
 
+#[test]
+fn legacy_config() {
+    let mut deps = mock_dependencies(&[]);
+
+    #[derive(Serialize, Deserialize)]
+    struct LegacyConfig {
+        owner: CanonicalAddr,
+        base_asset: String,
+    }
+    let legacy_config = LegacyConfig {
+        owner: deps.api.addr_canonicalize("owner0000").unwrap(),
+        base_asset: "base0000".to_string(),
+    };
+    singleton(deps.as_mut().storage, b"config")
+        .save(&legacy_config)
+        .unwrap();
+
+    // configs stored without a price timeframe take the default one
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let value: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!("owner0000", value.owner.as_str());
+    assert_eq!(DEFAULT_PRICE_TIMEFRAME, value.price_timeframe);
+}
+
 #[test]
 fn register_feeder() {
     let mut deps = mock_dependencies(&[]);
@@ -76,6 +111,7 @@ fn register_feeder() {
     let msg = InstantiateMsg {
         owner: "owner0000".to_string(),
         base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -115,6 +151,134 @@ fn register_feeder() {
     );
 }
 
+#[test]
+fn feeders() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
+    };
+
+    let info = mock_info("owner0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    for (asset, feeder) in [("mAAPL", "feeder0000"), ("mGOGL", "feeder0001")] {
+        let msg = ExecuteMsg::RegisterFeeder {
+            asset: asset.to_string(),
+            feeder: feeder.to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    }
+
+    let query_feeders = |deps: Deps, asset: &str| -> FeedersResponse {
+        from_binary(
+            &query(
+                deps,
+                mock_env(),
+                QueryMsg::Feeders {
+                    asset: asset.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+
+    // no price has been submitted yet
+    assert_eq!(
+        query_feeders(deps.as_ref(), "mAAPL"),
+        FeedersResponse {
+            asset: "mAAPL".to_string(),
+            feeders: vec![FeedersResponseElem {
+                feeder: "feeder0000".to_string(),
+                last_submission_time: None,
+            }],
+        }
+    );
+
+    let mut env = mock_env();
+    let stale_time = env.block.time.seconds();
+    let msg = ExecuteMsg::FeedPrice {
+        prices: vec![("mGOGL".to_string(), Decimal256::from_str("2.0").unwrap())],
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("feeder0001", &[]),
+        msg,
+    )
+    .unwrap();
+
+    // only the mAAPL feeder keeps submitting
+    env.block.time = env.block.time.plus_seconds(3600);
+    let msg = ExecuteMsg::FeedPrice {
+        prices: vec![("mAAPL".to_string(), Decimal256::from_str("1.0").unwrap())],
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("feeder0000", &[]),
+        msg,
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.1").unwrap(),
+            env.block.time.seconds() + 5,
+        )],
+    };
+    env.block.time = env.block.time.plus_seconds(10);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("feeder0000", &[]),
+        msg,
+    )
+    .unwrap();
+
+    assert_eq!(
+        query_feeders(deps.as_ref(), "mAAPL").feeders,
+        vec![FeedersResponseElem {
+            feeder: "feeder0000".to_string(),
+            last_submission_time: Some(env.block.time.seconds()),
+        }]
+    );
+    assert_eq!(
+        query_feeders(deps.as_ref(), "mGOGL").feeders,
+        vec![FeedersResponseElem {
+            feeder: "feeder0001".to_string(),
+            last_submission_time: Some(stale_time),
+        }]
+    );
+
+    // a newly registered feeder has not submitted yet
+    let msg = ExecuteMsg::RegisterFeeder {
+        asset: "mGOGL".to_string(),
+        feeder: "feeder0002".to_string(),
+    };
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        query_feeders(deps.as_ref(), "mGOGL").feeders,
+        vec![FeedersResponseElem {
+            feeder: "feeder0002".to_string(),
+            last_submission_time: None,
+        }]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Feeders {
+            asset: "mMSFT".to_string(),
+        },
+    );
+    assert!(res.is_err());
+}
+
 #[test]
 fn feed_price() {
     let mut deps = mock_dependencies(&[]);
@@ -122,6 +286,7 @@ fn feed_price() {
     let msg = InstantiateMsg {
         owner: "owner0000".to_string(),
         base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -170,6 +335,7 @@ fn feed_price() {
             rate: Decimal256::from_str("1.2").unwrap(),
             last_updated_base: env.block.time.seconds(),
             last_updated_quote: 9999999999,
+            confidence_spread: None,
         }
     );
 
@@ -190,6 +356,7 @@ fn feed_price() {
             rate: Decimal256::from_str("1.833333333333333333").unwrap(),
             last_updated_base: env.block.time.seconds(),
             last_updated_quote: env.block.time.seconds(),
+            confidence_spread: None,
         }
     );
 
@@ -234,3 +401,322 @@ fn feed_price() {
         _ => panic!("Must return unauthorized error"),
     }
 }
+
+#[test]
+fn feed_prices() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
+    };
+
+    let info = mock_info("owner0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let assets = ["mAAPL", "mAMZN", "mGOGL", "mMSFT", "mNFLX"];
+    for asset in assets.iter() {
+        let msg = ExecuteMsg::RegisterFeeder {
+            asset: asset.to_string(),
+            feeder: "feeder0000".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    }
+    let msg = ExecuteMsg::RegisterFeeder {
+        asset: "mTSLA".to_string(),
+        feeder: "feeder0001".to_string(),
+    };
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let query_prices = |deps: Deps| -> Vec<PricesResponseElem> {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::Prices {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: PricesResponse = from_binary(&res).unwrap();
+        value.prices
+    };
+
+    // the mTSLA price rejects the whole batch
+    let info = mock_info("feeder0000", &[]);
+    let msg = ExecuteMsg::FeedPrices {
+        prices: vec![
+            ("mAAPL".to_string(), Decimal256::from_str("1.2").unwrap()),
+            ("mTSLA".to_string(), Decimal256::from_str("3.4").unwrap()),
+        ],
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("Must return unauthorized error"),
+    }
+    assert_eq!(query_prices(deps.as_ref()), vec![]);
+
+    let env = mock_env();
+    let msg = ExecuteMsg::FeedPrices {
+        prices: assets
+            .iter()
+            .enumerate()
+            .map(|(i, asset)| (asset.to_string(), Decimal256::percent(100 + i as u64)))
+            .collect(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(res.attributes.len(), 11);
+
+    assert_eq!(
+        query_prices(deps.as_ref()),
+        assets
+            .iter()
+            .enumerate()
+            .map(|(i, asset)| PricesResponseElem {
+                asset: asset.to_string(),
+                price: Decimal256::percent(100 + i as u64),
+                last_updated_time: env.block.time.seconds(),
+            })
+            .collect::<Vec<PricesResponseElem>>()
+    );
+}
+
+#[test]
+fn feed_price_at() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // Register feeder for mAAPL
+    let msg = ExecuteMsg::RegisterFeeder {
+        asset: "mAAPL".to_string(),
+        feeder: "feeder0000".to_string(),
+    };
+    let info = mock_info("owner0000", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let env = mock_env();
+    let block_time = env.block.time.seconds();
+
+    // Unauthorized try
+    let info = mock_info("addr0001", &[]);
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.2").unwrap(),
+            block_time,
+        )],
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    // Observation from the future
+    let info = mock_info("feeder0000", &[]);
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.2").unwrap(),
+            block_time + 1u64,
+        )],
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::InvalidObservationTime(time)) => assert_eq!(time, block_time + 1u64),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Observation older than price timeframe
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.2").unwrap(),
+            block_time - 61u64,
+        )],
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::InvalidObservationTime(time)) => assert_eq!(time, block_time - 61u64),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Feed price observed 30 seconds ago
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.2").unwrap(),
+            block_time - 30u64,
+        )],
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "feed_prices_at"),
+            attr("asset", "mAAPL"),
+            attr("price", "1.2"),
+            attr("observed_at", (block_time - 30u64).to_string()),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Price {
+            base: "mAAPL".to_string(),
+            quote: "base0000".to_string(),
+        },
+    )
+    .unwrap();
+    let value: PriceResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        value,
+        PriceResponse {
+            rate: Decimal256::from_str("1.2").unwrap(),
+            last_updated_base: block_time - 30u64,
+            last_updated_quote: 9999999999,
+            confidence_spread: None,
+        }
+    );
+
+    // Replayed submission
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::OutdatedPriceObservation(asset)) => assert_eq!(asset, "mAAPL"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Out-of-order submission
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.1").unwrap(),
+            block_time - 40u64,
+        )],
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::OutdatedPriceObservation(asset)) => assert_eq!(asset, "mAAPL"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Newer observation overwrites the price
+    let msg = ExecuteMsg::FeedPriceAt {
+        prices: vec![(
+            "mAAPL".to_string(),
+            Decimal256::from_str("1.3").unwrap(),
+            block_time - 10u64,
+        )],
+    };
+    let _res = execute(deps.as_mut(), env, info, msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Price {
+            base: "mAAPL".to_string(),
+            quote: "base0000".to_string(),
+        },
+    )
+    .unwrap();
+    let value: PriceResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        value,
+        PriceResponse {
+            rate: Decimal256::from_str("1.3").unwrap(),
+            last_updated_base: block_time - 10u64,
+            last_updated_quote: 9999999999,
+            confidence_spread: None,
+        }
+    );
+}
+
+#[test]
+fn twap_price() {
+    let mut deps = mock_dependencies(&[]);
+
+    let msg = InstantiateMsg {
+        owner: "owner0000".to_string(),
+        base_asset: "base0000".to_string(),
+        price_timeframe: 60u64,
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::RegisterFeeder {
+        asset: "mAAPL".to_string(),
+        feeder: "feeder0000".to_string(),
+    };
+    let info = mock_info("owner0000", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // 1.0 for 100 seconds, then 2.0 for 100 seconds
+    let info = mock_info("feeder0000", &[]);
+    let mut env = mock_env();
+    let start_time = env.block.time.seconds();
+    let msg = ExecuteMsg::FeedPrice {
+        prices: vec![("mAAPL".to_string(), Decimal256::one())],
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    env.block.time = env.block.time.plus_seconds(100);
+    let msg = ExecuteMsg::FeedPrice {
+        prices: vec![("mAAPL".to_string(), Decimal256::from_str("2.0").unwrap())],
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    env.block.time = env.block.time.plus_seconds(100);
+
+    let query_twap = |window: u64| -> PriceResponse {
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::TwapPrice {
+                base: "mAAPL".to_string(),
+                quote: "base0000".to_string(),
+                window,
+            },
+        )
+        .unwrap();
+        from_binary(&res).unwrap()
+    };
+
+    assert_eq!(
+        query_twap(200u64),
+        PriceResponse {
+            rate: Decimal256::from_str("1.5").unwrap(),
+            last_updated_base: start_time + 100,
+            last_updated_quote: 9999999999,
+            confidence_spread: None,
+        }
+    );
+
+    // only the period with the latest price
+    assert_eq!(query_twap(50u64).rate, Decimal256::from_str("2.0").unwrap());
+
+    // 1.0 for 50 seconds, then 2.0 for 100 seconds
+    assert_eq!(
+        query_twap(150u64).rate,
+        Decimal256::from_ratio(250u64, 150u64)
+    );
+
+    // the window before the first price is not covered
+    assert_eq!(
+        query_twap(1000u64).rate,
+        Decimal256::from_str("1.5").unwrap()
+    );
+
+    // an empty window is the spot price
+    assert_eq!(query_twap(0u64).rate, Decimal256::from_str("2.0").unwrap());
+}
@@ -4,8 +4,17 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use moneymarket::overseer::{
-    AllCollateralsResponse, BorrowLimitResponse, CollateralsResponse, ConfigResponse, ExecuteMsg,
-    InstantiateMsg, QueryMsg, WhitelistResponse,
+    AllCollateralsResponse, AncBuybackEstimateResponse, AssetRegistryResponse,
+    AuthorizationResponse, BadDebtResponse, BorrowLimitResponse, BufferFlowResponse,
+    CategoryExposureResponse, CollateralCapUtilizationResponse, CollateralsResponse,
+    ConfigResponse, ConsistencyCheckResponse, EffectiveLtvResponse, EpochRatePerformanceResponse,
+    EpochTotalsResponse, ExecuteMsg, FairnessFundResponse, GlobalBorrowableByCollateralResponse,
+    InstantiateMsg, InterestBufferDepositResponse, LiquidationBonusResponse,
+    LiquidationHistoryResponse, LiquidationWaterfallResponse, MaxLiquidatableResponse, MigrateMsg,
+    NetDepositRateResponse, NextEpochResponse, PositionResponse, QueryMsg,
+    RiskContributionResponse, SafeBorrowAmountResponse, SimulationResponse, SnapshotResponse,
+    SolvencyCheckResponse, StaleCollateralsResponse, StressTestResponse, SubAccountsResponse,
+    TrustedContractsResponse, WhitelistResponse,
 };
 use moneymarket_overseer::state::EpochState;
 
@@ -18,10 +27,41 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(AllCollateralsResponse), &out_dir);
+    export_schema(&schema_for!(BadDebtResponse), &out_dir);
+    export_schema(&schema_for!(CategoryExposureResponse), &out_dir);
+    export_schema(&schema_for!(CollateralCapUtilizationResponse), &out_dir);
+    export_schema(&schema_for!(LiquidationBonusResponse), &out_dir);
+    export_schema(&schema_for!(MaxLiquidatableResponse), &out_dir);
+    export_schema(&schema_for!(LiquidationWaterfallResponse), &out_dir);
+    export_schema(&schema_for!(StressTestResponse), &out_dir);
+    export_schema(&schema_for!(LiquidationHistoryResponse), &out_dir);
+    export_schema(&schema_for!(EpochTotalsResponse), &out_dir);
+    export_schema(&schema_for!(NetDepositRateResponse), &out_dir);
+    export_schema(&schema_for!(SubAccountsResponse), &out_dir);
+    export_schema(&schema_for!(SafeBorrowAmountResponse), &out_dir);
+    export_schema(&schema_for!(EffectiveLtvResponse), &out_dir);
+    export_schema(&schema_for!(ConsistencyCheckResponse), &out_dir);
+    export_schema(&schema_for!(NextEpochResponse), &out_dir);
+    export_schema(&schema_for!(EpochRatePerformanceResponse), &out_dir);
+    export_schema(&schema_for!(BufferFlowResponse), &out_dir);
+    export_schema(&schema_for!(StaleCollateralsResponse), &out_dir);
+    export_schema(&schema_for!(SimulationResponse), &out_dir);
+    export_schema(&schema_for!(GlobalBorrowableByCollateralResponse), &out_dir);
+    export_schema(&schema_for!(PositionResponse), &out_dir);
+    export_schema(&schema_for!(RiskContributionResponse), &out_dir);
+    export_schema(&schema_for!(AncBuybackEstimateResponse), &out_dir);
+    export_schema(&schema_for!(SolvencyCheckResponse), &out_dir);
+    export_schema(&schema_for!(FairnessFundResponse), &out_dir);
+    export_schema(&schema_for!(AssetRegistryResponse), &out_dir);
+    export_schema(&schema_for!(InterestBufferDepositResponse), &out_dir);
+    export_schema(&schema_for!(SnapshotResponse), &out_dir);
+    export_schema(&schema_for!(AuthorizationResponse), &out_dir);
     export_schema(&schema_for!(BorrowLimitResponse), &out_dir);
     export_schema(&schema_for!(CollateralsResponse), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
     export_schema(&schema_for!(EpochState), &out_dir);
+    export_schema(&schema_for!(TrustedContractsResponse), &out_dir);
     export_schema(&schema_for!(WhitelistResponse), &out_dir);
 }
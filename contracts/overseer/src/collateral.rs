@@ -1,51 +1,173 @@
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    attr, to_binary, Addr, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response, StdResult, SubMsg,
-    WasmMsg,
+    attr, to_binary, Addr, Api, Attribute, BankMsg, CanonicalAddr, Coin, ContractResult, CosmosMsg,
+    Deps, DepsMut, Env, MessageInfo, OverflowError, OverflowOperation, Reply, Response, StdError,
+    StdResult, Storage, SubMsg, WasmMsg,
 };
 
+use crate::contract::{
+    LIQUIDATE_COLLATERAL_REPLY_ID, LIQUIDATION_RECORD_REPLY_ID, UNLOCK_COLLATERAL_REPLY_ID,
+};
 use crate::error::ContractError;
-use crate::querier::{query_borrower_info, query_liquidation_amount};
+use crate::querier::{
+    query_auction_duration, query_borrower_info, query_borrower_infos, query_conversion,
+    query_exchange_rate, query_liquidation_amount, query_liquidation_config, query_market_state,
+};
 use crate::state::{
-    read_all_collaterals, read_collaterals, read_config, read_whitelist_elem, store_collaterals,
-    Config, WhitelistElem,
+    format_addr, read_all_collaterals, read_all_whitelist_elems, read_backstop_used,
+    read_bad_debt_settled, read_collateral_transfers, read_collaterals, read_collaterals_page,
+    read_config, read_custody_locks, read_epoch_state, read_fairness_fund, read_last_price,
+    read_liquidation_delegation, read_liquidation_dispute, read_liquidation_record,
+    read_liquidation_records, read_pending_liquidation_record, read_position_transfer,
+    read_price_drop, read_price_observation, read_recent_lock, read_sub_account_collaterals,
+    read_total_locked, read_whitelist_elem, read_whitelist_elems, remove_liquidation_delegation,
+    remove_pending_liquidation_record, remove_position_transfer, store_backstop_used,
+    store_bad_debt_settled, store_collateral_transfers, store_collaterals, store_custody_locks,
+    store_epoch_state, store_fairness_fund, store_last_price, store_liquidation_delegation,
+    store_liquidation_dispute, store_liquidation_record, store_pending_liquidation_record,
+    store_position_transfer, store_price_drop, store_price_observation, store_recent_lock,
+    store_total_locked, CollateralTransfers, Config, FairnessFund, LiquidationDelegation,
+    LiquidationDispute, LiquidationRecord, PositionTransfer, PriceDrop, PriceObservation,
+    RecentLock, WhitelistElem,
 };
 
-use moneymarket::custody::ExecuteMsg as CustodyExecuteMsg;
-use moneymarket::liquidation::LiquidationAmountResponse;
+use moneymarket::converter::Cw20HookMsg as ConverterCw20HookMsg;
+use moneymarket::custody::{Cw20HookMsg as CustodyCw20HookMsg, ExecuteMsg as CustodyExecuteMsg};
+use moneymarket::liquidation::{ExecuteMsg as LiquidationExecuteMsg, LiquidationAmountResponse};
 use moneymarket::market::{BorrowerInfoResponse, ExecuteMsg as MarketExecuteMsg};
-use moneymarket::oracle::PriceResponse;
-use moneymarket::overseer::{AllCollateralsResponse, BorrowLimitResponse, CollateralsResponse};
-use moneymarket::querier::{query_balance, query_price, TimeConstraints};
+use moneymarket::oracle::{PriceMode, PriceResponse};
+use moneymarket::overseer::{
+    AllCollateralsResponse, BadDebtResponse, BorrowLimitResponse, CategoryExposureElem,
+    CategoryExposureResponse, CollateralCapUtilizationElem, CollateralCapUtilizationResponse,
+    CollateralsResponse, ConsistencyCheckElem, ConsistencyCheckResponse, EffectiveLtvResponse,
+    ExecuteMsg, FairnessFundResponse, GlobalBorrowableByCollateralResponse, GlobalBorrowableElem,
+    LiquidationBonusResponse, LiquidationHistoryResponse, LiquidationRecordResponse,
+    LiquidationWaterfallElem, LiquidationWaterfallResponse, LtvAdjustmentElem,
+    MaxLiquidatableResponse, PositionCollateralElem, PositionResponse, RiskContributionElem,
+    RiskContributionResponse, SafeBorrowAmountResponse, SolvencyCheckResponse, StaleCollateralElem,
+    StaleCollateralsResponse, StressTestResponse, SubAccountResponse, SubAccountsResponse,
+    TokenType, ValuationMode,
+};
+use moneymarket::querier::{
+    deduct_tax, query_balance, query_price_by_mode, query_supply, TimeConstraints,
+};
 use moneymarket::tokens::{Tokens, TokensHuman, TokensMath, TokensToHuman, TokensToRaw};
+use std::str::FromStr;
 
 pub fn lock_collateral(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     collaterals_human: TokensHuman,
+    sub_account: Option<String>,
 ) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
     let borrower_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
-    let mut cur_collaterals: Tokens = read_collaterals(deps.storage, &borrower_raw);
+    let mut cur_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+    let prev_collaterals_len = cur_collaterals.len();
 
     let collaterals: Tokens = collaterals_human.to_raw(deps.as_ref())?;
+    let mut native_collaterals: Vec<(String, Uint256)> = vec![];
+    for collateral in collaterals.iter() {
+        let collateral_token = deps.api.addr_humanize(&collateral.0)?;
+        let whitelist_elem = read_whitelist_elem(deps.storage, &collateral.0).ok();
+        if let Some(TokenType::Native { denom }) = whitelist_elem.as_ref().map(|e| &e.token_type) {
+            match native_collaterals.iter_mut().find(|c| c.0 == *denom) {
+                Some(native_collateral) => native_collateral.1 += collateral.1,
+                None => native_collaterals.push((denom.to_string(), collateral.1)),
+            }
+        } else if query_supply(deps.as_ref(), collateral_token.clone())?.is_zero() {
+            return Err(ContractError::ZeroSupplyCollateral(
+                collateral_token.to_string(),
+            ));
+        }
+
+        if let Some(whitelist_elem) = &whitelist_elem {
+            if !in_borrow_window(whitelist_elem, env.block.height) {
+                return Err(ContractError::OutsideBorrowWindow(
+                    collateral_token.to_string(),
+                ));
+            }
+        }
+
+        let min_lock_amount = whitelist_elem
+            .and_then(|elem| elem.min_lock_amount)
+            .unwrap_or(config.min_lock_amount);
+        if collateral.1 < min_lock_amount {
+            return Err(ContractError::LockAmountTooSmall(
+                collateral_token.to_string(),
+                min_lock_amount,
+            ));
+        }
+    }
+
+    assert_native_funds(&info.funds, &native_collaterals)?;
 
     cur_collaterals.add(collaterals.clone());
-    store_collaterals(deps.storage, &borrower_raw, &cur_collaterals)?;
+
+    // Positions already above the limit are allowed to keep their collaterals
+    if cur_collaterals.len() > prev_collaterals_len
+        && cur_collaterals.len() > config.max_collaterals_per_borrower as usize
+    {
+        return Err(ContractError::TooManyCollaterals(
+            config.max_collaterals_per_borrower,
+        ));
+    }
+
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
+
+    if config.same_block_collateral_delay {
+        let mut recent_collaterals: Tokens =
+            match read_recent_lock(deps.storage, &borrower_raw, sub_account.as_deref())? {
+                Some(recent_lock) if recent_lock.block_height == env.block.height => {
+                    recent_lock.collaterals
+                }
+                _ => vec![],
+            };
+        recent_collaterals.add(collaterals.clone());
+        store_recent_lock(
+            deps.storage,
+            &borrower_raw,
+            sub_account.as_deref(),
+            &RecentLock {
+                block_height: env.block.height,
+                collaterals: recent_collaterals,
+            },
+        )?;
+    }
 
     let mut messages: Vec<CosmosMsg> = vec![];
     for collateral in collaterals {
         let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
-        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: deps
-                .api
-                .addr_humanize(&whitelist_elem.custody_contract)?
-                .to_string(),
-            funds: vec![],
-            msg: to_binary(&CustodyExecuteMsg::LockCollateral {
-                borrower: info.sender.to_string(),
-                amount: collateral.1,
-            })?,
-        }));
+        let total_locked = read_total_locked(deps.storage, &collateral.0) + collateral.1;
+        store_total_locked(deps.storage, &collateral.0, &total_locked)?;
+
+        // Native collaterals are already held with the attached funds
+        if whitelist_elem.token_type != TokenType::Cw20 {
+            continue;
+        }
+        for (custody_contract, amount) in lock_custody_amount(
+            deps.storage,
+            &borrower_raw,
+            &collateral.0,
+            &whitelist_elem,
+            collateral.1,
+        )? {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: info.sender.to_string(),
+                    amount,
+                })?,
+            }));
+        }
     }
 
     // Logging stuff, so can be removed
@@ -66,49 +188,118 @@ pub fn unlock_collateral(
     env: Env,
     info: MessageInfo,
     collaterals_human: TokensHuman,
+    sub_account: Option<String>,
 ) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
     let market = deps.api.addr_humanize(&config.market_contract)?;
 
     let borrower = info.sender;
     let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
-    let mut cur_collaterals: Tokens = read_collaterals(deps.storage, &borrower_raw);
+    let mut cur_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
     let collaterals: Tokens = collaterals_human.to_raw(deps.as_ref())?;
+    let prev_collaterals: Tokens = cur_collaterals.clone();
 
     // Underflow check is done in sub_collateral
     if cur_collaterals.sub(collaterals.clone()).is_err() {
         return Err(ContractError::UnlockExceedsLocked {});
     }
 
+    // Underwater loans cannot unlock any collateral,
+    // regardless of the value of the unlock target collaterals
+    let (prev_borrow_limit, prev_collateral_prices) = compute_borrow_limit(
+        deps.as_ref(),
+        &prev_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    let borrow_amount_res: BorrowerInfoResponse = query_borrower_info(
+        deps.as_ref(),
+        market,
+        borrower.clone(),
+        env.block.height,
+        sub_account.clone(),
+    )?;
+    if prev_borrow_limit < borrow_amount_res.loan_amount {
+        return Err(ContractError::UnlockUnderwaterLoan(
+            prev_borrow_limit.into(),
+        ));
+    }
+
     // Compute borrow limit with collaterals except unlock target collaterals
     let (borrow_limit, _) = compute_borrow_limit(
         deps.as_ref(),
         &cur_collaterals,
         Some(env.block.time.seconds()),
     )?;
-    let borrow_amount_res: BorrowerInfoResponse =
-        query_borrower_info(deps.as_ref(), market, borrower.clone(), env.block.height)?;
     if borrow_limit < borrow_amount_res.loan_amount {
         return Err(ContractError::UnlockTooLarge(borrow_limit.into()));
     }
 
-    store_collaterals(deps.storage, &borrower_raw, &cur_collaterals)?;
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
+    let mut price_deviations = record_collateral_prices(
+        deps.storage,
+        deps.api,
+        config.price_deviation_threshold,
+        &prev_collaterals,
+        &prev_collateral_prices,
+    )?;
+    price_deviations.extend(observe_price_drops(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        &prev_collaterals,
+        &prev_collateral_prices,
+    )?);
 
     let mut messages: Vec<SubMsg> = vec![];
+    let mut transfers: Vec<(CanonicalAddr, Uint256, CanonicalAddr)> = vec![];
     for collateral in collaterals.clone() {
         let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
-        messages.push(SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: deps
-                .api
-                .addr_humanize(&whitelist_elem.custody_contract)?
-                .to_string(),
-            funds: vec![],
-            msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
-                borrower: borrower.to_string(),
-                amount: collateral.1,
-            })?,
-        })));
+        decrease_total_locked(deps.storage, &collateral.0, collateral.1)?;
+        if let TokenType::Native { denom } = &whitelist_elem.token_type {
+            messages.push(SubMsg::new(native_collateral_msg(
+                deps.as_ref(),
+                denom,
+                &borrower,
+                collateral.1,
+            )?));
+            continue;
+        }
+        for (custody_contract, amount) in unlock_custody_amount(
+            deps.storage,
+            &borrower_raw,
+            &collateral.0,
+            &whitelist_elem,
+            collateral.1,
+        )? {
+            messages.push(custody_transfer_msg(
+                &config,
+                &mut transfers,
+                UNLOCK_COLLATERAL_REPLY_ID,
+                (collateral.0.clone(), amount, custody_contract.clone()),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                        borrower: borrower.to_string(),
+                        amount,
+                    })?,
+                }),
+            ));
+        }
     }
+    store_custody_transfers(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        transfers,
+    )?;
 
     // Logging stuff, so can be removed
     let collateral_logs: Vec<String> = collaterals_human
@@ -122,171 +313,4221 @@ pub fn unlock_collateral(
             attr("action", "unlock_collateral"),
             attr("borrower", borrower),
             attr("collaterals", collateral_logs.join(",")),
-        ]))
+        ])
+        .add_attributes(price_deviations))
 }
 
-pub fn liquidate_collateral(
+pub fn unlock_to_target_ltv(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    borrower: Addr,
+    target_ltv: Decimal256,
+    sub_account: Option<String>,
 ) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
     let market = deps.api.addr_humanize(&config.market_contract)?;
 
-    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
-    let mut cur_collaterals: Tokens = read_collaterals(deps.storage, &borrower_raw);
+    let borrower_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let cur_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
 
-    // Compute borrow limit with collaterals except unlock target collaterals
-    let (borrow_limit, collateral_prices) = compute_borrow_limit(
+    let (collaterals_value, _) = compute_collaterals_limit(
         deps.as_ref(),
         &cur_collaterals,
         Some(env.block.time.seconds()),
+        Some(Decimal256::one()),
+        config.borrow_price_timeframe,
+        config.max_confidence_spread,
+        None,
+        &ValuationMode::Spot,
+    )?;
+    let borrow_amount_res: BorrowerInfoResponse = query_borrower_info(
+        deps.as_ref(),
+        market,
+        info.sender.clone(),
+        env.block.height,
+        sub_account.clone(),
     )?;
-    let borrow_amount_res: BorrowerInfoResponse =
-        query_borrower_info(deps.as_ref(), market, borrower.clone(), env.block.height)?;
-    let borrow_amount = borrow_amount_res.loan_amount;
 
-    // borrow limit is equal or bigger than loan amount
-    // cannot liquidation collaterals
-    if borrow_limit >= borrow_amount {
-        return Err(ContractError::CannotLiquidateSafeLoan {});
+    let current_ltv = if collaterals_value.is_zero() {
+        Decimal256::zero()
+    } else {
+        Decimal256::from_uint256(borrow_amount_res.loan_amount)
+            / Decimal256::from_uint256(collaterals_value)
+    };
+    if target_ltv < current_ltv {
+        return Err(ContractError::TargetLtvBelowCurrent(
+            current_ltv.to_string(),
+        ));
     }
 
-    let liquidation_amount_res: LiquidationAmountResponse = query_liquidation_amount(
+    // Unlocking (capacity - loan) / capacity of every collateral, with
+    // capacity = collateral_value * target_ltv, raises the LTV to
+    // target_ltv; each rounding keeps it below
+    let capacity = Decimal256::from_uint256(collaterals_value) * target_ltv;
+    let loan_amount = Decimal256::from_uint256(borrow_amount_res.loan_amount);
+    let unlock_ratio = if capacity.is_zero() {
+        Decimal256::one()
+    } else if capacity > loan_amount {
+        (capacity - loan_amount) / capacity
+    } else {
+        Decimal256::zero()
+    };
+    let collaterals: Tokens = cur_collaterals
+        .iter()
+        .map(|c| (c.0.clone(), c.1 * unlock_ratio))
+        .filter(|c| !c.1.is_zero())
+        .collect();
+
+    let collaterals_human = collaterals.to_human(deps.as_ref())?;
+    Ok(
+        unlock_collateral(deps, env, info, collaterals_human, sub_account)?.add_attributes(vec![
+            attr("current_ltv", current_ltv.to_string()),
+            attr("target_ltv", target_ltv.to_string()),
+        ]),
+    )
+}
+
+pub fn lock_collateral_for(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    collaterals_human: TokensHuman,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.market_contract {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // override info
+    let info = MessageInfo {
+        sender: borrower,
+        funds: vec![],
+    };
+    lock_collateral(deps, env, info, collaterals_human, None)
+}
+
+pub fn unlock_collateral_from_repay(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    prev_loan_amount: Uint256,
+    repay_amount: Uint256,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.market_contract {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut cur_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    // Underwater loans keep their collaterals locked
+    let (borrow_limit, collateral_prices) = compute_borrow_limit(
         deps.as_ref(),
-        deps.api.addr_humanize(&config.liquidation_contract)?,
-        borrow_amount,
-        borrow_limit,
-        &cur_collaterals.to_human(deps.as_ref())?,
-        collateral_prices,
+        &cur_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    let mut price_deviations = record_collateral_prices(
+        deps.storage,
+        deps.api,
+        config.price_deviation_threshold,
+        &cur_collaterals,
+        &collateral_prices,
     )?;
+    price_deviations.extend(observe_price_drops(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        &cur_collaterals,
+        &collateral_prices,
+    )?);
+    if prev_loan_amount.is_zero() || borrow_limit < prev_loan_amount {
+        return Ok(Response::new()
+            .add_attributes(vec![
+                attr("action", "unlock_collateral_from_repay"),
+                attr("borrower", borrower),
+                attr("collaterals", ""),
+            ])
+            .add_attributes(price_deviations));
+    }
 
-    let liquidation_amount = liquidation_amount_res.collaterals.to_raw(deps.as_ref())?;
+    // Unlocking the repaid ratio of each collateral keeps
+    // loan_amount / collateral_value unchanged
+    let unlock_ratio = Decimal256::from_uint256(repay_amount.min(prev_loan_amount))
+        / Decimal256::from_uint256(prev_loan_amount);
+    let collaterals: Tokens = cur_collaterals
+        .iter()
+        .map(|c| (c.0.clone(), c.1 * unlock_ratio))
+        .filter(|c| !c.1.is_zero())
+        .collect();
 
-    // Store left collaterals
-    cur_collaterals.sub(liquidation_amount.clone())?;
-    store_collaterals(deps.storage, &borrower_raw, &cur_collaterals)?;
+    cur_collaterals.sub(collaterals.clone())?;
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
 
-    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
-    let prev_balance: Uint256 =
-        query_balance(deps.as_ref(), market_contract.clone(), config.stable_denom)?;
+    let mut messages: Vec<SubMsg> = vec![];
+    let mut transfers: Vec<(CanonicalAddr, Uint256, CanonicalAddr)> = vec![];
+    for collateral in collaterals.iter() {
+        let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        decrease_total_locked(deps.storage, &collateral.0, collateral.1)?;
+        if let TokenType::Native { denom } = &whitelist_elem.token_type {
+            messages.push(SubMsg::new(native_collateral_msg(
+                deps.as_ref(),
+                denom,
+                &borrower,
+                collateral.1,
+            )?));
+            continue;
+        }
+        for (custody_contract, amount) in unlock_custody_amount(
+            deps.storage,
+            &borrower_raw,
+            &collateral.0,
+            &whitelist_elem,
+            collateral.1,
+        )? {
+            messages.push(custody_transfer_msg(
+                &config,
+                &mut transfers,
+                UNLOCK_COLLATERAL_REPLY_ID,
+                (collateral.0.clone(), amount, custody_contract.clone()),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                        borrower: borrower.to_string(),
+                        amount,
+                    })?,
+                }),
+            ));
+        }
+    }
+    store_custody_transfers(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        transfers,
+    )?;
 
-    let liquidation_messages: Vec<CosmosMsg> = liquidation_amount
+    let collateral_logs: Vec<String> = collaterals
+        .to_human(deps.as_ref())?
         .iter()
-        .map(|collateral| {
-            let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
-
-            Ok(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: deps
-                    .api
-                    .addr_humanize(&whitelist_elem.custody_contract)?
-                    .to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
-                    liquidator: info.sender.to_string(),
-                    borrower: borrower.to_string(),
-                    amount: collateral.1,
-                })?,
-            }))
-        })
-        .filter(|msg| msg.is_ok())
-        .collect::<StdResult<Vec<CosmosMsg>>>()?;
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
 
     Ok(Response::new()
-        .add_messages(liquidation_messages)
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: market_contract.to_string(),
-            funds: vec![],
-            msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
-                borrower: borrower.to_string(),
-                prev_balance,
-            })?,
-        })))
+        .add_submessages(messages)
+        .add_attributes(vec![
+            attr("action", "unlock_collateral_from_repay"),
+            attr("borrower", borrower),
+            attr("collaterals", collateral_logs.join(",")),
+        ])
+        .add_attributes(price_deviations))
 }
 
-pub fn query_collaterals(deps: Deps, borrower: Addr) -> StdResult<CollateralsResponse> {
-    let collaterals: Tokens = read_collaterals(
-        deps.storage,
-        &deps.api.addr_canonicalize(borrower.as_str())?,
-    );
+/// Repay the whole loan of the default position of the sender from the
+/// attached funds and send all of its collaterals to the recipient.
+/// Rejected while a sub account of the sender still carries a loan
+pub fn close_and_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
 
-    Ok(CollateralsResponse {
-        borrower: borrower.to_string(),
-        collaterals: collaterals
-            .iter()
-            .map(|c| Ok((deps.api.addr_humanize(&c.0)?.to_string(), c.1)))
-            .collect::<StdResult<Vec<(String, Uint256)>>>()?,
-    })
-}
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut start_after: Option<String> = None;
+    loop {
+        let sub_accounts =
+            read_sub_account_collaterals(deps.storage, &borrower_raw, start_after, None)?;
+        for (sub_account, _) in sub_accounts.iter() {
+            let sub_account_loan = query_borrower_info(
+                deps.as_ref(),
+                market.clone(),
+                borrower.clone(),
+                env.block.height,
+                Some(sub_account.clone()),
+            )?
+            .loan_amount;
+            if !sub_account_loan.is_zero() {
+                return Err(ContractError::CloseSubAccountLoan(sub_account.clone()));
+            }
+        }
 
-pub fn query_all_collaterals(
-    deps: Deps,
-    start_after: Option<Addr>,
-    limit: Option<u32>,
-) -> StdResult<AllCollateralsResponse> {
-    let start_after = if let Some(start_after) = start_after {
-        Some(deps.api.addr_canonicalize(start_after.as_str())?)
-    } else {
-        None
-    };
+        match sub_accounts.last() {
+            Some((sub_account, _)) => start_after = Some(sub_account.clone()),
+            None => break,
+        }
+    }
 
-    let all_collaterals: Vec<CollateralsResponse> = read_all_collaterals(deps, start_after, limit)?;
+    let loan_amount = query_borrower_info(
+        deps.as_ref(),
+        market.clone(),
+        borrower.clone(),
+        env.block.height,
+        None,
+    )?
+    .loan_amount;
 
-    Ok(AllCollateralsResponse { all_collaterals })
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let repay_amount: Uint256 = info
+        .funds
+        .iter()
+        .find(|c| c.denom == config.stable_denom)
+        .map(|c| Uint256::from(c.amount))
+        .unwrap_or_else(Uint256::zero);
+    if !loan_amount.is_zero() {
+        // The market receives the funds less the tax of the forwarding transfer
+        let repay_coin = deduct_tax(
+            deps.as_ref(),
+            Coin {
+                denom: config.stable_denom.clone(),
+                amount: repay_amount.into(),
+            },
+        )?;
+        let received_amount = Uint256::from(repay_coin.amount);
+        if received_amount < loan_amount {
+            return Err(ContractError::CloseRepaymentShortfall(
+                (loan_amount - received_amount).into(),
+            ));
+        }
+
+        // Repaid the way the borrower would, the market refunds any excess
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market.to_string(),
+            funds: vec![repay_coin],
+            msg: to_binary(&MarketExecuteMsg::RepayStableOnBehalf {
+                borrower: borrower.to_string(),
+                sub_account: None,
+            })?,
+        }));
+    } else if !repay_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: borrower.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom.clone(),
+                    amount: repay_amount.into(),
+                },
+            )?],
+        }));
+    }
+
+    // The collaterals are only released once the market confirms the loan is gone
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::CloseAndWithdrawHook {
+            borrower: borrower.to_string(),
+            recipient: recipient.to_string(),
+        })?,
+    }));
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "close_and_withdraw"),
+        attr("borrower", borrower),
+        attr("recipient", recipient),
+        attr("repay_amount", loan_amount),
+    ]))
 }
 
-#[allow(clippy::ptr_arg)]
-pub(crate) fn compute_borrow_limit(
-    deps: Deps,
-    collaterals: &Tokens,
-    block_time: Option<u64>,
-) -> StdResult<(Uint256, Vec<Decimal256>)> {
+pub fn close_and_withdraw_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
     let config: Config = read_config(deps.storage)?;
-    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+    let loan_amount = query_borrower_info(
+        deps.as_ref(),
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        None,
+    )?
+    .loan_amount;
+    if !loan_amount.is_zero() {
+        return Err(ContractError::CloseRepaymentShortfall(loan_amount.into()));
+    }
 
-    let mut borrow_limit: Uint256 = Uint256::zero();
-    let mut collateral_prices: Vec<Decimal256> = vec![];
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let collaterals: Tokens = read_collaterals(deps.storage, &borrower_raw, None);
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    store_collaterals(deps.storage, &borrower_raw, None, &vec![])?;
     for collateral in collaterals.iter() {
-        let collateral_token = collateral.0.clone();
-        let collateral_amount = collateral.1;
+        let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        decrease_total_locked(deps.storage, &collateral.0, collateral.1)?;
+        if let TokenType::Native { denom } = &whitelist_elem.token_type {
+            messages.push(native_collateral_msg(
+                deps.as_ref(),
+                denom,
+                &recipient,
+                collateral.1,
+            )?);
+            continue;
+        }
+        for (custody_contract, amount) in unlock_custody_amount(
+            deps.storage,
+            &borrower_raw,
+            &collateral.0,
+            &whitelist_elem,
+            collateral.1,
+        )? {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::ReleaseCollateral {
+                    borrower: borrower.to_string(),
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+            }));
+        }
+    }
 
-        let price: PriceResponse = query_price(
-            deps,
-            oracle_contract.clone(),
-            (deps.api.addr_humanize(&collateral_token)?).to_string(),
-            config.stable_denom.to_string(),
-            block_time.map(|block_time| TimeConstraints {
-                block_time,
-                valid_timeframe: config.price_timeframe,
-            }),
-        )?;
+    let collateral_logs: Vec<String> = collaterals
+        .to_human(deps.as_ref())?
+        .iter()
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
 
-        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
-        let collateral_value = collateral_amount * price.rate;
-        borrow_limit += collateral_value * elem.max_ltv;
-        collateral_prices.push(price.rate);
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "close_and_withdraw_hook"),
+        attr("borrower", borrower),
+        attr("recipient", recipient),
+        attr("collaterals", collateral_logs.join(",")),
+    ]))
+}
+
+/// Collaterals stay in the custody contracts,
+/// only the overseer positions are updated
+pub fn reallocate_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collaterals_human: TokensHuman,
+    from_sub_account: Option<String>,
+    to_sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    if from_sub_account == to_sub_account {
+        return Err(ContractError::SameSubAccount {});
     }
 
-    // returns borrow_limit with collaterals value in stable denom
-    Ok((borrow_limit, collateral_prices))
-}
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
 
-pub fn query_borrow_limit(
-    deps: Deps,
-    borrower: Addr,
-    block_time: Option<u64>,
-) -> StdResult<BorrowLimitResponse> {
-    let collaterals = read_collaterals(
-        deps.storage,
-        &deps.api.addr_canonicalize(borrower.as_str())?,
-    );
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut from_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, from_sub_account.as_deref());
+    let mut to_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, to_sub_account.as_deref());
+    let to_collaterals_len = to_collaterals.len();
+    let collaterals: Tokens = collaterals_human.to_raw(deps.as_ref())?;
 
-    // Compute borrow limit with collaterals
-    let (borrow_limit, _) = compute_borrow_limit(deps, &collaterals, block_time)?;
+    if from_collaterals.sub(collaterals.clone()).is_err() {
+        return Err(ContractError::UnlockExceedsLocked {});
+    }
 
-    Ok(BorrowLimitResponse {
-        borrower: borrower.to_string(),
-        borrow_limit,
+    let (borrow_limit, _) = compute_borrow_limit(
+        deps.as_ref(),
+        &from_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    let borrow_amount_res: BorrowerInfoResponse = query_borrower_info(
+        deps.as_ref(),
+        market,
+        borrower.clone(),
+        env.block.height,
+        from_sub_account.clone(),
+    )?;
+    if borrow_limit < borrow_amount_res.loan_amount {
+        return Err(ContractError::UnlockTooLarge(borrow_limit.into()));
+    }
+
+    to_collaterals.add(collaterals);
+    if to_collaterals.len() > to_collaterals_len
+        && to_collaterals.len() > config.max_collaterals_per_borrower as usize
+    {
+        return Err(ContractError::TooManyCollaterals(
+            config.max_collaterals_per_borrower,
+        ));
+    }
+
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        from_sub_account.as_deref(),
+        &from_collaterals,
+    )?;
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        to_sub_account.as_deref(),
+        &to_collaterals,
+    )?;
+
+    let collateral_logs: Vec<String> = collaterals_human
+        .iter()
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "reallocate_collateral"),
+        attr("borrower", borrower),
+        attr("from_sub_account", from_sub_account.unwrap_or_default()),
+        attr("to_sub_account", to_sub_account.unwrap_or_default()),
+        attr("collaterals", collateral_logs.join(",")),
+    ]))
+}
+
+pub fn split_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collaterals_human: TokensHuman,
+    debt_fraction: Decimal256,
+    from_sub_account: Option<String>,
+    to_sub_account: String,
+) -> Result<Response, ContractError> {
+    if from_sub_account.as_deref() == Some(to_sub_account.as_str()) {
+        return Err(ContractError::SameSubAccount {});
+    }
+    if debt_fraction > Decimal256::one() {
+        return Err(ContractError::InvalidDebtFraction {});
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let loan_amount = query_borrower_info(
+        deps.as_ref(),
+        market.clone(),
+        borrower.clone(),
+        env.block.height,
+        from_sub_account.clone(),
+    )?
+    .loan_amount;
+    let to_loan_amount = query_borrower_info(
+        deps.as_ref(),
+        market.clone(),
+        borrower.clone(),
+        env.block.height,
+        Some(to_sub_account.clone()),
+    )?
+    .loan_amount;
+    if !read_collaterals(deps.storage, &borrower_raw, Some(&to_sub_account)).is_empty()
+        || !to_loan_amount.is_zero()
+    {
+        return Err(ContractError::SubAccountNotEmpty(to_sub_account));
+    }
+
+    let mut from_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, from_sub_account.as_deref());
+    let mut to_collaterals: Tokens = vec![];
+    to_collaterals.add(collaterals_human.to_raw(deps.as_ref())?);
+    if from_collaterals.sub(to_collaterals.clone()).is_err() {
+        return Err(ContractError::UnlockExceedsLocked {});
+    }
+
+    // Both positions are checked against the loan they end up with
+    let split_loan_amount = loan_amount * debt_fraction;
+    for (collaterals, loan_amount) in [
+        (&from_collaterals, loan_amount - split_loan_amount),
+        (&to_collaterals, split_loan_amount),
+    ] {
+        let (borrow_limit, _) =
+            compute_borrow_limit(deps.as_ref(), collaterals, Some(env.block.time.seconds()))?;
+        if borrow_limit < loan_amount {
+            return Err(ContractError::SplitExceedsLimit(borrow_limit.into()));
+        }
+    }
+
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        from_sub_account.as_deref(),
+        &from_collaterals,
+    )?;
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        Some(&to_sub_account),
+        &to_collaterals,
+    )?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !split_loan_amount.is_zero() {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market.to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::SplitLoan {
+                borrower: borrower.to_string(),
+                from_sub_account: from_sub_account.clone(),
+                to_sub_account: to_sub_account.clone(),
+                fraction: debt_fraction,
+            })?,
+        }));
+    }
+
+    let collateral_logs: Vec<String> = collaterals_human
+        .iter()
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "split_position"),
+        attr("borrower", borrower),
+        attr("from_sub_account", from_sub_account.unwrap_or_default()),
+        attr("to_sub_account", to_sub_account),
+        attr("collaterals", collateral_logs.join(",")),
+        attr("debt_fraction", debt_fraction.to_string()),
+        attr("loan_amount", split_loan_amount),
+    ]))
+}
+
+/// The old collaterals are sent from their custody contracts to the
+/// converter, which deposits the new collaterals to their custody contract
+/// on behalf of the borrower before they are locked again
+pub fn migrate_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    from_token: Addr,
+    to_token: Addr,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    if from_token == to_token {
+        return Err(ContractError::SameCollateralToken {});
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let converter = match &config.collateral_converter {
+        Some(converter) => deps.api.addr_humanize(converter)?,
+        None => return Err(ContractError::ConverterNotConfigured {}),
+    };
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let from_token_raw = deps.api.addr_canonicalize(from_token.as_str())?;
+    let to_token_raw = deps.api.addr_canonicalize(to_token.as_str())?;
+    let from_whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &from_token_raw)?;
+    let to_whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &to_token_raw)?;
+
+    // Conversions go through the custody contracts of both collaterals
+    for (token, elem) in [
+        (&from_token, &from_whitelist_elem),
+        (&to_token, &to_whitelist_elem),
+    ] {
+        if elem.token_type != TokenType::Cw20 {
+            return Err(ContractError::NativeCollateralUnsupported(
+                token.to_string(),
+            ));
+        }
+    }
+
+    // The converter sends the whole converted amount to a single recipient
+    if to_whitelist_elem.custody_contracts.len() != 1 {
+        return Err(ContractError::MultipleCustodyContracts(
+            to_token.to_string(),
+        ));
+    }
+    let to_custody = deps
+        .api
+        .addr_humanize(&to_whitelist_elem.custody_contracts[0].custody_contract)?;
+
+    let borrower = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let prev_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+    let from_amount = prev_collaterals
+        .iter()
+        .find(|c| c.0 == from_token_raw)
+        .map(|c| c.1)
+        .unwrap_or_default();
+    if from_amount.is_zero() {
+        return Err(ContractError::NothingToMigrate(from_token.to_string()));
+    }
+
+    // Each custody contract sends its share to the converter separately
+    let deposit_msg = to_binary(&CustodyCw20HookMsg::DepositCollateralFor {
+        borrower: borrower.to_string(),
+    })?;
+    let mut to_amount = Uint256::zero();
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (custody_contract, amount) in unlock_custody_amount(
+        deps.storage,
+        &borrower_raw,
+        &from_token_raw,
+        &from_whitelist_elem,
+        from_amount,
+    )? {
+        to_amount += query_conversion(
+            deps.as_ref(),
+            converter.clone(),
+            from_token.clone(),
+            to_token.clone(),
+            amount,
+        )?;
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::ConvertCollateral {
+                borrower: borrower.to_string(),
+                amount,
+                converter: converter.to_string(),
+                msg: to_binary(&ConverterCw20HookMsg::Convert {
+                    ask_token: to_token.to_string(),
+                    recipient: to_custody.to_string(),
+                    msg: Some(deposit_msg.clone()),
+                })?,
+            })?,
+        }));
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: to_custody.to_string(),
+        funds: vec![],
+        msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+            borrower: borrower.to_string(),
+            amount: to_amount,
+        })?,
+    }));
+    add_custody_locks(
+        deps.storage,
+        &borrower_raw,
+        &to_token_raw,
+        vec![(
+            to_whitelist_elem.custody_contracts[0]
+                .custody_contract
+                .clone(),
+            to_amount,
+        )],
+    )?;
+
+    let mut cur_collaterals: Tokens = prev_collaterals.clone();
+    cur_collaterals.sub(vec![(from_token_raw.clone(), from_amount)])?;
+    cur_collaterals.add(vec![(to_token_raw.clone(), to_amount)]);
+
+    // Like an unlock, an underwater position cannot move its collaterals,
+    // and the migrated one is checked with the converted amount; the lock
+    // message fails if the converter returns less
+    let (prev_borrow_limit, prev_collateral_prices) = compute_borrow_limit(
+        deps.as_ref(),
+        &prev_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    let borrow_amount_res: BorrowerInfoResponse = query_borrower_info(
+        deps.as_ref(),
+        market,
+        borrower.clone(),
+        env.block.height,
+        sub_account.clone(),
+    )?;
+    if prev_borrow_limit < borrow_amount_res.loan_amount {
+        return Err(ContractError::UnlockUnderwaterLoan(
+            prev_borrow_limit.into(),
+        ));
+    }
+    let (borrow_limit, _) = compute_borrow_limit(
+        deps.as_ref(),
+        &cur_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    if borrow_limit < borrow_amount_res.loan_amount {
+        return Err(ContractError::MigrationUnderwater(borrow_limit.into()));
+    }
+
+    // Collaterals locked in this block keep backing borrows from the next
+    // one only, so the converted share of them is carried to the new token
+    if config.same_block_collateral_delay {
+        if let Some(mut recent_lock) =
+            read_recent_lock(deps.storage, &borrower_raw, sub_account.as_deref())?
+        {
+            let recent_amount = recent_lock
+                .collaterals
+                .iter()
+                .find(|c| c.0 == from_token_raw)
+                .map(|c| std::cmp::min(c.1, from_amount))
+                .unwrap_or_default();
+            if recent_lock.block_height == env.block.height && !recent_amount.is_zero() {
+                recent_lock
+                    .collaterals
+                    .sub(vec![(from_token_raw.clone(), recent_amount)])?;
+                recent_lock.collaterals.add(vec![(
+                    to_token_raw.clone(),
+                    to_amount * Decimal256::from_ratio(recent_amount, from_amount),
+                )]);
+                store_recent_lock(
+                    deps.storage,
+                    &borrower_raw,
+                    sub_account.as_deref(),
+                    &recent_lock,
+                )?;
+            }
+        }
+    }
+
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
+    decrease_total_locked(deps.storage, &from_token_raw, from_amount)?;
+    let total_locked = read_total_locked(deps.storage, &to_token_raw) + to_amount;
+    store_total_locked(deps.storage, &to_token_raw, &total_locked)?;
+    let mut price_deviations = record_collateral_prices(
+        deps.storage,
+        deps.api,
+        config.price_deviation_threshold,
+        &prev_collaterals,
+        &prev_collateral_prices,
+    )?;
+    price_deviations.extend(observe_price_drops(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        &prev_collaterals,
+        &prev_collateral_prices,
+    )?);
+
+    let prev_collateral_logs: Vec<String> = prev_collaterals
+        .to_human(deps.as_ref())?
+        .iter()
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
+    let collateral_logs: Vec<String> = cur_collaterals
+        .to_human(deps.as_ref())?
+        .iter()
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr("action", "migrate_position"),
+            attr("borrower", borrower),
+            attr("from_token", from_token),
+            attr("to_token", to_token),
+            attr("collaterals_before", prev_collateral_logs.join(",")),
+            attr("collaterals_after", collateral_logs.join(",")),
+            attr("borrow_limit_before", prev_borrow_limit),
+            attr("borrow_limit_after", borrow_limit),
+        ])
+        .add_attributes(price_deviations))
+}
+
+pub fn transfer_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: Addr,
+    expires_at: u64,
+) -> Result<Response, ContractError> {
+    if recipient == info.sender {
+        return Err(ContractError::SelfPositionTransfer {});
+    }
+    if expires_at <= env.block.height {
+        return Err(ContractError::InvalidTransferExpiry {});
+    }
+
+    store_position_transfer(
+        deps.storage,
+        &deps.api.addr_canonicalize(info.sender.as_str())?,
+        &PositionTransfer {
+            recipient: deps.api.addr_canonicalize(recipient.as_str())?,
+            expires_at,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_position"),
+        attr("borrower", info.sender),
+        attr("recipient", recipient),
+        attr("expires_at", expires_at.to_string()),
+    ]))
+}
+
+pub fn cancel_position_transfer(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let borrower_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if read_position_transfer(deps.storage, &borrower_raw)?.is_none() {
+        return Err(ContractError::NoPositionTransfer(info.sender.to_string()));
+    }
+
+    remove_position_transfer(deps.storage, &borrower_raw);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "cancel_position_transfer"),
+        attr("borrower", info.sender),
+    ]))
+}
+
+pub fn delegate_liquidation(
+    deps: DepsMut,
+    info: MessageInfo,
+    keeper: Addr,
+    max_bonus: Uint256,
+) -> Result<Response, ContractError> {
+    if keeper == info.sender {
+        return Err(ContractError::SelfLiquidationDelegation {});
+    }
+
+    store_liquidation_delegation(
+        deps.storage,
+        &deps.api.addr_canonicalize(info.sender.as_str())?,
+        &LiquidationDelegation {
+            keeper: deps.api.addr_canonicalize(keeper.as_str())?,
+            max_bonus,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "delegate_liquidation"),
+        attr("borrower", info.sender),
+        attr("keeper", keeper),
+        attr("max_bonus", max_bonus),
+    ]))
+}
+
+pub fn revoke_liquidation_delegation(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let borrower_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if read_liquidation_delegation(deps.storage, &borrower_raw)?.is_none() {
+        return Err(ContractError::NoLiquidationDelegation(
+            info.sender.to_string(),
+        ));
+    }
+
+    remove_liquidation_delegation(deps.storage, &borrower_raw);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_liquidation_delegation"),
+        attr("borrower", info.sender),
+    ]))
+}
+
+/// The locked collaterals move between the custody balances of the two
+/// addresses and the loan between their market positions
+pub fn accept_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let recipient = info.sender;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let recipient_raw = deps.api.addr_canonicalize(recipient.as_str())?;
+    let transfer = match read_position_transfer(deps.storage, &borrower_raw)? {
+        Some(transfer) if transfer.recipient == recipient_raw => transfer,
+        _ => return Err(ContractError::NoPositionTransfer(borrower.to_string())),
+    };
+    if env.block.height >= transfer.expires_at {
+        return Err(ContractError::PositionTransferExpired(borrower.to_string()));
+    }
+
+    let collaterals: Tokens = read_collaterals(deps.storage, &borrower_raw, None);
+    let mut recipient_collaterals: Tokens = read_collaterals(deps.storage, &recipient_raw, None);
+    let recipient_collaterals_len = recipient_collaterals.len();
+    recipient_collaterals.add(collaterals.clone());
+    if recipient_collaterals.len() > recipient_collaterals_len
+        && recipient_collaterals.len() > config.max_collaterals_per_borrower as usize
+    {
+        return Err(ContractError::TooManyCollaterals(
+            config.max_collaterals_per_borrower,
+        ));
+    }
+
+    let loan_amount = query_borrower_info(
+        deps.as_ref(),
+        market.clone(),
+        borrower.clone(),
+        env.block.height,
+        None,
+    )?
+    .loan_amount;
+    let recipient_loan_amount = query_borrower_info(
+        deps.as_ref(),
+        market.clone(),
+        recipient.clone(),
+        env.block.height,
+        None,
+    )?
+    .loan_amount;
+
+    let (borrow_limit, _) = compute_borrow_limit(
+        deps.as_ref(),
+        &recipient_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    if borrow_limit < loan_amount + recipient_loan_amount {
+        return Err(ContractError::TransferExceedsLimit(borrow_limit.into()));
+    }
+
+    remove_position_transfer(deps.storage, &borrower_raw);
+    store_collaterals(deps.storage, &borrower_raw, None, &vec![])?;
+    store_collaterals(deps.storage, &recipient_raw, None, &recipient_collaterals)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for collateral in collaterals.iter() {
+        let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        if whitelist_elem.token_type != TokenType::Cw20 {
+            continue;
+        }
+        let custody_amounts = unlock_custody_amount(
+            deps.storage,
+            &borrower_raw,
+            &collateral.0,
+            &whitelist_elem,
+            collateral.1,
+        )?;
+        add_custody_locks(
+            deps.storage,
+            &recipient_raw,
+            &collateral.0,
+            custody_amounts.clone(),
+        )?;
+        for (custody_contract, amount) in custody_amounts {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::TransferCollateral {
+                    borrower: borrower.to_string(),
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+            }));
+        }
+    }
+
+    if !loan_amount.is_zero() {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market.to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::TransferLoan {
+                borrower: borrower.to_string(),
+                recipient: recipient.to_string(),
+            })?,
+        }));
+    }
+
+    let collateral_logs: Vec<String> = collaterals
+        .to_human(deps.as_ref())?
+        .iter()
+        .map(|c| format!("{}{}", c.1, c.0))
+        .collect();
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "accept_position"),
+        attr("borrower", borrower),
+        attr("recipient", recipient),
+        attr("collaterals", collateral_logs.join(",")),
+        attr("loan_amount", loan_amount),
+    ]))
+}
+
+pub fn liquidate_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if config.liquidation_frozen {
+        return Err(ContractError::LiquidationFrozen {});
+    }
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut cur_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    // Compute borrow limit with collaterals except unlock target collaterals
+    let (borrow_limit, collateral_prices) = compute_liquidation_borrow_limit(
+        deps.as_ref(),
+        &cur_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    let borrow_amount_res: BorrowerInfoResponse = query_borrower_info(
+        deps.as_ref(),
+        market,
+        borrower.clone(),
+        env.block.height,
+        sub_account.clone(),
+    )?;
+    let borrow_amount = borrow_amount_res.loan_amount;
+
+    // The keeper delegated by the borrower may liquidate a safe loan,
+    // which is then sized to be repaid in full
+    let liquidator_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let delegation = read_liquidation_delegation(deps.storage, &borrower_raw)?
+        .filter(|delegation| delegation.keeper == liquidator_raw);
+    let safe_loan = borrow_limit >= borrow_amount;
+
+    // borrow limit is equal or bigger than loan amount
+    // cannot liquidation collaterals
+    if safe_loan && delegation.is_none() {
+        return Err(ContractError::CannotLiquidateSafeLoan {});
+    }
+
+    // A sharp price drop pauses the liquidations against the collateral
+    // for the cooldown, so the price can confirm first
+    let price_drops = observe_price_drops(
+        deps.storage,
+        deps.api,
+        &config,
+        env.block.height,
+        &cur_collaterals,
+        &collateral_prices,
+    )?;
+    if let Some(collateral_token) =
+        cooling_down_collateral(deps.storage, &config, env.block.height, &cur_collaterals)?
+    {
+        if price_drops.is_empty() {
+            return Err(ContractError::LiquidationPaused(
+                deps.api.addr_humanize(&collateral_token)?.to_string(),
+            ));
+        }
+
+        // The observation that started the pause is kept
+        return Ok(Response::new()
+            .add_attributes(vec![
+                attr("action", "pause_liquidation"),
+                attr("borrower", borrower),
+            ])
+            .add_attributes(price_drops));
+    }
+
+    // Cover a marginal shortfall from the interest buffer while the
+    // backstop has budget left in this epoch, only the owner and the
+    // risk admin draw on the buffer so it is not drained by any caller
+    let backstop_caller =
+        liquidator_raw == config.owner_addr || config.risk_admin.as_ref() == Some(&liquidator_raw);
+    if config.soft_liquidation && !safe_loan && backstop_caller {
+        let shortfall = borrow_amount - borrow_limit;
+        let backstop_used = checked_add(read_backstop_used(deps.storage)?, shortfall)?;
+        let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
+        if backstop_used <= config.buffer_backstop_limit && shortfall <= interest_buffer {
+            return backstop_repay(
+                deps,
+                &config,
+                borrower,
+                sub_account,
+                shortfall,
+                backstop_used,
+            );
+        }
+    }
+
+    let price_deviations = record_collateral_prices(
+        deps.storage,
+        deps.api,
+        config.price_deviation_threshold,
+        &cur_collaterals,
+        &collateral_prices,
+    )?;
+
+    let liquidation_contract = deps.api.addr_humanize(&config.liquidation_contract)?;
+    let liquidation_amount_res: LiquidationAmountResponse = query_liquidation_amount(
+        deps.as_ref(),
+        liquidation_contract.clone(),
+        borrow_amount,
+        if safe_loan {
+            Uint256::zero()
+        } else {
+            borrow_limit
+        },
+        &cur_collaterals.to_human(deps.as_ref())?,
+        collateral_prices.clone(),
+        collateral_premium_rates(
+            deps.as_ref(),
+            liquidation_contract.clone(),
+            &cur_collaterals,
+        )?,
+    )?;
+
+    let liquidation_amount = sweep_dust_collaterals(
+        &config,
+        &cur_collaterals,
+        &collateral_prices,
+        liquidation_amount_res.collaterals.to_raw(deps.as_ref())?,
+    )?;
+
+    let mut attributes = price_deviations;
+    attributes.extend(price_drops);
+
+    // Under the auction model the seized collaterals are sold by a Dutch
+    // auction instead, as long as every collateral is liquidated by an auction
+    // contract. A delegated liquidation is always executed at once
+    let liquidation_routes =
+        route_liquidation_amount(deps.as_ref(), &liquidation_contract, &liquidation_amount)?;
+    if delegation.is_none()
+        && liquidation_routes
+            .iter()
+            .map(|(contract, _)| query_auction_duration(deps.as_ref(), contract.clone()))
+            .collect::<StdResult<Vec<_>>>()?
+            .iter()
+            .all(|d| d.is_some())
+    {
+        return start_liquidation_auction(
+            deps,
+            liquidation_routes,
+            borrower,
+            sub_account,
+            cur_collaterals,
+            liquidation_amount,
+            attributes,
+        );
+    }
+
+    if let Some(delegation) = delegation {
+        let (seized_value, repaid, bid_fee) = compute_liquidation_proceeds(
+            deps.as_ref(),
+            liquidation_contract,
+            &cur_collaterals,
+            &collateral_prices,
+            &liquidation_amount,
+        )?;
+        let bonus = seized_value - repaid - bid_fee;
+        if bonus > delegation.max_bonus {
+            return Err(ContractError::DelegatedBonusExceedsCap(
+                delegation.max_bonus.into(),
+            ));
+        }
+
+        // A delegation is used up by the liquidation
+        remove_liquidation_delegation(deps.storage, &borrower_raw);
+        attributes.push(attr("delegated_keeper", info.sender.to_string()));
+    }
+
+    // The record is written once the market reports the amount it repaid
+    store_pending_liquidation_record(
+        deps.storage,
+        &LiquidationRecord {
+            borrower: borrower_raw.clone(),
+            liquidator: liquidator_raw,
+            repaid: Uint256::zero(),
+            seized_tokens: liquidation_amount.clone(),
+            block_height: env.block.height,
+        },
+    )?;
+
+    // Store left collaterals
+    cur_collaterals.sub(liquidation_amount.clone())?;
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
+
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+    let prev_balance: Uint256 = query_balance(
+        deps.as_ref(),
+        market_contract.clone(),
+        config.stable_denom.clone(),
+    )?;
+
+    let mut liquidation_messages: Vec<SubMsg> = vec![];
+    let mut transfers: Vec<(CanonicalAddr, Uint256, CanonicalAddr)> = vec![];
+    for collateral in liquidation_amount.iter() {
+        let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        decrease_total_locked(deps.storage, &collateral.0, collateral.1)?;
+        if let TokenType::Native { denom } = &whitelist_elem.token_type {
+            let liquidation_contract = match &whitelist_elem.liquidation_contract {
+                Some(contract) => contract,
+                None => &config.liquidation_contract,
+            };
+            liquidation_messages.push(SubMsg::new(native_liquidation_msg(
+                deps.as_ref(),
+                &deps.api.addr_humanize(liquidation_contract)?,
+                denom,
+                collateral.1,
+                &LiquidationExecuteMsg::ExecuteBid {
+                    collateral_token: deps.api.addr_humanize(&collateral.0)?.to_string(),
+                    liquidator: info.sender.to_string(),
+                    fee_address: Some(env.contract.address.to_string()),
+                    repay_address: Some(market_contract.to_string()),
+                    borrower: Some(borrower.to_string()),
+                    premium_rate: whitelist_elem.liquidation_premium,
+                },
+            )?));
+            continue;
+        }
+        for (custody_contract, amount) in unlock_custody_amount(
+            deps.storage,
+            &borrower_raw,
+            &collateral.0,
+            &whitelist_elem,
+            collateral.1,
+        )? {
+            liquidation_messages.push(custody_transfer_msg(
+                &config,
+                &mut transfers,
+                LIQUIDATE_COLLATERAL_REPLY_ID,
+                (collateral.0.clone(), amount, custody_contract.clone()),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                        liquidator: info.sender.to_string(),
+                        borrower: borrower.to_string(),
+                        amount,
+                        premium_rate: whitelist_elem.liquidation_premium,
+                    })?,
+                }),
+            ));
+        }
+    }
+    store_custody_transfers(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        transfers,
+    )?;
+
+    // The market repays the loan with the stable amount the liquidation
+    // contract paid, skipped collaterals are not paid for
+    Ok(Response::new()
+        .add_submessages(liquidation_messages)
+        .add_submessage(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: market_contract.to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                    borrower: borrower.to_string(),
+                    prev_balance,
+                    sub_account,
+                })?,
+            }),
+            LIQUIDATION_RECORD_REPLY_ID,
+        ))
+        .add_attributes(attributes))
+}
+
+/// Escrows the seized collaterals in the liquidation contract under an
+/// auction of the borrower, the loan is repaid once a bid fills it
+fn start_liquidation_auction(
+    deps: DepsMut,
+    liquidation_routes: Vec<(Addr, Tokens)>,
+    borrower: Addr,
+    sub_account: Option<String>,
+    mut cur_collaterals: Tokens,
+    liquidation_amount: Tokens,
+    attributes: Vec<Attribute>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    cur_collaterals.sub(liquidation_amount)?;
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
+
+    // Each liquidation contract auctions the collaterals routed to it,
+    // the auction is opened before the custody contracts escrow the collaterals
+    let mut auction_messages: Vec<SubMsg> = vec![];
+    let mut transfers: Vec<(CanonicalAddr, Uint256, CanonicalAddr)> = vec![];
+    for (liquidation_contract, collaterals) in liquidation_routes {
+        auction_messages.push(SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: liquidation_contract.to_string(),
+            funds: vec![],
+            msg: to_binary(&LiquidationExecuteMsg::StartAuction {
+                borrower: borrower.to_string(),
+                sub_account: sub_account.clone(),
+                collaterals: collaterals.to_human(deps.as_ref())?,
+            })?,
+        })));
+        for collateral in collaterals.iter() {
+            let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+            decrease_total_locked(deps.storage, &collateral.0, collateral.1)?;
+            if let TokenType::Native { denom } = &whitelist_elem.token_type {
+                auction_messages.push(SubMsg::new(native_liquidation_msg(
+                    deps.as_ref(),
+                    &liquidation_contract,
+                    denom,
+                    collateral.1,
+                    &LiquidationExecuteMsg::ExecuteBid {
+                        collateral_token: deps.api.addr_humanize(&collateral.0)?.to_string(),
+                        liquidator: liquidation_contract.to_string(),
+                        fee_address: None,
+                        repay_address: None,
+                        borrower: Some(borrower.to_string()),
+                        premium_rate: None,
+                    },
+                )?));
+                continue;
+            }
+            for (custody_contract, amount) in unlock_custody_amount(
+                deps.storage,
+                &borrower_raw,
+                &collateral.0,
+                &whitelist_elem,
+                collateral.1,
+            )? {
+                auction_messages.push(custody_transfer_msg(
+                    &config,
+                    &mut transfers,
+                    LIQUIDATE_COLLATERAL_REPLY_ID,
+                    (collateral.0.clone(), amount, custody_contract.clone()),
+                    CosmosMsg::Wasm(WasmMsg::Execute {
+                        contract_addr: deps.api.addr_humanize(&custody_contract)?.to_string(),
+                        funds: vec![],
+                        msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                            liquidator: liquidation_contract.to_string(),
+                            borrower: borrower.to_string(),
+                            amount,
+                            premium_rate: None,
+                        })?,
+                    }),
+                ));
+            }
+        }
+    }
+    store_custody_transfers(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        transfers,
+    )?;
+
+    Ok(Response::new()
+        .add_submessages(auction_messages)
+        .add_attributes(vec![
+            attr("action", "start_liquidation_auction"),
+            attr("borrower", borrower),
+        ])
+        .add_attributes(attributes))
+}
+
+/// Forwards the proceeds of a liquidation auction to the market
+/// as a repayment of the loan of the borrower
+pub fn repay_auction_proceeds(
+    deps: DepsMut,
+    info: MessageInfo,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.liquidation_contract
+        && !read_all_whitelist_elems(deps.storage)?
+            .iter()
+            .any(|(_, elem)| elem.liquidation_contract.as_ref() == Some(&sender_raw))
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let amount: Uint256 = info
+        .funds
+        .iter()
+        .find(|c| c.denom == config.stable_denom)
+        .map(|c| Uint256::from(c.amount))
+        .unwrap_or_else(Uint256::zero);
+    if amount.is_zero() {
+        return Err(ContractError::AuctionProceedsNotProvided(
+            config.stable_denom,
+        ));
+    }
+
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+    let prev_balance: Uint256 = query_balance(
+        deps.as_ref(),
+        market_contract.clone(),
+        config.stable_denom.to_string(),
+    )?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: market_contract.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: amount.into(),
+                },
+            )?],
+        }))
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market_contract.to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                borrower: borrower.to_string(),
+                prev_balance,
+                sub_account,
+            })?,
+        }))
+        .add_attributes(vec![
+            attr("action", "repay_auction_proceeds"),
+            attr("borrower", borrower),
+            attr("amount", amount),
+        ]))
+}
+
+/// Locks the collaterals of an expired liquidation auction back into the
+/// position, the liquidation contract already returned the cw20 collaterals
+/// to their custody contracts and attaches the native ones
+pub fn return_auction_collateral(
+    deps: DepsMut,
+    info: MessageInfo,
+    borrower: Addr,
+    sub_account: Option<String>,
+    collaterals: Vec<(String, String, Uint256)>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.liquidation_contract
+        && !read_all_whitelist_elems(deps.storage)?
+            .iter()
+            .any(|(_, elem)| elem.liquidation_contract.as_ref() == Some(&sender_raw))
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let mut cur_collaterals: Tokens =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut native_collaterals: Vec<(String, Uint256)> = vec![];
+    for (collateral_token, custody_contract, amount) in collaterals.iter() {
+        let collateral_token_raw = deps.api.addr_canonicalize(collateral_token)?;
+        let whitelist_elem: WhitelistElem =
+            read_whitelist_elem(deps.storage, &collateral_token_raw)?;
+        cur_collaterals.add(vec![(collateral_token_raw.clone(), *amount)]);
+        let total_locked = read_total_locked(deps.storage, &collateral_token_raw) + *amount;
+        store_total_locked(deps.storage, &collateral_token_raw, &total_locked)?;
+
+        if let TokenType::Native { denom } = &whitelist_elem.token_type {
+            match native_collaterals.iter_mut().find(|c| c.0 == *denom) {
+                Some(native_collateral) => native_collateral.1 += *amount,
+                None => native_collaterals.push((denom.to_string(), *amount)),
+            }
+            continue;
+        }
+
+        let custody_contract_raw = deps.api.addr_canonicalize(custody_contract)?;
+        if !whitelist_elem
+            .custody_contracts
+            .iter()
+            .any(|c| c.custody_contract == custody_contract_raw)
+        {
+            return Err(ContractError::Unauthorized {});
+        }
+        add_custody_locks(
+            deps.storage,
+            &borrower_raw,
+            &collateral_token_raw,
+            vec![(custody_contract_raw, *amount)],
+        )?;
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: custody_contract.to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                borrower: borrower.to_string(),
+                amount: *amount,
+            })?,
+        }));
+    }
+
+    assert_native_funds(&info.funds, &native_collaterals)?;
+
+    store_collaterals(
+        deps.storage,
+        &borrower_raw,
+        sub_account.as_deref(),
+        &cur_collaterals,
+    )?;
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "return_auction_collateral"),
+        attr("borrower", borrower),
+    ]))
+}
+
+pub fn fund_fairness_fund(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let amount: Uint256 = info
+        .funds
+        .iter()
+        .find(|c| c.denom == config.stable_denom)
+        .map(|c| Uint256::from(c.amount))
+        .unwrap_or_else(Uint256::zero);
+    if amount.is_zero() {
+        return Err(ContractError::FairnessFundNotProvided(config.stable_denom));
+    }
+
+    let mut fund: FairnessFund = read_fairness_fund(deps.storage)?;
+    fund.available += amount;
+    store_fairness_fund(deps.storage, &fund)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "fund_fairness_fund"),
+        attr("amount", amount),
+    ]))
+}
+
+pub fn report_price_correction(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    liquidation_id: u64,
+    compensation: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let record: LiquidationRecord = read_liquidation_record(deps.storage, liquidation_id)?
+        .ok_or(ContractError::LiquidationRecordNotFound(liquidation_id))?;
+    if env.block.height > record.block_height + config.dispute_window {
+        return Err(ContractError::DisputeWindowExpired(liquidation_id));
+    }
+
+    if read_liquidation_dispute(deps.storage, liquidation_id)?.is_some() {
+        return Err(ContractError::LiquidationAlreadyDisputed(liquidation_id));
+    }
+
+    // The borrower cannot be compensated for more than the seized
+    // collaterals were worth beyond the repaid loan
+    let (seized_value, _) = compute_collaterals_limit(
+        deps.as_ref(),
+        &record.seized_tokens,
+        Some(env.block.time.seconds()),
+        Some(Decimal256::one()),
+        config.borrow_price_timeframe,
+        None,
+        None,
+        &ValuationMode::Spot,
+    )?;
+    let loss = if seized_value > record.repaid {
+        seized_value - record.repaid
+    } else {
+        Uint256::zero()
+    };
+    if compensation > loss {
+        return Err(ContractError::CompensationExceedsLoss(loss.into()));
+    }
+
+    let mut fund: FairnessFund = read_fairness_fund(deps.storage)?;
+    if compensation > fund.available {
+        return Err(ContractError::InsufficientFairnessFund(
+            fund.available.into(),
+        ));
+    }
+
+    fund.available = fund.available - compensation;
+    fund.reserved += compensation;
+    store_fairness_fund(deps.storage, &fund)?;
+    store_liquidation_dispute(
+        deps.storage,
+        liquidation_id,
+        &LiquidationDispute {
+            borrower: record.borrower.clone(),
+            compensation,
+            claimed: false,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "report_price_correction"),
+        attr("liquidation_id", liquidation_id.to_string()),
+        attr("borrower", deps.api.addr_humanize(&record.borrower)?),
+        attr("compensation", compensation),
+    ]))
+}
+
+pub fn claim_compensation(
+    deps: DepsMut,
+    info: MessageInfo,
+    liquidation_id: u64,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut dispute: LiquidationDispute =
+        match read_liquidation_dispute(deps.storage, liquidation_id)? {
+            Some(dispute) if dispute.borrower == borrower_raw && !dispute.claimed => dispute,
+            _ => return Err(ContractError::NoCompensation(liquidation_id)),
+        };
+
+    dispute.claimed = true;
+    store_liquidation_dispute(deps.storage, liquidation_id, &dispute)?;
+
+    let mut fund: FairnessFund = read_fairness_fund(deps.storage)?;
+    fund.reserved = fund.reserved - dispute.compensation;
+    store_fairness_fund(deps.storage, &fund)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !dispute.compensation.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: dispute.compensation.into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "claim_compensation"),
+        attr("liquidation_id", liquidation_id.to_string()),
+        attr("borrower", info.sender),
+        attr("compensation", dispute.compensation),
+    ]))
+}
+
+/// Repays the shortfall of the borrower from the interest buffer,
+/// leaving the collaterals untouched
+fn backstop_repay(
+    deps: DepsMut,
+    config: &Config,
+    borrower: Addr,
+    sub_account: Option<String>,
+    shortfall: Uint256,
+    backstop_used: Uint256,
+) -> Result<Response, ContractError> {
+    store_backstop_used(deps.storage, &backstop_used)?;
+
+    let (res, repay_amount) = buffer_repay(deps, config, borrower.clone(), sub_account, shortfall)?;
+    Ok(res.add_attributes(vec![
+        attr("borrower", borrower),
+        attr("backstop_repay", repay_amount),
+    ]))
+}
+
+/// Sends `amount` of the interest buffer to the market
+/// to repay the loan of the borrower, returns the amount
+/// repaid after the tax of the transfer
+fn buffer_repay(
+    deps: DepsMut,
+    config: &Config,
+    borrower: Addr,
+    sub_account: Option<String>,
+    amount: Uint256,
+) -> StdResult<(Response, Uint256)> {
+    // The buffer shrinks outside of the epoch operations,
+    // so the accrued buffer of the next epoch is computed from the reduced amount
+    let mut epoch_state = read_epoch_state(deps.storage)?;
+    epoch_state.prev_interest_buffer = if epoch_state.prev_interest_buffer > amount {
+        epoch_state.prev_interest_buffer - amount
+    } else {
+        Uint256::zero()
+    };
+    store_epoch_state(deps.storage, &epoch_state)?;
+
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+    let prev_balance: Uint256 = query_balance(
+        deps.as_ref(),
+        market_contract.clone(),
+        config.stable_denom.to_string(),
+    )?;
+
+    // The market repays what it receives
+    let repay_coin = deduct_tax(
+        deps.as_ref(),
+        Coin {
+            denom: config.stable_denom.to_string(),
+            amount: amount.into(),
+        },
+    )?;
+    let repay_amount = Uint256::from(repay_coin.amount);
+
+    let res = Response::new()
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: market_contract.to_string(),
+            amount: vec![repay_coin],
+        }))
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market_contract.to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                borrower: borrower.to_string(),
+                prev_balance,
+                sub_account,
+            })?,
+        }));
+
+    Ok((res, repay_amount))
+}
+
+/// Repays the whole loan left on a position of a borrower without
+/// collaterals from the interest buffer. Only the owner and the risk admin
+/// draw on the buffer, and when the bad debt budget of the epoch or the
+/// buffer cannot cover the loan, the debt is left as is
+pub fn settle_bad_debt(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.owner_addr && config.risk_admin.as_ref() != Some(&sender_raw) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    if !read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref()).is_empty() {
+        return Err(ContractError::CollateralsNotSeized(borrower.to_string()));
+    }
+
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+    let loan_amount = query_borrower_info(
+        deps.as_ref(),
+        market,
+        borrower.clone(),
+        env.block.height,
+        sub_account.clone(),
+    )?
+    .loan_amount;
+    if loan_amount.is_zero() {
+        return Err(ContractError::NoBadDebt(borrower.to_string()));
+    }
+
+    let settled = read_bad_debt_settled(deps.storage)?;
+    let remaining_budget = if config.bad_debt_budget > settled {
+        config.bad_debt_budget - settled
+    } else {
+        Uint256::zero()
+    };
+    let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
+    let available = std::cmp::min(remaining_budget, interest_buffer);
+    if loan_amount > available {
+        return Ok(Response::new().add_attributes(vec![
+            attr("action", "settle_bad_debt"),
+            attr("borrower", borrower),
+            attr("shortfall", loan_amount - available),
+        ]));
+    }
+
+    store_bad_debt_settled(deps.storage, &(settled + loan_amount))?;
+    let (res, repay_amount) =
+        buffer_repay(deps, &config, borrower.clone(), sub_account, loan_amount)?;
+    Ok(res.add_attributes(vec![
+        attr("action", "settle_bad_debt"),
+        attr("borrower", borrower),
+        attr("settled_amount", repay_amount),
+    ]))
+}
+
+/// Extends the liquidation to the whole locked amount of a collateral when
+/// the remainder would be worth less than the dust threshold, as long as the
+/// extra seized value stays within the dust tolerance of the liquidation value
+fn sweep_dust_collaterals(
+    config: &Config,
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+    mut liquidation_amount: Tokens,
+) -> StdResult<Tokens> {
+    if config.dust_threshold.is_zero() {
+        return Ok(liquidation_amount);
+    }
+
+    let liquidation_value =
+        compute_liquidation_value(collaterals, collateral_prices, &liquidation_amount)?;
+    let max_extra_value = liquidation_value * config.dust_tolerance;
+    let mut extra_value = Uint256::zero();
+    for (token, amount) in liquidation_amount.iter_mut() {
+        let (locked_amount, price) = locked_collateral(collaterals, collateral_prices, token)?;
+        if locked_amount <= *amount {
+            continue;
+        }
+
+        let left_value = checked_mul_decimal(locked_amount - *amount, price)?;
+        if left_value < config.dust_threshold && extra_value + left_value <= max_extra_value {
+            extra_value += left_value;
+            *amount = locked_amount;
+        }
+    }
+
+    Ok(liquidation_amount)
+}
+
+/// Locked amount and price of a collateral being liquidated
+fn locked_collateral(
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+    collateral_token: &CanonicalAddr,
+) -> StdResult<(Uint256, Decimal256)> {
+    collaterals
+        .iter()
+        .zip(collateral_prices.iter())
+        .find(|(c, _)| c.0 == *collateral_token)
+        .map(|(c, price)| (c.1, *price))
+        .ok_or_else(|| StdError::generic_err("Liquidated collateral is not locked"))
+}
+
+fn compute_liquidation_value(
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+    liquidation_amount: &Tokens,
+) -> StdResult<Uint256> {
+    let mut liquidation_value = Uint256::zero();
+    for (collateral_token, amount) in liquidation_amount.iter() {
+        let (_, price) = locked_collateral(collaterals, collateral_prices, collateral_token)?;
+        liquidation_value += checked_mul_decimal(*amount, price)?;
+    }
+
+    Ok(liquidation_value)
+}
+
+/// Premium rate of each collateral, None when no collateral overrides
+/// the max premium rate or the liquidation contract. A collateral with its
+/// own liquidation contract defaults to the max premium rate of that contract
+fn collateral_premium_rates(
+    deps: Deps,
+    liquidation_contract: Addr,
+    collaterals: &Tokens,
+) -> StdResult<Option<Vec<Decimal256>>> {
+    let whitelist_elems = collaterals
+        .iter()
+        .map(|c| read_whitelist_elem(deps.storage, &c.0))
+        .collect::<StdResult<Vec<WhitelistElem>>>()?;
+    if whitelist_elems
+        .iter()
+        .all(|elem| elem.liquidation_premium.is_none() && elem.liquidation_contract.is_none())
+    {
+        return Ok(None);
+    }
+
+    let max_premium_rate = query_liquidation_config(deps, liquidation_contract)?.max_premium_rate;
+    Ok(Some(
+        whitelist_elems
+            .into_iter()
+            .map(
+                |elem| match (elem.liquidation_premium, elem.liquidation_contract) {
+                    (Some(liquidation_premium), _) => Ok(liquidation_premium),
+                    (None, Some(liquidation_contract)) => Ok(query_liquidation_config(
+                        deps,
+                        deps.api.addr_humanize(&liquidation_contract)?,
+                    )?
+                    .max_premium_rate),
+                    (None, None) => Ok(max_premium_rate),
+                },
+            )
+            .collect::<StdResult<Vec<Decimal256>>>()?,
+    ))
+}
+
+/// Groups the liquidation amount by the liquidation contract of
+/// each collateral, the global one unless the whitelist overrides it
+fn route_liquidation_amount(
+    deps: Deps,
+    liquidation_contract: &Addr,
+    liquidation_amount: &Tokens,
+) -> StdResult<Vec<(Addr, Tokens)>> {
+    let mut routes: Vec<(Addr, Tokens)> = vec![];
+    for collateral in liquidation_amount.iter() {
+        let contract = match read_whitelist_elem(deps.storage, &collateral.0)?.liquidation_contract
+        {
+            Some(contract) => deps.api.addr_humanize(&contract)?,
+            None => liquidation_contract.clone(),
+        };
+        match routes.iter_mut().find(|(c, _)| *c == contract) {
+            Some((_, collaterals)) => collaterals.push(collateral.clone()),
+            None => routes.push((contract, vec![collateral.clone()])),
+        }
+    }
+
+    Ok(routes)
+}
+
+/// Estimates the bids of a liquidation at the premium rate of each collateral:
+/// the liquidator pays the discounted collateral value, from which the bid
+/// fee is taken and the rest repays the loan.
+/// Returns the liquidation value, the repay amount and the bid fee
+fn compute_liquidation_proceeds(
+    deps: Deps,
+    liquidation_contract: Addr,
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+    liquidation_amount: &Tokens,
+) -> StdResult<(Uint256, Uint256, Uint256)> {
+    let mut liquidation_value = Uint256::zero();
+    let mut required_stable = Uint256::zero();
+    let mut bid_fee = Uint256::zero();
+    for (liquidation_contract, route_amount) in
+        route_liquidation_amount(deps, &liquidation_contract, liquidation_amount)?
+    {
+        let liquidation_config = query_liquidation_config(deps, liquidation_contract)?;
+
+        let mut route_stable = Uint256::zero();
+        for (collateral_token, amount) in route_amount.iter() {
+            let (_, price) = locked_collateral(collaterals, collateral_prices, collateral_token)?;
+            let premium_rate = read_whitelist_elem(deps.storage, collateral_token)?
+                .liquidation_premium
+                .unwrap_or(liquidation_config.max_premium_rate);
+
+            let value = checked_mul_decimal(*amount, price)?;
+            liquidation_value += value;
+            route_stable += value * (Decimal256::one() - premium_rate);
+        }
+        required_stable += route_stable;
+        bid_fee += route_stable * liquidation_config.bid_fee;
+    }
+
+    Ok((liquidation_value, required_stable - bid_fee, bid_fee))
+}
+
+pub fn query_collaterals(
+    deps: Deps,
+    borrower: Addr,
+    sub_account: Option<String>,
+    canonical: bool,
+) -> StdResult<CollateralsResponse> {
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let collaterals: Tokens = read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+
+    Ok(CollateralsResponse {
+        borrower: format_addr(deps, &borrower_raw, canonical)?,
+        sub_account,
+        collaterals: collaterals
+            .iter()
+            .map(|c| Ok((format_addr(deps, &c.0, canonical)?, c.1)))
+            .collect::<StdResult<Vec<(String, Uint256)>>>()?,
+    })
+}
+
+pub fn query_all_collaterals(
+    deps: Deps,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+    canonical: bool,
+) -> StdResult<AllCollateralsResponse> {
+    let start_after = if let Some(start_after) = start_after {
+        Some(deps.api.addr_canonicalize(start_after.as_str())?)
+    } else {
+        None
+    };
+
+    let limit = limit.unwrap_or(read_config(deps.storage)?.default_limit);
+    let all_collaterals: Vec<CollateralsResponse> =
+        read_all_collaterals(deps, start_after, Some(limit), canonical)?;
+
+    Ok(AllCollateralsResponse { all_collaterals })
+}
+
+/// Split the amount across the custody contracts proportionally to their weights,
+/// the last custody contract takes the rounding remainder
+pub(crate) fn split_custody_amount(
+    whitelist_elem: &WhitelistElem,
+    amount: Uint256,
+) -> Vec<(CanonicalAddr, Uint256)> {
+    let total_weight: Decimal256 = whitelist_elem
+        .custody_contracts
+        .iter()
+        .fold(Decimal256::zero(), |acc, c| acc + c.weight);
+
+    let last_index = whitelist_elem.custody_contracts.len().saturating_sub(1);
+    let mut left_amount = amount;
+    let mut amounts: Vec<(CanonicalAddr, Uint256)> = vec![];
+    for (index, elem) in whitelist_elem.custody_contracts.iter().enumerate() {
+        let custody_amount = if index == last_index {
+            left_amount
+        } else {
+            amount * (elem.weight / total_weight)
+        };
+
+        left_amount = left_amount - custody_amount;
+        if !custody_amount.is_zero() {
+            amounts.push((elem.custody_contract.clone(), custody_amount));
+        }
+    }
+
+    amounts
+}
+
+/// Lock the amount across the custody contracts by their weights,
+/// recording what each custody contract holds for the borrower
+fn lock_custody_amount(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+    whitelist_elem: &WhitelistElem,
+    amount: Uint256,
+) -> StdResult<Tokens> {
+    let amounts = split_custody_amount(whitelist_elem, amount);
+    add_custody_locks(storage, borrower, collateral_token, amounts.clone())?;
+    Ok(amounts)
+}
+
+fn add_custody_locks(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+    amounts: Tokens,
+) -> StdResult<()> {
+    let mut custody_locks: Tokens = read_custody_locks(storage, borrower, collateral_token);
+    add_custody_amounts(&mut custody_locks, amounts);
+    store_custody_locks(storage, borrower, collateral_token, &custody_locks)
+}
+
+/// Add the amounts keeping the order of the custody contracts
+pub(crate) fn add_custody_amounts(custody_amounts: &mut Tokens, amounts: Tokens) {
+    for (custody_contract, amount) in amounts {
+        match custody_amounts.iter_mut().find(|c| c.0 == custody_contract) {
+            Some(custody_amount) => custody_amount.1 += amount,
+            None => custody_amounts.push((custody_contract, amount)),
+        }
+    }
+}
+
+/// Take the amount out of what the custody contracts hold for the borrower
+/// in proportion to their holdings, whatever their current weights, the last
+/// custody contracts take the rounding remainder. Collaterals locked before
+/// the custody amounts were recorded are taken by the current weights
+pub(crate) fn unlock_custody_amount(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+    whitelist_elem: &WhitelistElem,
+    amount: Uint256,
+) -> StdResult<Tokens> {
+    let mut custody_locks: Tokens = read_custody_locks(storage, borrower, collateral_token);
+    let total_locked = custody_locks
+        .iter()
+        .fold(Uint256::zero(), |acc, c| acc + c.1);
+    let unlock_amount = std::cmp::min(amount, total_locked);
+
+    let mut amounts: Vec<Uint256> = custody_locks
+        .iter()
+        .map(|(_, locked_amount)| {
+            std::cmp::min(
+                *locked_amount * Decimal256::from_ratio(unlock_amount, total_locked),
+                *locked_amount,
+            )
+        })
+        .collect();
+    let mut left_amount = unlock_amount - amounts.iter().fold(Uint256::zero(), |acc, a| acc + *a);
+    for (custody_amount, (_, locked_amount)) in amounts.iter_mut().zip(custody_locks.iter()).rev() {
+        let extra_amount = std::cmp::min(left_amount, *locked_amount - *custody_amount);
+        *custody_amount += extra_amount;
+        left_amount = left_amount - extra_amount;
+    }
+
+    let mut custody_amounts: Tokens = vec![];
+    for (custody_amount, (custody_contract, locked_amount)) in
+        amounts.into_iter().zip(custody_locks.iter_mut())
+    {
+        if !custody_amount.is_zero() {
+            *locked_amount = *locked_amount - custody_amount;
+            custody_amounts.push((custody_contract.clone(), custody_amount));
+        }
+    }
+    custody_locks.retain(|c| !c.1.is_zero());
+    store_custody_locks(storage, borrower, collateral_token, &custody_locks)?;
+
+    if amount > unlock_amount {
+        add_custody_amounts(
+            &mut custody_amounts,
+            split_custody_amount(whitelist_elem, amount - unlock_amount),
+        );
+    }
+
+    Ok(custody_amounts)
+}
+
+/// Attached funds locking native collaterals must match
+/// the locked amount of each denom, without other funds
+fn assert_native_funds(
+    funds: &[Coin],
+    native_collaterals: &[(String, Uint256)],
+) -> Result<(), ContractError> {
+    for coin in funds.iter() {
+        if !coin.amount.is_zero() && !native_collaterals.iter().any(|c| c.0 == coin.denom) {
+            return Err(ContractError::NativeCollateralFundsMismatch(
+                coin.denom.to_string(),
+            ));
+        }
+    }
+
+    for (denom, amount) in native_collaterals.iter() {
+        let attached = funds
+            .iter()
+            .filter(|c| c.denom == *denom)
+            .fold(Uint256::zero(), |acc, c| acc + Uint256::from(c.amount));
+        if attached != *amount {
+            return Err(ContractError::NativeCollateralFundsMismatch(
+                denom.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Transfer of a native collateral held by the overseer
+fn native_collateral_msg(
+    deps: Deps,
+    denom: &str,
+    recipient: &Addr,
+    amount: Uint256,
+) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Bank(BankMsg::Send {
+        to_address: recipient.to_string(),
+        amount: vec![deduct_tax(
+            deps,
+            Coin {
+                denom: denom.to_string(),
+                amount: amount.into(),
+            },
+        )?],
+    }))
+}
+
+/// Sends a seized native collateral to the liquidation contract
+/// with the bid message, as custody contracts do with CW20 collaterals
+/// Both the liquidation contract and the liquidation queue take the
+/// native collateral attached to an ExecuteBid message
+fn native_liquidation_msg(
+    deps: Deps,
+    liquidation_contract: &Addr,
+    denom: &str,
+    amount: Uint256,
+    msg: &LiquidationExecuteMsg,
+) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: liquidation_contract.to_string(),
+        funds: vec![deduct_tax(
+            deps,
+            Coin {
+                denom: denom.to_string(),
+                amount: amount.into(),
+            },
+        )?],
+        msg: to_binary(msg)?,
+    }))
+}
+
+// Positions locked before the counter existed are not included in it
+/// Custody message transferring collaterals of a position, replied on error
+/// when failed transfers are handled, e.g. of a paused collateral token
+fn custody_transfer_msg(
+    config: &Config,
+    transfers: &mut Vec<(CanonicalAddr, Uint256, CanonicalAddr)>,
+    reply_id: u64,
+    transfer: (CanonicalAddr, Uint256, CanonicalAddr),
+    msg: CosmosMsg,
+) -> SubMsg {
+    if !config.skip_paused_collaterals {
+        return SubMsg::new(msg);
+    }
+
+    transfers.push(transfer);
+    SubMsg::reply_on_error(msg, reply_id + (transfers.len() - 1) as u64)
+}
+
+fn store_custody_transfers(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    transfers: Vec<(CanonicalAddr, Uint256, CanonicalAddr)>,
+) -> StdResult<()> {
+    if transfers.is_empty() {
+        return Ok(());
+    }
+
+    let (transfers, custodies) = transfers
+        .into_iter()
+        .map(|(collateral_token, amount, custody)| ((collateral_token, amount), custody))
+        .unzip();
+    store_collateral_transfers(
+        storage,
+        &CollateralTransfers {
+            borrower: borrower.clone(),
+            sub_account: sub_account.map(|s| s.to_string()),
+            transfers,
+            custodies,
+        },
+    )
+}
+
+fn read_custody_transfer(
+    storage: &dyn Storage,
+    reply_id: u64,
+    index: u64,
+) -> Result<(CollateralTransfers, CanonicalAddr, Uint256), ContractError> {
+    let transfers: CollateralTransfers = match read_collateral_transfers(storage)? {
+        Some(transfers) => transfers,
+        None => return Err(ContractError::InvalidReplyId(reply_id)),
+    };
+    match transfers.transfers.get(index as usize).cloned() {
+        Some((collateral_token, amount)) => Ok((transfers, collateral_token, amount)),
+        None => Err(ContractError::InvalidReplyId(reply_id)),
+    }
+}
+
+/// An unlocked collateral the custody contract failed to release
+/// reverts the unlock
+pub fn unlock_collateral_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let (_, collateral_token, _) =
+        read_custody_transfer(deps.storage, msg.id, msg.id - UNLOCK_COLLATERAL_REPLY_ID)?;
+
+    Err(ContractError::CollateralTransferPaused(
+        deps.api.addr_humanize(&collateral_token)?.to_string(),
+    ))
+}
+
+/// A seized collateral the custody contract failed to transfer is skipped,
+/// it is returned to the position while the other collaterals are liquidated
+pub fn liquidate_collateral_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let (transfers, collateral_token, amount) =
+        read_custody_transfer(deps.storage, msg.id, msg.id - LIQUIDATE_COLLATERAL_REPLY_ID)?;
+    let error = match msg.result {
+        ContractResult::Ok(_) => return Ok(Response::default()),
+        ContractResult::Err(err) => err,
+    };
+
+    let mut collaterals: Tokens = read_collaterals(
+        deps.storage,
+        &transfers.borrower,
+        transfers.sub_account.as_deref(),
+    );
+    collaterals.add(vec![(collateral_token.clone(), amount)]);
+    store_collaterals(
+        deps.storage,
+        &transfers.borrower,
+        transfers.sub_account.as_deref(),
+        &collaterals,
+    )?;
+    let total_locked = read_total_locked(deps.storage, &collateral_token) + amount;
+    store_total_locked(deps.storage, &collateral_token, &total_locked)?;
+    if let Some(custody) = transfers
+        .custodies
+        .get((msg.id - LIQUIDATE_COLLATERAL_REPLY_ID) as usize)
+    {
+        add_custody_locks(
+            deps.storage,
+            &transfers.borrower,
+            &collateral_token,
+            vec![(custody.clone(), amount)],
+        )?;
+    }
+    if let Some(mut record) = read_pending_liquidation_record(deps.storage)? {
+        record
+            .seized_tokens
+            .sub(vec![(collateral_token.clone(), amount)])?;
+        record.seized_tokens.retain(|c| !c.1.is_zero());
+        store_pending_liquidation_record(deps.storage, &record)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "skip_paused_collateral"),
+        attr(
+            "borrower",
+            deps.api.addr_humanize(&transfers.borrower)?.to_string(),
+        ),
+        attr(
+            "skipped_collateral",
+            format!("{}{}", amount, deps.api.addr_humanize(&collateral_token)?),
+        ),
+        attr("error", error),
+    ]))
+}
+
+/// Records the liquidation with the amount the market repaid,
+/// as reported by its repay_stable event
+pub fn liquidation_record_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let mut record = match read_pending_liquidation_record(deps.storage)? {
+        Some(record) => record,
+        None => return Err(ContractError::InvalidReplyId(msg.id)),
+    };
+    remove_pending_liquidation_record(deps.storage);
+
+    let events = match msg.result {
+        ContractResult::Ok(res) => res.events,
+        ContractResult::Err(err) => return Err(StdError::generic_err(err).into()),
+    };
+    let repaid = events
+        .iter()
+        .filter(|event| event.ty == "wasm")
+        .find(|event| {
+            event
+                .attributes
+                .iter()
+                .any(|a| a.key == "action" && a.value == "repay_stable")
+        })
+        .and_then(|event| event.attributes.iter().find(|a| a.key == "repay_amount"))
+        .map(|a| Uint256::from_str(&a.value))
+        .transpose()?
+        .unwrap_or_else(Uint256::zero);
+
+    record.repaid = repaid;
+    let id = store_liquidation_record(deps.storage, &record)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "record_liquidation"),
+        attr("liquidation_id", id.to_string()),
+        attr("repaid", repaid),
+    ]))
+}
+
+/// Released amounts come out of locked positions, so a counter below
+/// them is an accounting bug and fails rather than floors at zero
+fn decrease_total_locked(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    amount: Uint256,
+) -> Result<(), ContractError> {
+    let total_locked = read_total_locked(storage, collateral_token);
+    if total_locked < amount {
+        return Err(ContractError::TotalLockedUnderflow(
+            total_locked,
+            collateral_token.to_string(),
+            amount,
+        ));
+    }
+
+    store_total_locked(storage, collateral_token, &(total_locked - amount))?;
+    Ok(())
+}
+
+/// Oracle prices are quoted per 6 decimal unit of the collateral in
+/// 6 decimal stablecoins, scale them to the configured stable decimals
+/// and to the collateral decimals. Collateral amounts of any decimals
+/// are then valued as if normalized to 6 decimals
+pub(crate) fn normalize_price(
+    price: Decimal256,
+    stable_decimals: u8,
+    collateral_decimals: u8,
+) -> Decimal256 {
+    if stable_decimals >= collateral_decimals {
+        price
+            * Decimal256::from_uint256(Uint256::from(
+                10u128.pow((stable_decimals - collateral_decimals) as u32),
+            ))
+    } else {
+        price
+            / Decimal256::from_uint256(Uint256::from(
+                10u128.pow((collateral_decimals - stable_decimals) as u32),
+            ))
+    }
+}
+
+// Extreme prices or amounts must fail the computation instead of aborting it
+fn checked_mul_decimal(amount: Uint256, rate: Decimal256) -> StdResult<Uint256> {
+    let (value, overflow) = amount.0.overflowing_mul(rate.0);
+    if overflow {
+        return Err(StdError::overflow(OverflowError::new(
+            OverflowOperation::Mul,
+            amount.to_string(),
+            rate.to_string(),
+        )));
+    }
+
+    Ok(Uint256::from(value / Decimal256::DECIMAL_FRACTIONAL))
+}
+
+/// Stable balance of the overseer net of the fairness fund, which is held
+/// in the same balance as the interest buffer
+pub fn query_interest_buffer(deps: Deps, env: &Env, config: &Config) -> StdResult<Uint256> {
+    let balance = query_balance(
+        deps,
+        env.contract.address.clone(),
+        config.stable_denom.to_string(),
+    )?;
+    let fairness_fund = read_fairness_fund(deps.storage)?.total();
+    if fairness_fund > balance {
+        return Err(StdError::overflow(OverflowError::new(
+            OverflowOperation::Sub,
+            balance,
+            fairness_fund,
+        )));
+    }
+
+    Ok(balance - fairness_fund)
+}
+
+fn checked_add(a: Uint256, b: Uint256) -> StdResult<Uint256> {
+    let (value, overflow) = a.0.overflowing_add(b.0);
+    if overflow {
+        return Err(StdError::overflow(OverflowError::new(
+            OverflowOperation::Add,
+            a,
+            b,
+        )));
+    }
+
+    Ok(Uint256::from(value))
+}
+
+/// Stable price of the collateral, normalized to the stable decimals,
+/// with its confidence spread when the oracle reports one
+pub(crate) fn query_collateral_price(
+    deps: Deps,
+    config: &Config,
+    oracle_contract: Addr,
+    collateral_token: &CanonicalAddr,
+    elem: &WhitelistElem,
+    block_time: Option<u64>,
+    price_timeframe: u64,
+) -> StdResult<(Decimal256, Option<Decimal256>)> {
+    query_collateral_price_by_mode(
+        deps,
+        config,
+        oracle_contract,
+        collateral_token,
+        elem,
+        block_time,
+        price_timeframe,
+        &PriceMode::Spot,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn query_collateral_price_by_mode(
+    deps: Deps,
+    config: &Config,
+    oracle_contract: Addr,
+    collateral_token: &CanonicalAddr,
+    elem: &WhitelistElem,
+    block_time: Option<u64>,
+    price_timeframe: u64,
+    price_mode: &PriceMode,
+) -> StdResult<(Decimal256, Option<Decimal256>)> {
+    let time_constraints = block_time.map(|block_time| TimeConstraints {
+        block_time,
+        valid_timeframe: price_timeframe,
+    });
+
+    let price: PriceResponse = query_oracle_price(
+        deps,
+        config,
+        oracle_contract.clone(),
+        (deps.api.addr_humanize(collateral_token)?).to_string(),
+        elem.quote_denom
+            .clone()
+            .unwrap_or_else(|| config.stable_denom.to_string()),
+        price_mode,
+        time_constraints.clone(),
+    )?;
+
+    // Cross the quote asset price to get the stable value
+    let price = if let Some(quote_denom) = elem.quote_denom.clone() {
+        let quote_price: PriceResponse = query_oracle_price(
+            deps,
+            config,
+            oracle_contract,
+            quote_denom,
+            config.stable_denom.to_string(),
+            price_mode,
+            time_constraints,
+        )?;
+
+        // Relative spreads add up when crossing prices
+        let confidence_spread = match (price.confidence_spread, quote_price.confidence_spread) {
+            (Some(spread), Some(quote_spread)) => Some(spread + quote_spread),
+            (spread, quote_spread) => spread.or(quote_spread),
+        };
+
+        (price.rate * quote_price.rate, confidence_spread)
+    } else {
+        (price.rate, price.confidence_spread)
+    };
+
+    // The oracle prices the underlying asset of interest-bearing collaterals,
+    // so the accrued interest raises the value of every collateral token
+    let price = if let Some(exchange_rate_source) = &elem.exchange_rate_source {
+        let exchange_rate =
+            query_exchange_rate(deps, deps.api.addr_humanize(exchange_rate_source)?)?;
+        (price.0 * exchange_rate, price.1)
+    } else {
+        price
+    };
+
+    Ok((
+        normalize_price(price.0, config.stable_decimals, elem.decimals),
+        price.1,
+    ))
+}
+
+/// Price of the collateral under the valuation mode, the lower
+/// of both modes keeps the spread of the price it picks
+#[allow(clippy::too_many_arguments)]
+fn query_collateral_valuation(
+    deps: Deps,
+    config: &Config,
+    oracle_contract: Addr,
+    collateral_token: &CanonicalAddr,
+    elem: &WhitelistElem,
+    block_time: Option<u64>,
+    price_timeframe: u64,
+    valuation_mode: &ValuationMode,
+) -> StdResult<(Decimal256, Option<Decimal256>)> {
+    let price_mode = match valuation_mode {
+        ValuationMode::Spot => PriceMode::Spot,
+        ValuationMode::Twap { window } | ValuationMode::LowerOfBoth { window } => {
+            PriceMode::Twap { window: *window }
+        }
+    };
+    let price = query_collateral_price_by_mode(
+        deps,
+        config,
+        oracle_contract.clone(),
+        collateral_token,
+        elem,
+        block_time,
+        price_timeframe,
+        &price_mode,
+    )?;
+
+    if let ValuationMode::LowerOfBoth { .. } = valuation_mode {
+        let spot_price = query_collateral_price(
+            deps,
+            config,
+            oracle_contract,
+            collateral_token,
+            elem,
+            block_time,
+            price_timeframe,
+        )?;
+        if spot_price.0 < price.0 {
+            return Ok(spot_price);
+        }
+    }
+
+    Ok(price)
+}
+
+/// Queries the fallback oracle when the price of the primary one
+/// is unusable, the primary error is kept if both fail
+fn query_oracle_price(
+    deps: Deps,
+    config: &Config,
+    oracle_contract: Addr,
+    base: String,
+    quote: String,
+    price_mode: &PriceMode,
+    time_constraints: Option<TimeConstraints>,
+) -> StdResult<PriceResponse> {
+    let price = query_price_by_mode(
+        deps,
+        oracle_contract,
+        base.clone(),
+        quote.clone(),
+        price_mode,
+        time_constraints.clone(),
+    );
+
+    match (price, &config.fallback_oracle_contract) {
+        (Err(err), Some(fallback_oracle_contract)) => query_price_by_mode(
+            deps,
+            deps.api.addr_humanize(fallback_oracle_contract)?,
+            base,
+            quote,
+            price_mode,
+            time_constraints,
+        )
+        .map_err(|_| err),
+        (price, _) => price,
+    }
+}
+
+#[allow(clippy::ptr_arg)]
+pub(crate) fn compute_borrow_limit(
+    deps: Deps,
+    collaterals: &Tokens,
+    block_time: Option<u64>,
+) -> StdResult<(Uint256, Vec<Decimal256>)> {
+    let config: Config = read_config(deps.storage)?;
+    compute_collaterals_limit(
+        deps,
+        collaterals,
+        block_time,
+        None,
+        config.borrow_price_timeframe,
+        config.max_confidence_spread,
+        config.max_collateral_value_per_borrower,
+        &config.valuation_mode,
+    )
+}
+
+/// Borrow limit with prices as stale as the liquidation price timeframe allows
+#[allow(clippy::ptr_arg)]
+pub(crate) fn compute_liquidation_borrow_limit(
+    deps: Deps,
+    collaterals: &Tokens,
+    block_time: Option<u64>,
+) -> StdResult<(Uint256, Vec<Decimal256>)> {
+    let config: Config = read_config(deps.storage)?;
+    compute_collaterals_limit(
+        deps,
+        collaterals,
+        block_time,
+        None,
+        config.liquidation_price_timeframe,
+        None,
+        config.max_collateral_value_per_borrower,
+        &ValuationMode::Spot,
+    )
+}
+
+/// Borrow limit of the collaterals, valued at `ltv` instead of
+/// the max ltv of each collateral when given. Collaterals priced with
+/// a confidence spread above `max_confidence_spread` count for nothing,
+/// and the value of each collateral is clamped at `max_collateral_value`
+#[allow(clippy::ptr_arg, clippy::too_many_arguments)]
+fn compute_collaterals_limit(
+    deps: Deps,
+    collaterals: &Tokens,
+    block_time: Option<u64>,
+    ltv: Option<Decimal256>,
+    price_timeframe: u64,
+    max_confidence_spread: Option<Decimal256>,
+    max_collateral_value: Option<Uint256>,
+    valuation_mode: &ValuationMode,
+) -> StdResult<(Uint256, Vec<Decimal256>)> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+
+    let mut borrow_limit: Uint256 = Uint256::zero();
+    let mut collateral_prices: Vec<Decimal256> = vec![];
+    for collateral in collaterals.iter() {
+        let collateral_token = collateral.0.clone();
+        let collateral_amount = collateral.1;
+
+        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        let (price, confidence_spread) = query_collateral_valuation(
+            deps,
+            &config,
+            oracle_contract.clone(),
+            &collateral_token,
+            &elem,
+            block_time,
+            price_timeframe,
+            valuation_mode,
+        )?;
+        collateral_prices.push(price);
+
+        let uncertain = match (max_confidence_spread, confidence_spread) {
+            (Some(max_spread), Some(spread)) => spread > max_spread,
+            _ => false,
+        };
+        if uncertain {
+            continue;
+        }
+
+        let mut collateral_value = checked_mul_decimal(collateral_amount, price)?;
+        if let Some(max_collateral_value) = max_collateral_value {
+            collateral_value = std::cmp::min(collateral_value, max_collateral_value);
+        }
+
+        borrow_limit = checked_add(
+            borrow_limit,
+            checked_mul_decimal(collateral_value, ltv.unwrap_or(elem.max_ltv))?,
+        )?;
+    }
+
+    // returns borrow_limit with collaterals value in stable denom
+    Ok((borrow_limit, collateral_prices))
+}
+
+/// Collaterals left after taking out `excluded`, which may exceed
+/// the locked amounts after an unlock
+#[allow(clippy::ptr_arg)]
+fn exclude_collaterals(collaterals: Tokens, excluded: &Tokens) -> Tokens {
+    collaterals
+        .into_iter()
+        .filter_map(|(collateral_token, amount)| {
+            let excluded_amount = excluded
+                .iter()
+                .find(|c| c.0 == collateral_token)
+                .map(|c| c.1)
+                .unwrap_or_else(Uint256::zero);
+            if amount > excluded_amount {
+                Some((collateral_token, amount - excluded_amount))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Whether the whitelist borrow window of the collateral contains the height
+fn in_borrow_window(elem: &WhitelistElem, block_height: u64) -> bool {
+    elem.borrow_enabled_from
+        .is_none_or(|enabled_from| block_height >= enabled_from)
+        && elem
+            .borrow_disabled_from
+            .is_none_or(|disabled_from| block_height < disabled_from)
+}
+
+/// Collaterals within their borrow window at the height
+fn borrowable_collaterals(deps: Deps, collaterals: Tokens, block_height: u64) -> StdResult<Tokens> {
+    let mut borrowable: Tokens = vec![];
+    for collateral in collaterals {
+        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        if in_borrow_window(&elem, block_height) {
+            borrowable.push(collateral);
+        }
+    }
+
+    Ok(borrowable)
+}
+
+/// Collaterals whose token still has a nonzero total supply
+fn eligible_collaterals(deps: Deps, collaterals: Tokens) -> StdResult<Tokens> {
+    let mut eligible: Tokens = vec![];
+    for collateral in collaterals {
+        // The supply of native collaterals cannot be queried
+        let native = read_whitelist_elem(deps.storage, &collateral.0)
+            .map(|elem| elem.token_type != TokenType::Cw20)
+            .unwrap_or(false);
+        if native || !query_supply(deps, deps.api.addr_humanize(&collateral.0)?)?.is_zero() {
+            eligible.push(collateral);
+        }
+    }
+
+    Ok(eligible)
+}
+
+/// Store the prices used in a borrow limit computation and log the collaterals
+/// whose price moved more than the threshold since the previous record
+#[allow(clippy::ptr_arg)]
+pub(crate) fn record_collateral_prices(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    threshold: Decimal256,
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+) -> StdResult<Vec<Attribute>> {
+    let mut attributes: Vec<Attribute> = vec![];
+    for (collateral, price) in collaterals.iter().zip(collateral_prices.iter()) {
+        if let Some(last_price) = read_last_price(storage, &collateral.0) {
+            let delta = if *price > last_price {
+                *price - last_price
+            } else {
+                last_price - *price
+            };
+
+            if !last_price.is_zero() && delta / last_price > threshold {
+                attributes.push(attr(
+                    "price_deviation",
+                    format!(
+                        "{}:{}->{}",
+                        api.addr_humanize(&collateral.0)?,
+                        last_price,
+                        price
+                    ),
+                ));
+            }
+        }
+
+        store_last_price(storage, &collateral.0, price)?;
+    }
+
+    Ok(attributes)
+}
+
+/// Store the prices observed by a liquidation or unlock and start the
+/// cooldown of the collaterals whose price dropped by more than
+/// `max_block_price_drop` since their previous observation
+#[allow(clippy::ptr_arg)]
+pub(crate) fn observe_price_drops(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    config: &Config,
+    block_height: u64,
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+) -> StdResult<Vec<Attribute>> {
+    let max_block_price_drop = match config.max_block_price_drop {
+        Some(v) => v,
+        None => return Ok(vec![]),
+    };
+
+    let mut attributes: Vec<Attribute> = vec![];
+    for (collateral, price) in collaterals.iter().zip(collateral_prices.iter()) {
+        if let Some(observation) = read_price_observation(storage, &collateral.0)? {
+            if *price < observation.price {
+                let drop = (observation.price - *price) / observation.price;
+                if drop > max_block_price_drop {
+                    store_price_drop(storage, &collateral.0, &PriceDrop { drop, block_height })?;
+                    attributes.push(attr(
+                        "price_drop",
+                        format!("{}:{}", api.addr_humanize(&collateral.0)?, drop),
+                    ));
+                }
+            }
+        }
+
+        store_price_observation(
+            storage,
+            &collateral.0,
+            &PriceObservation {
+                price: *price,
+                block_height,
+            },
+        )?;
+    }
+
+    Ok(attributes)
+}
+
+fn in_price_drop_cooldown(
+    storage: &dyn Storage,
+    config: &Config,
+    block_height: u64,
+    collateral_token: &CanonicalAddr,
+) -> StdResult<bool> {
+    Ok(read_price_drop(storage, collateral_token)?
+        .map(|price_drop| block_height < price_drop.block_height + config.price_drop_cooldown)
+        .unwrap_or(false))
+}
+
+/// First collateral whose price drop cooldown is still running
+#[allow(clippy::ptr_arg)]
+fn cooling_down_collateral(
+    storage: &dyn Storage,
+    config: &Config,
+    block_height: u64,
+    collaterals: &Tokens,
+) -> StdResult<Option<CanonicalAddr>> {
+    for collateral in collaterals.iter() {
+        if in_price_drop_cooldown(storage, config, block_height, &collateral.0)? {
+            return Ok(Some(collateral.0.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn query_borrow_limit(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    block_time: Option<u64>,
+    sub_account: Option<String>,
+    price_mode: Option<PriceMode>,
+    quote_denom: Option<String>,
+) -> StdResult<BorrowLimitResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let position_collaterals =
+        read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref());
+    let mut collaterals = position_collaterals.clone();
+
+    // Collaterals locked in this block back borrows from the next one,
+    // so a borrow cannot follow a lock within a manipulated block
+    if config.same_block_collateral_delay {
+        if let Some(recent_lock) =
+            read_recent_lock(deps.storage, &borrower_raw, sub_account.as_deref())?
+        {
+            if recent_lock.block_height == env.block.height {
+                collaterals = exclude_collaterals(collaterals, &recent_lock.collaterals);
+            }
+        }
+    }
+
+    // New borrows cannot be backed by collaterals without supply,
+    // which are still counted when unlocking or liquidating
+    let collaterals = eligible_collaterals(deps, collaterals)?;
+
+    // Nor by collaterals outside their borrow window
+    let collaterals = borrowable_collaterals(deps, collaterals, env.block.height)?;
+
+    // Nor by collaterals in their price drop cooldown
+    let mut cooling_down: Tokens = vec![];
+    for collateral in collaterals.iter() {
+        if in_price_drop_cooldown(deps.storage, &config, env.block.height, &collateral.0)? {
+            cooling_down.push(collateral.clone());
+        }
+    }
+    let collaterals = exclude_collaterals(collaterals, &cooling_down);
+
+    // Compute borrow limit with collaterals, valued at
+    // the valuation mode the executing paths use by default
+    let valuation_mode = match &price_mode {
+        Some(PriceMode::Spot) => ValuationMode::Spot,
+        Some(PriceMode::Twap { window }) => ValuationMode::Twap { window: *window },
+        None => config.valuation_mode.clone(),
+    };
+    let (borrow_limit, _) = compute_collaterals_limit(
+        deps,
+        &collaterals,
+        block_time,
+        None,
+        config.borrow_price_timeframe,
+        config.max_confidence_spread,
+        config.max_collateral_value_per_borrower,
+        &valuation_mode,
+    )?;
+
+    let (liquidation_limit, liquidation_prices) =
+        compute_liquidation_borrow_limit(deps, &position_collaterals, block_time)?;
+    let borrow_rate_premium =
+        compute_borrow_rate_premium(deps, &position_collaterals, &liquidation_prices)?;
+    let loan_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+
+    // The health factor is a ratio, only the limit is converted
+    let borrow_limit = match quote_denom {
+        Some(quote_denom) if quote_denom != config.stable_denom => {
+            let quote_price: PriceResponse = query_oracle_price(
+                deps,
+                &config,
+                deps.api.addr_humanize(&config.oracle_contract)?,
+                quote_denom.clone(),
+                config.stable_denom.to_string(),
+                &price_mode.unwrap_or(PriceMode::Spot),
+                block_time.map(|block_time| TimeConstraints {
+                    block_time,
+                    valid_timeframe: config.borrow_price_timeframe,
+                }),
+            )
+            .map_err(|_| {
+                StdError::generic_err(format!(
+                    "No oracle price from {} to the stable denom",
+                    quote_denom
+                ))
+            })?;
+
+            Uint256::one() * (Decimal256::from_uint256(borrow_limit) / quote_price.rate)
+        }
+        _ => borrow_limit,
+    };
+
+    Ok(BorrowLimitResponse {
+        borrower: borrower.to_string(),
+        borrow_limit,
+        health_factor: health_factor(liquidation_limit, loan_amount),
+        borrow_rate_premium,
+    })
+}
+
+/// Borrow rate premium of each collateral weighted by its value
+fn compute_borrow_rate_premium(
+    deps: Deps,
+    collaterals: &Tokens,
+    collateral_prices: &[Decimal256],
+) -> StdResult<Decimal256> {
+    let mut total_value = Decimal256::zero();
+    let mut weighted_premium = Decimal256::zero();
+    for (collateral, price) in collaterals.iter().zip(collateral_prices.iter()) {
+        let value = Decimal256::from_uint256(checked_mul_decimal(collateral.1, *price)?);
+        total_value += value;
+        if let Some(premium) = read_whitelist_elem(deps.storage, &collateral.0)?.borrow_rate_premium
+        {
+            weighted_premium += value * premium;
+        }
+    }
+
+    if total_value.is_zero() {
+        return Ok(Decimal256::zero());
+    }
+
+    Ok(weighted_premium / total_value)
+}
+
+/// Borrow limit used by liquidations over the loan amount, the position
+/// is liquidatable below one. Without a loan it is `Decimal256::MAX`
+pub(crate) fn health_factor(liquidation_limit: Uint256, loan_amount: Uint256) -> Decimal256 {
+    if loan_amount.is_zero() {
+        Decimal256::MAX
+    } else {
+        Decimal256::from_ratio(liquidation_limit, loan_amount)
+    }
+}
+
+/// Lower the LTV to `new_ltv` for the adjustment, when it reduces it
+fn reduce_ltv(
+    adjustments: &mut Vec<LtvAdjustmentElem>,
+    ltv: &mut Decimal256,
+    adjustment: &str,
+    new_ltv: Decimal256,
+) {
+    if new_ltv < *ltv {
+        adjustments.push(LtvAdjustmentElem {
+            adjustment: adjustment.to_string(),
+            ltv_reduction: *ltv - new_ltv,
+        });
+        *ltv = new_ltv;
+    }
+}
+
+/// Borrow limit share of the locked collateral value, following
+/// the adjustments of `query_borrow_limit` in the same order
+pub fn query_effective_ltv(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    collateral_token: Addr,
+    sub_account: Option<String>,
+) -> StdResult<EffectiveLtvResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let borrower_raw = deps.api.addr_canonicalize(borrower.as_str())?;
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral_token_raw)?;
+    let locked_amount = read_collaterals(deps.storage, &borrower_raw, sub_account.as_deref())
+        .iter()
+        .find(|c| c.0 == collateral_token_raw)
+        .map(|c| c.1)
+        .unwrap_or_else(Uint256::zero);
+
+    let mut adjustments: Vec<LtvAdjustmentElem> = vec![];
+    let mut ltv = elem.max_ltv;
+
+    let mut counted_amount = locked_amount;
+    if config.same_block_collateral_delay {
+        if let Some(recent_lock) =
+            read_recent_lock(deps.storage, &borrower_raw, sub_account.as_deref())?
+        {
+            if recent_lock.block_height == env.block.height && !locked_amount.is_zero() {
+                counted_amount = exclude_collaterals(
+                    vec![(collateral_token_raw.clone(), locked_amount)],
+                    &recent_lock.collaterals,
+                )
+                .first()
+                .map(|c| c.1)
+                .unwrap_or_else(Uint256::zero);
+                reduce_ltv(
+                    &mut adjustments,
+                    &mut ltv,
+                    "same_block_lock",
+                    elem.max_ltv * Decimal256::from_ratio(counted_amount, locked_amount),
+                );
+            }
+        }
+    }
+
+    let collaterals: Tokens = vec![(collateral_token_raw.clone(), counted_amount)];
+    if eligible_collaterals(deps, collaterals)?.is_empty() {
+        reduce_ltv(
+            &mut adjustments,
+            &mut ltv,
+            "zero_supply",
+            Decimal256::zero(),
+        );
+    }
+    if !in_borrow_window(&elem, env.block.height) {
+        reduce_ltv(
+            &mut adjustments,
+            &mut ltv,
+            "borrow_window",
+            Decimal256::zero(),
+        );
+    }
+    if in_price_drop_cooldown(
+        deps.storage,
+        &config,
+        env.block.height,
+        &collateral_token_raw,
+    )? {
+        reduce_ltv(
+            &mut adjustments,
+            &mut ltv,
+            "price_drop_cooldown",
+            Decimal256::zero(),
+        );
+    }
+
+    let (price, confidence_spread) = query_collateral_valuation(
+        deps,
+        &config,
+        deps.api.addr_humanize(&config.oracle_contract)?,
+        &collateral_token_raw,
+        &elem,
+        Some(env.block.time.seconds()),
+        config.borrow_price_timeframe,
+        &config.valuation_mode,
+    )?;
+    if let (Some(max_spread), Some(spread)) = (config.max_confidence_spread, confidence_spread) {
+        if spread > max_spread {
+            reduce_ltv(
+                &mut adjustments,
+                &mut ltv,
+                "confidence_spread",
+                Decimal256::zero(),
+            );
+        }
+    }
+
+    if let Some(max_collateral_value) = config.max_collateral_value_per_borrower {
+        let locked_value = locked_amount * price;
+        if counted_amount * price > max_collateral_value {
+            reduce_ltv(
+                &mut adjustments,
+                &mut ltv,
+                "collateral_value_cap",
+                elem.max_ltv * Decimal256::from_ratio(max_collateral_value, locked_value),
+            );
+        }
+    }
+
+    Ok(EffectiveLtvResponse {
+        borrower: borrower.to_string(),
+        collateral_token: collateral_token.to_string(),
+        max_ltv: elem.max_ltv,
+        effective_ltv: ltv,
+        adjustments,
+    })
+}
+
+pub fn query_safe_borrow_amount(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    safety_ltv: Decimal256,
+    sub_account: Option<String>,
+) -> StdResult<SafeBorrowAmountResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals = read_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        sub_account.as_deref(),
+    );
+    let collaterals = eligible_collaterals(deps, collaterals)?;
+    let collaterals = borrowable_collaterals(deps, collaterals, env.block.height)?;
+
+    if safety_ltv.is_zero() {
+        return Err(StdError::generic_err("safety_ltv must be bigger than zero"));
+    }
+
+    for collateral in collaterals.iter() {
+        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        if safety_ltv > elem.max_ltv {
+            return Err(StdError::generic_err(format!(
+                "safety_ltv cannot exceed the max ltv of {}",
+                deps.api.addr_humanize(&collateral.0)?
+            )));
+        }
+    }
+
+    let (safe_borrow_limit, _) = compute_collaterals_limit(
+        deps,
+        &collaterals,
+        Some(env.block.time.seconds()),
+        Some(safety_ltv),
+        config.borrow_price_timeframe,
+        config.max_confidence_spread,
+        config.max_collateral_value_per_borrower,
+        &config.valuation_mode,
+    )?;
+    let loan_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+
+    let safe_borrow_amount = if safe_borrow_limit > loan_amount {
+        safe_borrow_limit - loan_amount
+    } else {
+        Uint256::zero()
+    };
+
+    Ok(SafeBorrowAmountResponse {
+        borrower: borrower.to_string(),
+        safe_borrow_limit,
+        loan_amount,
+        safe_borrow_amount,
+    })
+}
+
+/// The liquidation prices are derived from the borrow limit used by
+/// liquidations, each collateral counting for its value times its max ltv
+pub fn query_position(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> StdResult<PositionResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals: Tokens = read_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        sub_account.as_deref(),
+    );
+
+    let block_time = Some(env.block.time.seconds());
+    let (liquidation_limit, collateral_prices) =
+        compute_liquidation_borrow_limit(deps, &collaterals, block_time)?;
+    let borrow_limit = query_borrow_limit(
+        deps,
+        env.clone(),
+        borrower.clone(),
+        block_time,
+        sub_account.clone(),
+        None,
+        None,
+    )?
+    .borrow_limit;
+    let loan_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+
+    let mut collateral_value = Uint256::zero();
+    let mut elems: Vec<PositionCollateralElem> = vec![];
+    for (collateral, price) in collaterals.iter().zip(collateral_prices) {
+        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        let value = collateral.1 * price;
+        collateral_value += value;
+
+        // Moving this collateral price shifts the limit by its weighted value
+        let weighted_value = Decimal256::from_uint256(value) * elem.max_ltv;
+        let liquidation_price = if loan_amount.is_zero() || weighted_value.is_zero() {
+            None
+        } else if liquidation_limit >= loan_amount {
+            let headroom = Decimal256::from_uint256(liquidation_limit - loan_amount);
+            if headroom >= weighted_value {
+                None
+            } else {
+                Some(price * (Decimal256::one() - headroom / weighted_value))
+            }
+        } else {
+            let shortfall = Decimal256::from_uint256(loan_amount - liquidation_limit);
+            Some(price * (Decimal256::one() + shortfall / weighted_value))
+        };
+
+        elems.push(PositionCollateralElem {
+            collateral_token: deps.api.addr_humanize(&collateral.0)?.to_string(),
+            amount: collateral.1,
+            price,
+            value,
+            liquidation_price,
+        });
+    }
+
+    let ltv = if collateral_value.is_zero() {
+        Decimal256::zero()
+    } else {
+        Decimal256::from_ratio(loan_amount, collateral_value)
+    };
+
+    Ok(PositionResponse {
+        borrower: borrower.to_string(),
+        collaterals: elems,
+        collateral_value,
+        borrow_limit,
+        loan_amount,
+        ltv,
+        health_factor: health_factor(liquidation_limit, loan_amount),
+    })
+}
+
+/// With LTV = loan / sum(value), a price drop of `d` on a collateral raises
+/// the LTV by about LTV * d * value / sum(value), the sensitivity at d = 1%
+pub fn query_risk_contribution(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+) -> StdResult<RiskContributionResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals: Tokens = read_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        None,
+    );
+
+    let (_, collateral_prices) =
+        compute_liquidation_borrow_limit(deps, &collaterals, Some(env.block.time.seconds()))?;
+    let loan_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        None,
+    )?
+    .loan_amount;
+
+    let values: Vec<Uint256> = collaterals
+        .iter()
+        .zip(collateral_prices.iter())
+        .map(|(c, price)| c.1 * *price)
+        .collect();
+    let collateral_value = values.iter().fold(Uint256::zero(), |acc, v| acc + *v);
+    if collateral_value.is_zero() {
+        return Ok(RiskContributionResponse {
+            borrower: borrower.to_string(),
+            ltv: Decimal256::zero(),
+            contributions: vec![],
+        });
+    }
+
+    let mut contributions: Vec<RiskContributionElem> = vec![];
+    for (collateral, value) in collaterals.iter().zip(values) {
+        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        contributions.push(RiskContributionElem {
+            collateral_token: deps.api.addr_humanize(&collateral.0)?.to_string(),
+            value,
+            weighted_value: value * elem.max_ltv,
+            sensitivity: Decimal256::from_ratio(
+                loan_amount * value,
+                collateral_value * collateral_value,
+            ) * Decimal256::percent(1),
+        });
+    }
+
+    Ok(RiskContributionResponse {
+        borrower: borrower.to_string(),
+        ltv: Decimal256::from_ratio(loan_amount, collateral_value),
+        contributions,
+    })
+}
+
+/// Sums up the loan amounts exceeding the collateral value of each borrower,
+/// which cannot be recovered even by a full liquidation.
+/// Pages through every borrower of the market contract, so it is expensive
+pub fn query_bad_debt(deps: Deps, env: Env) -> StdResult<BadDebtResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let mut bad_debt = Uint256::zero();
+    let mut start_after: Option<String> = None;
+    loop {
+        let borrower_infos =
+            query_borrower_infos(deps, market.clone(), start_after, Some(30u32))?.borrower_infos;
+        if borrower_infos.is_empty() {
+            break;
+        }
+
+        for borrower_info in borrower_infos.iter() {
+            let borrower = deps.api.addr_validate(&borrower_info.borrower)?;
+            let loan_amount = query_borrower_info(
+                deps,
+                market.clone(),
+                borrower.clone(),
+                env.block.height,
+                borrower_info.sub_account.clone(),
+            )?
+            .loan_amount;
+
+            let collaterals: Tokens = read_collaterals(
+                deps.storage,
+                &deps.api.addr_canonicalize(borrower.as_str())?,
+                borrower_info.sub_account.as_deref(),
+            );
+            let (_, collateral_prices) = compute_liquidation_borrow_limit(
+                deps,
+                &collaterals,
+                Some(env.block.time.seconds()),
+            )?;
+            let collateral_value: Uint256 = collaterals
+                .iter()
+                .zip(collateral_prices.iter())
+                .fold(Uint256::zero(), |acc, (c, price)| acc + c.1 * *price);
+
+            if loan_amount > collateral_value {
+                bad_debt += loan_amount - collateral_value;
+            }
+        }
+
+        start_after = borrower_infos.last().map(|b| b.borrower.clone());
+    }
+
+    Ok(BadDebtResponse { bad_debt })
+}
+
+/// Collateral borrow caps are not supported yet,
+/// so every collateral is reported as unlimited
+pub fn query_collateral_cap_utilization(
+    deps: Deps,
+    env: Env,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<CollateralCapUtilizationResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+
+    let start_after = if let Some(start_after) = start_after {
+        Some(deps.api.addr_canonicalize(start_after.as_str())?)
+    } else {
+        None
+    };
+
+    let elems: Vec<CollateralCapUtilizationElem> =
+        read_whitelist_elems(deps.storage, start_after, limit)?
+            .into_iter()
+            .map(|(collateral_token, elem)| {
+                let (price, _) = query_collateral_price(
+                    deps,
+                    &config,
+                    oracle_contract.clone(),
+                    &collateral_token,
+                    &elem,
+                    Some(env.block.time.seconds()),
+                    config.borrow_price_timeframe,
+                )?;
+                let used =
+                    checked_mul_decimal(read_total_locked(deps.storage, &collateral_token), price)?;
+
+                Ok(CollateralCapUtilizationElem {
+                    collateral_token: deps.api.addr_humanize(&collateral_token)?.to_string(),
+                    cap: None,
+                    used,
+                    utilization_ratio: None,
+                })
+            })
+            .collect::<StdResult<Vec<CollateralCapUtilizationElem>>>()?;
+
+    Ok(CollateralCapUtilizationResponse { elems })
+}
+
+pub fn query_category_exposure(deps: Deps, env: Env) -> StdResult<CategoryExposureResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+
+    let mut categories: Vec<CategoryExposureElem> = vec![];
+    let mut total_borrow_capacity = Uint256::zero();
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        let (price, _) = query_collateral_price(
+            deps,
+            &config,
+            oracle_contract.clone(),
+            &collateral_token,
+            &elem,
+            Some(env.block.time.seconds()),
+            config.borrow_price_timeframe,
+        )?;
+        let locked_value =
+            checked_mul_decimal(read_total_locked(deps.storage, &collateral_token), price)?;
+        let borrow_capacity = locked_value * elem.max_ltv;
+        total_borrow_capacity += borrow_capacity;
+
+        match categories
+            .iter_mut()
+            .find(|c| c.correlation_group == elem.correlation_group)
+        {
+            Some(category) => {
+                category.locked_value += locked_value;
+                category.borrow_capacity += borrow_capacity;
+            }
+            None => categories.push(CategoryExposureElem {
+                correlation_group: elem.correlation_group,
+                locked_value,
+                borrow_capacity,
+                capacity_share: Decimal256::zero(),
+            }),
+        }
+    }
+
+    if !total_borrow_capacity.is_zero() {
+        for category in categories.iter_mut() {
+            category.capacity_share = Decimal256::from_uint256(category.borrow_capacity)
+                / Decimal256::from_uint256(total_borrow_capacity);
+        }
+    }
+
+    Ok(CategoryExposureResponse {
+        categories,
+        total_borrow_capacity,
+    })
+}
+
+pub fn query_global_borrowable_by_collateral(
+    deps: Deps,
+    env: Env,
+) -> StdResult<GlobalBorrowableByCollateralResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+
+    let elems: Vec<GlobalBorrowableElem> = read_all_whitelist_elems(deps.storage)?
+        .into_iter()
+        .map(|(collateral_token, elem)| {
+            let (price, _) = query_collateral_price(
+                deps,
+                &config,
+                oracle_contract.clone(),
+                &collateral_token,
+                &elem,
+                Some(env.block.time.seconds()),
+                config.borrow_price_timeframe,
+            )?;
+            let locked_value =
+                checked_mul_decimal(read_total_locked(deps.storage, &collateral_token), price)?;
+
+            // Collaterals carry no global cap, so the headroom is unbounded
+            Ok(GlobalBorrowableElem {
+                collateral_token: deps.api.addr_humanize(&collateral_token)?.to_string(),
+                locked_value,
+                borrow_capacity: locked_value * elem.max_ltv,
+                remaining_capacity: None,
+            })
+        })
+        .collect::<StdResult<Vec<GlobalBorrowableElem>>>()?;
+
+    Ok(GlobalBorrowableByCollateralResponse { elems })
+}
+
+pub fn query_solvency_check(deps: Deps, env: Env) -> StdResult<SolvencyCheckResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+
+    let mut total_collateral_value = Uint256::zero();
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        let (price, _) = query_collateral_price(
+            deps,
+            &config,
+            oracle_contract.clone(),
+            &collateral_token,
+            &elem,
+            Some(env.block.time.seconds()),
+            config.borrow_price_timeframe,
+        )?;
+        total_collateral_value = checked_add(
+            total_collateral_value,
+            checked_mul_decimal(read_total_locked(deps.storage, &collateral_token), price)?,
+        )?;
+    }
+
+    let total_debt = query_market_state(deps, market_contract, env.block.height)?.total_liabilities
+        * Uint256::one();
+
+    // solvent = collateral_value >= total_debt * (1 + solvency_margin)
+    let required_value =
+        checked_mul_decimal(total_debt, Decimal256::one() + config.solvency_margin)?;
+    let collateralization_ratio = if total_debt.is_zero() {
+        None
+    } else {
+        Some(Decimal256::from_ratio(total_collateral_value, total_debt))
+    };
+
+    Ok(SolvencyCheckResponse {
+        solvent: total_collateral_value >= required_value,
+        total_collateral_value,
+        total_debt,
+        collateralization_ratio,
+    })
+}
+
+pub fn query_liquidation_bonus(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> StdResult<LiquidationBonusResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals: Tokens = read_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        sub_account.as_deref(),
+    );
+
+    let (borrow_limit, collateral_prices) =
+        compute_liquidation_borrow_limit(deps, &collaterals, Some(env.block.time.seconds()))?;
+    let borrow_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+
+    if borrow_limit >= borrow_amount {
+        return Ok(LiquidationBonusResponse {
+            borrower: borrower.to_string(),
+            seized_value: Uint256::zero(),
+            repay_amount: Uint256::zero(),
+            protocol_fee: Uint256::zero(),
+            bonus: Uint256::zero(),
+        });
+    }
+
+    let liquidation_contract = deps.api.addr_humanize(&config.liquidation_contract)?;
+    let liquidation_amount = query_liquidation_amount(
+        deps,
+        liquidation_contract.clone(),
+        borrow_amount,
+        borrow_limit,
+        &collaterals.to_human(deps)?,
+        collateral_prices.clone(),
+        collateral_premium_rates(deps, liquidation_contract.clone(), &collaterals)?,
+    )?
+    .collaterals
+    .to_raw(deps)?;
+
+    let (seized_value, repay_amount, protocol_fee) = compute_liquidation_proceeds(
+        deps,
+        liquidation_contract,
+        &collaterals,
+        &collateral_prices,
+        &liquidation_amount,
+    )?;
+
+    Ok(LiquidationBonusResponse {
+        borrower: borrower.to_string(),
+        seized_value,
+        repay_amount,
+        protocol_fee,
+        bonus: seized_value - repay_amount - protocol_fee,
+    })
+}
+
+/// Liquidation amount of the borrower as `liquidate_collateral` sizes it,
+/// dust collaterals included
+pub fn query_max_liquidatable(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> StdResult<MaxLiquidatableResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals: Tokens = read_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        sub_account.as_deref(),
+    );
+
+    let (borrow_limit, collateral_prices) =
+        compute_liquidation_borrow_limit(deps, &collaterals, Some(env.block.time.seconds()))?;
+    let borrow_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+
+    if borrow_limit >= borrow_amount {
+        return Ok(MaxLiquidatableResponse {
+            borrower: borrower.to_string(),
+            repay_amount: Uint256::zero(),
+            bid_fee: Uint256::zero(),
+            collaterals: vec![],
+        });
+    }
+
+    let liquidation_contract = deps.api.addr_humanize(&config.liquidation_contract)?;
+    let liquidation_amount_res: LiquidationAmountResponse = query_liquidation_amount(
+        deps,
+        liquidation_contract.clone(),
+        borrow_amount,
+        borrow_limit,
+        &collaterals.to_human(deps)?,
+        collateral_prices.clone(),
+        collateral_premium_rates(deps, liquidation_contract.clone(), &collaterals)?,
+    )?;
+    let liquidation_amount = sweep_dust_collaterals(
+        &config,
+        &collaterals,
+        &collateral_prices,
+        liquidation_amount_res.collaterals.to_raw(deps)?,
+    )?;
+
+    let (_, repay_amount, bid_fee) = compute_liquidation_proceeds(
+        deps,
+        liquidation_contract,
+        &collaterals,
+        &collateral_prices,
+        &liquidation_amount,
+    )?;
+
+    Ok(MaxLiquidatableResponse {
+        borrower: borrower.to_string(),
+        repay_amount,
+        bid_fee,
+        collaterals: liquidation_amount.to_human(deps)?,
+    })
+}
+
+/// Steps of the liquidation `query_max_liquidatable` sizes, one per seized
+/// collateral in the order `liquidate_collateral` seizes them
+pub fn query_liquidation_waterfall(
+    deps: Deps,
+    env: Env,
+    borrower: Addr,
+    sub_account: Option<String>,
+) -> StdResult<LiquidationWaterfallResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals: Tokens = read_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(borrower.as_str())?,
+        sub_account.as_deref(),
+    );
+
+    let (borrow_limit, collateral_prices) =
+        compute_liquidation_borrow_limit(deps, &collaterals, Some(env.block.time.seconds()))?;
+    let loan_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+
+    if borrow_limit >= loan_amount {
+        return Ok(LiquidationWaterfallResponse {
+            borrower: borrower.to_string(),
+            loan_amount,
+            steps: vec![],
+            repay_amount: Uint256::zero(),
+            cures_shortfall: true,
+            residual_bad_debt: Uint256::zero(),
+        });
+    }
+
+    let liquidation_contract = deps.api.addr_humanize(&config.liquidation_contract)?;
+    let liquidation_amount_res: LiquidationAmountResponse = query_liquidation_amount(
+        deps,
+        liquidation_contract.clone(),
+        loan_amount,
+        borrow_limit,
+        &collaterals.to_human(deps)?,
+        collateral_prices.clone(),
+        collateral_premium_rates(deps, liquidation_contract.clone(), &collaterals)?,
+    )?;
+    let liquidation_amount = sweep_dust_collaterals(
+        &config,
+        &collaterals,
+        &collateral_prices,
+        liquidation_amount_res.collaterals.to_raw(deps)?,
+    )?;
+
+    let mut steps: Vec<LiquidationWaterfallElem> = vec![];
+    let mut repay_amount = Uint256::zero();
+    for (collateral_token, amount) in liquidation_amount.iter() {
+        let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, collateral_token)?;
+        let liquidation_config = query_liquidation_config(
+            deps,
+            match &whitelist_elem.liquidation_contract {
+                Some(contract) => deps.api.addr_humanize(contract)?,
+                None => liquidation_contract.clone(),
+            },
+        )?;
+        let premium_rate = whitelist_elem
+            .liquidation_premium
+            .unwrap_or(liquidation_config.max_premium_rate);
+
+        let (_, price) = locked_collateral(&collaterals, &collateral_prices, collateral_token)?;
+        let value = checked_mul_decimal(*amount, price)?;
+        let required_stable = value * (Decimal256::one() - premium_rate);
+        let step_repay = required_stable - required_stable * liquidation_config.bid_fee;
+        repay_amount += step_repay;
+
+        steps.push(LiquidationWaterfallElem {
+            collateral_token: deps.api.addr_humanize(collateral_token)?.to_string(),
+            amount: *amount,
+            value,
+            repay_amount: step_repay,
+            remaining_loan: loan_amount - std::cmp::min(repay_amount, loan_amount),
+        });
+    }
+
+    let remaining_loan = loan_amount - std::cmp::min(repay_amount, loan_amount);
+    let mut remaining_collaterals = collaterals.clone();
+    remaining_collaterals.sub(liquidation_amount)?;
+    remaining_collaterals.retain(|c| !c.1.is_zero());
+
+    let (remaining_limit, _) = compute_liquidation_borrow_limit(
+        deps,
+        &remaining_collaterals,
+        Some(env.block.time.seconds()),
+    )?;
+    let residual_bad_debt = if remaining_collaterals.is_empty() {
+        remaining_loan
+    } else {
+        Uint256::zero()
+    };
+
+    Ok(LiquidationWaterfallResponse {
+        borrower: borrower.to_string(),
+        loan_amount,
+        steps,
+        repay_amount,
+        cures_shortfall: remaining_loan <= remaining_limit,
+        residual_bad_debt,
+    })
+}
+
+/// Liquidations of a page of borrowers, default positions and sub-accounts,
+/// sized as `query_max_liquidatable` does with every collateral price
+/// lowered by `price_drop`
+pub fn query_stress_test(
+    deps: Deps,
+    env: Env,
+    price_drop: Decimal256,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<StressTestResponse> {
+    if price_drop > Decimal256::one() {
+        return Err(StdError::generic_err("price_drop cannot exceed one"));
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let start_after = if let Some(start_after) = start_after {
+        Some(deps.api.addr_canonicalize(start_after.as_str())?)
+    } else {
+        None
+    };
+    let limit = limit.unwrap_or(config.default_limit);
+    let borrowers: Vec<(CanonicalAddr, Tokens)> =
+        read_collaterals_page(deps.storage, start_after, Some(limit))?;
+
+    let mut res = StressTestResponse {
+        price_drop,
+        positions: 0,
+        liquidatable_positions: 0,
+        loan_amount: Uint256::zero(),
+        repay_amount: Uint256::zero(),
+        seized_value: Uint256::zero(),
+        last_borrower: None,
+    };
+    for (borrower_raw, collaterals) in borrowers.iter() {
+        let borrower = deps.api.addr_humanize(borrower_raw)?;
+        let mut positions: Vec<(Option<String>, Tokens)> = vec![(None, collaterals.clone())];
+        let mut sub_account_start_after: Option<String> = None;
+        loop {
+            let sub_accounts = read_sub_account_collaterals(
+                deps.storage,
+                borrower_raw,
+                sub_account_start_after,
+                None,
+            )?;
+            if sub_accounts.is_empty() {
+                break;
+            }
+
+            sub_account_start_after = sub_accounts.last().map(|(name, _)| name.clone());
+            positions.extend(
+                sub_accounts
+                    .into_iter()
+                    .map(|(sub_account, collaterals)| (Some(sub_account), collaterals)),
+            );
+        }
+
+        for (sub_account, collaterals) in positions {
+            res.positions += 1;
+            if let Some((loan_amount, repay_amount, seized_value)) = stress_position(
+                deps,
+                &env,
+                &config,
+                &borrower,
+                sub_account,
+                &collaterals,
+                price_drop,
+            )? {
+                res.liquidatable_positions += 1;
+                res.loan_amount += loan_amount;
+                res.repay_amount += repay_amount;
+                res.seized_value += seized_value;
+            }
+        }
+        res.last_borrower = Some(borrower.to_string());
+    }
+
+    Ok(res)
+}
+
+/// Loan, repayment and seized value of the liquidation of the position
+/// at the dropped prices, `None` when it stays above its liquidation limit
+fn stress_position(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    borrower: &Addr,
+    sub_account: Option<String>,
+    collaterals: &Tokens,
+    price_drop: Decimal256,
+) -> StdResult<Option<(Uint256, Uint256, Uint256)>> {
+    let (_, collateral_prices) =
+        compute_liquidation_borrow_limit(deps, collaterals, Some(env.block.time.seconds()))?;
+    let collateral_prices: Vec<Decimal256> = collateral_prices
+        .into_iter()
+        .map(|price| price * (Decimal256::one() - price_drop))
+        .collect();
+
+    // Same valuation as the liquidation borrow limit, at the dropped prices
+    let mut borrow_limit = Uint256::zero();
+    for (collateral, price) in collaterals.iter().zip(collateral_prices.iter()) {
+        let elem: WhitelistElem = read_whitelist_elem(deps.storage, &collateral.0)?;
+        let mut collateral_value = checked_mul_decimal(collateral.1, *price)?;
+        if let Some(max_collateral_value) = config.max_collateral_value_per_borrower {
+            collateral_value = std::cmp::min(collateral_value, max_collateral_value);
+        }
+        borrow_limit = checked_add(
+            borrow_limit,
+            checked_mul_decimal(collateral_value, elem.max_ltv)?,
+        )?;
+    }
+
+    let loan_amount = query_borrower_info(
+        deps,
+        deps.api.addr_humanize(&config.market_contract)?,
+        borrower.clone(),
+        env.block.height,
+        sub_account,
+    )?
+    .loan_amount;
+    if borrow_limit >= loan_amount {
+        return Ok(None);
+    }
+
+    let liquidation_contract = deps.api.addr_humanize(&config.liquidation_contract)?;
+    let liquidation_amount_res: LiquidationAmountResponse = query_liquidation_amount(
+        deps,
+        liquidation_contract.clone(),
+        loan_amount,
+        borrow_limit,
+        &collaterals.to_human(deps)?,
+        collateral_prices.clone(),
+        collateral_premium_rates(deps, liquidation_contract.clone(), collaterals)?,
+    )?;
+    let liquidation_amount = sweep_dust_collaterals(
+        config,
+        collaterals,
+        &collateral_prices,
+        liquidation_amount_res.collaterals.to_raw(deps)?,
+    )?;
+
+    let (seized_value, repay_amount, _) = compute_liquidation_proceeds(
+        deps,
+        liquidation_contract,
+        collaterals,
+        &collateral_prices,
+        &liquidation_amount,
+    )?;
+
+    Ok(Some((loan_amount, repay_amount, seized_value)))
+}
+
+pub fn query_liquidation_history(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<LiquidationHistoryResponse> {
+    let records = read_liquidation_records(deps.storage, start_after, limit)?
+        .into_iter()
+        .map(|(id, record)| {
+            Ok(LiquidationRecordResponse {
+                id,
+                borrower: deps.api.addr_humanize(&record.borrower)?.to_string(),
+                liquidator: deps.api.addr_humanize(&record.liquidator)?.to_string(),
+                repaid: record.repaid,
+                seized_tokens: record.seized_tokens.to_human(deps)?,
+                block_height: record.block_height,
+            })
+        })
+        .collect::<StdResult<Vec<LiquidationRecordResponse>>>()?;
+
+    Ok(LiquidationHistoryResponse { records })
+}
+
+pub fn query_fairness_fund(deps: Deps) -> StdResult<FairnessFundResponse> {
+    let fund: FairnessFund = read_fairness_fund(deps.storage)?;
+    Ok(FairnessFundResponse {
+        available: fund.available,
+        reserved: fund.reserved,
+    })
+}
+
+/// Positions without collaterals are removed from the overseer,
+/// so sub accounts left with only a loan are not listed
+pub fn query_sub_accounts(
+    deps: Deps,
+    env: Env,
+    owner: Addr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<SubAccountsResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let positions = read_sub_account_collaterals(
+        deps.storage,
+        &deps.api.addr_canonicalize(owner.as_str())?,
+        start_after,
+        limit,
+    )?;
+
+    let mut sub_accounts: Vec<SubAccountResponse> = vec![];
+    for (sub_account, collaterals) in positions {
+        let (_, collateral_prices) =
+            compute_borrow_limit(deps, &collaterals, Some(env.block.time.seconds()))?;
+        let mut collateral_value = Uint256::zero();
+        for (collateral, price) in collaterals.iter().zip(collateral_prices.iter()) {
+            collateral_value =
+                checked_add(collateral_value, checked_mul_decimal(collateral.1, *price)?)?;
+        }
+
+        let loan_amount = query_borrower_info(
+            deps,
+            market.clone(),
+            owner.clone(),
+            env.block.height,
+            Some(sub_account.clone()),
+        )?
+        .loan_amount;
+
+        sub_accounts.push(SubAccountResponse {
+            sub_account,
+            collateral_value,
+            loan_amount,
+        });
+    }
+
+    Ok(SubAccountsResponse {
+        owner: owner.to_string(),
+        sub_accounts,
+    })
+}
+
+pub fn query_stale_collaterals(deps: Deps, env: Env) -> StdResult<StaleCollateralsResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+    let block_time = env.block.time.seconds();
+
+    let mut elems: Vec<StaleCollateralElem> = vec![];
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        let price: PriceResponse = query_oracle_price(
+            deps,
+            &config,
+            oracle_contract.clone(),
+            deps.api.addr_humanize(&collateral_token)?.to_string(),
+            elem.quote_denom
+                .clone()
+                .unwrap_or_else(|| config.stable_denom.to_string()),
+            &PriceMode::Spot,
+            None,
+        )?;
+        let mut last_updated = price.last_updated_base.min(price.last_updated_quote);
+
+        if let Some(quote_denom) = elem.quote_denom {
+            let quote_price: PriceResponse = query_oracle_price(
+                deps,
+                &config,
+                oracle_contract.clone(),
+                quote_denom,
+                config.stable_denom.to_string(),
+                &PriceMode::Spot,
+                None,
+            )?;
+            last_updated = last_updated
+                .min(quote_price.last_updated_base)
+                .min(quote_price.last_updated_quote);
+        }
+
+        let price_age = block_time.saturating_sub(last_updated);
+        if price_age > config.borrow_price_timeframe {
+            elems.push(StaleCollateralElem {
+                collateral_token: deps.api.addr_humanize(&collateral_token)?.to_string(),
+                last_updated,
+                price_age,
+            });
+        }
+    }
+
+    Ok(StaleCollateralsResponse { elems })
+}
+
+pub fn query_consistency_check(
+    deps: Deps,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<ConsistencyCheckResponse> {
+    let start_after = if let Some(start_after) = start_after {
+        Some(deps.api.addr_canonicalize(start_after.as_str())?)
+    } else {
+        None
+    };
+
+    let borrowers: Vec<(CanonicalAddr, Tokens)> =
+        read_collaterals_page(deps.storage, start_after, limit)?;
+
+    let mut page_locked: Tokens = vec![];
+    for (borrower, collaterals) in borrowers.iter() {
+        page_locked.add(collaterals.clone());
+
+        let mut sub_account_start_after: Option<String> = None;
+        loop {
+            let sub_accounts = read_sub_account_collaterals(
+                deps.storage,
+                borrower,
+                sub_account_start_after,
+                None,
+            )?;
+            if sub_accounts.is_empty() {
+                break;
+            }
+
+            sub_account_start_after = sub_accounts.last().map(|(name, _)| name.clone());
+            for (_, collaterals) in sub_accounts {
+                page_locked.add(collaterals);
+            }
+        }
+    }
+
+    let mut elems: Vec<ConsistencyCheckElem> = vec![];
+    let mut discrepancies: Vec<String> = vec![];
+    for (collateral_token, page_locked) in page_locked.into_iter() {
+        let collateral_token_human = deps.api.addr_humanize(&collateral_token)?.to_string();
+        let total_locked = read_total_locked(deps.storage, &collateral_token);
+        if total_locked < page_locked {
+            discrepancies.push(collateral_token_human.clone());
+        }
+
+        elems.push(ConsistencyCheckElem {
+            collateral_token: collateral_token_human,
+            total_locked,
+            page_locked,
+        });
+    }
+
+    let last_borrower = borrowers
+        .last()
+        .map(|(borrower, _)| deps.api.addr_humanize(borrower).map(|b| b.to_string()))
+        .transpose()?;
+
+    Ok(ConsistencyCheckResponse {
+        elems,
+        discrepancies,
+        last_borrower,
     })
 }
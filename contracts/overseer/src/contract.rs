@@ -1,30 +1,88 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo,
-    Response, StdResult, WasmMsg,
+    attr, to_binary, Addr, Attribute, BankMsg, Binary, CanonicalAddr, Coin, ContractResult,
+    CosmosMsg, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdError, StdResult, Storage,
+    SubMsg, WasmMsg,
 };
 
 use crate::collateral::{
-    liquidate_collateral, lock_collateral, query_all_collaterals, query_borrow_limit,
-    query_collaterals, unlock_collateral,
+    accept_position, add_custody_amounts, cancel_position_transfer, claim_compensation,
+    close_and_withdraw, close_and_withdraw_hook, delegate_liquidation, fund_fairness_fund,
+    liquidate_collateral, liquidate_collateral_reply, liquidation_record_reply, lock_collateral,
+    lock_collateral_for, migrate_position, query_all_collaterals, query_bad_debt,
+    query_borrow_limit, query_category_exposure, query_collateral_cap_utilization,
+    query_collateral_price, query_collaterals, query_consistency_check, query_effective_ltv,
+    query_fairness_fund, query_global_borrowable_by_collateral, query_interest_buffer,
+    query_liquidation_bonus, query_liquidation_history, query_liquidation_waterfall,
+    query_max_liquidatable, query_position, query_risk_contribution, query_safe_borrow_amount,
+    query_solvency_check, query_stale_collaterals, query_stress_test, query_sub_accounts,
+    reallocate_collateral, repay_auction_proceeds, report_price_correction,
+    return_auction_collateral, revoke_liquidation_delegation, settle_bad_debt,
+    split_custody_amount, split_position, transfer_position, unlock_collateral,
+    unlock_collateral_from_repay, unlock_collateral_reply, unlock_to_target_ltv,
 };
 use crate::error::ContractError;
-use crate::querier::query_epoch_state;
+use crate::querier::{
+    query_anc_emission_rate, query_anc_price, query_anchor_token, query_aterra_contract,
+    query_borrower_info, query_custody_pending_rewards, query_custody_total_balance,
+    query_epoch_state, query_interest_model_config, query_market_reserves, query_market_state,
+    query_max_redeemable, query_native_conversion,
+};
 use crate::state::{
-    read_config, read_epoch_state, read_whitelist, read_whitelist_elem, store_config,
-    store_epoch_state, store_whitelist_elem, Config, EpochState, WhitelistElem,
+    read_all_whitelist_elems, read_buffer_deposit, read_buffer_flow_records,
+    read_buffer_redeem_height, read_collaterals, read_collaterals_page, read_config,
+    read_custody_locks, read_custody_quorum, read_epoch_progress, read_epoch_rate_records,
+    read_epoch_state, read_epoch_steps, read_epoch_totals, read_legacy_config,
+    read_legacy_whitelist, read_ltv_change, read_recent_deposit_rates,
+    read_sub_account_collaterals, read_total_locked, read_whitelist, read_whitelist_elem,
+    remove_custody_quorum, remove_epoch_progress, remove_epoch_steps, remove_whitelist_elem,
+    store_backstop_used, store_bad_debt_settled, store_buffer_deposit, store_buffer_flow_record,
+    store_buffer_redeem_height, store_config, store_custody_quorum, store_epoch_progress,
+    store_epoch_rate_record, store_epoch_state, store_epoch_steps, store_epoch_totals,
+    store_ltv_change, store_total_locked, store_whitelist_elem, sum_locked_collaterals,
+    whitelist_response_elem, BufferFlowRecord, Config, CustodyContractElem, CustodyQuorum,
+    EpochProgress, EpochRateRecord, EpochState, EpochSteps, EpochTotals, LtvChange, WhitelistElem,
+    DEFAULT_LIMIT, MAX_EPOCH_RATE_HISTORY, MAX_LIMIT,
 };
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
-use moneymarket::common::optional_addr_validate;
+use cw20::Cw20ExecuteMsg;
+use moneymarket::common::{optional_addr_validate, round_decimal, AssetInfo};
 use moneymarket::custody::ExecuteMsg as CustodyExecuteMsg;
 use moneymarket::market::EpochStateResponse;
-use moneymarket::market::ExecuteMsg as MarketExecuteMsg;
+use moneymarket::market::{Cw20HookMsg as MarketCw20HookMsg, ExecuteMsg as MarketExecuteMsg};
+use moneymarket::oracle::ExecuteMsg as OracleExecuteMsg;
 use moneymarket::overseer::{
-    ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg, WhitelistResponse, WhitelistResponseElem,
+    AncBuybackEstimateResponse, AssetRegistryResponse, AuthorizationResponse, BufferFlowElem,
+    BufferFlowResponse, ConfigResponse, EpochRatePerformanceElem, EpochRatePerformanceResponse,
+    EpochTotalsResponse, ExecuteMsg, InstantiateMsg, InterestBufferDepositResponse, MigrateMsg,
+    NetDepositRateResponse, NextEpochResponse, QueryMsg, SimulationResponse, SnapshotPositionElem,
+    SnapshotResponse, TokenType, TrustedContractsResponse, UpdateWhitelistEntry, ValuationMode,
+    WhitelistResponse, WhitelistResponseElem,
+};
+use moneymarket::querier::{
+    deduct_tax, query_balance, query_price, query_token_balance, TimeConstraints,
 };
-use moneymarket::querier::{deduct_tax, query_balance};
+use moneymarket::tokens::{Tokens, TokensHuman, TokensToHuman};
+
+// Percent, high enough to keep regular price moves out of the logs
+const DEFAULT_PRICE_DEVIATION_THRESHOLD: u64 = 50;
+const MAX_LIQUIDATION_PREMIUM: u64 = 30;
+// Decimals of the collateral unit oracle prices are quoted for
+const DEFAULT_COLLATERAL_DECIMALS: u8 = 6;
+const MAX_COLLATERAL_DECIMALS: u8 = 18;
+// Decimals of the Terra stable coins, assumed for migrated configs
+const DEFAULT_STABLE_DECIMALS: u8 = 6;
+const MAX_STABLE_DECIMALS: u8 = 18;
+// Reply ids of the DistributeRewards submessages,
+// the ids below belong to the ordered epoch operation steps
+const DISTRIBUTE_REWARDS_REPLY_ID: u64 = 1_000_000;
+// Reply ids of the custody transfers of unlocks and liquidations
+pub(crate) const UNLOCK_COLLATERAL_REPLY_ID: u64 = 2_000_000;
+pub(crate) const LIQUIDATE_COLLATERAL_REPLY_ID: u64 = 3_000_000;
+// Reply id of the market repayment of a liquidation
+pub(crate) const LIQUIDATION_RECORD_REPLY_ID: u64 = 4_000_000;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -32,24 +90,92 @@ pub fn instantiate(
     env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
-    store_config(
-        deps.storage,
-        &Config {
-            owner_addr: deps.api.addr_canonicalize(&msg.owner_addr)?,
-            oracle_contract: deps.api.addr_canonicalize(&msg.oracle_contract)?,
-            market_contract: deps.api.addr_canonicalize(&msg.market_contract)?,
-            liquidation_contract: deps.api.addr_canonicalize(&msg.liquidation_contract)?,
-            collector_contract: deps.api.addr_canonicalize(&msg.collector_contract)?,
-            stable_denom: msg.stable_denom,
-            epoch_period: msg.epoch_period,
-            threshold_deposit_rate: msg.threshold_deposit_rate,
-            target_deposit_rate: msg.target_deposit_rate,
-            buffer_distribution_factor: msg.buffer_distribution_factor,
-            anc_purchase_factor: msg.anc_purchase_factor,
-            price_timeframe: msg.price_timeframe,
-        },
+) -> Result<Response, ContractError> {
+    let liquidation_price_timeframe = msg
+        .liquidation_price_timeframe
+        .unwrap_or(msg.borrow_price_timeframe);
+    assert_price_timeframes(msg.borrow_price_timeframe, liquidation_price_timeframe)?;
+    assert_stable_decimals(msg.stable_decimals)?;
+
+    let collateral_converter = msg
+        .collateral_converter
+        .map(|c| deps.api.addr_canonicalize(&c))
+        .transpose()?;
+    let fallback_oracle_contract = msg
+        .fallback_oracle_contract
+        .map(|c| deps.api.addr_canonicalize(&c))
+        .transpose()?;
+    let risk_admin = msg
+        .risk_admin
+        .map(|r| deps.api.addr_canonicalize(&r))
+        .transpose()?;
+    let anc_buyback_amm = msg
+        .anc_buyback_amm
+        .map(|a| deps.api.addr_canonicalize(&a))
+        .transpose()?;
+    let config = Config {
+        owner_addr: deps.api.addr_canonicalize(&msg.owner_addr)?,
+        oracle_contract: deps.api.addr_canonicalize(&msg.oracle_contract)?,
+        market_contract: deps.api.addr_canonicalize(&msg.market_contract)?,
+        liquidation_contract: deps.api.addr_canonicalize(&msg.liquidation_contract)?,
+        collector_contract: deps.api.addr_canonicalize(&msg.collector_contract)?,
+        stable_denom: msg.stable_denom,
+        epoch_period: msg.epoch_period,
+        threshold_deposit_rate: msg.threshold_deposit_rate,
+        target_deposit_rate: msg.target_deposit_rate,
+        buffer_distribution_factor: msg.buffer_distribution_factor,
+        anc_purchase_factor: msg.anc_purchase_factor,
+        borrow_price_timeframe: msg.borrow_price_timeframe,
+        liquidation_price_timeframe,
+        max_anc_price: msg.max_anc_price,
+        buffer_target: msg.buffer_target,
+        surplus_recipient: deps.api.addr_canonicalize(&msg.surplus_recipient)?,
+        stable_decimals: msg.stable_decimals,
+        price_deviation_threshold: msg
+            .price_deviation_threshold
+            .unwrap_or_else(|| Decimal256::percent(DEFAULT_PRICE_DEVIATION_THRESHOLD)),
+        max_collaterals_per_borrower: msg.max_collaterals_per_borrower,
+        ordered_epoch_operations: msg.ordered_epoch_operations.unwrap_or(false),
+        dust_threshold: msg.dust_threshold.unwrap_or_else(Uint256::zero),
+        dust_tolerance: msg.dust_tolerance.unwrap_or_else(Decimal256::zero),
+        collateral_converter,
+        soft_liquidation: msg.soft_liquidation.unwrap_or(false),
+        buffer_backstop_limit: msg.buffer_backstop_limit.unwrap_or_else(Uint256::zero),
+        max_confidence_spread: msg.max_confidence_spread,
+        reserve_target: msg.reserve_target.unwrap_or_else(Uint256::zero),
+        reserve_topup_factor: msg.reserve_topup_factor.unwrap_or_else(Decimal256::zero),
+        epoch_requires_fresh_prices: msg.epoch_requires_fresh_prices.unwrap_or(false),
+        fallback_oracle_contract,
+        min_custody_quorum: msg.min_custody_quorum.unwrap_or_else(Decimal256::zero),
+        same_block_collateral_delay: msg.same_block_collateral_delay.unwrap_or(false),
+        max_collateral_value_per_borrower: msg.max_collateral_value_per_borrower,
+        risk_admin,
+        epoch_custody_batch_size: msg.epoch_custody_batch_size,
+        anc_buyback_amm,
+        min_lock_amount: msg.min_lock_amount.unwrap_or_else(Uint256::zero),
+        solvency_margin: msg.solvency_margin.unwrap_or_else(Decimal256::zero),
+        max_block_price_drop: msg.max_block_price_drop,
+        price_drop_cooldown: msg.price_drop_cooldown.unwrap_or(0),
+        dispute_window: msg.dispute_window.unwrap_or(0),
+        valuation_mode: msg.valuation_mode.unwrap_or(ValuationMode::Spot),
+        default_limit: msg.default_limit.unwrap_or(DEFAULT_LIMIT),
+        bad_debt_budget: msg.bad_debt_budget.unwrap_or_else(Uint256::zero),
+        deposit_rate_smoothing_epochs: msg.deposit_rate_smoothing_epochs.unwrap_or(1),
+        skip_paused_collaterals: msg.skip_paused_collaterals.unwrap_or(false),
+        max_ltv_delta_per_epoch: msg.max_ltv_delta_per_epoch,
+        liquidation_frozen: false,
+        max_anc_purchase_per_epoch: msg.max_anc_purchase_per_epoch,
+    };
+    assert_reserve_topup_factor(config.reserve_topup_factor)?;
+    assert_epoch_custody_batch_size(config.epoch_custody_batch_size)?;
+    assert_default_limit(config.default_limit)?;
+    assert_deposit_rate_smoothing(config.deposit_rate_smoothing_epochs)?;
+    assert_min_custody_quorum(config.min_custody_quorum)?;
+    assert_contract_addresses(
+        &deps.api.addr_canonicalize(env.contract.address.as_str())?,
+        &config,
     )?;
+    store_config(deps.storage, &config)?;
 
     store_epoch_state(
         deps.storage,
@@ -82,11 +208,48 @@ pub fn execute(
             buffer_distribution_factor,
             anc_purchase_factor,
             epoch_period,
-            price_timeframe,
+            borrow_price_timeframe,
+            liquidation_price_timeframe,
+            max_anc_price,
+            buffer_target,
+            surplus_recipient,
+            price_deviation_threshold,
+            max_collaterals_per_borrower,
+            ordered_epoch_operations,
+            dust_threshold,
+            dust_tolerance,
+            collateral_converter,
+            soft_liquidation,
+            buffer_backstop_limit,
+            max_confidence_spread,
+            reserve_target,
+            reserve_topup_factor,
+            epoch_requires_fresh_prices,
+            fallback_oracle_contract,
+            min_custody_quorum,
+            same_block_collateral_delay,
+            max_collateral_value_per_borrower,
+            risk_admin,
+            epoch_custody_batch_size,
+            anc_buyback_amm,
+            min_lock_amount,
+            solvency_margin,
+            max_block_price_drop,
+            price_drop_cooldown,
+            dispute_window,
+            valuation_mode,
+            default_limit,
+            bad_debt_budget,
+            deposit_rate_smoothing_epochs,
+            skip_paused_collaterals,
+            max_ltv_delta_per_epoch,
+            max_anc_purchase_per_epoch,
+            stable_decimals,
         } => {
             let api = deps.api;
             update_config(
                 deps,
+                env,
                 info,
                 optional_addr_validate(api, owner_addr)?,
                 optional_addr_validate(api, oracle_contract)?,
@@ -96,15 +259,71 @@ pub fn execute(
                 buffer_distribution_factor,
                 anc_purchase_factor,
                 epoch_period,
-                price_timeframe,
+                borrow_price_timeframe,
+                liquidation_price_timeframe,
+                max_anc_price,
+                buffer_target,
+                optional_addr_validate(api, surplus_recipient)?,
+                price_deviation_threshold,
+                max_collaterals_per_borrower,
+                ordered_epoch_operations,
+                dust_threshold,
+                dust_tolerance,
+                optional_addr_validate(api, collateral_converter)?,
+                soft_liquidation,
+                buffer_backstop_limit,
+                max_confidence_spread,
+                reserve_target,
+                reserve_topup_factor,
+                epoch_requires_fresh_prices,
+                optional_addr_validate(api, fallback_oracle_contract)?,
+                min_custody_quorum,
+                same_block_collateral_delay,
+                max_collateral_value_per_borrower,
+                optional_addr_validate(api, risk_admin)?,
+                epoch_custody_batch_size,
+                optional_addr_validate(api, anc_buyback_amm)?,
+                min_lock_amount,
+                solvency_margin,
+                max_block_price_drop,
+                price_drop_cooldown,
+                dispute_window,
+                valuation_mode,
+                default_limit,
+                bad_debt_budget,
+                deposit_rate_smoothing_epochs,
+                skip_paused_collaterals,
+                max_ltv_delta_per_epoch,
+                max_anc_purchase_per_epoch,
+                stable_decimals,
             )
         }
+        ExecuteMsg::RotateOracle { new_oracle } => {
+            let api = deps.api;
+            rotate_oracle(deps, env, info, api.addr_validate(&new_oracle)?)
+        }
+        ExecuteMsg::SetLiquidationFreeze { frozen } => set_liquidation_freeze(deps, info, frozen),
+        ExecuteMsg::SweepTokens { asset, recipient } => {
+            let api = deps.api;
+            sweep_tokens(deps, env, info, asset, api.addr_validate(&recipient)?)
+        }
         ExecuteMsg::Whitelist {
             name,
             symbol,
             collateral_token,
             custody_contract,
             max_ltv,
+            quote_denom,
+            liquidation_premium,
+            correlation_group,
+            min_lock_amount,
+            borrow_enabled_from,
+            borrow_disabled_from,
+            liquidation_contract,
+            decimals,
+            exchange_rate_source,
+            borrow_rate_premium,
+            token_type,
         } => {
             let api = deps.api;
             register_whitelist(
@@ -115,12 +334,78 @@ pub fn execute(
                 api.addr_validate(&collateral_token)?,
                 api.addr_validate(&custody_contract)?,
                 max_ltv,
+                quote_denom,
+                liquidation_premium,
+                correlation_group,
+                min_lock_amount,
+                borrow_enabled_from,
+                borrow_disabled_from,
+                optional_addr_validate(api, liquidation_contract)?,
+                decimals,
+                optional_addr_validate(api, exchange_rate_source)?,
+                borrow_rate_premium,
+                token_type,
+            )
+        }
+        ExecuteMsg::WhitelistWithPrice {
+            name,
+            symbol,
+            collateral_token,
+            custody_contract,
+            max_ltv,
+            quote_denom,
+            liquidation_premium,
+            correlation_group,
+            min_lock_amount,
+            borrow_enabled_from,
+            borrow_disabled_from,
+            liquidation_contract,
+            decimals,
+            exchange_rate_source,
+            borrow_rate_premium,
+            token_type,
+            initial_price,
+            feeder,
+        } => {
+            let api = deps.api;
+            register_whitelist_with_price(
+                deps,
+                env,
+                info,
+                name,
+                symbol,
+                api.addr_validate(&collateral_token)?,
+                api.addr_validate(&custody_contract)?,
+                max_ltv,
+                quote_denom,
+                liquidation_premium,
+                correlation_group,
+                min_lock_amount,
+                borrow_enabled_from,
+                borrow_disabled_from,
+                optional_addr_validate(api, liquidation_contract)?,
+                decimals,
+                optional_addr_validate(api, exchange_rate_source)?,
+                borrow_rate_premium,
+                token_type,
+                initial_price,
+                api.addr_validate(&feeder)?,
             )
         }
         ExecuteMsg::UpdateWhitelist {
             collateral_token,
             custody_contract,
             max_ltv,
+            quote_denom,
+            liquidation_premium,
+            correlation_group,
+            min_lock_amount,
+            borrow_enabled_from,
+            borrow_disabled_from,
+            liquidation_contract,
+            decimals,
+            exchange_rate_source,
+            borrow_rate_premium,
         } => {
             let api = deps.api;
             update_whitelist(
@@ -129,20 +414,239 @@ pub fn execute(
                 api.addr_validate(&collateral_token)?,
                 optional_addr_validate(api, custody_contract)?,
                 max_ltv,
+                quote_denom,
+                liquidation_premium,
+                correlation_group,
+                min_lock_amount,
+                borrow_enabled_from,
+                borrow_disabled_from,
+                optional_addr_validate(api, liquidation_contract)?,
+                decimals,
+                optional_addr_validate(api, exchange_rate_source)?,
+                borrow_rate_premium,
+            )
+        }
+        ExecuteMsg::UpdateWhitelistBatch { updates } => update_whitelist_batch(deps, info, updates),
+        ExecuteMsg::RemoveWhitelist { collateral_token } => {
+            let api = deps.api;
+            remove_whitelist(deps, info, api.addr_validate(&collateral_token)?)
+        }
+        ExecuteMsg::RegisterCustodyContract {
+            collateral_token,
+            custody_contract,
+            weight,
+        } => {
+            let api = deps.api;
+            register_custody_contract(
+                deps,
+                info,
+                api.addr_validate(&collateral_token)?,
+                api.addr_validate(&custody_contract)?,
+                weight,
             )
         }
+        ExecuteMsg::FundFairnessFund {} => fund_fairness_fund(deps, info),
+        ExecuteMsg::ReportPriceCorrection {
+            liquidation_id,
+            compensation,
+        } => report_price_correction(deps, env, info, liquidation_id, compensation),
+        ExecuteMsg::DepositInterestBuffer { amount } => {
+            deposit_interest_buffer(deps, env, info, amount)
+        }
         ExecuteMsg::ExecuteEpochOperations {} => execute_epoch_operations(deps, env),
+        ExecuteMsg::ResetEpochProgress {} => reset_epoch_progress(deps, info),
         ExecuteMsg::UpdateEpochState {
             interest_buffer,
             distributed_interest,
-        } => update_epoch_state(deps, env, info, interest_buffer, distributed_interest),
-        ExecuteMsg::LockCollateral { collaterals } => lock_collateral(deps, info, collaterals),
-        ExecuteMsg::UnlockCollateral { collaterals } => {
-            unlock_collateral(deps, env, info, collaterals)
+            reserve_topup,
+        } => update_epoch_state(
+            deps,
+            env,
+            info,
+            interest_buffer,
+            distributed_interest,
+            reserve_topup,
+        ),
+        ExecuteMsg::HarvestProtocolYield {} => harvest_protocol_yield(deps, env),
+        ExecuteMsg::HarvestProtocolYieldHook {
+            prev_interest_buffer,
+            protocol_share,
+            pending_custodies,
+            harvested_amount,
+        } => harvest_protocol_yield_hook(
+            deps,
+            env,
+            info,
+            prev_interest_buffer,
+            protocol_share,
+            pending_custodies,
+            harvested_amount,
+        ),
+        ExecuteMsg::UnlockCollateralFromRepay {
+            borrower,
+            prev_loan_amount,
+            repay_amount,
+            sub_account,
+        } => {
+            let api = deps.api;
+            unlock_collateral_from_repay(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                prev_loan_amount,
+                repay_amount,
+                sub_account,
+            )
+        }
+        ExecuteMsg::LockCollateralFor {
+            borrower,
+            collaterals,
+        } => {
+            let api = deps.api;
+            lock_collateral_for(deps, env, info, api.addr_validate(&borrower)?, collaterals)
+        }
+        ExecuteMsg::RepayAuctionProceeds {
+            borrower,
+            sub_account,
+        } => {
+            let api = deps.api;
+            repay_auction_proceeds(deps, info, api.addr_validate(&borrower)?, sub_account)
+        }
+        ExecuteMsg::ReturnAuctionCollateral {
+            borrower,
+            sub_account,
+            collaterals,
+        } => {
+            let api = deps.api;
+            return_auction_collateral(
+                deps,
+                info,
+                api.addr_validate(&borrower)?,
+                sub_account,
+                collaterals,
+            )
+        }
+        ExecuteMsg::LockCollateral {
+            collaterals,
+            sub_account,
+        } => lock_collateral(deps, env, info, collaterals, sub_account),
+        ExecuteMsg::UnlockCollateral {
+            collaterals,
+            sub_account,
+        } => unlock_collateral(deps, env, info, collaterals, sub_account),
+        ExecuteMsg::UnlockToTargetLtv {
+            target_ltv,
+            sub_account,
+        } => unlock_to_target_ltv(deps, env, info, target_ltv, sub_account),
+        ExecuteMsg::CloseAndWithdraw { recipient } => {
+            let recipient_addr = deps.api.addr_validate(&recipient)?;
+            close_and_withdraw(deps, env, info, recipient_addr)
+        }
+        ExecuteMsg::CloseAndWithdrawHook {
+            borrower,
+            recipient,
+        } => {
+            let borrower_addr = deps.api.addr_validate(&borrower)?;
+            let recipient_addr = deps.api.addr_validate(&recipient)?;
+            close_and_withdraw_hook(deps, env, info, borrower_addr, recipient_addr)
+        }
+        ExecuteMsg::ReallocateCollateral {
+            collaterals,
+            from_sub_account,
+            to_sub_account,
+        } => reallocate_collateral(
+            deps,
+            env,
+            info,
+            collaterals,
+            from_sub_account,
+            to_sub_account,
+        ),
+        ExecuteMsg::SplitPosition {
+            collaterals,
+            debt_fraction,
+            from_sub_account,
+            to_sub_account,
+        } => split_position(
+            deps,
+            env,
+            info,
+            collaterals,
+            debt_fraction,
+            from_sub_account,
+            to_sub_account,
+        ),
+        ExecuteMsg::MigratePosition {
+            from_token,
+            to_token,
+            sub_account,
+        } => {
+            let api = deps.api;
+            migrate_position(
+                deps,
+                env,
+                info,
+                api.addr_validate(&from_token)?,
+                api.addr_validate(&to_token)?,
+                sub_account,
+            )
+        }
+        ExecuteMsg::TransferPosition {
+            recipient,
+            expires_at,
+        } => {
+            let api = deps.api;
+            transfer_position(deps, env, info, api.addr_validate(&recipient)?, expires_at)
+        }
+        ExecuteMsg::CancelPositionTransfer {} => cancel_position_transfer(deps, info),
+        ExecuteMsg::AcceptPosition { borrower } => {
+            let api = deps.api;
+            accept_position(deps, env, info, api.addr_validate(&borrower)?)
+        }
+        ExecuteMsg::DelegateLiquidation { keeper, max_bonus } => {
+            let api = deps.api;
+            delegate_liquidation(deps, info, api.addr_validate(&keeper)?, max_bonus)
+        }
+        ExecuteMsg::RevokeLiquidationDelegation {} => revoke_liquidation_delegation(deps, info),
+        ExecuteMsg::ClaimCompensation { liquidation_id } => {
+            claim_compensation(deps, info, liquidation_id)
         }
-        ExecuteMsg::LiquidateCollateral { borrower } => {
+        ExecuteMsg::ClaimAllRewards {} => claim_all_rewards(deps, env, info),
+        ExecuteMsg::ClaimAllRewardsHook {
+            borrower,
+            prev_balance,
+        } => {
+            let api = deps.api;
+            claim_all_rewards_hook(deps, env, info, api.addr_validate(&borrower)?, prev_balance)
+        }
+        ExecuteMsg::ClaimRewardsAndRepay {} => claim_rewards_and_repay(deps, env, info),
+        ExecuteMsg::ClaimRewardsAndRepayHook {
+            borrower,
+            prev_balance,
+        } => {
+            let api = deps.api;
+            claim_rewards_and_repay_hook(
+                deps,
+                env,
+                info,
+                api.addr_validate(&borrower)?,
+                prev_balance,
+            )
+        }
+        ExecuteMsg::LiquidateCollateral {
+            borrower,
+            sub_account,
+        } => {
+            let api = deps.api;
+            liquidate_collateral(deps, env, info, api.addr_validate(&borrower)?, sub_account)
+        }
+        ExecuteMsg::SettleBadDebt {
+            borrower,
+            sub_account,
+        } => {
             let api = deps.api;
-            liquidate_collateral(deps, env, info, api.addr_validate(&borrower)?)
+            settle_bad_debt(deps, env, info, api.addr_validate(&borrower)?, sub_account)
         }
     }
 }
@@ -150,6 +654,7 @@ pub fn execute(
 #[allow(clippy::too_many_arguments)]
 pub fn update_config(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     owner_addr: Option<Addr>,
     oracle_contract: Option<Addr>,
@@ -159,13 +664,97 @@ pub fn update_config(
     buffer_distribution_factor: Option<Decimal256>,
     anc_purchase_factor: Option<Decimal256>,
     epoch_period: Option<u64>,
-    price_timeframe: Option<u64>,
+    borrow_price_timeframe: Option<u64>,
+    liquidation_price_timeframe: Option<u64>,
+    max_anc_price: Option<Option<Decimal256>>,
+    buffer_target: Option<Uint256>,
+    surplus_recipient: Option<Addr>,
+    price_deviation_threshold: Option<Decimal256>,
+    max_collaterals_per_borrower: Option<u32>,
+    ordered_epoch_operations: Option<bool>,
+    dust_threshold: Option<Uint256>,
+    dust_tolerance: Option<Decimal256>,
+    collateral_converter: Option<Addr>,
+    soft_liquidation: Option<bool>,
+    buffer_backstop_limit: Option<Uint256>,
+    max_confidence_spread: Option<Decimal256>,
+    reserve_target: Option<Uint256>,
+    reserve_topup_factor: Option<Decimal256>,
+    epoch_requires_fresh_prices: Option<bool>,
+    fallback_oracle_contract: Option<Addr>,
+    min_custody_quorum: Option<Decimal256>,
+    same_block_collateral_delay: Option<bool>,
+    max_collateral_value_per_borrower: Option<Uint256>,
+    risk_admin: Option<Addr>,
+    epoch_custody_batch_size: Option<u32>,
+    anc_buyback_amm: Option<Addr>,
+    min_lock_amount: Option<Uint256>,
+    solvency_margin: Option<Decimal256>,
+    max_block_price_drop: Option<Decimal256>,
+    price_drop_cooldown: Option<u64>,
+    dispute_window: Option<u64>,
+    valuation_mode: Option<ValuationMode>,
+    default_limit: Option<u32>,
+    bad_debt_budget: Option<Uint256>,
+    deposit_rate_smoothing_epochs: Option<u64>,
+    skip_paused_collaterals: Option<bool>,
+    max_ltv_delta_per_epoch: Option<Decimal256>,
+    max_anc_purchase_per_epoch: Option<Uint256>,
+    stable_decimals: Option<u8>,
 ) -> Result<Response, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
 
-    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
-        return Err(ContractError::Unauthorized {});
-    }
+    let risk_update = max_collaterals_per_borrower.is_some()
+        || max_confidence_spread.is_some()
+        || max_collateral_value_per_borrower.is_some()
+        || max_block_price_drop.is_some()
+        || price_drop_cooldown.is_some()
+        || valuation_mode.is_some();
+    let owner_update = owner_addr.is_some()
+        || oracle_contract.is_some()
+        || liquidation_contract.is_some()
+        || threshold_deposit_rate.is_some()
+        || target_deposit_rate.is_some()
+        || buffer_distribution_factor.is_some()
+        || anc_purchase_factor.is_some()
+        || epoch_period.is_some()
+        || borrow_price_timeframe.is_some()
+        || liquidation_price_timeframe.is_some()
+        || max_anc_price.is_some()
+        || buffer_target.is_some()
+        || surplus_recipient.is_some()
+        || price_deviation_threshold.is_some()
+        || ordered_epoch_operations.is_some()
+        || dust_threshold.is_some()
+        || dust_tolerance.is_some()
+        || collateral_converter.is_some()
+        || soft_liquidation.is_some()
+        || buffer_backstop_limit.is_some()
+        || reserve_target.is_some()
+        || reserve_topup_factor.is_some()
+        || epoch_requires_fresh_prices.is_some()
+        || fallback_oracle_contract.is_some()
+        || min_custody_quorum.is_some()
+        || same_block_collateral_delay.is_some()
+        || risk_admin.is_some()
+        || epoch_custody_batch_size.is_some()
+        || anc_buyback_amm.is_some()
+        || min_lock_amount.is_some()
+        || solvency_margin.is_some()
+        || dispute_window.is_some()
+        || default_limit.is_some()
+        || bad_debt_budget.is_some()
+        || deposit_rate_smoothing_epochs.is_some()
+        || skip_paused_collaterals.is_some()
+        || max_ltv_delta_per_epoch.is_some()
+        || max_anc_purchase_per_epoch.is_some()
+        || stable_decimals.is_some();
+    assert_update_authority(
+        &deps.api.addr_canonicalize(info.sender.as_str())?,
+        &config,
+        owner_update,
+        risk_update,
+    )?;
 
     if let Some(owner_addr) = owner_addr {
         config.owner_addr = deps.api.addr_canonicalize(&owner_addr.to_string())?;
@@ -201,292 +790,2401 @@ pub fn update_config(
         config.epoch_period = epoch_period;
     }
 
-    if let Some(price_timeframe) = price_timeframe {
-        config.price_timeframe = price_timeframe;
+    if let Some(borrow_price_timeframe) = borrow_price_timeframe {
+        config.borrow_price_timeframe = borrow_price_timeframe;
+    }
+
+    if let Some(liquidation_price_timeframe) = liquidation_price_timeframe {
+        config.liquidation_price_timeframe = liquidation_price_timeframe;
+    }
+
+    assert_price_timeframes(
+        config.borrow_price_timeframe,
+        config.liquidation_price_timeframe,
+    )?;
+
+    if let Some(max_anc_price) = max_anc_price {
+        config.max_anc_price = max_anc_price;
+    }
+
+    if let Some(buffer_target) = buffer_target {
+        config.buffer_target = buffer_target;
+    }
+
+    if let Some(surplus_recipient) = surplus_recipient {
+        config.surplus_recipient = deps.api.addr_canonicalize(surplus_recipient.as_str())?;
     }
 
+    if let Some(price_deviation_threshold) = price_deviation_threshold {
+        config.price_deviation_threshold = price_deviation_threshold;
+    }
+
+    if let Some(max_collaterals_per_borrower) = max_collaterals_per_borrower {
+        config.max_collaterals_per_borrower = max_collaterals_per_borrower;
+    }
+
+    if let Some(ordered_epoch_operations) = ordered_epoch_operations {
+        config.ordered_epoch_operations = ordered_epoch_operations;
+    }
+
+    if let Some(dust_threshold) = dust_threshold {
+        config.dust_threshold = dust_threshold;
+    }
+
+    if let Some(dust_tolerance) = dust_tolerance {
+        config.dust_tolerance = dust_tolerance;
+    }
+
+    if let Some(collateral_converter) = collateral_converter {
+        config.collateral_converter =
+            Some(deps.api.addr_canonicalize(collateral_converter.as_str())?);
+    }
+
+    if let Some(soft_liquidation) = soft_liquidation {
+        config.soft_liquidation = soft_liquidation;
+    }
+
+    if let Some(buffer_backstop_limit) = buffer_backstop_limit {
+        config.buffer_backstop_limit = buffer_backstop_limit;
+    }
+
+    if let Some(max_confidence_spread) = max_confidence_spread {
+        config.max_confidence_spread = Some(max_confidence_spread);
+    }
+
+    if let Some(reserve_target) = reserve_target {
+        config.reserve_target = reserve_target;
+    }
+
+    if let Some(reserve_topup_factor) = reserve_topup_factor {
+        assert_reserve_topup_factor(reserve_topup_factor)?;
+        config.reserve_topup_factor = reserve_topup_factor;
+    }
+
+    if let Some(epoch_requires_fresh_prices) = epoch_requires_fresh_prices {
+        config.epoch_requires_fresh_prices = epoch_requires_fresh_prices;
+    }
+
+    if let Some(fallback_oracle_contract) = fallback_oracle_contract {
+        config.fallback_oracle_contract = Some(
+            deps.api
+                .addr_canonicalize(fallback_oracle_contract.as_str())?,
+        );
+    }
+
+    if let Some(min_custody_quorum) = min_custody_quorum {
+        assert_min_custody_quorum(min_custody_quorum)?;
+        config.min_custody_quorum = min_custody_quorum;
+    }
+
+    if let Some(same_block_collateral_delay) = same_block_collateral_delay {
+        config.same_block_collateral_delay = same_block_collateral_delay;
+    }
+
+    if let Some(max_collateral_value_per_borrower) = max_collateral_value_per_borrower {
+        config.max_collateral_value_per_borrower = Some(max_collateral_value_per_borrower);
+    }
+
+    if let Some(risk_admin) = risk_admin {
+        config.risk_admin = Some(deps.api.addr_canonicalize(risk_admin.as_str())?);
+    }
+
+    if epoch_custody_batch_size.is_some() {
+        assert_epoch_custody_batch_size(epoch_custody_batch_size)?;
+        config.epoch_custody_batch_size = epoch_custody_batch_size;
+    }
+
+    if let Some(anc_buyback_amm) = anc_buyback_amm {
+        config.anc_buyback_amm = Some(deps.api.addr_canonicalize(anc_buyback_amm.as_str())?);
+    }
+
+    if let Some(min_lock_amount) = min_lock_amount {
+        config.min_lock_amount = min_lock_amount;
+    }
+
+    if let Some(solvency_margin) = solvency_margin {
+        config.solvency_margin = solvency_margin;
+    }
+
+    if let Some(max_block_price_drop) = max_block_price_drop {
+        config.max_block_price_drop = Some(max_block_price_drop);
+    }
+
+    if let Some(price_drop_cooldown) = price_drop_cooldown {
+        config.price_drop_cooldown = price_drop_cooldown;
+    }
+
+    if let Some(dispute_window) = dispute_window {
+        config.dispute_window = dispute_window;
+    }
+
+    if let Some(valuation_mode) = valuation_mode {
+        config.valuation_mode = valuation_mode;
+    }
+
+    if let Some(default_limit) = default_limit {
+        assert_default_limit(default_limit)?;
+        config.default_limit = default_limit;
+    }
+
+    if let Some(bad_debt_budget) = bad_debt_budget {
+        config.bad_debt_budget = bad_debt_budget;
+    }
+
+    if let Some(deposit_rate_smoothing_epochs) = deposit_rate_smoothing_epochs {
+        assert_deposit_rate_smoothing(deposit_rate_smoothing_epochs)?;
+        config.deposit_rate_smoothing_epochs = deposit_rate_smoothing_epochs;
+    }
+
+    if let Some(skip_paused_collaterals) = skip_paused_collaterals {
+        config.skip_paused_collaterals = skip_paused_collaterals;
+    }
+
+    if let Some(max_ltv_delta_per_epoch) = max_ltv_delta_per_epoch {
+        config.max_ltv_delta_per_epoch = Some(max_ltv_delta_per_epoch);
+    }
+
+    if let Some(max_anc_purchase_per_epoch) = max_anc_purchase_per_epoch {
+        config.max_anc_purchase_per_epoch = Some(max_anc_purchase_per_epoch);
+    }
+
+    if let Some(stable_decimals) = stable_decimals {
+        assert_stable_decimals(stable_decimals)?;
+        config.stable_decimals = stable_decimals;
+    }
+
+    assert_contract_addresses(
+        &deps.api.addr_canonicalize(env.contract.address.as_str())?,
+        &config,
+    )?;
+
     store_config(deps.storage, &config)?;
 
     Ok(Response::new().add_attributes(vec![attr("action", "update_config")]))
 }
 
-pub fn register_whitelist(
+pub fn set_liquidation_freeze(
     deps: DepsMut,
     info: MessageInfo,
-    name: String,
-    symbol: String,
-    collateral_token: Addr,
-    custody_contract: Addr,
-    max_ltv: Decimal256,
+    frozen: bool,
 ) -> Result<Response, ContractError> {
-    let config: Config = read_config(deps.storage)?;
+    let mut config: Config = read_config(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
         return Err(ContractError::Unauthorized {});
     }
 
-    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
-    if read_whitelist_elem(deps.storage, &collateral_token_raw).is_ok() {
-        return Err(ContractError::TokenAlreadyRegistered {});
-    }
-
-    store_whitelist_elem(
-        deps.storage,
-        &collateral_token_raw,
-        &WhitelistElem {
-            name: name.to_string(),
-            symbol: symbol.to_string(),
-            custody_contract: deps.api.addr_canonicalize(custody_contract.as_str())?,
-            max_ltv,
-        },
-    )?;
+    config.liquidation_frozen = frozen;
+    store_config(deps.storage, &config)?;
 
     Ok(Response::new().add_attributes(vec![
-        attr("action", "register_whitelist"),
-        attr("name", name),
-        attr("symbol", symbol),
-        attr("collateral_token", collateral_token),
-        attr("custody_contract", custody_contract),
-        attr("LTV", max_ltv.to_string()),
+        attr("action", "set_liquidation_freeze"),
+        attr("frozen", frozen.to_string()),
     ]))
 }
 
-pub fn update_whitelist(
+pub fn rotate_oracle(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
-    collateral_token: Addr,
-    custody_contract: Option<Addr>,
-    max_ltv: Option<Decimal256>,
+    new_oracle: Addr,
 ) -> Result<Response, ContractError> {
-    let config: Config = read_config(deps.storage)?;
+    let mut config: Config = read_config(deps.storage)?;
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
         return Err(ContractError::Unauthorized {});
     }
 
-    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
-    let mut whitelist_elem: WhitelistElem =
-        read_whitelist_elem(deps.storage, &collateral_token_raw)?;
-
-    if let Some(custody_contract) = custody_contract {
-        whitelist_elem.custody_contract = deps.api.addr_canonicalize(custody_contract.as_str())?;
+    // The fallback oracle must not cover the prices missing from the new one
+    let validation_config = Config {
+        fallback_oracle_contract: None,
+        ..config.clone()
+    };
+    let mut stale_collaterals: Vec<String> = vec![];
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        if query_collateral_price(
+            deps.as_ref(),
+            &validation_config,
+            new_oracle.clone(),
+            &collateral_token,
+            &elem,
+            Some(env.block.time.seconds()),
+            config.borrow_price_timeframe,
+        )
+        .is_err()
+        {
+            stale_collaterals.push(deps.api.addr_humanize(&collateral_token)?.to_string());
+        }
     }
 
-    if let Some(max_ltv) = max_ltv {
-        whitelist_elem.max_ltv = max_ltv;
+    if !stale_collaterals.is_empty() {
+        return Err(ContractError::StalePrices(stale_collaterals.join(",")));
     }
 
-    store_whitelist_elem(deps.storage, &collateral_token_raw, &whitelist_elem)?;
+    config.oracle_contract = deps.api.addr_canonicalize(new_oracle.as_str())?;
+    store_config(deps.storage, &config)?;
 
     Ok(Response::new().add_attributes(vec![
-        attr("action", "update_whitelist"),
-        attr("collateral_token", collateral_token),
-        attr(
-            "custody_contract",
-            deps.api.addr_humanize(&whitelist_elem.custody_contract)?,
-        ),
-        attr("LTV", whitelist_elem.max_ltv.to_string()),
+        attr("action", "rotate_oracle"),
+        attr("new_oracle", new_oracle),
     ]))
 }
 
-pub fn execute_epoch_operations(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+pub fn sweep_tokens(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset: AssetInfo,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
     let config: Config = read_config(deps.storage)?;
-    let state: EpochState = read_epoch_state(deps.storage)?;
-    if env.block.height < state.last_executed_height + config.epoch_period {
-        return Err(ContractError::EpochNotPassed(state.last_executed_height));
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // The interest buffer is kept in stable denom and whitelisted
+    // collaterals are tracked by the custody contracts or the overseer
+    let (asset_name, amount, message) = match asset {
+        AssetInfo::NativeToken { denom } => {
+            if denom == config.stable_denom
+                || read_all_whitelist_elems(deps.storage)?
+                    .iter()
+                    .any(|(_, elem)| {
+                        elem.token_type
+                            == TokenType::Native {
+                                denom: denom.clone(),
+                            }
+                    })
+            {
+                return Err(ContractError::SweepTrackedAsset(denom));
+            }
+
+            let amount = query_balance(deps.as_ref(), env.contract.address, denom.to_string())?;
+            let message = CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: denom.to_string(),
+                        amount: amount.into(),
+                    },
+                )?],
+            });
+            (denom, amount, message)
+        }
+        AssetInfo::Token { contract_addr } => {
+            let token_raw = deps.api.addr_canonicalize(&contract_addr)?;
+            if read_whitelist_elem(deps.storage, &token_raw).is_ok() {
+                return Err(ContractError::SweepTrackedAsset(contract_addr));
+            }
+
+            let amount = query_token_balance(
+                deps.as_ref(),
+                deps.api.addr_validate(&contract_addr)?,
+                env.contract.address,
+            )?;
+            let message = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: amount.into(),
+                })?,
+            });
+            (contract_addr, amount, message)
+        }
+    };
+
+    Ok(Response::new().add_message(message).add_attributes(vec![
+        attr("action", "sweep"),
+        attr("asset", asset_name),
+        attr("recipient", recipient),
+        attr("amount", amount),
+    ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn register_whitelist(
+    deps: DepsMut,
+    info: MessageInfo,
+    name: String,
+    symbol: String,
+    collateral_token: Addr,
+    custody_contract: Addr,
+    max_ltv: Decimal256,
+    quote_denom: Option<String>,
+    liquidation_premium: Option<Decimal256>,
+    correlation_group: Option<String>,
+    min_lock_amount: Option<Uint256>,
+    borrow_enabled_from: Option<u64>,
+    borrow_disabled_from: Option<u64>,
+    liquidation_contract: Option<Addr>,
+    decimals: Option<u8>,
+    exchange_rate_source: Option<Addr>,
+    borrow_rate_premium: Option<Decimal256>,
+    token_type: Option<TokenType>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    assert_max_ltv(max_ltv)?;
+    assert_borrow_window(borrow_enabled_from, borrow_disabled_from)?;
+    let decimals = decimals.unwrap_or(DEFAULT_COLLATERAL_DECIMALS);
+    assert_collateral_decimals(decimals)?;
+
+    // The interest buffer is kept in the stable denom
+    let token_type = token_type.unwrap_or(TokenType::Cw20);
+    if let TokenType::Native { denom } = &token_type {
+        if *denom == config.stable_denom {
+            return Err(ContractError::NativeStableCollateral(denom.to_string()));
+        }
+    }
+
+    if let Some(quote_denom) = &quote_denom {
+        assert_quote_price(deps.as_ref(), &config, quote_denom)?;
+    }
+
+    if let Some(liquidation_premium) = liquidation_premium {
+        assert_liquidation_premium(liquidation_premium)?;
+    }
+
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    if read_whitelist_elem(deps.storage, &collateral_token_raw).is_ok() {
+        return Err(ContractError::TokenAlreadyRegistered {});
+    }
+
+    let liquidation_contract = liquidation_contract
+        .map(|c| deps.api.addr_canonicalize(c.as_str()))
+        .transpose()?;
+    let exchange_rate_source = exchange_rate_source
+        .map(|c| deps.api.addr_canonicalize(c.as_str()))
+        .transpose()?;
+
+    store_whitelist_elem(
+        deps.storage,
+        &collateral_token_raw,
+        &WhitelistElem {
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            custody_contracts: vec![CustodyContractElem {
+                custody_contract: deps.api.addr_canonicalize(custody_contract.as_str())?,
+                weight: Decimal256::one(),
+            }],
+            max_ltv,
+            quote_denom,
+            liquidation_premium,
+            correlation_group,
+            min_lock_amount,
+            borrow_enabled_from,
+            borrow_disabled_from,
+            liquidation_contract,
+            decimals,
+            exchange_rate_source,
+            borrow_rate_premium,
+            token_type,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "register_whitelist"),
+        attr("name", name),
+        attr("symbol", symbol),
+        attr("collateral_token", collateral_token),
+        attr("custody_contract", custody_contract),
+        attr("LTV", max_ltv.to_string()),
+    ]))
+}
+
+/// Register the whitelist entry along with the oracle messages seeding its price,
+/// a failure of any of them reverts the whole transaction
+#[allow(clippy::too_many_arguments)]
+pub fn register_whitelist_with_price(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    name: String,
+    symbol: String,
+    collateral_token: Addr,
+    custody_contract: Addr,
+    max_ltv: Decimal256,
+    quote_denom: Option<String>,
+    liquidation_premium: Option<Decimal256>,
+    correlation_group: Option<String>,
+    min_lock_amount: Option<Uint256>,
+    borrow_enabled_from: Option<u64>,
+    borrow_disabled_from: Option<u64>,
+    liquidation_contract: Option<Addr>,
+    decimals: Option<u8>,
+    exchange_rate_source: Option<Addr>,
+    borrow_rate_premium: Option<Decimal256>,
+    token_type: Option<TokenType>,
+    initial_price: Decimal256,
+    feeder: Addr,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+
+    let res = register_whitelist(
+        deps,
+        info,
+        name,
+        symbol,
+        collateral_token.clone(),
+        custody_contract,
+        max_ltv,
+        quote_denom,
+        liquidation_premium,
+        correlation_group,
+        min_lock_amount,
+        borrow_enabled_from,
+        borrow_disabled_from,
+        liquidation_contract,
+        decimals,
+        exchange_rate_source,
+        borrow_rate_premium,
+        token_type,
+    )?;
+
+    Ok(res
+        .add_messages(vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: oracle_contract.to_string(),
+                funds: vec![],
+                msg: to_binary(&OracleExecuteMsg::RegisterFeeder {
+                    asset: collateral_token.to_string(),
+                    feeder: env.contract.address.to_string(),
+                })?,
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: oracle_contract.to_string(),
+                funds: vec![],
+                msg: to_binary(&OracleExecuteMsg::FeedPrice {
+                    prices: vec![(collateral_token.to_string(), initial_price)],
+                })?,
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: oracle_contract.to_string(),
+                funds: vec![],
+                msg: to_binary(&OracleExecuteMsg::RegisterFeeder {
+                    asset: collateral_token.to_string(),
+                    feeder: feeder.to_string(),
+                })?,
+            }),
+        ])
+        .add_attributes(vec![
+            attr("initial_price", initial_price.to_string()),
+            attr("feeder", feeder),
+        ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_whitelist(
+    deps: DepsMut,
+    info: MessageInfo,
+    collateral_token: Addr,
+    custody_contract: Option<Addr>,
+    max_ltv: Option<Decimal256>,
+    quote_denom: Option<String>,
+    liquidation_premium: Option<Decimal256>,
+    correlation_group: Option<String>,
+    min_lock_amount: Option<Uint256>,
+    borrow_enabled_from: Option<u64>,
+    borrow_disabled_from: Option<u64>,
+    liquidation_contract: Option<Addr>,
+    decimals: Option<u8>,
+    exchange_rate_source: Option<Addr>,
+    borrow_rate_premium: Option<Decimal256>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    assert_update_authority(
+        &deps.api.addr_canonicalize(info.sender.as_str())?,
+        &config,
+        custody_contract.is_some()
+            || quote_denom.is_some()
+            || min_lock_amount.is_some()
+            || borrow_enabled_from.is_some()
+            || borrow_disabled_from.is_some()
+            || liquidation_contract.is_some()
+            || decimals.is_some()
+            || exchange_rate_source.is_some(),
+        max_ltv.is_some()
+            || liquidation_premium.is_some()
+            || correlation_group.is_some()
+            || borrow_rate_premium.is_some(),
+    )?;
+
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let (whitelist_elem, ltv_change) = compute_whitelist_update(
+        deps.as_ref(),
+        &config,
+        &collateral_token,
+        custody_contract,
+        max_ltv,
+        quote_denom,
+        liquidation_premium,
+        correlation_group,
+        min_lock_amount,
+        borrow_enabled_from,
+        borrow_disabled_from,
+        liquidation_contract,
+        decimals,
+        exchange_rate_source,
+        borrow_rate_premium,
+    )?;
+    store_whitelist_elem(deps.storage, &collateral_token_raw, &whitelist_elem)?;
+    if let Some(ltv_change) = ltv_change {
+        store_ltv_change(deps.storage, &collateral_token_raw, &ltv_change)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_whitelist"),
+        attr("collateral_token", collateral_token),
+        attr(
+            "custody_contract",
+            deps.api
+                .addr_humanize(&whitelist_elem.custody_contracts[0].custody_contract)?,
+        ),
+        attr("LTV", whitelist_elem.max_ltv.to_string()),
+    ]))
+}
+
+/// Every update is validated before any gets stored,
+/// so a single invalid entry rejects the whole batch
+pub fn update_whitelist_batch(
+    deps: DepsMut,
+    info: MessageInfo,
+    updates: Vec<UpdateWhitelistEntry>,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    assert_update_authority(
+        &deps.api.addr_canonicalize(info.sender.as_str())?,
+        &config,
+        updates.iter().any(|u| {
+            u.custody_contract.is_some()
+                || u.quote_denom.is_some()
+                || u.min_lock_amount.is_some()
+                || u.borrow_enabled_from.is_some()
+                || u.borrow_disabled_from.is_some()
+                || u.liquidation_contract.is_some()
+                || u.decimals.is_some()
+                || u.exchange_rate_source.is_some()
+        }),
+        updates.iter().any(|u| {
+            u.max_ltv.is_some()
+                || u.liquidation_premium.is_some()
+                || u.correlation_group.is_some()
+                || u.borrow_rate_premium.is_some()
+        }),
+    )?;
+
+    let mut whitelist_elems: Vec<(CanonicalAddr, WhitelistElem, Option<LtvChange>)> = vec![];
+    let mut attributes = vec![attr("action", "update_whitelist_batch")];
+    for update in updates {
+        let collateral_token = deps.api.addr_validate(&update.collateral_token)?;
+        let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+        if whitelist_elems
+            .iter()
+            .any(|(token, _, _)| *token == collateral_token_raw)
+        {
+            return Err(ContractError::DuplicateWhitelistUpdate(
+                collateral_token.to_string(),
+            ));
+        }
+
+        let (whitelist_elem, ltv_change) = compute_whitelist_update(
+            deps.as_ref(),
+            &config,
+            &collateral_token,
+            optional_addr_validate(deps.api, update.custody_contract)?,
+            update.max_ltv,
+            update.quote_denom,
+            update.liquidation_premium,
+            update.correlation_group,
+            update.min_lock_amount,
+            update.borrow_enabled_from,
+            update.borrow_disabled_from,
+            optional_addr_validate(deps.api, update.liquidation_contract)?,
+            update.decimals,
+            optional_addr_validate(deps.api, update.exchange_rate_source)?,
+            update.borrow_rate_premium,
+        )?;
+
+        attributes.push(attr(
+            collateral_token.as_str(),
+            whitelist_elem.max_ltv.to_string(),
+        ));
+        whitelist_elems.push((collateral_token_raw, whitelist_elem, ltv_change));
+    }
+
+    for (collateral_token_raw, whitelist_elem, ltv_change) in whitelist_elems.iter() {
+        store_whitelist_elem(deps.storage, collateral_token_raw, whitelist_elem)?;
+        if let Some(ltv_change) = ltv_change {
+            store_ltv_change(deps.storage, collateral_token_raw, ltv_change)?;
+        }
+    }
+
+    Ok(Response::new().add_attributes(attributes))
+}
+
+/// Risk parameters are updated by the risk admin when one is set and by
+/// the owner otherwise, every other parameter only by the owner
+fn assert_update_authority(
+    sender: &CanonicalAddr,
+    config: &Config,
+    owner_update: bool,
+    risk_update: bool,
+) -> Result<(), ContractError> {
+    let risk_authority = config.risk_admin.as_ref().unwrap_or(&config.owner_addr);
+    let authorized = if owner_update || risk_update {
+        (!owner_update || *sender == config.owner_addr)
+            && (!risk_update || sender == risk_authority)
+    } else {
+        *sender == config.owner_addr || sender == risk_authority
+    };
+
+    if !authorized {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(())
+}
+
+/// Validated whitelist elem with the update applied, not stored yet
+#[allow(clippy::too_many_arguments)]
+fn compute_whitelist_update(
+    deps: Deps,
+    config: &Config,
+    collateral_token: &Addr,
+    custody_contract: Option<Addr>,
+    max_ltv: Option<Decimal256>,
+    quote_denom: Option<String>,
+    liquidation_premium: Option<Decimal256>,
+    correlation_group: Option<String>,
+    min_lock_amount: Option<Uint256>,
+    borrow_enabled_from: Option<u64>,
+    borrow_disabled_from: Option<u64>,
+    liquidation_contract: Option<Addr>,
+    decimals: Option<u8>,
+    exchange_rate_source: Option<Addr>,
+    borrow_rate_premium: Option<Decimal256>,
+) -> Result<(WhitelistElem, Option<LtvChange>), ContractError> {
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let mut whitelist_elem: WhitelistElem =
+        read_whitelist_elem(deps.storage, &collateral_token_raw)
+            .map_err(|_| ContractError::CollateralNotWhitelisted(collateral_token.to_string()))?;
+
+    // Replaces the primary custody contract
+    if let Some(custody_contract) = custody_contract {
+        whitelist_elem.custody_contracts[0].custody_contract =
+            deps.api.addr_canonicalize(custody_contract.as_str())?;
+    }
+
+    let mut ltv_change: Option<LtvChange> = None;
+    if let Some(max_ltv) = max_ltv {
+        assert_max_ltv(max_ltv)?;
+        ltv_change = compute_ltv_change(
+            deps,
+            config,
+            collateral_token,
+            &collateral_token_raw,
+            whitelist_elem.max_ltv,
+            max_ltv,
+        )?;
+        whitelist_elem.max_ltv = max_ltv;
+    }
+
+    if let Some(quote_denom) = quote_denom {
+        assert_quote_price(deps, config, &quote_denom)?;
+        whitelist_elem.quote_denom = Some(quote_denom);
+    }
+
+    if let Some(liquidation_premium) = liquidation_premium {
+        assert_liquidation_premium(liquidation_premium)?;
+        whitelist_elem.liquidation_premium = Some(liquidation_premium);
+    }
+
+    if let Some(correlation_group) = correlation_group {
+        whitelist_elem.correlation_group = Some(correlation_group);
+    }
+
+    if let Some(min_lock_amount) = min_lock_amount {
+        whitelist_elem.min_lock_amount = Some(min_lock_amount);
+    }
+
+    if let Some(borrow_enabled_from) = borrow_enabled_from {
+        whitelist_elem.borrow_enabled_from = Some(borrow_enabled_from);
+    }
+
+    if let Some(borrow_disabled_from) = borrow_disabled_from {
+        whitelist_elem.borrow_disabled_from = Some(borrow_disabled_from);
+    }
+
+    if let Some(liquidation_contract) = liquidation_contract {
+        whitelist_elem.liquidation_contract =
+            Some(deps.api.addr_canonicalize(liquidation_contract.as_str())?);
+    }
+
+    if let Some(decimals) = decimals {
+        assert_collateral_decimals(decimals)?;
+        whitelist_elem.decimals = decimals;
+    }
+
+    if let Some(exchange_rate_source) = exchange_rate_source {
+        whitelist_elem.exchange_rate_source =
+            Some(deps.api.addr_canonicalize(exchange_rate_source.as_str())?);
+    }
+
+    if let Some(borrow_rate_premium) = borrow_rate_premium {
+        whitelist_elem.borrow_rate_premium = Some(borrow_rate_premium);
+    }
+
+    assert_borrow_window(
+        whitelist_elem.borrow_enabled_from,
+        whitelist_elem.borrow_disabled_from,
+    )?;
+
+    Ok((whitelist_elem, ltv_change))
+}
+
+/// `max_ltv` change of the collateral summed over the updates of the
+/// current epoch, which cannot exceed `max_ltv_delta_per_epoch`
+fn compute_ltv_change(
+    deps: Deps,
+    config: &Config,
+    collateral_token: &Addr,
+    collateral_token_raw: &CanonicalAddr,
+    prev_ltv: Decimal256,
+    max_ltv: Decimal256,
+) -> Result<Option<LtvChange>, ContractError> {
+    let max_ltv_delta = match config.max_ltv_delta_per_epoch {
+        Some(max_ltv_delta) => max_ltv_delta,
+        None => return Ok(None),
+    };
+
+    // The cumulative change is reset by every executed epoch
+    let epoch_height = read_epoch_state(deps.storage)?.last_executed_height;
+    let prev_delta = match read_ltv_change(deps.storage, collateral_token_raw)? {
+        Some(ltv_change) if ltv_change.epoch_height == epoch_height => ltv_change.cumulative_delta,
+        _ => Decimal256::zero(),
+    };
+
+    let delta = if max_ltv > prev_ltv {
+        max_ltv - prev_ltv
+    } else {
+        prev_ltv - max_ltv
+    };
+    let cumulative_delta = prev_delta + delta;
+    if cumulative_delta > max_ltv_delta {
+        return Err(ContractError::MaxLtvDeltaExceeded(
+            collateral_token.to_string(),
+            if prev_delta < max_ltv_delta {
+                max_ltv_delta - prev_delta
+            } else {
+                Decimal256::zero()
+            },
+        ));
+    }
+
+    Ok(Some(LtvChange {
+        epoch_height,
+        cumulative_delta,
+    }))
+}
+
+pub fn remove_whitelist(
+    deps: DepsMut,
+    info: MessageInfo,
+    collateral_token: Addr,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let whitelist_elem = read_whitelist_elem(deps.storage, &collateral_token_raw)?;
+
+    // Positions holding the collateral could not be valued anymore. The
+    // custody contracts count deposits the overseer counter may predate
+    let mut still_locked = !read_total_locked(deps.storage, &collateral_token_raw).is_zero();
+    for elem in whitelist_elem.custody_contracts.iter() {
+        still_locked = still_locked
+            || !query_custody_total_balance(
+                deps.as_ref(),
+                deps.api.addr_humanize(&elem.custody_contract)?,
+            )?
+            .is_zero();
+    }
+    if still_locked {
+        return Err(ContractError::CollateralStillLocked(
+            collateral_token.to_string(),
+        ));
+    }
+
+    remove_whitelist_elem(deps.storage, &collateral_token_raw);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "remove_whitelist"),
+        attr("collateral_token", collateral_token),
+    ]))
+}
+
+/// Liquidations may use staler prices than borrows, never fresher ones
+fn assert_price_timeframes(
+    borrow_price_timeframe: u64,
+    liquidation_price_timeframe: u64,
+) -> Result<(), ContractError> {
+    if liquidation_price_timeframe < borrow_price_timeframe {
+        return Err(ContractError::InvalidPriceTimeframe {});
+    }
+
+    Ok(())
+}
+
+// Queries and messages between the overseer and the contracts it calls
+// must not loop back into itself or reach the wrong counterpart
+fn assert_contract_addresses(
+    contract_addr: &CanonicalAddr,
+    config: &Config,
+) -> Result<(), ContractError> {
+    let mut contracts = vec![
+        ("oracle_contract", &config.oracle_contract),
+        ("market_contract", &config.market_contract),
+        ("liquidation_contract", &config.liquidation_contract),
+        ("collector_contract", &config.collector_contract),
+    ];
+    if let Some(fallback_oracle_contract) = &config.fallback_oracle_contract {
+        contracts.push(("fallback_oracle_contract", fallback_oracle_contract));
+    }
+
+    for (i, (name, addr)) in contracts.iter().enumerate() {
+        if *addr == contract_addr {
+            return Err(ContractError::SelfReferentialAddress(name.to_string()));
+        }
+
+        if let Some((other, _)) = contracts[i + 1..].iter().find(|(_, other)| other == addr) {
+            return Err(ContractError::DuplicateContractAddress(
+                name.to_string(),
+                other.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn assert_reserve_topup_factor(reserve_topup_factor: Decimal256) -> Result<(), ContractError> {
+    if reserve_topup_factor > Decimal256::one() {
+        return Err(ContractError::InvalidReserveTopupFactor {});
+    }
+
+    Ok(())
+}
+
+fn assert_min_custody_quorum(min_custody_quorum: Decimal256) -> Result<(), ContractError> {
+    if min_custody_quorum > Decimal256::one() {
+        return Err(ContractError::InvalidMinCustodyQuorum {});
+    }
+
+    Ok(())
+}
+
+fn assert_deposit_rate_smoothing(smoothing_epochs: u64) -> Result<(), ContractError> {
+    if smoothing_epochs == 0 || smoothing_epochs > MAX_EPOCH_RATE_HISTORY {
+        return Err(ContractError::InvalidDepositRateSmoothing(
+            MAX_EPOCH_RATE_HISTORY,
+        ));
+    }
+
+    Ok(())
+}
+
+fn assert_default_limit(default_limit: u32) -> Result<(), ContractError> {
+    if default_limit == 0 || default_limit > MAX_LIMIT {
+        return Err(ContractError::InvalidDefaultLimit(MAX_LIMIT));
+    }
+
+    Ok(())
+}
+
+fn assert_epoch_custody_batch_size(batch_size: Option<u32>) -> Result<(), ContractError> {
+    if batch_size == Some(0) {
+        return Err(ContractError::InvalidEpochCustodyBatchSize {});
+    }
+
+    Ok(())
+}
+
+// A collateral cannot back loans worth more than itself
+fn assert_max_ltv(max_ltv: Decimal256) -> Result<(), ContractError> {
+    if max_ltv >= Decimal256::one() {
+        return Err(ContractError::InvalidMaxLtv {});
+    }
+
+    Ok(())
+}
+
+fn assert_liquidation_premium(liquidation_premium: Decimal256) -> Result<(), ContractError> {
+    let max_liquidation_premium = Decimal256::percent(MAX_LIQUIDATION_PREMIUM);
+    if liquidation_premium > max_liquidation_premium {
+        return Err(ContractError::InvalidLiquidationPremium(
+            max_liquidation_premium,
+        ));
+    }
+
+    Ok(())
+}
+
+fn assert_borrow_window(
+    borrow_enabled_from: Option<u64>,
+    borrow_disabled_from: Option<u64>,
+) -> Result<(), ContractError> {
+    if let (Some(enabled_from), Some(disabled_from)) = (borrow_enabled_from, borrow_disabled_from) {
+        if enabled_from >= disabled_from {
+            return Err(ContractError::InvalidBorrowWindow {});
+        }
+    }
+
+    Ok(())
+}
+
+fn assert_stable_decimals(decimals: u8) -> Result<(), ContractError> {
+    if decimals > MAX_STABLE_DECIMALS {
+        return Err(ContractError::InvalidStableDecimals(decimals));
+    }
+
+    Ok(())
+}
+
+fn assert_collateral_decimals(decimals: u8) -> Result<(), ContractError> {
+    if decimals > MAX_COLLATERAL_DECIMALS {
+        return Err(ContractError::InvalidCollateralDecimals(decimals));
+    }
+
+    Ok(())
+}
+
+// Collaterals quoted in another asset are valued through its stable_denom price
+fn assert_quote_price(deps: Deps, config: &Config, quote_denom: &str) -> Result<(), ContractError> {
+    query_price(
+        deps,
+        deps.api.addr_humanize(&config.oracle_contract)?,
+        quote_denom.to_string(),
+        config.stable_denom.to_string(),
+        None,
+    )
+    .map_err(|_| ContractError::NoQuotePrice(quote_denom.to_string()))?;
+
+    Ok(())
+}
+
+pub fn register_custody_contract(
+    deps: DepsMut,
+    info: MessageInfo,
+    collateral_token: Addr,
+    custody_contract: Addr,
+    weight: Decimal256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if weight.is_zero() {
+        return Err(ContractError::InvalidCustodyWeight {});
+    }
+
+    let collateral_token_raw = deps.api.addr_canonicalize(collateral_token.as_str())?;
+    let mut whitelist_elem: WhitelistElem =
+        read_whitelist_elem(deps.storage, &collateral_token_raw)?;
+
+    let custody_contract_raw = deps.api.addr_canonicalize(custody_contract.as_str())?;
+    if let Some(elem) = whitelist_elem
+        .custody_contracts
+        .iter_mut()
+        .find(|c| c.custody_contract == custody_contract_raw)
+    {
+        elem.weight = weight;
+    } else {
+        whitelist_elem.custody_contracts.push(CustodyContractElem {
+            custody_contract: custody_contract_raw,
+            weight,
+        });
+    }
+
+    store_whitelist_elem(deps.storage, &collateral_token_raw, &whitelist_elem)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "register_custody_contract"),
+        attr("collateral_token", collateral_token),
+        attr("custody_contract", custody_contract),
+        attr("weight", weight.to_string()),
+    ]))
+}
+
+/// The deposit earns the deposit rate until the next epoch, which is
+/// why the buffer cannot be deposited once that epoch is due
+pub fn deposit_interest_buffer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let state: EpochState = read_epoch_state(deps.storage)?;
+    if env.block.height >= state.last_executed_height + config.epoch_period {
+        return Err(ContractError::BufferNeededForEpoch {});
+    }
+
+    let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
+    if amount > interest_buffer {
+        return Err(ContractError::InsufficientInterestBuffer(
+            interest_buffer.into(),
+        ));
+    }
+
+    let deposited_amount = read_buffer_deposit(deps.storage)?;
+    store_buffer_deposit(deps.storage, &(deposited_amount + amount))?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: deps.api.addr_humanize(&config.market_contract)?.to_string(),
+            funds: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: amount.into(),
+                },
+            )?],
+            msg: to_binary(&MarketExecuteMsg::DepositStable {})?,
+        }))
+        .add_attributes(vec![
+            attr("action", "deposit_interest_buffer"),
+            attr("amount", amount),
+        ]))
+}
+
+/// Redeem the aTerra position of the deposited interest buffer as far as
+/// the market liquidity allows, then execute the epoch operations again
+/// with the stables back. The rest stays deposited until the next epoch
+fn redeem_buffer_deposit(
+    deps: DepsMut,
+    env: Env,
+    config: &Config,
+) -> Result<Response, ContractError> {
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+    let aterra_contract = query_aterra_contract(deps.as_ref(), market_contract.clone())?;
+    let held_amount = query_token_balance(
+        deps.as_ref(),
+        aterra_contract.clone(),
+        env.contract.address.clone(),
+    )?;
+    let aterra_amount = std::cmp::min(
+        held_amount,
+        query_max_redeemable(
+            deps.as_ref(),
+            market_contract.clone(),
+            env.contract.address.clone(),
+        )?,
+    );
+
+    let left_deposit = if held_amount.is_zero() {
+        Uint256::zero()
+    } else {
+        read_buffer_deposit(deps.storage)?
+            * Decimal256::from_ratio(held_amount - aterra_amount, held_amount)
+    };
+    store_buffer_deposit(deps.storage, &left_deposit)?;
+    store_buffer_redeem_height(deps.storage, env.block.height)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !aterra_amount.is_zero() {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: aterra_contract.to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: market_contract.to_string(),
+                amount: aterra_amount.into(),
+                msg: to_binary(&MarketCw20HookMsg::RedeemStable {})?,
+            })?,
+        }));
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::ExecuteEpochOperations {})?,
+    }));
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "redeem_interest_buffer"),
+        attr("aterra_amount", aterra_amount),
+        attr("left_deposit", left_deposit),
+    ]))
+}
+
+pub fn execute_epoch_operations(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let state: EpochState = read_epoch_state(deps.storage)?;
+
+    // An epoch executed in several calls keeps going until it is finalized
+    let epoch_progress = read_epoch_progress(deps.storage)?;
+    if epoch_progress.is_none()
+        && !read_buffer_deposit(deps.storage)?.is_zero()
+        && read_buffer_redeem_height(deps.storage)? != Some(env.block.height)
+    {
+        assert_epoch_executable(deps.as_ref(), &env, &config, &state)?;
+        return redeem_buffer_deposit(deps, env, &config);
+    }
+    if config.epoch_custody_batch_size.is_some() || epoch_progress.is_some() {
+        return execute_epoch_operations_batch(deps, env, config, state, epoch_progress);
+    }
+
+    let stale_collaterals = assert_epoch_executable(deps.as_ref(), &env, &config, &state)?;
+    let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
+    let distribution =
+        compute_buffer_distribution(deps.as_ref(), &env, &config, &state, interest_buffer)?;
+    store_distribution_totals(deps.storage, &env, &distribution)?;
+
+    // Execute DistributeRewards
+    let mut messages: Vec<CosmosMsg> = distribution.messages.clone();
+    let distribute_start = messages.len();
+    let custodies: Vec<String> = epoch_custodies(deps.as_ref(), &stale_collaterals)?;
+    for custody_contract in custodies.iter() {
+        messages.push(distribute_rewards_msg(custody_contract)?);
+    }
+
+    // Execute store epoch state operation
+    messages.push(update_epoch_state_msg(&env, &distribution)?);
+
+    let distribute_end = distribute_start + custodies.len();
+    if !config.ordered_epoch_operations {
+        store_custody_quorum(
+            deps.storage,
+            &CustodyQuorum {
+                custodies,
+                unresponsive: vec![],
+            },
+        )?;
+    }
+    let response = Response::new().add_submessages(epoch_submessages(
+        deps.storage,
+        &config,
+        messages,
+        distribute_start..distribute_end,
+        0,
+        true,
+    )?);
+
+    let mut attributes = distribution_attributes(&distribution);
+    if !stale_collaterals.is_empty() {
+        attributes.push(attr("stale_collaterals", stale_collaterals.join(",")));
+    }
+
+    Ok(response.add_attributes(attributes))
+}
+
+/// Ordered epoch operations chain the messages one step at a time when
+/// they end with the epoch state update. Otherwise a failing custody
+/// contract of `distribute` is skipped instead of reverting the epoch,
+/// its reply records it for the quorum checked by the epoch state update.
+/// `first_custody` is the quorum index of the first custody contract
+fn epoch_submessages(
+    storage: &mut dyn Storage,
+    config: &Config,
+    mut messages: Vec<CosmosMsg>,
+    distribute: std::ops::Range<usize>,
+    first_custody: usize,
+    updates_epoch_state: bool,
+) -> StdResult<Vec<SubMsg>> {
+    if config.ordered_epoch_operations {
+        if !updates_epoch_state {
+            return Ok(messages.into_iter().map(SubMsg::new).collect());
+        }
+
+        let first_step = messages.remove(0);
+        store_epoch_steps(
+            storage,
+            &EpochSteps {
+                next_step: 0,
+                steps: messages,
+            },
+        )?;
+
+        return Ok(vec![SubMsg::reply_on_success(first_step, 0)]);
+    }
+
+    Ok(messages
+        .into_iter()
+        .enumerate()
+        .map(|(i, msg)| {
+            if distribute.contains(&i) {
+                SubMsg::reply_on_error(
+                    msg,
+                    DISTRIBUTE_REWARDS_REPLY_ID + (first_custody + i - distribute.start) as u64,
+                )
+            } else {
+                SubMsg::new(msg)
+            }
+        })
+        .collect())
+}
+
+/// Epoch operations asking at most `epoch_custody_batch_size` custodies
+/// per call to distribute their rewards. The first call holds the custody
+/// list, the call reaching the last custody reads the interest buffer,
+/// distributes it and then updates the epoch state
+fn execute_epoch_operations_batch(
+    deps: DepsMut,
+    env: Env,
+    config: Config,
+    state: EpochState,
+    epoch_progress: Option<EpochProgress>,
+) -> Result<Response, ContractError> {
+    let mut attributes = vec![attr("action", "epoch_operations")];
+    let mut epoch_progress = match epoch_progress {
+        Some(epoch_progress) => epoch_progress,
+        None => {
+            let stale_collaterals = assert_epoch_executable(deps.as_ref(), &env, &config, &state)?;
+            if !stale_collaterals.is_empty() {
+                attributes.push(attr("stale_collaterals", stale_collaterals.join(",")));
+            }
+
+            let custodies = epoch_custodies(deps.as_ref(), &stale_collaterals)?;
+            if !config.ordered_epoch_operations {
+                store_custody_quorum(
+                    deps.storage,
+                    &CustodyQuorum {
+                        custodies: custodies.clone(),
+                        unresponsive: vec![],
+                    },
+                )?;
+            }
+
+            EpochProgress {
+                custodies,
+                cursor: 0,
+            }
+        }
+    };
+
+    // The batch size may be unset while an epoch is in progress,
+    // the remaining custodies are then processed at once
+    let batch_size = config.epoch_custody_batch_size.unwrap_or(u32::MAX) as usize;
+    let cursor = epoch_progress.cursor as usize;
+    let batch_end = std::cmp::min(
+        cursor.saturating_add(batch_size),
+        epoch_progress.custodies.len(),
+    );
+    let mut distribute_messages: Vec<CosmosMsg> = vec![];
+    for custody_contract in epoch_progress.custodies[cursor..batch_end].iter() {
+        distribute_messages.push(distribute_rewards_msg(custody_contract)?);
+    }
+
+    attributes.push(attr("epoch_cursor", batch_end.to_string()));
+    attributes.push(attr(
+        "epoch_custodies",
+        epoch_progress.custodies.len().to_string(),
+    ));
+    if batch_end < epoch_progress.custodies.len() {
+        epoch_progress.cursor = batch_end as u32;
+        store_epoch_progress(deps.storage, &epoch_progress)?;
+
+        let distribute_end = distribute_messages.len();
+        return Ok(Response::new()
+            .add_submessages(epoch_submessages(
+                deps.storage,
+                &config,
+                distribute_messages,
+                0..distribute_end,
+                cursor,
+                false,
+            )?)
+            .add_attributes(attributes));
+    }
+
+    // The rewards distributed by the previous calls are part of the buffer
+    remove_epoch_progress(deps.storage);
+    let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
+    let distribution =
+        compute_buffer_distribution(deps.as_ref(), &env, &config, &state, interest_buffer)?;
+    store_distribution_totals(deps.storage, &env, &distribution)?;
+
+    let mut messages: Vec<CosmosMsg> = distribution.messages.clone();
+    let distribute_start = messages.len();
+    let distribute_end = distribute_start + distribute_messages.len();
+    messages.extend(distribute_messages);
+    messages.push(update_epoch_state_msg(&env, &distribution)?);
+
+    attributes.extend(distribution_attributes(&distribution).into_iter().skip(1));
+    Ok(Response::new()
+        .add_submessages(epoch_submessages(
+            deps.storage,
+            &config,
+            messages,
+            distribute_start..distribute_end,
+            cursor,
+            true,
+        )?)
+        .add_attributes(attributes))
+}
+
+pub fn reset_epoch_progress(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner_addr {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let epoch_progress = match read_epoch_progress(deps.storage)? {
+        Some(epoch_progress) => epoch_progress,
+        None => return Err(ContractError::NoEpochInProgress {}),
+    };
+    remove_epoch_progress(deps.storage);
+    remove_custody_quorum(deps.storage);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "reset_epoch_progress"),
+        attr("epoch_cursor", epoch_progress.cursor.to_string()),
+    ]))
+}
+
+/// Outcome of the interest buffer distribution of an epoch
+struct BufferDistribution {
+    messages: Vec<CosmosMsg>,
+    epoch_state: EpochStateResponse,
+    deposit_rate: Decimal256,
+    interest_buffer: Uint256,
+    distributed_interest: Uint256,
+    reserve_topup: Uint256,
+    /// Interest buffer accrued since the previous epoch
+    accrued_buffer: Uint256,
+    anc_purchase_amount: Uint256,
+    anc_purchase_executed: bool,
+    /// Why the ANC price could not be checked against max_anc_price
+    anc_price_error: Option<String>,
+    surplus_amount: Uint256,
+}
+
+/// Returns the collaterals left out of the reward distribution
+/// for their stale prices
+fn assert_epoch_executable(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    state: &EpochState,
+) -> Result<Vec<String>, ContractError> {
+    if env.block.height < state.last_executed_height + config.epoch_period {
+        return Err(ContractError::EpochNotPassed(state.last_executed_height));
+    }
+
+    // Collaterals with a price older than borrow_price_timeframe either
+    // abort the epoch or are left out of the reward distribution
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+    let mut stale_collaterals: Vec<String> = vec![];
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        if query_collateral_price(
+            deps,
+            config,
+            oracle_contract.clone(),
+            &collateral_token,
+            &elem,
+            Some(env.block.time.seconds()),
+            config.borrow_price_timeframe,
+        )
+        .is_err()
+        {
+            stale_collaterals.push(deps.api.addr_humanize(&collateral_token)?.to_string());
+        }
+    }
+
+    if config.epoch_requires_fresh_prices && !stale_collaterals.is_empty() {
+        return Err(ContractError::StalePrices(stale_collaterals.join(",")));
+    }
+
+    Ok(stale_collaterals)
+}
+
+/// Custody contracts of the whitelisted collaterals with a fresh price
+fn epoch_custodies(deps: Deps, stale_collaterals: &[String]) -> StdResult<Vec<String>> {
+    let mut custodies: Vec<String> = vec![];
+    let whitelist: Vec<WhitelistResponseElem> = read_whitelist(deps, None, None)?;
+    for elem in whitelist.iter() {
+        // Native collaterals are held by the overseer without rewards
+        if stale_collaterals.contains(&elem.collateral_token) || elem.token_type != TokenType::Cw20
+        {
+            continue;
+        }
+
+        for custody_contract in elem.custody_contracts.iter() {
+            custodies.push(custody_contract.0.clone());
+        }
+    }
+
+    Ok(custodies)
+}
+
+fn distribute_rewards_msg(custody_contract: &str) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: custody_contract.to_string(),
+        funds: vec![],
+        msg: to_binary(&CustodyExecuteMsg::DistributeRewards {})?,
+    }))
+}
+
+fn update_epoch_state_msg(env: &Env, distribution: &BufferDistribution) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::UpdateEpochState {
+            interest_buffer: distribution.interest_buffer,
+            distributed_interest: distribution.distributed_interest,
+            reserve_topup: distribution.reserve_topup,
+        })?,
+    }))
+}
+
+fn compute_buffer_distribution(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    state: &EpochState,
+    mut interest_buffer: Uint256,
+) -> StdResult<BufferDistribution> {
+    // # of blocks from the last executed height
+    let blocks = Uint256::from(env.block.height - state.last_executed_height);
+
+    // Compute next epoch state
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+    let epoch_state: EpochStateResponse =
+        query_epoch_state(deps, market_contract.clone(), env.block.height, None)?;
+
+    // effective_deposit_rate = cur_exchange_rate / prev_exchange_rate
+    // deposit_rate = (effective_deposit_rate - 1) / blocks
+    let effective_deposit_rate = epoch_state.exchange_rate / state.prev_exchange_rate;
+    let deposit_rate =
+        (effective_deposit_rate - Decimal256::one()) / Decimal256::from_uint256(blocks);
+
+    // The buffer distribution is decided on the deposit rate averaged with
+    // the recorded rates of the previous epochs, so a noisy epoch alone
+    // does not flip it
+    let prev_deposit_rates =
+        read_recent_deposit_rates(deps.storage, config.deposit_rate_smoothing_epochs - 1)?;
+    let smoothed_deposit_rate = prev_deposit_rates
+        .iter()
+        .fold(deposit_rate, |acc, rate| acc + *rate)
+        / Decimal256::from_uint256(prev_deposit_rates.len() as u64 + 1);
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+
+    // Purchase ANC only when the ANC price is at or below max_anc_price,
+    // otherwise the purchase portion is retained in the interest buffer.
+    // So is it when the ANC price is unavailable, the error is reported
+    // in the attributes instead of failing the epoch
+    let mut anc_price_error: Option<String> = None;
+    let anc_purchase_executed = if let Some(max_anc_price) = config.max_anc_price {
+        match query_anc_price(
+            deps,
+            deps.api.addr_humanize(&config.collector_contract)?,
+            deps.api.addr_humanize(&config.oracle_contract)?,
+            config.stable_denom.to_string(),
+            Some(TimeConstraints {
+                block_time: env.block.time.seconds(),
+                valid_timeframe: config.borrow_price_timeframe,
+            }),
+        ) {
+            Ok(anc_price) => anc_price <= max_anc_price,
+            Err(err) => {
+                anc_price_error = Some(err.to_string());
+                false
+            }
+        }
+    } else {
+        true
+    };
+
+    // Send accrued_buffer * config.anc_purchase_factor amount stable token to collector,
+    // up to max_anc_purchase_per_epoch with the remainder retained in the interest buffer
+    let accrued_buffer = interest_buffer - state.prev_interest_buffer;
+    let anc_purchase_amount = if anc_purchase_executed {
+        let anc_purchase_amount = accrued_buffer * config.anc_purchase_factor;
+        match config.max_anc_purchase_per_epoch {
+            Some(max_anc_purchase) => std::cmp::min(anc_purchase_amount, max_anc_purchase),
+            None => anc_purchase_amount,
+        }
+    } else {
+        Uint256::zero()
+    };
+    if !anc_purchase_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: deps
+                .api
+                .addr_humanize(&config.collector_contract)?
+                .to_string(),
+            amount: vec![deduct_tax(
+                deps,
+                Coin {
+                    denom: config.stable_denom.to_string(),
+                    amount: anc_purchase_amount.into(),
+                },
+            )?],
+        }));
+    }
+
+    // Deduct anc_purchase_amount from the interest_buffer
+    interest_buffer = interest_buffer - anc_purchase_amount;
+
+    // Distribute Interest Buffer to depositor
+    // Only executed when deposit rate < threshold_deposit_rate
+    let mut distributed_interest: Uint256 = Uint256::zero();
+    let mut reserve_topup: Uint256 = Uint256::zero();
+    if smoothed_deposit_rate < config.threshold_deposit_rate {
+        // missing_deposit_rate(_per_block)
+        let missing_deposit_rate = config.threshold_deposit_rate - smoothed_deposit_rate;
+        let prev_deposits = state.prev_aterra_supply * state.prev_exchange_rate;
+
+        // missing_deposits = prev_deposits * missing_deposit_rate(_per_block) * blocks
+        let missing_deposits = prev_deposits * blocks * missing_deposit_rate;
+        let distribution_buffer = interest_buffer * config.buffer_distribution_factor;
+
+        // When there was not enough deposits happens,
+        // distribute interest to market contract
+        distributed_interest = std::cmp::min(missing_deposits, distribution_buffer);
+        interest_buffer = interest_buffer - distributed_interest;
+
+        if !distributed_interest.is_zero() {
+            // deduct tax
+            distributed_interest = Uint256::from(
+                deduct_tax(
+                    deps,
+                    Coin {
+                        denom: config.stable_denom.to_string(),
+                        amount: distributed_interest.into(),
+                    },
+                )?
+                .amount,
+            );
+
+            // Divert some portion of it to the market reserves
+            // while they are below reserve_target, the market receives
+            // the top-up with its epoch operations
+            if !config.reserve_target.is_zero() {
+                let market_reserves =
+                    query_market_reserves(deps, market_contract.clone(), env.block.height)?
+                        * Uint256::one();
+
+                if market_reserves < config.reserve_target {
+                    reserve_topup = std::cmp::min(
+                        distributed_interest * config.reserve_topup_factor,
+                        config.reserve_target - market_reserves,
+                    );
+                    distributed_interest = distributed_interest - reserve_topup;
+                }
+            }
+
+            // Send some portion of interest buffer to Market contract
+            if !distributed_interest.is_zero() {
+                messages.push(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: market_contract.to_string(),
+                    amount: vec![Coin {
+                        denom: config.stable_denom.to_string(),
+                        amount: distributed_interest.into(),
+                    }],
+                }));
+            }
+        }
+    }
+
+    // Route the interest buffer exceeding buffer_target to surplus_recipient
+    let mut surplus_amount: Uint256 = Uint256::zero();
+    if interest_buffer > config.buffer_target {
+        surplus_amount = interest_buffer - config.buffer_target;
+        interest_buffer = config.buffer_target;
+
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: deps
+                .api
+                .addr_humanize(&config.surplus_recipient)?
+                .to_string(),
+            amount: vec![deduct_tax(
+                deps,
+                Coin {
+                    denom: config.stable_denom.to_string(),
+                    amount: surplus_amount.into(),
+                },
+            )?],
+        }));
+    }
+
+    Ok(BufferDistribution {
+        messages,
+        epoch_state,
+        deposit_rate,
+        interest_buffer,
+        distributed_interest,
+        reserve_topup,
+        anc_purchase_amount,
+        anc_purchase_executed,
+        anc_price_error,
+        accrued_buffer,
+        surplus_amount,
+    })
+}
+
+fn store_distribution_totals(
+    storage: &mut dyn Storage,
+    env: &Env,
+    distribution: &BufferDistribution,
+) -> StdResult<()> {
+    let mut epoch_totals: EpochTotals = read_epoch_totals(storage)?;
+    epoch_totals.total_anc_purchased += distribution.anc_purchase_amount;
+    epoch_totals.total_buffer_distributed += distribution.distributed_interest;
+    store_epoch_totals(storage, &epoch_totals)?;
+
+    store_buffer_flow_record(
+        storage,
+        &BufferFlowRecord {
+            height: env.block.height,
+            inflow: distribution.accrued_buffer,
+            distributed: distribution.distributed_interest,
+            anc_purchased: distribution.anc_purchase_amount,
+            ending_balance: distribution.interest_buffer,
+        },
+    )
+}
+
+fn distribution_attributes(distribution: &BufferDistribution) -> Vec<Attribute> {
+    let mut attributes = vec![
+        attr("action", "epoch_operations"),
+        attr("deposit_rate", distribution.deposit_rate.to_string()),
+        attr(
+            "exchange_rate",
+            distribution.epoch_state.exchange_rate.to_string(),
+        ),
+        attr("aterra_supply", distribution.epoch_state.aterra_supply),
+        attr("distributed_interest", distribution.distributed_interest),
+        attr("anc_purchase_amount", distribution.anc_purchase_amount),
+        attr(
+            "anc_purchase_executed",
+            distribution.anc_purchase_executed.to_string(),
+        ),
+        attr("surplus_amount", distribution.surplus_amount),
+        attr("reserve_topup", distribution.reserve_topup),
+    ];
+    if let Some(anc_price_error) = &distribution.anc_price_error {
+        attributes.push(attr("anc_price_error", anc_price_error));
+    }
+
+    attributes
+}
+
+/// Dispatches the next epoch operation message once the previous one
+/// succeeded, the whole chain reverts if any of them fails
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id == LIQUIDATION_RECORD_REPLY_ID {
+        return liquidation_record_reply(deps, msg);
+    }
+    if msg.id >= LIQUIDATE_COLLATERAL_REPLY_ID {
+        return liquidate_collateral_reply(deps, msg);
+    }
+    if msg.id >= UNLOCK_COLLATERAL_REPLY_ID {
+        return unlock_collateral_reply(deps, msg);
+    }
+    if msg.id >= DISTRIBUTE_REWARDS_REPLY_ID {
+        return distribute_rewards_reply(deps, msg);
+    }
+
+    let mut epoch_steps: EpochSteps = match read_epoch_steps(deps.storage)? {
+        Some(epoch_steps) => epoch_steps,
+        None => return Err(ContractError::InvalidReplyId(msg.id)),
+    };
+    if msg.id != epoch_steps.next_step {
+        return Err(ContractError::InvalidReplyId(msg.id));
+    }
+
+    if let ContractResult::Err(err) = msg.result {
+        return Err(ContractError::EpochStepFailed(msg.id, err));
+    }
+
+    if epoch_steps.steps.is_empty() {
+        // The last step stores the epoch state
+        let state: EpochState = read_epoch_state(deps.storage)?;
+        if state.last_executed_height != env.block.height {
+            return Err(ContractError::EpochStepFailed(
+                msg.id,
+                "epoch state is not updated".to_string(),
+            ));
+        }
+
+        remove_epoch_steps(deps.storage);
+        return Ok(Response::new().add_attributes(vec![
+            attr("action", "epoch_operations_completed"),
+            attr("steps", (msg.id + 1).to_string()),
+        ]));
+    }
+
+    let step = epoch_steps.steps.remove(0);
+    epoch_steps.next_step += 1;
+    store_epoch_steps(deps.storage, &epoch_steps)?;
+
+    Ok(Response::new().add_submessage(SubMsg::reply_on_success(step, epoch_steps.next_step)))
+}
+
+/// Records the custody contract of the reply as failing to distribute its rewards,
+/// the rewards of the other custody contracts are distributed regardless
+fn distribute_rewards_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let mut quorum: CustodyQuorum = match read_custody_quorum(deps.storage)? {
+        Some(quorum) => quorum,
+        None => return Err(ContractError::InvalidReplyId(msg.id)),
+    };
+    let custody = match quorum
+        .custodies
+        .get((msg.id - DISTRIBUTE_REWARDS_REPLY_ID) as usize)
+    {
+        Some(custody) => custody.clone(),
+        None => return Err(ContractError::InvalidReplyId(msg.id)),
+    };
+
+    // Only failed distributions reply
+    let err = match msg.result {
+        ContractResult::Ok(_) => return Ok(Response::default()),
+        ContractResult::Err(err) => err,
+    };
+    quorum.unresponsive.push(custody.clone());
+    store_custody_quorum(deps.storage, &quorum)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "distribute_rewards_failed"),
+        attr("custody_contract", custody),
+        attr("error", err),
+    ]))
+}
+
+pub fn update_epoch_state(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    // To store interest buffer before receiving epoch staking rewards,
+    // pass interest_buffer from execute_epoch_operations
+    interest_buffer: Uint256,
+    distributed_interest: Uint256,
+    reserve_topup: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let overseer_epoch_state: EpochState = read_epoch_state(deps.storage)?;
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // Revert the whole epoch when too few custody contracts distributed
+    let mut quorum_attributes = vec![];
+    if let Some(quorum) = read_custody_quorum(deps.storage)? {
+        remove_custody_quorum(deps.storage);
+
+        let responsive: Vec<String> = quorum
+            .custodies
+            .iter()
+            .filter(|c| !quorum.unresponsive.contains(c))
+            .cloned()
+            .collect();
+        let total = quorum.custodies.len() as u64;
+        if total > 0
+            && Decimal256::from_ratio(responsive.len() as u64, total) < config.min_custody_quorum
+        {
+            return Err(ContractError::CustodyQuorumNotReached(
+                responsive.len() as u64,
+                total,
+            ));
+        }
+
+        quorum_attributes.push(attr("responsive_custodies", responsive.join(",")));
+        quorum_attributes.push(attr(
+            "unresponsive_custodies",
+            quorum.unresponsive.join(","),
+        ));
     }
 
     // # of blocks from the last executed height
-    let blocks = Uint256::from(env.block.height - state.last_executed_height);
+    let blocks = Uint256::from(env.block.height - overseer_epoch_state.last_executed_height);
 
     // Compute next epoch state
     let market_contract = deps.api.addr_humanize(&config.market_contract)?;
-    let epoch_state: EpochStateResponse = query_epoch_state(
+    let market_epoch_state: EpochStateResponse = query_epoch_state(
         deps.as_ref(),
         market_contract.clone(),
         env.block.height,
-        None,
+        Some(distributed_interest),
     )?;
 
     // effective_deposit_rate = cur_exchange_rate / prev_exchange_rate
     // deposit_rate = (effective_deposit_rate - 1) / blocks
-    let effective_deposit_rate = epoch_state.exchange_rate / state.prev_exchange_rate;
+    let effective_deposit_rate =
+        market_epoch_state.exchange_rate / overseer_epoch_state.prev_exchange_rate;
     let deposit_rate =
         (effective_deposit_rate - Decimal256::one()) / Decimal256::from_uint256(blocks);
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let mut interest_buffer = query_balance(
-        deps.as_ref(),
-        env.contract.address.clone(),
-        config.stable_denom.to_string(),
+    // store updated epoch state
+    store_epoch_state(
+        deps.storage,
+        &EpochState {
+            last_executed_height: env.block.height,
+            prev_aterra_supply: market_epoch_state.aterra_supply,
+            prev_exchange_rate: market_epoch_state.exchange_rate,
+            prev_interest_buffer: interest_buffer,
+            deposit_rate,
+        },
     )?;
 
-    // Send accrued_buffer * config.anc_purchase_factor amount stable token to collector
-    let accrued_buffer = interest_buffer - state.prev_interest_buffer;
-    let anc_purchase_amount = accrued_buffer * config.anc_purchase_factor;
-    if !anc_purchase_amount.is_zero() {
-        messages.push(CosmosMsg::Bank(BankMsg::Send {
-            to_address: deps
-                .api
-                .addr_humanize(&config.collector_contract)?
-                .to_string(),
-            amount: vec![deduct_tax(
-                deps.as_ref(),
-                Coin {
+    store_epoch_rate_record(
+        deps.storage,
+        &EpochRateRecord {
+            height: env.block.height,
+            deposit_rate,
+            target_deposit_rate: config.target_deposit_rate,
+        },
+    )?;
+
+    // The backstop and bad debt budgets are renewed every epoch
+    store_backstop_used(deps.storage, &Uint256::zero())?;
+    store_bad_debt_settled(deps.storage, &Uint256::zero())?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market_contract.to_string(),
+            funds: if reserve_topup.is_zero() {
+                vec![]
+            } else {
+                vec![Coin {
                     denom: config.stable_denom.to_string(),
-                    amount: anc_purchase_amount.into(),
-                },
-            )?],
-        }));
+                    amount: reserve_topup.into(),
+                }]
+            },
+            msg: to_binary(&MarketExecuteMsg::ExecuteEpochOperations {
+                deposit_rate,
+                target_deposit_rate: config.target_deposit_rate,
+                threshold_deposit_rate: config.threshold_deposit_rate,
+                distributed_interest,
+            })?,
+        }))
+        .add_attributes(vec![
+            attr("action", "update_epoch_state"),
+            attr("deposit_rate", deposit_rate.to_string()),
+            attr("aterra_supply", market_epoch_state.aterra_supply),
+            attr(
+                "exchange_rate",
+                market_epoch_state.exchange_rate.to_string(),
+            ),
+            attr("interest_buffer", interest_buffer),
+        ])
+        .add_attributes(quorum_attributes))
+}
+
+/// The market holds the collaterals bought with its reserves as its own
+/// position, their custody contracts distribute the rewards to the interest
+/// buffer. Custodies without protocol-owned collateral are left alone
+pub fn harvest_protocol_yield(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+
+    let mut custody_amounts: Vec<(CanonicalAddr, Uint256)> = vec![];
+    for (collateral_token, amount) in
+        read_collaterals(deps.storage, &config.market_contract, None).iter()
+    {
+        let whitelist_elem: WhitelistElem = read_whitelist_elem(deps.storage, collateral_token)?;
+        // Native collaterals are held by the overseer without rewards
+        if whitelist_elem.token_type != TokenType::Cw20 {
+            continue;
+        }
+
+        // Reserves deployed before the custody amounts were recorded follow the weights
+        let mut locks = read_custody_locks(deps.storage, &config.market_contract, collateral_token);
+        let recorded_amount = locks.iter().fold(Uint256::zero(), |acc, c| acc + c.1);
+        if *amount > recorded_amount {
+            add_custody_amounts(
+                &mut locks,
+                split_custody_amount(&whitelist_elem, *amount - recorded_amount),
+            );
+        }
+        add_custody_amounts(&mut custody_amounts, locks);
     }
 
-    // Deduct anc_purchase_amount from the interest_buffer
-    interest_buffer = interest_buffer - anc_purchase_amount;
+    if custody_amounts.is_empty() {
+        return Ok(Response::new().add_attributes(vec![
+            attr("action", "harvest_protocol_yield"),
+            attr("harvested_amount", Uint256::zero()),
+        ]));
+    }
 
-    // Distribute Interest Buffer to depositor
-    // Only executed when deposit rate < threshold_deposit_rate
-    let mut distributed_interest: Uint256 = Uint256::zero();
-    if deposit_rate < config.threshold_deposit_rate {
-        // missing_deposit_rate(_per_block)
-        let missing_deposit_rate = config.threshold_deposit_rate - deposit_rate;
-        let prev_deposits = state.prev_aterra_supply * state.prev_exchange_rate;
+    // Custody rewards are pooled over every depositor, the protocol
+    // only owns the share of its collateral in the custody balance
+    let mut custodies: Vec<(String, Decimal256)> = vec![];
+    for (custody_contract, amount) in custody_amounts {
+        let custody_contract = deps.api.addr_humanize(&custody_contract)?;
+        let total_balance = query_custody_total_balance(deps.as_ref(), custody_contract.clone())?;
+        let protocol_share = if total_balance.is_zero() {
+            Decimal256::zero()
+        } else {
+            std::cmp::min(
+                Decimal256::from_ratio(amount, total_balance),
+                Decimal256::one(),
+            )
+        };
+        custodies.push((custody_contract.to_string(), protocol_share));
+    }
 
-        // missing_deposits = prev_deposits * missing_deposit_rate(_per_block) * blocks
-        let missing_deposits = prev_deposits * blocks * missing_deposit_rate;
-        let distribution_buffer = interest_buffer * config.buffer_distribution_factor;
+    let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
 
-        // When there was not enough deposits happens,
-        // distribute interest to market contract
-        distributed_interest = std::cmp::min(missing_deposits, distribution_buffer);
-        interest_buffer = interest_buffer - distributed_interest;
+    let custody_logs: Vec<String> = custodies.iter().map(|c| c.0.clone()).collect();
+    let (custody_contract, protocol_share) = custodies.remove(0);
+    Ok(Response::new()
+        .add_messages(harvest_custody_msgs(
+            &env,
+            custody_contract,
+            protocol_share,
+            custodies,
+            interest_buffer,
+            Uint256::zero(),
+        )?)
+        .add_attribute("harvested_custodies", custody_logs.join(",")))
+}
 
-        if !distributed_interest.is_zero() {
-            // deduct tax
-            distributed_interest = Uint256::from(
-                deduct_tax(
-                    deps.as_ref(),
-                    Coin {
-                        denom: config.stable_denom.to_string(),
-                        amount: distributed_interest.into(),
-                    },
-                )?
-                .amount,
-            );
+/// The custody distributes its rewards and the hook counts them right after,
+/// so the rewards of each custody are measured on their own
+fn harvest_custody_msgs(
+    env: &Env,
+    custody_contract: String,
+    protocol_share: Decimal256,
+    pending_custodies: Vec<(String, Decimal256)>,
+    prev_interest_buffer: Uint256,
+    harvested_amount: Uint256,
+) -> StdResult<Vec<CosmosMsg>> {
+    Ok(vec![
+        distribute_rewards_msg(&custody_contract)?,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            funds: vec![],
+            msg: to_binary(&ExecuteMsg::HarvestProtocolYieldHook {
+                prev_interest_buffer,
+                protocol_share,
+                pending_custodies,
+                harvested_amount,
+            })?,
+        }),
+    ])
+}
 
-            // Send some portion of interest buffer to Market contract
-            messages.push(CosmosMsg::Bank(BankMsg::Send {
-                to_address: market_contract.to_string(),
-                amount: vec![Coin {
-                    denom: config.stable_denom,
-                    amount: distributed_interest.into(),
-                }],
-            }));
+pub fn harvest_protocol_yield_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    prev_interest_buffer: Uint256,
+    protocol_share: Decimal256,
+    mut pending_custodies: Vec<(String, Decimal256)>,
+    harvested_amount: Uint256,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let interest_buffer = query_interest_buffer(deps.as_ref(), &env, &config)?;
+    let rewards = if interest_buffer > prev_interest_buffer {
+        interest_buffer - prev_interest_buffer
+    } else {
+        Uint256::zero()
+    };
+    let harvested_amount = harvested_amount + rewards * protocol_share;
+
+    if !pending_custodies.is_empty() {
+        let (custody_contract, protocol_share) = pending_custodies.remove(0);
+        return Ok(Response::new().add_messages(harvest_custody_msgs(
+            &env,
+            custody_contract,
+            protocol_share,
+            pending_custodies,
+            interest_buffer,
+            harvested_amount,
+        )?));
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "harvest_protocol_yield"),
+        attr("harvested_amount", harvested_amount),
+    ]))
+}
+
+/// Ask every custody contract holding rewards of the sender to send them
+/// to the overseer, the hook forwards the stable coins received
+pub fn claim_all_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    claim_rewards_with_hook(
+        deps,
+        env,
+        info,
+        "claim_all_rewards",
+        |borrower, prev_balance| ExecuteMsg::ClaimAllRewardsHook {
+            borrower,
+            prev_balance,
+        },
+    )
+}
+
+/// Ask every custody contract holding rewards of the sender to send them
+/// to the overseer, the hook repays the loan of the sender with them
+pub fn claim_rewards_and_repay(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    claim_rewards_with_hook(
+        deps,
+        env,
+        info,
+        "claim_rewards_and_repay",
+        |borrower, prev_balance| ExecuteMsg::ClaimRewardsAndRepayHook {
+            borrower,
+            prev_balance,
+        },
+    )
+}
+
+/// Claim the custody rewards of the sender, followed by the hook that
+/// spends the stable coins received above the current balance
+fn claim_rewards_with_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    action: &str,
+    hook_msg: fn(String, Uint256) -> ExecuteMsg,
+) -> Result<Response, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let (mut messages, total_rewards, custody_logs) =
+        claim_custody_rewards_msgs(deps.as_ref(), &info.sender)?;
+
+    if !messages.is_empty() {
+        let prev_balance = query_balance(
+            deps.as_ref(),
+            env.contract.address.clone(),
+            config.stable_denom,
+        )?;
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            funds: vec![],
+            msg: to_binary(&hook_msg(info.sender.to_string(), prev_balance))?,
+        }));
+    }
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![attr("action", action), attr("borrower", info.sender)])
+        .add_attributes(custody_logs)
+        .add_attribute("total_rewards", total_rewards))
+}
+
+/// `ClaimRewardsFor` messages of the custody contracts with rewards
+/// of the borrower, which skips the others
+fn claim_custody_rewards_msgs(
+    deps: Deps,
+    borrower: &Addr,
+) -> StdResult<(Vec<CosmosMsg>, Uint256, Vec<Attribute>)> {
+    let mut custodies: Vec<CanonicalAddr> = vec![];
+    for (_, elem) in read_all_whitelist_elems(deps.storage)? {
+        for custody in elem.custody_contracts {
+            if !custodies.contains(&custody.custody_contract) {
+                custodies.push(custody.custody_contract);
+            }
         }
     }
 
-    // Execute DistributeRewards
-    let whitelist: Vec<WhitelistResponseElem> = read_whitelist(deps.as_ref(), None, None)?;
-    for elem in whitelist.iter() {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut total_rewards = Uint256::zero();
+    let mut custody_logs: Vec<Attribute> = vec![];
+    for custody_contract in custodies {
+        let custody_contract = deps.api.addr_humanize(&custody_contract)?;
+        let rewards =
+            query_custody_pending_rewards(deps, custody_contract.clone(), borrower.clone())?;
+        if rewards.is_zero() {
+            continue;
+        }
+
         messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: elem.custody_contract.clone(),
+            contract_addr: custody_contract.to_string(),
             funds: vec![],
-            msg: to_binary(&CustodyExecuteMsg::DistributeRewards {})?,
+            msg: to_binary(&CustodyExecuteMsg::ClaimRewardsFor {
+                borrower: borrower.to_string(),
+            })?,
         }));
+        total_rewards += rewards;
+        custody_logs.push(attr(
+            "custody_rewards",
+            format!("{}{}", rewards, custody_contract),
+        ));
     }
 
-    // TODO: Should this become a reply? If so which SubMsg to make reply_on?
-    // Execute store epoch state operation
-    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: env.contract.address.to_string(),
-        funds: vec![],
-        msg: to_binary(&ExecuteMsg::UpdateEpochState {
-            interest_buffer,
-            distributed_interest,
-        })?,
-    }));
+    Ok((messages, total_rewards, custody_logs))
+}
+
+/// Forward the stable coins the custody contracts sent since `prev_balance`
+pub fn claim_all_rewards_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: Addr,
+    prev_balance: Uint256,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let claimed_amount = claimed_stable_amount(deps.as_ref(), &env, &config, prev_balance)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !claimed_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: borrower.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: claimed_amount.into(),
+                },
+            )?],
+        }));
+    }
 
     Ok(Response::new().add_messages(messages).add_attributes(vec![
-        attr("action", "epoch_operations"),
-        attr("deposit_rate", deposit_rate.to_string()),
-        attr("exchange_rate", epoch_state.exchange_rate.to_string()),
-        attr("aterra_supply", epoch_state.aterra_supply),
-        attr("distributed_interest", distributed_interest),
-        attr("anc_purchase_amount", anc_purchase_amount),
+        attr("action", "claim_all_rewards_hook"),
+        attr("borrower", borrower),
+        attr("claimed_amount", claimed_amount),
     ]))
 }
 
-pub fn update_epoch_state(
+/// Repay the loan of the default position of the borrower with the
+/// stable coins received above `prev_balance` and refund the rest
+/// Executor: itself
+pub fn claim_rewards_and_repay_hook(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    // To store interest buffer before receiving epoch staking rewards,
-    // pass interest_buffer from execute_epoch_operations
-    interest_buffer: Uint256,
-    distributed_interest: Uint256,
+    borrower: Addr,
+    prev_balance: Uint256,
 ) -> Result<Response, ContractError> {
-    let config: Config = read_config(deps.storage)?;
-    let overseer_epoch_state: EpochState = read_epoch_state(deps.storage)?;
     if info.sender != env.contract.address {
         return Err(ContractError::Unauthorized {});
     }
 
-    // # of blocks from the last executed height
-    let blocks = Uint256::from(env.block.height - overseer_epoch_state.last_executed_height);
-
-    // Compute next epoch state
-    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
-    let market_epoch_state: EpochStateResponse = query_epoch_state(
+    let config: Config = read_config(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+    let claimed_amount = claimed_stable_amount(deps.as_ref(), &env, &config, prev_balance)?;
+    let loan_amount = query_borrower_info(
         deps.as_ref(),
-        market_contract.clone(),
+        market.clone(),
+        borrower.clone(),
         env.block.height,
-        Some(distributed_interest),
-    )?;
+        None,
+    )?
+    .loan_amount;
+
+    let repay_amount = std::cmp::min(claimed_amount, loan_amount);
+    let refund_amount = claimed_amount - repay_amount;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut repaid_amount = Uint256::zero();
+    if !repay_amount.is_zero() {
+        // The market repays what it receives, less the tax of the transfer
+        let repay_coin = deduct_tax(
+            deps.as_ref(),
+            Coin {
+                denom: config.stable_denom.clone(),
+                amount: repay_amount.into(),
+            },
+        )?;
+        repaid_amount = Uint256::from(repay_coin.amount);
+
+        let market_balance = query_balance(
+            deps.as_ref(),
+            market.clone(),
+            config.stable_denom.to_string(),
+        )?;
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: market.to_string(),
+            amount: vec![repay_coin],
+        }));
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market.to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                borrower: borrower.to_string(),
+                prev_balance: market_balance,
+                sub_account: None,
+            })?,
+        }));
+    }
+
+    if !refund_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: borrower.to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: config.stable_denom,
+                    amount: refund_amount.into(),
+                },
+            )?],
+        }));
+    }
 
-    // effective_deposit_rate = cur_exchange_rate / prev_exchange_rate
-    // deposit_rate = (effective_deposit_rate - 1) / blocks
-    let effective_deposit_rate =
-        market_epoch_state.exchange_rate / overseer_epoch_state.prev_exchange_rate;
-    let deposit_rate =
-        (effective_deposit_rate - Decimal256::one()) / Decimal256::from_uint256(blocks);
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "claim_rewards_and_repay_hook"),
+        attr("borrower", borrower),
+        attr("claimed_amount", claimed_amount),
+        attr("repaid_amount", repaid_amount),
+        attr("refunded_amount", refund_amount),
+    ]))
+}
 
-    // store updated epoch state
-    store_epoch_state(
-        deps.storage,
-        &EpochState {
-            last_executed_height: env.block.height,
-            prev_aterra_supply: market_epoch_state.aterra_supply,
-            prev_exchange_rate: market_epoch_state.exchange_rate,
-            prev_interest_buffer: interest_buffer,
-            deposit_rate,
-        },
+/// Stable coins the overseer received above `prev_balance`
+fn claimed_stable_amount(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    prev_balance: Uint256,
+) -> StdResult<Uint256> {
+    let balance = query_balance(
+        deps,
+        env.contract.address.clone(),
+        config.stable_denom.to_string(),
     )?;
+    Ok(if balance > prev_balance {
+        balance - prev_balance
+    } else {
+        Uint256::zero()
+    })
+}
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: market_contract.to_string(),
-            funds: vec![],
-            msg: to_binary(&MarketExecuteMsg::ExecuteEpochOperations {
-                deposit_rate,
-                target_deposit_rate: config.target_deposit_rate,
-                threshold_deposit_rate: config.threshold_deposit_rate,
-                distributed_interest,
-            })?,
-        }))
-        .add_attributes(vec![
-            attr("action", "update_epoch_state"),
-            attr("deposit_rate", deposit_rate.to_string()),
-            attr("aterra_supply", market_epoch_state.aterra_supply),
-            attr(
-                "exchange_rate",
-                market_epoch_state.exchange_rate.to_string(),
-            ),
-            attr("interest_buffer", interest_buffer),
-        ]))
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
+    // Configs from before the price timeframes were split take the single
+    // timeframe for both, and the defaults of instantiate for the rest.
+    // The surplus routing and the collateral count limit stay disabled
+    if read_config(deps.storage).is_err() {
+        let legacy_config = read_legacy_config(deps.storage)?;
+        store_config(
+            deps.storage,
+            &Config {
+                owner_addr: legacy_config.owner_addr,
+                oracle_contract: legacy_config.oracle_contract,
+                market_contract: legacy_config.market_contract,
+                liquidation_contract: legacy_config.liquidation_contract,
+                collector_contract: legacy_config.collector_contract.clone(),
+                stable_denom: legacy_config.stable_denom,
+                epoch_period: legacy_config.epoch_period,
+                threshold_deposit_rate: legacy_config.threshold_deposit_rate,
+                target_deposit_rate: legacy_config.target_deposit_rate,
+                buffer_distribution_factor: legacy_config.buffer_distribution_factor,
+                anc_purchase_factor: legacy_config.anc_purchase_factor,
+                borrow_price_timeframe: legacy_config.price_timeframe,
+                liquidation_price_timeframe: legacy_config.price_timeframe,
+                max_anc_price: None,
+                buffer_target: Uint256::from(u128::MAX),
+                surplus_recipient: legacy_config.collector_contract,
+                stable_decimals: DEFAULT_STABLE_DECIMALS,
+                price_deviation_threshold: Decimal256::percent(DEFAULT_PRICE_DEVIATION_THRESHOLD),
+                max_collaterals_per_borrower: u32::MAX,
+                ordered_epoch_operations: false,
+                dust_threshold: Uint256::zero(),
+                dust_tolerance: Decimal256::zero(),
+                collateral_converter: None,
+                soft_liquidation: false,
+                buffer_backstop_limit: Uint256::zero(),
+                max_confidence_spread: None,
+                reserve_target: Uint256::zero(),
+                reserve_topup_factor: Decimal256::zero(),
+                epoch_requires_fresh_prices: false,
+                fallback_oracle_contract: None,
+                min_custody_quorum: Decimal256::zero(),
+                same_block_collateral_delay: false,
+                max_collateral_value_per_borrower: None,
+                risk_admin: None,
+                epoch_custody_batch_size: None,
+                anc_buyback_amm: None,
+                min_lock_amount: Uint256::zero(),
+                solvency_margin: Decimal256::zero(),
+                max_block_price_drop: None,
+                price_drop_cooldown: 0,
+                dispute_window: 0,
+                valuation_mode: ValuationMode::Spot,
+                default_limit: DEFAULT_LIMIT,
+                bad_debt_budget: Uint256::zero(),
+                deposit_rate_smoothing_epochs: 1,
+                skip_paused_collaterals: false,
+                max_ltv_delta_per_epoch: None,
+                liquidation_frozen: false,
+                max_anc_purchase_per_epoch: None,
+            },
+        )?;
+    }
+
+    // Move the single custody contract of each legacy whitelist elem
+    // into the custody contract list as the primary entry,
+    // unless the whitelist was migrated already
+    let legacy_whitelist = match read_all_whitelist_elems(deps.storage) {
+        Ok(_) => vec![],
+        Err(_) => read_legacy_whitelist(deps.storage)?,
+    };
+    for (collateral_token, elem) in legacy_whitelist {
+        store_whitelist_elem(
+            deps.storage,
+            &collateral_token,
+            &WhitelistElem {
+                name: elem.name,
+                symbol: elem.symbol,
+                max_ltv: elem.max_ltv,
+                custody_contracts: vec![CustodyContractElem {
+                    custody_contract: elem.custody_contract,
+                    weight: Decimal256::one(),
+                }],
+                quote_denom: None,
+                liquidation_premium: None,
+                correlation_group: None,
+                min_lock_amount: None,
+                borrow_enabled_from: None,
+                borrow_disabled_from: None,
+                liquidation_contract: None,
+                decimals: DEFAULT_COLLATERAL_DECIMALS,
+                token_type: TokenType::Cw20,
+                exchange_rate_source: None,
+                borrow_rate_premium: None,
+            },
+        )?;
+    }
+
+    // Deployments from before the locked amounts were counted start the
+    // counters from the positions of their borrowers
+    let total_locked = sum_locked_collaterals(deps.storage)?;
+    for (collateral_token, _) in read_all_whitelist_elems(deps.storage)? {
+        let amount = total_locked
+            .iter()
+            .find(|c| c.0 == collateral_token)
+            .map(|c| c.1)
+            .unwrap_or_default();
+        store_total_locked(deps.storage, &collateral_token, &amount)?;
+    }
+
+    Ok(Response::default())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
-        QueryMsg::EpochState {} => to_binary(&query_state(deps)?),
+        QueryMsg::EpochState { precision } => to_binary(&query_state(deps, precision)?),
         QueryMsg::Whitelist {
             collateral_token,
             start_after,
@@ -497,22 +3195,178 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             optional_addr_validate(deps.api, start_after)?,
             limit,
         )?),
-        QueryMsg::Collaterals { borrower } => to_binary(&query_collaterals(
+        QueryMsg::WhitelistEntry { collateral_token } => to_binary(
+            &query_whitelist_entry(deps, deps.api.addr_validate(&collateral_token)?)
+                .map_err(|err| StdError::generic_err(err.to_string()))?,
+        ),
+        QueryMsg::Collaterals {
+            borrower,
+            sub_account,
+            canonical,
+        } => to_binary(&query_collaterals(
             deps,
             deps.api.addr_validate(&borrower)?,
+            sub_account,
+            canonical.unwrap_or(false),
         )?),
-        QueryMsg::AllCollaterals { start_after, limit } => to_binary(&query_all_collaterals(
+        QueryMsg::AllCollaterals {
+            start_after,
+            limit,
+            canonical,
+        } => to_binary(&query_all_collaterals(
             deps,
             optional_addr_validate(deps.api, start_after)?,
             limit,
+            canonical.unwrap_or(false),
         )?),
         QueryMsg::BorrowLimit {
             borrower,
             block_time,
+            sub_account,
+            price_mode,
+            quote_denom,
         } => to_binary(&query_borrow_limit(
             deps,
+            env,
             deps.api.addr_validate(&borrower)?,
             block_time,
+            sub_account,
+            price_mode,
+            quote_denom,
+        )?),
+        QueryMsg::BadDebt {} => to_binary(&query_bad_debt(deps, env)?),
+        QueryMsg::TrustedContracts {} => to_binary(&query_trusted_contracts(deps)?),
+        QueryMsg::CollateralCapUtilization { start_after, limit } => {
+            to_binary(&query_collateral_cap_utilization(
+                deps,
+                env,
+                optional_addr_validate(deps.api, start_after)?,
+                limit,
+            )?)
+        }
+        QueryMsg::LiquidationBonus {
+            borrower,
+            sub_account,
+        } => to_binary(&query_liquidation_bonus(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            sub_account,
+        )?),
+        QueryMsg::MaxLiquidatable {
+            borrower,
+            sub_account,
+        } => to_binary(&query_max_liquidatable(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            sub_account,
+        )?),
+        QueryMsg::LiquidationWaterfall {
+            borrower,
+            sub_account,
+        } => to_binary(&query_liquidation_waterfall(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            sub_account,
+        )?),
+        QueryMsg::StressTest {
+            price_drop,
+            start_after,
+            limit,
+        } => to_binary(&query_stress_test(
+            deps,
+            env,
+            price_drop,
+            optional_addr_validate(deps.api, start_after)?,
+            limit,
+        )?),
+        QueryMsg::LiquidationHistory { start_after, limit } => {
+            to_binary(&query_liquidation_history(deps, start_after, limit)?)
+        }
+        QueryMsg::EpochTotals {} => to_binary(&query_epoch_totals(deps)?),
+        QueryMsg::CategoryExposure {} => to_binary(&query_category_exposure(deps, env)?),
+        QueryMsg::NextEpoch {} => to_binary(&query_next_epoch(deps, env)?),
+        QueryMsg::EpochRatePerformance { limit } => {
+            to_binary(&query_epoch_rate_performance(deps, limit)?)
+        }
+        QueryMsg::BufferFlow { limit } => to_binary(&query_buffer_flow(deps, limit)?),
+        QueryMsg::NetDepositRate { anc_price } => {
+            to_binary(&query_net_deposit_rate(deps, env, anc_price)?)
+        }
+        QueryMsg::SubAccounts {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_sub_accounts(
+            deps,
+            env,
+            deps.api.addr_validate(&owner)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::SafeBorrowAmount {
+            borrower,
+            safety_ltv,
+            sub_account,
+        } => to_binary(&query_safe_borrow_amount(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            safety_ltv,
+            sub_account,
+        )?),
+        QueryMsg::EffectiveLtv {
+            borrower,
+            collateral_token,
+            sub_account,
+        } => to_binary(&query_effective_ltv(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            deps.api.addr_validate(&collateral_token)?,
+            sub_account,
+        )?),
+        QueryMsg::ConsistencyCheck { start_after, limit } => to_binary(&query_consistency_check(
+            deps,
+            optional_addr_validate(deps.api, start_after)?,
+            limit,
+        )?),
+        QueryMsg::StaleCollaterals {} => to_binary(&query_stale_collaterals(deps, env)?),
+        QueryMsg::Simulation {} => to_binary(&query_simulation(deps, env)?),
+        QueryMsg::GlobalBorrowableByCollateral {} => {
+            to_binary(&query_global_borrowable_by_collateral(deps, env)?)
+        }
+        QueryMsg::Position {
+            borrower,
+            sub_account,
+        } => to_binary(&query_position(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+            sub_account,
+        )?),
+        QueryMsg::RiskContribution { borrower } => to_binary(&query_risk_contribution(
+            deps,
+            env,
+            deps.api.addr_validate(&borrower)?,
+        )?),
+        QueryMsg::AncBuybackEstimate { amount } => {
+            to_binary(&query_anc_buyback_estimate(deps, env, amount)?)
+        }
+        QueryMsg::SolvencyCheck {} => to_binary(&query_solvency_check(deps, env)?),
+        QueryMsg::FairnessFund {} => to_binary(&query_fairness_fund(deps)?),
+        QueryMsg::AssetRegistry {} => to_binary(&query_asset_registry(deps)?),
+        QueryMsg::InterestBufferDeposit {} => to_binary(&query_interest_buffer_deposit(deps, env)?),
+        QueryMsg::Snapshot { start_after, limit } => to_binary(&query_snapshot(
+            deps,
+            optional_addr_validate(deps.api, start_after)?,
+            limit,
+        )?),
+        QueryMsg::Authorization { address } => to_binary(&query_authorization(
+            deps,
+            deps.api.addr_validate(&address)?,
         )?),
     }
 }
@@ -537,12 +3391,420 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
         target_deposit_rate: config.target_deposit_rate,
         buffer_distribution_factor: config.buffer_distribution_factor,
         anc_purchase_factor: config.anc_purchase_factor,
-        price_timeframe: config.price_timeframe,
+        borrow_price_timeframe: config.borrow_price_timeframe,
+        liquidation_price_timeframe: config.liquidation_price_timeframe,
+        max_anc_price: config.max_anc_price,
+        buffer_target: config.buffer_target,
+        surplus_recipient: deps
+            .api
+            .addr_humanize(&config.surplus_recipient)?
+            .to_string(),
+        stable_decimals: config.stable_decimals,
+        price_deviation_threshold: config.price_deviation_threshold,
+        max_collaterals_per_borrower: config.max_collaterals_per_borrower,
+        ordered_epoch_operations: config.ordered_epoch_operations,
+        dust_threshold: config.dust_threshold,
+        dust_tolerance: config.dust_tolerance,
+        collateral_converter: config
+            .collateral_converter
+            .map(|c| deps.api.addr_humanize(&c).map(|c| c.to_string()))
+            .transpose()?,
+        soft_liquidation: config.soft_liquidation,
+        buffer_backstop_limit: config.buffer_backstop_limit,
+        max_confidence_spread: config.max_confidence_spread,
+        reserve_target: config.reserve_target,
+        reserve_topup_factor: config.reserve_topup_factor,
+        epoch_requires_fresh_prices: config.epoch_requires_fresh_prices,
+        fallback_oracle_contract: config
+            .fallback_oracle_contract
+            .map(|c| deps.api.addr_humanize(&c).map(|c| c.to_string()))
+            .transpose()?,
+        min_custody_quorum: config.min_custody_quorum,
+        same_block_collateral_delay: config.same_block_collateral_delay,
+        max_collateral_value_per_borrower: config.max_collateral_value_per_borrower,
+        risk_admin: config
+            .risk_admin
+            .map(|r| deps.api.addr_humanize(&r).map(|r| r.to_string()))
+            .transpose()?,
+        epoch_custody_batch_size: config.epoch_custody_batch_size,
+        anc_buyback_amm: config
+            .anc_buyback_amm
+            .map(|a| deps.api.addr_humanize(&a).map(|a| a.to_string()))
+            .transpose()?,
+        min_lock_amount: config.min_lock_amount,
+        solvency_margin: config.solvency_margin,
+        max_block_price_drop: config.max_block_price_drop,
+        price_drop_cooldown: config.price_drop_cooldown,
+        dispute_window: config.dispute_window,
+        valuation_mode: config.valuation_mode,
+        default_limit: config.default_limit,
+        bad_debt_budget: config.bad_debt_budget,
+        deposit_rate_smoothing_epochs: config.deposit_rate_smoothing_epochs,
+        skip_paused_collaterals: config.skip_paused_collaterals,
+        max_ltv_delta_per_epoch: config.max_ltv_delta_per_epoch,
+        liquidation_frozen: config.liquidation_frozen,
+        max_anc_purchase_per_epoch: config.max_anc_purchase_per_epoch,
+    })
+}
+
+pub fn query_simulation(deps: Deps, env: Env) -> StdResult<SimulationResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let oracle_contract = deps.api.addr_humanize(&config.oracle_contract)?;
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+
+    let mut whitelist: Vec<WhitelistResponseElem> = vec![];
+    let mut collateral_prices: Vec<(String, Decimal256)> = vec![];
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        let (price, _) = query_collateral_price(
+            deps,
+            &config,
+            oracle_contract.clone(),
+            &collateral_token,
+            &elem,
+            None,
+            config.borrow_price_timeframe,
+        )?;
+
+        let collateral_token = deps.api.addr_humanize(&collateral_token)?.to_string();
+        collateral_prices.push((collateral_token.clone(), price));
+        whitelist.push(whitelist_response_elem(deps, collateral_token, elem)?);
+    }
+
+    Ok(SimulationResponse {
+        config: query_config(deps)?,
+        whitelist,
+        collateral_prices,
+        market_state: query_market_state(deps, market_contract.clone(), env.block.height)?,
+        interest_model_config: query_interest_model_config(deps, market_contract)?,
+    })
+}
+
+pub fn query_snapshot(
+    deps: Deps,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<SnapshotResponse> {
+    let start_after = if let Some(start_after) = start_after {
+        Some(deps.api.addr_canonicalize(start_after.as_str())?)
+    } else {
+        None
+    };
+
+    let mut whitelist: Vec<WhitelistResponseElem> = vec![];
+    let mut total_locked: TokensHuman = vec![];
+    for (collateral_token, elem) in read_all_whitelist_elems(deps.storage)? {
+        let locked = read_total_locked(deps.storage, &collateral_token);
+        let collateral_token = deps.api.addr_humanize(&collateral_token)?.to_string();
+        total_locked.push((collateral_token.clone(), locked));
+        whitelist.push(whitelist_response_elem(deps, collateral_token, elem)?);
+    }
+
+    let limit = limit.unwrap_or(read_config(deps.storage)?.default_limit);
+    let borrowers: Vec<(CanonicalAddr, Tokens)> =
+        read_collaterals_page(deps.storage, start_after, Some(limit))?;
+
+    let mut positions: Vec<SnapshotPositionElem> = vec![];
+    for (borrower_raw, collaterals) in borrowers.iter() {
+        let borrower = deps.api.addr_humanize(borrower_raw)?.to_string();
+        positions.push(SnapshotPositionElem {
+            borrower: borrower.clone(),
+            sub_account: None,
+            collaterals: collaterals.to_human(deps)?,
+        });
+
+        let mut sub_account_start_after: Option<String> = None;
+        loop {
+            let sub_accounts = read_sub_account_collaterals(
+                deps.storage,
+                borrower_raw,
+                sub_account_start_after,
+                None,
+            )?;
+            if sub_accounts.is_empty() {
+                break;
+            }
+
+            sub_account_start_after = sub_accounts.last().map(|(name, _)| name.clone());
+            for (sub_account, collaterals) in sub_accounts {
+                positions.push(SnapshotPositionElem {
+                    borrower: borrower.clone(),
+                    sub_account: Some(sub_account),
+                    collaterals: collaterals.to_human(deps)?,
+                });
+            }
+        }
+    }
+
+    let last_borrower = borrowers
+        .last()
+        .map(|(borrower, _)| deps.api.addr_humanize(borrower).map(|b| b.to_string()))
+        .transpose()?;
+
+    Ok(SnapshotResponse {
+        config: query_config(deps)?,
+        whitelist,
+        total_locked,
+        positions,
+        last_borrower,
+    })
+}
+
+pub fn query_authorization(deps: Deps, address: Addr) -> StdResult<AuthorizationResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let address_raw = deps.api.addr_canonicalize(address.as_str())?;
+
+    // Updates touching neither owner nor risk fields are open to both roles
+    let can_update = assert_update_authority(&address_raw, &config, false, false).is_ok();
+    Ok(AuthorizationResponse {
+        address: address.to_string(),
+        can_update_config: can_update,
+        can_update_whitelist: can_update,
+        can_pause: assert_update_authority(&address_raw, &config, true, false).is_ok(),
+        can_liquidate: true,
+        can_run_epoch: true,
+    })
+}
+
+pub fn query_anc_buyback_estimate(
+    deps: Deps,
+    env: Env,
+    amount: Uint256,
+) -> StdResult<AncBuybackEstimateResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let anc_buyback_amm = match config.anc_buyback_amm {
+        Some(anc_buyback_amm) => deps.api.addr_humanize(&anc_buyback_amm)?,
+        None => return Err(StdError::generic_err("ANC buyback AMM is not configured")),
+    };
+
+    let collector_contract = deps.api.addr_humanize(&config.collector_contract)?;
+    let anc_amount = query_native_conversion(
+        deps,
+        anc_buyback_amm,
+        config.stable_denom.to_string(),
+        query_anchor_token(deps, collector_contract.clone())?,
+        amount,
+    )?;
+    if anc_amount.is_zero() {
+        return Err(StdError::generic_err("ANC buyback estimate is zero"));
+    }
+
+    let spot_price = query_anc_price(
+        deps,
+        collector_contract,
+        deps.api.addr_humanize(&config.oracle_contract)?,
+        config.stable_denom.to_string(),
+        Some(TimeConstraints {
+            block_time: env.block.time.seconds(),
+            valid_timeframe: config.borrow_price_timeframe,
+        }),
+    )?;
+
+    // average_price = amount / anc_amount
+    // slippage = average_price / spot_price - 1
+    let average_price = Decimal256::from_uint256(amount) / Decimal256::from_uint256(anc_amount);
+    let slippage = if average_price > spot_price {
+        average_price / spot_price - Decimal256::one()
+    } else {
+        Decimal256::zero()
+    };
+
+    Ok(AncBuybackEstimateResponse {
+        amount,
+        anc_amount,
+        average_price,
+        spot_price,
+        slippage,
+    })
+}
+
+pub fn query_trusted_contracts(deps: Deps) -> StdResult<TrustedContractsResponse> {
+    let config: Config = read_config(deps.storage)?;
+
+    let mut custody_contracts: Vec<String> = vec![];
+    for (_, elem) in read_all_whitelist_elems(deps.storage)? {
+        for custody_contract in elem.custody_contracts.iter() {
+            custody_contracts.push(
+                deps.api
+                    .addr_humanize(&custody_contract.custody_contract)?
+                    .to_string(),
+            );
+        }
+    }
+
+    Ok(TrustedContractsResponse {
+        oracle_contract: deps.api.addr_humanize(&config.oracle_contract)?.to_string(),
+        market_contract: deps.api.addr_humanize(&config.market_contract)?.to_string(),
+        liquidation_contract: deps
+            .api
+            .addr_humanize(&config.liquidation_contract)?
+            .to_string(),
+        collector_contract: deps
+            .api
+            .addr_humanize(&config.collector_contract)?
+            .to_string(),
+        custody_contracts,
+    })
+}
+
+pub fn query_asset_registry(deps: Deps) -> StdResult<AssetRegistryResponse> {
+    let config: Config = read_config(deps.storage)?;
+
+    let mut collateral_tokens: Vec<String> = vec![];
+    for (collateral_token, _) in read_all_whitelist_elems(deps.storage)? {
+        collateral_tokens.push(deps.api.addr_humanize(&collateral_token)?.to_string());
+    }
+
+    Ok(AssetRegistryResponse {
+        stable_denom: config.stable_denom,
+        collateral_tokens,
+        aterra_contract: query_aterra_contract(
+            deps,
+            deps.api.addr_humanize(&config.market_contract)?,
+        )?
+        .to_string(),
+        anc_token: query_anchor_token(deps, deps.api.addr_humanize(&config.collector_contract)?)?
+            .to_string(),
+    })
+}
+
+pub fn query_interest_buffer_deposit(
+    deps: Deps,
+    env: Env,
+) -> StdResult<InterestBufferDepositResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let market_contract = deps.api.addr_humanize(&config.market_contract)?;
+    let aterra_amount = query_token_balance(
+        deps,
+        query_aterra_contract(deps, market_contract.clone())?,
+        env.contract.address,
+    )?;
+    let exchange_rate =
+        query_epoch_state(deps, market_contract, env.block.height, None)?.exchange_rate;
+
+    Ok(InterestBufferDepositResponse {
+        deposited_amount: read_buffer_deposit(deps.storage)?,
+        aterra_amount,
+        value: aterra_amount * exchange_rate,
+    })
+}
+
+pub fn query_state(deps: Deps, precision: Option<u32>) -> StdResult<EpochState> {
+    let mut state: EpochState = read_epoch_state(deps.storage)?;
+    state.deposit_rate = round_decimal(state.deposit_rate, precision);
+
+    Ok(state)
+}
+
+pub fn query_epoch_totals(deps: Deps) -> StdResult<EpochTotalsResponse> {
+    let epoch_totals: EpochTotals = read_epoch_totals(deps.storage)?;
+    Ok(EpochTotalsResponse {
+        total_anc_purchased: epoch_totals.total_anc_purchased,
+        total_buffer_distributed: epoch_totals.total_buffer_distributed,
+    })
+}
+
+pub fn query_next_epoch(deps: Deps, env: Env) -> StdResult<NextEpochResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let state: EpochState = read_epoch_state(deps.storage)?;
+
+    let next_epoch_height = state.last_executed_height + config.epoch_period;
+    Ok(NextEpochResponse {
+        last_epoch_height: state.last_executed_height,
+        epoch_period: config.epoch_period,
+        blocks_until_next: next_epoch_height.saturating_sub(env.block.height),
+        in_progress: read_epoch_progress(deps.storage)?.is_some(),
     })
 }
 
-pub fn query_state(deps: Deps) -> StdResult<EpochState> {
-    read_epoch_state(deps.storage)
+pub fn query_epoch_rate_performance(
+    deps: Deps,
+    limit: Option<u32>,
+) -> StdResult<EpochRatePerformanceResponse> {
+    let epochs = read_epoch_rate_records(deps.storage, limit)?
+        .into_iter()
+        .map(|record| {
+            let below_target = record.deposit_rate < record.target_deposit_rate;
+            EpochRatePerformanceElem {
+                height: record.height,
+                realized_deposit_rate: record.deposit_rate,
+                target_deposit_rate: record.target_deposit_rate,
+                deviation: if below_target {
+                    record.target_deposit_rate - record.deposit_rate
+                } else {
+                    record.deposit_rate - record.target_deposit_rate
+                },
+                below_target,
+            }
+        })
+        .collect();
+
+    Ok(EpochRatePerformanceResponse { epochs })
+}
+
+pub fn query_buffer_flow(deps: Deps, limit: Option<u32>) -> StdResult<BufferFlowResponse> {
+    let epochs = read_buffer_flow_records(deps.storage, limit)?
+        .into_iter()
+        .map(|record| BufferFlowElem {
+            height: record.height,
+            inflow: record.inflow,
+            distributed: record.distributed,
+            anc_purchased: record.anc_purchased,
+            ending_balance: record.ending_balance,
+        })
+        .collect();
+
+    Ok(BufferFlowResponse { epochs })
+}
+
+/// ANC emissions dilute the supply by anc_emission_rate / anc_supply
+/// each block, which is subtracted from the deposit rate of the last epoch
+pub fn query_net_deposit_rate(
+    deps: Deps,
+    env: Env,
+    anc_price: Option<Decimal256>,
+) -> StdResult<NetDepositRateResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let epoch_state: EpochState = read_epoch_state(deps.storage)?;
+    let market = deps.api.addr_humanize(&config.market_contract)?;
+
+    let anc_emission_rate = query_anc_emission_rate(deps, market.clone(), env.block.height)?;
+    let anc_price = match anc_price {
+        Some(anc_price) => anc_price,
+        None => query_anc_price(
+            deps,
+            deps.api.addr_humanize(&config.collector_contract)?,
+            deps.api.addr_humanize(&config.oracle_contract)?,
+            config.stable_denom.to_string(),
+            Some(TimeConstraints {
+                block_time: env.block.time.seconds(),
+                valid_timeframe: config.borrow_price_timeframe,
+            }),
+        )?,
+    };
+    let market_epoch_state = query_epoch_state(deps, market, env.block.height, None)?;
+    let total_deposits = market_epoch_state.aterra_supply * market_epoch_state.exchange_rate;
+
+    // The emissions are valued in the stable denom
+    // to compare with the deposit rate
+    let dilution_rate = if total_deposits.is_zero() {
+        Decimal256::zero()
+    } else {
+        anc_emission_rate * anc_price / Decimal256::from_uint256(total_deposits)
+    };
+    let net_deposit_rate = if epoch_state.deposit_rate > dilution_rate {
+        epoch_state.deposit_rate - dilution_rate
+    } else {
+        Decimal256::zero()
+    };
+
+    Ok(NetDepositRateResponse {
+        deposit_rate: epoch_state.deposit_rate,
+        anc_emission_rate,
+        anc_price,
+        total_deposits,
+        dilution_rate,
+        net_deposit_rate,
+    })
 }
 
 pub fn query_whitelist(
@@ -557,16 +3819,11 @@ pub fn query_whitelist(
             &deps.api.addr_canonicalize(collateral_token.as_str())?,
         )?;
         Ok(WhitelistResponse {
-            elems: vec![WhitelistResponseElem {
-                name: whitelist_elem.name,
-                symbol: whitelist_elem.symbol,
-                max_ltv: whitelist_elem.max_ltv,
-                custody_contract: deps
-                    .api
-                    .addr_humanize(&whitelist_elem.custody_contract)?
-                    .to_string(),
-                collateral_token: collateral_token.to_string(),
-            }],
+            elems: vec![whitelist_response_elem(
+                deps,
+                collateral_token.to_string(),
+                whitelist_elem,
+            )?],
         })
     } else {
         let start_after = if let Some(start_after) = start_after {
@@ -575,7 +3832,25 @@ pub fn query_whitelist(
             None
         };
 
-        let whitelist: Vec<WhitelistResponseElem> = read_whitelist(deps, start_after, limit)?;
+        let limit = limit.unwrap_or(read_config(deps.storage)?.default_limit);
+        let whitelist: Vec<WhitelistResponseElem> = read_whitelist(deps, start_after, Some(limit))?;
         Ok(WhitelistResponse { elems: whitelist })
     }
 }
+
+pub fn query_whitelist_entry(
+    deps: Deps,
+    collateral_token: Addr,
+) -> Result<WhitelistResponseElem, ContractError> {
+    let whitelist_elem: WhitelistElem = read_whitelist_elem(
+        deps.storage,
+        &deps.api.addr_canonicalize(collateral_token.as_str())?,
+    )
+    .map_err(|_| ContractError::CollateralNotWhitelisted(collateral_token.to_string()))?;
+
+    Ok(whitelist_response_elem(
+        deps,
+        collateral_token.to_string(),
+        whitelist_elem,
+    )?)
+}
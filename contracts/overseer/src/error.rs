@@ -1,3 +1,4 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{OverflowError, StdError};
 use thiserror::Error;
 
@@ -18,12 +19,219 @@ pub enum ContractError {
     #[error("An epoch has not passed yet; last executed height: {0}")]
     EpochNotPassed(u64),
 
+    #[error("Collateral prices are stale: {0}")]
+    StalePrices(String),
+
     #[error("Token is already registered as collateral")]
     TokenAlreadyRegistered {},
 
+    #[error("Custody contract weight must be greater than zero")]
+    InvalidCustodyWeight {},
+
+    #[error("Cannot sweep tracked asset: {0}")]
+    SweepTrackedAsset(String),
+
+    #[error("Collateral is not whitelisted: {0}")]
+    CollateralNotWhitelisted(String),
+
+    #[error("Epoch operation step {0} failed: {1}")]
+    EpochStepFailed(u64, String),
+
+    #[error("Unexpected reply id: {0}")]
+    InvalidReplyId(u64),
+
+    #[error("Cannot lock more than {0} distinct collaterals")]
+    TooManyCollaterals(u32),
+
+    #[error("Max LTV must be lower than 1")]
+    InvalidMaxLtv {},
+
+    #[error("Whitelist update of {0} is repeated in the batch")]
+    DuplicateWhitelistUpdate(String),
+
+    #[error("Liquidation premium cannot exceed {0}")]
+    InvalidLiquidationPremium(Decimal256),
+
+    #[error("Borrow window must be enabled before it is disabled")]
+    InvalidBorrowWindow {},
+
+    #[error("Collateral {0} has no supply and cannot back new borrows")]
+    ZeroSupplyCollateral(String),
+
+    #[error("Lock amount of {0} is below the minimum {1}")]
+    LockAmountTooSmall(String, Uint256),
+
+    #[error("Collateral {0} is outside its borrow window")]
+    OutsideBorrowWindow(String),
+
+    #[error("Liquidations against {0} are paused after a sharp price drop")]
+    LiquidationPaused(String),
+
+    #[error("Collateral converter is not configured")]
+    ConverterNotConfigured {},
+
+    #[error("Collateral must be converted to a different token")]
+    SameCollateralToken {},
+
+    #[error("No {0} collateral is locked in the position")]
+    NothingToMigrate(String),
+
+    #[error("Collateral {0} must have a single custody contract to be migrated to")]
+    MultipleCustodyContracts(String),
+
+    #[error("Borrow limit after migration falls below loan liability: {0}")]
+    MigrationUnderwater(u128),
+
+    #[error("No oracle price from {0} to the stable denom")]
+    NoQuotePrice(String),
+
+    #[error("Cannot reallocate collaterals within the same position")]
+    SameSubAccount {},
+
+    #[error("Cannot transfer a position to its own borrower")]
+    SelfPositionTransfer {},
+
+    #[error("No position transfer of {0} is pending")]
+    NoPositionTransfer(String),
+
+    #[error("Position transfer must expire after the current block height")]
+    InvalidTransferExpiry {},
+
+    #[error("Position transfer of {0} has expired")]
+    PositionTransferExpired(String),
+
+    #[error("Combined position exceeds the recipient borrow limit: {0}")]
+    TransferExceedsLimit(u128),
+
+    #[error("Cannot delegate the liquidation of a position to its own borrower")]
+    SelfLiquidationDelegation {},
+
+    #[error("No liquidation delegation of {0} exists")]
+    NoLiquidationDelegation(String),
+
+    #[error("Liquidation bonus exceeds the delegated cap: {0}")]
+    DelegatedBonusExceedsCap(u128),
+
+    #[error("Liquidation price timeframe cannot be shorter than the borrow price timeframe")]
+    InvalidPriceTimeframe {},
+
+    #[error("Collateral {0} is still locked; set its max ltv to zero and wait for the positions to unwind before removing it")]
+    CollateralStillLocked(String),
+
     #[error("Unlock amount cannot exceed locked amount")]
     UnlockExceedsLocked {},
 
     #[error("Unlock amount too high; Loan liability becomes greater than borrow limit: {0}")]
     UnlockTooLarge(u128),
+
+    #[error("Target LTV cannot be lower than the current LTV: {0}")]
+    TargetLtvBelowCurrent(String),
+
+    #[error("Cannot unlock collateral while loan liability exceeds borrow limit: {0}")]
+    UnlockUnderwaterLoan(u128),
+
+    #[error("Repayment falls short of the loan to close by {0}")]
+    CloseRepaymentShortfall(u128),
+
+    #[error("Cannot close the default position while a sub account carries a loan: {0}")]
+    CloseSubAccountLoan(String),
+
+    #[error("Native collateral cannot be the stable denom: {0}")]
+    NativeStableCollateral(String),
+
+    #[error("Attached funds do not match the locked native collateral: {0}")]
+    NativeCollateralFundsMismatch(String),
+
+    #[error("Native collateral is not supported by this operation: {0}")]
+    NativeCollateralUnsupported(String),
+
+    #[error("Reserve top-up factor cannot exceed 1")]
+    InvalidReserveTopupFactor {},
+
+    #[error("Min custody quorum cannot exceed 1")]
+    InvalidMinCustodyQuorum {},
+
+    #[error("Epoch custody batch size must be greater than 0")]
+    InvalidEpochCustodyBatchSize {},
+
+    #[error("No epoch operations are in progress")]
+    NoEpochInProgress {},
+
+    #[error("Default limit must be between 1 and {0}")]
+    InvalidDefaultLimit(u32),
+
+    #[error("Custody quorum not reached: {0} of {1} custody contracts distributed rewards")]
+    CustodyQuorumNotReached(u64, u64),
+
+    #[error("{0} cannot be set to the overseer contract itself")]
+    SelfReferentialAddress(String),
+
+    #[error("{0} and {1} cannot be set to the same contract")]
+    DuplicateContractAddress(String, String),
+
+    #[error("No {0} auction proceeds have been provided")]
+    AuctionProceedsNotProvided(String),
+
+    #[error("No {0} has been provided to the fairness fund")]
+    FairnessFundNotProvided(String),
+
+    #[error("Liquidation {0} is not in the liquidation history")]
+    LiquidationRecordNotFound(u64),
+
+    #[error("Dispute window of liquidation {0} has expired")]
+    DisputeWindowExpired(u64),
+
+    #[error("Liquidation {0} has already been disputed")]
+    LiquidationAlreadyDisputed(u64),
+
+    #[error("Fairness fund has only {0} available")]
+    InsufficientFairnessFund(u128),
+
+    #[error("Compensation exceeds the liquidation loss of {0}")]
+    CompensationExceedsLoss(u128),
+
+    #[error("Interest buffer has only {0} available")]
+    InsufficientInterestBuffer(u128),
+
+    #[error("Interest buffer is needed by the due epoch operations")]
+    BufferNeededForEpoch {},
+
+    #[error("No compensation of liquidation {0} can be claimed")]
+    NoCompensation(u64),
+
+    #[error("Collaterals of {0} have not been fully seized")]
+    CollateralsNotSeized(String),
+
+    #[error("{0} has no debt to settle")]
+    NoBadDebt(String),
+
+    #[error("Deposit rate smoothing must be between 1 and {0} epochs")]
+    InvalidDepositRateSmoothing(u64),
+
+    #[error("Transfer of collateral {0} failed, the token may be paused")]
+    CollateralTransferPaused(String),
+
+    #[error("Collateral decimals cannot exceed 18, got {0}")]
+    InvalidCollateralDecimals(u8),
+
+    #[error("Stable decimals cannot exceed 18, got {0}")]
+    InvalidStableDecimals(u8),
+
+    #[error("Max LTV of {0} can only change by {1} more this epoch")]
+    MaxLtvDeltaExceeded(String, Decimal256),
+
+    #[error("Liquidations are frozen")]
+    LiquidationFrozen {},
+
+    #[error("Debt fraction must be between 0 and 1")]
+    InvalidDebtFraction {},
+
+    #[error("Position {0} is not empty")]
+    SubAccountNotEmpty(String),
+
+    #[error("Split position exceeds its borrow limit: {0}")]
+    SplitExceedsLimit(u128),
+
+    #[error("Total locked {0} of {1} is below the released amount {2}")]
+    TotalLockedUnderflow(Uint256, String, Uint256),
 }
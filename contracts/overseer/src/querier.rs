@@ -1,9 +1,33 @@
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{to_binary, Addr, Deps, QueryRequest, StdResult, WasmQuery};
 
-use moneymarket::liquidation::{LiquidationAmountResponse, QueryMsg as LiquidationQueryMsg};
-use moneymarket::market::{BorrowerInfoResponse, EpochStateResponse, QueryMsg as MarketQueryMsg};
+use anchor_token::collector::{
+    ConfigResponse as CollectorConfigResponse, QueryMsg as CollectorQueryMsg,
+};
+use moneymarket::converter::{QueryMsg as ConverterQueryMsg, SimulationResponse};
+use moneymarket::custody::{BorrowerResponse, QueryMsg as CustodyQueryMsg, TotalBalanceResponse};
+use moneymarket::exchange_rate::{ExchangeRateResponse, QueryMsg as ExchangeRateQueryMsg};
+use moneymarket::interest_model::{
+    ConfigResponse as InterestModelConfigResponse, QueryMsg as InterestModelQueryMsg,
+};
+use moneymarket::liquidation::{
+    ConfigResponse as LiquidationConfigResponse, LiquidationAmountResponse,
+    QueryMsg as LiquidationQueryMsg,
+};
+use moneymarket::market::{
+    BorrowerInfoResponse, BorrowerInfosResponse, ConfigResponse as MarketConfigResponse,
+    EpochStateResponse, MaxRedeemableResponse, QueryMsg as MarketQueryMsg, StateResponse,
+};
+use moneymarket::querier::{query_price, TimeConstraints};
 use moneymarket::tokens::TokensHuman;
+use serde::Deserialize;
+
+/// The part of the config every liquidation contract answers with,
+/// the liquidation queue has no auctions
+#[derive(Deserialize)]
+struct AuctionConfigResponse {
+    auction_duration: Option<u64>,
+}
 
 pub fn query_epoch_state(
     deps: Deps,
@@ -29,6 +53,7 @@ pub fn query_borrower_info(
     market_addr: Addr,
     borrower: Addr,
     block_height: u64,
+    sub_account: Option<String>,
 ) -> StdResult<BorrowerInfoResponse> {
     let borrower_amount: BorrowerInfoResponse =
         deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
@@ -36,12 +61,29 @@ pub fn query_borrower_info(
             msg: to_binary(&MarketQueryMsg::BorrowerInfo {
                 borrower: borrower.to_string(),
                 block_height: Some(block_height),
+                sub_account,
             })?,
         }))?;
 
     Ok(borrower_amount)
 }
 
+/// Query a page of borrower infos from the market contract
+pub fn query_borrower_infos(
+    deps: Deps,
+    market_addr: Addr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<BorrowerInfosResponse> {
+    let borrower_infos: BorrowerInfosResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::BorrowerInfos { start_after, limit })?,
+        }))?;
+
+    Ok(borrower_infos)
+}
+
 #[allow(clippy::ptr_arg)]
 pub fn query_liquidation_amount(
     deps: Deps,
@@ -50,6 +92,7 @@ pub fn query_liquidation_amount(
     borrow_limit: Uint256,
     collaterals: &TokensHuman,
     collateral_prices: Vec<Decimal256>,
+    premium_rates: Option<Vec<Decimal256>>,
 ) -> StdResult<LiquidationAmountResponse> {
     let liquidation_amount_res: LiquidationAmountResponse =
         deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
@@ -59,8 +102,236 @@ pub fn query_liquidation_amount(
                 borrow_limit,
                 collaterals: collaterals.clone(),
                 collateral_prices,
+                premium_rates,
             })?,
         }))?;
 
     Ok(liquidation_amount_res)
 }
+
+pub fn query_liquidation_config(
+    deps: Deps,
+    liquidation_contract: Addr,
+) -> StdResult<LiquidationConfigResponse> {
+    let liquidation_config: LiquidationConfigResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: liquidation_contract.to_string(),
+            msg: to_binary(&LiquidationQueryMsg::Config {})?,
+        }))?;
+
+    Ok(liquidation_config)
+}
+
+/// Auction duration of the liquidation contract, None unless it sells
+/// the seized collaterals by auction
+pub fn query_auction_duration(deps: Deps, liquidation_contract: Addr) -> StdResult<Option<u64>> {
+    let auction_config: AuctionConfigResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: liquidation_contract.to_string(),
+            msg: to_binary(&LiquidationQueryMsg::Config {})?,
+        }))?;
+
+    Ok(auction_config.auction_duration)
+}
+
+/// Query ANC price from the oracle contract,
+/// the ANC token address is reported by the collector contract
+pub fn query_anc_price(
+    deps: Deps,
+    collector_addr: Addr,
+    oracle_addr: Addr,
+    stable_denom: String,
+    time_constraints: Option<TimeConstraints>,
+) -> StdResult<Decimal256> {
+    let anchor_token = query_anchor_token(deps, collector_addr)?;
+    let anc_price = query_price(
+        deps,
+        oracle_addr,
+        anchor_token.to_string(),
+        stable_denom,
+        time_constraints,
+    )?;
+
+    Ok(anc_price.rate)
+}
+
+/// Query the ANC token address from the collector contract
+pub fn query_anchor_token(deps: Deps, collector_addr: Addr) -> StdResult<Addr> {
+    let collector_config: CollectorConfigResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: collector_addr.to_string(),
+            msg: to_binary(&CollectorQueryMsg::Config {})?,
+        }))?;
+
+    deps.api.addr_validate(&collector_config.anchor_token)
+}
+
+/// Query the aTerra token address from the market contract
+pub fn query_aterra_contract(deps: Deps, market_addr: Addr) -> StdResult<Addr> {
+    let market_config: MarketConfigResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::Config {})?,
+        }))?;
+
+    deps.api.addr_validate(&market_config.aterra_contract)
+}
+
+/// Query the collateral balance deposited in the custody contract
+pub fn query_custody_total_balance(deps: Deps, custody_addr: Addr) -> StdResult<Uint256> {
+    let total_balance: TotalBalanceResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: custody_addr.to_string(),
+            msg: to_binary(&CustodyQueryMsg::TotalBalance {})?,
+        }))?;
+
+    Ok(total_balance.total_balance)
+}
+
+/// Query the aTerra amount the depositor can redeem within the market liquidity
+pub fn query_max_redeemable(deps: Deps, market_addr: Addr, depositor: Addr) -> StdResult<Uint256> {
+    let max_redeemable: MaxRedeemableResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::MaxRedeemable {
+                depositor: depositor.to_string(),
+            })?,
+        }))?;
+
+    Ok(max_redeemable.aterra_amount)
+}
+
+/// Query the ANC emission rate last set by the market contract
+pub fn query_anc_emission_rate(
+    deps: Deps,
+    market_addr: Addr,
+    block_height: u64,
+) -> StdResult<Decimal256> {
+    let market_state: StateResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::State {
+                block_height: Some(block_height),
+            })?,
+        }))?;
+
+    Ok(market_state.anc_emission_rate)
+}
+
+pub fn query_market_reserves(
+    deps: Deps,
+    market_addr: Addr,
+    block_height: u64,
+) -> StdResult<Decimal256> {
+    let market_state: StateResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::State {
+                block_height: Some(block_height),
+            })?,
+        }))?;
+
+    Ok(market_state.total_reserves)
+}
+
+pub fn query_market_state(
+    deps: Deps,
+    market_addr: Addr,
+    block_height: u64,
+) -> StdResult<StateResponse> {
+    let market_state: StateResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::State {
+                block_height: Some(block_height),
+            })?,
+        }))?;
+
+    Ok(market_state)
+}
+
+/// Query the config of the interest model registered in the market contract
+pub fn query_interest_model_config(
+    deps: Deps,
+    market_addr: Addr,
+) -> StdResult<InterestModelConfigResponse> {
+    let market_config: MarketConfigResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_addr.to_string(),
+            msg: to_binary(&MarketQueryMsg::Config {})?,
+        }))?;
+
+    let interest_model_config: InterestModelConfigResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: market_config.interest_model,
+            msg: to_binary(&InterestModelQueryMsg::Config {})?,
+        }))?;
+
+    Ok(interest_model_config)
+}
+
+/// Query the amount of `ask_token` the converter returns for `amount` of `offer_token`
+pub fn query_conversion(
+    deps: Deps,
+    converter: Addr,
+    offer_token: Addr,
+    ask_token: Addr,
+    amount: Uint256,
+) -> StdResult<Uint256> {
+    let res: SimulationResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: converter.to_string(),
+        msg: to_binary(&ConverterQueryMsg::Simulation {
+            offer_token: offer_token.to_string(),
+            ask_token: ask_token.to_string(),
+            amount,
+        })?,
+    }))?;
+
+    Ok(res.amount)
+}
+
+/// Query the amount of `ask_token` the converter returns for `amount` of the `offer_denom` coin
+pub fn query_native_conversion(
+    deps: Deps,
+    converter: Addr,
+    offer_denom: String,
+    ask_token: Addr,
+    amount: Uint256,
+) -> StdResult<Uint256> {
+    let res: SimulationResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: converter.to_string(),
+        msg: to_binary(&ConverterQueryMsg::Simulation {
+            offer_token: offer_denom,
+            ask_token: ask_token.to_string(),
+            amount,
+        })?,
+    }))?;
+
+    Ok(res.amount)
+}
+
+/// Query the underlying asset amount of a single interest-bearing collateral token
+pub fn query_exchange_rate(deps: Deps, exchange_rate_source: Addr) -> StdResult<Decimal256> {
+    let res: ExchangeRateResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: exchange_rate_source.to_string(),
+        msg: to_binary(&ExchangeRateQueryMsg::ExchangeRate {})?,
+    }))?;
+
+    Ok(res.exchange_rate)
+}
+
+/// Holder rewards the borrower can claim from the custody contract
+pub fn query_custody_pending_rewards(
+    deps: Deps,
+    custody_addr: Addr,
+    borrower: Addr,
+) -> StdResult<Uint256> {
+    let borrower: BorrowerResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: custody_addr.to_string(),
+        msg: to_binary(&CustodyQueryMsg::Borrower {
+            address: borrower.to_string(),
+        })?,
+    }))?;
+
+    Ok(borrower.pending_rewards)
+}
@@ -2,17 +2,55 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
-use cosmwasm_std::{CanonicalAddr, Deps, Order, StdError, StdResult, Storage};
-use cosmwasm_storage::{Bucket, ReadonlyBucket, ReadonlySingleton, Singleton};
+use cosmwasm_std::{CanonicalAddr, CosmosMsg, Deps, Order, StdError, StdResult, Storage};
+use cosmwasm_storage::{to_length_prefixed, Bucket, ReadonlyBucket, ReadonlySingleton, Singleton};
 
-use moneymarket::overseer::{CollateralsResponse, WhitelistResponseElem};
-use moneymarket::tokens::Tokens;
+use moneymarket::overseer::{CollateralsResponse, TokenType, ValuationMode, WhitelistResponseElem};
+use moneymarket::tokens::{Tokens, TokensMath};
 
 const KEY_CONFIG: &[u8] = b"config";
 const KEY_EPOCH_STATE: &[u8] = b"epoch_state";
+const KEY_EPOCH_STEPS: &[u8] = b"epoch_steps";
+const KEY_CUSTODY_QUORUM: &[u8] = b"custody_quorum";
+const KEY_EPOCH_PROGRESS: &[u8] = b"epoch_progress";
+const KEY_EPOCH_TOTALS: &[u8] = b"epoch_totals";
+const KEY_LIQUIDATION_COUNT: &[u8] = b"liquidation_count";
+const KEY_BACKSTOP_USED: &[u8] = b"backstop_used";
+const KEY_EPOCH_RATE_COUNT: &[u8] = b"epoch_rate_count";
+const KEY_FAIRNESS_FUND: &[u8] = b"fairness_fund";
+const KEY_BUFFER_DEPOSIT: &[u8] = b"buffer_deposit";
+const KEY_BUFFER_REDEEM_HEIGHT: &[u8] = b"buffer_redeem_height";
+const KEY_BAD_DEBT_SETTLED: &[u8] = b"bad_debt_settled";
+const KEY_COLLATERAL_TRANSFERS: &[u8] = b"collateral_transfers";
+const KEY_PENDING_LIQUIDATION_RECORD: &[u8] = b"pending_liquidation_record";
+const KEY_BUFFER_FLOW_COUNT: &[u8] = b"buffer_flow_count";
 
 const PREFIX_WHITELIST: &[u8] = b"whitelist";
 const PREFIX_COLLATERALS: &[u8] = b"collateral";
+const PREFIX_SUB_ACCOUNT_COLLATERALS: &[u8] = b"sub_account_collateral";
+const PREFIX_LAST_PRICE: &[u8] = b"last_price";
+const PREFIX_TOTAL_LOCKED: &[u8] = b"total_locked";
+const PREFIX_LIQUIDATION_HISTORY: &[u8] = b"liquidation_history";
+const PREFIX_POSITION_TRANSFER: &[u8] = b"position_transfer";
+const PREFIX_LIQUIDATION_DELEGATION: &[u8] = b"liquidation_delegation";
+const PREFIX_RECENT_LOCK: &[u8] = b"recent_lock";
+const PREFIX_SUB_ACCOUNT_RECENT_LOCK: &[u8] = b"sub_account_recent_lock";
+const PREFIX_EPOCH_RATE_HISTORY: &[u8] = b"epoch_rate_history";
+const PREFIX_BUFFER_FLOW_HISTORY: &[u8] = b"buffer_flow_history";
+const PREFIX_LTV_CHANGE: &[u8] = b"ltv_change";
+const PREFIX_PRICE_OBSERVATION: &[u8] = b"price_observation";
+const PREFIX_PRICE_DROP: &[u8] = b"price_drop";
+const PREFIX_LIQUIDATION_DISPUTE: &[u8] = b"liquidation_dispute";
+const PREFIX_CUSTODY_LOCKS: &[u8] = b"custody_locks";
+
+/// # of the most recent liquidation records kept in the history
+pub const MAX_LIQUIDATION_HISTORY: u64 = 100;
+
+/// # of the most recent epoch rate records kept in the history
+pub const MAX_EPOCH_RATE_HISTORY: u64 = 100;
+
+/// # of the most recent buffer flow records kept in the history
+pub const MAX_BUFFER_FLOW_HISTORY: u64 = 100;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
@@ -27,7 +65,110 @@ pub struct Config {
     pub target_deposit_rate: Decimal256,
     pub buffer_distribution_factor: Decimal256,
     pub anc_purchase_factor: Decimal256,
-    pub price_timeframe: u64,
+    pub borrow_price_timeframe: u64,
+    pub liquidation_price_timeframe: u64,
+    pub max_anc_price: Option<Decimal256>,
+    pub buffer_target: Uint256,
+    pub surplus_recipient: CanonicalAddr,
+    pub stable_decimals: u8,
+    pub price_deviation_threshold: Decimal256,
+    pub max_collaterals_per_borrower: u32,
+    pub ordered_epoch_operations: bool,
+    pub dust_threshold: Uint256,
+    pub dust_tolerance: Decimal256,
+    pub collateral_converter: Option<CanonicalAddr>,
+    pub soft_liquidation: bool,
+    pub buffer_backstop_limit: Uint256,
+    pub max_confidence_spread: Option<Decimal256>,
+    pub reserve_target: Uint256,
+    pub reserve_topup_factor: Decimal256,
+    pub epoch_requires_fresh_prices: bool,
+    pub fallback_oracle_contract: Option<CanonicalAddr>,
+    pub min_custody_quorum: Decimal256,
+    pub same_block_collateral_delay: bool,
+    pub max_collateral_value_per_borrower: Option<Uint256>,
+    pub risk_admin: Option<CanonicalAddr>,
+    pub epoch_custody_batch_size: Option<u32>,
+    pub anc_buyback_amm: Option<CanonicalAddr>,
+    pub min_lock_amount: Uint256,
+    pub solvency_margin: Decimal256,
+    pub max_block_price_drop: Option<Decimal256>,
+    pub price_drop_cooldown: u64,
+    pub dispute_window: u64,
+    pub valuation_mode: ValuationMode,
+    pub default_limit: u32,
+    pub bad_debt_budget: Uint256,
+    pub deposit_rate_smoothing_epochs: u64,
+    pub skip_paused_collaterals: bool,
+    pub max_ltv_delta_per_epoch: Option<Decimal256>,
+    pub liquidation_frozen: bool,
+    pub max_anc_purchase_per_epoch: Option<Uint256>,
+}
+
+/// Cumulative amounts sent out of the interest buffer by epoch operations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct EpochTotals {
+    pub total_anc_purchased: Uint256,
+    pub total_buffer_distributed: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationRecord {
+    pub borrower: CanonicalAddr,
+    pub liquidator: CanonicalAddr,
+    /// Amount the market repaid with the liquidation proceeds
+    pub repaid: Uint256,
+    pub seized_tokens: Tokens,
+    pub block_height: u64,
+}
+
+/// Stable amounts held by the overseer for the fairness fund,
+/// which never count as interest buffer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct FairnessFund {
+    pub available: Uint256,
+    /// Reserved for reported but unclaimed compensations
+    pub reserved: Uint256,
+}
+
+impl FairnessFund {
+    pub fn total(&self) -> Uint256 {
+        self.available + self.reserved
+    }
+}
+
+/// Compensation of a liquidation reported to be done on a corrected price
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationDispute {
+    pub borrower: CanonicalAddr,
+    pub compensation: Uint256,
+    pub claimed: bool,
+}
+
+/// Deposit rate realized over an epoch and the target it was steered to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochRateRecord {
+    pub height: u64,
+    pub deposit_rate: Decimal256,
+    pub target_deposit_rate: Decimal256,
+}
+
+/// Sum of the `max_ltv` changes of a collateral
+/// since the epoch executed at `epoch_height`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LtvChange {
+    pub epoch_height: u64,
+    pub cumulative_delta: Decimal256,
+}
+
+/// Interest buffer amounts moved by the epoch operations of an epoch
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BufferFlowRecord {
+    pub height: u64,
+    pub inflow: Uint256,
+    pub distributed: Uint256,
+    pub anc_purchased: Uint256,
+    pub ending_balance: Uint256,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -39,12 +180,144 @@ pub struct EpochState {
     pub last_executed_height: u64,
 }
 
+/// Epoch operation messages not dispatched yet,
+/// `next_step` is the reply id of the message in flight
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochSteps {
+    pub next_step: u64,
+    pub steps: Vec<CosmosMsg>,
+}
+
+/// Epoch operations executed over several calls, `cursor` custodies
+/// were asked to distribute their rewards so far
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochProgress {
+    pub custodies: Vec<String>,
+    pub cursor: u32,
+}
+
+/// Custody contracts asked to distribute rewards by the epoch operations,
+/// the failing ones are recorded by their replies until the epoch state is updated
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CustodyQuorum {
+    pub custodies: Vec<String>,
+    pub unresponsive: Vec<String>,
+}
+
+/// Custody transfers in flight for a position, the reply id of each
+/// transfer is its index offset by the reply id of the operation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollateralTransfers {
+    pub borrower: CanonicalAddr,
+    pub sub_account: Option<String>,
+    pub transfers: Vec<(CanonicalAddr, Uint256)>,
+    /// Custody contract of each transfer
+    #[serde(default)]
+    pub custodies: Vec<CanonicalAddr>,
+}
+
+/// Collaterals locked into a position during the block of its last lock
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RecentLock {
+    pub block_height: u64,
+    pub collaterals: Tokens,
+}
+
+/// Collateral price seen by the latest liquidation or unlock
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceObservation {
+    pub price: Decimal256,
+    pub block_height: u64,
+}
+
+/// Latest price drop exceeding `max_block_price_drop`,
+/// its cooldown runs from `block_height`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceDrop {
+    pub drop: Decimal256,
+    pub block_height: u64,
+}
+
+/// Default position offered by a borrower to `recipient`, who can
+/// take it over before the `expires_at` block height
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionTransfer {
+    pub recipient: CanonicalAddr,
+    pub expires_at: u64,
+}
+
+/// Keeper allowed by a borrower to liquidate its positions once,
+/// for a bonus of at most `max_bonus`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationDelegation {
+    pub keeper: CanonicalAddr,
+    pub max_bonus: Uint256,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct WhitelistElem {
     pub name: String,
     pub symbol: String,
     pub max_ltv: Decimal256,
+    /// The first entry is the primary custody contract
+    pub custody_contracts: Vec<CustodyContractElem>,
+    /// The collateral is priced against this asset,
+    /// which is then priced against stable_denom
+    pub quote_denom: Option<String>,
+    /// Overrides the max premium rate of the liquidation contract
+    pub liquidation_premium: Option<Decimal256>,
+    /// Tags collaterals of the same asset class
+    pub correlation_group: Option<String>,
+    /// Overrides the global min lock amount
+    pub min_lock_amount: Option<Uint256>,
+    /// Height window the collateral backs borrows and can be locked in,
+    /// unlocks and liquidations are allowed at any height
+    pub borrow_enabled_from: Option<u64>,
+    pub borrow_disabled_from: Option<u64>,
+    /// Overrides the global liquidation contract, it must be the
+    /// liquidation contract the custody contracts send the collateral to
+    pub liquidation_contract: Option<CanonicalAddr>,
+    /// Oracle prices are quoted per 6 decimal unit of the collateral
+    pub decimals: u8,
+    /// Contract reporting the underlying asset amount of a collateral token
+    pub exchange_rate_source: Option<CanonicalAddr>,
+    /// Per-block rate added to the borrow rate of the debt it backs
+    pub borrow_rate_premium: Option<Decimal256>,
+    pub token_type: TokenType,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CustodyContractElem {
     pub custody_contract: CanonicalAddr,
+    pub weight: Decimal256,
+}
+
+/// Whitelist layout before multiple custody contracts were supported,
+/// only used to migrate the stored whitelist
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyWhitelistElem {
+    pub name: String,
+    pub symbol: String,
+    pub max_ltv: Decimal256,
+    pub custody_contract: CanonicalAddr,
+}
+
+/// Config layout before the price timeframes were split,
+/// only used to migrate the stored config
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyConfig {
+    pub owner_addr: CanonicalAddr,
+    pub oracle_contract: CanonicalAddr,
+    pub market_contract: CanonicalAddr,
+    pub liquidation_contract: CanonicalAddr,
+    pub collector_contract: CanonicalAddr,
+    pub stable_denom: String,
+    pub epoch_period: u64,
+    pub threshold_deposit_rate: Decimal256,
+    pub target_deposit_rate: Decimal256,
+    pub buffer_distribution_factor: Decimal256,
+    pub anc_purchase_factor: Decimal256,
+    pub price_timeframe: u64,
 }
 
 pub fn store_config(storage: &mut dyn Storage, data: &Config) -> StdResult<()> {
@@ -55,6 +328,10 @@ pub fn read_config(storage: &dyn Storage) -> StdResult<Config> {
     ReadonlySingleton::new(storage, KEY_CONFIG).load()
 }
 
+pub fn read_legacy_config(storage: &dyn Storage) -> StdResult<LegacyConfig> {
+    ReadonlySingleton::new(storage, KEY_CONFIG).load()
+}
+
 pub fn store_epoch_state(storage: &mut dyn Storage, data: &EpochState) -> StdResult<()> {
     Singleton::new(storage, KEY_EPOCH_STATE).save(data)
 }
@@ -63,6 +340,116 @@ pub fn read_epoch_state(storage: &dyn Storage) -> StdResult<EpochState> {
     ReadonlySingleton::new(storage, KEY_EPOCH_STATE).load()
 }
 
+pub fn store_epoch_steps(storage: &mut dyn Storage, data: &EpochSteps) -> StdResult<()> {
+    Singleton::new(storage, KEY_EPOCH_STEPS).save(data)
+}
+
+pub fn read_epoch_steps(storage: &dyn Storage) -> StdResult<Option<EpochSteps>> {
+    ReadonlySingleton::new(storage, KEY_EPOCH_STEPS).may_load()
+}
+
+pub fn remove_epoch_steps(storage: &mut dyn Storage) {
+    Singleton::<EpochSteps>::new(storage, KEY_EPOCH_STEPS).remove()
+}
+
+pub fn store_epoch_progress(storage: &mut dyn Storage, data: &EpochProgress) -> StdResult<()> {
+    Singleton::new(storage, KEY_EPOCH_PROGRESS).save(data)
+}
+
+pub fn read_epoch_progress(storage: &dyn Storage) -> StdResult<Option<EpochProgress>> {
+    ReadonlySingleton::new(storage, KEY_EPOCH_PROGRESS).may_load()
+}
+
+pub fn remove_epoch_progress(storage: &mut dyn Storage) {
+    Singleton::<EpochProgress>::new(storage, KEY_EPOCH_PROGRESS).remove()
+}
+
+pub fn store_custody_quorum(storage: &mut dyn Storage, data: &CustodyQuorum) -> StdResult<()> {
+    Singleton::new(storage, KEY_CUSTODY_QUORUM).save(data)
+}
+
+pub fn read_custody_quorum(storage: &dyn Storage) -> StdResult<Option<CustodyQuorum>> {
+    ReadonlySingleton::new(storage, KEY_CUSTODY_QUORUM).may_load()
+}
+
+pub fn remove_custody_quorum(storage: &mut dyn Storage) {
+    Singleton::<CustodyQuorum>::new(storage, KEY_CUSTODY_QUORUM).remove()
+}
+
+pub fn store_collateral_transfers(
+    storage: &mut dyn Storage,
+    data: &CollateralTransfers,
+) -> StdResult<()> {
+    Singleton::new(storage, KEY_COLLATERAL_TRANSFERS).save(data)
+}
+
+pub fn read_collateral_transfers(storage: &dyn Storage) -> StdResult<Option<CollateralTransfers>> {
+    ReadonlySingleton::new(storage, KEY_COLLATERAL_TRANSFERS).may_load()
+}
+
+pub fn store_epoch_totals(storage: &mut dyn Storage, data: &EpochTotals) -> StdResult<()> {
+    Singleton::new(storage, KEY_EPOCH_TOTALS).save(data)
+}
+
+/// Interest buffer spent on shortfalls since the last epoch operations
+pub fn store_backstop_used(storage: &mut dyn Storage, amount: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_BACKSTOP_USED).save(amount)
+}
+
+pub fn read_backstop_used(storage: &dyn Storage) -> StdResult<Uint256> {
+    Ok(ReadonlySingleton::new(storage, KEY_BACKSTOP_USED)
+        .may_load()?
+        .unwrap_or_else(Uint256::zero))
+}
+
+/// Interest buffer spent on settling bad debt since the last epoch operations
+pub fn store_bad_debt_settled(storage: &mut dyn Storage, amount: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_BAD_DEBT_SETTLED).save(amount)
+}
+
+pub fn read_bad_debt_settled(storage: &dyn Storage) -> StdResult<Uint256> {
+    Ok(ReadonlySingleton::new(storage, KEY_BAD_DEBT_SETTLED)
+        .may_load()?
+        .unwrap_or_else(Uint256::zero))
+}
+
+/// Stable amount of the interest buffer deposited into the market
+pub fn store_buffer_deposit(storage: &mut dyn Storage, amount: &Uint256) -> StdResult<()> {
+    Singleton::new(storage, KEY_BUFFER_DEPOSIT).save(amount)
+}
+
+pub fn read_buffer_deposit(storage: &dyn Storage) -> StdResult<Uint256> {
+    Ok(ReadonlySingleton::new(storage, KEY_BUFFER_DEPOSIT)
+        .may_load()?
+        .unwrap_or_else(Uint256::zero))
+}
+
+/// Height the buffer deposit was last redeemed at, the epoch operations
+/// of that height run with what the redemption returned
+pub fn store_buffer_redeem_height(storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+    Singleton::new(storage, KEY_BUFFER_REDEEM_HEIGHT).save(&height)
+}
+
+pub fn read_buffer_redeem_height(storage: &dyn Storage) -> StdResult<Option<u64>> {
+    ReadonlySingleton::new(storage, KEY_BUFFER_REDEEM_HEIGHT).may_load()
+}
+
+pub fn store_fairness_fund(storage: &mut dyn Storage, fund: &FairnessFund) -> StdResult<()> {
+    Singleton::new(storage, KEY_FAIRNESS_FUND).save(fund)
+}
+
+pub fn read_fairness_fund(storage: &dyn Storage) -> StdResult<FairnessFund> {
+    Ok(ReadonlySingleton::new(storage, KEY_FAIRNESS_FUND)
+        .may_load()?
+        .unwrap_or_default())
+}
+
+pub fn read_epoch_totals(storage: &dyn Storage) -> StdResult<EpochTotals> {
+    Ok(ReadonlySingleton::new(storage, KEY_EPOCH_TOTALS)
+        .may_load()?
+        .unwrap_or_default())
+}
+
 pub fn store_whitelist_elem(
     storage: &mut dyn Storage,
     collateral_token: &CanonicalAddr,
@@ -88,6 +475,95 @@ pub fn read_whitelist_elem(
     }
 }
 
+/// Removes the whitelist entry with the price and lock records of the collateral
+pub fn remove_whitelist_elem(storage: &mut dyn Storage, collateral_token: &CanonicalAddr) {
+    let mut whitelist_bucket: Bucket<WhitelistElem> = Bucket::new(storage, PREFIX_WHITELIST);
+    whitelist_bucket.remove(collateral_token.as_slice());
+
+    let mut price_bucket: Bucket<Decimal256> = Bucket::new(storage, PREFIX_LAST_PRICE);
+    price_bucket.remove(collateral_token.as_slice());
+
+    let mut total_locked_bucket: Bucket<Uint256> = Bucket::new(storage, PREFIX_TOTAL_LOCKED);
+    total_locked_bucket.remove(collateral_token.as_slice());
+}
+
+/// Reads the whole whitelist without pagination
+pub fn read_all_whitelist_elems(
+    storage: &dyn Storage,
+) -> StdResult<Vec<(CanonicalAddr, WhitelistElem)>> {
+    let whitelist_bucket: ReadonlyBucket<WhitelistElem> =
+        ReadonlyBucket::new(storage, PREFIX_WHITELIST);
+
+    whitelist_bucket
+        .range(None, None, Order::Ascending)
+        .map(|elem| {
+            let (k, v) = elem?;
+            Ok((CanonicalAddr::from(k), v))
+        })
+        .collect()
+}
+
+pub fn read_legacy_whitelist(
+    storage: &dyn Storage,
+) -> StdResult<Vec<(CanonicalAddr, LegacyWhitelistElem)>> {
+    let whitelist_bucket: ReadonlyBucket<LegacyWhitelistElem> =
+        ReadonlyBucket::new(storage, PREFIX_WHITELIST);
+
+    whitelist_bucket
+        .range(None, None, Order::Ascending)
+        .map(|elem| {
+            let (k, v) = elem?;
+            Ok((CanonicalAddr::from(k), v))
+        })
+        .collect()
+}
+
+pub fn whitelist_response_elem(
+    deps: Deps,
+    collateral_token: String,
+    whitelist_elem: WhitelistElem,
+) -> StdResult<WhitelistResponseElem> {
+    let custody_contracts: Vec<(String, Decimal256)> = whitelist_elem
+        .custody_contracts
+        .iter()
+        .map(|c| {
+            Ok((
+                deps.api.addr_humanize(&c.custody_contract)?.to_string(),
+                c.weight,
+            ))
+        })
+        .collect::<StdResult<Vec<(String, Decimal256)>>>()?;
+
+    Ok(WhitelistResponseElem {
+        name: whitelist_elem.name,
+        symbol: whitelist_elem.symbol,
+        max_ltv: whitelist_elem.max_ltv,
+        custody_contract: custody_contracts
+            .first()
+            .map(|c| c.0.clone())
+            .unwrap_or_default(),
+        custody_contracts,
+        collateral_token,
+        quote_denom: whitelist_elem.quote_denom,
+        liquidation_premium: whitelist_elem.liquidation_premium,
+        correlation_group: whitelist_elem.correlation_group,
+        min_lock_amount: whitelist_elem.min_lock_amount,
+        borrow_enabled_from: whitelist_elem.borrow_enabled_from,
+        borrow_disabled_from: whitelist_elem.borrow_disabled_from,
+        liquidation_contract: whitelist_elem
+            .liquidation_contract
+            .map(|c| deps.api.addr_humanize(&c).map(|c| c.to_string()))
+            .transpose()?,
+        decimals: whitelist_elem.decimals,
+        exchange_rate_source: whitelist_elem
+            .exchange_rate_source
+            .map(|c| deps.api.addr_humanize(&c).map(|c| c.to_string()))
+            .transpose()?,
+        borrow_rate_premium: whitelist_elem.borrow_rate_premium,
+        token_type: whitelist_elem.token_type,
+    })
+}
+
 pub fn read_whitelist(
     deps: Deps,
     start_after: Option<CanonicalAddr>,
@@ -105,14 +581,7 @@ pub fn read_whitelist(
         .map(|elem| {
             let (k, v) = elem?;
             let collateral_token = deps.api.addr_humanize(&CanonicalAddr::from(k))?.to_string();
-            let custody_contract = deps.api.addr_humanize(&v.custody_contract)?.to_string();
-            Ok(WhitelistResponseElem {
-                name: v.name,
-                symbol: v.symbol,
-                collateral_token,
-                custody_contract,
-                max_ltv: v.max_ltv,
-            })
+            whitelist_response_elem(deps, collateral_token, v)
         })
         .collect()
 }
@@ -121,60 +590,669 @@ pub fn read_whitelist(
 pub fn store_collaterals(
     storage: &mut dyn Storage,
     borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
     collaterals: &Tokens,
 ) -> StdResult<()> {
-    let mut collaterals_bucket: Bucket<Tokens> = Bucket::new(storage, PREFIX_COLLATERALS);
+    let (mut collaterals_bucket, key): (Bucket<Tokens>, &[u8]) = match sub_account {
+        Some(sub_account) => (
+            Bucket::multilevel(
+                storage,
+                &[PREFIX_SUB_ACCOUNT_COLLATERALS, borrower.as_slice()],
+            ),
+            sub_account.as_bytes(),
+        ),
+        None => (
+            Bucket::new(storage, PREFIX_COLLATERALS),
+            borrower.as_slice(),
+        ),
+    };
     if collaterals.is_empty() {
-        collaterals_bucket.remove(borrower.as_slice());
+        collaterals_bucket.remove(key);
     } else {
-        collaterals_bucket.save(borrower.as_slice(), collaterals)?;
+        collaterals_bucket.save(key, collaterals)?;
     }
 
     Ok(())
 }
 
-pub fn read_collaterals(storage: &dyn Storage, borrower: &CanonicalAddr) -> Tokens {
-    let collaterals_bucket: ReadonlyBucket<Tokens> =
-        ReadonlyBucket::new(storage, PREFIX_COLLATERALS);
-    match collaterals_bucket.load(borrower.as_slice()) {
-        Ok(v) => v,
-        _ => vec![],
+/// Sub-account positions are kept out of the default collaterals bucket,
+/// so AllCollaterals only pages through default positions
+pub fn read_collaterals(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+) -> Tokens {
+    let (collaterals_bucket, key): (ReadonlyBucket<Tokens>, &[u8]) = match sub_account {
+        Some(sub_account) => (
+            ReadonlyBucket::multilevel(
+                storage,
+                &[PREFIX_SUB_ACCOUNT_COLLATERALS, borrower.as_slice()],
+            ),
+            sub_account.as_bytes(),
+        ),
+        None => (
+            ReadonlyBucket::new(storage, PREFIX_COLLATERALS),
+            borrower.as_slice(),
+        ),
+    };
+    collaterals_bucket.load(key).unwrap_or_default()
+}
+
+/// Amounts of the collateral each custody contract holds locked for the
+/// borrower, across the default position and the sub-accounts
+pub fn store_custody_locks(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+    custody_locks: &Tokens,
+) -> StdResult<()> {
+    let mut custody_locks_bucket: Bucket<Tokens> =
+        Bucket::multilevel(storage, &[PREFIX_CUSTODY_LOCKS, borrower.as_slice()]);
+    if custody_locks.is_empty() {
+        custody_locks_bucket.remove(collateral_token.as_slice());
+        Ok(())
+    } else {
+        custody_locks_bucket.save(collateral_token.as_slice(), custody_locks)
+    }
+}
+
+pub fn read_custody_locks(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    collateral_token: &CanonicalAddr,
+) -> Tokens {
+    let custody_locks_bucket: ReadonlyBucket<Tokens> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_CUSTODY_LOCKS, borrower.as_slice()]);
+    custody_locks_bucket
+        .load(collateral_token.as_slice())
+        .unwrap_or_default()
+}
+
+pub fn store_recent_lock(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+    recent_lock: &RecentLock,
+) -> StdResult<()> {
+    let (mut recent_lock_bucket, key): (Bucket<RecentLock>, &[u8]) = match sub_account {
+        Some(sub_account) => (
+            Bucket::multilevel(
+                storage,
+                &[PREFIX_SUB_ACCOUNT_RECENT_LOCK, borrower.as_slice()],
+            ),
+            sub_account.as_bytes(),
+        ),
+        None => (
+            Bucket::new(storage, PREFIX_RECENT_LOCK),
+            borrower.as_slice(),
+        ),
+    };
+    recent_lock_bucket.save(key, recent_lock)
+}
+
+pub fn read_recent_lock(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    sub_account: Option<&str>,
+) -> StdResult<Option<RecentLock>> {
+    let (recent_lock_bucket, key): (ReadonlyBucket<RecentLock>, &[u8]) = match sub_account {
+        Some(sub_account) => (
+            ReadonlyBucket::multilevel(
+                storage,
+                &[PREFIX_SUB_ACCOUNT_RECENT_LOCK, borrower.as_slice()],
+            ),
+            sub_account.as_bytes(),
+        ),
+        None => (
+            ReadonlyBucket::new(storage, PREFIX_RECENT_LOCK),
+            borrower.as_slice(),
+        ),
+    };
+    recent_lock_bucket.may_load(key)
+}
+
+/// Reads a page of the named positions of a borrower, ordered by name
+pub fn read_sub_account_collaterals(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(String, Tokens)>> {
+    let collaterals_bucket: ReadonlyBucket<Tokens> = ReadonlyBucket::multilevel(
+        storage,
+        &[PREFIX_SUB_ACCOUNT_COLLATERALS, borrower.as_slice()],
+    );
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|sub_account| {
+        let mut v = sub_account.into_bytes();
+        v.push(1);
+        v
+    });
+
+    collaterals_bucket
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|elem| {
+            let (k, v) = elem?;
+            let sub_account = String::from_utf8(k)
+                .map_err(|_| StdError::generic_err("Invalid sub account key"))?;
+            Ok((sub_account, v))
+        })
+        .collect()
+}
+
+pub fn store_last_price(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    price: &Decimal256,
+) -> StdResult<()> {
+    let mut price_bucket: Bucket<Decimal256> = Bucket::new(storage, PREFIX_LAST_PRICE);
+    price_bucket.save(collateral_token.as_slice(), price)
+}
+
+/// Price of the collateral used in the last executed borrow limit computation
+pub fn read_last_price(
+    storage: &dyn Storage,
+    collateral_token: &CanonicalAddr,
+) -> Option<Decimal256> {
+    let price_bucket: ReadonlyBucket<Decimal256> = ReadonlyBucket::new(storage, PREFIX_LAST_PRICE);
+    price_bucket
+        .may_load(collateral_token.as_slice())
+        .unwrap_or(None)
+}
+
+pub fn store_price_observation(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    observation: &PriceObservation,
+) -> StdResult<()> {
+    let mut observation_bucket: Bucket<PriceObservation> =
+        Bucket::new(storage, PREFIX_PRICE_OBSERVATION);
+    observation_bucket.save(collateral_token.as_slice(), observation)
+}
+
+pub fn read_price_observation(
+    storage: &dyn Storage,
+    collateral_token: &CanonicalAddr,
+) -> StdResult<Option<PriceObservation>> {
+    let observation_bucket: ReadonlyBucket<PriceObservation> =
+        ReadonlyBucket::new(storage, PREFIX_PRICE_OBSERVATION);
+    observation_bucket.may_load(collateral_token.as_slice())
+}
+
+pub fn store_price_drop(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    price_drop: &PriceDrop,
+) -> StdResult<()> {
+    let mut price_drop_bucket: Bucket<PriceDrop> = Bucket::new(storage, PREFIX_PRICE_DROP);
+    price_drop_bucket.save(collateral_token.as_slice(), price_drop)
+}
+
+pub fn read_price_drop(
+    storage: &dyn Storage,
+    collateral_token: &CanonicalAddr,
+) -> StdResult<Option<PriceDrop>> {
+    let price_drop_bucket: ReadonlyBucket<PriceDrop> =
+        ReadonlyBucket::new(storage, PREFIX_PRICE_DROP);
+    price_drop_bucket.may_load(collateral_token.as_slice())
+}
+
+pub fn store_position_transfer(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    transfer: &PositionTransfer,
+) -> StdResult<()> {
+    let mut transfer_bucket: Bucket<PositionTransfer> =
+        Bucket::new(storage, PREFIX_POSITION_TRANSFER);
+    transfer_bucket.save(borrower.as_slice(), transfer)
+}
+
+pub fn read_position_transfer(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+) -> StdResult<Option<PositionTransfer>> {
+    let transfer_bucket: ReadonlyBucket<PositionTransfer> =
+        ReadonlyBucket::new(storage, PREFIX_POSITION_TRANSFER);
+    transfer_bucket.may_load(borrower.as_slice())
+}
+
+pub fn remove_position_transfer(storage: &mut dyn Storage, borrower: &CanonicalAddr) {
+    let mut transfer_bucket: Bucket<PositionTransfer> =
+        Bucket::new(storage, PREFIX_POSITION_TRANSFER);
+    transfer_bucket.remove(borrower.as_slice())
+}
+
+pub fn store_liquidation_delegation(
+    storage: &mut dyn Storage,
+    borrower: &CanonicalAddr,
+    delegation: &LiquidationDelegation,
+) -> StdResult<()> {
+    let mut delegation_bucket: Bucket<LiquidationDelegation> =
+        Bucket::new(storage, PREFIX_LIQUIDATION_DELEGATION);
+    delegation_bucket.save(borrower.as_slice(), delegation)
+}
+
+pub fn read_liquidation_delegation(
+    storage: &dyn Storage,
+    borrower: &CanonicalAddr,
+) -> StdResult<Option<LiquidationDelegation>> {
+    let delegation_bucket: ReadonlyBucket<LiquidationDelegation> =
+        ReadonlyBucket::new(storage, PREFIX_LIQUIDATION_DELEGATION);
+    delegation_bucket.may_load(borrower.as_slice())
+}
+
+pub fn remove_liquidation_delegation(storage: &mut dyn Storage, borrower: &CanonicalAddr) {
+    let mut delegation_bucket: Bucket<LiquidationDelegation> =
+        Bucket::new(storage, PREFIX_LIQUIDATION_DELEGATION);
+    delegation_bucket.remove(borrower.as_slice())
+}
+
+pub fn store_total_locked(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    amount: &Uint256,
+) -> StdResult<()> {
+    let mut total_locked_bucket: Bucket<Uint256> = Bucket::new(storage, PREFIX_TOTAL_LOCKED);
+    total_locked_bucket.save(collateral_token.as_slice(), amount)
+}
+
+/// Amount of the collateral locked by all borrowers
+pub fn read_total_locked(storage: &dyn Storage, collateral_token: &CanonicalAddr) -> Uint256 {
+    let total_locked_bucket: ReadonlyBucket<Uint256> =
+        ReadonlyBucket::new(storage, PREFIX_TOTAL_LOCKED);
+    total_locked_bucket
+        .may_load(collateral_token.as_slice())
+        .unwrap_or(None)
+        .unwrap_or_default()
+}
+
+/// Liquidation waiting for the market repayment to be recorded
+pub fn store_pending_liquidation_record(
+    storage: &mut dyn Storage,
+    record: &LiquidationRecord,
+) -> StdResult<()> {
+    Singleton::new(storage, KEY_PENDING_LIQUIDATION_RECORD).save(record)
+}
+
+pub fn read_pending_liquidation_record(
+    storage: &dyn Storage,
+) -> StdResult<Option<LiquidationRecord>> {
+    ReadonlySingleton::new(storage, KEY_PENDING_LIQUIDATION_RECORD).may_load()
+}
+
+pub fn remove_pending_liquidation_record(storage: &mut dyn Storage) {
+    let mut store: Singleton<LiquidationRecord> =
+        Singleton::new(storage, KEY_PENDING_LIQUIDATION_RECORD);
+    store.remove()
+}
+
+/// Appends the record to the liquidation history and prunes the records
+/// older than the last MAX_LIQUIDATION_HISTORY ones, returns the record id
+pub fn store_liquidation_record(
+    storage: &mut dyn Storage,
+    record: &LiquidationRecord,
+) -> StdResult<u64> {
+    let id: u64 = ReadonlySingleton::new(storage, KEY_LIQUIDATION_COUNT)
+        .may_load()?
+        .unwrap_or(0);
+    Singleton::new(storage, KEY_LIQUIDATION_COUNT).save(&(id + 1))?;
+
+    let mut history_bucket: Bucket<LiquidationRecord> =
+        Bucket::new(storage, PREFIX_LIQUIDATION_HISTORY);
+    history_bucket.save(&id.to_be_bytes(), record)?;
+    if id >= MAX_LIQUIDATION_HISTORY {
+        history_bucket.remove(&(id - MAX_LIQUIDATION_HISTORY).to_be_bytes());
+    }
+
+    Ok(id)
+}
+
+pub fn read_liquidation_records(
+    storage: &dyn Storage,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(u64, LiquidationRecord)>> {
+    let history_bucket: ReadonlyBucket<LiquidationRecord> =
+        ReadonlyBucket::new(storage, PREFIX_LIQUIDATION_HISTORY);
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+
+    history_bucket
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|elem| {
+            let (k, v) = elem?;
+            let mut id = [0u8; 8];
+            id.copy_from_slice(&k);
+            Ok((u64::from_be_bytes(id), v))
+        })
+        .collect()
+}
+
+pub fn read_liquidation_record(
+    storage: &dyn Storage,
+    id: u64,
+) -> StdResult<Option<LiquidationRecord>> {
+    let history_bucket: ReadonlyBucket<LiquidationRecord> =
+        ReadonlyBucket::new(storage, PREFIX_LIQUIDATION_HISTORY);
+    history_bucket.may_load(&id.to_be_bytes())
+}
+
+pub fn store_liquidation_dispute(
+    storage: &mut dyn Storage,
+    id: u64,
+    dispute: &LiquidationDispute,
+) -> StdResult<()> {
+    let mut dispute_bucket: Bucket<LiquidationDispute> =
+        Bucket::new(storage, PREFIX_LIQUIDATION_DISPUTE);
+    dispute_bucket.save(&id.to_be_bytes(), dispute)
+}
+
+pub fn read_liquidation_dispute(
+    storage: &dyn Storage,
+    id: u64,
+) -> StdResult<Option<LiquidationDispute>> {
+    let dispute_bucket: ReadonlyBucket<LiquidationDispute> =
+        ReadonlyBucket::new(storage, PREFIX_LIQUIDATION_DISPUTE);
+    dispute_bucket.may_load(&id.to_be_bytes())
+}
+
+/// Appends the record to the epoch rate history and prunes the records
+/// older than the last MAX_EPOCH_RATE_HISTORY ones
+pub fn store_epoch_rate_record(
+    storage: &mut dyn Storage,
+    record: &EpochRateRecord,
+) -> StdResult<()> {
+    let id: u64 = ReadonlySingleton::new(storage, KEY_EPOCH_RATE_COUNT)
+        .may_load()?
+        .unwrap_or(0);
+    Singleton::new(storage, KEY_EPOCH_RATE_COUNT).save(&(id + 1))?;
+
+    let mut history_bucket: Bucket<EpochRateRecord> =
+        Bucket::new(storage, PREFIX_EPOCH_RATE_HISTORY);
+    history_bucket.save(&id.to_be_bytes(), record)?;
+    if id >= MAX_EPOCH_RATE_HISTORY {
+        history_bucket.remove(&(id - MAX_EPOCH_RATE_HISTORY).to_be_bytes());
     }
+
+    Ok(())
+}
+
+/// Most recent epoch rate records, newest first
+pub fn read_epoch_rate_records(
+    storage: &dyn Storage,
+    limit: Option<u32>,
+) -> StdResult<Vec<EpochRateRecord>> {
+    let history_bucket: ReadonlyBucket<EpochRateRecord> =
+        ReadonlyBucket::new(storage, PREFIX_EPOCH_RATE_HISTORY);
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    history_bucket
+        .range(None, None, Order::Descending)
+        .take(limit)
+        .map(|elem| {
+            let (_, v) = elem?;
+            Ok(v)
+        })
+        .collect()
+}
+
+/// Deposit rates of the last `count` epoch rate records, newest first
+pub fn read_recent_deposit_rates(storage: &dyn Storage, count: u64) -> StdResult<Vec<Decimal256>> {
+    let history_bucket: ReadonlyBucket<EpochRateRecord> =
+        ReadonlyBucket::new(storage, PREFIX_EPOCH_RATE_HISTORY);
+
+    history_bucket
+        .range(None, None, Order::Descending)
+        .take(count as usize)
+        .map(|elem| {
+            let (_, v) = elem?;
+            Ok(v.deposit_rate)
+        })
+        .collect()
+}
+
+/// Appends the record to the buffer flow history and prunes the records
+/// older than the last MAX_BUFFER_FLOW_HISTORY ones
+pub fn store_buffer_flow_record(
+    storage: &mut dyn Storage,
+    record: &BufferFlowRecord,
+) -> StdResult<()> {
+    let id: u64 = ReadonlySingleton::new(storage, KEY_BUFFER_FLOW_COUNT)
+        .may_load()?
+        .unwrap_or(0);
+    Singleton::new(storage, KEY_BUFFER_FLOW_COUNT).save(&(id + 1))?;
+
+    let mut history_bucket: Bucket<BufferFlowRecord> =
+        Bucket::new(storage, PREFIX_BUFFER_FLOW_HISTORY);
+    history_bucket.save(&id.to_be_bytes(), record)?;
+    if id >= MAX_BUFFER_FLOW_HISTORY {
+        history_bucket.remove(&(id - MAX_BUFFER_FLOW_HISTORY).to_be_bytes());
+    }
+
+    Ok(())
+}
+
+/// Most recent buffer flow records, newest first
+pub fn read_buffer_flow_records(
+    storage: &dyn Storage,
+    limit: Option<u32>,
+) -> StdResult<Vec<BufferFlowRecord>> {
+    let history_bucket: ReadonlyBucket<BufferFlowRecord> =
+        ReadonlyBucket::new(storage, PREFIX_BUFFER_FLOW_HISTORY);
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    history_bucket
+        .range(None, None, Order::Descending)
+        .take(limit)
+        .map(|elem| {
+            let (_, v) = elem?;
+            Ok(v)
+        })
+        .collect()
+}
+
+pub fn store_ltv_change(
+    storage: &mut dyn Storage,
+    collateral_token: &CanonicalAddr,
+    ltv_change: &LtvChange,
+) -> StdResult<()> {
+    let mut ltv_change_bucket: Bucket<LtvChange> = Bucket::new(storage, PREFIX_LTV_CHANGE);
+    ltv_change_bucket.save(collateral_token.as_slice(), ltv_change)
+}
+
+pub fn read_ltv_change(
+    storage: &dyn Storage,
+    collateral_token: &CanonicalAddr,
+) -> StdResult<Option<LtvChange>> {
+    let ltv_change_bucket: ReadonlyBucket<LtvChange> =
+        ReadonlyBucket::new(storage, PREFIX_LTV_CHANGE);
+    ltv_change_bucket.may_load(collateral_token.as_slice())
+}
+
+/// Reads a page of the whitelist with the raw collateral token keys
+pub fn read_whitelist_elems(
+    storage: &dyn Storage,
+    start_after: Option<CanonicalAddr>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(CanonicalAddr, WhitelistElem)>> {
+    let whitelist_bucket: ReadonlyBucket<WhitelistElem> =
+        ReadonlyBucket::new(storage, PREFIX_WHITELIST);
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = calc_range_start(start_after);
+
+    whitelist_bucket
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|elem| {
+            let (k, v) = elem?;
+            Ok((CanonicalAddr::from(k), v))
+        })
+        .collect()
 }
 
 // settings for pagination
-const MAX_LIMIT: u32 = 30;
-const DEFAULT_LIMIT: u32 = 10;
+pub const MAX_LIMIT: u32 = 30;
+pub const DEFAULT_LIMIT: u32 = 10;
+/// Pages over the borrowers with a default or a sub-account position,
+/// each listed with its default position first
 pub fn read_all_collaterals(
     deps: Deps,
     start_after: Option<CanonicalAddr>,
     limit: Option<u32>,
+    canonical: bool,
 ) -> StdResult<Vec<CollateralsResponse>> {
-    let whitelist_bucket: ReadonlyBucket<Tokens> =
-        ReadonlyBucket::new(deps.storage, PREFIX_COLLATERALS);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let mut all_collaterals: Vec<CollateralsResponse> = vec![];
+    for borrower_raw in read_position_borrowers(deps.storage, start_after, limit)? {
+        let borrower = format_addr(deps, &borrower_raw, canonical)?;
+        let mut positions: Vec<(Option<String>, Tokens)> = vec![];
+        let collaterals = read_collaterals(deps.storage, &borrower_raw, None);
+        if !collaterals.is_empty() {
+            positions.push((None, collaterals));
+        }
+
+        let mut sub_account_start_after: Option<String> = None;
+        loop {
+            let sub_accounts = read_sub_account_collaterals(
+                deps.storage,
+                &borrower_raw,
+                sub_account_start_after,
+                None,
+            )?;
+            if sub_accounts.is_empty() {
+                break;
+            }
+
+            sub_account_start_after = sub_accounts.last().map(|(name, _)| name.clone());
+            positions.extend(
+                sub_accounts
+                    .into_iter()
+                    .map(|(sub_account, collaterals)| (Some(sub_account), collaterals)),
+            );
+        }
+
+        for (sub_account, collaterals) in positions {
+            all_collaterals.push(CollateralsResponse {
+                borrower: borrower.clone(),
+                sub_account,
+                collaterals: collaterals
+                    .iter()
+                    .map(|c| Ok((format_addr(deps, &c.0, canonical)?, c.1)))
+                    .collect::<StdResult<Vec<(String, Uint256)>>>()?,
+            });
+        }
+    }
+
+    Ok(all_collaterals)
+}
+
+/// Reads a page of the borrowers with a default or a sub-account position,
+/// ordered by address. Sub-account keys start with the length-prefixed borrower
+fn read_position_borrowers(
+    storage: &dyn Storage,
+    start_after: Option<CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let collaterals_bucket: ReadonlyBucket<Tokens> =
+        ReadonlyBucket::new(storage, PREFIX_COLLATERALS);
+    let mut borrowers: Vec<CanonicalAddr> = collaterals_bucket
+        .range(
+            calc_range_start(start_after.clone()).as_deref(),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|elem| Ok(CanonicalAddr::from(elem?.0)))
+        .collect::<StdResult<Vec<CanonicalAddr>>>()?;
+
+    let sub_account_bucket: ReadonlyBucket<Tokens> =
+        ReadonlyBucket::new(storage, PREFIX_SUB_ACCOUNT_COLLATERALS);
+    let sub_account_start = start_after
+        .as_ref()
+        .map(|borrower| to_length_prefixed(borrower.as_slice()));
+    let mut sub_account_borrowers: Vec<CanonicalAddr> = vec![];
+    for elem in sub_account_bucket.range(sub_account_start.as_deref(), None, Order::Ascending) {
+        let (k, _) = elem?;
+        let len = u16::from_be_bytes([k[0], k[1]]) as usize;
+        let borrower = CanonicalAddr::from(k[2..2 + len].to_vec());
+        if start_after.as_ref() == Some(&borrower)
+            || sub_account_borrowers.last() == Some(&borrower)
+        {
+            continue;
+        }
+        if sub_account_borrowers.len() == limit {
+            break;
+        }
+
+        sub_account_borrowers.push(borrower);
+    }
+
+    borrowers.extend(sub_account_borrowers);
+    borrowers.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+    borrowers.dedup();
+    borrowers.truncate(limit);
+    Ok(borrowers)
+}
+
+/// Formats a stored address either as its human form or, for indexers
+/// keyed by raw bytes, as the hex encoded canonical address
+pub fn format_addr(deps: Deps, addr: &CanonicalAddr, canonical: bool) -> StdResult<String> {
+    if canonical {
+        Ok(addr.to_string())
+    } else {
+        Ok(deps.api.addr_humanize(addr)?.to_string())
+    }
+}
+
+/// Reads a page of the default positions, ordered by borrower
+pub fn read_collaterals_page(
+    storage: &dyn Storage,
+    start_after: Option<CanonicalAddr>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(CanonicalAddr, Tokens)>> {
+    let collaterals_bucket: ReadonlyBucket<Tokens> =
+        ReadonlyBucket::new(storage, PREFIX_COLLATERALS);
 
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start = calc_range_start(start_after);
 
-    whitelist_bucket
+    collaterals_bucket
         .range(start.as_deref(), None, Order::Ascending)
         .take(limit)
         .map(|elem| {
             let (k, v) = elem?;
-            let borrower = deps.api.addr_humanize(&CanonicalAddr::from(k))?.to_string();
-            let collaterals: Vec<(String, Uint256)> = v
-                .iter()
-                .map(|c| Ok((deps.api.addr_humanize(&c.0)?.to_string(), c.1)))
-                .collect::<StdResult<Vec<(String, Uint256)>>>()?;
-
-            Ok(CollateralsResponse {
-                borrower,
-                collaterals,
-            })
+            Ok((CanonicalAddr::from(k), v))
         })
         .collect()
 }
 
+/// Sums the locked amount of each collateral over the default positions
+/// and the sub-accounts of all borrowers
+pub fn sum_locked_collaterals(storage: &dyn Storage) -> StdResult<Tokens> {
+    let collaterals_bucket: ReadonlyBucket<Tokens> =
+        ReadonlyBucket::new(storage, PREFIX_COLLATERALS);
+    // Sub-account keys are prefixed by their borrower below the namespace,
+    // ranging over it visits the sub-accounts of every borrower
+    let sub_account_bucket: ReadonlyBucket<Tokens> =
+        ReadonlyBucket::new(storage, PREFIX_SUB_ACCOUNT_COLLATERALS);
+
+    let mut total_locked: Tokens = vec![];
+    for item in collaterals_bucket
+        .range(None, None, Order::Ascending)
+        .chain(sub_account_bucket.range(None, None, Order::Ascending))
+    {
+        total_locked.add(item?.1);
+    }
+
+    Ok(total_locked)
+}
+
 // this will set the first key after the provided key, by appending a 1 byte
 fn calc_range_start(start_after: Option<CanonicalAddr>) -> Option<Vec<u8>> {
     start_after.map(|addr| {
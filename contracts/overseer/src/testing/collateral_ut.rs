@@ -1,12 +1,14 @@
 use crate::collateral::compute_borrow_limit;
-use crate::contract::{execute, instantiate};
+use crate::contract::{execute, instantiate, query};
+use crate::error::ContractError;
+use crate::testing::instantiate_msg;
 use crate::testing::mock_querier::mock_dependencies;
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::testing::{mock_env, mock_info};
-use cosmwasm_std::Api;
+use cosmwasm_std::{Api, OverflowOperation, StdError};
 
-use moneymarket::overseer::{ExecuteMsg, InstantiateMsg};
+use moneymarket::overseer::{ExecuteMsg, InstantiateMsg, QueryMsg};
 use moneymarket::tokens::{Token, Tokens};
 
 #[test]
@@ -15,20 +17,7 @@ fn proper_compute_borrow_limit() {
 
     let info = mock_info("owner", &[]);
     let env = mock_env();
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
+    let msg = instantiate_msg();
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
@@ -40,6 +29,17 @@ fn proper_compute_borrow_limit() {
         collateral_token: "bluna".to_string(),
         custody_contract: "custody_bluna".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
     let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
@@ -50,6 +50,17 @@ fn proper_compute_borrow_limit() {
         collateral_token: "batom".to_string(),
         custody_contract: "custody_batom".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
     let _res = execute(deps.as_mut(), env.clone(), info, msg);
@@ -94,3 +105,218 @@ fn proper_compute_borrow_limit() {
     let res2 = (Uint256::from(1800000u128), vec);
     assert_eq!(res, res2);
 }
+
+#[test]
+fn compute_borrow_limit_with_stable_decimals() {
+    let mut borrow_limits: Vec<Uint256> = vec![];
+    for stable_decimals in [6u8, 18u8] {
+        let mut deps = mock_dependencies(&[]);
+
+        let info = mock_info("owner", &[]);
+        let env = mock_env();
+        let msg = InstantiateMsg {
+            stable_decimals,
+            ..instantiate_msg()
+        };
+
+        // we can just call .unwrap() to assert this was a success
+        let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Whitelist {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            collateral_token: "bluna".to_string(),
+            custody_contract: "custody_bluna".to_string(),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+        deps.querier.with_oracle_price(&[(
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_uint256(1000u128),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        )]);
+
+        let collaterals: Tokens = vec![(
+            deps.api.addr_canonicalize("bluna").unwrap(),
+            Uint256::from(1000u128),
+        )];
+        let (borrow_limit, collateral_prices) =
+            compute_borrow_limit(deps.as_ref(), &collaterals, None).unwrap();
+
+        // prices are scaled by 10^(stable_decimals - 6)
+        let scale = Uint256::from(10u128.pow((stable_decimals - 6) as u32));
+        assert_eq!(
+            collateral_prices,
+            vec![Decimal256::from_uint256(Uint256::from(1000u128) * scale)]
+        );
+        borrow_limits.push(borrow_limit);
+    }
+
+    // 1000 * 1000 * 0.6 = 600,000 in 6 decimals
+    assert_eq!(borrow_limits[0], Uint256::from(600000u128));
+    assert_eq!(
+        borrow_limits[1],
+        borrow_limits[0] * Uint256::from(1_000_000_000_000u128)
+    );
+}
+
+#[test]
+fn compute_borrow_limit_overflow() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_uint256(u128::MAX),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let collaterals: Tokens = vec![(
+        deps.api.addr_canonicalize("bluna").unwrap(),
+        Uint256::from(u128::MAX),
+    )];
+
+    match compute_borrow_limit(deps.as_ref(), &collaterals, None) {
+        Err(StdError::Overflow { source, .. }) => {
+            assert_eq!(source.operation, OverflowOperation::Mul)
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the query surfaces the same error
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(u128::MAX))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    );
+    match res {
+        Err(StdError::Overflow { .. }) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn compute_borrow_limit_with_quote_denom() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: Some("uluna".to_string()),
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    // there is no uluna price to cross with
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::NoQuotePrice(denom)) => assert_eq!(denom, "uluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uluna".to_string()),
+            &(
+                Decimal256::percent(98),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("uluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_uint256(50u128),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let collaterals: Tokens = vec![(
+        deps.api.addr_canonicalize("bluna").unwrap(),
+        Uint256::from(1000u128),
+    )];
+
+    // price = 0.98 * 50 = 49
+    // borrow_limit = 1000 * 49 * 0.6 = 29400
+    let res =
+        compute_borrow_limit(deps.as_ref(), &collaterals, Some(env.block.time.seconds())).unwrap();
+    assert_eq!(
+        res,
+        (
+            Uint256::from(29400u128),
+            vec![Decimal256::from_uint256(49u128)]
+        )
+    );
+}
@@ -9,8 +9,21 @@ use cosmwasm_std::{
 };
 use std::collections::HashMap;
 
-use moneymarket::liquidation::LiquidationAmountResponse;
-use moneymarket::market::{BorrowerInfoResponse, EpochStateResponse};
+use anchor_token::collector::ConfigResponse as CollectorConfigResponse;
+use cw20::TokenInfoResponse;
+use moneymarket::converter::SimulationResponse;
+use moneymarket::custody::BorrowerResponse;
+use moneymarket::custody::TotalBalanceResponse;
+use moneymarket::exchange_rate::ExchangeRateResponse;
+use moneymarket::interest_model::ConfigResponse as InterestModelConfigResponse;
+use moneymarket::liquidation::{
+    ConfigResponse as LiquidationConfigResponse, LiquidationAmountResponse,
+};
+use moneymarket::liquidation_queue::ConfigResponse as LiquidationQueueConfigResponse;
+use moneymarket::market::{
+    BorrowerInfoResponse, BorrowerInfosResponse, ConfigResponse as MarketConfigResponse,
+    EpochStateResponse, MaxRedeemableResponse, SameBlockRepayPolicy, StateResponse,
+};
 use moneymarket::oracle::PriceResponse;
 use moneymarket::tokens::TokensHuman;
 
@@ -28,9 +41,24 @@ pub enum QueryMsg {
     BorrowerInfo {
         borrower: String,
         block_height: Option<u64>,
+        sub_account: Option<String>,
+    },
+    /// Query borrower list to market contract
+    BorrowerInfos {
+        start_after: Option<String>,
+        limit: Option<u32>,
     },
     /// Query oracle price to oracle contract
     Price { base: String, quote: String },
+    /// Query oracle TWAP price to oracle contract
+    TwapPrice {
+        base: String,
+        quote: String,
+        window: u64,
+    },
+    /// Query config to collector, liquidation, market
+    /// or interest model contract
+    Config {},
     /// Query liquidation amount to liquidation model contract
     LiquidationAmount {
         borrow_amount: Uint256,
@@ -38,6 +66,26 @@ pub enum QueryMsg {
         collaterals: TokensHuman,
         collateral_prices: Vec<Decimal256>,
     },
+    /// Query state to market contract
+    State { block_height: Option<u64> },
+    /// Query total supply to token contract
+    TokenInfo {},
+    /// Query token balance to token contract
+    Balance { address: String },
+    /// Query conversion amount to collateral converter contract
+    Simulation {
+        offer_token: String,
+        ask_token: String,
+        amount: Uint256,
+    },
+    /// Query exchange rate to interest-bearing collateral exchange rate source
+    ExchangeRate {},
+    /// Query redeemable aTerra amount to market contract
+    MaxRedeemable { depositor: String },
+    /// Query deposited collateral balance to custody contract
+    TotalBalance {},
+    /// Query holder rewards to custody contract
+    Borrower { address: String },
 }
 
 /// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
@@ -62,6 +110,19 @@ pub struct WasmMockQuerier {
     oracle_price_querier: OraclePriceQuerier,
     loan_amount_querier: LoanAmountQuerier,
     liquidation_percent_querier: LiquidationPercentQuerier,
+    anchor_token_querier: AnchorTokenQuerier,
+    conversion_rate_querier: ConversionRateQuerier,
+    exchange_rate_querier: ExchangeRateQuerier,
+    amm_pool_querier: AmmPoolQuerier,
+    anc_emission_rate_querier: AncEmissionRateQuerier,
+    market_reserves_querier: MarketReservesQuerier,
+    market_liabilities_querier: MarketLiabilitiesQuerier,
+    token_supply_querier: TokenSupplyQuerier,
+    token_balance_querier: TokenBalanceQuerier,
+    interest_model_querier: InterestModelQuerier,
+    max_redeemable_querier: MaxRedeemableQuerier,
+    custody_balance_querier: CustodyBalanceQuerier,
+    custody_rewards_querier: CustodyRewardsQuerier,
 }
 
 #[derive(Clone, Default)]
@@ -89,9 +150,14 @@ pub(crate) fn caps_to_map(caps: &[(&String, &Uint128)]) -> HashMap<String, Uint1
 }
 
 #[derive(Clone, Default)]
+#[allow(clippy::type_complexity)]
 pub struct OraclePriceQuerier {
     // this lets us iterate over all pairs that match the first string
     oracle_price: HashMap<(String, String), (Decimal256, u64, u64)>,
+    confidence_spread: HashMap<(String, String), Decimal256>,
+    twap_price: HashMap<(String, String), Decimal256>,
+    // prices reported by oracles other than the primary one
+    contract_oracle_price: HashMap<String, HashMap<(String, String), (Decimal256, u64, u64)>>,
 }
 
 #[allow(clippy::type_complexity)]
@@ -99,6 +165,9 @@ impl OraclePriceQuerier {
     pub fn new(oracle_price: &[(&(String, String), &(Decimal256, u64, u64))]) -> Self {
         OraclePriceQuerier {
             oracle_price: oracle_price_to_map(oracle_price),
+            confidence_spread: HashMap::new(),
+            twap_price: HashMap::new(),
+            contract_oracle_price: HashMap::new(),
         }
     }
 }
@@ -183,12 +252,16 @@ impl Querier for WasmMockQuerier {
 pub struct LiquidationPercentQuerier {
     // this lets us iterate over all pairs that match the first string
     liquidation_percent: HashMap<String, Decimal256>,
+    auction_duration: HashMap<String, u64>,
+    liquidation_queue: Vec<String>,
 }
 
 impl LiquidationPercentQuerier {
     pub fn new(liquidation_percent: &[(&String, &Decimal256)]) -> Self {
         LiquidationPercentQuerier {
             liquidation_percent: liquidation_percent_to_map(liquidation_percent),
+            auction_duration: HashMap::new(),
+            liquidation_queue: vec![],
         }
     }
 }
@@ -203,6 +276,249 @@ pub(crate) fn liquidation_percent_to_map(
     liquidation_percent_map
 }
 
+#[derive(Clone, Default)]
+pub struct AnchorTokenQuerier {
+    // this lets us iterate over all pairs that match the first string
+    anchor_token: HashMap<String, String>,
+}
+
+impl AnchorTokenQuerier {
+    pub fn new(anchor_token: &[(&String, &String)]) -> Self {
+        AnchorTokenQuerier {
+            anchor_token: anchor_token_to_map(anchor_token),
+        }
+    }
+}
+
+pub(crate) fn anchor_token_to_map(anchor_token: &[(&String, &String)]) -> HashMap<String, String> {
+    let mut anchor_token_map: HashMap<String, String> = HashMap::new();
+    for (collector_contract, anchor_token) in anchor_token.iter() {
+        anchor_token_map.insert((*collector_contract).clone(), (*anchor_token).clone());
+    }
+    anchor_token_map
+}
+
+#[derive(Clone, Default)]
+pub struct AmmPoolQuerier {
+    // (offer pool, ask pool) of each constant product AMM contract
+    pools: HashMap<String, (Uint256, Uint256)>,
+}
+
+impl AmmPoolQuerier {
+    pub fn new(pools: &[(&String, &(Uint256, Uint256))]) -> Self {
+        let mut pools_map: HashMap<String, (Uint256, Uint256)> = HashMap::new();
+        for (amm_contract, pool) in pools.iter() {
+            pools_map.insert((*amm_contract).clone(), **pool);
+        }
+        AmmPoolQuerier { pools: pools_map }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ConversionRateQuerier {
+    // this lets us iterate over all pairs that match the first string
+    conversion_rate: HashMap<String, Decimal256>,
+}
+
+impl ConversionRateQuerier {
+    pub fn new(conversion_rate: &[(&String, &Decimal256)]) -> Self {
+        ConversionRateQuerier {
+            conversion_rate: conversion_rate_to_map(conversion_rate),
+        }
+    }
+}
+
+pub(crate) fn conversion_rate_to_map(
+    conversion_rate: &[(&String, &Decimal256)],
+) -> HashMap<String, Decimal256> {
+    let mut conversion_rate_map: HashMap<String, Decimal256> = HashMap::new();
+    for (converter_contract, conversion_rate) in conversion_rate.iter() {
+        conversion_rate_map.insert((*converter_contract).clone(), **conversion_rate);
+    }
+    conversion_rate_map
+}
+
+#[derive(Clone, Default)]
+pub struct ExchangeRateQuerier {
+    // exchange rate source contract to the underlying amount of a collateral token
+    exchange_rate: HashMap<String, Decimal256>,
+}
+
+impl ExchangeRateQuerier {
+    pub fn new(exchange_rate: &[(&String, &Decimal256)]) -> Self {
+        ExchangeRateQuerier {
+            exchange_rate: conversion_rate_to_map(exchange_rate),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct AncEmissionRateQuerier {
+    // this lets us iterate over all pairs that match the first string
+    anc_emission_rate: HashMap<String, Decimal256>,
+}
+
+impl AncEmissionRateQuerier {
+    pub fn new(anc_emission_rate: &[(&String, &Decimal256)]) -> Self {
+        let mut anc_emission_rate_map: HashMap<String, Decimal256> = HashMap::new();
+        for (market_contract, anc_emission_rate) in anc_emission_rate.iter() {
+            anc_emission_rate_map.insert((*market_contract).clone(), **anc_emission_rate);
+        }
+        AncEmissionRateQuerier {
+            anc_emission_rate: anc_emission_rate_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MarketReservesQuerier {
+    // this lets us iterate over all pairs that match the first string
+    market_reserves: HashMap<String, Decimal256>,
+}
+
+impl MarketReservesQuerier {
+    pub fn new(market_reserves: &[(&String, &Decimal256)]) -> Self {
+        let mut market_reserves_map: HashMap<String, Decimal256> = HashMap::new();
+        for (market_contract, market_reserves) in market_reserves.iter() {
+            market_reserves_map.insert((*market_contract).clone(), **market_reserves);
+        }
+        MarketReservesQuerier {
+            market_reserves: market_reserves_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MaxRedeemableQuerier {
+    // this lets us iterate over all pairs that match the first string
+    max_redeemable: HashMap<String, Uint256>,
+}
+
+impl MaxRedeemableQuerier {
+    pub fn new(max_redeemable: &[(&String, &Uint256)]) -> Self {
+        let mut max_redeemable_map: HashMap<String, Uint256> = HashMap::new();
+        for (market_contract, aterra_amount) in max_redeemable.iter() {
+            max_redeemable_map.insert((*market_contract).clone(), **aterra_amount);
+        }
+        MaxRedeemableQuerier {
+            max_redeemable: max_redeemable_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CustodyBalanceQuerier {
+    // this lets us iterate over all pairs that match the first string
+    custody_balance: HashMap<String, Uint256>,
+}
+
+impl CustodyBalanceQuerier {
+    pub fn new(custody_balance: &[(&String, &Uint256)]) -> Self {
+        let mut custody_balance_map: HashMap<String, Uint256> = HashMap::new();
+        for (custody_contract, total_balance) in custody_balance.iter() {
+            custody_balance_map.insert((*custody_contract).clone(), **total_balance);
+        }
+        CustodyBalanceQuerier {
+            custody_balance: custody_balance_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MarketLiabilitiesQuerier {
+    // this lets us iterate over all pairs that match the first string
+    market_liabilities: HashMap<String, Decimal256>,
+}
+
+impl MarketLiabilitiesQuerier {
+    pub fn new(market_liabilities: &[(&String, &Decimal256)]) -> Self {
+        let mut market_liabilities_map: HashMap<String, Decimal256> = HashMap::new();
+        for (market_contract, market_liabilities) in market_liabilities.iter() {
+            market_liabilities_map.insert((*market_contract).clone(), **market_liabilities);
+        }
+        MarketLiabilitiesQuerier {
+            market_liabilities: market_liabilities_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct InterestModelQuerier {
+    // this lets us iterate over all pairs that match the first string
+    interest_model: HashMap<String, String>,
+}
+
+impl InterestModelQuerier {
+    pub fn new(interest_model: &[(&String, &String)]) -> Self {
+        let mut interest_model_map: HashMap<String, String> = HashMap::new();
+        for (market_contract, interest_model) in interest_model.iter() {
+            interest_model_map.insert((*market_contract).clone(), (*interest_model).clone());
+        }
+        InterestModelQuerier {
+            interest_model: interest_model_map,
+        }
+    }
+}
+
+const DEFAULT_TOKEN_SUPPLY: u128 = 1_000_000_000_000_000u128;
+
+#[derive(Clone, Default)]
+pub struct TokenSupplyQuerier {
+    // this lets us iterate over all pairs that match the first string
+    token_supply: HashMap<String, Uint128>,
+}
+
+#[derive(Clone, Default)]
+pub struct TokenBalanceQuerier {
+    // (token contract, holder) balances
+    balances: HashMap<(String, String), Uint128>,
+}
+
+impl TokenBalanceQuerier {
+    pub fn new(balances: &[(&(String, String), &Uint128)]) -> Self {
+        let mut balances_map: HashMap<(String, String), Uint128> = HashMap::new();
+        for (token_holder, balance) in balances.iter() {
+            balances_map.insert((*token_holder).clone(), **balance);
+        }
+        TokenBalanceQuerier {
+            balances: balances_map,
+        }
+    }
+}
+
+impl TokenSupplyQuerier {
+    pub fn new(token_supply: &[(&String, &Uint128)]) -> Self {
+        let mut token_supply_map: HashMap<String, Uint128> = HashMap::new();
+        for (token_contract, token_supply) in token_supply.iter() {
+            token_supply_map.insert((*token_contract).clone(), **token_supply);
+        }
+        TokenSupplyQuerier {
+            token_supply: token_supply_map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CustodyRewardsQuerier {
+    // (custody contract, borrower) -> pending rewards
+    rewards: HashMap<(String, String), Uint256>,
+}
+
+impl CustodyRewardsQuerier {
+    pub fn new(rewards: &[(&String, &String, &Uint256)]) -> Self {
+        let mut rewards_map: HashMap<(String, String), Uint256> = HashMap::new();
+        for (custody_contract, borrower, pending_rewards) in rewards.iter() {
+            rewards_map.insert(
+                ((*custody_contract).clone(), (*borrower).clone()),
+                **pending_rewards,
+            );
+        }
+        CustodyRewardsQuerier {
+            rewards: rewards_map,
+        }
+    }
+}
+
 impl WasmMockQuerier {
     pub fn handle_query(&self, request: &QueryRequest<TerraQueryWrapper>) -> QuerierResult {
         match &request {
@@ -251,28 +567,86 @@ impl WasmMockQuerier {
                     QueryMsg::BorrowerInfo {
                         borrower,
                         block_height: _,
-                    } => match self.loan_amount_querier.borrower_amount.get(&borrower) {
-                        Some(v) => SystemResult::Ok(ContractResult::from(to_binary(
-                            &BorrowerInfoResponse {
-                                borrower,
-                                interest_index: Decimal256::one(),
-                                reward_index: Decimal256::zero(),
-                                loan_amount: *v,
-                                pending_rewards: Decimal256::zero(),
-                            },
-                        ))),
-                        None => SystemResult::Err(SystemError::InvalidRequest {
-                            error: "No borrow amount exists".to_string(),
-                            request: msg.as_slice().into(),
-                        }),
-                    },
+                        sub_account,
+                    } => {
+                        // Like the market, borrowers without a loan have a zero loan amount
+                        let loan_amount = self
+                            .loan_amount_querier
+                            .borrower_amount
+                            .get(
+                                &sub_account
+                                    .as_ref()
+                                    .map(|s| format!("{}/{}", borrower, s))
+                                    .unwrap_or_else(|| borrower.clone()),
+                            )
+                            .copied()
+                            .unwrap_or_default();
+                        SystemResult::Ok(ContractResult::from(to_binary(&BorrowerInfoResponse {
+                            borrower,
+                            sub_account,
+                            interest_index: Decimal256::one(),
+                            reward_index: Decimal256::zero(),
+                            loan_amount,
+                            pending_rewards: Decimal256::zero(),
+                            interest_free_allowance: Uint256::zero(),
+                            repay_credit: Uint256::zero(),
+                        })))
+                    }
+                    QueryMsg::BorrowerInfos { start_after, limit } => {
+                        // Sub-account loans are keyed "borrower/sub_account"
+                        let mut positions: Vec<(&str, Option<&str>, Uint256)> = self
+                            .loan_amount_querier
+                            .borrower_amount
+                            .iter()
+                            .map(|(key, amount)| match key.split_once('/') {
+                                Some((borrower, sub_account)) => {
+                                    (borrower, Some(sub_account), *amount)
+                                }
+                                None => (key.as_str(), None, *amount),
+                            })
+                            .filter(|(b, _, _)| start_after.as_deref().is_none_or(|s| *b > s))
+                            .collect();
+                        positions.sort();
+
+                        let mut borrowers: Vec<&str> = positions.iter().map(|p| p.0).collect();
+                        borrowers.dedup();
+                        borrowers.truncate(limit.unwrap_or(10) as usize);
+
+                        SystemResult::Ok(ContractResult::from(to_binary(&BorrowerInfosResponse {
+                            borrower_infos: positions
+                                .into_iter()
+                                .filter(|(b, _, _)| borrowers.contains(b))
+                                .map(|(b, sub_account, loan_amount)| BorrowerInfoResponse {
+                                    borrower: b.to_string(),
+                                    sub_account: sub_account.map(|s| s.to_string()),
+                                    interest_index: Decimal256::one(),
+                                    reward_index: Decimal256::zero(),
+                                    loan_amount,
+                                    pending_rewards: Decimal256::zero(),
+                                    interest_free_allowance: Uint256::zero(),
+                                    repay_credit: Uint256::zero(),
+                                })
+                                .collect(),
+                        })))
+                    }
                     QueryMsg::Price { base, quote } => {
-                        match self.oracle_price_querier.oracle_price.get(&(base, quote)) {
+                        let base_quote = (base, quote);
+                        let oracle_price = self
+                            .oracle_price_querier
+                            .contract_oracle_price
+                            .get(contract_addr)
+                            .unwrap_or(&self.oracle_price_querier.oracle_price);
+                        match oracle_price.get(&base_quote) {
                             Some(v) => {
                                 SystemResult::Ok(ContractResult::from(to_binary(&PriceResponse {
                                     rate: v.0,
                                     last_updated_base: v.1,
                                     last_updated_quote: v.2,
+                                    confidence_spread: self
+                                        .oracle_price_querier
+                                        .confidence_spread
+                                        .get(&base_quote)
+                                        .copied(),
                                 })))
                             }
                             None => SystemResult::Err(SystemError::InvalidRequest {
@@ -281,6 +655,153 @@ impl WasmMockQuerier {
                             }),
                         }
                     }
+                    QueryMsg::TwapPrice {
+                        base,
+                        quote,
+                        window: _,
+                    } => {
+                        let base_quote = (base, quote);
+                        match (
+                            self.oracle_price_querier.twap_price.get(&base_quote),
+                            self.oracle_price_querier.oracle_price.get(&base_quote),
+                        ) {
+                            (Some(rate), Some(v)) => {
+                                SystemResult::Ok(ContractResult::from(to_binary(&PriceResponse {
+                                    rate: *rate,
+                                    last_updated_base: v.1,
+                                    last_updated_quote: v.2,
+                                    confidence_spread: None,
+                                })))
+                            }
+                            _ => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No oracle twap price exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
+                    QueryMsg::Config {} => {
+                        match self.anchor_token_querier.anchor_token.get(contract_addr) {
+                            Some(v) => SystemResult::Ok(ContractResult::from(to_binary(
+                                &CollectorConfigResponse {
+                                    gov_contract: "gov".to_string(),
+                                    terraswap_factory: "terraswap_factory".to_string(),
+                                    anchor_token: v.to_string(),
+                                    distributor_contract: "distributor".to_string(),
+                                    reward_factor: Decimal::percent(90),
+                                },
+                            ))),
+                            None if self
+                                .liquidation_percent_querier
+                                .liquidation_queue
+                                .contains(contract_addr) =>
+                            {
+                                SystemResult::Ok(ContractResult::from(to_binary(
+                                    &LiquidationQueueConfigResponse {
+                                        owner: "owner".to_string(),
+                                        oracle_contract: "oracle".to_string(),
+                                        stable_denom: "uusd".to_string(),
+                                        safe_ratio: Decimal256::percent(80),
+                                        bid_fee: Decimal256::percent(1),
+                                        liquidator_fee: Decimal256::zero(),
+                                        self_liquidation_bonus: Decimal256::zero(),
+                                        liquidation_threshold: Uint256::from(200u64),
+                                        price_timeframe: 60u64,
+                                        waiting_period: 600u64,
+                                        overseer: MOCK_CONTRACT_ADDR.to_string(),
+                                    },
+                                )))
+                            }
+                            None if self
+                                .liquidation_percent_querier
+                                .liquidation_percent
+                                .contains_key(contract_addr) =>
+                            {
+                                SystemResult::Ok(ContractResult::from(to_binary(
+                                    &LiquidationConfigResponse {
+                                        owner: "owner".to_string(),
+                                        oracle_contract: "oracle".to_string(),
+                                        stable_denom: "uusd".to_string(),
+                                        safe_ratio: Decimal256::percent(80),
+                                        bid_fee: Decimal256::percent(1),
+                                        max_premium_rate: Decimal256::percent(5),
+                                        liquidation_threshold: Uint256::from(200u64),
+                                        price_timeframe: 60u64,
+                                        liquidation_fee_recipient: None,
+                                        max_liquidation_value: None,
+                                        conservative_rounding: true,
+                                        post_liquidation_safe_ltv: None,
+                                        overseer_contract: None,
+                                        auction_duration: self
+                                            .liquidation_percent_querier
+                                            .auction_duration
+                                            .get(contract_addr)
+                                            .copied(),
+                                        self_liquidation_fee: None,
+                                    },
+                                )))
+                            }
+                            None if self
+                                .interest_model_querier
+                                .interest_model
+                                .contains_key(contract_addr) =>
+                            {
+                                SystemResult::Ok(ContractResult::from(to_binary(
+                                    &MarketConfigResponse {
+                                        owner_addr: "owner".to_string(),
+                                        aterra_contract: "aterra".to_string(),
+                                        interest_model: self.interest_model_querier.interest_model
+                                            [contract_addr]
+                                            .clone(),
+                                        distribution_model: "distribution".to_string(),
+                                        overseer_contract: MOCK_CONTRACT_ADDR.to_string(),
+                                        collector_contract: "collector".to_string(),
+                                        distributor_contract: "distributor".to_string(),
+                                        stable_denom: "uusd".to_string(),
+                                        max_borrow_factor: Decimal256::one(),
+                                        min_liquidity_ratio: Decimal256::zero(),
+                                        repay_dust_epsilon: Uint256::zero(),
+                                        blocks_per_year: 4656810u64,
+                                        borrow_fee: Decimal256::zero(),
+                                        accrual_block_threshold: 0u64,
+                                        accrue_by_time: false,
+                                        reserve_route: None,
+                                        flash_fee: Decimal256::zero(),
+                                        accepted_repay_denoms: vec!["uusd".to_string()],
+                                        yield_strategy: None,
+                                        max_deployed_ratio: Decimal256::zero(),
+                                        rate_discount_source: None,
+                                        rate_discount_tiers: vec![],
+                                        min_repay_amount: Uint256::zero(),
+                                        repay_protocol_fee: Decimal256::zero(),
+                                        commitment_fee: Decimal256::zero(),
+                                        max_total_borrow: None,
+                                        same_block_repay_policy: SameBlockRepayPolicy::Allow,
+                                    },
+                                )))
+                            }
+                            None if self
+                                .interest_model_querier
+                                .interest_model
+                                .values()
+                                .any(|i| i == contract_addr) =>
+                            {
+                                SystemResult::Ok(ContractResult::from(to_binary(
+                                    &InterestModelConfigResponse {
+                                        owner: "owner".to_string(),
+                                        base_rate: Decimal256::percent(2),
+                                        interest_multiplier: Decimal256::percent(20),
+                                        risk_weight: Decimal256::zero(),
+                                        market_contract: None,
+                                        smoothing_factor: Decimal256::zero(),
+                                    },
+                                )))
+                            }
+                            None => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No collector config exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
                     QueryMsg::LiquidationAmount {
                         borrow_amount,
                         borrow_limit,
@@ -317,6 +838,154 @@ impl WasmMockQuerier {
                             }),
                         }
                     }
+                    QueryMsg::State { block_height: _ } => {
+                        match self
+                            .anc_emission_rate_querier
+                            .anc_emission_rate
+                            .get(contract_addr)
+                        {
+                            Some(v) => {
+                                SystemResult::Ok(ContractResult::from(to_binary(&StateResponse {
+                                    total_liabilities: self
+                                        .market_liabilities_querier
+                                        .market_liabilities
+                                        .get(contract_addr)
+                                        .copied()
+                                        .unwrap_or_else(Decimal256::zero),
+                                    total_reserves: self
+                                        .market_reserves_querier
+                                        .market_reserves
+                                        .get(contract_addr)
+                                        .copied()
+                                        .unwrap_or_else(Decimal256::zero),
+                                    last_interest_updated: 0u64,
+                                    last_reward_updated: 0u64,
+                                    global_interest_index: Decimal256::one(),
+                                    global_reward_index: Decimal256::zero(),
+                                    anc_emission_rate: *v,
+                                    prev_aterra_supply: Uint256::zero(),
+                                    prev_exchange_rate: Decimal256::one(),
+                                    liquidity_ratio: Decimal256::one(),
+                                    total_deployed: Uint256::zero(),
+                                    max_total_borrow: None,
+                                })))
+                            }
+                            None => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No market state exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
+                    QueryMsg::TokenInfo {} => {
+                        // tokens without a mocked supply are assumed to be in circulation
+                        let total_supply = self
+                            .token_supply_querier
+                            .token_supply
+                            .get(contract_addr)
+                            .copied()
+                            .unwrap_or_else(|| Uint128::from(DEFAULT_TOKEN_SUPPLY));
+                        SystemResult::Ok(ContractResult::from(to_binary(&TokenInfoResponse {
+                            name: "mAPPL".to_string(),
+                            symbol: "mAPPL".to_string(),
+                            decimals: 6,
+                            total_supply,
+                        })))
+                    }
+                    QueryMsg::Balance { address } => {
+                        let balance = self
+                            .token_balance_querier
+                            .balances
+                            .get(&(contract_addr.to_string(), address))
+                            .copied()
+                            .unwrap_or_default();
+                        SystemResult::Ok(ContractResult::from(to_binary(&balance)))
+                    }
+                    QueryMsg::Simulation {
+                        offer_token: _,
+                        ask_token: _,
+                        amount,
+                    } => {
+                        if let Some((offer_pool, ask_pool)) =
+                            self.amm_pool_querier.pools.get(contract_addr)
+                        {
+                            // ask_amount = ask_pool * amount / (offer_pool + amount)
+                            let ask_amount =
+                                *ask_pool * Decimal256::from_ratio(amount, *offer_pool + amount);
+                            return SystemResult::Ok(ContractResult::from(to_binary(
+                                &SimulationResponse { amount: ask_amount },
+                            )));
+                        }
+
+                        match self
+                            .conversion_rate_querier
+                            .conversion_rate
+                            .get(contract_addr)
+                        {
+                            Some(v) => SystemResult::Ok(ContractResult::from(to_binary(
+                                &SimulationResponse {
+                                    amount: amount * *v,
+                                },
+                            ))),
+                            None => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No conversion rate exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
+                    QueryMsg::ExchangeRate {} => {
+                        match self.exchange_rate_querier.exchange_rate.get(contract_addr) {
+                            Some(v) => SystemResult::Ok(ContractResult::from(to_binary(
+                                &ExchangeRateResponse { exchange_rate: *v },
+                            ))),
+                            None => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No exchange rate exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
+                    QueryMsg::TotalBalance {} => {
+                        SystemResult::Ok(ContractResult::from(to_binary(&TotalBalanceResponse {
+                            total_balance: self
+                                .custody_balance_querier
+                                .custody_balance
+                                .get(contract_addr)
+                                .copied()
+                                .unwrap_or_else(Uint256::zero),
+                        })))
+                    }
+                    QueryMsg::Borrower { address } => {
+                        let pending_rewards = self
+                            .custody_rewards_querier
+                            .rewards
+                            .get(&(contract_addr.to_string(), address.clone()))
+                            .copied()
+                            .unwrap_or_default();
+                        SystemResult::Ok(ContractResult::from(to_binary(&BorrowerResponse {
+                            borrower: address,
+                            balance: Uint256::zero(),
+                            spendable: Uint256::zero(),
+                            pending_rewards,
+                            reward_boost: Decimal256::one(),
+                        })))
+                    }
+                    QueryMsg::MaxRedeemable { depositor: _ } => {
+                        match self
+                            .max_redeemable_querier
+                            .max_redeemable
+                            .get(contract_addr)
+                        {
+                            Some(v) => SystemResult::Ok(ContractResult::from(to_binary(
+                                &MaxRedeemableResponse {
+                                    aterra_amount: *v,
+                                    stable_amount: *v,
+                                },
+                            ))),
+                            None => SystemResult::Err(SystemError::InvalidRequest {
+                                error: "No max redeemable exists".to_string(),
+                                request: msg.as_slice().into(),
+                            }),
+                        }
+                    }
                 }
             }
             _ => self.base.handle_query(request),
@@ -333,9 +1002,31 @@ impl WasmMockQuerier {
             oracle_price_querier: OraclePriceQuerier::default(),
             loan_amount_querier: LoanAmountQuerier::default(),
             liquidation_percent_querier: LiquidationPercentQuerier::default(),
+            anchor_token_querier: AnchorTokenQuerier::default(),
+            conversion_rate_querier: ConversionRateQuerier::default(),
+            exchange_rate_querier: ExchangeRateQuerier::default(),
+            amm_pool_querier: AmmPoolQuerier::default(),
+            anc_emission_rate_querier: AncEmissionRateQuerier::default(),
+            market_reserves_querier: MarketReservesQuerier::default(),
+            max_redeemable_querier: MaxRedeemableQuerier::default(),
+            custody_balance_querier: CustodyBalanceQuerier::default(),
+            market_liabilities_querier: MarketLiabilitiesQuerier::default(),
+            token_supply_querier: TokenSupplyQuerier::default(),
+            token_balance_querier: TokenBalanceQuerier::default(),
+            interest_model_querier: InterestModelQuerier::default(),
+            custody_rewards_querier: CustodyRewardsQuerier::default(),
         }
     }
 
+    // set a new balance for the given address and return the old balance
+    pub fn update_balance<U: Into<String>>(
+        &mut self,
+        addr: U,
+        balance: Vec<Coin>,
+    ) -> Option<Vec<Coin>> {
+        self.base.update_balance(addr, balance)
+    }
+
     // configure the tax mock querier
     pub fn with_tax(&mut self, rate: Decimal, caps: &[(&String, &Uint128)]) {
         self.tax_querier = TaxQuerier::new(rate, caps);
@@ -353,6 +1044,36 @@ impl WasmMockQuerier {
         self.oracle_price_querier = OraclePriceQuerier::new(oracle_price);
     }
 
+    #[allow(clippy::type_complexity)]
+    pub fn with_contract_oracle_price(
+        &mut self,
+        contract_addr: &str,
+        oracle_price: &[(&(String, String), &(Decimal256, u64, u64))],
+    ) {
+        self.oracle_price_querier
+            .contract_oracle_price
+            .insert(contract_addr.to_string(), oracle_price_to_map(oracle_price));
+    }
+
+    pub fn with_confidence_spread(
+        &mut self,
+        confidence_spread: &[(&(String, String), &Decimal256)],
+    ) {
+        for (base_quote, spread) in confidence_spread.iter() {
+            self.oracle_price_querier
+                .confidence_spread
+                .insert((*base_quote).clone(), **spread);
+        }
+    }
+
+    pub fn with_twap_price(&mut self, twap_price: &[(&(String, String), &Decimal256)]) {
+        for (base_quote, rate) in twap_price.iter() {
+            self.oracle_price_querier
+                .twap_price
+                .insert((*base_quote).clone(), **rate);
+        }
+    }
+
     pub fn with_loan_amount(&mut self, loan_amount: &[(&String, &Uint256)]) {
         self.loan_amount_querier = LoanAmountQuerier::new(loan_amount);
     }
@@ -360,4 +1081,69 @@ impl WasmMockQuerier {
     pub fn with_liquidation_percent(&mut self, liquidation_percent: &[(&String, &Decimal256)]) {
         self.liquidation_percent_querier = LiquidationPercentQuerier::new(liquidation_percent);
     }
+
+    pub fn with_liquidation_queue(&mut self, liquidation_queue: &[&String]) {
+        self.liquidation_percent_querier.liquidation_queue =
+            liquidation_queue.iter().map(|c| (*c).clone()).collect();
+    }
+
+    pub fn with_liquidation_auction_duration(&mut self, auction_duration: &[(&String, &u64)]) {
+        for (liquidation_contract, auction_duration) in auction_duration.iter() {
+            self.liquidation_percent_querier
+                .auction_duration
+                .insert((*liquidation_contract).clone(), **auction_duration);
+        }
+    }
+
+    pub fn with_anchor_token(&mut self, anchor_token: &[(&String, &String)]) {
+        self.anchor_token_querier = AnchorTokenQuerier::new(anchor_token);
+    }
+
+    pub fn with_conversion_rate(&mut self, conversion_rate: &[(&String, &Decimal256)]) {
+        self.conversion_rate_querier = ConversionRateQuerier::new(conversion_rate);
+    }
+
+    pub fn with_exchange_rate(&mut self, exchange_rate: &[(&String, &Decimal256)]) {
+        self.exchange_rate_querier = ExchangeRateQuerier::new(exchange_rate);
+    }
+
+    pub fn with_amm_pool(&mut self, pools: &[(&String, &(Uint256, Uint256))]) {
+        self.amm_pool_querier = AmmPoolQuerier::new(pools);
+    }
+
+    pub fn with_anc_emission_rate(&mut self, anc_emission_rate: &[(&String, &Decimal256)]) {
+        self.anc_emission_rate_querier = AncEmissionRateQuerier::new(anc_emission_rate);
+    }
+
+    pub fn with_max_redeemable(&mut self, max_redeemable: &[(&String, &Uint256)]) {
+        self.max_redeemable_querier = MaxRedeemableQuerier::new(max_redeemable);
+    }
+
+    pub fn with_custody_balance(&mut self, custody_balance: &[(&String, &Uint256)]) {
+        self.custody_balance_querier = CustodyBalanceQuerier::new(custody_balance);
+    }
+
+    pub fn with_market_reserves(&mut self, market_reserves: &[(&String, &Decimal256)]) {
+        self.market_reserves_querier = MarketReservesQuerier::new(market_reserves);
+    }
+
+    pub fn with_market_liabilities(&mut self, market_liabilities: &[(&String, &Decimal256)]) {
+        self.market_liabilities_querier = MarketLiabilitiesQuerier::new(market_liabilities);
+    }
+
+    pub fn with_token_supply(&mut self, token_supply: &[(&String, &Uint128)]) {
+        self.token_supply_querier = TokenSupplyQuerier::new(token_supply);
+    }
+
+    pub fn with_token_balances(&mut self, balances: &[(&(String, String), &Uint128)]) {
+        self.token_balance_querier = TokenBalanceQuerier::new(balances);
+    }
+
+    pub fn with_interest_model(&mut self, interest_model: &[(&String, &String)]) {
+        self.interest_model_querier = InterestModelQuerier::new(interest_model);
+    }
+
+    pub fn with_custody_rewards(&mut self, rewards: &[(&String, &String, &Uint256)]) {
+        self.custody_rewards_querier = CustodyRewardsQuerier::new(rewards);
+    }
 }
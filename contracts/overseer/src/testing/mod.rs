@@ -1,3 +1,61 @@
 mod collateral_ut;
 mod mock_querier;
 mod tests;
+
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use moneymarket::overseer::InstantiateMsg;
+
+/// Instantiate message the tests start from,
+/// each test overrides the fields it exercises
+pub(crate) fn instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        owner_addr: "owner".to_string(),
+        oracle_contract: "oracle".to_string(),
+        market_contract: "market".to_string(),
+        liquidation_contract: "liquidation".to_string(),
+        collector_contract: "collector".to_string(),
+        stable_denom: "uusd".to_string(),
+        epoch_period: 86400u64,
+        threshold_deposit_rate: Decimal256::permille(3),
+        target_deposit_rate: Decimal256::permille(5),
+        buffer_distribution_factor: Decimal256::percent(20),
+        anc_purchase_factor: Decimal256::percent(20),
+        borrow_price_timeframe: 60u64,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: Uint256::from(1_000_000_000_000u128),
+        surplus_recipient: "surplus".to_string(),
+        stable_decimals: 6,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: 10,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+    }
+}
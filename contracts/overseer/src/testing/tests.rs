@@ -1,21 +1,48 @@
-use crate::contract::{execute, instantiate, query};
+use crate::contract::{execute, instantiate, migrate, query, reply};
 use crate::error::ContractError;
 use crate::querier::query_epoch_state;
-use crate::state::{read_epoch_state, store_epoch_state, EpochState};
+use crate::state::{
+    read_buffer_deposit, read_custody_quorum, read_epoch_state, read_total_locked,
+    store_collaterals, store_epoch_rate_record, store_epoch_state, store_liquidation_record,
+    store_total_locked, EpochRateRecord, EpochState, LegacyConfig, LegacyWhitelistElem,
+    LiquidationRecord,
+};
+use crate::testing::instantiate_msg;
 use crate::testing::mock_querier::mock_dependencies;
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    attr, from_binary, to_binary, Addr, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, Decimal,
-    SubMsg, Uint128, WasmMsg,
+    attr, from_binary, from_slice, to_binary, Addr, Api, BankMsg, CanonicalAddr, Coin,
+    ContractResult, CosmosMsg, Decimal, Deps, Env, Event, Reply, StdError, SubMsg,
+    SubMsgExecutionResponse, Uint128, WasmMsg,
 };
 
-use moneymarket::custody::ExecuteMsg as CustodyExecuteMsg;
-use moneymarket::market::ExecuteMsg as MarketExecuteMsg;
+use cosmwasm_storage::{Bucket, Singleton};
+use cw20::Cw20ExecuteMsg;
+use moneymarket::common::AssetInfo;
+use moneymarket::converter::Cw20HookMsg as ConverterCw20HookMsg;
+use moneymarket::custody::{Cw20HookMsg as CustodyCw20HookMsg, ExecuteMsg as CustodyExecuteMsg};
+use moneymarket::liquidation::ExecuteMsg as LiquidationExecuteMsg;
+use moneymarket::market::{Cw20HookMsg as MarketCw20HookMsg, ExecuteMsg as MarketExecuteMsg};
+use moneymarket::oracle::{ExecuteMsg as OracleExecuteMsg, PriceMode};
 use moneymarket::overseer::{
-    AllCollateralsResponse, BorrowLimitResponse, CollateralsResponse, ConfigResponse, ExecuteMsg,
-    InstantiateMsg, QueryMsg, WhitelistResponse, WhitelistResponseElem,
+    AllCollateralsResponse, AncBuybackEstimateResponse, AssetRegistryResponse,
+    AuthorizationResponse, BadDebtResponse, BorrowLimitResponse, BufferFlowElem,
+    BufferFlowResponse, CategoryExposureElem, CategoryExposureResponse,
+    CollateralCapUtilizationElem, CollateralCapUtilizationResponse, CollateralsResponse,
+    ConfigResponse, ConsistencyCheckResponse, EffectiveLtvResponse, EpochRatePerformanceElem,
+    EpochRatePerformanceResponse, EpochTotalsResponse, ExecuteMsg, FairnessFundResponse,
+    GlobalBorrowableByCollateralResponse, GlobalBorrowableElem, InstantiateMsg,
+    InterestBufferDepositResponse, LiquidationBonusResponse, LiquidationHistoryResponse,
+    LiquidationRecordResponse, LiquidationWaterfallElem, LiquidationWaterfallResponse,
+    LtvAdjustmentElem, MaxLiquidatableResponse, MigrateMsg, NetDepositRateResponse,
+    NextEpochResponse, PositionCollateralElem, PositionResponse, QueryMsg, RiskContributionElem,
+    RiskContributionResponse, SafeBorrowAmountResponse, SimulationResponse, SnapshotPositionElem,
+    SnapshotResponse, SolvencyCheckResponse, StaleCollateralElem, StaleCollateralsResponse,
+    StressTestResponse, SubAccountResponse, SubAccountsResponse, TokenType,
+    TrustedContractsResponse, UpdateWhitelistEntry, ValuationMode, WhitelistResponse,
+    WhitelistResponseElem,
 };
 use moneymarket::querier::deduct_tax;
 
@@ -25,23 +52,20 @@ use std::str::FromStr;
 fn proper_initialization() {
     let mut deps = mock_dependencies(&[]);
 
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
+    let msg = instantiate_msg();
 
     let info = mock_info("addr0000", &[]);
 
+    // stable decimals beyond 18 overflow the price normalization
+    let invalid_msg = InstantiateMsg {
+        stable_decimals: 19,
+        ..msg.clone()
+    };
+    match instantiate(deps.as_mut(), mock_env(), info.clone(), invalid_msg) {
+        Err(ContractError::InvalidStableDecimals(19)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
@@ -61,11 +85,53 @@ fn proper_initialization() {
             target_deposit_rate: Decimal256::permille(5),
             buffer_distribution_factor: Decimal256::percent(20),
             anc_purchase_factor: Decimal256::percent(20),
-            price_timeframe: 60u64,
+            borrow_price_timeframe: 60u64,
+            liquidation_price_timeframe: 60u64,
+            max_anc_price: None,
+            buffer_target: Uint256::from(1_000_000_000_000u128),
+            surplus_recipient: "surplus".to_string(),
+            stable_decimals: 6,
+            price_deviation_threshold: Decimal256::percent(50),
+            max_collaterals_per_borrower: 10,
+            ordered_epoch_operations: false,
+            dust_threshold: Uint256::zero(),
+            dust_tolerance: Decimal256::zero(),
+            collateral_converter: None,
+            soft_liquidation: false,
+            buffer_backstop_limit: Uint256::zero(),
+            max_confidence_spread: None,
+            reserve_target: Uint256::zero(),
+            reserve_topup_factor: Decimal256::zero(),
+            epoch_requires_fresh_prices: false,
+            fallback_oracle_contract: None,
+            min_custody_quorum: Decimal256::zero(),
+            same_block_collateral_delay: false,
+            max_collateral_value_per_borrower: None,
+            risk_admin: None,
+            epoch_custody_batch_size: None,
+            anc_buyback_amm: None,
+            min_lock_amount: Uint256::zero(),
+            solvency_margin: Decimal256::zero(),
+            max_block_price_drop: None,
+            price_drop_cooldown: 0,
+            dispute_window: 0,
+            valuation_mode: ValuationMode::Spot,
+            default_limit: 10,
+            bad_debt_budget: Uint256::zero(),
+            deposit_rate_smoothing_epochs: 1,
+            skip_paused_collaterals: false,
+            max_ltv_delta_per_epoch: None,
+            liquidation_frozen: false,
+            max_anc_purchase_per_epoch: None,
         }
     );
 
-    let query_res = query(deps.as_ref(), mock_env(), QueryMsg::EpochState {}).unwrap();
+    let query_res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::EpochState { precision: None },
+    )
+    .unwrap();
     let epoch_state: EpochState = from_binary(&query_res).unwrap();
     assert_eq!(
         epoch_state,
@@ -84,20 +150,7 @@ fn update_config() {
     let mut deps = mock_dependencies(&[]);
 
     let info = mock_info("addr0000", &[]);
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
+    let msg = instantiate_msg();
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -113,7 +166,43 @@ fn update_config() {
         buffer_distribution_factor: None,
         anc_purchase_factor: None,
         epoch_period: None,
-        price_timeframe: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
     };
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -135,7 +224,43 @@ fn update_config() {
         buffer_distribution_factor: Some(Decimal256::percent(10)),
         anc_purchase_factor: Some(Decimal256::percent(10)),
         epoch_period: Some(100000u64),
-        price_timeframe: Some(120u64),
+        borrow_price_timeframe: Some(120u64),
+        liquidation_price_timeframe: Some(180u64),
+        max_anc_price: Some(Some(Decimal256::percent(50))),
+        buffer_target: Some(Uint256::from(100_000_000u128)),
+        surplus_recipient: Some("surplus1".to_string()),
+        price_deviation_threshold: Some(Decimal256::percent(20)),
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
     };
 
     let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -155,7 +280,29 @@ fn update_config() {
     );
     assert_eq!(Decimal256::percent(10), config_res.anc_purchase_factor);
     assert_eq!(100000u64, config_res.epoch_period);
-    assert_eq!(120u64, config_res.price_timeframe);
+    assert_eq!(120u64, config_res.borrow_price_timeframe);
+    assert_eq!(180u64, config_res.liquidation_price_timeframe);
+    assert_eq!(Some(Decimal256::percent(50)), config_res.max_anc_price);
+    assert_eq!(Uint256::from(100_000_000u128), config_res.buffer_target);
+    assert_eq!("surplus1".to_string(), config_res.surplus_recipient);
+    assert_eq!(
+        Decimal256::percent(20),
+        config_res.price_deviation_threshold
+    );
+
+    // an absent max_anc_price is left unchanged, a null one removes the cap
+    let info = mock_info("owner1", &[]);
+    let msg: ExecuteMsg = from_slice(br#"{"update_config":{}}"#).unwrap();
+    execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(Some(Decimal256::percent(50)), config_res.max_anc_price);
+
+    let msg: ExecuteMsg = from_slice(br#"{"update_config":{"max_anc_price":null}}"#).unwrap();
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(None, config_res.max_anc_price);
 
     // Unauthorized err
     let info = mock_info("owner", &[]);
@@ -168,7 +315,43 @@ fn update_config() {
         buffer_distribution_factor: None,
         anc_purchase_factor: None,
         epoch_period: None,
-        price_timeframe: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
     };
 
     let res = execute(deps.as_mut(), mock_env(), info, msg);
@@ -176,27 +359,377 @@ fn update_config() {
         Err(ContractError::Unauthorized {}) => (),
         _ => panic!("Must return unauthorized error"),
     }
+
+    // liquidations cannot require fresher prices than borrows
+    let info = mock_info("owner1", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: Some(100u64),
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::InvalidPriceTimeframe {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let info = mock_info("owner1", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: Some(19),
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::InvalidStableDecimals(19)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: Some(18),
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    let query_res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&query_res).unwrap();
+    assert_eq!(config_res.stable_decimals, 18);
 }
 
 #[test]
-fn whitelist() {
+fn contract_address_validation() {
     let mut deps = mock_dependencies(&[]);
 
     let info = mock_info("addr0000", &[]);
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
+    let mut msg = instantiate_msg();
+
+    // the overseer cannot be its own market
+    msg.market_contract = MOCK_CONTRACT_ADDR.to_string();
+    let res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::SelfReferentialAddress(field)) => {
+            assert_eq!(field, "market_contract")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // nor can the collector be the market
+    msg.market_contract = "collector".to_string();
+    let res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::DuplicateContractAddress(field, other)) => {
+            assert_eq!(field, "market_contract");
+            assert_eq!(other, "collector_contract");
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    msg.market_contract = "market".to_string();
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let info = mock_info("owner", &[]);
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: Some(MOCK_CONTRACT_ADDR.to_string()),
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::SelfReferentialAddress(field)) => {
+            assert_eq!(field, "oracle_contract")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: Some("oracle1".to_string()),
+        liquidation_contract: Some("oracle1".to_string()),
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
     };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::DuplicateContractAddress(field, other)) => {
+            assert_eq!(field, "oracle_contract");
+            assert_eq!(other, "liquidation_contract");
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // rejected updates are not stored
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!("oracle".to_string(), config_res.oracle_contract);
+    assert_eq!("liquidation".to_string(), config_res.liquidation_contract);
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: Some("oracle1".to_string()),
+        liquidation_contract: Some("liquidation1".to_string()),
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+}
+
+#[test]
+fn whitelist() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("addr0000", &[]);
+    let msg = instantiate_msg();
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -207,6 +740,17 @@ fn whitelist() {
         collateral_token: "bluna".to_string(),
         custody_contract: "custody".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -249,7 +793,19 @@ fn whitelist() {
                 symbol: "bluna".to_string(),
                 collateral_token: "bluna".to_string(),
                 custody_contract: "custody".to_string(),
+                custody_contracts: vec![("custody".to_string(), Decimal256::one())],
                 max_ltv: Decimal256::percent(60),
+                quote_denom: None,
+                liquidation_premium: None,
+                correlation_group: None,
+                min_lock_amount: None,
+                borrow_enabled_from: None,
+                borrow_disabled_from: None,
+                liquidation_contract: None,
+                decimals: 6,
+                token_type: TokenType::Cw20,
+                exchange_rate_source: None,
+                borrow_rate_premium: None,
             }]
         }
     );
@@ -261,6 +817,17 @@ fn whitelist() {
         collateral_token: "bluna".to_string(),
         custody_contract: "custody".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
     let info = mock_info("owner", &[]);
@@ -274,6 +841,16 @@ fn whitelist() {
         collateral_token: "bluna".to_string(),
         custody_contract: Some("custody2".to_string()),
         max_ltv: Some(Decimal256::percent(30)),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -314,606 +891,15236 @@ fn whitelist() {
                 symbol: "bluna".to_string(),
                 collateral_token: "bluna".to_string(),
                 custody_contract: "custody2".to_string(),
+                custody_contracts: vec![("custody2".to_string(), Decimal256::one())],
                 max_ltv: Decimal256::percent(30),
+                quote_denom: None,
+                liquidation_premium: None,
+                correlation_group: None,
+                min_lock_amount: None,
+                borrow_enabled_from: None,
+                borrow_disabled_from: None,
+                liquidation_contract: None,
+                decimals: 6,
+                token_type: TokenType::Cw20,
+                exchange_rate_source: None,
+                borrow_rate_premium: None,
             }]
         }
     );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::WhitelistEntry {
+            collateral_token: "bluna".to_string(),
+        },
+    )
+    .unwrap();
+    let whitelist_elem: WhitelistResponseElem = from_binary(&res).unwrap();
+    assert_eq!(
+        whitelist_elem,
+        WhitelistResponseElem {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            collateral_token: "bluna".to_string(),
+            custody_contract: "custody2".to_string(),
+            custody_contracts: vec![("custody2".to_string(), Decimal256::one())],
+            max_ltv: Decimal256::percent(30),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: 6,
+            token_type: TokenType::Cw20,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        }
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::WhitelistEntry {
+            collateral_token: "batom".to_string(),
+        },
+    );
+    match res {
+        Err(StdError::GenericErr { msg, .. }) => {
+            assert_eq!(msg, "Collateral is not whitelisted: batom")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 }
 
 #[test]
-fn execute_epoch_operations() {
-    let mut deps = mock_dependencies(&[Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(10000000000u128),
-    }]);
+fn whitelist_with_price() {
+    let mut deps = mock_dependencies(&[]);
 
-    let mut env = mock_env();
-    let info = mock_info("owner", &[]);
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
+    let info = mock_info("addr0000", &[]);
+    let msg = instantiate_msg();
 
     // we can just call .unwrap() to assert this was a success
-    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-
-    let batom_collat_token = deps
-        .api
-        .addr_humanize(&CanonicalAddr::from(vec![
-            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ]))
-        .unwrap()
-        .to_string();
-
-    let bluna_collat_token = deps
-        .api
-        .addr_humanize(&CanonicalAddr::from(vec![
-            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ]))
-        .unwrap()
-        .to_string();
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-    // store whitelist elems
-    let msg = ExecuteMsg::Whitelist {
+    let msg = ExecuteMsg::WhitelistWithPrice {
         name: "bluna".to_string(),
         symbol: "bluna".to_string(),
-        collateral_token: bluna_collat_token,
-        custody_contract: "custody_bluna".to_string(),
-        max_ltv: Decimal256::percent(60),
-    };
-
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-
-    let msg = ExecuteMsg::Whitelist {
-        name: "batom".to_string(),
-        symbol: "batom".to_string(),
-        collateral_token: batom_collat_token,
-        custody_contract: "custody_batom".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        initial_price: Decimal256::percent(250),
+        feeder: "feeder".to_string(),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-
-    let msg = ExecuteMsg::ExecuteEpochOperations {};
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
     match res {
-        Err(ContractError::EpochNotPassed(12345)) => (),
+        Err(ContractError::Unauthorized {}) => (),
         _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    env.block.height += 86400u64;
-
-    // If deposit_rate is bigger than threshold_deposit_rate
-    deps.querier.with_epoch_state(&[(
-        &"market".to_string(),
-        &(Uint256::from(1000000u64), Decimal256::percent(120)),
-    )]);
+    };
 
-    // (120 / 100 - 1) / 86400
-    // deposit rate = 0.000002314814814814
-    // accrued_buffer = 10,000,000,000
-    // anc_purchase_amount = accrued_buffer * 0.2 = 2,000,000,000
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    // the price is fed within the whitelisting transaction,
+    // so the collateral is priceable as soon as it is listed
+    let info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(
         res.messages,
         vec![
-            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-                to_address: "collector".to_string(),
-                amount: vec![deduct_tax(
-                    deps.as_ref(),
-                    Coin {
-                        denom: "uusd".to_string(),
-                        amount: Uint128::from(2_000_000_000u128),
-                    }
-                )
-                .unwrap()],
-            })),
             SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_batom".to_string(),
+                contract_addr: "oracle".to_string(),
                 funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                msg: to_binary(&OracleExecuteMsg::RegisterFeeder {
+                    asset: "bluna".to_string(),
+                    feeder: MOCK_CONTRACT_ADDR.to_string(),
+                })
+                .unwrap(),
             })),
             SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_bluna".to_string(),
+                contract_addr: "oracle".to_string(),
                 funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                msg: to_binary(&OracleExecuteMsg::FeedPrice {
+                    prices: vec![("bluna".to_string(), Decimal256::percent(250))],
+                })
+                .unwrap(),
             })),
             SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                contract_addr: "oracle".to_string(),
                 funds: vec![],
-                msg: to_binary(&ExecuteMsg::UpdateEpochState {
-                    interest_buffer: Uint256::from(8_000_000_000u128),
-                    distributed_interest: Uint256::zero(),
+                msg: to_binary(&OracleExecuteMsg::RegisterFeeder {
+                    asset: "bluna".to_string(),
+                    feeder: "feeder".to_string(),
                 })
                 .unwrap(),
-            }))
+            })),
         ]
     );
-
     assert_eq!(
         res.attributes,
         vec![
-            attr("action", "epoch_operations"),
-            attr("deposit_rate", "0.000002314814814814"),
-            attr("exchange_rate", "1.2"),
-            attr("aterra_supply", "1000000"),
-            attr("distributed_interest", "0"),
-            attr("anc_purchase_amount", "2000000000"),
+            attr("action", "register_whitelist"),
+            attr("name", "bluna"),
+            attr("symbol", "bluna"),
+            attr("collateral_token", "bluna"),
+            attr("custody_contract", "custody"),
+            attr("LTV", "0.6"),
+            attr("initial_price", "2.5"),
+            attr("feeder", "feeder"),
         ]
     );
 
-    // store epoch state for test purpose
-    store_epoch_state(
-        deps.as_mut().storage,
-        &EpochState {
-            last_executed_height: env.block.height,
-            prev_exchange_rate: Decimal256::from_str("1.2").unwrap(),
-            prev_aterra_supply: Uint256::from_str("1000000").unwrap(),
-            prev_interest_buffer: Uint256::from_str("9999000000").unwrap(),
-            deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+    let res: WhitelistResponseElem = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::WhitelistEntry {
+                collateral_token: "bluna".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.max_ltv, Decimal256::percent(60));
+}
+
+#[test]
+fn risk_admin() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("addr0000", &[]);
+    let msg = InstantiateMsg {
+        risk_admin: Some("risk".to_string()),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    // Registration stays with the owner
+    let info = mock_info("risk", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+
+    let info = mock_info("owner", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // The owner cannot touch the risk parameters once a risk admin is set
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: Some(Decimal256::percent(55)),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+
+    let info = mock_info("risk", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // The risk admin cannot touch the custody contract
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: Some("custody2".to_string()),
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let info = mock_info("risk", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Whitelist {
+            collateral_token: Some("bluna".to_string()),
+            start_after: None,
+            limit: None,
         },
     )
     .unwrap();
+    let whitelist_res: WhitelistResponse = from_binary(&res).unwrap();
+    assert_eq!(whitelist_res.elems[0].max_ltv, Decimal256::percent(55));
+    assert_eq!(
+        whitelist_res.elems[0].custody_contract,
+        "custody".to_string()
+    );
 
-    // If deposit rate is bigger than threshold
-    deps.querier.with_epoch_state(&[(
-        &"market".to_string(),
-        &(Uint256::from(1000000u64), Decimal256::percent(125)),
-    )]);
+    // The risk admin cannot reroute the treasury
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: Some("risk".to_string()),
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
 
-    deps.querier.with_tax(
-        Decimal::percent(1),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    let info = mock_info("risk", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+
+    // Borrower caps go through the risk admin
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: Some(5),
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+
+    let info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+
+    let info = mock_info("risk", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.max_collaterals_per_borrower, 5);
+    assert_eq!(config_res.surplus_recipient, "surplus".to_string());
+    assert_eq!(config_res.risk_admin, Some("risk".to_string()));
+}
+
+#[test]
+fn update_whitelist_batch() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("addr0000", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let info = mock_info("owner", &[]);
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    }
+
+    let entry = |collateral_token: &str, max_ltv: u64| UpdateWhitelistEntry {
+        collateral_token: collateral_token.to_string(),
+        custody_contract: None,
+        max_ltv: Some(Decimal256::percent(max_ltv)),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let query_max_ltv = |deps: Deps, collateral_token: &str| -> Decimal256 {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::WhitelistEntry {
+                collateral_token: collateral_token.to_string(),
+            },
+        )
+        .unwrap();
+        let elem: WhitelistResponseElem = from_binary(&res).unwrap();
+        elem.max_ltv
+    };
+
+    let msg = ExecuteMsg::UpdateWhitelistBatch {
+        updates: vec![entry("bluna", 50), entry("batom", 50)],
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the invalid batom entry rejects the bluna update as well
+    let msg = ExecuteMsg::UpdateWhitelistBatch {
+        updates: vec![entry("bluna", 50), entry("batom", 100)],
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::InvalidMaxLtv {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    assert_eq!(
+        query_max_ltv(deps.as_ref(), "bluna"),
+        Decimal256::percent(60)
     );
 
-    env.block.height += 86400u64;
+    let msg = ExecuteMsg::UpdateWhitelistBatch {
+        updates: vec![entry("bluna", 50), entry("bnone", 50)],
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::CollateralNotWhitelisted(collateral_token)) => {
+            assert_eq!(collateral_token, "bnone")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 
-    // accrued_buffer = 1,000,000
-    // interest_buffer = 9,999,000,000
-    // (125 / 120 - 1) / 86400
-    // deposit rate = 0.000000482253086419
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    let msg = ExecuteMsg::UpdateWhitelistBatch {
+        updates: vec![entry("bluna", 50), entry("bluna", 40)],
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::DuplicateWhitelistUpdate(collateral_token)) => {
+            assert_eq!(collateral_token, "bluna")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
     assert_eq!(
-        res.messages,
-        vec![
-            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-                to_address: "collector".to_string(),
-                amount: vec![deduct_tax(
-                    deps.as_ref(),
-                    Coin {
-                        denom: "uusd".to_string(),
-                        amount: Uint128::from(200_000u128),
-                    }
-                )
-                .unwrap()]
-            })),
-            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-                to_address: "market".to_string(),
-                amount: vec![deduct_tax(
-                    deps.as_ref(),
-                    Coin {
-                        denom: "uusd".to_string(),
-                        amount: Uint128::from(53680u128),
-                    }
-                )
-                .unwrap()]
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_batom".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_bluna".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
-                funds: vec![],
-                msg: to_binary(&ExecuteMsg::UpdateEpochState {
-                    interest_buffer: Uint256::from(9999746320u128),
-                    distributed_interest: Uint256::from(53148u128),
-                })
-                .unwrap(),
-            }))
-        ]
+        query_max_ltv(deps.as_ref(), "bluna"),
+        Decimal256::percent(60)
     );
 
+    let msg = ExecuteMsg::UpdateWhitelistBatch {
+        updates: vec![entry("bluna", 50), entry("batom", 40)],
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     assert_eq!(
         res.attributes,
         vec![
-            attr("action", "epoch_operations"),
-            attr("deposit_rate", "0.000000482253086419"),
-            attr("exchange_rate", "1.25"),
-            attr("aterra_supply", "1000000"),
-            attr("distributed_interest", "53148"),
-            attr("anc_purchase_amount", "200000")
+            attr("action", "update_whitelist_batch"),
+            attr("bluna", "0.5"),
+            attr("batom", "0.4"),
         ]
     );
-}
-
+    assert_eq!(
+        query_max_ltv(deps.as_ref(), "bluna"),
+        Decimal256::percent(50)
+    );
+    assert_eq!(
+        query_max_ltv(deps.as_ref(), "batom"),
+        Decimal256::percent(40)
+    );
+}
+
 #[test]
-fn update_epoch_state() {
+fn max_ltv_delta_per_epoch() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("addr0000", &[]);
+    let msg = InstantiateMsg {
+        max_ltv_delta_per_epoch: Some(Decimal256::percent(10)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let info = mock_info("owner", &[]);
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    }
+
+    let update = |max_ltv: u64| ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: Some(Decimal256::percent(max_ltv)),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    // a single step beyond the delta is rejected
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), update(40));
+    match res {
+        Err(ContractError::MaxLtvDeltaExceeded(collateral_token, remaining_delta)) => {
+            assert_eq!(collateral_token, "bluna");
+            assert_eq!(remaining_delta, Decimal256::percent(10));
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // steps within the delta succeed until their sum reaches it
+    execute(deps.as_mut(), mock_env(), info.clone(), update(55)).unwrap();
+    execute(deps.as_mut(), mock_env(), info.clone(), update(60)).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), update(59));
+    match res {
+        Err(ContractError::MaxLtvDeltaExceeded(_, remaining_delta)) => {
+            assert_eq!(remaining_delta, Decimal256::zero());
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // batch updates are tracked the same way
+    let msg = ExecuteMsg::UpdateWhitelistBatch {
+        updates: vec![UpdateWhitelistEntry {
+            collateral_token: "batom".to_string(),
+            custody_contract: None,
+            max_ltv: Some(Decimal256::percent(45)),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        }],
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    match res {
+        Err(ContractError::MaxLtvDeltaExceeded(collateral_token, _)) => {
+            assert_eq!(collateral_token, "batom");
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the next epoch renews the delta
+    let mut epoch_state = read_epoch_state(deps.as_ref().storage).unwrap();
+    epoch_state.last_executed_height += 86400u64;
+    store_epoch_state(deps.as_mut().storage, &epoch_state).unwrap();
+    execute(deps.as_mut(), mock_env(), info, update(50)).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::WhitelistEntry {
+            collateral_token: "bluna".to_string(),
+        },
+    )
+    .unwrap();
+    let elem: WhitelistResponseElem = from_binary(&res).unwrap();
+    assert_eq!(elem.max_ltv, Decimal256::percent(50));
+}
+
+#[test]
+fn sweep_tokens() {
     let mut deps = mock_dependencies(&[Coin {
-        denom: "uusd".to_string(),
-        amount: Uint128::from(10000000000u128),
+        denom: "ukrw".to_string(),
+        amount: Uint128::from(1000u128),
     }]);
 
     let info = mock_info("owner", &[]);
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
-
-    // we can just call .unwrap() to assert this was a success
-    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::SweepTokens {
+        asset: AssetInfo::NativeToken {
+            denom: "ukrw".to_string(),
+        },
+        recipient: "recipient".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // whitelisted collateral cannot be swept
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::SweepTokens {
+            asset: AssetInfo::Token {
+                contract_addr: "bluna".to_string(),
+            },
+            recipient: "recipient".to_string(),
+        },
+    );
+    match res {
+        Err(ContractError::SweepTrackedAsset(asset)) => assert_eq!(asset, "bluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // interest buffer cannot be swept
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::SweepTokens {
+            asset: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            recipient: "recipient".to_string(),
+        },
+    );
+    match res {
+        Err(ContractError::SweepTrackedAsset(asset)) => assert_eq!(asset, "uusd"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "recipient".to_string(),
+            amount: vec![Coin {
+                denom: "ukrw".to_string(),
+                amount: Uint128::from(1000u128),
+            }],
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "sweep"),
+            attr("asset", "ukrw"),
+            attr("recipient", "recipient"),
+            attr("amount", "1000"),
+        ]
+    );
+}
+
+#[test]
+fn register_custody_contract() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::RegisterCustodyContract {
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna2".to_string(),
+        weight: Decimal256::from_uint256(3u64),
+    };
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::RegisterCustodyContract {
+            collateral_token: "bluna".to_string(),
+            custody_contract: "custody_bluna2".to_string(),
+            weight: Decimal256::zero(),
+        },
+    );
+    match res {
+        Err(ContractError::InvalidCustodyWeight {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "register_custody_contract"),
+            attr("collateral_token", "bluna"),
+            attr("custody_contract", "custody_bluna2"),
+            attr("weight", "3"),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::WhitelistEntry {
+            collateral_token: "bluna".to_string(),
+        },
+    )
+    .unwrap();
+    let whitelist_elem: WhitelistResponseElem = from_binary(&res).unwrap();
+    assert_eq!(
+        whitelist_elem,
+        WhitelistResponseElem {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            collateral_token: "bluna".to_string(),
+            custody_contract: "custody_bluna".to_string(),
+            custody_contracts: vec![
+                ("custody_bluna".to_string(), Decimal256::one()),
+                ("custody_bluna2".to_string(), Decimal256::from_uint256(3u64)),
+            ],
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: 6,
+            token_type: TokenType::Cw20,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        }
+    );
+
+    // locked amounts are split 1:3 across the custody contracts
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(250000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna2".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(750000u64),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+
+    // the last custody contract takes the rounding remainder
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(10u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(2u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna2".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(8u64),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+
+    // after a weight change the unlock follows what each custody contract holds
+    let msg = ExecuteMsg::RegisterCustodyContract {
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna2".to_string(),
+        weight: Decimal256::one(),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(999990u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(249998u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna2".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(749992u64),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+}
+#[test]
+fn migrate_legacy_whitelist() {
+    let mut deps = mock_dependencies(&[]);
+
+    let legacy_config = LegacyConfig {
+        owner_addr: deps.api.addr_canonicalize("owner").unwrap(),
+        oracle_contract: deps.api.addr_canonicalize("oracle").unwrap(),
+        market_contract: deps.api.addr_canonicalize("market").unwrap(),
+        liquidation_contract: deps.api.addr_canonicalize("liquidation").unwrap(),
+        collector_contract: deps.api.addr_canonicalize("collector").unwrap(),
+        stable_denom: "uusd".to_string(),
+        epoch_period: 86400u64,
+        threshold_deposit_rate: Decimal256::permille(3),
+        target_deposit_rate: Decimal256::permille(5),
+        buffer_distribution_factor: Decimal256::percent(20),
+        anc_purchase_factor: Decimal256::percent(20),
+        price_timeframe: 60u64,
+    };
+    Singleton::new(deps.as_mut().storage, b"config")
+        .save(&legacy_config)
+        .unwrap();
+
+    let collateral_token = deps.api.addr_canonicalize("bluna").unwrap();
+    let custody_contract = deps.api.addr_canonicalize("custody_bluna").unwrap();
+    let mut whitelist_bucket: Bucket<LegacyWhitelistElem> =
+        Bucket::new(deps.as_mut().storage, b"whitelist");
+    whitelist_bucket
+        .save(
+            collateral_token.as_slice(),
+            &LegacyWhitelistElem {
+                name: "bluna".to_string(),
+                symbol: "bluna".to_string(),
+                max_ltv: Decimal256::percent(60),
+                custody_contract,
+            },
+        )
+        .unwrap();
+
+    let borrower = deps.api.addr_canonicalize("addr0000").unwrap();
+    store_collaterals(
+        deps.as_mut().storage,
+        &borrower,
+        None,
+        &vec![(collateral_token.clone(), Uint256::from(200u64))],
+    )
+    .unwrap();
+    store_collaterals(
+        deps.as_mut().storage,
+        &borrower,
+        Some("vault"),
+        &vec![(collateral_token.clone(), Uint256::from(30u64))],
+    )
+    .unwrap();
+
+    let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    assert_eq!(
+        read_total_locked(deps.as_ref().storage, &collateral_token),
+        Uint256::from(230u64)
+    );
+
+    // the single price timeframe applies to borrows and liquidations
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.owner_addr, "owner".to_string());
+    assert_eq!(config_res.epoch_period, 86400u64);
+    assert_eq!(config_res.borrow_price_timeframe, 60u64);
+    assert_eq!(config_res.liquidation_price_timeframe, 60u64);
+    assert_eq!(config_res.surplus_recipient, "collector".to_string());
+    assert_eq!(config_res.buffer_target, Uint256::from(u128::MAX));
+    assert_eq!(config_res.max_collaterals_per_borrower, u32::MAX);
+    assert_eq!(config_res.stable_decimals, 6u8);
+
+    // a migrated config is kept on the next migration
+    let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    assert_eq!(from_binary::<ConfigResponse>(&res).unwrap(), config_res);
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::WhitelistEntry {
+            collateral_token: "bluna".to_string(),
+        },
+    )
+    .unwrap();
+    let whitelist_elem: WhitelistResponseElem = from_binary(&res).unwrap();
+    assert_eq!(
+        whitelist_elem,
+        WhitelistResponseElem {
+            name: "bluna".to_string(),
+            symbol: "bluna".to_string(),
+            collateral_token: "bluna".to_string(),
+            custody_contract: "custody_bluna".to_string(),
+            custody_contracts: vec![("custody_bluna".to_string(), Decimal256::one())],
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: 6,
+            token_type: TokenType::Cw20,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        }
+    );
+}
+
+#[test]
+fn execute_epoch_operations() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::EpochNotPassed(12345)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    env.block.height += 86400u64;
+
+    // If deposit_rate is bigger than threshold_deposit_rate
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    // (120 / 100 - 1) / 86400
+    // deposit rate = 0.000002314814814814
+    // accrued_buffer = 10,000,000,000
+    // anc_purchase_amount = accrued_buffer * 0.2 = 2,000,000,000
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_batom".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_000
+            ),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_bluna".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_001
+            ),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(8_000_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations"),
+            attr("deposit_rate", "0.000002314814814814"),
+            attr("exchange_rate", "1.2"),
+            attr("aterra_supply", "1000000"),
+            attr("distributed_interest", "0"),
+            attr("anc_purchase_amount", "2000000000"),
+            attr("anc_purchase_executed", "true"),
+            attr("surplus_amount", "0"),
+            attr("reserve_topup", "0"),
+        ]
+    );
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::EpochTotals {}).unwrap();
+    let epoch_totals: EpochTotalsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        epoch_totals,
+        EpochTotalsResponse {
+            total_anc_purchased: Uint256::from(2_000_000_000u128),
+            total_buffer_distributed: Uint256::zero(),
+        }
+    );
+
+    // store epoch state for test purpose
+    store_epoch_state(
+        deps.as_mut().storage,
+        &EpochState {
+            last_executed_height: env.block.height,
+            prev_exchange_rate: Decimal256::from_str("1.2").unwrap(),
+            prev_aterra_supply: Uint256::from_str("1000000").unwrap(),
+            prev_interest_buffer: Uint256::from_str("9999000000").unwrap(),
+            deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+        },
+    )
+    .unwrap();
+
+    // If deposit rate is bigger than threshold
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    env.block.height += 86400u64;
+
+    // accrued_buffer = 1,000,000
+    // interest_buffer = 9,999,000,000
+    // (125 / 120 - 1) / 86400
+    // deposit rate = 0.000000482253086419
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(200_000u128),
+                    }
+                )
+                .unwrap()]
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "market".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(53680u128),
+                    }
+                )
+                .unwrap()]
+            })),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_batom".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_000
+            ),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_bluna".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_001
+            ),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(9999746320u128),
+                    distributed_interest: Uint256::from(53148u128),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations"),
+            attr("deposit_rate", "0.000000482253086419"),
+            attr("exchange_rate", "1.25"),
+            attr("aterra_supply", "1000000"),
+            attr("distributed_interest", "53148"),
+            attr("anc_purchase_amount", "200000"),
+            attr("anc_purchase_executed", "true"),
+            attr("surplus_amount", "0"),
+            attr("reserve_topup", "0"),
+        ]
+    );
+
+    // both epochs are accumulated
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::EpochTotals {}).unwrap();
+    let epoch_totals: EpochTotalsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        epoch_totals,
+        EpochTotalsResponse {
+            total_anc_purchased: Uint256::from(2_000_200_000u128),
+            total_buffer_distributed: Uint256::from(53148u128),
+        }
+    );
+}
+
+#[test]
+fn epoch_totals() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        max_anc_purchase_per_epoch: Some(Uint256::from(500_000_000u128)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::EpochTotals {}).unwrap();
+    let epoch_totals: EpochTotalsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        epoch_totals,
+        EpochTotalsResponse {
+            total_anc_purchased: Uint256::zero(),
+            total_buffer_distributed: Uint256::zero(),
+        }
+    );
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    // accrued_buffer = 10,000,000,000
+    // anc_purchase_amount = min(accrued_buffer * 0.2, 500,000,000) = 500,000,000
+    env.block.height += 86400u64;
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+
+    // the counters hold what was sent, not what the factor asked for
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::EpochTotals {}).unwrap();
+    let epoch_totals: EpochTotalsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        epoch_totals,
+        EpochTotalsResponse {
+            total_anc_purchased: Uint256::from(500_000_000u128),
+            total_buffer_distributed: Uint256::zero(),
+        }
+    );
+
+    // store epoch state for test purpose
+    store_epoch_state(
+        deps.as_mut().storage,
+        &EpochState {
+            last_executed_height: env.block.height,
+            prev_exchange_rate: Decimal256::from_str("1.2").unwrap(),
+            prev_aterra_supply: Uint256::from_str("1000000").unwrap(),
+            prev_interest_buffer: Uint256::from_str("9999000000").unwrap(),
+            deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+        },
+    )
+    .unwrap();
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    // accrued_buffer = 1,000,000
+    // anc_purchase_amount = 200,000
+    // distributed_interest = 53,148
+    env.block.height += 86400u64;
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert!(res
+        .attributes
+        .contains(&attr("distributed_interest", "53148")));
+
+    // both epochs are accumulated
+    let res = query(deps.as_ref(), env, QueryMsg::EpochTotals {}).unwrap();
+    let epoch_totals: EpochTotalsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        epoch_totals,
+        EpochTotalsResponse {
+            total_anc_purchased: Uint256::from(500_200_000u128),
+            total_buffer_distributed: Uint256::from(53148u128),
+        }
+    );
+}
+
+#[test]
+fn buffer_flow() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    // no epoch has been executed yet
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BufferFlow { limit: None },
+    )
+    .unwrap();
+    let buffer_flow: BufferFlowResponse = from_binary(&res).unwrap();
+    assert_eq!(buffer_flow.epochs, vec![]);
+
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+    env.block.height += 86400u64;
+    let first_height = env.block.height;
+
+    // accrued_buffer = 10,000,000,000
+    // anc_purchase_amount = accrued_buffer * 0.2 = 2,000,000,000
+    execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+
+    // store epoch state for test purpose
+    store_epoch_state(
+        deps.as_mut().storage,
+        &EpochState {
+            last_executed_height: env.block.height,
+            prev_exchange_rate: Decimal256::from_str("1.2").unwrap(),
+            prev_aterra_supply: Uint256::from_str("1000000").unwrap(),
+            prev_interest_buffer: Uint256::from_str("9999000000").unwrap(),
+            deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+        },
+    )
+    .unwrap();
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    env.block.height += 86400u64;
+
+    // accrued_buffer = 1,000,000
+    // anc_purchase_amount = 200,000
+    // distributed_interest = 53,148 after tax
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BufferFlow { limit: None },
+    )
+    .unwrap();
+    let buffer_flow: BufferFlowResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        buffer_flow.epochs,
+        vec![
+            BufferFlowElem {
+                height: env.block.height,
+                inflow: Uint256::from(1_000_000u128),
+                distributed: Uint256::from(53148u128),
+                anc_purchased: Uint256::from(200_000u128),
+                ending_balance: Uint256::from(9999746320u128),
+            },
+            BufferFlowElem {
+                height: first_height,
+                inflow: Uint256::from(10_000_000_000u128),
+                distributed: Uint256::zero(),
+                anc_purchased: Uint256::from(2_000_000_000u128),
+                ending_balance: Uint256::from(8_000_000_000u128),
+            },
+        ]
+    );
+
+    // the limit keeps the newest epochs
+    let res = query(deps.as_ref(), env, QueryMsg::BufferFlow { limit: Some(1) }).unwrap();
+    let buffer_flow: BufferFlowResponse = from_binary(&res).unwrap();
+    assert_eq!(buffer_flow.epochs.len(), 1);
+    assert_eq!(buffer_flow.epochs[0].inflow, Uint256::from(1_000_000u128));
+}
+
+#[test]
+fn deposit_rate_smoothing() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        deposit_rate_smoothing_epochs: Some(3),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    store_epoch_state(
+        deps.as_mut().storage,
+        &EpochState {
+            last_executed_height: env.block.height,
+            prev_exchange_rate: Decimal256::from_str("1.2").unwrap(),
+            prev_aterra_supply: Uint256::from_str("1000000").unwrap(),
+            prev_interest_buffer: Uint256::from_str("9999000000").unwrap(),
+            deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+        },
+    )
+    .unwrap();
+
+    // the previous epochs realized a deposit rate above the threshold
+    for _ in 0..2 {
+        store_epoch_rate_record(
+            deps.as_mut().storage,
+            &EpochRateRecord {
+                height: env.block.height,
+                deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+                target_deposit_rate: Decimal256::permille(5),
+            },
+        )
+        .unwrap();
+    }
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+    env.block.height += 86400u64;
+
+    // (125 / 120 - 1) / 86400
+    // deposit rate = 0.000000482253086419 is below the threshold,
+    // the rate averaged over 3 epochs = 0.000001703960905349 is not
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert!(!res.messages.iter().any(|m| matches!(
+        &m.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == "market"
+    )));
+    assert!(res
+        .attributes
+        .contains(&attr("deposit_rate", "0.000000482253086419")));
+    assert!(res.attributes.contains(&attr("distributed_interest", "0")));
+
+    // without smoothing the single epoch rate distributes the buffer
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: Some(1),
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert!(res.messages.iter().any(|m| matches!(
+        &m.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == "market"
+    )));
+
+    // the window cannot exceed the epoch rate history
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: Some(101),
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg);
+    match res {
+        Err(ContractError::InvalidDepositRateSmoothing(max_epochs)) => assert_eq!(max_epochs, 100),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn deposit_interest_buffer() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    deps.querier
+        .with_interest_model(&[(&"market".to_string(), &"interest".to_string())]);
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::one()),
+    )]);
+
+    let msg = ExecuteMsg::DepositInterestBuffer {
+        amount: Uint256::from(4_000_000_000u128),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::DepositInterestBuffer {
+            amount: Uint256::from(10_000_000_001u128),
+        },
+    );
+    match res {
+        Err(ContractError::InsufficientInterestBuffer(amount)) => {
+            assert_eq!(amount, 10_000_000_000u128)
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "market".to_string(),
+            funds: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(4_000_000_000u128),
+                }
+            )
+            .unwrap()],
+            msg: to_binary(&MarketExecuteMsg::DepositStable {}).unwrap(),
+        }))]
+    );
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(6_000_000_000u128),
+        }],
+    );
+    deps.querier.with_token_balances(&[(
+        &("aterra".to_string(), MOCK_CONTRACT_ADDR.to_string()),
+        &Uint128::from(4_000_000_000u128),
+    )]);
+
+    // the deposit earns the deposit rate until the epoch
+    env.block.height += 86400u64;
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::InterestBufferDeposit {},
+    )
+    .unwrap();
+    let deposit_res: InterestBufferDepositResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        deposit_res,
+        InterestBufferDepositResponse {
+            deposited_amount: Uint256::from(4_000_000_000u128),
+            aterra_amount: Uint256::from(4_000_000_000u128),
+            value: Uint256::from(4_800_000_000u128),
+        }
+    );
+
+    // the buffer cannot leave once the epoch is due
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::BufferNeededForEpoch {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the epoch operations redeem what the market liquidity allows first,
+    // the rest stays deposited
+    deps.querier
+        .with_max_redeemable(&[(&"market".to_string(), &Uint256::from(3_000_000_000u128))]);
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "aterra".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "market".to_string(),
+                    amount: Uint128::from(3_000_000_000u128),
+                    msg: to_binary(&MarketCw20HookMsg::RedeemStable {}).unwrap(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::ExecuteEpochOperations {}).unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "redeem_interest_buffer"),
+            attr("aterra_amount", "3000000000"),
+            attr("left_deposit", "1000000000"),
+        ]
+    );
+    assert_eq!(
+        read_buffer_deposit(deps.as_ref().storage).unwrap(),
+        Uint256::from(1_000_000_000u128)
+    );
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(9_600_000_000u128),
+        }],
+    );
+    deps.querier.with_token_balances(&[(
+        &("aterra".to_string(), MOCK_CONTRACT_ADDR.to_string()),
+        &Uint128::from(1_000_000_000u128),
+    )]);
+
+    // the epoch runs with the redeemed stables
+    // accrued_buffer = 9,600,000,000
+    // anc_purchase_amount = accrued_buffer * 0.2 = 1,920,000,000
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages.last().unwrap(),
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+            funds: vec![],
+            msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                interest_buffer: Uint256::from(7_680_000_000u128),
+                distributed_interest: Uint256::zero(),
+                reserve_topup: Uint256::zero(),
+            })
+            .unwrap(),
+        }))
+    );
+}
+
+#[test]
+fn harvest_protocol_yield() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // without protocol-owned collateral nothing is harvested
+    let msg = ExecuteMsg::HarvestProtocolYield {};
+    let res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), msg).unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "harvest_protocol_yield"),
+            attr("harvested_amount", "0"),
+        ]
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the collaterals bought with the market reserves
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(2000000u64)),
+            ("batom".to_string(), Uint256::from(1000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("market", &[]), msg).unwrap();
+
+    // the rewards of each custody are pooled over its depositors
+    deps.querier.with_custody_balance(&[
+        (&"custody_bluna".to_string(), &Uint256::from(8000000u64)),
+        (&"custody_batom".to_string(), &Uint256::from(2000000u64)),
+    ]);
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+
+    // the custodies distribute one after the other
+    let msg = ExecuteMsg::HarvestProtocolYield {};
+    let res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::HarvestProtocolYieldHook {
+                    prev_interest_buffer: Uint256::from(1000000u64),
+                    protocol_share: Decimal256::percent(25),
+                    pending_custodies: vec![("custody_batom".to_string(), Decimal256::percent(50))],
+                    harvested_amount: Uint256::zero(),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![attr("harvested_custodies", "custody_bluna,custody_batom")]
+    );
+
+    // the rewards reached the interest buffer
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1500000u128),
+        }],
+    );
+
+    let msg = ExecuteMsg::HarvestProtocolYieldHook {
+        prev_interest_buffer: Uint256::from(1000000u64),
+        protocol_share: Decimal256::percent(25),
+        pending_custodies: vec![("custody_batom".to_string(), Decimal256::percent(50))],
+        harvested_amount: Uint256::zero(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("keeper", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // a quarter of the bluna rewards is protocol yield
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::HarvestProtocolYieldHook {
+                    prev_interest_buffer: Uint256::from(1500000u64),
+                    protocol_share: Decimal256::percent(50),
+                    pending_custodies: vec![],
+                    harvested_amount: Uint256::from(125000u64),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1700000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        ExecuteMsg::HarvestProtocolYieldHook {
+            prev_interest_buffer: Uint256::from(1500000u64),
+            protocol_share: Decimal256::percent(50),
+            pending_custodies: vec![],
+            harvested_amount: Uint256::from(125000u64),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "harvest_protocol_yield"),
+            attr("harvested_amount", "225000"),
+        ]
+    );
+
+    // the user position is untouched
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res.collaterals,
+        vec![("batom".to_string(), Uint256::from(1000000u64))]
+    );
+}
+
+#[test]
+fn execute_epoch_operations_with_stale_prices() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::EpochNotPassed(12345)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    env.block.height += 86400u64;
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    // batom price is older than borrow_price_timeframe
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds() - 100u64,
+                env.block.time.seconds() - 100u64,
+            ),
+        ),
+    ]);
+
+    // the stale collateral is skipped from the reward distribution
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_bluna".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_000
+            ),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(8_000_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+    assert_eq!(
+        res.attributes.last().unwrap(),
+        &attr("stale_collaterals", batom_collat_token.clone())
+    );
+
+    // abort the epoch when fresh prices are required
+    let update_msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: Some(true),
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), update_msg).unwrap();
+
+    let res = execute(deps.as_mut(), env, info, msg);
+    match res {
+        Err(ContractError::StalePrices(stale_collaterals)) => {
+            assert_eq!(stale_collaterals, batom_collat_token)
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn execute_epoch_operations_ordered() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ordered_epoch_operations: Some(true),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    env.block.height += 86400u64;
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    let reply_msg = |id: u64, result: Result<(), &str>| Reply {
+        id,
+        result: match result {
+            Ok(()) => ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+            Err(err) => ContractResult::Err(err.to_string()),
+        },
+    };
+
+    // only the first step is dispatched
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            }),
+            0
+        )]
+    );
+
+    match reply(deps.as_mut(), env.clone(), reply_msg(1, Ok(()))) {
+        Err(ContractError::InvalidReplyId(1)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = reply(deps.as_mut(), env.clone(), reply_msg(0, Ok(()))).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+            }),
+            1
+        )]
+    );
+
+    // a failed step is not followed by the epoch state update
+    match reply(
+        deps.as_mut(),
+        env.clone(),
+        reply_msg(1, Err("reward distribution failed")),
+    ) {
+        Err(ContractError::EpochStepFailed(1, err)) => {
+            assert_eq!(err, "reward distribution failed")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    assert_eq!(
+        read_epoch_state(deps.as_ref().storage)
+            .unwrap()
+            .last_executed_height,
+        mock_env().block.height
+    );
+
+    let res = reply(deps.as_mut(), env.clone(), reply_msg(1, Ok(()))).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(8_000_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }),
+            2
+        )]
+    );
+
+    // the chain cannot complete before the epoch state is stored
+    match reply(deps.as_mut(), env.clone(), reply_msg(2, Ok(()))) {
+        Err(ContractError::EpochStepFailed(2, _)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        ExecuteMsg::UpdateEpochState {
+            interest_buffer: Uint256::from(8_000_000_000u128),
+            distributed_interest: Uint256::zero(),
+            reserve_topup: Uint256::zero(),
+        },
+    )
+    .unwrap();
+    let res = reply(deps.as_mut(), env.clone(), reply_msg(2, Ok(()))).unwrap();
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations_completed"),
+            attr("steps", "3"),
+        ]
+    );
+
+    match reply(deps.as_mut(), env, reply_msg(0, Ok(()))) {
+        Err(ContractError::InvalidReplyId(0)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn execute_epoch_operations_with_custody_quorum() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        min_custody_quorum: Some(Decimal256::one()),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    env.block.height += 86400u64;
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    let reply_msg = |id: u64, result: Result<(), &str>| Reply {
+        id,
+        result: match result {
+            Ok(()) => ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+            Err(err) => ContractResult::Err(err.to_string()),
+        },
+    };
+    let update_epoch_state = ExecuteMsg::UpdateEpochState {
+        interest_buffer: Uint256::from(8_000_000_000u128),
+        distributed_interest: Uint256::zero(),
+        reserve_topup: Uint256::zero(),
+    };
+
+    // only the failed reward distributions reply
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_batom".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_000
+            ),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_bluna".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_001
+            ),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&update_epoch_state).unwrap(),
+            }))
+        ]
+    );
+
+    match reply(deps.as_mut(), env.clone(), reply_msg(1_000_002, Ok(()))) {
+        Err(ContractError::InvalidReplyId(1_000_002)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // a failed distribution does not revert the reply itself
+    let res = reply(
+        deps.as_mut(),
+        env.clone(),
+        reply_msg(1_000_000, Err("reward distribution failed")),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![]);
+    reply(deps.as_mut(), env.clone(), reply_msg(1_000_001, Ok(()))).unwrap();
+
+    // but the epoch state update reverts the epoch operations
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        update_epoch_state.clone(),
+    );
+    match res {
+        Err(ContractError::CustodyQuorumNotReached(1, 2)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the quorum is reached once half of the custody contracts distribute
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: Some(Decimal256::percent(50)),
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    reply(
+        deps.as_mut(),
+        env.clone(),
+        reply_msg(1_000_000, Err("reward distribution failed")),
+    )
+    .unwrap();
+    reply(deps.as_mut(), env.clone(), reply_msg(1_000_001, Ok(()))).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        update_epoch_state,
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes[res.attributes.len() - 2..],
+        [
+            attr("responsive_custodies", "custody_bluna"),
+            attr("unresponsive_custodies", "custody_batom"),
+        ]
+    );
+
+    // the replies are not tracked across epochs
+    match reply(deps.as_mut(), env, reply_msg(1_000_000, Ok(()))) {
+        Err(ContractError::InvalidReplyId(1_000_000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn execute_epoch_operations_with_failing_custody() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    env.block.height += 86400u64;
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    let reply_msg = |id: u64, result: Result<(), &str>| Reply {
+        id,
+        result: match result {
+            Ok(()) => ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+            Err(err) => ContractResult::Err(err.to_string()),
+        },
+    };
+    let update_epoch_state = ExecuteMsg::UpdateEpochState {
+        interest_buffer: Uint256::from(8_000_000_000u128),
+        distributed_interest: Uint256::zero(),
+        reserve_topup: Uint256::zero(),
+    };
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[1..3],
+        [
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_batom".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_000
+            ),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_bluna".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::DistributeRewards {}).unwrap(),
+                }),
+                1_000_001
+            ),
+        ]
+    );
+
+    // the reverting custody is skipped, the others still distribute
+    let res = reply(
+        deps.as_mut(),
+        env.clone(),
+        reply_msg(1_000_000, Err("custody paused")),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "distribute_rewards_failed"),
+            attr("custody_contract", "custody_batom"),
+            attr("error", "custody paused"),
+        ]
+    );
+
+    // and the epoch still finalizes
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        update_epoch_state,
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes[res.attributes.len() - 2..],
+        [
+            attr("responsive_custodies", "custody_bluna"),
+            attr("unresponsive_custodies", "custody_batom"),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::EpochState { precision: None },
+    )
+    .unwrap();
+    let epoch_state: EpochState = from_binary(&res).unwrap();
+    assert_eq!(epoch_state.last_executed_height, env.block.height);
+}
+
+#[test]
+fn execute_epoch_operations_batched() {
+    let setup = |epoch_custody_batch_size: Option<u32>| {
+        let mut deps = mock_dependencies(&[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(10000000000u128),
+        }]);
+
+        let mut env = mock_env();
+        let info = mock_info("owner", &[]);
+        let msg = InstantiateMsg {
+            threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+            epoch_custody_batch_size,
+            ..instantiate_msg()
+        };
+        let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        deps.querier.with_oracle_price(&[
+            (
+                &("bluna".to_string(), "uusd".to_string()),
+                &(
+                    Decimal256::from_ratio(1000u64, 1u64),
+                    env.block.time.seconds(),
+                    env.block.time.seconds(),
+                ),
+            ),
+            (
+                &("batom".to_string(), "uusd".to_string()),
+                &(
+                    Decimal256::from_ratio(1000u64, 1u64),
+                    env.block.time.seconds(),
+                    env.block.time.seconds(),
+                ),
+            ),
+        ]);
+
+        for name in ["bluna", "batom"] {
+            let msg = ExecuteMsg::Whitelist {
+                name: name.to_string(),
+                symbol: name.to_string(),
+                collateral_token: name.to_string(),
+                custody_contract: format!("custody_{}", name),
+                max_ltv: Decimal256::percent(60),
+                quote_denom: None,
+                liquidation_premium: None,
+                correlation_group: None,
+                min_lock_amount: None,
+                borrow_enabled_from: None,
+                borrow_disabled_from: None,
+                liquidation_contract: None,
+                decimals: None,
+                token_type: None,
+                exchange_rate_source: None,
+                borrow_rate_premium: None,
+            };
+            execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        }
+
+        env.block.height += 86400u64;
+        deps.querier.with_epoch_state(&[(
+            &"market".to_string(),
+            &(Uint256::from(1000000u64), Decimal256::percent(120)),
+        )]);
+
+        (deps, env)
+    };
+    let info = mock_info("addr0000", &[]);
+    let contract_info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+
+    let (mut single_deps, env) = setup(None);
+    let single_res = execute(
+        single_deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    let messages_len = single_res.messages.len();
+
+    let (mut deps, env) = setup(Some(1));
+
+    // a zero batch would never finish an epoch
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: Some(0),
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::InvalidEpochCustodyBatchSize {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the buffer seen by the first call is stale by the last one
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(5000000000u128),
+        }],
+    );
+
+    // the first call only asks the first custody to distribute
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_error(
+            single_res.messages[messages_len - 3].msg.clone(),
+            1_000_000
+        )]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations"),
+            attr("epoch_cursor", "1"),
+            attr("epoch_custodies", "2"),
+        ]
+    );
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::NextEpoch {}).unwrap();
+    let next_epoch_res: NextEpochResponse = from_binary(&res).unwrap();
+    assert!(next_epoch_res.in_progress);
+    assert_eq!(
+        read_custody_quorum(deps.as_ref().storage)
+            .unwrap()
+            .unwrap()
+            .custodies
+            .len(),
+        2
+    );
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(10000000000u128),
+        }],
+    );
+
+    // the last call distributes the buffer and updates the epoch state
+    // exactly as the single call did
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+    let mut expected_messages = single_res.messages.clone();
+    expected_messages.remove(messages_len - 3);
+    assert_eq!(res.messages, expected_messages);
+    assert_eq!(res.attributes[1], attr("epoch_cursor", "2"));
+    assert_eq!(res.attributes[3..], single_res.attributes[1..]);
+
+    let update_epoch_state = match &single_res.messages[messages_len - 1].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => from_binary::<ExecuteMsg>(msg).unwrap(),
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+    execute(
+        single_deps.as_mut(),
+        env.clone(),
+        contract_info.clone(),
+        update_epoch_state.clone(),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        contract_info,
+        update_epoch_state,
+    )
+    .unwrap();
+    assert_eq!(
+        read_epoch_state(deps.as_ref().storage).unwrap(),
+        read_epoch_state(single_deps.as_ref().storage).unwrap()
+    );
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::NextEpoch {}).unwrap();
+    let next_epoch_res: NextEpochResponse = from_binary(&res).unwrap();
+    assert!(!next_epoch_res.in_progress);
+
+    // a new epoch starts only after the epoch period
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::ExecuteEpochOperations {},
+    );
+    match res {
+        Err(ContractError::EpochNotPassed(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the owner can drop an epoch left in progress
+    let mut env = env;
+    env.block.height += 86400u64;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::ExecuteEpochOperations {},
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        ExecuteMsg::ResetEpochProgress {},
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::ResetEpochProgress {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "reset_epoch_progress"),
+            attr("epoch_cursor", "1"),
+        ]
+    );
+    assert_eq!(read_custody_quorum(deps.as_ref().storage).unwrap(), None);
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::NextEpoch {}).unwrap();
+    let next_epoch_res: NextEpochResponse = from_binary(&res).unwrap();
+    assert!(!next_epoch_res.in_progress);
+
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("owner", &[]),
+        ExecuteMsg::ResetEpochProgress {},
+    );
+    match res {
+        Err(ContractError::NoEpochInProgress {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn execute_epoch_operations_with_max_anc_price() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        max_anc_price: Some(Decimal256::percent(50)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    env.block.height += 86400u64;
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+    deps.querier
+        .with_anchor_token(&[(&"collector".to_string(), &"anc".to_string())]);
+
+    // ANC price is above max_anc_price; skip the purchase
+    deps.querier.with_oracle_price(&[(
+        &("anc".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(60),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+            funds: vec![],
+            msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                interest_buffer: Uint256::from(10_000_000_000u128),
+                distributed_interest: Uint256::zero(),
+                reserve_topup: Uint256::zero(),
+            })
+            .unwrap(),
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations"),
+            attr("deposit_rate", "0.000002314814814814"),
+            attr("exchange_rate", "1.2"),
+            attr("aterra_supply", "1000000"),
+            attr("distributed_interest", "0"),
+            attr("anc_purchase_amount", "0"),
+            attr("anc_purchase_executed", "false"),
+            attr("surplus_amount", "0"),
+            attr("reserve_topup", "0"),
+        ]
+    );
+
+    // ANC price is too old; skip the purchase
+    deps.querier.with_oracle_price(&[(
+        &("anc".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(40),
+            env.block.time.seconds() - 100u64,
+            env.block.time.seconds() - 100u64,
+        ),
+    )]);
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(res.attributes[6], attr("anc_purchase_executed", "false"));
+    assert_eq!(
+        res.attributes[9],
+        attr("anc_price_error", "Generic error: Price is too old")
+    );
+
+    // ANC price is at or below max_anc_price; execute the purchase
+    deps.querier.with_oracle_price(&[(
+        &("anc".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(8_000_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations"),
+            attr("deposit_rate", "0.000002314814814814"),
+            attr("exchange_rate", "1.2"),
+            attr("aterra_supply", "1000000"),
+            attr("distributed_interest", "0"),
+            attr("anc_purchase_amount", "2000000000"),
+            attr("anc_purchase_executed", "true"),
+            attr("surplus_amount", "0"),
+            attr("reserve_topup", "0"),
+        ]
+    );
+}
+
+#[test]
+fn execute_epoch_operations_with_max_anc_purchase() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        max_anc_purchase_per_epoch: Some(Uint256::from(500_000_000u128)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    env.block.height += 86400u64;
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+    deps.querier
+        .with_anchor_token(&[(&"collector".to_string(), &"anc".to_string())]);
+
+    // The factor amount of 2_000_000_000 is capped; the remainder stays in the buffer
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(500_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(9_500_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+    assert_eq!(res.attributes[5], attr("anc_purchase_amount", "500000000"));
+    assert_eq!(res.attributes[6], attr("anc_purchase_executed", "true"));
+}
+
+#[test]
+fn anc_buyback_estimate() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_anchor_token(&[(&"collector".to_string(), &"anc".to_string())]);
+    deps.querier.with_oracle_price(&[(
+        &("anc".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::percent(50),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+    // 10 UST / 20 ANC constant product pool, priced at the spot
+    deps.querier.with_amm_pool(&[(
+        &"amm".to_string(),
+        &(Uint256::from(10_000_000u128), Uint256::from(20_000_000u128)),
+    )]);
+
+    // No AMM configured
+    let query_msg = QueryMsg::AncBuybackEstimate {
+        amount: Uint256::from(1_000_000u128),
+    };
+    let res = query(deps.as_ref(), env.clone(), query_msg.clone());
+    match res {
+        Err(StdError::GenericErr { msg, .. }) => {
+            assert_eq!(msg, "ANC buyback AMM is not configured")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: Some("amm".to_string()),
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let config_res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(config_res.anc_buyback_amm, Some("amm".to_string()));
+
+    // ANC received = 20_000_000 * 1_000_000 / 11_000_000
+    let res: AncBuybackEstimateResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        AncBuybackEstimateResponse {
+            amount: Uint256::from(1_000_000u128),
+            anc_amount: Uint256::from(1_818_181u128),
+            average_price: Decimal256::from_str("0.550000247500111375").unwrap(),
+            spot_price: Decimal256::percent(50),
+            slippage: Decimal256::from_str("0.10000049500022275").unwrap(),
+        }
+    );
+
+    // A smaller buyback moves the price less
+    let res: AncBuybackEstimateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::AncBuybackEstimate {
+                amount: Uint256::from(10_000u128),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.anc_amount, Uint256::from(19_980u128));
+    assert_eq!(
+        res.average_price,
+        Decimal256::from_str("0.5005005005005005").unwrap()
+    );
+    assert_eq!(
+        res.slippage,
+        Decimal256::from_str("0.001001001001001").unwrap()
+    );
+}
+
+#[test]
+fn claim_rewards_and_repay() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_custody_rewards(&[
+        (
+            &"custody_bluna".to_string(),
+            &"addr0000".to_string(),
+            &Uint256::from(300000u64),
+        ),
+        (
+            &"custody_batom".to_string(),
+            &"addr0000".to_string(),
+            &Uint256::from(200000u64),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::ClaimRewardsAndRepay {};
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages.last().unwrap(),
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+            funds: vec![],
+            msg: to_binary(&ExecuteMsg::ClaimRewardsAndRepayHook {
+                borrower: "addr0000".to_string(),
+                prev_balance: Uint256::from(1000000u64),
+            })
+            .unwrap(),
+        }))
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_rewards_and_repay"),
+            attr("borrower", "addr0000"),
+            attr("custody_rewards", "300000custody_bluna"),
+            attr("custody_rewards", "200000custody_batom"),
+            attr("total_rewards", "500000"),
+        ]
+    );
+
+    // no custody holds rewards of addr0001
+    let msg = ExecuteMsg::ClaimRewardsAndRepay {};
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert!(res.messages.is_empty());
+
+    // the custodies sent their rewards on top of the previous balance
+    let msg = ExecuteMsg::ClaimRewardsAndRepayHook {
+        borrower: "addr0000".to_string(),
+        prev_balance: Uint256::from(500000u64),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the loan decreases by the whole claimed amount
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(800000u64))]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "market".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(500000u128),
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                    borrower: "addr0000".to_string(),
+                    prev_balance: Uint256::zero(),
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_rewards_and_repay_hook"),
+            attr("borrower", "addr0000"),
+            attr("claimed_amount", "500000"),
+            attr("repaid_amount", "500000"),
+            attr("refunded_amount", "0"),
+        ]
+    );
+
+    // the rewards above a smaller loan are refunded
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(400000u64))]);
+    let res = execute(deps.as_mut(), env, mock_info(MOCK_CONTRACT_ADDR, &[]), msg).unwrap();
+    assert_eq!(
+        res.messages[2],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(100000u128),
+            }],
+        }))
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_rewards_and_repay_hook"),
+            attr("borrower", "addr0000"),
+            attr("claimed_amount", "500000"),
+            attr("repaid_amount", "400000"),
+            attr("refunded_amount", "100000"),
+        ]
+    );
+}
+
+#[test]
+fn execute_epoch_operations_with_buffer_target() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        buffer_target: Uint256::from(5_000_000_000u128),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    env.block.height += 86400u64;
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    // accrued_buffer = 10,000,000,000
+    // anc_purchase_amount = accrued_buffer * 0.2 = 2,000,000,000
+    // interest_buffer = 8,000,000,000
+    // surplus_amount = interest_buffer - buffer_target = 3,000,000,000
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "surplus".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(3_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(5_000_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "epoch_operations"),
+            attr("deposit_rate", "0.000002314814814814"),
+            attr("exchange_rate", "1.2"),
+            attr("aterra_supply", "1000000"),
+            attr("distributed_interest", "0"),
+            attr("anc_purchase_amount", "2000000000"),
+            attr("anc_purchase_executed", "true"),
+            attr("surplus_amount", "3000000000"),
+            attr("reserve_topup", "0"),
+        ]
+    );
+
+    // interest_buffer below buffer_target; no surplus transfer
+    let info = mock_info("owner", &[]);
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::UpdateConfig {
+            owner_addr: None,
+            oracle_contract: None,
+            liquidation_contract: None,
+            threshold_deposit_rate: None,
+            target_deposit_rate: None,
+            buffer_distribution_factor: None,
+            anc_purchase_factor: None,
+            epoch_period: None,
+            borrow_price_timeframe: None,
+            liquidation_price_timeframe: None,
+            max_anc_price: None,
+            buffer_target: Some(Uint256::from(9_000_000_000u128)),
+            surplus_recipient: None,
+            price_deviation_threshold: None,
+            max_collaterals_per_borrower: None,
+            ordered_epoch_operations: None,
+            dust_threshold: None,
+            dust_tolerance: None,
+            collateral_converter: None,
+            soft_liquidation: None,
+            buffer_backstop_limit: None,
+            max_confidence_spread: None,
+            reserve_target: None,
+            reserve_topup_factor: None,
+            epoch_requires_fresh_prices: None,
+            fallback_oracle_contract: None,
+            min_custody_quorum: None,
+            same_block_collateral_delay: None,
+            max_collateral_value_per_borrower: None,
+            risk_admin: None,
+            epoch_custody_batch_size: None,
+            anc_buyback_amm: None,
+            min_lock_amount: None,
+            solvency_margin: None,
+            max_block_price_drop: None,
+            price_drop_cooldown: None,
+            dispute_window: None,
+            valuation_mode: None,
+            default_limit: None,
+            bad_debt_budget: None,
+            deposit_rate_smoothing_epochs: None,
+            skip_paused_collaterals: None,
+            max_ltv_delta_per_epoch: None,
+            max_anc_purchase_per_epoch: None,
+            stable_decimals: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![deduct_tax(
+                    deps.as_ref(),
+                    Coin {
+                        denom: "uusd".to_string(),
+                        amount: Uint128::from(2_000_000_000u128),
+                    }
+                )
+                .unwrap()],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::UpdateEpochState {
+                    interest_buffer: Uint256::from(8_000_000_000u128),
+                    distributed_interest: Uint256::zero(),
+                    reserve_topup: Uint256::zero(),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+    assert_eq!(res.attributes[7], attr("surplus_amount", "0"));
+}
+
+#[test]
+fn execute_epoch_operations_with_reserve_target() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let mut env = mock_env();
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        reserve_target: Some(Uint256::from(1000000u64)),
+        reserve_topup_factor: Some(Decimal256::percent(50)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token,
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token,
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    // the whole distribution at most
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: Some(Decimal256::percent(101)),
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::InvalidReserveTopupFactor {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    env.block.height += 86400u64;
+    store_epoch_state(
+        deps.as_mut().storage,
+        &EpochState {
+            last_executed_height: env.block.height - 86400u64,
+            prev_exchange_rate: Decimal256::from_str("1.2").unwrap(),
+            prev_aterra_supply: Uint256::from_str("1000000").unwrap(),
+            prev_interest_buffer: Uint256::from_str("9999000000").unwrap(),
+            deposit_rate: Decimal256::zero(),
+        },
+    )
+    .unwrap();
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+    deps.querier
+        .with_anc_emission_rate(&[(&"market".to_string(), &Decimal256::one())]);
+
+    // Reserves are 10,000 below the target
+    // distributed_interest = 53,680
+    // reserve_topup = min(53,680 * 0.5, 10,000) = 10,000
+    deps.querier
+        .with_market_reserves(&[(&"market".to_string(), &Decimal256::from_uint256(990000u64))]);
+    let msg = ExecuteMsg::ExecuteEpochOperations {};
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "market".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(43680u128),
+            }],
+        }))
+    );
+    let update_epoch_state = ExecuteMsg::UpdateEpochState {
+        interest_buffer: Uint256::from(9999746320u128),
+        distributed_interest: Uint256::from(43680u128),
+        reserve_topup: Uint256::from(10000u128),
+    };
+    assert_eq!(
+        res.messages[4],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+            funds: vec![],
+            msg: to_binary(&update_epoch_state).unwrap(),
+        }))
+    );
+    assert_eq!(res.attributes[4], attr("distributed_interest", "43680"));
+    assert_eq!(res.attributes[8], attr("reserve_topup", "10000"));
+
+    // No top-up once the reserves reach the target
+    deps.querier
+        .with_market_reserves(&[(&"market".to_string(), &Decimal256::from_uint256(1000000u64))]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "market".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(53680u128),
+            }],
+        }))
+    );
+    assert_eq!(res.attributes[4], attr("distributed_interest", "53680"));
+    assert_eq!(res.attributes[8], attr("reserve_topup", "0"));
+
+    // the top-up is sent along with the market epoch operations
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        update_epoch_state,
+    )
+    .unwrap();
+    match &res.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr,
+            funds,
+            ..
+        }) => {
+            assert_eq!(contract_addr, "market");
+            assert_eq!(
+                funds,
+                &vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(10000u128),
+                }]
+            );
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn epoch_rate_performance() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    // no epoch has been recorded yet
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::EpochRatePerformance { limit: None },
+    )
+    .unwrap();
+    let performance_res: EpochRatePerformanceResponse = from_binary(&res).unwrap();
+    assert_eq!(performance_res.epochs, vec![]);
+
+    let msg = ExecuteMsg::UpdateEpochState {
+        interest_buffer: Uint256::from(10000000000u128),
+        distributed_interest: Uint256::from(1000000u128),
+        reserve_topup: Uint256::zero(),
+    };
+    let mut env = mock_env();
+    let contract_info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+    env.block.height += 86400u64;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        contract_info.clone(),
+        msg.clone(),
+    )
+    .unwrap();
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+    env.block.height += 86400u64;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        contract_info.clone(),
+        msg.clone(),
+    )
+    .unwrap();
+
+    // lower the target so the next epoch overshoots it
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: Some(Decimal256::from_ratio(1u64, 10000000u64)),
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateEpochState {
+        interest_buffer: Uint256::from(10000000000u128),
+        distributed_interest: Uint256::from(1000000u128),
+        reserve_topup: Uint256::zero(),
+    };
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(130)),
+    )]);
+    env.block.height += 86400u64;
+    execute(deps.as_mut(), env.clone(), contract_info, msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::EpochRatePerformance { limit: None },
+    )
+    .unwrap();
+    let performance_res: EpochRatePerformanceResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        performance_res.epochs,
+        vec![
+            EpochRatePerformanceElem {
+                height: env.block.height,
+                realized_deposit_rate: Decimal256::from_str("0.000000462962962962").unwrap(),
+                target_deposit_rate: Decimal256::from_ratio(1u64, 10000000u64),
+                deviation: Decimal256::from_str("0.000000362962962962").unwrap(),
+                below_target: false,
+            },
+            EpochRatePerformanceElem {
+                height: env.block.height - 86400u64,
+                realized_deposit_rate: Decimal256::from_str("0.000000482253086419").unwrap(),
+                target_deposit_rate: Decimal256::permille(5),
+                deviation: Decimal256::from_str("0.004999517746913581").unwrap(),
+                below_target: true,
+            },
+            EpochRatePerformanceElem {
+                height: env.block.height - 2 * 86400u64,
+                realized_deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+                target_deposit_rate: Decimal256::permille(5),
+                deviation: Decimal256::from_str("0.004997685185185186").unwrap(),
+                below_target: true,
+            },
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::EpochRatePerformance { limit: Some(1) },
+    )
+    .unwrap();
+    let performance_res: EpochRatePerformanceResponse = from_binary(&res).unwrap();
+    assert_eq!(performance_res.epochs.len(), 1);
+    assert_eq!(performance_res.epochs[0].height, env.block.height);
+}
+
+#[test]
+fn update_epoch_state() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(10000000000u128),
+    }]);
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    // only contract itself can execute update_epoch_state
+    let msg = ExecuteMsg::UpdateEpochState {
+        interest_buffer: Uint256::from(10000000000u128),
+        distributed_interest: Uint256::from(1000000u128),
+        reserve_topup: Uint256::zero(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Assume execute epoch operation is executed
+    let mut env = mock_env();
+    let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+    env.block.height += 86400u64;
+
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(120)),
+    )]);
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "market".to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::ExecuteEpochOperations {
+                deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
+                target_deposit_rate: Decimal256::permille(5),
+                threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+                distributed_interest: Uint256::from(1000000u128),
+            })
+            .unwrap(),
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_epoch_state"),
+            attr("deposit_rate", "0.000002314814814814"),
+            attr("aterra_supply", "1000000"),
+            attr("exchange_rate", "1.2"),
+            attr("interest_buffer", "10000000000"),
+        ]
+    );
+
+    // Deposit rate increased
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(Uint256::from(1000000u64), Decimal256::percent(125)),
+    )]);
+
+    env.block.height += 86400u64;
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "market".to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::ExecuteEpochOperations {
+                deposit_rate: Decimal256::from_str("0.000000482253086419").unwrap(),
+                target_deposit_rate: Decimal256::permille(5),
+                threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
+                distributed_interest: Uint256::from(1000000u128),
+            })
+            .unwrap(),
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_epoch_state"),
+            attr("deposit_rate", "0.000000482253086419"),
+            attr("aterra_supply", "1000000"),
+            attr("exchange_rate", "1.25"),
+            attr("interest_buffer", "10000000000"),
+        ]
+    );
+
+    let epoch_state_response = query_epoch_state(
+        deps.as_ref(),
+        Addr::unchecked("market"),
+        env.block.height,
+        None,
+    )
+    .unwrap();
+    let epoch_state = read_epoch_state(deps.as_ref().storage).unwrap();
+
+    // deposit rate = 0.000000482253078703
+    assert_eq!(
+        epoch_state,
+        EpochState {
+            deposit_rate: Decimal256::from_ratio(482253086419u64, 1000000000000000000u64),
+            prev_aterra_supply: epoch_state_response.aterra_supply,
+            prev_exchange_rate: epoch_state_response.exchange_rate,
+            prev_interest_buffer: Uint256::from(10000000000u128),
+            last_executed_height: env.block.height,
+        }
+    )
+}
+
+#[test]
+fn lock_collateral() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(1000000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000000u64),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "lock_collateral"),
+            attr("borrower", "addr0000"),
+            attr(
+                "collaterals",
+                format!(
+                    "1000000{},10000000{}",
+                    bluna_collat_token, batom_collat_token
+                )
+            ),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res,
+        CollateralsResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                (batom_collat_token.clone(), Uint256::from(10000000u64)),
+                (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            ]
+        }
+    );
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::AllCollaterals {
+            start_after: None,
+            limit: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        all_collaterals_res,
+        AllCollateralsResponse {
+            all_collaterals: vec![CollateralsResponse {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+                collaterals: vec![
+                    (batom_collat_token, Uint256::from(10000000u64)),
+                    (bluna_collat_token, Uint256::from(1000000u64)),
+                ]
+            }]
+        }
+    );
+}
+
+#[test]
+fn all_collaterals_page_limit() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        default_limit: Some(5),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let collateral_token = deps.api.addr_canonicalize("bluna").unwrap();
+    for i in 0..35 {
+        let borrower = deps
+            .api
+            .addr_canonicalize(&format!("addr{:04}", i))
+            .unwrap();
+        store_collaterals(
+            deps.as_mut().storage,
+            &borrower,
+            None,
+            &vec![(collateral_token.clone(), Uint256::from(100u64))],
+        )
+        .unwrap();
+    }
+
+    // no limit returns a page of the default limit
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::AllCollaterals {
+            start_after: None,
+            limit: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(all_collaterals_res.all_collaterals.len(), 5);
+
+    // an over-large limit is clamped to the max page size
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::AllCollaterals {
+            start_after: None,
+            limit: Some(100),
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(all_collaterals_res.all_collaterals.len(), 30);
+
+    // the default limit cannot exceed the max page size
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: Some(31),
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg);
+    match res {
+        Err(ContractError::InvalidDefaultLimit(max_limit)) => assert_eq!(max_limit, 30),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn lock_collateral_for() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateralFor {
+        borrower: "addr0000".to_string(),
+        collaterals: vec![(bluna_collat_token.clone(), Uint256::from(1000000u64))],
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("market", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(1000000u64),
+            })
+            .unwrap(),
+        }))]
+    );
+
+    let res: CollateralsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Collaterals {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+                canonical: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![(bluna_collat_token, Uint256::from(1000000u64))]
+    );
+}
+
+#[test]
+fn min_lock_amount() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        min_lock_amount: Some(Uint256::from(1000u64)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: Some(Uint256::from(100000u64)),
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info, msg);
+
+    let res: WhitelistResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Whitelist {
+                collateral_token: Some(batom_collat_token.clone()),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.elems[0].min_lock_amount, Some(Uint256::from(100000u64)));
+
+    let info = mock_info("addr0000", &[]);
+
+    // bluna falls back to the global minimum
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![(bluna_collat_token.clone(), Uint256::from(999u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::LockAmountTooSmall(
+            bluna_collat_token.clone(),
+            Uint256::from(1000u64),
+        ))
+    );
+
+    // batom is held to its own minimum, above the global one
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000u64)),
+            (batom_collat_token.clone(), Uint256::from(99999u64)),
+        ],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::LockAmountTooSmall(
+            batom_collat_token.clone(),
+            Uint256::from(100000u64),
+        ))
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token, Uint256::from(1000u64)),
+            (batom_collat_token, Uint256::from(100000u64)),
+        ],
+        sub_account: None,
+    };
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+}
+
+#[test]
+fn canonical_collaterals() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), mock_env(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(1000000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000000u64),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "lock_collateral"),
+            attr("borrower", "addr0000"),
+            attr(
+                "collaterals",
+                format!(
+                    "1000000{},10000000{}",
+                    bluna_collat_token, batom_collat_token
+                )
+            ),
+        ]
+    );
+
+    let query_both = |canonical: bool| -> CollateralsResponse {
+        from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Collaterals {
+                    borrower: "addr0000".to_string(),
+                    sub_account: None,
+                    canonical: Some(canonical),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+    let human_res = query_both(false);
+    let canonical_res = query_both(true);
+
+    let humanize = |hex: &str| -> String {
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        deps.api
+            .addr_humanize(&CanonicalAddr::from(bytes))
+            .unwrap()
+            .to_string()
+    };
+
+    assert_eq!(
+        canonical_res.borrower,
+        deps.api.addr_canonicalize("addr0000").unwrap().to_string()
+    );
+    assert_eq!(humanize(&canonical_res.borrower), human_res.borrower);
+    assert_eq!(canonical_res.collaterals.len(), 2);
+    for (canonical, human) in canonical_res
+        .collaterals
+        .iter()
+        .zip(human_res.collaterals.iter())
+    {
+        assert_eq!(humanize(&canonical.0), human.0);
+        assert_eq!(canonical.1, human.1);
+    }
+
+    let res = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::AllCollaterals {
+            start_after: None,
+            limit: None,
+            canonical: Some(true),
+        },
+    )
+    .unwrap();
+    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(all_collaterals_res.all_collaterals, vec![canonical_res]);
+}
+
+#[test]
+fn lock_collateral_max_collaterals() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        max_collaterals_per_borrower: 2,
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    for token in ["bluna", "batom", "beth"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: token.to_string(),
+            symbol: token.to_string(),
+            collateral_token: token.to_string(),
+            custody_contract: format!("custody_{}", token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    }
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // third distinct collateral
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("beth".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::TooManyCollaterals(2)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // increasing an existing collateral is fine
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(10000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // existing positions are grandfathered when the limit goes down
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: Some(1),
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res: CollateralsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Collaterals {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+                canonical: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![
+            ("bluna".to_string(), Uint256::from(2000000u64)),
+            ("batom".to_string(), Uint256::from(20000000u64)),
+        ]
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("beth".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::TooManyCollaterals(1)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn unlock_collateral() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // Failed to unlock more than locked amount
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000001u64)),
+            ("batom".to_string(), Uint256::from(10000001u64)),
+        ],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::UnlockExceedsLocked {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+
+    // cannot unlock any tokens
+    // Failed to unlock more than locked amount
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::one())],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::UnlockTooLarge(12599999400)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::one())],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::UnlockTooLarge(12599998800)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12599999400u64))]);
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(12600000000u64),);
+
+    // Cannot unlock 2bluna
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(2u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::UnlockTooLarge(12599998800)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // Can unlock 1bluna
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::one())],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::one(),
+            })
+            .unwrap(),
+        }))]
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "unlock_collateral"),
+            attr("borrower", "addr0000"),
+            attr("collaterals", "1bluna"),
+        ]
+    );
+
+    //testing for unlocking more collaterals
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(125999900u128))]);
+
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1u128)),
+            ("batom".to_string(), Uint256::from(1u128)),
+        ],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(1u128),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(1u128),
+                })
+                .unwrap(),
+            }))
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "unlock_collateral"),
+            attr("borrower", "addr0000"),
+            attr("collaterals", "1bluna,1batom"),
+        ]
+    );
+
+    // a counter drifted below the locked positions fails the unlock
+    let batom_raw = deps.api.addr_canonicalize("batom").unwrap();
+    store_total_locked(deps.as_mut().storage, &batom_raw, &Uint256::zero()).unwrap();
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(1u128))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::TotalLockedUnderflow(total_locked, _, amount)) => {
+            assert_eq!(total_locked, Uint256::zero());
+            assert_eq!(amount, Uint256::from(1u128));
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn transfer_position() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let accept_msg = ExecuteMsg::AcceptPosition {
+        borrower: "addr0000".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        accept_msg.clone(),
+    );
+    match res {
+        Err(ContractError::NoPositionTransfer(borrower)) => assert_eq!(borrower, "addr0000"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::TransferPosition {
+            recipient: "addr0000".to_string(),
+            expires_at: env.block.height + 100,
+        },
+    );
+    match res {
+        Err(ContractError::SelfPositionTransfer {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::TransferPosition {
+            recipient: "addr0001".to_string(),
+            expires_at: env.block.height,
+        },
+    );
+    match res {
+        Err(ContractError::InvalidTransferExpiry {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let transfer_msg = ExecuteMsg::TransferPosition {
+        recipient: "addr0001".to_string(),
+        expires_at: env.block.height + 100,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        transfer_msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "transfer_position"),
+            attr("borrower", "addr0000"),
+            attr("recipient", "addr0001"),
+            attr("expires_at", "12445"),
+        ]
+    );
+
+    // only the offered recipient can accept
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0002", &[]),
+        accept_msg.clone(),
+    );
+    match res {
+        Err(ContractError::NoPositionTransfer(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the offer lapses at its expiry height
+    let mut expired_env = env.clone();
+    expired_env.block.height += 100;
+    let res = execute(
+        deps.as_mut(),
+        expired_env,
+        mock_info("addr0001", &[]),
+        accept_msg.clone(),
+    );
+    match res {
+        Err(ContractError::PositionTransferExpired(borrower)) => assert_eq!(borrower, "addr0000"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // a cancelled offer cannot be accepted
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::CancelPositionTransfer {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "cancel_position_transfer"),
+            attr("borrower", "addr0000"),
+        ]
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        accept_msg.clone(),
+    );
+    match res {
+        Err(ContractError::NoPositionTransfer(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::CancelPositionTransfer {},
+    );
+    match res {
+        Err(ContractError::NoPositionTransfer(borrower)) => assert_eq!(borrower, "addr0000"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    execute(deps.as_mut(), env.clone(), info, transfer_msg).unwrap();
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd, below the combined loan
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(12000000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(1000000000u64)),
+    ]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        accept_msg.clone(),
+    );
+    match res {
+        Err(ContractError::TransferExceedsLimit(12600000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(12000000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(600000000u64)),
+    ]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        accept_msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::TransferCollateral {
+                    borrower: "addr0000".to_string(),
+                    recipient: "addr0001".to_string(),
+                    amount: Uint256::from(1000000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::TransferCollateral {
+                    borrower: "addr0000".to_string(),
+                    recipient: "addr0001".to_string(),
+                    amount: Uint256::from(10000000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::TransferLoan {
+                    borrower: "addr0000".to_string(),
+                    recipient: "addr0001".to_string(),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "accept_position"),
+            attr("borrower", "addr0000"),
+            attr("recipient", "addr0001"),
+            attr("collaterals", "1000000bluna,10000000batom"),
+            attr("loan_amount", "12000000000"),
+        ]
+    );
+
+    let collaterals = |borrower: &str| {
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Collaterals {
+                borrower: borrower.to_string(),
+                sub_account: None,
+                canonical: None,
+            },
+        )
+        .unwrap();
+        from_binary::<CollateralsResponse>(&res)
+            .unwrap()
+            .collaterals
+    };
+    assert_eq!(collaterals("addr0000"), vec![]);
+    assert_eq!(
+        collaterals("addr0001"),
+        vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ]
+    );
+
+    // the offer is consumed by the transfer
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), accept_msg);
+    match res {
+        Err(ContractError::NoPositionTransfer(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn unlock_collateral_underwater_loan() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bworthless".to_string(),
+        symbol: "bworthless".to_string(),
+        collateral_token: "bworthless".to_string(),
+        custody_contract: "custody_bworthless".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("bworthless".to_string(), Uint256::from(1000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("bworthless".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::zero(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 0 * 1000000 * 0.6
+    // = 600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(600000001u64))]);
+
+    // cannot unlock worthless collateral while the loan is underwater
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bworthless".to_string(), Uint256::one())],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::UnlockUnderwaterLoan(600000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // worthless collateral can be unlocked once the loan is safe
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(600000000u64))]);
+
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bworthless".to_string(), Uint256::one())],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_bworthless".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::one(),
+            })
+            .unwrap(),
+        }))]
+    );
+}
+
+#[test]
+fn unlock_collateral_from_repay() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::UnlockCollateralFromRepay {
+        borrower: "addr0000".to_string(),
+        prev_loan_amount: Uint256::from(1000000000u64),
+        repay_amount: Uint256::from(250000000u64),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let prev_borrow_limit: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        prev_borrow_limit.borrow_limit,
+        Uint256::from(12600000000u64)
+    );
+
+    // 25% of the loan is repaid, so 25% of each collateral gets unlocked
+    let res = execute(deps.as_mut(), env.clone(), mock_info("market", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(250000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(2500000u64),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "unlock_collateral_from_repay"),
+            attr("borrower", "addr0000"),
+            attr("collaterals", "250000bluna,2500000batom"),
+        ]
+    );
+
+    // loan_amount / borrow_limit is preserved after the repayment
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit.borrow_limit, Uint256::from(9450000000u64));
+    assert_eq!(
+        Uint256::from(750000000u64) * prev_borrow_limit.borrow_limit,
+        Uint256::from(1000000000u64) * borrow_limit.borrow_limit
+    );
+
+    // underwater loans do not unlock any collateral
+    let msg = ExecuteMsg::UnlockCollateralFromRepay {
+        borrower: "addr0000".to_string(),
+        prev_loan_amount: Uint256::from(10000000000u64),
+        repay_amount: Uint256::from(100u64),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, mock_info("market", &[]), msg).unwrap();
+    assert_eq!(res.messages, vec![]);
+}
+
+#[test]
+fn liquidation_freeze() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::SetLiquidationFreeze { frozen: true };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "set_liquidation_freeze"),
+            attr("frozen", "true"),
+        ]
+    );
+    let config_res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert!(config_res.liquidation_frozen);
+
+    // the loan is above the borrow limit of 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    let liquidate_msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        liquidate_msg.clone(),
+    );
+    match res {
+        Err(ContractError::LiquidationFrozen {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // repayments still unlock collaterals
+    let msg = ExecuteMsg::UnlockCollateralFromRepay {
+        borrower: "addr0000".to_string(),
+        prev_loan_amount: Uint256::from(1000000000u64),
+        repay_amount: Uint256::from(250000000u64),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("market", &[]), msg).unwrap();
+    assert_eq!(res.messages.len(), 2);
+
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    let msg = ExecuteMsg::SetLiquidationFreeze { frozen: false };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("addr0001", &[]),
+        liquidate_msg,
+    )
+    .unwrap();
+}
+
+#[test]
+fn max_liquidatable() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    let max_liquidatable = |deps: Deps| -> MaxLiquidatableResponse {
+        let msg = QueryMsg::MaxLiquidatable {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+        };
+        from_binary(&query(deps, env.clone(), msg).unwrap()).unwrap()
+    };
+
+    // a safe loan cannot be liquidated
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+    assert_eq!(
+        max_liquidatable(deps.as_ref()),
+        MaxLiquidatableResponse {
+            borrower: "addr0000".to_string(),
+            repay_amount: Uint256::zero(),
+            bid_fee: Uint256::zero(),
+            collaterals: vec![],
+        }
+    );
+
+    // the liquidation model takes 1% of each collateral
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    let res = max_liquidatable(deps.as_ref());
+    assert_eq!(
+        res.collaterals,
+        vec![
+            ("bluna".to_string(), Uint256::from(10000u64)),
+            ("batom".to_string(), Uint256::from(100000u64)),
+        ]
+    );
+    let msg = QueryMsg::LiquidationBonus {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let bonus_res: LiquidationBonusResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(res.repay_amount, bonus_res.repay_amount);
+    assert_eq!(res.bid_fee, bonus_res.protocol_fee);
+
+    // the liquidation seizes the same collaterals
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let liquidation = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    for (collateral_token, amount) in res.collaterals {
+        assert!(liquidation
+            .messages
+            .contains(&SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: format!("custody_{}", collateral_token),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "addr0001".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount,
+                    premium_rate: None,
+                })
+                .unwrap(),
+            }))));
+    }
+}
+
+#[test]
+fn liquidation_waterfall() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "beth".to_string(),
+        symbol: "beth".to_string(),
+        collateral_token: "beth".to_string(),
+        custody_contract: "custody_beth".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: Some(Decimal256::percent(10)),
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+            ("beth".to_string(), Uint256::from(1000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("beth".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let liquidation_waterfall = |deps: Deps| -> LiquidationWaterfallResponse {
+        let msg = QueryMsg::LiquidationWaterfall {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+        };
+        from_binary(&query(deps, env.clone(), msg).unwrap()).unwrap()
+    };
+
+    // 1% of each collateral cures the shortfall, beth is seized at a 10% premium;
+    // the steps follow the order the collaterals are locked in storage
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(13_200_000_001u64))]);
+    assert_eq!(
+        liquidation_waterfall(deps.as_ref()),
+        LiquidationWaterfallResponse {
+            borrower: "addr0000".to_string(),
+            loan_amount: Uint256::from(13_200_000_001u64),
+            steps: vec![
+                LiquidationWaterfallElem {
+                    collateral_token: "beth".to_string(),
+                    amount: Uint256::from(10_000u64),
+                    value: Uint256::from(10_000_000u64),
+                    repay_amount: Uint256::from(8_910_000u64),
+                    remaining_loan: Uint256::from(13_191_090_001u64),
+                },
+                LiquidationWaterfallElem {
+                    collateral_token: "bluna".to_string(),
+                    amount: Uint256::from(10_000u64),
+                    value: Uint256::from(10_000_000u64),
+                    repay_amount: Uint256::from(9_405_000u64),
+                    remaining_loan: Uint256::from(13_181_685_001u64),
+                },
+                LiquidationWaterfallElem {
+                    collateral_token: "batom".to_string(),
+                    amount: Uint256::from(100_000u64),
+                    value: Uint256::from(200_000_000u64),
+                    repay_amount: Uint256::from(188_100_000u64),
+                    remaining_loan: Uint256::from(12_993_585_001u64),
+                },
+            ],
+            repay_amount: Uint256::from(206_415_000u64),
+            cures_shortfall: true,
+            residual_bad_debt: Uint256::zero(),
+        }
+    );
+
+    // seizing every collateral leaves bad debt
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(100))]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(30_000_000_000u64))]);
+    let res = liquidation_waterfall(deps.as_ref());
+    assert_eq!(
+        res.steps
+            .iter()
+            .map(|s| (s.collateral_token.as_str(), s.repay_amount))
+            .collect::<Vec<(&str, Uint256)>>(),
+        vec![
+            ("beth", Uint256::from(891_000_000u64)),
+            ("bluna", Uint256::from(940_500_000u64)),
+            ("batom", Uint256::from(18_810_000_000u64)),
+        ]
+    );
+    assert_eq!(res.repay_amount, Uint256::from(20_641_500_000u64));
+    assert!(!res.cures_shortfall);
+    assert_eq!(res.residual_bad_debt, Uint256::from(9_358_500_000u64));
+    assert_eq!(res.steps[2].remaining_loan, Uint256::from(9_358_500_000u64));
+}
+
+#[test]
+fn stress_test() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // addr0002 keeps a safe default position and borrows on a sub-account
+    for (borrower, sub_account) in [
+        ("addr0000", None),
+        ("addr0001", None),
+        ("addr0002", None),
+        ("addr0002", Some("trade".to_string())),
+    ] {
+        let msg = ExecuteMsg::LockCollateral {
+            collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+            sub_account,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info(borrower, &[]), msg).unwrap();
+    }
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(10))]);
+
+    // liquidation limit = 1000000 * 1000 * 0.6 = 600000000 per position
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(500000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(400000000u64)),
+        (&"addr0002/trade".to_string(), &Uint256::from(500000000u64)),
+    ]);
+    let stress_test = |deps: Deps, price_drop: Decimal256, start_after: Option<String>, limit| {
+        let msg = QueryMsg::StressTest {
+            price_drop,
+            start_after,
+            limit,
+        };
+        let res: StressTestResponse = from_binary(&query(deps, env.clone(), msg).unwrap()).unwrap();
+        res
+    };
+
+    // every position stays safe without a shock
+    let res = stress_test(deps.as_ref(), Decimal256::zero(), None, None);
+    assert_eq!(res.positions, 4);
+    assert_eq!(res.liquidatable_positions, 0);
+    assert_eq!(res.repay_amount, Uint256::zero());
+
+    // at a 20% drop the limit is 480000000, the loans of 500000000 are liquidatable.
+    // Each liquidation seizes 100000 bluna worth 80000000 at the dropped price and
+    // repays 80000000 * 0.95 * 0.99 = 75240000
+    let res = stress_test(deps.as_ref(), Decimal256::percent(20), None, None);
+    assert_eq!(
+        res,
+        StressTestResponse {
+            price_drop: Decimal256::percent(20),
+            positions: 4,
+            liquidatable_positions: 2,
+            loan_amount: Uint256::from(1000000000u64),
+            repay_amount: Uint256::from(150480000u64),
+            seized_value: Uint256::from(160000000u64),
+            last_borrower: res.last_borrower.clone(),
+        }
+    );
+
+    // the chunks add up to the whole borrower set
+    let first = stress_test(deps.as_ref(), Decimal256::percent(20), None, Some(2u32));
+    let second = stress_test(
+        deps.as_ref(),
+        Decimal256::percent(20),
+        first.last_borrower.clone(),
+        Some(2u32),
+    );
+    assert_eq!(first.positions + second.positions, 4);
+    assert_eq!(
+        first.liquidatable_positions + second.liquidatable_positions,
+        2
+    );
+    assert_eq!(
+        first.repay_amount + second.repay_amount,
+        Uint256::from(150480000u64)
+    );
+    assert_eq!(second.last_borrower, res.last_borrower);
+
+    // prices cannot drop by more than their value
+    let msg = QueryMsg::StressTest {
+        price_drop: Decimal256::percent(101),
+        start_after: None,
+        limit: None,
+    };
+    assert!(query(deps.as_ref(), env.clone(), msg).is_err());
+}
+
+#[test]
+fn price_deviation_alert() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        price_deviation_threshold: Some(Decimal256::percent(10)),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+
+    let unlock_msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1u64))],
+        sub_account: None,
+    };
+    let unlock_attributes = vec![
+        attr("action", "unlock_collateral"),
+        attr("borrower", "addr0000"),
+        attr("collaterals", "1bluna"),
+    ];
+
+    let mut unlock_with_price = |price: u64| {
+        deps.querier.with_oracle_price(&[(
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(price, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        )]);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("addr0000", &[]),
+            unlock_msg.clone(),
+        )
+        .unwrap()
+        .attributes
+    };
+
+    // nothing to compare with on the first computation
+    assert_eq!(unlock_with_price(100), unlock_attributes);
+
+    // 10% move is not past the threshold
+    assert_eq!(unlock_with_price(110), unlock_attributes);
+
+    // 20% drop from the last recorded price
+    let mut expected = unlock_attributes.clone();
+    expected.push(attr("price_deviation", "bluna:110->88"));
+    assert_eq!(unlock_with_price(88), expected);
+
+    // the last recorded price moved along
+    assert_eq!(unlock_with_price(90), unlock_attributes);
+}
+
+#[test]
+fn liquidate_collateral() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "addr0001".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "addr0001".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "market".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                        borrower: "addr0000".to_string(),
+                        prev_balance: Uint256::zero(),
+                        sub_account: None,
+                    })
+                    .unwrap(),
+                }),
+                4_000_000
+            )
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res,
+        CollateralsResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                (batom_collat_token, Uint256::from(9900000u64)),
+                (bluna_collat_token, Uint256::from(990000u64)),
+            ]
+        }
+    );
+}
+
+#[test]
+fn native_collateral() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: Some(TokenType::Native {
+            denom: "uluna".to_string(),
+        }),
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let lock_msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+
+    // the native collateral is locked with exactly its attached amount
+    for funds in [
+        vec![],
+        vec![Coin {
+            denom: "uluna".to_string(),
+            amount: Uint128::from(999999u128),
+        }],
+        vec![
+            Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(1000000u128),
+            },
+            Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(1u128),
+            },
+        ],
+    ] {
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("addr0000", &funds),
+            lock_msg.clone(),
+        );
+        match res {
+            Err(ContractError::NativeCollateralFundsMismatch(_)) => (),
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(1000000u128),
+            }],
+        ),
+        lock_msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_batom".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(10000000u64),
+            })
+            .unwrap(),
+        }))]
+    );
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // the overseer sends the unlocked native collateral back
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![(bluna_collat_token.clone(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uluna".to_string(),
+                amount: Uint128::from(500000u128),
+            }],
+        }))]
+    );
+
+    // borrow_limit = 1000 * 500000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,300,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12300000001u64))]);
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "addr0001".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "liquidation".to_string(),
+                funds: vec![Coin {
+                    denom: "uluna".to_string(),
+                    amount: Uint128::from(5000u128),
+                }],
+                msg: to_binary(&LiquidationExecuteMsg::ExecuteBid {
+                    collateral_token: bluna_collat_token,
+                    liquidator: "addr0001".to_string(),
+                    fee_address: Some(MOCK_CONTRACT_ADDR.to_string()),
+                    repay_address: Some("market".to_string()),
+                    borrower: Some("addr0000".to_string()),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "market".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                        borrower: "addr0000".to_string(),
+                        prev_balance: Uint256::zero(),
+                        sub_account: None,
+                    })
+                    .unwrap(),
+                }),
+                4_000_000
+            ),
+        ]
+    );
+}
+
+#[test]
+fn skip_paused_collaterals() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        skip_paused_collaterals: Some(true),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_batom".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                        liquidator: "addr0001".to_string(),
+                        borrower: "addr0000".to_string(),
+                        amount: Uint256::from(100000u64),
+                        premium_rate: None,
+                    })
+                    .unwrap(),
+                }),
+                3_000_000
+            ),
+            SubMsg::reply_on_error(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "custody_bluna".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                        liquidator: "addr0001".to_string(),
+                        borrower: "addr0000".to_string(),
+                        amount: Uint256::from(10000u64),
+                        premium_rate: None,
+                    })
+                    .unwrap(),
+                }),
+                3_000_001
+            ),
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "market".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                        borrower: "addr0000".to_string(),
+                        prev_balance: Uint256::zero(),
+                        sub_account: None,
+                    })
+                    .unwrap(),
+                }),
+                4_000_000
+            )
+        ]
+    );
+
+    let reply_msg = |id: u64, result: Result<(), &str>| Reply {
+        id,
+        result: match result {
+            Ok(()) => ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+            Err(err) => ContractResult::Err(err.to_string()),
+        },
+    };
+
+    match reply(deps.as_mut(), env.clone(), reply_msg(3_000_002, Ok(()))) {
+        Err(ContractError::InvalidReplyId(3_000_002)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // The paused bluna is skipped, batom is still liquidated
+    let res = reply(
+        deps.as_mut(),
+        env.clone(),
+        reply_msg(3_000_001, Err("transfers are paused")),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "skip_paused_collateral"),
+            attr("borrower", "addr0000"),
+            attr("skipped_collateral", format!("10000{}", bluna_collat_token)),
+            attr("error", "transfers are paused"),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res,
+        CollateralsResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                (batom_collat_token.clone(), Uint256::from(9900000u64)),
+                (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            ]
+        }
+    );
+
+    // A failed unlock transfer reverts the unlock
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![(bluna_collat_token.clone(), Uint256::from(100u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_error(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100u64),
+                })
+                .unwrap(),
+            }),
+            2_000_000
+        )]
+    );
+
+    match reply(
+        deps.as_mut(),
+        env,
+        reply_msg(2_000_000, Err("transfers are paused")),
+    ) {
+        Err(ContractError::CollateralTransferPaused(token)) => {
+            assert_eq!(token, bluna_collat_token)
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn liquidation_auction() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_liquidation_auction_duration(&[(&"liquidation".to_string(), &100u64)]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "liquidation".to_string(),
+                funds: vec![],
+                msg: to_binary(&LiquidationExecuteMsg::StartAuction {
+                    borrower: "addr0000".to_string(),
+                    sub_account: None,
+                    collaterals: vec![
+                        (batom_collat_token.clone(), Uint256::from(100000u64)),
+                        (bluna_collat_token.clone(), Uint256::from(10000u64)),
+                    ],
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "liquidation".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "liquidation".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res,
+        CollateralsResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                (batom_collat_token.clone(), Uint256::from(9900000u64)),
+                (bluna_collat_token.clone(), Uint256::from(990000u64)),
+            ]
+        }
+    );
+
+    // the bid on the auction repays the loan through the overseer
+    let msg = ExecuteMsg::RepayAuctionProceeds {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let funds = [Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }];
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &funds),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidation", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::AuctionProceedsNotProvided(denom)) => assert_eq!(denom, "uusd"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidation", &funds),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "market".to_string(),
+                amount: funds.to_vec(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                    borrower: "addr0000".to_string(),
+                    prev_balance: Uint256::zero(),
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    // the collaterals of an auction nobody bid on return to the position
+    let msg = ExecuteMsg::ReturnAuctionCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+        collaterals: vec![
+            (
+                batom_collat_token.clone(),
+                "custody_batom".to_string(),
+                Uint256::from(100000u64),
+            ),
+            (
+                bluna_collat_token.clone(),
+                "custody_batom".to_string(),
+                Uint256::from(10000u64),
+            ),
+        ],
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidation", &[]),
+        msg,
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::ReturnAuctionCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+        collaterals: vec![
+            (
+                batom_collat_token.clone(),
+                "custody_batom".to_string(),
+                Uint256::from(100000u64),
+            ),
+            (
+                bluna_collat_token.clone(),
+                "custody_bluna".to_string(),
+                Uint256::from(10000u64),
+            ),
+        ],
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidation", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100000u64),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res,
+        CollateralsResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                (batom_collat_token, Uint256::from(10000000u64)),
+                (bluna_collat_token, Uint256::from(1000000u64)),
+            ]
+        }
+    );
+}
+
+#[test]
+fn collateral_liquidation_contract() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_liquidation_percent(&[
+        (&"liquidation".to_string(), &Decimal256::percent(1)),
+        (&"liquidation_bluna".to_string(), &Decimal256::percent(1)),
+    ]);
+    deps.querier.with_liquidation_auction_duration(&[
+        (&"liquidation".to_string(), &100u64),
+        (&"liquidation_bluna".to_string(), &100u64),
+    ]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: Some("liquidation_bluna".to_string()),
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "liquidation".to_string(),
+                funds: vec![],
+                msg: to_binary(&LiquidationExecuteMsg::StartAuction {
+                    borrower: "addr0000".to_string(),
+                    sub_account: None,
+                    collaterals: vec![(batom_collat_token, Uint256::from(100000u64))],
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "liquidation".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "liquidation_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&LiquidationExecuteMsg::StartAuction {
+                    borrower: "addr0000".to_string(),
+                    sub_account: None,
+                    collaterals: vec![(bluna_collat_token.clone(), Uint256::from(10000u64))],
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "liquidation_bluna".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+
+    // the collateral liquidation contract may repay the auction proceeds
+    let msg = ExecuteMsg::RepayAuctionProceeds {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "liquidation_bluna",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(1000u128),
+            }],
+        ),
+        msg,
+    )
+    .unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::WhitelistEntry {
+            collateral_token: bluna_collat_token.clone(),
+        },
+    )
+    .unwrap();
+    let whitelist_res: WhitelistResponseElem = from_binary(&res).unwrap();
+    assert_eq!(
+        whitelist_res.liquidation_contract,
+        Some("liquidation_bluna".to_string())
+    );
+
+    // an empty liquidation contract is rejected
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: bluna_collat_token,
+        custody_contract: None,
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: Some("".to_string()),
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let res = execute(deps.as_mut(), env, mock_info("owner", &[]), msg);
+    assert!(res.is_err());
+}
+
+#[test]
+fn price_drop_cooldown() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let mut env = mock_env();
+    let msg = InstantiateMsg {
+        max_block_price_drop: Some(Decimal256::percent(20)),
+        price_drop_cooldown: Some(10u64),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+
+    // bluna drops by 30% since the previous observation
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(700u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "pause_liquidation"),
+            attr("borrower", "addr0000"),
+            attr(
+                "price_drop",
+                format!("{}:{}", bluna_collat_token, Decimal256::percent(30))
+            ),
+        ]
+    );
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    assert_eq!(
+        res,
+        Err(ContractError::LiquidationPaused(bluna_collat_token.clone()))
+    );
+
+    // bluna backs no borrows during the cooldown
+    // borrow_limit = 2000 * 9900000 * 0.6 = 11,880,000,000 uusd
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(11880000000u64));
+
+    // the price is confirmed once the cooldown has passed
+    env.block.height += 10;
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(12295800000u64));
+
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(res.messages.len(), 3);
+}
+
+#[test]
+fn delegate_liquidation() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+
+    let msg = ExecuteMsg::DelegateLiquidation {
+        keeper: "addr0000".to_string(),
+        max_bonus: Uint256::from(10000000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res {
+        Err(ContractError::SelfLiquidationDelegation {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::DelegateLiquidation {
+        keeper: "keeper0000".to_string(),
+        max_bonus: Uint256::from(10000000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "delegate_liquidation"),
+            attr("borrower", "addr0000"),
+            attr("keeper", "keeper0000"),
+            attr("max_bonus", "10000000"),
+        ]
+    );
+
+    // only the delegated keeper may liquidate the safe loan
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // bonus = (2000 * 100000 + 1000 * 10000) * 5% = 10,500,000 uusd
+    let keeper_info = mock_info("keeper0000", &[]);
+    let res = execute(deps.as_mut(), env.clone(), keeper_info.clone(), msg.clone());
+    match res {
+        Err(ContractError::DelegatedBonusExceedsCap(10000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::DelegateLiquidation {
+            keeper: "keeper0000".to_string(),
+            max_bonus: Uint256::from(10500000u64),
+        },
+    )
+    .unwrap();
+    let res = execute(deps.as_mut(), env.clone(), keeper_info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "keeper0000".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(100000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "keeper0000".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "market".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                        borrower: "addr0000".to_string(),
+                        prev_balance: Uint256::zero(),
+                        sub_account: None,
+                    })
+                    .unwrap(),
+                }),
+                4_000_000
+            )
+        ]
+    );
+    assert_eq!(res.attributes, vec![attr("delegated_keeper", "keeper0000")]);
+
+    // the delegation is used up, the repaid loan is safe again
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(1000000u64))]);
+    let res = execute(deps.as_mut(), env.clone(), keeper_info.clone(), msg.clone());
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the borrower can revoke a delegation
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::DelegateLiquidation {
+            keeper: "keeper0000".to_string(),
+            max_bonus: Uint256::from(10500000u64),
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::RevokeLiquidationDelegation {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "revoke_liquidation_delegation"),
+            attr("borrower", "addr0000"),
+        ]
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::RevokeLiquidationDelegation {},
+    );
+    match res {
+        Err(ContractError::NoLiquidationDelegation(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), env, keeper_info, msg);
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn liquidation_price_timeframe() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let mut msg = InstantiateMsg {
+        liquidation_price_timeframe: Some(50u64),
+        ..instantiate_msg()
+    };
+
+    let res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::InvalidPriceTimeframe {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    msg.liquidation_price_timeframe = Some(600u64);
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // price is 300 seconds old, too stale to borrow against
+    // but still fresh enough to liquidate with
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds() - 300,
+            env.block.time.seconds() - 300,
+        ),
+    )]);
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+    match res {
+        Err(ContractError::Std(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // borrow_limit = 1000 * 1000000 * 0.6 = 600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(600000001u64))]);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: Some(env.block.time.seconds()),
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    );
+    assert!(res.is_err());
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "addr0001".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "market".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                        borrower: "addr0000".to_string(),
+                        prev_balance: Uint256::zero(),
+                        sub_account: None,
+                    })
+                    .unwrap(),
+                }),
+                4_000_000
+            )
+        ]
+    );
+
+    // beyond the liquidation price timeframe liquidations stop as well
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds() - 601,
+            env.block.time.seconds() - 601,
+        ),
+    )]);
+    let res = execute(deps.as_mut(), env, info, msg);
+    match res {
+        Err(ContractError::Std(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn max_confidence_spread() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        max_confidence_spread: Some(Decimal256::percent(5)),
+        ..instantiate_msg()
+    };
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000u64)),
+            ("batom".to_string(), Uint256::from(1000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let query_borrow_limit = |deps: Deps| -> Uint256 {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: Some(mock_env().block.time.seconds()),
+                sub_account: None,
+                price_mode: None,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+        borrow_limit_res.borrow_limit
+    };
+
+    // oracle does not report confidence
+    assert_eq!(query_borrow_limit(deps.as_ref()), Uint256::from(1200u64));
+
+    // bluna spread is wider than 5%
+    deps.querier.with_confidence_spread(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &Decimal256::percent(10),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &Decimal256::percent(5),
+        ),
+    ]);
+    assert_eq!(query_borrow_limit(deps.as_ref()), Uint256::from(600u64));
+
+    // liquidations still value bluna
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(700u64))]);
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg);
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        config_res.max_confidence_spread,
+        Some(Decimal256::percent(5))
+    );
+}
+
+#[test]
+fn borrow_limit_price_mode() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    deps.querier.with_twap_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &Decimal256::percent(150),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &Decimal256::percent(50),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // borrow_limit = 2 * 1000000 * 0.6 = 1200000
+    for price_mode in [None, Some(PriceMode::Spot)] {
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+        assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(1200000u64));
+    }
+
+    // borrow_limit = 1.5 * 1000000 * 0.6 = 900000
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: Some(PriceMode::Twap { window: 3600u64 }),
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(900000u64));
+}
+
+#[test]
+fn borrow_rate_premium() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: Some(Decimal256::percent(3)),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(2000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // borrow_rate_premium = (2000000 * 0.03 + 1000000 * 0) / 3000000 = 0.02
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_rate_premium, Decimal256::percent(2));
+
+    // the premium follows the whitelist entry
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        quote_denom: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: Some(Decimal256::zero()),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_rate_premium, Decimal256::zero());
+}
+
+#[test]
+fn borrow_limit_quote_denom() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("uluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let borrow_limit = |deps: Deps, quote_denom: Option<&str>| {
+        query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode: None,
+                quote_denom: quote_denom.map(|d| d.to_string()),
+            },
+        )
+        .map(|res| from_binary::<BorrowLimitResponse>(&res).unwrap())
+    };
+
+    // borrow_limit = 2 * 1000000 * 0.6 = 1200000 uusd
+    let stable_res = borrow_limit(deps.as_ref(), None).unwrap();
+    assert_eq!(stable_res.borrow_limit, Uint256::from(1200000u64));
+    assert_eq!(
+        borrow_limit(deps.as_ref(), Some("uusd")).unwrap(),
+        stable_res
+    );
+
+    // 1200000 uusd / 0.5 = 2400000 uluna
+    let luna_res = borrow_limit(deps.as_ref(), Some("uluna")).unwrap();
+    assert_eq!(luna_res.borrow_limit, Uint256::from(2400000u64));
+    assert_eq!(luna_res.health_factor, stable_res.health_factor);
+
+    match borrow_limit(deps.as_ref(), Some("ukrw")) {
+        Err(StdError::GenericErr { msg, .. }) => {
+            assert_eq!(msg, "No oracle price from ukrw to the stable denom")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn valuation_mode() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        valuation_mode: Some(ValuationMode::LowerOfBoth { window: 3600 }),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    deps.querier.with_twap_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &Decimal256::percent(150),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &Decimal256::percent(50),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    deps.querier.with_twap_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &Decimal256::percent(150),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &Decimal256::percent(80),
+        ),
+    ]);
+
+    // bluna is valued at its twap and batom at its spot price
+    // borrow_limit = 1.5 * 1000000 * 0.6 + 0.5 * 1000000 * 0.4 = 1100000
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(1100000u64));
+
+    // an explicit price mode overrides the valuation mode
+    // borrow_limit = 2 * 1000000 * 0.6 + 0.5 * 1000000 * 0.4 = 1400000
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: Some(PriceMode::Spot),
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(1400000u64));
+
+    let res = query(deps.as_ref(), env, QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        config_res.valuation_mode,
+        ValuationMode::LowerOfBoth { window: 3600 }
+    );
+}
+
+#[test]
+fn fallback_oracle() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        fallback_oracle_contract: Some("fallback_oracle".to_string()),
+        ..instantiate_msg()
+    };
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000u64)),
+            ("batom".to_string(), Uint256::from(1000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the primary bluna price is stale
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds() - 100u64,
+                env.block.time.seconds() - 100u64,
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let borrow_limit_query = QueryMsg::BorrowLimit {
+        borrower: "addr0000".to_string(),
+        block_time: Some(env.block.time.seconds()),
+        sub_account: None,
+        price_mode: None,
+        quote_denom: None,
+    };
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query.clone());
+    match res {
+        Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "Price is too old"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the fallback oracle provides a fresh price
+    deps.querier.with_contract_oracle_price(
+        "fallback_oracle",
+        &[(
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        )],
+    );
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(1800u64));
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        config_res.fallback_oracle_contract,
+        Some("fallback_oracle".to_string())
+    );
+
+    // the fallback cannot be the primary oracle
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: Some("oracle".to_string()),
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let res = execute(deps.as_mut(), env, info, msg);
+    match res {
+        Err(ContractError::DuplicateContractAddress(name, other)) => {
+            assert_eq!(name, "oracle_contract");
+            assert_eq!(other, "fallback_oracle_contract");
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn rotate_oracle() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    // the new oracle lacks a price for batom
+    let env = mock_env();
+    deps.querier.with_contract_oracle_price(
+        "new_oracle",
+        &[(
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        )],
+    );
+
+    let msg = ExecuteMsg::RotateOracle {
+        new_oracle: "new_oracle".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::StalePrices(collaterals)) => assert_eq!(collaterals, "batom"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.oracle_contract, "oracle".to_string());
+
+    deps.querier.with_contract_oracle_price(
+        "new_oracle",
+        &[
+            (
+                &("bluna".to_string(), "uusd".to_string()),
+                &(
+                    Decimal256::one(),
+                    env.block.time.seconds(),
+                    env.block.time.seconds(),
+                ),
+            ),
+            (
+                &("batom".to_string(), "uusd".to_string()),
+                &(
+                    Decimal256::one(),
+                    env.block.time.seconds(),
+                    env.block.time.seconds(),
+                ),
+            ),
+        ],
+    );
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "rotate_oracle"),
+            attr("new_oracle", "new_oracle"),
+        ]
+    );
+
+    let res = query(deps.as_ref(), env, QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(config_res.oracle_contract, "new_oracle".to_string());
+}
+
+#[test]
+fn unlock_to_target_ltv() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000u64)),
+            ("batom".to_string(), Uint256::from(1000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // ltv = 500 / 2000
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(500u64))]);
+
+    let unlock_msg = |target_ltv: Decimal256| ExecuteMsg::UnlockToTargetLtv {
+        target_ltv,
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        unlock_msg(Decimal256::percent(20)),
+    );
+    match res {
+        Err(ContractError::TargetLtvBelowCurrent(current_ltv)) => {
+            assert_eq!(current_ltv, "0.25")
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // above the max ltv of the collaterals
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        unlock_msg(Decimal256::percent(70)),
+    );
+    match res {
+        Err(ContractError::UnlockTooLarge(_)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        unlock_msg(Decimal256::percent(50)),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert!(res.attributes.contains(&attr("target_ltv", "0.5")));
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    let collaterals_value = collaterals_res
+        .collaterals
+        .iter()
+        .fold(Uint256::zero(), |acc, c| acc + c.1);
+    assert_eq!(collaterals_value, Uint256::from(1000u64));
+
+    // post unlock ltv = 500 / 1000
+    assert_eq!(
+        Decimal256::from_ratio(500u64, 1u64) / Decimal256::from_uint256(collaterals_value),
+        Decimal256::percent(50)
+    );
+}
+
+#[test]
+fn close_and_withdraw() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000u64)),
+            ("batom".to_string(), Uint256::from(1000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // a sub account carrying a loan blocks the close
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+        sub_account: Some("vault".to_string()),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(500u64)),
+        (&"addr0000/vault".to_string(), &Uint256::from(50u64)),
+    ]);
+
+    let msg = ExecuteMsg::CloseAndWithdraw {
+        recipient: "addr0001".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(500u128),
+            }],
+        ),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::CloseSubAccountLoan(sub_account)) => assert_eq!(sub_account, "vault"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(500u64))]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(300u128),
+            }],
+        ),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::CloseRepaymentShortfall(200)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(500u128),
+            }],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "market".to_string(),
+            funds: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(500u128),
+            }],
+            msg: to_binary(&MarketExecuteMsg::RepayStableOnBehalf {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+            })
+            .unwrap(),
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+            funds: vec![],
+            msg: to_binary(&ExecuteMsg::CloseAndWithdrawHook {
+                borrower: "addr0000".to_string(),
+                recipient: "addr0001".to_string(),
+            })
+            .unwrap(),
+        }))
+    );
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "close_and_withdraw"),
+            attr("borrower", "addr0000"),
+            attr("recipient", "addr0001"),
+            attr("repay_amount", "500"),
+        ]
+    );
+
+    // only the overseer itself runs the hook
+    let msg = ExecuteMsg::CloseAndWithdrawHook {
+        borrower: "addr0000".to_string(),
+        recipient: "addr0001".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // nothing is released while the market still holds a loan
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::CloseRepaymentShortfall(500)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        msg,
+    )
+    .unwrap();
+
+    // the collaterals go to the recipient, not the borrower
+    for collateral_token in ["bluna", "batom"] {
+        assert!(res
+            .messages
+            .contains(&SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: format!("custody_{}", collateral_token),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::ReleaseCollateral {
+                    borrower: "addr0000".to_string(),
+                    recipient: "addr0001".to_string(),
+                    amount: Uint256::from(1000u64),
+                })
+                .unwrap(),
+            }))));
+    }
+    assert_eq!(res.messages.len(), 2);
+    assert!(res
+        .attributes
+        .contains(&attr("action", "close_and_withdraw_hook")));
+    assert!(res.attributes.contains(&attr("recipient", "addr0001")));
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert!(collaterals_res.collaterals.is_empty());
+}
+
+#[test]
+fn liquidate_collateral_dust() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(90))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        dust_threshold: Some(Uint256::from(1000u64)),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    for token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: token.to_string(),
+            symbol: token.to_string(),
+            collateral_token: token.to_string(),
+            custody_contract: format!("custody_{}", token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::one(),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    for borrower in ["addr0000", "addr0001"] {
+        let msg = ExecuteMsg::LockCollateral {
+            collaterals: vec![
+                ("bluna".to_string(), Uint256::from(1000000u64)),
+                ("batom".to_string(), Uint256::from(1000u64)),
+            ],
+            sub_account: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info(borrower, &[]), msg).unwrap();
+    }
+
+    // borrow_limit = (1000000 + 1000) * 0.6 = 600,600 uusd
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(800000u64)),
+        (&"addr0001".to_string(), &Uint256::from(800000u64)),
+    ]);
+
+    // 100 batom left worth less than the dust threshold,
+    // but sweeping it exceeds the zero tolerance
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidator", &[]),
+        msg,
+    )
+    .unwrap();
+    let res: CollateralsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Collaterals {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+                canonical: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![
+            ("bluna".to_string(), Uint256::from(100000u64)),
+            ("batom".to_string(), Uint256::from(100u64)),
+        ]
+    );
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: Some(Decimal256::percent(1)),
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // the batom dust is seized with the liquidation, 100000 bluna is left
+    // as it exceeds the dust threshold
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0001".to_string(),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidator", &[]),
+        msg,
+    )
+    .unwrap();
+    assert!(res
+        .messages
+        .contains(&SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_batom".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                liquidator: "liquidator".to_string(),
+                borrower: "addr0001".to_string(),
+                amount: Uint256::from(1000u64),
+                premium_rate: None,
+            })
+            .unwrap(),
+        }))));
+    let res: CollateralsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Collaterals {
+                borrower: "addr0001".to_string(),
+                sub_account: None,
+                canonical: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![("bluna".to_string(), Uint256::from(100000u64))]
+    );
+}
+
+#[test]
+fn liquidation_history() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    deps.querier
+        .with_liquidation_queue(&[&"liquidation".to_string()]);
+
+    let info = mock_info("owner", &[]);
+    let mut env = mock_env();
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(600000001u64))]);
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidator", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+
+    // the record waits for the amount the market repaid
+    let res: LiquidationHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::LiquidationHistory {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(res.records.is_empty());
+
+    let repay_reply = |repay_amount: &str| Reply {
+        id: 4_000_000,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("_contract_address", "market")
+                .add_attribute("action", "repay_stable")
+                .add_attribute("borrower", "addr0000")
+                .add_attribute("repay_amount", repay_amount)],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), env.clone(), repay_reply("9400000")).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "record_liquidation"),
+            attr("liquidation_id", "0"),
+            attr("repaid", "9400000"),
+        ]
+    );
+
+    // no liquidation is pending anymore
+    let res = reply(deps.as_mut(), env.clone(), repay_reply("9400000"));
+    match res {
+        Err(ContractError::InvalidReplyId(4_000_000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res: LiquidationHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::LiquidationHistory {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.records,
+        vec![LiquidationRecordResponse {
+            id: 0,
+            borrower: "addr0000".to_string(),
+            liquidator: "liquidator".to_string(),
+            repaid: Uint256::from(9400000u64),
+            seized_tokens: vec![("bluna".to_string(), Uint256::from(10000u64))],
+            block_height: env.block.height,
+        }]
+    );
+
+    // only the last 100 records are kept
+    for _ in 0..100 {
+        env.block.height += 1;
+        let _res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("liquidator", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        reply(deps.as_mut(), env.clone(), repay_reply("9400000")).unwrap();
+    }
+
+    let res: LiquidationHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::LiquidationHistory {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.records.iter().map(|r| r.id).collect::<Vec<u64>>(),
+        (1u64..11u64).collect::<Vec<u64>>()
+    );
+
+    let res: LiquidationHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::LiquidationHistory {
+                start_after: Some(98u64),
+                limit: Some(30u32),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.records.iter().map(|r| r.id).collect::<Vec<u64>>(),
+        vec![99u64, 100u64]
+    );
+    assert_eq!(res.records[1].block_height, env.block.height);
+}
+
+#[test]
+fn fairness_fund() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        dispute_window: Some(100u64),
+        ..instantiate_msg()
+    };
+
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let fund_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        fund_info,
+        ExecuteMsg::FundFairnessFund {},
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let fund_info = mock_info(
+        "owner",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000000u128),
+        }],
+    );
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        fund_info,
+        ExecuteMsg::FundFairnessFund {},
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::one(),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let borrower_raw = deps.api.addr_canonicalize("addr0000").unwrap();
+    let liquidator_raw = deps.api.addr_canonicalize("liquidator").unwrap();
+    let bluna_raw = deps.api.addr_canonicalize("bluna").unwrap();
+    store_liquidation_record(
+        deps.as_mut().storage,
+        &LiquidationRecord {
+            borrower: borrower_raw.clone(),
+            liquidator: liquidator_raw.clone(),
+            repaid: Uint256::from(5000000u64),
+            seized_tokens: vec![],
+            block_height: env.block.height - 101,
+        },
+    )
+    .unwrap();
+    store_liquidation_record(
+        deps.as_mut().storage,
+        &LiquidationRecord {
+            borrower: borrower_raw,
+            liquidator: liquidator_raw,
+            repaid: Uint256::from(5000000u64),
+            seized_tokens: vec![(bluna_raw, Uint256::from(7000000u64))],
+            block_height: env.block.height - 100,
+        },
+    )
+    .unwrap();
+
+    // the first liquidation is out of the dispute window
+    let msg = ExecuteMsg::ReportPriceCorrection {
+        liquidation_id: 0,
+        compensation: Uint256::from(400000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::DisputeWindowExpired(0));
+
+    // the compensation is capped at the seized value beyond the repaid loan
+    let msg = ExecuteMsg::ReportPriceCorrection {
+        liquidation_id: 1,
+        compensation: Uint256::from(2000001u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::CompensationExceedsLoss(2000000u128));
+
+    let msg = ExecuteMsg::ReportPriceCorrection {
+        liquidation_id: 1,
+        compensation: Uint256::from(1000001u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::InsufficientFairnessFund(1000000u128));
+
+    let msg = ExecuteMsg::ReportPriceCorrection {
+        liquidation_id: 1,
+        compensation: Uint256::from(400000u64),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "report_price_correction"),
+            attr("liquidation_id", "1"),
+            attr("borrower", "addr0000"),
+            attr("compensation", "400000"),
+        ]
+    );
+
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+    assert_eq!(res, ContractError::LiquidationAlreadyDisputed(1));
+
+    let res: FairnessFundResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::FairnessFund {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res,
+        FairnessFundResponse {
+            available: Uint256::from(600000u64),
+            reserved: Uint256::from(400000u64),
+        }
+    );
+
+    // only the liquidated borrower can claim the compensation
+    let msg = ExecuteMsg::ClaimCompensation { liquidation_id: 1 };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::NoCompensation(1));
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![deduct_tax(
+                deps.as_ref(),
+                Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(400000u128),
+                }
+            )
+            .unwrap()],
+        }))]
+    );
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap_err();
+    assert_eq!(res, ContractError::NoCompensation(1));
+
+    let res: FairnessFundResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::FairnessFund {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        FairnessFundResponse {
+            available: Uint256::from(600000u64),
+            reserved: Uint256::zero(),
+        }
+    );
+}
+
+#[test]
+fn sub_account_positions() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let info = mock_info("addr0000", &[]);
+    for sub_account in [None, Some("hedge".to_string())] {
+        let msg = ExecuteMsg::LockCollateral {
+            collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+            sub_account,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    // borrow_limit of each position = 1000 * 1000000 * 0.6
+    // = 600,000,000 uusd
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::zero()),
+        (&"addr0000/hedge".to_string(), &Uint256::from(600000001u64)),
+    ]);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: Some("hedge".to_string()),
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(600000000u64));
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: Some("hedge".to_string()),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                    liquidator: "addr0001".to_string(),
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(10000u64),
+                    premium_rate: None,
+                })
+                .unwrap(),
+            })),
+            SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: "market".to_string(),
+                    funds: vec![],
+                    msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                        borrower: "addr0000".to_string(),
+                        prev_balance: Uint256::zero(),
+                        sub_account: Some("hedge".to_string()),
+                    })
+                    .unwrap(),
+                }),
+                4_000_000
+            )
+        ]
+    );
+
+    // the default position is untouched
+    let query_collaterals = |deps: Deps, sub_account: Option<&str>| -> Vec<(String, Uint256)> {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::Collaterals {
+                borrower: "addr0000".to_string(),
+                sub_account: sub_account.map(|s| s.to_string()),
+                canonical: None,
+            },
+        )
+        .unwrap();
+        let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+        collaterals_res.collaterals
+    };
+    assert_eq!(
+        query_collaterals(deps.as_ref(), None),
+        vec![("bluna".to_string(), Uint256::from(1000000u64))]
+    );
+    assert_eq!(
+        query_collaterals(deps.as_ref(), Some("hedge")),
+        vec![("bluna".to_string(), Uint256::from(990000u64))]
+    );
+
+    let msg = ExecuteMsg::ReallocateCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        from_sub_account: Some("hedge".to_string()),
+        to_sub_account: Some("hedge".to_string()),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res {
+        Err(ContractError::SameSubAccount {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::ReallocateCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        from_sub_account: None,
+        to_sub_account: Some("hedge".to_string()),
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    assert_eq!(res.messages, vec![]);
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "reallocate_collateral"),
+            attr("borrower", "addr0000"),
+            attr("from_sub_account", ""),
+            attr("to_sub_account", "hedge"),
+            attr("collaterals", "500000bluna"),
+        ]
+    );
+    assert_eq!(
+        query_collaterals(deps.as_ref(), None),
+        vec![("bluna".to_string(), Uint256::from(500000u64))]
+    );
+    assert_eq!(
+        query_collaterals(deps.as_ref(), Some("hedge")),
+        vec![("bluna".to_string(), Uint256::from(1490000u64))]
+    );
+
+    // borrow_limit of the hedge position would be 1000 * 490000 * 0.6
+    // = 294,000,000 uusd
+    let msg = ExecuteMsg::ReallocateCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        from_sub_account: Some("hedge".to_string()),
+        to_sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+    match res {
+        Err(ContractError::UnlockTooLarge(294000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // sub-accounts are listed after the default position of their borrower,
+    // even for borrowers without a default position
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+        sub_account: Some("solo".to_string()),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::AllCollaterals {
+            start_after: None,
+            limit: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        all_collaterals_res.all_collaterals,
+        vec![
+            CollateralsResponse {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+                collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+            },
+            CollateralsResponse {
+                borrower: "addr0000".to_string(),
+                sub_account: Some("hedge".to_string()),
+                collaterals: vec![("bluna".to_string(), Uint256::from(1490000u64))],
+            },
+            CollateralsResponse {
+                borrower: "addr0001".to_string(),
+                sub_account: Some("solo".to_string()),
+                collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+            },
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::AllCollaterals {
+            start_after: Some("addr0000".to_string()),
+            limit: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(all_collaterals_res.all_collaterals.len(), 1);
+
+    // the hedge collaterals are worth 1000 * 1490000 = 1,490,000,000 uusd
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::zero()),
+        (&"addr0000/hedge".to_string(), &Uint256::from(1500000000u64)),
+        (&"addr0001/solo".to_string(), &Uint256::zero()),
+    ]);
+    let res = query(deps.as_ref(), env, QueryMsg::BadDebt {}).unwrap();
+    let bad_debt_res: BadDebtResponse = from_binary(&res).unwrap();
+    assert_eq!(bad_debt_res.bad_debt, Uint256::from(10000000u64));
+}
+
+#[test]
+fn split_position() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let info = mock_info("addr0000", &[]);
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // borrow_limit = 1000 * 1000000 * 0.6 = 600,000,000 uusd
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(400000000u64)),
+        (&"addr0000/busy".to_string(), &Uint256::from(1u64)),
+    ]);
+
+    let split_msg = |bluna_amount: u64, debt_fraction: Decimal256, to_sub_account: &str| {
+        ExecuteMsg::SplitPosition {
+            collaterals: vec![("bluna".to_string(), Uint256::from(bluna_amount))],
+            debt_fraction,
+            from_sub_account: None,
+            to_sub_account: to_sub_account.to_string(),
+        }
+    };
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        split_msg(400000, Decimal256::percent(101), "split"),
+    );
+    match res {
+        Err(ContractError::InvalidDebtFraction {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        split_msg(400000, Decimal256::percent(40), "busy"),
+    );
+    match res {
+        Err(ContractError::SubAccountNotEmpty(sub_account)) => assert_eq!(sub_account, "busy"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the original position would keep 360,000,000 uusd of the loan
+    // with a borrow_limit of 1000 * 500000 * 0.6 = 300,000,000 uusd
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        split_msg(500000, Decimal256::percent(10), "split"),
+    );
+    match res {
+        Err(ContractError::SplitExceedsLimit(300000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // nor can the new position take more debt than its collaterals back
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        split_msg(100000, Decimal256::percent(40), "split"),
+    );
+    match res {
+        Err(ContractError::SplitExceedsLimit(60000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        split_msg(400000, Decimal256::percent(40), "split"),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "market".to_string(),
+            funds: vec![],
+            msg: to_binary(&MarketExecuteMsg::SplitLoan {
+                borrower: "addr0000".to_string(),
+                from_sub_account: None,
+                to_sub_account: "split".to_string(),
+                fraction: Decimal256::percent(40),
+            })
+            .unwrap(),
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "split_position"),
+            attr("borrower", "addr0000"),
+            attr("from_sub_account", ""),
+            attr("to_sub_account", "split"),
+            attr("collaterals", "400000bluna"),
+            attr("debt_fraction", "0.4"),
+            attr("loan_amount", "160000000"),
+        ]
+    );
+
+    // the positions together hold the collaterals before the split
+    let query_collaterals = |deps: Deps, sub_account: Option<&str>| -> Vec<(String, Uint256)> {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::Collaterals {
+                borrower: "addr0000".to_string(),
+                sub_account: sub_account.map(|s| s.to_string()),
+                canonical: None,
+            },
+        )
+        .unwrap();
+        let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+        collaterals_res.collaterals
+    };
+    assert_eq!(
+        query_collaterals(deps.as_ref(), None),
+        vec![("bluna".to_string(), Uint256::from(600000u64))]
+    );
+    assert_eq!(
+        query_collaterals(deps.as_ref(), Some("split")),
+        vec![("bluna".to_string(), Uint256::from(400000u64))]
+    );
+}
+
+#[test]
+fn migrate_position() {
+    let mut deps = mock_dependencies(&[]);
+    let mut env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        same_block_collateral_delay: Some(true),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    for token in ["bluna", "bluna_v2"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: token.to_string(),
+            symbol: token.to_string(),
+            collateral_token: token.to_string(),
+            custody_contract: format!("custody_{}", token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("bluna_v2".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let borrower_info = mock_info("addr0000", &[]);
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), borrower_info.clone(), msg).unwrap();
+
+    // borrow_limit = 1000 * 1000000 * 0.6
+    // = 600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(300000000u64))]);
+
+    let msg = ExecuteMsg::MigratePosition {
+        from_token: "bluna".to_string(),
+        to_token: "bluna_v2".to_string(),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::ConverterNotConfigured {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let update_msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: Some("converter".to_string()),
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: None,
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, update_msg).unwrap();
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap();
+    let config_res: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        config_res.collateral_converter,
+        Some("converter".to_string())
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        ExecuteMsg::MigratePosition {
+            from_token: "bluna".to_string(),
+            to_token: "bluna".to_string(),
+            sub_account: None,
+        },
+    );
+    match res {
+        Err(ContractError::SameCollateralToken {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        ExecuteMsg::MigratePosition {
+            from_token: "bluna_v2".to_string(),
+            to_token: "bluna".to_string(),
+            sub_account: None,
+        },
+    );
+    match res {
+        Err(ContractError::NothingToMigrate(token)) => assert_eq!(token, "bluna_v2"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // an underwater position cannot migrate its collaterals
+    deps.querier
+        .with_conversion_rate(&[(&"converter".to_string(), &Decimal256::percent(90))]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(700000000u64))]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::UnlockUnderwaterLoan(600000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(300000000u64))]);
+
+    // borrow_limit after migration = 1000 * 400000 * 0.6
+    // = 240,000,000 uusd
+    deps.querier
+        .with_conversion_rate(&[(&"converter".to_string(), &Decimal256::percent(40))]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::MigrationUnderwater(240000000)) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // borrow_limit after migration = 1000 * 900000 * 0.6
+    // = 540,000,000 uusd
+    deps.querier
+        .with_conversion_rate(&[(&"converter".to_string(), &Decimal256::percent(90))]);
+    let res = execute(deps.as_mut(), env.clone(), borrower_info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::ConvertCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(1000000u64),
+                    converter: "converter".to_string(),
+                    msg: to_binary(&ConverterCw20HookMsg::Convert {
+                        ask_token: "bluna_v2".to_string(),
+                        recipient: "custody_bluna_v2".to_string(),
+                        msg: Some(
+                            to_binary(&CustodyCw20HookMsg::DepositCollateralFor {
+                                borrower: "addr0000".to_string(),
+                            })
+                            .unwrap()
+                        ),
+                    })
+                    .unwrap(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna_v2".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
+                    borrower: "addr0000".to_string(),
+                    amount: Uint256::from(900000u64),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "migrate_position"),
+            attr("borrower", "addr0000"),
+            attr("from_token", "bluna"),
+            attr("to_token", "bluna_v2"),
+            attr("collaterals_before", "1000000bluna"),
+            attr("collaterals_after", "900000bluna_v2"),
+            attr("borrow_limit_before", "600000000"),
+            attr("borrow_limit_after", "540000000"),
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res.collaterals,
+        vec![("bluna_v2".to_string(), Uint256::from(900000u64))]
+    );
+
+    // the collaterals locked in this block still back borrows from the next
+    // block only once migrated
+    let borrow_limit_query = QueryMsg::BorrowLimit {
+        borrower: "addr0000".to_string(),
+        block_time: None,
+        sub_account: None,
+        price_mode: None,
+        quote_denom: None,
+    };
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query.clone()).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::zero());
+
+    env.block.height += 1;
+    let res = query(deps.as_ref(), env, borrow_limit_query).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(540000000u64));
+}
+
+#[test]
+fn bad_debt() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    // collateral value of addr0000 and addr0001 = 1000 * 1000000
+    // = 1,000,000,000 uusd
+    // addr0000 is deeply underwater, addr0001 is safe and
+    // addr0002 has no collateral left after a liquidation
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(3000000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(100u64)),
+        (&"addr0002".to_string(), &Uint256::from(500u64)),
+    ]);
+
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::BadDebt {}).unwrap();
+    let bad_debt_res: BadDebtResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        bad_debt_res,
+        BadDebtResponse {
+            bad_debt: Uint256::from(2000000500u64),
+        }
+    );
+
+    // no bad debt when every loan is backed by collateral
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(1000000000u64)),
+        (&"addr0001".to_string(), &Uint256::from(100u64)),
+    ]);
+
+    let res = query(deps.as_ref(), env, QueryMsg::BadDebt {}).unwrap();
+    let bad_debt_res: BadDebtResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        bad_debt_res,
+        BadDebtResponse {
+            bad_debt: Uint256::zero(),
+        }
+    );
+}
+
+#[test]
+fn trusted_contracts() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::RegisterCustodyContract {
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna2".to_string(),
+        weight: Decimal256::one(),
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::TrustedContracts {}).unwrap();
+    let trusted_contracts_res: TrustedContractsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        trusted_contracts_res,
+        TrustedContractsResponse {
+            oracle_contract: "oracle".to_string(),
+            market_contract: "market".to_string(),
+            liquidation_contract: "liquidation".to_string(),
+            collector_contract: "collector".to_string(),
+            custody_contracts: vec![
+                "custody_bluna".to_string(),
+                "custody_bluna2".to_string(),
+                "custody_batom".to_string(),
+            ],
+        }
+    );
+}
+
+#[test]
+fn asset_registry() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    deps.querier
+        .with_interest_model(&[(&"market".to_string(), &"interest".to_string())]);
+    deps.querier
+        .with_anchor_token(&[(&"collector".to_string(), &"anc".to_string())]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::AssetRegistry {}).unwrap();
+    let asset_registry_res: AssetRegistryResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        asset_registry_res,
+        AssetRegistryResponse {
+            stable_denom: "uusd".to_string(),
+            collateral_tokens: vec!["bluna".to_string(), "batom".to_string()],
+            aterra_contract: "aterra".to_string(),
+            anc_token: "anc".to_string(),
+        }
+    );
+}
+
+#[test]
+fn collateral_cap_utilization() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::zero()),
+        (&"addr0001".to_string(), &Uint256::zero()),
+    ]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(4000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::CollateralCapUtilization {
+            start_after: None,
+            limit: None,
+        },
+    )
+    .unwrap();
+    let utilization_res: CollateralCapUtilizationResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        utilization_res.elems,
+        vec![
+            CollateralCapUtilizationElem {
+                collateral_token: "bluna".to_string(),
+                cap: None,
+                used: Uint256::from(3000000u64),
+                utilization_ratio: None,
+            },
+            CollateralCapUtilizationElem {
+                collateral_token: "batom".to_string(),
+                cap: None,
+                used: Uint256::from(3000000u64),
+                utilization_ratio: None,
+            },
+        ]
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::CollateralCapUtilization {
+            start_after: Some("bluna".to_string()),
+            limit: Some(1),
+        },
+    )
+    .unwrap();
+    let utilization_res: CollateralCapUtilizationResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        utilization_res.elems,
+        vec![CollateralCapUtilizationElem {
+            collateral_token: "batom".to_string(),
+            cap: None,
+            used: Uint256::from(3000000u64),
+            utilization_ratio: None,
+        }]
+    );
+}
+
+#[test]
+fn global_borrowable_by_collateral() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::zero()),
+        (&"addr0001".to_string(), &Uint256::zero()),
+    ]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(4000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::GlobalBorrowableByCollateral {},
+    )
+    .unwrap();
+    let borrowable_res: GlobalBorrowableByCollateralResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        borrowable_res.elems,
+        vec![
+            GlobalBorrowableElem {
+                collateral_token: "bluna".to_string(),
+                locked_value: Uint256::from(3000000u64),
+                borrow_capacity: Uint256::from(1800000u64),
+                remaining_capacity: None,
+            },
+            GlobalBorrowableElem {
+                collateral_token: "batom".to_string(),
+                locked_value: Uint256::from(3000000u64),
+                borrow_capacity: Uint256::from(1200000u64),
+                remaining_capacity: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn solvency_check() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        solvency_margin: Some(Decimal256::percent(10)),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::zero()),
+        (&"addr0001".to_string(), &Uint256::zero()),
+    ]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(4000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // 6000000 of locked value against 5000000 of debt clears the 10% margin
+    deps.querier
+        .with_anc_emission_rate(&[(&"market".to_string(), &Decimal256::zero())]);
+    deps.querier.with_market_liabilities(&[(
+        &"market".to_string(),
+        &Decimal256::from_uint256(Uint256::from(5000000u64)),
+    )]);
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::SolvencyCheck {}).unwrap();
+    let solvency_res: SolvencyCheckResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        solvency_res,
+        SolvencyCheckResponse {
+            solvent: true,
+            total_collateral_value: Uint256::from(6000000u64),
+            total_debt: Uint256::from(5000000u64),
+            collateralization_ratio: Some(Decimal256::from_ratio(6, 5)),
+        }
+    );
+
+    // covers the debt alone but not the margin on top of it
+    deps.querier.with_market_liabilities(&[(
+        &"market".to_string(),
+        &Decimal256::from_uint256(Uint256::from(5600000u64)),
+    )]);
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::SolvencyCheck {}).unwrap();
+    let solvency_res: SolvencyCheckResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        solvency_res,
+        SolvencyCheckResponse {
+            solvent: false,
+            total_collateral_value: Uint256::from(6000000u64),
+            total_debt: Uint256::from(5600000u64),
+            collateralization_ratio: Some(Decimal256::from_ratio(6000000, 5600000)),
+        }
+    );
+
+    // no debt is always solvent
+    deps.querier.with_market_liabilities(&[]);
+    let res = query(deps.as_ref(), env, QueryMsg::SolvencyCheck {}).unwrap();
+    let solvency_res: SolvencyCheckResponse = from_binary(&res).unwrap();
+    assert!(solvency_res.solvent);
+    assert_eq!(solvency_res.collateralization_ratio, None);
+}
+
+#[test]
+fn liquidation_bonus() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 = 600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(600000000u64))]);
+
+    let msg = QueryMsg::LiquidationBonus {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res: LiquidationBonusResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg.clone()).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        LiquidationBonusResponse {
+            borrower: "addr0000".to_string(),
+            seized_value: Uint256::zero(),
+            repay_amount: Uint256::zero(),
+            protocol_fee: Uint256::zero(),
+            bonus: Uint256::zero(),
+        }
+    );
+
+    // 1% of the collaterals is liquidated at 5% premium and 1% bid fee
+    // seized_value = 10000 * 1000 = 10,000,000 uusd
+    // protocol_fee = 10,000,000 * 0.95 * 0.01 = 95,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(600000001u64))]);
+    let res: LiquidationBonusResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        LiquidationBonusResponse {
+            borrower: "addr0000".to_string(),
+            seized_value: Uint256::from(10000000u64),
+            repay_amount: Uint256::from(9405000u64),
+            protocol_fee: Uint256::from(95000u64),
+            bonus: Uint256::from(500000u64),
+        }
+    );
+
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: Some(Decimal256::percent(31)),
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    match execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg) {
+        Err(ContractError::InvalidLiquidationPremium(max)) => {
+            assert_eq!(max, Decimal256::percent(30))
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: Some(Decimal256::percent(2)),
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+
+    let whitelist_elem: WhitelistResponseElem = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::WhitelistEntry {
+                collateral_token: "bluna".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        whitelist_elem.liquidation_premium,
+        Some(Decimal256::percent(2))
+    );
+
+    // the collateral premium replaces the 5% max premium rate
+    // protocol_fee = 10,000,000 * 0.98 * 0.01 = 98,000 uusd
+    let res: LiquidationBonusResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::LiquidationBonus {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        LiquidationBonusResponse {
+            borrower: "addr0000".to_string(),
+            seized_value: Uint256::from(10000000u64),
+            repay_amount: Uint256::from(9702000u64),
+            protocol_fee: Uint256::from(98000u64),
+            bonus: Uint256::from(200000u64),
+        }
+    );
+
+    // the liquidation contract is told to execute the bid at the collateral premium
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                liquidator: "addr0001".to_string(),
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(10000u64),
+                premium_rate: Some(Decimal256::percent(2)),
+            })
+            .unwrap(),
+        })
+    );
+}
+
+#[test]
+fn net_deposit_rate() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // ~14% yearly deposit rate
+    store_epoch_state(
+        deps.as_mut().storage,
+        &EpochState {
+            deposit_rate: Decimal256::from_str("0.00000003").unwrap(),
+            prev_aterra_supply: Uint256::zero(),
+            prev_exchange_rate: Decimal256::one(),
+            prev_interest_buffer: Uint256::zero(),
+            last_executed_height: env.block.height,
+        },
+    )
+    .unwrap();
+
+    // 20 ANC worth 1 UST emitted per block over 1B UST of deposits
+    deps.querier.with_anc_emission_rate(&[(
+        &"market".to_string(),
+        &Decimal256::from_uint256(20_000_000u64),
+    )]);
+    deps.querier
+        .with_anchor_token(&[(&"collector".to_string(), &"anc".to_string())]);
+    deps.querier.with_oracle_price(&[(
+        &("anc".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::one(),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+    deps.querier.with_epoch_state(&[(
+        &"market".to_string(),
+        &(
+            Uint256::from(800_000_000_000_000u128),
+            Decimal256::percent(125),
+        ),
+    )]);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::NetDepositRate { anc_price: None },
+    )
+    .unwrap();
+    let net_deposit_rate: NetDepositRateResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        net_deposit_rate,
+        NetDepositRateResponse {
+            deposit_rate: Decimal256::from_str("0.00000003").unwrap(),
+            anc_emission_rate: Decimal256::from_uint256(20_000_000u64),
+            anc_price: Decimal256::one(),
+            total_deposits: Uint256::from(1_000_000_000_000_000u128),
+            dilution_rate: Decimal256::from_str("0.00000002").unwrap(),
+            net_deposit_rate: Decimal256::from_str("0.00000001").unwrap(),
+        }
+    );
+
+    // at a higher provided price the dilution exceeds the deposit rate
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::NetDepositRate {
+            anc_price: Some(Decimal256::from_uint256(2u64)),
+        },
+    )
+    .unwrap();
+    let net_deposit_rate: NetDepositRateResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        net_deposit_rate.dilution_rate,
+        Decimal256::from_str("0.00000004").unwrap()
+    );
+    assert_eq!(net_deposit_rate.net_deposit_rate, Decimal256::zero());
+}
+
+#[test]
+fn same_block_collateral_delay() {
+    let mut deps = mock_dependencies(&[]);
+    let mut env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        same_block_collateral_delay: Some(true),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let borrow_limit = |deps: Deps, env: Env| {
+        let res = query(
+            deps,
+            env,
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode: None,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        from_binary::<BorrowLimitResponse>(&res)
+            .unwrap()
+            .borrow_limit
+    };
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the collateral locked in this block cannot back a borrow yet
+    assert_eq!(borrow_limit(deps.as_ref(), env.clone()), Uint256::zero());
+
+    // borrow_limit = 1000000 * 2 * 0.6
+    env.block.height += 1;
+    assert_eq!(
+        borrow_limit(deps.as_ref(), env.clone()),
+        Uint256::from(1200000u64)
+    );
+
+    // every lock of the same block is delayed
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(10000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        borrow_limit(deps.as_ref(), env.clone()),
+        Uint256::from(1200000u64)
+    );
+
+    // borrow_limit = 1500000 * 2 * 0.6 + 10000000 * 0.5 * 0.6
+    env.block.height += 1;
+    assert_eq!(
+        borrow_limit(deps.as_ref(), env.clone()),
+        Uint256::from(4800000u64)
+    );
+
+    // without the delay the collateral counts right away
+    let msg = ExecuteMsg::UpdateConfig {
+        owner_addr: None,
+        oracle_contract: None,
+        liquidation_contract: None,
+        threshold_deposit_rate: None,
+        target_deposit_rate: None,
+        buffer_distribution_factor: None,
+        anc_purchase_factor: None,
+        epoch_period: None,
+        borrow_price_timeframe: None,
+        liquidation_price_timeframe: None,
+        max_anc_price: None,
+        buffer_target: None,
+        surplus_recipient: None,
+        price_deviation_threshold: None,
+        max_collaterals_per_borrower: None,
+        ordered_epoch_operations: None,
+        dust_threshold: None,
+        dust_tolerance: None,
+        collateral_converter: None,
+        soft_liquidation: None,
+        buffer_backstop_limit: None,
+        max_confidence_spread: None,
+        reserve_target: None,
+        reserve_topup_factor: None,
+        epoch_requires_fresh_prices: None,
+        fallback_oracle_contract: None,
+        min_custody_quorum: None,
+        same_block_collateral_delay: Some(false),
+        max_collateral_value_per_borrower: None,
+        risk_admin: None,
+        epoch_custody_batch_size: None,
+        anc_buyback_amm: None,
+        min_lock_amount: None,
+        solvency_margin: None,
+        max_block_price_drop: None,
+        price_drop_cooldown: None,
+        dispute_window: None,
+        valuation_mode: None,
+        default_limit: None,
+        bad_debt_budget: None,
+        deposit_rate_smoothing_epochs: None,
+        skip_paused_collaterals: None,
+        max_ltv_delta_per_epoch: None,
+        max_anc_purchase_per_epoch: None,
+        stable_decimals: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(borrow_limit(deps.as_ref(), env), Uint256::from(5400000u64));
+}
+
+#[test]
+fn effective_ltv() {
+    let mut deps = mock_dependencies(&[]);
+    let mut env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        same_block_collateral_delay: Some(true),
+        max_collateral_value_per_borrower: Some(Uint256::from(1500000u64)),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let effective_ltv = |deps: Deps, env: Env| -> EffectiveLtvResponse {
+        let res = query(
+            deps,
+            env,
+            QueryMsg::EffectiveLtv {
+                borrower: "addr0000".to_string(),
+                collateral_token: "bluna".to_string(),
+                sub_account: None,
+            },
+        )
+        .unwrap();
+        from_binary(&res).unwrap()
+    };
+    let borrow_limit = |deps: Deps, env: Env| {
+        let res = query(
+            deps,
+            env,
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode: None,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        from_binary::<BorrowLimitResponse>(&res)
+            .unwrap()
+            .borrow_limit
+    };
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    env.block.height += 1;
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // half of the 4000000 collateral value is locked in this block, and the
+    // left 2000000 is capped at 1500000: 0.6 * 1500000 / 4000000 = 0.225
+    let res = effective_ltv(deps.as_ref(), env.clone());
+    assert_eq!(
+        res,
+        EffectiveLtvResponse {
+            borrower: "addr0000".to_string(),
+            collateral_token: "bluna".to_string(),
+            max_ltv: Decimal256::percent(60),
+            effective_ltv: Decimal256::from_str("0.225").unwrap(),
+            adjustments: vec![
+                LtvAdjustmentElem {
+                    adjustment: "same_block_lock".to_string(),
+                    ltv_reduction: Decimal256::percent(30),
+                },
+                LtvAdjustmentElem {
+                    adjustment: "collateral_value_cap".to_string(),
+                    ltv_reduction: Decimal256::from_str("0.075").unwrap(),
+                },
+            ],
+        }
+    );
+    let total_reduction = res
+        .adjustments
+        .iter()
+        .fold(Decimal256::zero(), |sum, a| sum + a.ltv_reduction);
+    assert_eq!(total_reduction, res.max_ltv - res.effective_ltv);
+    assert_eq!(
+        borrow_limit(deps.as_ref(), env.clone()),
+        Uint256::from(4000000u64) * res.effective_ltv
+    );
+
+    // only the value cap is left in the next block
+    env.block.height += 1;
+    let res = effective_ltv(deps.as_ref(), env.clone());
+    assert_eq!(
+        res.adjustments,
+        vec![LtvAdjustmentElem {
+            adjustment: "collateral_value_cap".to_string(),
+            ltv_reduction: Decimal256::from_str("0.375").unwrap(),
+        }]
+    );
+    assert_eq!(
+        borrow_limit(deps.as_ref(), env),
+        Uint256::from(4000000u64) * res.effective_ltv
+    );
+}
+
+#[test]
+fn max_collateral_value_per_borrower() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        max_collateral_value_per_borrower: Some(Uint256::from(1000000u64)),
+        ..instantiate_msg()
+    };
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("luna".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("atom".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let config_res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        config_res.max_collateral_value_per_borrower,
+        Some(Uint256::from(1000000u64))
+    );
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(10000000u64)),
+            ("batom".to_string(), Uint256::from(1000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the whole position stays locked
+    let collaterals_res: CollateralsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Collaterals {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+                canonical: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        collaterals_res.collaterals,
+        vec![
+            ("bluna".to_string(), Uint256::from(10000000u64)),
+            ("batom".to_string(), Uint256::from(1000000u64)),
+        ]
+    );
+
+    // bluna value 20000000 is capped at 1000000, batom value 500000 is not
+    // borrow_limit = 1000000 * 0.6 + 500000 * 0.6
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: None,
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(900000u64));
+}
+
+#[test]
+fn category_exposure() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("luna".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("atom".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(500000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::zero()),
+        (&"addr0001".to_string(), &Uint256::zero()),
+    ]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("batom".to_string(), Uint256::from(4000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // locked value: bluna 1,500,000 * 2 and batom 6,000,000 * 0.5
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::CategoryExposure {}).unwrap();
+    let exposure_res: CategoryExposureResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        exposure_res.total_borrow_capacity,
+        Uint256::from(3600000u64)
+    );
+    for group in ["luna", "atom"] {
+        assert_eq!(
+            exposure_res
+                .categories
+                .iter()
+                .find(|c| c.correlation_group == Some(group.to_string()))
+                .unwrap(),
+            &CategoryExposureElem {
+                correlation_group: Some(group.to_string()),
+                locked_value: Uint256::from(3000000u64),
+                borrow_capacity: Uint256::from(1800000u64),
+                capacity_share: Decimal256::percent(50),
+            }
+        );
+    }
+
+    // batom joins the luna group
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "batom".to_string(),
+        custody_contract: None,
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("luna".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let res = query(deps.as_ref(), env, QueryMsg::CategoryExposure {}).unwrap();
+    let exposure_res: CategoryExposureResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        exposure_res,
+        CategoryExposureResponse {
+            categories: vec![CategoryExposureElem {
+                correlation_group: Some("luna".to_string()),
+                locked_value: Uint256::from(6000000u64),
+                borrow_capacity: Uint256::from(3600000u64),
+                capacity_share: Decimal256::one(),
+            }],
+            total_borrow_capacity: Uint256::from(3600000u64),
+        }
+    );
+}
+
+#[test]
+fn claim_all_rewards() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(40),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "beth".to_string(),
+        symbol: "beth".to_string(),
+        collateral_token: "beth".to_string(),
+        custody_contract: "custody_beth".to_string(),
+        max_ltv: Decimal256::percent(50),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // nothing to claim in the beth custody
+    deps.querier.with_custody_rewards(&[
+        (
+            &"custody_bluna".to_string(),
+            &"addr0000".to_string(),
+            &Uint256::from(300000u64),
+        ),
+        (
+            &"custody_batom".to_string(),
+            &"addr0000".to_string(),
+            &Uint256::from(200000u64),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::ClaimAllRewards {};
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_bluna".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::ClaimRewardsFor {
+                    borrower: "addr0000".to_string(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "custody_batom".to_string(),
+                funds: vec![],
+                msg: to_binary(&CustodyExecuteMsg::ClaimRewardsFor {
+                    borrower: "addr0000".to_string(),
+                })
+                .unwrap(),
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::ClaimAllRewardsHook {
+                    borrower: "addr0000".to_string(),
+                    prev_balance: Uint256::from(1000000u64),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_all_rewards"),
+            attr("borrower", "addr0000"),
+            attr("custody_rewards", "300000custody_bluna"),
+            attr("custody_rewards", "200000custody_batom"),
+            attr("total_rewards", "500000"),
+        ]
+    );
+
+    // no custody holds rewards of addr0001
+    let msg = ExecuteMsg::ClaimAllRewards {};
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert!(res.messages.is_empty());
+
+    // the custodies sent 500000uusd since the claim
+    let msg = ExecuteMsg::ClaimAllRewardsHook {
+        borrower: "addr0000".to_string(),
+        prev_balance: Uint256::from(500000u64),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    let res = execute(deps.as_mut(), env, mock_info(MOCK_CONTRACT_ADDR, &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(500000u128),
+            }],
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "claim_all_rewards_hook"),
+            attr("borrower", "addr0000"),
+            attr("claimed_amount", "500000"),
+        ]
+    );
+}
+
+#[test]
+fn zero_supply_collateral() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let borrower_info = mock_info("addr0000", &[]);
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        msg.clone(),
+    )
+    .unwrap();
+
+    let borrow_limit_query = QueryMsg::BorrowLimit {
+        borrower: "addr0000".to_string(),
+        block_time: None,
+        sub_account: None,
+        price_mode: None,
+        quote_denom: None,
+    };
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query.clone()).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(600000000u64));
+
+    // the whole bluna supply is unbonded
+    deps.querier
+        .with_token_supply(&[(&"bluna".to_string(), &Uint128::zero())]);
+
+    // borrows are checked against this borrow limit by the market
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::zero());
+
+    let res = execute(deps.as_mut(), env.clone(), borrower_info.clone(), msg);
+    match res {
+        Err(ContractError::ZeroSupplyCollateral(token)) => assert_eq!(token, "bluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the remaining balance can still be unlocked
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, borrower_info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(1000000u64),
+            })
+            .unwrap(),
+        }))]
+    );
+}
+
+#[test]
+fn borrow_window() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: Some(env.block.height + 10),
+        borrow_disabled_from: Some(env.block.height + 20),
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: Some(env.block.height + 10),
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+    assert_eq!(res, ContractError::InvalidBorrowWindow {});
+
+    let res: WhitelistResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Whitelist {
+                collateral_token: Some("bluna".to_string()),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.elems[0].borrow_enabled_from,
+        Some(env.block.height + 10)
+    );
+    assert_eq!(
+        res.elems[0].borrow_disabled_from,
+        Some(env.block.height + 20)
+    );
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    // the collateral cannot be locked before the window
+    let borrower_info = mock_info("addr0000", &[]);
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        msg.clone(),
+    );
+    assert_eq!(
+        res,
+        Err(ContractError::OutsideBorrowWindow("bluna".to_string()))
+    );
+
+    let mut env = env;
+    env.block.height += 10;
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        borrower_info.clone(),
+        msg.clone(),
+    )
+    .unwrap();
+
+    let borrow_limit_query = QueryMsg::BorrowLimit {
+        borrower: "addr0000".to_string(),
+        block_time: None,
+        sub_account: None,
+        price_mode: None,
+        quote_denom: None,
+    };
+    env.block.height += 9;
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query.clone()).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(600000000u64));
+
+    // the collateral no longer backs borrows from the end of the window
+    env.block.height += 1;
+    let res = query(deps.as_ref(), env.clone(), borrow_limit_query).unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(borrow_limit_res.borrow_limit, Uint256::zero());
+
+    let res = execute(deps.as_mut(), env.clone(), borrower_info.clone(), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::OutsideBorrowWindow("bluna".to_string()))
+    );
+
+    // but can still be unlocked
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env, borrower_info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_bluna".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(1000000u64),
+            })
+            .unwrap(),
+        }))]
+    );
+}
+
+#[test]
+fn sub_accounts() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let info = mock_info("addr0000", &[]);
+    for (sub_account, amount) in [
+        (None, 100u64),
+        (Some("gamma"), 300u64),
+        (Some("alpha"), 100u64),
+        (Some("beta"), 200u64),
+    ] {
+        let msg = ExecuteMsg::LockCollateral {
+            collaterals: vec![("bluna".to_string(), Uint256::from(amount))],
+            sub_account: sub_account.map(|s| s.to_string()),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
+
+    // positions of other borrowers are not listed
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+        sub_account: Some("alpha".to_string()),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000/alpha".to_string(), &Uint256::from(50000u64)),
+        (&"addr0000/beta".to_string(), &Uint256::zero()),
+        (&"addr0000/gamma".to_string(), &Uint256::from(150000u64)),
+    ]);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::SubAccounts {
+            owner: "addr0000".to_string(),
+            start_after: None,
+            limit: Some(2u32),
+        },
+    )
+    .unwrap();
+    let sub_accounts_res: SubAccountsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        sub_accounts_res,
+        SubAccountsResponse {
+            owner: "addr0000".to_string(),
+            sub_accounts: vec![
+                SubAccountResponse {
+                    sub_account: "alpha".to_string(),
+                    collateral_value: Uint256::from(100000u64),
+                    loan_amount: Uint256::from(50000u64),
+                },
+                SubAccountResponse {
+                    sub_account: "beta".to_string(),
+                    collateral_value: Uint256::from(200000u64),
+                    loan_amount: Uint256::zero(),
+                },
+            ],
+        }
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::SubAccounts {
+            owner: "addr0000".to_string(),
+            start_after: Some("beta".to_string()),
+            limit: None,
+        },
+    )
+    .unwrap();
+    let sub_accounts_res: SubAccountsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        sub_accounts_res.sub_accounts,
+        vec![SubAccountResponse {
+            sub_account: "gamma".to_string(),
+            collateral_value: Uint256::from(300000u64),
+            loan_amount: Uint256::from(150000u64),
+        }]
+    );
+}
+
+#[test]
+fn soft_liquidation() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1_000_000u128),
+    }]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        soft_liquidation: Some(true),
+        buffer_backstop_limit: Some(Uint256::from(100u64)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    // shortfall of 60 uusd is covered by the interest buffer
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000060u64))]);
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("owner", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "market".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(60u128),
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                    borrower: "addr0000".to_string(),
+                    prev_balance: Uint256::zero(),
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![attr("borrower", "addr0000"), attr("backstop_repay", "60")]
+    );
+
+    // the remaining budget of 40 uusd cannot cover the shortfall,
+    // fallback to the normal liquidation
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_batom".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                liquidator: "owner".to_string(),
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(100000u64),
+                premium_rate: None,
+            })
+            .unwrap(),
+        }))
+    );
+    assert!(!res.attributes.iter().any(|a| a.key == "backstop_repay"));
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        collaterals_res,
+        CollateralsResponse {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                (batom_collat_token, Uint256::from(9900000u64)),
+                (bluna_collat_token, Uint256::from(990000u64)),
+            ]
+        }
+    );
+
+    // borrow_limit = 1000 * 990000 * 0.6 + 2000 * 9900000 * 0.6
+    // = 12,474,000,000 uusd
+    // the budget left covers the shortfall of 30 uusd, but only
+    // the owner may draw on it, other liquidators liquidate normally
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12474000030u64))]);
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg).unwrap();
+    assert!(!res.attributes.iter().any(|a| a.key == "backstop_repay"));
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "custody_batom".to_string(),
+            funds: vec![],
+            msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
+                liquidator: "addr0001".to_string(),
+                borrower: "addr0000".to_string(),
+                amount: Uint256::from(99000u64),
+                premium_rate: None,
+            })
+            .unwrap(),
+        }))
+    );
+}
+
+#[test]
+fn settle_bad_debt() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1_000_000u128),
+    }]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        bad_debt_budget: Some(Uint256::from(100u64)),
+        ..instantiate_msg()
+    };
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    deps.querier.with_loan_amount(&[
+        (&"addr0000".to_string(), &Uint256::from(60u64)),
+        (&"addr0001".to_string(), &Uint256::from(60u64)),
+        (&"addr0001/vault".to_string(), &Uint256::from(30u64)),
+    ]);
+
+    // only the owner and the risk admin draw on the interest buffer
+    let msg = ExecuteMsg::SettleBadDebt {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), msg);
+    match res {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the debt of a borrower with collaterals left is not bad debt
+    let msg = ExecuteMsg::SettleBadDebt {
+        borrower: "addr0001".to_string(),
+        sub_account: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), msg);
+    match res {
+        Err(ContractError::CollateralsNotSeized(borrower)) => assert_eq!(borrower, "addr0001"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // the residual debt is repaid from the interest buffer
+    let msg = ExecuteMsg::SettleBadDebt {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "market".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(60u128),
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                    borrower: "addr0000".to_string(),
+                    prev_balance: Uint256::zero(),
+                    sub_account: None,
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "settle_bad_debt"),
+            attr("borrower", "addr0000"),
+            attr("settled_amount", "60"),
+        ]
+    );
+
+    // the debt of a sub-account without collaterals is settled on its own,
+    // the market repays what is left after the tax of the transfer
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::SettleBadDebt {
+            borrower: "addr0001".to_string(),
+            sub_account: Some("vault".to_string()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "market".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(29u128),
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                funds: vec![],
+                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
+                    borrower: "addr0001".to_string(),
+                    prev_balance: Uint256::zero(),
+                    sub_account: Some("vault".to_string()),
+                })
+                .unwrap(),
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "settle_bad_debt"),
+            attr("borrower", "addr0001"),
+            attr("settled_amount", "29"),
+        ]
+    );
+
+    // the remaining budget of 10 uusd cannot cover the debt, so it is left
+    let res = execute(deps.as_mut(), env, mock_info("owner", &[]), msg).unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "settle_bad_debt"),
+            attr("borrower", "addr0000"),
+            attr("shortfall", "50"),
+        ]
+    );
+}
+
+#[test]
+fn safe_borrow_amount() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1000000u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // collateral_value = 1000 * 1000000 = 1,000,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(300000000u64))]);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::SafeBorrowAmount {
+            borrower: "addr0000".to_string(),
+            safety_ltv: Decimal256::percent(48),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let safe_borrow_res: SafeBorrowAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        safe_borrow_res,
+        SafeBorrowAmountResponse {
+            borrower: "addr0000".to_string(),
+            safe_borrow_limit: Uint256::from(480000000u64),
+            loan_amount: Uint256::from(300000000u64),
+            safe_borrow_amount: Uint256::from(180000000u64),
+        }
+    );
+
+    // borrowing the whole amount stays within the safety band
+    let ltv = Decimal256::from_ratio(
+        safe_borrow_res.loan_amount + safe_borrow_res.safe_borrow_amount,
+        Uint256::from(1000000000u64),
+    );
+    assert!(ltv <= Decimal256::percent(48));
+
+    // loans above the safety band cannot borrow more
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(500000000u64))]);
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::SafeBorrowAmount {
+            borrower: "addr0000".to_string(),
+            safety_ltv: Decimal256::percent(48),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let safe_borrow_res: SafeBorrowAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(safe_borrow_res.safe_borrow_amount, Uint256::zero());
+
+    for safety_ltv in [Decimal256::zero(), Decimal256::percent(61)] {
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::SafeBorrowAmount {
+                borrower: "addr0000".to_string(),
+                safety_ltv,
+                sub_account: None,
+            },
+        );
+        match res {
+            Err(StdError::GenericErr { .. }) => (),
+            _ => panic!("DO NOT ENTER HERE"),
+        }
+    }
+}
+
+#[test]
+fn position() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(50),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(10u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // borrow_limit = 1,000,000,000 * 0.6 + 100,000,000 * 0.5 = 650,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(500000000u64))]);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Position {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let position_res: PositionResponse = from_binary(&res).unwrap();
+
+    // the consolidated fields match the individual queries
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Collaterals {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            canonical: None,
+        },
+    )
+    .unwrap();
+    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        position_res
+            .collaterals
+            .iter()
+            .map(|c| (c.collateral_token.clone(), c.amount))
+            .collect::<Vec<(String, Uint256)>>(),
+        collaterals_res.collaterals
+    );
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::BorrowLimit {
+            borrower: "addr0000".to_string(),
+            block_time: Some(env.block.time.seconds()),
+            sub_account: None,
+            price_mode: None,
+            quote_denom: None,
+        },
+    )
+    .unwrap();
+    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+    assert_eq!(position_res.borrow_limit, borrow_limit_res.borrow_limit);
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::SafeBorrowAmount {
+            borrower: "addr0000".to_string(),
+            safety_ltv: Decimal256::percent(50),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let safe_borrow_res: SafeBorrowAmountResponse = from_binary(&res).unwrap();
+    assert_eq!(position_res.loan_amount, safe_borrow_res.loan_amount);
+
+    // bluna liquidates once its price drops by 150,000,000 / 600,000,000,
+    // batom alone cannot lose the 150,000,000 headroom
+    assert_eq!(
+        position_res,
+        PositionResponse {
+            borrower: "addr0000".to_string(),
+            collaterals: vec![
+                PositionCollateralElem {
+                    collateral_token: "bluna".to_string(),
+                    amount: Uint256::from(1000000u64),
+                    price: Decimal256::from_ratio(1000u64, 1u64),
+                    value: Uint256::from(1000000000u64),
+                    liquidation_price: Some(Decimal256::from_ratio(750u64, 1u64)),
+                },
+                PositionCollateralElem {
+                    collateral_token: "batom".to_string(),
+                    amount: Uint256::from(10000000u64),
+                    price: Decimal256::from_ratio(10u64, 1u64),
+                    value: Uint256::from(100000000u64),
+                    liquidation_price: None,
+                },
+            ],
+            collateral_value: Uint256::from(1100000000u64),
+            borrow_limit: Uint256::from(650000000u64),
+            loan_amount: Uint256::from(500000000u64),
+            ltv: Decimal256::from_ratio(5u64, 11u64),
+            health_factor: Decimal256::from_ratio(13u64, 10u64),
+        }
+    );
+
+    // a position without a loan has no liquidation price
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::Position {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+        },
+    )
+    .unwrap();
+    let position_res: PositionResponse = from_binary(&res).unwrap();
+    assert_eq!(position_res.ltv, Decimal256::zero());
+    assert!(position_res
+        .collaterals
+        .iter()
+        .all(|c| c.liquidation_price.is_none()));
+}
+
+#[test]
+fn health_factor() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
+
+    let info = mock_info("owner", &[]);
+    let env = mock_env();
+    let msg = instantiate_msg();
+
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let batom_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    let bluna_collat_token = deps
+        .api
+        .addr_humanize(&CanonicalAddr::from(vec![
+            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]))
+        .unwrap()
+        .to_string();
+
+    // store whitelist elems
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: bluna_collat_token.clone(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: batom_collat_token.clone(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+
+    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
+            (batom_collat_token.clone(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let info = mock_info("addr0000", &[]);
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &(batom_collat_token.clone(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(2000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let health_factors = |deps: Deps| {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::Position {
+                borrower: "addr0000".to_string(),
+                sub_account: None,
+            },
+        )
+        .unwrap();
+        let position_res: PositionResponse = from_binary(&res).unwrap();
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode: None,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
+        assert_eq!(position_res.health_factor, borrow_limit_res.health_factor);
+        position_res.health_factor
+    };
+
+    // no debt
+    assert_eq!(health_factors(deps.as_ref()), Decimal256::MAX);
+
+    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
+    // = 12,600,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+    assert_eq!(health_factors(deps.as_ref()), Decimal256::one());
+
+    let msg = ExecuteMsg::LiquidateCollateral {
+        borrower: "addr0000".to_string(),
+        sub_account: None,
+    };
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
+    match res {
+        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
+    assert!(health_factors(deps.as_ref()) < Decimal256::one());
+    let _res = execute(deps.as_mut(), env, info, msg).unwrap();
+}
+
+#[test]
+fn risk_contribution() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(50),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(1000u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::from_ratio(10u64, 1u64),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![
+            ("bluna".to_string(), Uint256::from(1000000u64)),
+            ("batom".to_string(), Uint256::from(10000000u64)),
+        ],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // borrow_limit = 1,000,000,000 * 0.6 + 100,000,000 * 0.5 = 650,000,000 uusd
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(500000000u64))]);
+
+    let res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::RiskContribution {
+            borrower: "addr0000".to_string(),
+        },
+    )
+    .unwrap();
+    let risk_res: RiskContributionResponse = from_binary(&res).unwrap();
+
+    // ltv = 500,000,000 / 1,100,000,000
+    // sensitivity = ltv * value / 1,100,000,000 * 1%
+    assert_eq!(
+        risk_res,
+        RiskContributionResponse {
+            borrower: "addr0000".to_string(),
+            ltv: Decimal256::from_str("0.454545454545454545").unwrap(),
+            contributions: vec![
+                RiskContributionElem {
+                    collateral_token: "bluna".to_string(),
+                    value: Uint256::from(1000000000u64),
+                    weighted_value: Uint256::from(600000000u64),
+                    sensitivity: Decimal256::from_str("0.004132231404958677").unwrap(),
+                },
+                RiskContributionElem {
+                    collateral_token: "batom".to_string(),
+                    value: Uint256::from(100000000u64),
+                    weighted_value: Uint256::from(50000000u64),
+                    sensitivity: Decimal256::from_str("0.000413223140495867").unwrap(),
+                },
+            ],
+        }
+    );
+
+    // the largest collateral endangers the position the most
+    let riskiest = risk_res
+        .contributions
+        .iter()
+        .max_by(|a, b| a.sensitivity.cmp(&b.sensitivity))
+        .unwrap();
+    assert_eq!(riskiest.collateral_token, "bluna");
+}
+
+#[test]
+fn remove_whitelist() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
-    // store whitelist elems
     let msg = ExecuteMsg::Whitelist {
         name: "bluna".to_string(),
         symbol: "bluna".to_string(),
         collateral_token: "bluna".to_string(),
         custody_contract: "custody_bluna".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
-    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    deps.querier.with_oracle_price(&[(
+        &("bluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
 
-    let msg = ExecuteMsg::Whitelist {
-        name: "batom".to_string(),
-        symbol: "batom".to_string(),
-        collateral_token: "batom".to_string(),
-        custody_contract: "custody_batom".to_string(),
-        max_ltv: Decimal256::percent(60),
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+        sub_account: None,
     };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
 
-    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
-
-    // only contract itself can execute update_epoch_state
-    let msg = ExecuteMsg::UpdateEpochState {
-        interest_buffer: Uint256::from(10000000000u128),
-        distributed_interest: Uint256::from(1000000u128),
+    let msg = ExecuteMsg::RemoveWhitelist {
+        collateral_token: "bluna".to_string(),
     };
-    let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
     match res {
         Err(ContractError::Unauthorized {}) => (),
         _ => panic!("DO NOT ENTER HERE"),
     }
 
-    // Assume execute epoch operation is executed
-    let mut env = mock_env();
-    let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
-    env.block.height += 86400u64;
-
-    deps.querier.with_epoch_state(&[(
-        &"market".to_string(),
-        &(Uint256::from(1000000u64), Decimal256::percent(120)),
-    )]);
-
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
-    assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "market".to_string(),
-            funds: vec![],
-            msg: to_binary(&MarketExecuteMsg::ExecuteEpochOperations {
-                deposit_rate: Decimal256::from_str("0.000002314814814814").unwrap(),
-                target_deposit_rate: Decimal256::permille(5),
-                threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
-                distributed_interest: Uint256::from(1000000u128),
-            })
-            .unwrap(),
-        }))]
-    );
-    assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "update_epoch_state"),
-            attr("deposit_rate", "0.000002314814814814"),
-            attr("aterra_supply", "1000000"),
-            attr("exchange_rate", "1.2"),
-            attr("interest_buffer", "10000000000"),
-        ]
+    // blocked while the collateral is locked
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        msg.clone(),
     );
+    match res {
+        Err(ContractError::CollateralStillLocked(token)) => assert_eq!(token, "bluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 
-    // Deposit rate increased
-    deps.querier.with_epoch_state(&[(
-        &"market".to_string(),
-        &(Uint256::from(1000000u64), Decimal256::percent(125)),
-    )]);
+    deps.querier
+        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::zero())]);
+    let unlock_msg = ExecuteMsg::UnlockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+        sub_account: None,
+    };
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        unlock_msg,
+    )
+    .unwrap();
 
-    env.block.height += 86400u64;
-    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-    assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "market".to_string(),
-            funds: vec![],
-            msg: to_binary(&MarketExecuteMsg::ExecuteEpochOperations {
-                deposit_rate: Decimal256::from_str("0.000000482253086419").unwrap(),
-                target_deposit_rate: Decimal256::permille(5),
-                threshold_deposit_rate: Decimal256::from_ratio(1u64, 1000000u64),
-                distributed_interest: Uint256::from(1000000u128),
-            })
-            .unwrap(),
-        }))]
+    // deposits the counter does not cover still block it
+    deps.querier
+        .with_custody_balance(&[(&"custody_bluna".to_string(), &Uint256::from(100u64))]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        msg.clone(),
     );
+    match res {
+        Err(ContractError::CollateralStillLocked(token)) => assert_eq!(token, "bluna"),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    deps.querier.with_custody_balance(&[]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        msg.clone(),
+    )
+    .unwrap();
     assert_eq!(
         res.attributes,
         vec![
-            attr("action", "update_epoch_state"),
-            attr("deposit_rate", "0.000000482253086419"),
-            attr("aterra_supply", "1000000"),
-            attr("exchange_rate", "1.25"),
-            attr("interest_buffer", "10000000000"),
+            attr("action", "remove_whitelist"),
+            attr("collateral_token", "bluna"),
         ]
     );
 
-    let epoch_state_response = query_epoch_state(
+    let res = query(
         deps.as_ref(),
-        Addr::unchecked("market"),
-        env.block.height,
-        None,
+        env.clone(),
+        QueryMsg::Whitelist {
+            collateral_token: None,
+            start_after: None,
+            limit: None,
+        },
     )
     .unwrap();
-    let epoch_state = read_epoch_state(deps.as_ref().storage).unwrap();
+    let whitelist_res: WhitelistResponse = from_binary(&res).unwrap();
+    assert_eq!(whitelist_res.elems, vec![]);
 
-    // deposit rate = 0.000000482253078703
-    assert_eq!(
-        epoch_state,
-        EpochState {
-            deposit_rate: Decimal256::from_ratio(482253086419u64, 1000000000000000000u64),
-            prev_aterra_supply: epoch_state_response.aterra_supply,
-            prev_exchange_rate: epoch_state_response.exchange_rate,
-            prev_interest_buffer: Uint256::from(10000000000u128),
-            last_executed_height: env.block.height,
-        }
-    )
+    // delisted collaterals cannot be locked nor removed again
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(100u64))],
+        sub_account: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap_err();
+    let msg = ExecuteMsg::RemoveWhitelist {
+        collateral_token: "bluna".to_string(),
+    };
+    let _res = execute(deps.as_mut(), env, mock_info("owner", &[]), msg).unwrap_err();
 }
 
 #[test]
-fn lock_collateral() {
+fn consistency_check() {
     let mut deps = mock_dependencies(&[]);
 
-    let info = mock_info("owner", &[]);
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
+    let bluna = deps.api.addr_canonicalize("bluna").unwrap();
+    let batom = deps.api.addr_canonicalize("batom").unwrap();
+    let addr0000 = deps.api.addr_canonicalize("addr0000").unwrap();
+    let addr0001 = deps.api.addr_canonicalize("addr0001").unwrap();
+
+    let storage = deps.as_mut().storage;
+    store_collaterals(
+        storage,
+        &addr0000,
+        None,
+        &vec![
+            (bluna.clone(), Uint256::from(100u64)),
+            (batom.clone(), Uint256::from(50u64)),
+        ],
+    )
+    .unwrap();
+    store_collaterals(
+        storage,
+        &addr0001,
+        None,
+        &vec![(bluna.clone(), Uint256::from(200u64))],
+    )
+    .unwrap();
+    store_collaterals(
+        storage,
+        &addr0001,
+        Some("vault"),
+        &vec![(bluna.clone(), Uint256::from(30u64))],
+    )
+    .unwrap();
+    store_total_locked(storage, &bluna, &Uint256::from(330u64)).unwrap();
+    store_total_locked(storage, &batom, &Uint256::from(50u64)).unwrap();
+
+    let check = |deps: Deps, start_after: Option<String>, limit: Option<u32>| {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::ConsistencyCheck { start_after, limit },
+        )
+        .unwrap();
+        let res: ConsistencyCheckResponse = from_binary(&res).unwrap();
+        res
+    };
+    let page_locked = |res: &ConsistencyCheckResponse, token: &str| {
+        res.elems
+            .iter()
+            .find(|elem| elem.collateral_token == token)
+            .map(|elem| elem.page_locked)
+            .unwrap_or_else(Uint256::zero)
     };
 
-    // we can just call .unwrap() to assert this was a success
-    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    let res = check(deps.as_ref(), None, None);
+    assert!(res.discrepancies.is_empty());
+    assert_eq!(page_locked(&res, "bluna"), Uint256::from(330u64));
+    assert_eq!(page_locked(&res, "batom"), Uint256::from(50u64));
 
-    let batom_collat_token = deps
-        .api
-        .addr_humanize(&CanonicalAddr::from(vec![
-            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ]))
-        .unwrap()
-        .to_string();
+    // paging through the borrowers adds up to the counters
+    let first = check(deps.as_ref(), None, Some(1));
+    let second = check(deps.as_ref(), first.last_borrower.clone(), Some(1));
+    let third = check(deps.as_ref(), second.last_borrower.clone(), Some(1));
+    assert!(first.discrepancies.is_empty() && second.discrepancies.is_empty());
+    assert_eq!(
+        page_locked(&first, "bluna") + page_locked(&second, "bluna"),
+        Uint256::from(330u64)
+    );
+    assert!(third.elems.is_empty());
+    assert_eq!(third.last_borrower, None);
 
-    let bluna_collat_token = deps
-        .api
-        .addr_humanize(&CanonicalAddr::from(vec![
-            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ]))
-        .unwrap()
-        .to_string();
+    // counter drifted below the locked positions
+    store_total_locked(deps.as_mut().storage, &bluna, &Uint256::from(250u64)).unwrap();
+    let res = check(deps.as_ref(), None, None);
+    assert_eq!(res.discrepancies, vec!["bluna".to_string()]);
+    let elem = res
+        .elems
+        .iter()
+        .find(|elem| elem.collateral_token == "bluna")
+        .unwrap();
+    assert_eq!(elem.total_locked, Uint256::from(250u64));
+    assert_eq!(elem.page_locked, Uint256::from(330u64));
+}
 
-    // store whitelist elems
-    let msg = ExecuteMsg::Whitelist {
-        name: "bluna".to_string(),
-        symbol: "bluna".to_string(),
-        collateral_token: bluna_collat_token.clone(),
-        custody_contract: "custody_bluna".to_string(),
-        max_ltv: Decimal256::percent(60),
-    };
+#[test]
+fn snapshot() {
+    let mut deps = mock_dependencies(&[]);
 
-    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let env = mock_env();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
-    let msg = ExecuteMsg::Whitelist {
-        name: "batom".to_string(),
-        symbol: "batom".to_string(),
-        collateral_token: batom_collat_token.clone(),
-        custody_contract: "custody_batom".to_string(),
-        max_ltv: Decimal256::percent(60),
-    };
+    for collateral_token in ["bluna", "batom"] {
+        let msg = ExecuteMsg::Whitelist {
+            name: collateral_token.to_string(),
+            symbol: collateral_token.to_string(),
+            collateral_token: collateral_token.to_string(),
+            custody_contract: format!("custody_{}", collateral_token),
+            max_ltv: Decimal256::percent(60),
+            quote_denom: None,
+            liquidation_premium: None,
+            correlation_group: None,
+            min_lock_amount: None,
+            borrow_enabled_from: None,
+            borrow_disabled_from: None,
+            liquidation_contract: None,
+            decimals: None,
+            token_type: None,
+            exchange_rate_source: None,
+            borrow_rate_premium: None,
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    }
 
-    let _res = execute(deps.as_mut(), mock_env(), info, msg);
+    // addr0001 holds a sub-account besides its default position
+    let positions: Vec<SnapshotPositionElem> = vec![
+        SnapshotPositionElem {
+            borrower: "addr0000".to_string(),
+            sub_account: None,
+            collaterals: vec![
+                ("bluna".to_string(), Uint256::from(100u64)),
+                ("batom".to_string(), Uint256::from(50u64)),
+            ],
+        },
+        SnapshotPositionElem {
+            borrower: "addr0001".to_string(),
+            sub_account: None,
+            collaterals: vec![("bluna".to_string(), Uint256::from(200u64))],
+        },
+        SnapshotPositionElem {
+            borrower: "addr0001".to_string(),
+            sub_account: Some("vault".to_string()),
+            collaterals: vec![("bluna".to_string(), Uint256::from(30u64))],
+        },
+        SnapshotPositionElem {
+            borrower: "addr0002".to_string(),
+            sub_account: None,
+            collaterals: vec![("batom".to_string(), Uint256::from(70u64))],
+        },
+    ];
+    for position in positions.iter() {
+        let msg = ExecuteMsg::LockCollateral {
+            collaterals: position.collaterals.clone(),
+            sub_account: position.sub_account.clone(),
+        };
+        let info = mock_info(&position.borrower, &[]);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    }
 
-    let msg = ExecuteMsg::LockCollateral {
-        collaterals: vec![
-            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
-            (batom_collat_token.clone(), Uint256::from(10000000u64)),
-        ],
+    let snapshot = |deps: Deps, start_after: Option<String>| {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::Snapshot {
+                start_after,
+                limit: Some(2),
+            },
+        )
+        .unwrap();
+        let res: SnapshotResponse = from_binary(&res).unwrap();
+        res
     };
-    let info = mock_info("addr0000", &[]);
-    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    let first = snapshot(deps.as_ref(), None);
     assert_eq!(
-        res.messages,
+        first.config,
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap()
+    );
+    assert_eq!(first.whitelist.len(), 2);
+    assert_eq!(
+        first.total_locked,
         vec![
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_bluna".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
-                    borrower: "addr0000".to_string(),
-                    amount: Uint256::from(1000000u64),
-                })
-                .unwrap(),
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_batom".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::LockCollateral {
-                    borrower: "addr0000".to_string(),
-                    amount: Uint256::from(10000000u64),
-                })
-                .unwrap(),
-            }))
+            ("bluna".to_string(), Uint256::from(330u64)),
+            ("batom".to_string(), Uint256::from(120u64)),
         ]
     );
 
+    // concatenating the chunks rebuilds every position
+    let mut chunks = vec![];
+    let mut start_after: Option<String> = None;
+    loop {
+        let chunk = snapshot(deps.as_ref(), start_after);
+        if chunk.positions.is_empty() {
+            assert_eq!(chunk.last_borrower, None);
+            break;
+        }
+        start_after = chunk.last_borrower.clone();
+        chunks.push(chunk);
+    }
+    assert_eq!(chunks.len(), 2);
+
+    let rebuilt: Vec<SnapshotPositionElem> = chunks
+        .into_iter()
+        .flat_map(|chunk| chunk.positions)
+        .collect();
+    assert_eq!(rebuilt, positions);
+}
+
+#[test]
+fn authorization() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = InstantiateMsg {
+        risk_admin: Some("risk".to_string()),
+        ..instantiate_msg()
+    };
+    let env = mock_env();
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let authorization = |deps: Deps, address: &str| {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::Authorization {
+                address: address.to_string(),
+            },
+        )
+        .unwrap();
+        let res: AuthorizationResponse = from_binary(&res).unwrap();
+        res
+    };
+
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "lock_collateral"),
-            attr("borrower", "addr0000"),
-            attr(
-                "collaterals",
-                format!(
-                    "1000000{},10000000{}",
-                    bluna_collat_token, batom_collat_token
-                )
-            ),
-        ]
+        authorization(deps.as_ref(), "owner"),
+        AuthorizationResponse {
+            address: "owner".to_string(),
+            can_update_config: true,
+            can_update_whitelist: true,
+            can_pause: true,
+            can_liquidate: true,
+            can_run_epoch: true,
+        }
     );
 
-    let res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::Collaterals {
-            borrower: "addr0000".to_string(),
-        },
-    )
-    .unwrap();
-    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    // the risk admin cannot disable borrows
     assert_eq!(
-        collaterals_res,
-        CollateralsResponse {
-            borrower: "addr0000".to_string(),
-            collaterals: vec![
-                (batom_collat_token.clone(), Uint256::from(10000000u64)),
-                (bluna_collat_token.clone(), Uint256::from(1000000u64)),
-            ]
+        authorization(deps.as_ref(), "risk"),
+        AuthorizationResponse {
+            address: "risk".to_string(),
+            can_update_config: true,
+            can_update_whitelist: true,
+            can_pause: false,
+            can_liquidate: true,
+            can_run_epoch: true,
         }
     );
 
-    let res = query(
-        deps.as_ref(),
-        mock_env(),
-        QueryMsg::AllCollaterals {
-            start_after: None,
-            limit: None,
-        },
-    )
-    .unwrap();
-    let all_collaterals_res: AllCollateralsResponse = from_binary(&res).unwrap();
     assert_eq!(
-        all_collaterals_res,
-        AllCollateralsResponse {
-            all_collaterals: vec![CollateralsResponse {
-                borrower: "addr0000".to_string(),
-                collaterals: vec![
-                    (batom_collat_token, Uint256::from(10000000u64)),
-                    (bluna_collat_token, Uint256::from(1000000u64)),
-                ]
-            }]
+        authorization(deps.as_ref(), "addr0000"),
+        AuthorizationResponse {
+            address: "addr0000".to_string(),
+            can_update_config: false,
+            can_update_whitelist: false,
+            can_pause: false,
+            can_liquidate: true,
+            can_run_epoch: true,
         }
     );
+
+    // the answers match the checks of the actions themselves
+    let msg = ExecuteMsg::UpdateWhitelist {
+        collateral_token: "bluna".to_string(),
+        custody_contract: None,
+        max_ltv: None,
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: Some(env.block.height),
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    match execute(deps.as_mut(), env, mock_info("risk", &[]), msg) {
+        Err(ContractError::Unauthorized {}) => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
 }
 
 #[test]
-fn unlock_collateral() {
+fn collateral_decimals() {
     let mut deps = mock_dependencies(&[]);
 
     let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
     let env = mock_env();
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
-
-    // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
-    // store whitelist elems
-    let msg = ExecuteMsg::Whitelist {
-        name: "bluna".to_string(),
-        symbol: "bluna".to_string(),
-        collateral_token: "bluna".to_string(),
-        custody_contract: "custody_bluna".to_string(),
-        max_ltv: Decimal256::percent(60),
-    };
-
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-
-    let msg = ExecuteMsg::Whitelist {
-        name: "batom".to_string(),
-        symbol: "batom".to_string(),
-        collateral_token: "batom".to_string(),
-        custody_contract: "custody_batom".to_string(),
+    let whitelist_msg = |collateral_token: &str, decimals: Option<u8>| ExecuteMsg::Whitelist {
+        name: collateral_token.to_string(),
+        symbol: collateral_token.to_string(),
+        collateral_token: collateral_token.to_string(),
+        custody_contract: format!("custody_{}", collateral_token),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
 
-    let _res = execute(deps.as_mut(), env.clone(), info, msg);
-
-    let msg = ExecuteMsg::LockCollateral {
-        collaterals: vec![
-            ("bluna".to_string(), Uint256::from(1000000u64)),
-            ("batom".to_string(), Uint256::from(10000000u64)),
-        ],
-    };
-    let info = mock_info("addr0000", &[]);
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-
-    // Failed to unlock more than locked amount
-    let msg = ExecuteMsg::UnlockCollateral {
-        collaterals: vec![
-            ("bluna".to_string(), Uint256::from(1000001u64)),
-            ("batom".to_string(), Uint256::from(10000001u64)),
-        ],
-    };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        whitelist_msg("beth", Some(19)),
+    );
     match res {
-        Err(ContractError::UnlockExceedsLocked {}) => (),
+        Err(ContractError::InvalidCollateralDecimals(19)) => (),
         _ => panic!("DO NOT ENTER HERE"),
     }
 
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        whitelist_msg("bluna", None),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info,
+        whitelist_msg("beth", Some(18)),
+    )
+    .unwrap();
+
+    let res = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::Whitelist {
+            collateral_token: Some("beth".to_string()),
+            start_after: None,
+            limit: None,
+        },
+    )
+    .unwrap();
+    let whitelist_res: WhitelistResponse = from_binary(&res).unwrap();
+    assert_eq!(whitelist_res.elems[0].decimals, 18);
+
+    // both collaterals are quoted at 1000 uusd per token
     deps.querier.with_oracle_price(&[
         (
             &("bluna".to_string(), "uusd".to_string()),
@@ -924,300 +16131,510 @@ fn unlock_collateral() {
             ),
         ),
         (
-            &("batom".to_string(), "uusd".to_string()),
+            &("beth".to_string(), "uusd".to_string()),
             &(
-                Decimal256::from_ratio(2000u64, 1u64),
+                Decimal256::from_ratio(1000u64, 1u64),
                 env.block.time.seconds(),
                 env.block.time.seconds(),
             ),
         ),
     ]);
 
-    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
-    // = 12,600,000,000 uusd
-    deps.querier
-        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+    // one token of each, in their own decimals
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("bluna".to_string(), Uint256::from(1_000_000u64))],
+        sub_account: None,
+    };
+    execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![(
+            "beth".to_string(),
+            Uint256::from(1_000_000_000_000_000_000u128),
+        )],
+        sub_account: None,
+    };
+    execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
 
-    // cannot unlock any tokens
-    // Failed to unlock more than locked amount
-    let msg = ExecuteMsg::UnlockCollateral {
-        collaterals: vec![("bluna".to_string(), Uint256::one())],
+    let borrow_limit = |deps: Deps, borrower: &str| {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowLimit {
+                borrower: borrower.to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode: None,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        let res: BorrowLimitResponse = from_binary(&res).unwrap();
+        res.borrow_limit
     };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-    match res {
-        Err(ContractError::UnlockTooLarge(12599999400)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
 
-    let msg = ExecuteMsg::UnlockCollateral {
-        collaterals: vec![("batom".to_string(), Uint256::one())],
+    // 1000 uusd * 60% in 6 decimals each
+    assert_eq!(
+        borrow_limit(deps.as_ref(), "addr0000"),
+        Uint256::from(600_000_000u64)
+    );
+    assert_eq!(
+        borrow_limit(deps.as_ref(), "addr0001"),
+        borrow_limit(deps.as_ref(), "addr0000")
+    );
+}
+
+#[test]
+fn collateral_exchange_rate_source() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let env = mock_env();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "stluna".to_string(),
+        symbol: "stluna".to_string(),
+        collateral_token: "stluna".to_string(),
+        custody_contract: "custody_stluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        exchange_rate_source: Some("stluna_hub".to_string()),
+        token_type: None,
+        borrow_rate_premium: None,
     };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-    match res {
-        Err(ContractError::UnlockTooLarge(12599998800)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
-    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
-    // = 12,600,000,000 uusd
-    deps.querier
-        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12599999400u64))]);
     let res = query(
         deps.as_ref(),
         env.clone(),
-        QueryMsg::BorrowLimit {
-            borrower: "addr0000".to_string(),
-            block_time: None,
+        QueryMsg::Whitelist {
+            collateral_token: Some("stluna".to_string()),
+            start_after: None,
+            limit: None,
         },
     )
     .unwrap();
-    let borrow_limit_res: BorrowLimitResponse = from_binary(&res).unwrap();
-    assert_eq!(borrow_limit_res.borrow_limit, Uint256::from(12600000000u64),);
-
-    // Cannot unlock 2bluna
-    let msg = ExecuteMsg::UnlockCollateral {
-        collaterals: vec![("bluna".to_string(), Uint256::from(2u64))],
-    };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
-    match res {
-        Err(ContractError::UnlockTooLarge(12599998800)) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
-
-    // Can unlock 1bluna
-    let msg = ExecuteMsg::UnlockCollateral {
-        collaterals: vec![("bluna".to_string(), Uint256::one())],
-    };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
-    assert_eq!(
-        res.messages,
-        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "custody_bluna".to_string(),
-            funds: vec![],
-            msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
-                borrower: "addr0000".to_string(),
-                amount: Uint256::one(),
-            })
-            .unwrap(),
-        }))]
-    );
-
+    let whitelist_res: WhitelistResponse = from_binary(&res).unwrap();
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "unlock_collateral"),
-            attr("borrower", "addr0000"),
-            attr("collaterals", "1bluna"),
-        ]
+        whitelist_res.elems[0].exchange_rate_source,
+        Some("stluna_hub".to_string())
     );
 
-    //testing for unlocking more collaterals
+    // the oracle prices the underlying asset
+    deps.querier.with_oracle_price(&[(
+        &("stluna".to_string(), "uusd".to_string()),
+        &(
+            Decimal256::from_ratio(1000u64, 1u64),
+            env.block.time.seconds(),
+            env.block.time.seconds(),
+        ),
+    )]);
     deps.querier
-        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(125999900u128))]);
+        .with_exchange_rate(&[(&"stluna_hub".to_string(), &Decimal256::one())]);
 
-    let msg = ExecuteMsg::UnlockCollateral {
-        collaterals: vec![
-            ("bluna".to_string(), Uint256::from(1u128)),
-            ("batom".to_string(), Uint256::from(1u128)),
-        ],
+    let msg = ExecuteMsg::LockCollateral {
+        collaterals: vec![("stluna".to_string(), Uint256::from(1_000_000u64))],
+        sub_account: None,
     };
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    assert_eq!(
-        res.messages,
-        vec![
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_bluna".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
-                    borrower: "addr0000".to_string(),
-                    amount: Uint256::from(1u128),
-                })
-                .unwrap(),
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_batom".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::UnlockCollateral {
-                    borrower: "addr0000".to_string(),
-                    amount: Uint256::from(1u128),
-                })
-                .unwrap(),
-            }))
-        ]
+    execute(deps.as_mut(), env, mock_info("addr0000", &[]), msg).unwrap();
+
+    let borrow_limit = |deps: Deps| {
+        let res = query(
+            deps,
+            mock_env(),
+            QueryMsg::BorrowLimit {
+                borrower: "addr0000".to_string(),
+                block_time: None,
+                sub_account: None,
+                price_mode: None,
+                quote_denom: None,
+            },
+        )
+        .unwrap();
+        let res: BorrowLimitResponse = from_binary(&res).unwrap();
+        res.borrow_limit
+    };
+
+    // 1000 uusd * 60%
+    assert_eq!(borrow_limit(deps.as_ref()), Uint256::from(600_000_000u64));
+
+    // the accrued interest makes each token worth 1.1 underlying assets
+    deps.querier.with_exchange_rate(&[(
+        &"stluna_hub".to_string(),
+        &Decimal256::from_ratio(11u64, 10u64),
+    )]);
+    assert_eq!(borrow_limit(deps.as_ref()), Uint256::from(660_000_000u64));
+}
+
+#[test]
+fn next_epoch() {
+    let mut deps = mock_dependencies(&[]);
+
+    let info = mock_info("addr0000", &[]);
+    let msg = instantiate_msg();
+    let mut env = mock_env();
+    let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let next_epoch = |deps: Deps, env: Env| {
+        let res = query(deps, env, QueryMsg::NextEpoch {}).unwrap();
+        let res: NextEpochResponse = from_binary(&res).unwrap();
+        res
+    };
+
+    assert_eq!(
+        next_epoch(deps.as_ref(), env.clone()),
+        NextEpochResponse {
+            last_epoch_height: env.block.height,
+            epoch_period: 86400u64,
+            blocks_until_next: 86400u64,
+            in_progress: false,
+        }
     );
+
+    env.block.height += 100;
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "unlock_collateral"),
-            attr("borrower", "addr0000"),
-            attr("collaterals", "1bluna,1batom"),
-        ]
+        next_epoch(deps.as_ref(), env.clone()).blocks_until_next,
+        86300u64
+    );
+
+    // executable from the end of the epoch on
+    env.block.height += 86300;
+    assert_eq!(
+        next_epoch(deps.as_ref(), env.clone()).blocks_until_next,
+        0u64
     );
+    env.block.height += 100;
+    assert_eq!(next_epoch(deps.as_ref(), env).blocks_until_next, 0u64);
 }
 
 #[test]
-fn liquidate_collateral() {
+fn stale_collaterals() {
     let mut deps = mock_dependencies(&[]);
-    deps.querier
-        .with_liquidation_percent(&[(&"liquidation".to_string(), &Decimal256::percent(1))]);
-
-    let info = mock_info("owner", &[]);
     let env = mock_env();
-    let msg = InstantiateMsg {
-        owner_addr: "owner".to_string(),
-        oracle_contract: "oracle".to_string(),
-        market_contract: "market".to_string(),
-        liquidation_contract: "liquidation".to_string(),
-        collector_contract: "collector".to_string(),
-        stable_denom: "uusd".to_string(),
-        epoch_period: 86400u64,
-        threshold_deposit_rate: Decimal256::permille(3),
-        target_deposit_rate: Decimal256::permille(5),
-        buffer_distribution_factor: Decimal256::percent(20),
-        anc_purchase_factor: Decimal256::percent(20),
-        price_timeframe: 60u64,
-    };
 
-    // we can just call .unwrap() to assert this was a success
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
     let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
-    let batom_collat_token = deps
-        .api
-        .addr_humanize(&CanonicalAddr::from(vec![
-            1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ]))
-        .unwrap()
-        .to_string();
-
-    let bluna_collat_token = deps
-        .api
-        .addr_humanize(&CanonicalAddr::from(vec![
-            1, 1, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        ]))
-        .unwrap()
-        .to_string();
-
-    // store whitelist elems
     let msg = ExecuteMsg::Whitelist {
         name: "bluna".to_string(),
         symbol: "bluna".to_string(),
-        collateral_token: bluna_collat_token.clone(),
+        collateral_token: "bluna".to_string(),
         custody_contract: "custody_bluna".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("luna".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
-
-    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
     let msg = ExecuteMsg::Whitelist {
         name: "batom".to_string(),
         symbol: "batom".to_string(),
-        collateral_token: batom_collat_token.clone(),
+        collateral_token: "batom".to_string(),
         custody_contract: "custody_batom".to_string(),
         max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("atom".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
 
-    let _res = execute(deps.as_mut(), env.clone(), info, msg);
+    // batom was last updated 100 seconds ago, beyond the 60 seconds timeframe
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds() - 100,
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("beth".to_string(), "uluna".to_string()),
+            &(
+                Decimal256::percent(100),
+                env.block.time.seconds() - 30,
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("uluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(300),
+                env.block.time.seconds(),
+                env.block.time.seconds() - 60,
+            ),
+        ),
+    ]);
 
-    let msg = ExecuteMsg::LockCollateral {
-        collaterals: vec![
-            (bluna_collat_token.clone(), Uint256::from(1000000u64)),
-            (batom_collat_token.clone(), Uint256::from(10000000u64)),
-        ],
+    let msg = ExecuteMsg::Whitelist {
+        name: "beth".to_string(),
+        symbol: "beth".to_string(),
+        collateral_token: "beth".to_string(),
+        custody_contract: "custody_beth".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: Some("uluna".to_string()),
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
-    let info = mock_info("addr0000", &[]);
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::StaleCollaterals {}).unwrap();
+    let res: StaleCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(
+        res.elems,
+        vec![StaleCollateralElem {
+            collateral_token: "batom".to_string(),
+            last_updated: env.block.time.seconds() - 100,
+            price_age: 100,
+        }]
+    );
+
+    // the quote price of beth goes stale as well
     deps.querier.with_oracle_price(&[
         (
-            &(bluna_collat_token.clone(), "uusd".to_string()),
+            &("bluna".to_string(), "uusd".to_string()),
             &(
-                Decimal256::from_ratio(1000u64, 1u64),
+                Decimal256::percent(200),
                 env.block.time.seconds(),
                 env.block.time.seconds(),
             ),
         ),
         (
-            &(batom_collat_token.clone(), "uusd".to_string()),
+            &("batom".to_string(), "uusd".to_string()),
             &(
-                Decimal256::from_ratio(2000u64, 1u64),
+                Decimal256::percent(50),
+                env.block.time.seconds() - 100,
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("beth".to_string(), "uluna".to_string()),
+            &(
+                Decimal256::percent(100),
+                env.block.time.seconds() - 30,
                 env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("uluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(300),
                 env.block.time.seconds(),
+                env.block.time.seconds() - 61,
             ),
         ),
     ]);
 
-    // borrow_limit = 1000 * 1000000 * 0.6 + 2000 * 10000000 * 0.6
-    // = 12,600,000,000 uusd
-    deps.querier
-        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000000u64))]);
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::StaleCollaterals {}).unwrap();
+    let res: StaleCollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(res.elems.len(), 2);
+    assert!(res.elems.contains(&StaleCollateralElem {
+        collateral_token: "beth".to_string(),
+        last_updated: env.block.time.seconds() - 61,
+        price_age: 61,
+    }));
+}
 
-    let msg = ExecuteMsg::LiquidateCollateral {
-        borrower: "addr0000".to_string(),
+#[test]
+fn simulation() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    let info = mock_info("owner", &[]);
+    let msg = instantiate_msg();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "bluna".to_string(),
+        symbol: "bluna".to_string(),
+        collateral_token: "bluna".to_string(),
+        custody_contract: "custody_bluna".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("luna".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
     };
-    let info = mock_info("addr0001", &[]);
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone());
-    match res {
-        Err(ContractError::CannotLiquidateSafeLoan {}) => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "batom".to_string(),
+        symbol: "batom".to_string(),
+        collateral_token: "batom".to_string(),
+        custody_contract: "custody_batom".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: None,
+        liquidation_premium: None,
+        correlation_group: Some("atom".to_string()),
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // batom was last updated 100 seconds ago, beyond the 60 seconds timeframe
+    deps.querier.with_oracle_price(&[
+        (
+            &("bluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(200),
+                env.block.time.seconds(),
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("batom".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(50),
+                env.block.time.seconds() - 100,
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("beth".to_string(), "uluna".to_string()),
+            &(
+                Decimal256::percent(100),
+                env.block.time.seconds() - 30,
+                env.block.time.seconds(),
+            ),
+        ),
+        (
+            &("uluna".to_string(), "uusd".to_string()),
+            &(
+                Decimal256::percent(300),
+                env.block.time.seconds(),
+                env.block.time.seconds() - 60,
+            ),
+        ),
+    ]);
+
+    let msg = ExecuteMsg::Whitelist {
+        name: "beth".to_string(),
+        symbol: "beth".to_string(),
+        collateral_token: "beth".to_string(),
+        custody_contract: "custody_beth".to_string(),
+        max_ltv: Decimal256::percent(60),
+        quote_denom: Some("uluna".to_string()),
+        liquidation_premium: None,
+        correlation_group: None,
+        min_lock_amount: None,
+        borrow_enabled_from: None,
+        borrow_disabled_from: None,
+        liquidation_contract: None,
+        decimals: None,
+        token_type: None,
+        exchange_rate_source: None,
+        borrow_rate_premium: None,
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     deps.querier
-        .with_loan_amount(&[(&"addr0000".to_string(), &Uint256::from(12600000001u64))]);
-    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-    assert_eq!(
-        res.messages,
-        vec![
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_batom".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
-                    liquidator: "addr0001".to_string(),
-                    borrower: "addr0000".to_string(),
-                    amount: Uint256::from(100000u64),
-                })
-                .unwrap(),
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "custody_bluna".to_string(),
-                funds: vec![],
-                msg: to_binary(&CustodyExecuteMsg::LiquidateCollateral {
-                    liquidator: "addr0001".to_string(),
-                    borrower: "addr0000".to_string(),
-                    amount: Uint256::from(10000u64),
-                })
-                .unwrap(),
-            })),
-            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "market".to_string(),
-                funds: vec![],
-                msg: to_binary(&MarketExecuteMsg::RepayStableFromLiquidation {
-                    borrower: "addr0000".to_string(),
-                    prev_balance: Uint256::zero(),
-                })
-                .unwrap(),
-            }))
-        ]
-    );
+        .with_anc_emission_rate(&[(&"market".to_string(), &Decimal256::percent(120))]);
+    deps.querier
+        .with_market_reserves(&[(&"market".to_string(), &Decimal256::from_uint256(5000u64))]);
+    deps.querier
+        .with_interest_model(&[(&"market".to_string(), &"interest".to_string())]);
 
-    let res = query(
-        deps.as_ref(),
-        env,
-        QueryMsg::Collaterals {
-            borrower: "addr0000".to_string(),
-        },
+    let res: SimulationResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Simulation {}).unwrap()).unwrap();
+
+    let config_res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.config, config_res);
+
+    let whitelist_res: WhitelistResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Whitelist {
+                collateral_token: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
     )
     .unwrap();
-    let collaterals_res: CollateralsResponse = from_binary(&res).unwrap();
+    assert_eq!(res.whitelist, whitelist_res.elems);
+
+    // beth is crossed through its uluna quote price, 1 * 3
     assert_eq!(
-        collaterals_res,
-        CollateralsResponse {
-            borrower: "addr0000".to_string(),
-            collaterals: vec![
-                (batom_collat_token, Uint256::from(9900000u64)),
-                (bluna_collat_token, Uint256::from(990000u64)),
-            ]
-        }
+        res.collateral_prices,
+        res.whitelist
+            .iter()
+            .map(|elem| {
+                let price = match elem.collateral_token.as_str() {
+                    "bluna" => Decimal256::percent(200),
+                    "batom" => Decimal256::percent(50),
+                    _ => Decimal256::percent(300),
+                };
+                (elem.collateral_token.clone(), price)
+            })
+            .collect::<Vec<(String, Decimal256)>>()
+    );
+
+    assert_eq!(res.market_state.anc_emission_rate, Decimal256::percent(120));
+    assert_eq!(
+        res.market_state.total_reserves,
+        Decimal256::from_uint256(5000u64)
+    );
+    assert_eq!(res.interest_model_config.base_rate, Decimal256::percent(2));
+    assert_eq!(
+        res.interest_model_config.interest_multiplier,
+        Decimal256::percent(20)
     );
 }
@@ -1,5 +1,28 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{Addr, Api, StdResult};
 
+/// Native coin or CW20 token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Token { contract_addr: String },
+    NativeToken { denom: String },
+}
+
+/// Deserializes a field that is present, even as `null`, into `Some`,
+/// so that an `Option<Option<T>>` update tells a value to clear from
+/// one to leave unchanged
+pub fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
 pub fn optional_addr_validate(api: &dyn Api, addr: Option<String>) -> StdResult<Option<Addr>> {
     let addr = if let Some(addr) = addr {
         Some(api.addr_validate(&addr)?)
@@ -9,3 +32,37 @@ pub fn optional_addr_validate(api: &dyn Api, addr: Option<String>) -> StdResult<
 
     Ok(addr)
 }
+
+/// Rounds the value to `precision` decimal places, half to even so that
+/// rounded rates are not biased upwards. Unchanged when precision is not
+/// given or exceeds the 18 decimal places of Decimal256
+pub fn round_decimal(value: Decimal256, precision: Option<u32>) -> Decimal256 {
+    let precision = match precision {
+        Some(precision) if precision < 18 => precision,
+        _ => return value,
+    };
+
+    let scale = Decimal256::from_uint256(10u64.pow(precision));
+    let scaled = value * scale;
+    let mut rounded: Uint256 = scaled * Uint256::one();
+    let remainder = scaled - Decimal256::from_uint256(rounded);
+
+    let half_rounded = Decimal256::from_uint256(rounded) * Decimal256::percent(50);
+    let is_odd = half_rounded != Decimal256::from_uint256(half_rounded * Uint256::one());
+    if remainder > Decimal256::percent(50) || (remainder == Decimal256::percent(50) && is_odd) {
+        rounded += Uint256::one();
+    }
+
+    Decimal256::from_uint256(rounded) / scale
+}
+
+/// Multiplies the value by the ratio, rounding up instead of down
+pub fn mul_ceil(value: Uint256, ratio: Decimal256) -> Uint256 {
+    let product = Decimal256::from_uint256(value) * ratio;
+    let floored = product * Uint256::one();
+    if Decimal256::from_uint256(floored) < product {
+        floored + Uint256::one()
+    } else {
+        floored
+    }
+}
@@ -0,0 +1,52 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_bignumber::Uint256;
+use cosmwasm_std::Binary;
+
+/// Interface of the contracts converting a deprecated collateral token
+/// into its successor, used by the overseer to migrate positions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    /// Convert the received tokens to `ask_token` and send them to `recipient`.
+    /// If `msg` is given the tokens are sent with it as a cw20 hook
+    Convert {
+        ask_token: String,
+        recipient: String,
+        msg: Option<Binary>,
+    },
+}
+
+/// Conversions of native coins, used by the market to deploy
+/// its reserves into collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Convert the attached coins to `ask_token` and send them to `recipient`.
+    /// If `msg` is given the tokens are sent with it as a cw20 hook.
+    /// Fails when less than `min_receive` would be sent
+    ConvertNative {
+        ask_token: String,
+        recipient: String,
+        msg: Option<Binary>,
+        min_receive: Option<Uint256>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Amount of `ask_token` returned for `amount` of `offer_token`
+    Simulation {
+        offer_token: String,
+        ask_token: String,
+        amount: Uint256,
+    },
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub amount: Uint256,
+}
@@ -1,7 +1,9 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_bignumber::Uint256;
+use crate::common::AssetInfo;
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::Binary;
 use cw20::Cw20ReceiveMsg;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -23,6 +25,24 @@ pub struct InstantiateMsg {
     /// it, we try to convert the reward to the `stable_denom`.
     pub stable_denom: String,
     pub basset_info: BAssetInfo,
+    /// Record the balances in shares of the collateral token, which reports
+    /// the token amount of a share through `RebaseIndex`, defaults to false.
+    /// The overseer values the shares with the custody as `exchange_rate_source`
+    pub rebasing: Option<bool>,
+    /// Denom the bAsset rewards are paid in, defaults to `stable_denom`
+    pub reward_denom: Option<String>,
+    /// Swap the rewards to `stable_denom` before sending them to
+    /// the overseer, defaults to true
+    pub swap_rewards: Option<bool>,
+    /// Min # of blocks between two reward distributions, earlier
+    /// `DistributeRewards` calls are skipped. Defaults to zero
+    pub reward_distribution_interval: Option<u64>,
+    /// Share of the claimed rewards remitted to the overseer, the rest
+    /// is distributed to the collateral holders. Defaults to one
+    pub protocol_reward_share: Option<Decimal256>,
+    /// Multiply the reward share of the holders keeping collateral
+    /// locked, disabled when not given
+    pub reward_boost: Option<RewardBoost>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -39,20 +59,64 @@ pub enum ExecuteMsg {
     UpdateConfig {
         owner: Option<String>,
         liquidation_contract: Option<String>,
+        reward_distribution_interval: Option<u64>,
+        protocol_reward_share: Option<Decimal256>,
+        /// `null` disables the reward boost
+        #[serde(default, deserialize_with = "crate::common::deserialize_some")]
+        reward_boost: Option<Option<RewardBoost>>,
     },
+    /// Transfer the whole balance of an asset sent to this contract by mistake.
+    /// The collateral token and stable denom cannot be swept
+    SweepTokens { asset: AssetInfo, recipient: String },
+    /// Compare the recorded total balance to the collateral balance of the
+    /// custody. A surplus is kept as a reserve owned by no borrower and a
+    /// deficit is flagged, the borrower balances are never changed
+    Reconcile {},
     /// Make specified amount of tokens unspendable
     LockCollateral { borrower: String, amount: Uint256 },
     /// Make specified amount of collateral tokens spendable
     UnlockCollateral { borrower: String, amount: Uint256 },
-    /// Claim bAsset rewards and distribute claimed rewards
-    /// to market and overseer contracts
+    /// Send the holder rewards of the borrower to the overseer, which forwards
+    /// them. Rewards not swapped to `stable_denom` go to the borrower directly
+    ClaimRewardsFor { borrower: String },
+    /// Claim bAsset rewards, remit `protocol_reward_share` of them
+    /// to the overseer and keep the rest for the collateral holders
     DistributeRewards {},
+    /// Move the holder rewards accrued so far to claimable balances and
+    /// restart the reward indexes from zero, `limit` holders per call.
+    /// Called again until it reports completion
+    MigrateRewardIndexes { limit: Option<u32> },
+    /// Move locked collateral of the borrower to the recipient,
+    /// where it stays locked
+    TransferCollateral {
+        borrower: String,
+        recipient: String,
+        amount: Uint256,
+    },
 
     /// Liquidate collateral and send liquidated collateral to `to` address
     LiquidateCollateral {
         liquidator: String,
         borrower: String,
         amount: Uint256,
+        premium_rate: Option<Decimal256>, // Premium rate of the collateral, passed to the bid
+    },
+
+    /// Remove locked collateral of the borrower and send it
+    /// to the `converter` contract with the given cw20 hook `msg`
+    ConvertCollateral {
+        borrower: String,
+        amount: Uint256,
+        converter: String,
+        msg: Binary,
+    },
+
+    /// Remove locked collateral of the borrower and transfer
+    /// the tokens to the recipient
+    ReleaseCollateral {
+        borrower: String,
+        recipient: String,
+        amount: Uint256,
     },
 
     ////////////////////
@@ -63,6 +127,9 @@ pub enum ExecuteMsg {
     /// If the amount is not given,
     /// return all spendable collateral
     WithdrawCollateral { amount: Option<Uint256> },
+    /// Claim the holder share of the distributed rewards,
+    /// which also brings the reward boost of the sender up to date
+    ClaimRewards {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -70,6 +137,9 @@ pub enum ExecuteMsg {
 pub enum Cw20HookMsg {
     /// Deposit collateral token
     DepositCollateral {},
+    /// Deposit collateral token on behalf of `borrower`,
+    /// sent by collateral converters
+    DepositCollateralFor { borrower: String },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -83,6 +153,27 @@ pub enum QueryMsg {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    TotalBalance {},
+    LastDistribution {},
+    /// Outcome of the last `Reconcile`
+    Reconciliation {},
+    /// Collateral tokens a single recorded share is worth
+    ExchangeRate {},
+    /// Rewards distributed to the holders and remitted to the overseer
+    RewardState {},
+}
+
+/// Query served by rebasing collateral tokens
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RebasingTokenQueryMsg {
+    RebaseIndex {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RebaseIndexResponse {
+    /// Token amount of a single share
+    pub rebase_index: Decimal256,
 }
 
 // We define a custom struct for each query response
@@ -96,6 +187,12 @@ pub struct ConfigResponse {
     pub liquidation_contract: String,
     pub stable_denom: String,
     pub basset_info: BAssetInfo,
+    pub rebasing: bool,
+    pub reward_denom: String,
+    pub swap_rewards: bool,
+    pub reward_distribution_interval: u64,
+    pub protocol_reward_share: Decimal256,
+    pub reward_boost: Option<RewardBoost>,
 }
 
 // We define a custom struct for each query response
@@ -104,6 +201,11 @@ pub struct BorrowerResponse {
     pub borrower: String,
     pub balance: Uint256,
     pub spendable: Uint256,
+    /// Holder rewards the borrower can claim
+    pub pending_rewards: Uint256,
+    /// Multiplier of the reward share of the borrower at the queried
+    /// height, applied from its next settlement
+    pub reward_boost: Decimal256,
 }
 
 // We define a custom struct for each query response
@@ -112,9 +214,221 @@ pub struct BorrowersResponse {
     pub borrowers: Vec<BorrowerResponse>,
 }
 
+/// We currently take no arguments for migrations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TotalBalanceResponse {
+    pub total_balance: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LastDistributionResponse {
+    pub last_distributed_height: u64,
+    pub next_distribution_height: u64,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReconciliationResponse {
+    pub reserve: Uint256,
+    pub deficit: Uint256,
+    pub reconciled_height: u64,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardStateResponse {
+    pub global_index: Decimal256,
+    pub index_epoch: u64,
+    pub total_weight: Uint256,
+    pub unclaimed_rewards: Uint256,
+    pub total_remitted: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardBoost {
+    /// Boost gained per block the collateral of a holder stays locked
+    pub boost_per_block: Decimal256,
+    /// Max multiplier of the reward share, at least one
+    pub max_boost: Decimal256,
+}
+
+impl RewardBoost {
+    /// Multiplier of a holder locking collateral since `lock_start_height`,
+    /// one when nothing is locked
+    pub fn boost(&self, lock_start_height: Option<u64>, height: u64) -> Decimal256 {
+        match lock_start_height {
+            Some(lock_start_height) => std::cmp::min(
+                Decimal256::one()
+                    + self.boost_per_block
+                        * Decimal256::from_uint256(height.saturating_sub(lock_start_height)),
+                self.max_boost,
+            ),
+            None => Decimal256::one(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct BAssetInfo {
     pub name: String,
     pub symbol: String,
     pub decimals: u8,
 }
+
+/// Collateral a custody contract holds for a borrower, of which
+/// `balance - spendable` is locked by the overseer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BorrowerInfo {
+    pub balance: Uint256,
+    pub spendable: Uint256,
+}
+
+impl BorrowerInfo {
+    /// Move `amount` of the locked collateral to `recipient`, where it
+    /// stays locked. Fails with the locked amount when it is exceeded
+    pub fn transfer_locked(
+        &mut self,
+        recipient: &mut BorrowerInfo,
+        amount: Uint256,
+    ) -> Result<(), Uint256> {
+        let locked_amount = self.balance - self.spendable;
+        if amount > locked_amount {
+            return Err(locked_amount);
+        }
+
+        self.balance = self.balance - amount;
+        recipient.balance += amount;
+        Ok(())
+    }
+}
+
+/// Collateral balance of the custody against the recorded total balance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct Reconciliation {
+    /// Collateral held above the total balance, owned by no borrower
+    pub reserve: Uint256,
+    /// Collateral missing from the total balance, left for investigation
+    pub deficit: Uint256,
+    pub reconciled_height: u64,
+}
+
+impl Reconciliation {
+    /// Compare the token `balance` of the custody with its `total_balance`,
+    /// both in collateral tokens
+    pub fn new(total_balance: Uint256, balance: Uint256, reconciled_height: u64) -> Self {
+        Reconciliation {
+            reserve: if balance > total_balance {
+                balance - total_balance
+            } else {
+                Uint256::zero()
+            },
+            deficit: if total_balance > balance {
+                total_balance - balance
+            } else {
+                Uint256::zero()
+            },
+            reconciled_height,
+        }
+    }
+}
+
+/// Rewards the custody keeps for its collateral holders
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct RewardState {
+    /// Holder rewards distributed per unit of weight
+    pub global_index: Decimal256,
+    /// Number of times the indexes were restarted from zero
+    #[serde(default)]
+    pub index_epoch: u64,
+    /// Sum of the holder weights, their balances multiplied by their boosts
+    pub total_weight: Uint256,
+    /// Holder rewards held by the custody until they are claimed
+    pub unclaimed_rewards: Uint256,
+    /// Protocol share of the rewards remitted to the overseer so far
+    pub total_remitted: Uint256,
+}
+
+impl RewardState {
+    /// Split `reward_amount` between the overseer, which gets
+    /// `protocol_reward_share` of it, and the holders by weight.
+    /// Everything is remitted while no collateral is held.
+    /// Returns the amount to remit
+    pub fn distribute(
+        &mut self,
+        reward_amount: Uint256,
+        protocol_reward_share: Decimal256,
+    ) -> Uint256 {
+        let holder_rewards = if self.total_weight.is_zero() {
+            Uint256::zero()
+        } else {
+            reward_amount - reward_amount * protocol_reward_share
+        };
+
+        if !holder_rewards.is_zero() {
+            self.global_index += Decimal256::from_ratio(holder_rewards, self.total_weight);
+            self.unclaimed_rewards += holder_rewards;
+        }
+
+        let remitted = reward_amount - holder_rewards;
+        self.total_remitted += remitted;
+        remitted
+    }
+}
+
+/// Holder rewards a borrower accrued on their collateral balance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct HolderReward {
+    /// Global index at the last settlement
+    pub reward_index: Decimal256,
+    pub pending_rewards: Decimal256,
+    /// Balance multiplied by the reward boost at the last settlement
+    pub weight: Uint256,
+    /// Height since which some collateral of the holder is locked
+    pub lock_start_height: Option<u64>,
+    /// Rewards accrued before the indexes were restarted, claimed at once
+    #[serde(default)]
+    pub claimable_rewards: Uint256,
+    /// Epoch of the global index `reward_index` refers to
+    #[serde(default)]
+    pub index_epoch: u64,
+}
+
+impl HolderReward {
+    /// Accrue the rewards of the recorded weight up to `global_index`
+    pub fn settle(&mut self, global_index: Decimal256) {
+        self.pending_rewards +=
+            Decimal256::from_uint256(self.weight) * (global_index - self.reward_index);
+        self.reward_index = global_index;
+    }
+
+    /// Accrue the rewards up to `legacy_index`, the last global index of the
+    /// previous epoch, into the claimable balance and start `index_epoch`
+    /// from a zero index. The fraction below one unit stays pending
+    pub fn reset_index(&mut self, legacy_index: Decimal256, index_epoch: u64) {
+        self.settle(legacy_index);
+        let carried_rewards = Uint256::one() * self.pending_rewards;
+        self.pending_rewards = self.pending_rewards - Decimal256::from_uint256(carried_rewards);
+        self.claimable_rewards += carried_rewards;
+        self.reward_index = Decimal256::zero();
+        self.index_epoch = index_epoch;
+    }
+
+    /// Whole amount of rewards the holder can claim
+    pub fn claimable(&self) -> Uint256 {
+        self.claimable_rewards + Uint256::one() * self.pending_rewards
+    }
+
+    /// Multiplier of the reward share of the holder at `height`,
+    /// one without a reward boost
+    pub fn boost(&self, reward_boost: Option<&RewardBoost>, height: u64) -> Decimal256 {
+        reward_boost.map_or(Decimal256::one(), |reward_boost| {
+            reward_boost.boost(self.lock_start_height, height)
+        })
+    }
+}
@@ -8,26 +8,51 @@ pub struct InstantiateMsg {
     pub owner: String,
     pub base_rate: Decimal256,
     pub interest_multiplier: Decimal256,
+    /// Weight applied to the raw utilization ratio, defaults to 1.0
+    pub risk_weight: Option<Decimal256>,
+    /// Market contract whose state is recorded into the smoothed borrow rate
+    pub market_contract: Option<String>,
+    /// Weight of the newest rate in the exponential moving average,
+    /// defaults to 1.0 (no smoothing)
+    pub smoothing_factor: Option<Decimal256>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     UpdateConfig {
         owner: Option<String>,
         base_rate: Option<Decimal256>,
         interest_multiplier: Option<Decimal256>,
+        risk_weight: Option<Decimal256>,
+        market_contract: Option<String>,
+        smoothing_factor: Option<Decimal256>,
     },
+    /// Record the current borrow rate of the market contract
+    /// into the moving average, at most once per block
+    UpdateSmoothedRate {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
+    /// Moving average of the borrow rate once a rate is recorded
+    /// with smoothing enabled, the instantaneous rate otherwise
     BorrowRate {
         market_balance: Uint256,
         total_liabilities: Decimal256,
         total_reserves: Decimal256,
+        /// Decimal places of the reported rate, full precision by default
+        precision: Option<u32>,
+    },
+    /// Borrow rate of the given market state, never smoothed
+    InstantBorrowRate {
+        market_balance: Uint256,
+        total_liabilities: Decimal256,
+        total_reserves: Decimal256,
+        precision: Option<u32>,
     },
 }
 
@@ -37,6 +62,9 @@ pub struct ConfigResponse {
     pub owner: String,
     pub base_rate: Decimal256,
     pub interest_multiplier: Decimal256,
+    pub risk_weight: Decimal256,
+    pub market_contract: Option<String>,
+    pub smoothing_factor: Decimal256,
 }
 
 // We define a custom struct for each query response
@@ -1,6 +1,8 @@
 pub mod common;
+pub mod converter;
 pub mod custody;
 pub mod distribution_model;
+pub mod exchange_rate;
 pub mod interest_model;
 pub mod liquidation;
 pub mod liquidation_queue;
@@ -8,6 +10,8 @@ pub mod market;
 pub mod oracle;
 pub mod overseer;
 pub mod querier;
+pub mod staking;
+pub mod strategy;
 pub mod tokens;
 
 #[cfg(test)]
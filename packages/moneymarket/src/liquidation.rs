@@ -26,22 +26,58 @@ pub struct InstantiateMsg {
     pub liquidation_threshold: Uint256,
     /// Valid oracle price timeframe
     pub price_timeframe: u64,
+    /// Receives the bid fees instead of the fee address of the bid,
+    /// e.g. an insurance fund
+    pub liquidation_fee_recipient: Option<String>,
+    /// Max collateral value liquidated by a single liquidation,
+    /// larger positions are liquidated over multiple calls
+    pub max_liquidation_value: Option<Uint256>,
+    /// Round the seized collaterals up and the repaid amounts down,
+    /// so that rounding never favors the borrower. Defaults to true
+    pub conservative_rounding: Option<bool>,
+    /// LTV the position is brought back to by a liquidation, below the
+    /// borrow limit to leave a buffer against an immediate re-liquidation.
+    /// Positions are brought to `safe_ratio` of the borrow limit when unset
+    /// or not below the liquidation LTV of the position
+    pub post_liquidation_safe_ltv: Option<Decimal256>,
+    /// Overseer allowed to start liquidation auctions
+    pub overseer_contract: Option<String>,
+    /// Blocks over which the discount of a liquidation auction grows
+    /// to the max premium rate. Liquidations are executed against
+    /// the bids at once when unset
+    pub auction_duration: Option<u64>,
+    /// Replaces bid_fee when the borrower liquidates its own position,
+    /// which then pays no premium to a third party. Cannot exceed bid_fee
+    pub self_liquidation_fee: Option<Decimal256>,
+}
+
+/// Config values updated by `UpdateConfig`, unset ones are left unchanged
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UpdateConfigMsg {
+    pub owner: Option<String>,
+    pub oracle_contract: Option<String>,
+    pub stable_denom: Option<String>,
+    pub safe_ratio: Option<Decimal256>,
+    pub bid_fee: Option<Decimal256>,
+    pub max_premium_rate: Option<Decimal256>,
+    pub liquidation_threshold: Option<Uint256>,
+    pub price_timeframe: Option<u64>,
+    /// `null` sends the bid fees to the fee address of the bid again
+    #[serde(default, deserialize_with = "crate::common::deserialize_some")]
+    pub liquidation_fee_recipient: Option<Option<String>>,
+    pub max_liquidation_value: Option<Uint256>,
+    pub conservative_rounding: Option<bool>,
+    pub post_liquidation_safe_ltv: Option<Decimal256>,
+    pub overseer_contract: Option<String>,
+    pub auction_duration: Option<u64>,
+    pub self_liquidation_fee: Option<Decimal256>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
-    UpdateConfig {
-        owner: Option<String>,
-        oracle_contract: Option<String>,
-        stable_denom: Option<String>,
-        safe_ratio: Option<Decimal256>,
-        bid_fee: Option<Decimal256>,
-        max_premium_rate: Option<Decimal256>,
-        liquidation_threshold: Option<Uint256>,
-        price_timeframe: Option<u64>,
-    },
+    UpdateConfig(Box<UpdateConfigMsg>),
     SubmitBid {
         collateral_token: String,
         premium_rate: Decimal256,
@@ -50,6 +86,55 @@ pub enum ExecuteMsg {
         collateral_token: String,
         amount: Option<Uint256>,
     },
+    /// Transfer every collateral won by the sender's bids
+    /// out of escrow
+    ClaimLiquidationProceeds {},
+    /// Deposit the attached stable funds to the keeper balance of the sender,
+    /// which pays at the max premium rate for the liquidations of
+    /// collaterals the sender has no bid for
+    DepositKeeperFunds {},
+    /// Withdraw from the keeper balance of the sender,
+    /// the whole balance if no amount is given
+    WithdrawKeeperFunds {
+        amount: Option<Uint256>,
+    },
+
+    ////////////////////
+    /// Overseer operations
+    ////////////////////
+
+    /// Open a Dutch auction over the collaterals seized from the borrower,
+    /// which are escrowed by the custody contracts right after
+    StartAuction {
+        borrower: String,
+        sub_account: Option<String>,
+        collaterals: TokensHuman,
+    },
+    /// Liquidate the attached native collateral, which the overseer holds
+    /// and sends in place of a custody contract
+    ExecuteBid {
+        collateral_token: String,
+        liquidator: String,
+        fee_address: Option<String>,
+        repay_address: Option<String>,
+        borrower: Option<String>, // Owner of the liquidated collateral
+        premium_rate: Option<Decimal256>, // Overrides the max premium rate for the collateral
+    },
+
+    ////////////////////
+    /// User operations
+    ////////////////////
+
+    /// Buy the escrowed collaterals of the auction at the current discount,
+    /// paying with the attached stable funds
+    Bid {
+        borrower: String,
+    },
+    /// Close an auction left without a bid after its duration,
+    /// the escrowed collaterals are transferred to the owner
+    SettleAuction {
+        borrower: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -59,6 +144,8 @@ pub enum Cw20HookMsg {
         liquidator: String,
         fee_address: Option<String>,
         repay_address: Option<String>,
+        borrower: Option<String>, // Owner of the liquidated collateral
+        premium_rate: Option<Decimal256>, // Overrides the max premium rate for the collateral
     },
 }
 
@@ -71,6 +158,8 @@ pub enum QueryMsg {
         borrow_limit: Uint256,
         collaterals: TokensHuman,
         collateral_prices: Vec<Decimal256>,
+        /// Premium rate of each collateral, max_premium_rate if not given
+        premium_rates: Option<Vec<Decimal256>>,
     },
     Bid {
         collateral_token: String,
@@ -86,6 +175,18 @@ pub enum QueryMsg {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    KeeperFunds {
+        keeper: String,
+    },
+    /// Every parameter the liquidation model uses to compute
+    /// liquidation amounts and execute bids
+    LiquidationConfig {},
+    /// Discount curve of the active auction of the borrower, with the
+    /// collaterals `bid_amount` of stable would buy at this block
+    Auction {
+        borrower: String,
+        bid_amount: Option<Uint256>,
+    },
 }
 
 // We define a custom struct for each query response
@@ -99,6 +200,29 @@ pub struct ConfigResponse {
     pub max_premium_rate: Decimal256,
     pub liquidation_threshold: Uint256,
     pub price_timeframe: u64,
+    pub liquidation_fee_recipient: Option<String>,
+    pub max_liquidation_value: Option<Uint256>,
+    pub conservative_rounding: bool,
+    pub post_liquidation_safe_ltv: Option<Decimal256>,
+    pub overseer_contract: Option<String>,
+    pub auction_duration: Option<u64>,
+    pub self_liquidation_fee: Option<Decimal256>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationConfigResponse {
+    pub stable_denom: String,
+    pub safe_ratio: Decimal256,
+    pub bid_fee: Decimal256,
+    pub max_premium_rate: Decimal256,
+    pub liquidation_threshold: Uint256,
+    pub price_timeframe: u64,
+    pub liquidation_fee_recipient: Option<String>,
+    pub max_liquidation_value: Option<Uint256>,
+    pub conservative_rounding: bool,
+    pub post_liquidation_safe_ltv: Option<Decimal256>,
+    pub auction_duration: Option<u64>,
 }
 
 // We define a custom struct for each query response
@@ -121,3 +245,27 @@ pub struct BidResponse {
 pub struct BidsResponse {
     pub bids: Vec<BidResponse>,
 }
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeeperFundsResponse {
+    pub keeper: String,
+    pub amount: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AuctionResponse {
+    pub borrower: String,
+    pub sub_account: Option<String>,
+    pub collaterals: TokensHuman,
+    pub start_height: u64,
+    pub discount: Decimal256,
+    pub blocks_elapsed: u64,
+    pub blocks_remaining: u64,
+    /// Stable amount buying the whole escrowed collaterals
+    pub auction_price: Uint256,
+    /// Share of the escrowed collaterals `bid_amount` buys,
+    /// the whole of them without `bid_amount`
+    pub implied_collaterals: TokensHuman,
+}
@@ -21,6 +21,9 @@ pub struct InstantiateMsg {
     /// Fee applied to executed bids
     /// Sent to the address executing the liquidation
     pub liquidator_fee: Decimal256,
+    /// Replaces liquidator_fee when the borrower liquidates
+    /// its own position, cannot exceed liquidator_fee
+    pub self_liquidation_bonus: Decimal256,
     /// Liquidation threshold amount in stable denom.
     /// When the current collaterals value is smaller than
     /// the threshold, all collaterals will be liquidated
@@ -34,6 +37,7 @@ pub struct InstantiateMsg {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
     UpdateConfig {
@@ -42,6 +46,7 @@ pub enum ExecuteMsg {
         safe_ratio: Option<Decimal256>,
         bid_fee: Option<Decimal256>,
         liquidator_fee: Option<Decimal256>,
+        self_liquidation_bonus: Option<Decimal256>,
         liquidation_threshold: Option<Uint256>,
         price_timeframe: Option<u64>,
         waiting_period: Option<u64>,
@@ -79,6 +84,15 @@ pub enum ExecuteMsg {
         collateral_token: String,
         bids_idx: Option<Vec<Uint128>>,
     },
+    /// Liquidate the attached native collateral, which the overseer holds
+    /// and sends in place of a custody contract
+    ExecuteBid {
+        collateral_token: String,
+        liquidator: String, // Legacy parameter, ignored
+        fee_address: Option<String>,
+        repay_address: Option<String>,
+        borrower: Option<String>, // Owner of the liquidated collateral
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -89,6 +103,7 @@ pub enum Cw20HookMsg {
         liquidator: String, // Legacy parameter, ignored
         fee_address: Option<String>,
         repay_address: Option<String>,
+        borrower: Option<String>, // Owner of the liquidated collateral
     },
 }
 
@@ -133,6 +148,7 @@ pub struct ConfigResponse {
     pub safe_ratio: Decimal256,
     pub bid_fee: Decimal256,
     pub liquidator_fee: Decimal256,
+    pub self_liquidation_bonus: Decimal256,
     pub liquidation_threshold: Uint256,
     pub price_timeframe: u64,
     pub waiting_period: u64,
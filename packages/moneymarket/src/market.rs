@@ -2,6 +2,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::Binary;
 use cw20::Cw20ReceiveMsg;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -17,6 +18,88 @@ pub struct InstantiateMsg {
     pub anc_emission_rate: Decimal256,
     /// Maximum allowed borrow rate over deposited stable balance
     pub max_borrow_factor: Decimal256,
+    /// Borrows are disabled while the available stable balance
+    /// is below this ratio of the total deposits
+    pub min_liquidity_ratio: Decimal256,
+    /// A loan left with at most this amount after a repayment
+    /// is closed, to absorb decimal rounding. Cannot exceed 1000
+    pub repay_dust_epsilon: Uint256,
+    /// Number of blocks produced per year, used to annualize
+    /// the per-block borrow rate
+    pub blocks_per_year: u64,
+    /// Portion of each borrow kept as reserves, the borrower receives
+    /// the rest but owes the whole amount. Defaults to zero
+    pub borrow_fee: Option<Decimal256>,
+    /// Interest is only accrued by user operations once this many
+    /// blocks passed since the last accrual, defaults to zero
+    pub accrual_block_threshold: Option<u64>,
+    /// Accrue interest over the elapsed block time instead of the
+    /// elapsed blocks, converting the per-block rate of the interest model
+    /// with `blocks_per_year`. The accrual threshold is then converted
+    /// to seconds at the same rate. Defaults to false
+    pub accrue_by_time: Option<bool>,
+    /// Converter acquiring collateral with the stable coins
+    /// deployed from the reserves
+    pub reserve_route: Option<String>,
+    /// Portion of a flash deleverage amount charged on top of it
+    /// and kept as reserves. Defaults to zero
+    pub flash_fee: Option<Decimal256>,
+    /// Denoms accepted by `RepayStable`, which must include the
+    /// stable denom. Defaults to just the stable denom. Other denoms
+    /// are valued at the oracle price, unpriced denoms are rejected
+    pub accepted_repay_denoms: Option<Vec<String>>,
+    /// External strategy the owner deploys idle liquidity into for yield
+    pub yield_strategy: Option<String>,
+    /// Max ratio of the market liquidity deployed to the yield strategy,
+    /// the rest stays available for borrows and redemptions. Defaults to zero
+    pub max_deployed_ratio: Option<Decimal256>,
+    /// Staking contract reporting the discount tier of each borrower
+    pub rate_discount_source: Option<String>,
+    /// Share of the accrued interest rebated to the borrowers of each tier
+    /// out of the reserves. Tiers without a discount pay the full interest
+    pub rate_discount_tiers: Option<Vec<(u32, Decimal256)>>,
+    /// Smallest repayment accepted unless it closes the loan,
+    /// as every repayment writes the interest indexes. Defaults to zero
+    pub min_repay_amount: Option<Uint256>,
+    /// Portion of the interest repaid, never of the principal,
+    /// kept as reserves. Defaults to zero
+    pub repay_protocol_fee: Option<Decimal256>,
+    /// Yearly fee rate on the undrawn commitment of credit lines,
+    /// kept as reserves. Defaults to zero, which disables credit lines
+    pub commitment_fee: Option<Decimal256>,
+    /// Ceiling of the total liabilities across every borrower,
+    /// borrows are not capped when not given
+    pub max_total_borrow: Option<Uint256>,
+    /// Handling of a repayment in the block of the last borrow
+    /// of the borrower. Defaults to allowing it
+    pub same_block_repay_policy: Option<SameBlockRepayPolicy>,
+}
+
+/// Config values updated by `UpdateConfig`, unset ones are left unchanged
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UpdateConfigMsg {
+    pub owner_addr: Option<String>,
+    pub max_borrow_factor: Option<Decimal256>,
+    pub interest_model: Option<String>,
+    pub distribution_model: Option<String>,
+    pub min_liquidity_ratio: Option<Decimal256>,
+    pub repay_dust_epsilon: Option<Uint256>,
+    pub blocks_per_year: Option<u64>,
+    pub borrow_fee: Option<Decimal256>,
+    pub accrual_block_threshold: Option<u64>,
+    pub accrue_by_time: Option<bool>,
+    pub reserve_route: Option<String>,
+    pub flash_fee: Option<Decimal256>,
+    pub accepted_repay_denoms: Option<Vec<String>>,
+    pub yield_strategy: Option<String>,
+    pub max_deployed_ratio: Option<Decimal256>,
+    pub rate_discount_source: Option<String>,
+    pub rate_discount_tiers: Option<Vec<(u32, Decimal256)>>,
+    pub min_repay_amount: Option<Uint256>,
+    pub repay_protocol_fee: Option<Decimal256>,
+    pub commitment_fee: Option<Decimal256>,
+    pub max_total_borrow: Option<Uint256>,
+    pub same_block_repay_policy: Option<SameBlockRepayPolicy>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -43,11 +126,48 @@ pub enum ExecuteMsg {
     },
 
     /// Update config values
-    UpdateConfig {
-        owner_addr: Option<String>,
-        max_borrow_factor: Option<Decimal256>,
-        interest_model: Option<String>,
-        distribution_model: Option<String>,
+    UpdateConfig(Box<UpdateConfigMsg>),
+
+    /// Grant the borrower an amount of interest-free borrowing
+    /// against the collateral, replacing any previous grant for it.
+    /// Allowances only apply to the default position
+    GrantInterestFreeAllowance {
+        borrower: String,
+        collateral_token: String,
+        amount: Uint256,
+    },
+
+    /// Revoke the interest-free allowance granted against the collateral
+    RevokeInterestFreeAllowance {
+        borrower: String,
+        collateral_token: String,
+    },
+
+    /// Spend `amount` of the reserves acquiring `collateral_token` through
+    /// the reserve route, and lock what the route delivers in the overseer
+    /// as collateral owned by the market, across its custody contracts
+    DeployReserves {
+        collateral_token: String,
+        amount: Uint256,
+    },
+
+    /// Unlock `amount` of the collateral owned by the market and convert
+    /// it back through the reserve route, the stable coins received
+    /// return to the reserves
+    RecallReserves {
+        collateral_token: String,
+        amount: Uint256,
+    },
+
+    /// Deposit `amount` of the market liquidity into the yield strategy,
+    /// within `max_deployed_ratio` of the liquidity
+    DeployToStrategy {
+        amount: Uint256,
+    },
+
+    /// Withdraw `amount` of the deployed liquidity from the yield strategy
+    RecallFromStrategy {
+        amount: Uint256,
     },
 
     ////////////////////
@@ -57,11 +177,61 @@ pub enum ExecuteMsg {
     RepayStableFromLiquidation {
         borrower: String,
         prev_balance: Uint256,
+        sub_account: Option<String>,
+    },
+
+    /// Repay stable for the borrower with the attached funds, the same
+    /// way the borrower repaying it would, sent when a position is closed
+    RepayStableOnBehalf {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+
+    /// Move the default position loan of the borrower onto the default
+    /// position of the recipient, sent when a position is transferred
+    TransferLoan {
+        borrower: String,
+        recipient: String,
+    },
+
+    /// Move `fraction` of the loan of a position of the borrower onto
+    /// another of its positions, sent when a position is split
+    SplitLoan {
+        borrower: String,
+        from_sub_account: Option<String>,
+        to_sub_account: String,
+        fraction: Decimal256,
+    },
+
+    ////////////////////
+    /// Internal operations
+    ////////////////////
+    /// Check the pending flash deleverage was paid back, sent by the
+    /// market itself after the borrower callback
+    SettleFlashDeleverage {},
+
+    /// Convert the collateral withdrawn by a `RecallReserves` above
+    /// `prev_balance`, sent by the market itself
+    ConvertRecalledReserves {
+        collateral_token: String,
+        prev_balance: Uint256,
+    },
+
+    /// Next step of a `SetLeverage`, sent by the market itself
+    /// once the previous step locked its collateral
+    ContinueLeverage {
+        borrower: String,
+        collateral_token: String,
+        target_leverage: Decimal256,
+        iterations_left: u32,
+        slippage: Decimal256,
     },
 
     /// Execute epoch operations
     /// 1. send reserve to collector contract
     /// 2. update anc_emission_rate state
+    ///
+    /// Stable coins sent along are added to the reserves
     ExecuteEpochOperations {
         deposit_rate: Decimal256,
         target_deposit_rate: Decimal256,
@@ -76,17 +246,83 @@ pub enum ExecuteMsg {
     DepositStable {},
 
     /// Borrow stable asset with collaterals in overseer contract
+    /// Positions of the same address are kept apart by `sub_account`,
+    /// `None` is the default position
     BorrowStable {
         borrow_amount: Uint256,
         to: Option<String>,
+        sub_account: Option<String>,
+    },
+
+    /// Repay stable asset to decrease liability.
+    /// With `auto_unlock`, collateral is unlocked proportionally
+    /// to the repaid portion of the loan.
+    /// Funds above the loan are refunded unless `overpay_action` is `Credit`
+    RepayStable {
+        auto_unlock: Option<bool>,
+        sub_account: Option<String>,
+        overpay_action: Option<OverpayAction>,
+    },
+
+    /// Send the repay credit of the sender back, all of it
+    /// when no amount is given
+    WithdrawRepayCredit {
+        amount: Option<Uint256>,
+    },
+
+    /// Repay `repay_amount` of the loan with the attached stable funds
+    /// and borrow `new_borrow_amount` in the same transaction.
+    /// The resulting loan must be within the borrow limit
+    Refinance {
+        repay_amount: Uint256,
+        new_borrow_amount: Uint256,
+        sub_account: Option<String>,
     },
 
-    /// Repay stable asset to decrease liability
-    RepayStable {},
+    /// Repay `borrow_amount` of the default position loan with stable
+    /// coins lent by the market, then execute `callback` on the borrower,
+    /// which can unlock the freed collateral and must send back
+    /// `borrow_amount` plus the flash fee within the same transaction.
+    /// Any excess is refunded. Other market operations are rejected
+    /// until the flash deleverage is settled
+    FlashDeleverage {
+        borrow_amount: Uint256,
+        callback: Binary,
+    },
+
+    /// Raise the default position leverage, collateral value over its
+    /// value net of the loan, toward `target_leverage`. Each iteration
+    /// borrows within the borrow limit, converts the loan into the locked
+    /// `collateral_token` through the reserve route and locks it, until the
+    /// target or `max_iterations` is reached. A conversion returning less
+    /// than the borrowed value net of `slippage` fails the whole adjustment
+    SetLeverage {
+        collateral_token: String,
+        target_leverage: Decimal256,
+        max_iterations: u32,
+        slippage: Decimal256,
+    },
+
+    /// Commit `amount` of borrow capacity to the default position, replacing
+    /// any previous commitment. The commitment must fit the borrow limit on
+    /// top of the loan and is reserved from the capacity of other borrowers.
+    /// The fee accrued on the previous commitment is paid with attached
+    /// stable funds, any excess is refunded
+    OpenCreditLine {
+        amount: Uint256,
+    },
+
+    /// Borrow `amount` of the undrawn commitment into the default position.
+    /// The accrued commitment fee is kept from the drawn funds
+    DrawCredit {
+        amount: Uint256,
+        to: Option<String>,
+    },
 
     /// Claim distributed ANC rewards
     ClaimRewards {
         to: Option<String>,
+        sub_account: Option<String>,
     },
 }
 
@@ -98,6 +334,28 @@ pub enum Cw20HookMsg {
     RedeemStable {},
 }
 
+/// What happens to the part of a repayment exceeding the loan
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverpayAction {
+    /// Send the excess back to the borrower
+    Refund,
+    /// Keep the excess as a credit paying the future interest of the borrower
+    Credit,
+}
+
+/// What happens to a repayment in the same block as a borrow of the borrower
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SameBlockRepayPolicy {
+    Allow,
+    /// Keep this portion of the repayment as reserves
+    Fee {
+        rate: Decimal256,
+    },
+    Block,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
@@ -112,11 +370,64 @@ pub enum QueryMsg {
     BorrowerInfo {
         borrower: String,
         block_height: Option<u64>,
+        sub_account: Option<String>,
     },
+    /// Pages over borrowers, their sub-accounts are listed
+    /// after their default position
     BorrowerInfos {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    /// Interest the depositors accrued since the last epoch operation,
+    /// at the deposit rate capped by the target deposit rate
+    AccruedInterest {},
+    /// Exchange rates at two heights and the implied APR in between,
+    /// using the rates recorded by epoch operations
+    ExchangeRateBetween {
+        start_height: u64,
+        end_height: u64,
+    },
+    /// Stored interest accrual state with the aterra supply and the
+    /// current borrow rate, enough to reproduce the exchange rate
+    AccrualParams {},
+    /// Stored global and borrower interest indexes without accrual,
+    /// loan_amount * global / borrower index gives the current liability
+    InterestIndexes {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// Whether borrowing the amount would succeed now,
+    /// with the first failing check as the reason otherwise
+    CanBorrow {
+        borrower: String,
+        amount: Uint256,
+        sub_account: Option<String>,
+    },
+    /// Current per-block borrow rate of the interest model,
+    /// annualized with the configured blocks per year
+    BorrowApr {},
+    /// Current per-block deposit rate compounded over the year into an APY,
+    /// `compounding_periods` times a year, at most and by default every block
+    DepositApy {
+        compounding_periods: Option<u64>,
+    },
+    /// Interest clock and height the interest and the ANC rewards were
+    /// last accrued at, callers can trigger an accrual before reading
+    /// the stored state
+    LastAccrual {},
+    /// aTerra amount the depositor can redeem right now within the
+    /// market liquidity, with the stable amount it redeems for
+    MaxRedeemable {
+        depositor: String,
+    },
+    /// Undrawn commitment of the borrower with the commitment fee
+    /// accrued up to the current block
+    CreditLine {
+        borrower: String,
+    },
+    /// Reserves as a fraction of the total liabilities,
+    /// both accrued up to the current block
+    ReserveCoverage {},
 }
 
 // We define a custom struct for each query response
@@ -131,6 +442,24 @@ pub struct ConfigResponse {
     pub distributor_contract: String,
     pub stable_denom: String,
     pub max_borrow_factor: Decimal256,
+    pub min_liquidity_ratio: Decimal256,
+    pub repay_dust_epsilon: Uint256,
+    pub blocks_per_year: u64,
+    pub borrow_fee: Decimal256,
+    pub accrual_block_threshold: u64,
+    pub accrue_by_time: bool,
+    pub reserve_route: Option<String>,
+    pub flash_fee: Decimal256,
+    pub accepted_repay_denoms: Vec<String>,
+    pub yield_strategy: Option<String>,
+    pub max_deployed_ratio: Decimal256,
+    pub rate_discount_source: Option<String>,
+    pub rate_discount_tiers: Vec<(u32, Decimal256)>,
+    pub min_repay_amount: Uint256,
+    pub repay_protocol_fee: Decimal256,
+    pub commitment_fee: Decimal256,
+    pub max_total_borrow: Option<Uint256>,
+    pub same_block_repay_policy: SameBlockRepayPolicy,
 }
 
 // We define a custom struct for each query response
@@ -145,6 +474,11 @@ pub struct StateResponse {
     pub anc_emission_rate: Decimal256,
     pub prev_aterra_supply: Uint256,
     pub prev_exchange_rate: Decimal256,
+    pub liquidity_ratio: Decimal256,
+    /// Liquidity deployed to the yield strategy
+    pub total_deployed: Uint256,
+    /// Ceiling of `total_liabilities`
+    pub max_total_borrow: Option<Uint256>,
 }
 
 // We define a custom struct for each query response
@@ -158,10 +492,15 @@ pub struct EpochStateResponse {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct BorrowerInfoResponse {
     pub borrower: String,
+    pub sub_account: Option<String>,
     pub interest_index: Decimal256,
     pub reward_index: Decimal256,
     pub loan_amount: Uint256,
     pub pending_rewards: Decimal256,
+    /// Interest-free borrowing granted to the default position
+    pub interest_free_allowance: Uint256,
+    /// Over-repaid amount paying the future interest of the borrower
+    pub repay_credit: Uint256,
 }
 
 // We define a custom struct for each query response
@@ -169,3 +508,103 @@ pub struct BorrowerInfoResponse {
 pub struct BorrowerInfosResponse {
     pub borrower_infos: Vec<BorrowerInfoResponse>,
 }
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccruedInterestResponse {
+    pub accrued_interest: Uint256,
+    pub last_epoch_height: Option<u64>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateBetweenResponse {
+    pub start_exchange_rate: Decimal256,
+    /// Height of the epoch the start exchange rate was recorded at
+    pub start_snapshot_height: u64,
+    pub end_exchange_rate: Decimal256,
+    pub end_snapshot_height: u64,
+    pub apr: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccrualParamsResponse {
+    pub global_interest_index: Decimal256,
+    pub last_interest_updated: u64,
+    pub total_liabilities: Decimal256,
+    pub total_reserves: Decimal256,
+    pub aterra_supply: Uint256,
+    /// Borrow rate per block reported by the interest model
+    pub borrow_rate: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InterestIndexesResponse {
+    pub borrower: String,
+    pub global_interest_index: Decimal256,
+    pub last_interest_updated: u64,
+    /// Global interest index when the loan amount was last updated
+    pub interest_index: Decimal256,
+    pub loan_amount: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CanBorrowResponse {
+    pub allowed: bool,
+    pub reason: Option<String>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BorrowAprResponse {
+    pub borrow_rate: Decimal256,
+    pub blocks_per_year: u64,
+    pub apr: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositApyResponse {
+    pub deposit_rate: Decimal256,
+    pub compounding_periods: u64,
+    pub apy: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LastAccrualResponse {
+    /// Block height of the last interest accrual, or its block time
+    /// in seconds when `accrue_by_time` is set
+    pub last_interest_clock: u64,
+    pub accrue_by_time: bool,
+    /// Block height of the last ANC reward accrual
+    pub last_reward_updated: u64,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MaxRedeemableResponse {
+    pub aterra_amount: Uint256,
+    pub stable_amount: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreditLineResponse {
+    pub borrower: String,
+    pub committed: Uint256,
+    pub accrued_fee: Uint256,
+    pub total_committed: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReserveCoverageResponse {
+    pub total_reserves: Decimal256,
+    pub total_liabilities: Decimal256,
+    /// Zero when there are no liabilities
+    pub coverage_ratio: Decimal256,
+}
@@ -137,6 +137,7 @@ impl WasmMockQuerier {
                                 rate: v.0,
                                 last_updated_base: v.1,
                                 last_updated_quote: v.2,
+                                confidence_spread: None,
                             })))
                         }
                         None => SystemResult::Err(SystemError::InvalidRequest {
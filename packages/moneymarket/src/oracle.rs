@@ -7,6 +7,7 @@ use cosmwasm_bignumber::Decimal256;
 pub struct InstantiateMsg {
     pub owner: String,
     pub base_asset: String,
+    pub price_timeframe: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -14,6 +15,7 @@ pub struct InstantiateMsg {
 pub enum ExecuteMsg {
     UpdateConfig {
         owner: Option<String>,
+        price_timeframe: Option<u64>,
     },
     RegisterFeeder {
         asset: String,
@@ -22,6 +24,14 @@ pub enum ExecuteMsg {
     FeedPrice {
         prices: Vec<(String, Decimal256)>, // (asset, price)
     },
+    /// Prices of several assets recorded at the block time, the whole
+    /// batch is rejected when the sender does not feed one of the assets
+    FeedPrices {
+        prices: Vec<(String, Decimal256)>, // (asset, price)
+    },
+    FeedPriceAt {
+        prices: Vec<(String, Decimal256, u64)>, // (asset, price, observed_at)
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -31,6 +41,11 @@ pub enum QueryMsg {
     Feeder {
         asset: String,
     },
+    /// Feeders registered for the asset with the block time
+    /// of their most recent price submission
+    Feeders {
+        asset: String,
+    },
     Price {
         base: String,
         quote: String,
@@ -39,6 +54,24 @@ pub enum QueryMsg {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    /// Time weighted average of the prices fed over the last `window`
+    /// seconds, limited to the part of the window the kept price
+    /// history covers
+    TwapPrice {
+        base: String,
+        quote: String,
+        window: u64,
+    },
+}
+
+/// Oracle price a query is valued with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceMode {
+    /// Latest fed price
+    Spot,
+    /// Time weighted average price over the last `window` seconds
+    Twap { window: u64 },
 }
 
 // We define a custom struct for each query response
@@ -46,6 +79,7 @@ pub enum QueryMsg {
 pub struct ConfigResponse {
     pub owner: String,
     pub base_asset: String,
+    pub price_timeframe: u64,
 }
 
 // We define a custom struct for each query response
@@ -55,12 +89,29 @@ pub struct FeederResponse {
     pub feeder: String,
 }
 
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeedersResponse {
+    pub asset: String,
+    pub feeders: Vec<FeedersResponseElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeedersResponseElem {
+    pub feeder: String,
+    /// None until the feeder submits a price for the asset
+    pub last_submission_time: Option<u64>,
+}
+
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct PriceResponse {
     pub rate: Decimal256,
     pub last_updated_base: u64,
     pub last_updated_quote: u64,
+    /// Width of the price confidence band relative to the rate,
+    /// for oracles reporting one
+    pub confidence_spread: Option<Decimal256>,
 }
 
 // We define a custom struct for each query response
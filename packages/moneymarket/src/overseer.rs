@@ -1,6 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::common::AssetInfo;
+use crate::interest_model::ConfigResponse as InterestModelConfigResponse;
+use crate::market::StateResponse as MarketStateResponse;
+use crate::oracle::PriceMode;
 use crate::tokens::TokensHuman;
 use cosmwasm_bignumber::{Decimal256, Uint256};
 
@@ -33,12 +37,145 @@ pub struct InstantiateMsg {
     pub buffer_distribution_factor: Decimal256,
     /// Ratio to be used for purchasing ANC token from the interest buffer
     pub anc_purchase_factor: Decimal256,
-    /// Valid oracle price timeframe
-    pub price_timeframe: u64,
+    /// Valid oracle price timeframe for new borrows and unlocks
+    pub borrow_price_timeframe: u64,
+    /// Valid oracle price timeframe for liquidations, not shorter than
+    /// `borrow_price_timeframe` which it defaults to
+    pub liquidation_price_timeframe: Option<u64>,
+    /// Max ANC price at which the interest buffer purchases ANC token
+    pub max_anc_price: Option<Decimal256>,
+    /// Target amount of the interest buffer
+    pub buffer_target: Uint256,
+    /// Address to receive the interest buffer exceeding buffer_target
+    pub surplus_recipient: String,
+    /// Decimals of the stable denom; oracle prices are quoted
+    /// for 6 decimal stablecoins and get normalized with it
+    pub stable_decimals: u8,
+    /// Price move since the last recorded price of a collateral
+    /// that gets logged as price_deviation, defaults to 50%
+    pub price_deviation_threshold: Option<Decimal256>,
+    /// Max # of distinct collaterals a borrower can lock
+    pub max_collaterals_per_borrower: u32,
+    /// Dispatch the epoch operation messages one at a time,
+    /// each only after the previous one succeeded. Defaults to false
+    pub ordered_epoch_operations: Option<bool>,
+    /// Collateral left by a liquidation worth less than this stable amount
+    /// is seized as well, defaults to zero (disabled)
+    pub dust_threshold: Option<Uint256>,
+    /// Max ratio of the liquidation value that can be added by
+    /// seizing dust collaterals, defaults to zero
+    pub dust_tolerance: Option<Decimal256>,
+    /// Contract converting deprecated collaterals to their successor
+    pub collateral_converter: Option<String>,
+    /// Repay marginal shortfalls from the interest buffer instead of
+    /// liquidating when the owner or the risk admin liquidates,
+    /// defaults to false
+    pub soft_liquidation: Option<bool>,
+    /// Max amount of the interest buffer spent on shortfalls per epoch,
+    /// defaults to zero
+    pub buffer_backstop_limit: Option<Uint256>,
+    /// Collaterals priced with a wider oracle confidence spread add
+    /// nothing to borrow limits. Unchecked when not given or not reported
+    pub max_confidence_spread: Option<Decimal256>,
+    /// Market reserves below which part of the distributed interest
+    /// tops them up at epoch operations, defaults to zero
+    pub reserve_target: Option<Uint256>,
+    /// Max portion of the distributed interest diverted to the reserves,
+    /// defaults to zero
+    pub reserve_topup_factor: Option<Decimal256>,
+    /// Abort the epoch operations when any collateral price is older than
+    /// `borrow_price_timeframe` instead of skipping its reward distribution.
+    /// Defaults to false
+    pub epoch_requires_fresh_prices: Option<bool>,
+    /// Oracle queried for collateral prices the primary oracle
+    /// cannot provide within the price timeframe
+    pub fallback_oracle_contract: Option<String>,
+    /// Min ratio of the custody contracts that must distribute their rewards
+    /// for the epoch operations to succeed, defaults to zero (disabled).
+    /// Ordered epoch operations revert on any failure regardless
+    pub min_custody_quorum: Option<Decimal256>,
+    /// Collaterals locked in the current block only count toward
+    /// the borrow limit from the next block on, defaults to false
+    pub same_block_collateral_delay: Option<bool>,
+    /// Value in stable denom of each collateral of a borrower counted
+    /// toward its borrow limit at most. Uncapped when not given
+    pub max_collateral_value_per_borrower: Option<Uint256>,
+    /// Manages collateral LTVs, liquidation premiums, correlation groups
+    /// and the per borrower caps instead of the owner when set
+    pub risk_admin: Option<String>,
+    /// Epoch operations ask at most this many custody contracts to
+    /// distribute rewards per call, the buffer distribution and epoch
+    /// state update only happen with the call reaching the last one.
+    /// Takes precedence over `ordered_epoch_operations` and
+    /// `min_custody_quorum`. All custodies are processed at once when unset
+    pub epoch_custody_batch_size: Option<u32>,
+    /// AMM simulating the ANC received for stable coins, queried
+    /// by `AncBuybackEstimate`
+    pub anc_buyback_amm: Option<String>,
+    /// Min amount of a collateral locked at once, overridden per collateral
+    /// by its whitelist `min_lock_amount`. Defaults to zero
+    pub min_lock_amount: Option<Uint256>,
+    /// Share of the total debt the collateral value must exceed it by
+    /// to be reported solvent by `SolvencyCheck`. Defaults to zero
+    pub solvency_margin: Option<Decimal256>,
+    /// A collateral whose price dropped by more than this ratio since its
+    /// previous observation cannot be liquidated nor back new borrows for
+    /// `price_drop_cooldown` blocks. Unchecked when not given
+    pub max_block_price_drop: Option<Decimal256>,
+    /// # of blocks the price drop pause lasts, defaults to zero
+    pub price_drop_cooldown: Option<u64>,
+    /// # of blocks after a liquidation the owner can report it was done
+    /// on a price corrected since, defaults to zero which disables disputes
+    pub dispute_window: Option<u64>,
+    /// Prices the borrow limits value the collaterals at, spot by default
+    pub valuation_mode: Option<ValuationMode>,
+    /// Page size of `Whitelist` and `AllCollaterals` when no limit is given,
+    /// at most the max page size of 30. Defaults to 10
+    pub default_limit: Option<u32>,
+    /// Max amount of the interest buffer spent on settling bad debt
+    /// per epoch, defaults to zero
+    pub bad_debt_budget: Option<Uint256>,
+    /// # of epochs the deposit rate deciding the buffer distribution is
+    /// averaged over, up to 100. Defaults to 1, the rate of the epoch alone
+    pub deposit_rate_smoothing_epochs: Option<u64>,
+    /// Skip collaterals whose custody transfer fails, e.g. paused tokens,
+    /// when liquidating instead of reverting. Defaults to false
+    pub skip_paused_collaterals: Option<bool>,
+    /// Max change of the `max_ltv` of a collateral within an epoch, summed
+    /// over the whitelist updates. Unchecked when not given
+    pub max_ltv_delta_per_epoch: Option<Decimal256>,
+    /// Max stable amount an epoch sends to the collector to purchase ANC,
+    /// the rest of the purchase portion stays in the interest buffer
+    pub max_anc_purchase_per_epoch: Option<Uint256>,
 }
 
+/// Price a collateral is valued at in the borrow limit
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+pub enum ValuationMode {
+    /// Latest fed price
+    Spot,
+    /// Time weighted average price over the last `window` seconds
+    Twap { window: u64 },
+    /// Lower of the spot price and the TWAP over the last `window` seconds,
+    /// so an upward spike ahead of a borrow does not raise the limit
+    LowerOfBoth { window: u64 },
+}
+
+/// How a collateral is moved in and out of positions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    /// CW20 token deposited to the custody contracts of the collateral
+    Cw20,
+    /// Native token held by the overseer, locked with the attached funds.
+    /// The custody contract of the collateral is not used
+    Native { denom: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     ////////////////////
     /// Owner operations
@@ -54,22 +191,154 @@ pub enum ExecuteMsg {
         buffer_distribution_factor: Option<Decimal256>,
         anc_purchase_factor: Option<Decimal256>,
         epoch_period: Option<u64>,
-        price_timeframe: Option<u64>,
+        borrow_price_timeframe: Option<u64>,
+        liquidation_price_timeframe: Option<u64>,
+        /// `null` removes the ANC price cap
+        #[serde(default, deserialize_with = "crate::common::deserialize_some")]
+        max_anc_price: Option<Option<Decimal256>>,
+        buffer_target: Option<Uint256>,
+        surplus_recipient: Option<String>,
+        price_deviation_threshold: Option<Decimal256>,
+        max_collaterals_per_borrower: Option<u32>,
+        ordered_epoch_operations: Option<bool>,
+        dust_threshold: Option<Uint256>,
+        dust_tolerance: Option<Decimal256>,
+        collateral_converter: Option<String>,
+        soft_liquidation: Option<bool>,
+        buffer_backstop_limit: Option<Uint256>,
+        max_confidence_spread: Option<Decimal256>,
+        reserve_target: Option<Uint256>,
+        reserve_topup_factor: Option<Decimal256>,
+        epoch_requires_fresh_prices: Option<bool>,
+        fallback_oracle_contract: Option<String>,
+        min_custody_quorum: Option<Decimal256>,
+        same_block_collateral_delay: Option<bool>,
+        max_collateral_value_per_borrower: Option<Uint256>,
+        risk_admin: Option<String>,
+        epoch_custody_batch_size: Option<u32>,
+        anc_buyback_amm: Option<String>,
+        min_lock_amount: Option<Uint256>,
+        solvency_margin: Option<Decimal256>,
+        max_block_price_drop: Option<Decimal256>,
+        price_drop_cooldown: Option<u64>,
+        dispute_window: Option<u64>,
+        valuation_mode: Option<ValuationMode>,
+        default_limit: Option<u32>,
+        bad_debt_budget: Option<Uint256>,
+        deposit_rate_smoothing_epochs: Option<u64>,
+        skip_paused_collaterals: Option<bool>,
+        max_ltv_delta_per_epoch: Option<Decimal256>,
+        max_anc_purchase_per_epoch: Option<Uint256>,
+        stable_decimals: Option<u8>,
+    },
+
+    /// Switch to a new oracle contract, only if it prices every
+    /// whitelisted collateral within the borrow price timeframe
+    RotateOracle {
+        new_oracle: String,
     },
 
+    /// Halt every `LiquidateCollateral` while `frozen`, e.g. when oracle
+    /// prices are suspected to be manipulated. Borrows and repays go on
+    SetLiquidationFreeze {
+        frozen: bool,
+    },
+    /// Transfer the whole balance of an asset sent to this contract by mistake.
+    /// Whitelisted collaterals and the stable denom cannot be swept
+    SweepTokens { asset: AssetInfo, recipient: String },
+
     /// Create new custody contract for the given collateral token
     Whitelist {
-        name: String,             // bAsset name
-        symbol: String,           // bAsset symbol
-        collateral_token: String, // bAsset token contract
-        custody_contract: String, // bAsset custody contract
-        max_ltv: Decimal256,      // Loan To Value ratio
+        name: String,                            // bAsset name
+        symbol: String,                          // bAsset symbol
+        collateral_token: String,                // bAsset token contract
+        custody_contract: String,                // bAsset custody contract
+        max_ltv: Decimal256,                     // Loan To Value ratio
+        quote_denom: Option<String>,             // Oracle quote asset, stable_denom if not given
+        liquidation_premium: Option<Decimal256>, // Overrides the liquidation contract max premium rate
+        correlation_group: Option<String>,       // Asset class the collateral is grouped in
+        min_lock_amount: Option<Uint256>,        // Overrides the global min lock amount
+        borrow_enabled_from: Option<u64>,        // Height the collateral backs borrows from
+        borrow_disabled_from: Option<u64>,       // Height the collateral stops backing borrows
+        liquidation_contract: Option<String>,    // Overrides the global liquidation contract
+        decimals: Option<u8>,                    // Token decimals, 6 by default
+        exchange_rate_source: Option<String>,    // Values the collateral in its underlying asset
+        borrow_rate_premium: Option<Decimal256>, // Added to the borrow rate of the debt it backs
+        token_type: Option<TokenType>,           // Cw20 by default
+    },
+    /// Whitelist a collateral and seed its oracle price in the same transaction.
+    /// The overseer must own the oracle contract: it registers itself as the
+    /// feeder of the collateral, feeds `initial_price` in the oracle base asset
+    /// and then hands the feed over to `feeder`
+    WhitelistWithPrice {
+        name: String,
+        symbol: String,
+        collateral_token: String,
+        custody_contract: String,
+        max_ltv: Decimal256,
+        quote_denom: Option<String>,
+        liquidation_premium: Option<Decimal256>,
+        correlation_group: Option<String>,
+        min_lock_amount: Option<Uint256>,
+        borrow_enabled_from: Option<u64>,
+        borrow_disabled_from: Option<u64>,
+        liquidation_contract: Option<String>,
+        decimals: Option<u8>,
+        exchange_rate_source: Option<String>,
+        borrow_rate_premium: Option<Decimal256>,
+        token_type: Option<TokenType>,
+        initial_price: Decimal256,
+        feeder: String,
     },
     /// Update registered whitelist info
     UpdateWhitelist {
-        collateral_token: String,         // bAsset token contract
-        custody_contract: Option<String>, // bAsset custody contract
-        max_ltv: Option<Decimal256>,      // Loan To Value ratio
+        collateral_token: String,                // bAsset token contract
+        custody_contract: Option<String>,        // bAsset custody contract
+        max_ltv: Option<Decimal256>,             // Loan To Value ratio
+        quote_denom: Option<String>,             // Oracle quote asset
+        liquidation_premium: Option<Decimal256>, // Liquidation premium rate of the collateral
+        correlation_group: Option<String>,       // Asset class the collateral is grouped in
+        min_lock_amount: Option<Uint256>,        // Overrides the global min lock amount
+        borrow_enabled_from: Option<u64>,        // Height the collateral backs borrows from
+        borrow_disabled_from: Option<u64>,       // Height the collateral stops backing borrows
+        liquidation_contract: Option<String>,    // Overrides the global liquidation contract
+        decimals: Option<u8>,                    // Token decimals, 6 by default
+        exchange_rate_source: Option<String>,    // Values the collateral in its underlying asset
+        borrow_rate_premium: Option<Decimal256>, // Added to the borrow rate of the debt it backs
+    },
+    /// Apply several whitelist updates at once, none if any is invalid
+    UpdateWhitelistBatch { updates: Vec<UpdateWhitelistEntry> },
+    /// Delist a collateral no borrower has locked anymore
+    /// and whose custody contracts hold no deposits
+    RemoveWhitelist {
+        collateral_token: String, // bAsset token contract
+    },
+    /// Register an additional custody contract for the given collateral token,
+    /// or update the weight of an already registered one. Collateral locks,
+    /// unlocks and liquidations are split across custody contracts by weight
+    RegisterCustodyContract {
+        collateral_token: String, // bAsset token contract
+        custody_contract: String, // bAsset custody contract
+        weight: Decimal256,
+    },
+
+    /// Add the sent stable coins to the fairness fund,
+    /// which is kept apart from the interest buffer
+    FundFairnessFund {},
+    /// Report that the liquidation was done on a price corrected since,
+    /// reserving `compensation` of the fairness fund for the borrower.
+    /// Only allowed within the dispute window of the liquidation
+    ReportPriceCorrection {
+        liquidation_id: u64,
+        compensation: Uint256,
+    },
+
+    /// Deposit `amount` of the idle interest buffer into the market,
+    /// the epoch operations redeem the aTerra position before distributing,
+    /// as far as the market liquidity allows. Not allowed once the next
+    /// epoch is due
+    DepositInterestBuffer {
+        amount: Uint256,
     },
 
     /// Claims all staking rewards from the bAsset contracts
@@ -77,51 +346,409 @@ pub enum ExecuteMsg {
     /// 1. Distribute interest buffers to depositors
     /// 2. Invoke [Custody] DistributeRewards
     /// 3. Update epoch state
+    ///
+    /// With `epoch_custody_batch_size`, call it again until the
+    /// epoch is no longer in progress
     ExecuteEpochOperations {},
+    /// Drop the progress of an epoch executed in several calls,
+    /// the next call starts the epoch over. Owner only
+    ResetEpochProgress {},
     UpdateEpochState {
         interest_buffer: Uint256,
         distributed_interest: Uint256,
+        reserve_topup: Uint256,
+    },
+    /// Ask the custody contracts holding the protocol-owned collaterals,
+    /// deployed from the market reserves, to distribute their rewards
+    /// to the interest buffer
+    HarvestProtocolYield {},
+    /// Counts the protocol share of the rewards the last custody contract
+    /// sent, then asks the next pending one to distribute
+    HarvestProtocolYieldHook {
+        prev_interest_buffer: Uint256,
+        protocol_share: Decimal256,
+        pending_custodies: Vec<(String, Decimal256)>,
+        harvested_amount: Uint256,
+    },
+
+    ////////////////////
+    /// Market operations
+    ////////////////////
+
+    /// Unlock the borrower's collaterals by the repaid ratio of the loan,
+    /// which keeps the position at its pre-repayment LTV
+    UnlockCollateralFromRepay {
+        borrower: String,
+        prev_loan_amount: Uint256,
+        repay_amount: Uint256,
+        sub_account: Option<String>,
+    },
+
+    /// Lock collaterals the market deposited for the borrower
+    /// into its default position, sent by leverage adjustments
+    LockCollateralFor {
+        borrower: String,
+        collaterals: TokensHuman, // <(Collateral Token, Amount)>
+    },
+
+    ////////////////////
+    /// Liquidation operations
+    ////////////////////
+
+    /// Repay the loan of the borrower with the attached stable funds,
+    /// the proceeds of its liquidation auction
+    RepayAuctionProceeds {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+
+    /// Lock the collaterals of an expired liquidation auction back into
+    /// the position, cw20 collaterals are deposited to their custody
+    /// contracts beforehand and native collaterals are attached
+    ReturnAuctionCollateral {
+        borrower: String,
+        sub_account: Option<String>,
+        collaterals: Vec<(String, String, Uint256)>, // <(Collateral Token, Custody Contract, Amount)>
     },
 
     ////////////////////
     /// User operations
     ////////////////////
+    /// Each `sub_account` of an address is a separate position with
+    /// its own borrow limit, `None` is the default position
     LockCollateral {
         collaterals: TokensHuman, // <(Collateral Token, Amount)>
+        sub_account: Option<String>,
     },
     UnlockCollateral {
         collaterals: TokensHuman, // <(Collateral Token, Amount)>
+        sub_account: Option<String>,
+    },
+    /// Unlock the same ratio of every locked collateral so that
+    /// loan_amount / collateral_value rises to `target_ltv`, never above it.
+    /// The position must stay within its borrow limit
+    UnlockToTargetLtv {
+        target_ltv: Decimal256,
+        sub_account: Option<String>,
     },
+    /// Repay the whole loan of the default position from the attached funds
+    /// and send all of its collaterals to `recipient` instead of the sender
+    CloseAndWithdraw {
+        recipient: String,
+    },
+    CloseAndWithdrawHook {
+        borrower: String,
+        recipient: String,
+    },
+    /// Move locked collaterals between two positions of the sender,
+    /// the source position must stay within its borrow limit
+    ReallocateCollateral {
+        collaterals: TokensHuman, // <(Collateral Token, Amount)>
+        from_sub_account: Option<String>,
+        to_sub_account: Option<String>,
+    },
+    /// Move `collaterals` and `debt_fraction` of the loan of a position of
+    /// the sender into `to_sub_account`, which must be an empty position,
+    /// to manage it on its own. Both positions must stay within their borrow limits
+    SplitPosition {
+        collaterals: TokensHuman, // <(Collateral Token, Amount)>
+        debt_fraction: Decimal256,
+        from_sub_account: Option<String>,
+        to_sub_account: String,
+    },
+
+    /// Replace the whole locked amount of `from_token` in the position
+    /// by `to_token` converted through the collateral converter,
+    /// the position must stay within its borrow limit
+    MigratePosition {
+        from_token: String,
+        to_token: String,
+        sub_account: Option<String>,
+    },
+
+    /// Offer the default position of the sender, its locked collaterals
+    /// and loan, to `recipient`, who takes it over with `AcceptPosition`
+    /// before the `expires_at` block height.
+    /// A new offer replaces the pending one
+    TransferPosition { recipient: String, expires_at: u64 },
+
+    /// Withdraw the pending position offer of the sender
+    CancelPositionTransfer {},
+
+    /// Take over the default position offered by `borrower`,
+    /// the combined position must stay within its borrow limit
+    AcceptPosition { borrower: String },
+
+    /// Allow `keeper` to liquidate the positions of the sender once, even
+    /// safe ones, as long as its bonus stays within `max_bonus`.
+    /// A safe position is liquidated as if its borrow limit were zero.
+    /// A new delegation replaces the previous one
+    DelegateLiquidation { keeper: String, max_bonus: Uint256 },
+
+    /// Withdraw the liquidation delegation of the sender
+    RevokeLiquidationDelegation {},
+
+    /// Claim the compensation reserved for a disputed liquidation
+    /// of the sender
+    ClaimCompensation { liquidation_id: u64 },
+
+    /// Claim the holder rewards of the sender from every custody contract
+    /// holding some and send the stable coins back in a single transfer
+    ClaimAllRewards {},
+    ClaimAllRewardsHook { borrower: String, prev_balance: Uint256 },
+
+    /// Claim the holder rewards of the sender like `ClaimAllRewards` and
+    /// repay the loan of its default position with them, the stable coins
+    /// above the loan are refunded
+    ClaimRewardsAndRepay {},
+    ClaimRewardsAndRepayHook { borrower: String, prev_balance: Uint256 },
 
     /////////////////////////////
     /// Permissionless operations
     /////////////////////////////
-    LiquidateCollateral { borrower: String },
+    LiquidateCollateral {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// Repay the debt left on a position of a borrower whose collaterals
+    /// were fully seized from the interest buffer, within the bad debt
+    /// budget of the epoch. Owner or risk admin only
+    SettleBadDebt {
+        borrower: String,
+        sub_account: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
-    EpochState {},
+    EpochState {
+        /// Decimal places of the reported deposit rate, full precision by default
+        precision: Option<u32>,
+    },
+    /// Limits above the max page size of 30 are clamped to it,
+    /// no limit returns a page of `default_limit` elems
     Whitelist {
         collateral_token: Option<String>,
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    WhitelistEntry {
+        collateral_token: String,
+    },
     Collaterals {
         borrower: String,
+        sub_account: Option<String>,
+        /// Return canonical (hex encoded) addresses instead of human ones
+        canonical: Option<bool>,
     },
+    /// Paginated like `Whitelist` over borrowers, their sub-accounts
+    /// are listed after their default position
     AllCollaterals {
         start_after: Option<String>,
         limit: Option<u32>,
+        /// Return canonical (hex encoded) addresses instead of human ones
+        canonical: Option<bool>,
     },
     BorrowLimit {
         borrower: String,
         block_time: Option<u64>,
+        sub_account: Option<String>,
+        /// Oracle price the collaterals are valued with, spot by default
+        price_mode: Option<PriceMode>,
+        /// Asset the borrow limit is denominated in, converted with its
+        /// oracle price against the stable denom. The stable denom by default
+        quote_denom: Option<String>,
+    },
+    /// Total loan amount not backed by collateral value at current prices.
+    /// Iterates every position of the market contract, sub-accounts
+    /// included, so this is an expensive read meant for monitoring only
+    BadDebt {},
+    /// Every external contract the overseer trusts
+    TrustedContracts {},
+    /// Stable value of each whitelisted collateral locked by all borrowers
+    /// compared to its borrow cap
+    CollateralCapUtilization {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Profit a liquidator would net by liquidating the borrower right now,
+    /// zero when the loan is safely collateralized
+    LiquidationBonus {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// Largest repayment a `LiquidateCollateral` of the borrower would make
+    /// right now, as sized by the liquidation model, with the collaterals it
+    /// would seize. Zero when the loan is safely collateralized
+    MaxLiquidatable {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// Collaterals a `LiquidateCollateral` of the borrower would seize right
+    /// now, in the order it seizes them, with the repayment each covers and
+    /// whether the position is cured or left with bad debt afterwards
+    LiquidationWaterfall {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// Positions of a page of borrowers, with their sub-accounts, that would
+    /// be liquidatable if every collateral price fell by `price_drop`, with
+    /// the repayment and the collateral value their liquidations would seize
+    StressTest {
+        price_drop: Decimal256,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Most recent liquidations, oldest first;
+    /// only a bounded number of records is kept
+    LiquidationHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Stable amounts spent on ANC purchases and distributed
+    /// to the market from the interest buffer since instantiation
+    EpochTotals {},
+    /// Locked value and borrow capacity of the collaterals aggregated by
+    /// `correlation_group`, untagged collaterals are grouped under `None`.
+    /// Reads every whitelist entry with its total locked counter and
+    /// queries one or two oracle prices per collateral
+    CategoryExposure {},
+    /// Blocks left until epoch operations can be executed again,
+    /// zero once they are executable
+    NextEpoch {},
+    /// Deposit rate realized by the most recent epochs against the target
+    /// deposit rate of each epoch, newest first. Only a bounded number
+    /// of epochs is kept
+    EpochRatePerformance {
+        limit: Option<u32>,
+    },
+    /// Interest buffer inflow of the most recent epochs and the amounts
+    /// their epoch operations distributed and spent on ANC, newest first.
+    /// Only a bounded number of epochs is kept
+    BufferFlow {
+        limit: Option<u32>,
+    },
+    /// Estimated deposit rate net of the dilution caused by ANC emissions.
+    /// The dilution rate is the value of the ANC the market emits per block,
+    /// at `anc_price` or the oracle price when not given, divided by the
+    /// stable value of the deposits
+    NetDepositRate {
+        anc_price: Option<Decimal256>,
+    },
+    /// Named positions of the owner holding collaterals, ordered by name
+    SubAccounts {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Additional stable amount the borrower can borrow while keeping
+    /// the loan at or below `safety_ltv` of the collateral value.
+    /// `safety_ltv` cannot exceed the max ltv of any locked collateral
+    SafeBorrowAmount {
+        borrower: String,
+        safety_ltv: Decimal256,
+        sub_account: Option<String>,
+    },
+    /// LTV the locked collateral counts at in the borrow limit, after the
+    /// same block lock delay, eligibility, confidence and value cap checks,
+    /// with the reduction of each applicable adjustment
+    EffectiveLtv {
+        borrower: String,
+        collateral_token: String,
+        sub_account: Option<String>,
+    },
+    /// Compares the total locked counters against the collaterals of a page
+    /// of borrowers with a default position, their sub-accounts included.
+    /// Summing `page_locked` over all pages gives the full recompute
+    ConsistencyCheck {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Whitelisted collaterals whose oracle price is older than
+    /// `borrow_price_timeframe`, with the age of the oldest price involved
+    StaleCollaterals {},
+    /// Snapshot of every live parameter needed to simulate the protocol
+    /// off-chain: the config, all whitelist entries with their current
+    /// prices, the market state and the interest model config. Reads the
+    /// whole whitelist and queries the oracle, market and interest model,
+    /// so it is a heavy read meant for off-chain analysis only
+    Simulation {},
+    /// Stablecoin borrowing capacity each whitelisted collateral can still
+    /// support at current prices, from its total locked counter. Reads
+    /// every whitelist entry and queries the oracle per collateral
+    GlobalBorrowableByCollateral {},
+    /// Collaterals, borrow limit and loan of the position with its LTV
+    /// and collateral liquidation prices, all read at the same height.
+    /// Queries the oracle per collateral and the market once
+    Position {
+        borrower: String,
+        sub_account: Option<String>,
+    },
+    /// LTV-weighted value of each collateral of the default position with
+    /// the LTV increase a 1% drop of its price alone would cause,
+    /// linearized from the partial derivative of the LTV
+    RiskContribution {
+        borrower: String,
+    },
+    /// ANC the `anc_buyback_amm` returns for `amount` of the interest
+    /// buffer, with the implied average price and its slippage over
+    /// the oracle spot price. Read only, executes no buyback
+    AncBuybackEstimate {
+        amount: Uint256,
+    },
+    /// Whether the value of all locked collaterals at current prices
+    /// covers the total debt of the market plus `solvency_margin`.
+    /// Reads every whitelist entry with its total locked counter and
+    /// queries the oracle per collateral and the market once
+    SolvencyCheck {},
+    /// Stable amounts of the fairness fund still available
+    /// and reserved for unclaimed compensations
+    FairnessFund {},
+    /// Native denom and tokens a user of the protocol may need allowances for,
+    /// gathered from the whitelist and the market and collector configs
+    AssetRegistry {},
+    /// Interest buffer deposited into the market and its current value
+    InterestBufferDeposit {},
+    /// Chunk of the collaterals of every position, for a page of borrowers
+    /// with a default position and their sub-accounts, along with the config,
+    /// the whitelist and the total locked counters. Concatenating all chunks
+    /// rebuilds the full collateral map for off-chain verification
+    Snapshot {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Privileged actions the address may perform given its roles,
+    /// decided by the same checks as the actions themselves
+    Authorization {
+        address: String,
     },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct UpdateWhitelistEntry {
+    pub collateral_token: String,                // bAsset token contract
+    pub custody_contract: Option<String>,        // bAsset custody contract
+    pub max_ltv: Option<Decimal256>,             // Loan To Value ratio
+    pub quote_denom: Option<String>,             // Oracle quote asset
+    pub liquidation_premium: Option<Decimal256>, // Liquidation premium rate of the collateral
+    pub correlation_group: Option<String>,       // Asset class the collateral is grouped in
+    pub min_lock_amount: Option<Uint256>,        // Overrides the global min lock amount
+    pub borrow_enabled_from: Option<u64>,        // Height the collateral backs borrows from
+    pub borrow_disabled_from: Option<u64>,       // Height the collateral stops backing borrows
+    pub liquidation_contract: Option<String>,    // Overrides the global liquidation contract
+    pub decimals: Option<u8>,                    // Token decimals, 6 by default
+    pub exchange_rate_source: Option<String>,    // Values the collateral in its underlying asset
+    pub borrow_rate_premium: Option<Decimal256>, // Added to the borrow rate of the debt it backs
+}
+
+/// We currently take no arguments for migrations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {}
+
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
@@ -136,7 +763,44 @@ pub struct ConfigResponse {
     pub anc_purchase_factor: Decimal256,
     pub stable_denom: String,
     pub epoch_period: u64,
-    pub price_timeframe: u64,
+    pub borrow_price_timeframe: u64,
+    pub liquidation_price_timeframe: u64,
+    pub max_anc_price: Option<Decimal256>,
+    pub buffer_target: Uint256,
+    pub surplus_recipient: String,
+    pub stable_decimals: u8,
+    pub price_deviation_threshold: Decimal256,
+    pub max_collaterals_per_borrower: u32,
+    pub ordered_epoch_operations: bool,
+    pub dust_threshold: Uint256,
+    pub dust_tolerance: Decimal256,
+    pub collateral_converter: Option<String>,
+    pub soft_liquidation: bool,
+    pub buffer_backstop_limit: Uint256,
+    pub max_confidence_spread: Option<Decimal256>,
+    pub reserve_target: Uint256,
+    pub reserve_topup_factor: Decimal256,
+    pub epoch_requires_fresh_prices: bool,
+    pub fallback_oracle_contract: Option<String>,
+    pub min_custody_quorum: Decimal256,
+    pub same_block_collateral_delay: bool,
+    pub max_collateral_value_per_borrower: Option<Uint256>,
+    pub risk_admin: Option<String>,
+    pub epoch_custody_batch_size: Option<u32>,
+    pub anc_buyback_amm: Option<String>,
+    pub min_lock_amount: Uint256,
+    pub solvency_margin: Decimal256,
+    pub max_block_price_drop: Option<Decimal256>,
+    pub price_drop_cooldown: u64,
+    pub dispute_window: u64,
+    pub valuation_mode: ValuationMode,
+    pub default_limit: u32,
+    pub bad_debt_budget: Uint256,
+    pub deposit_rate_smoothing_epochs: u64,
+    pub skip_paused_collaterals: bool,
+    pub max_ltv_delta_per_epoch: Option<Decimal256>,
+    pub liquidation_frozen: bool,
+    pub max_anc_purchase_per_epoch: Option<Uint256>,
 }
 
 // We define a custom struct for each query response
@@ -146,7 +810,27 @@ pub struct WhitelistResponseElem {
     pub symbol: String,
     pub max_ltv: Decimal256,
     pub custody_contract: String,
+    pub custody_contracts: Vec<(String, Decimal256)>, // <(Custody Contract, Weight)>
     pub collateral_token: String,
+    pub quote_denom: Option<String>,
+    pub liquidation_premium: Option<Decimal256>,
+    pub correlation_group: Option<String>,
+    /// Overrides the global `min_lock_amount` for the collateral
+    pub min_lock_amount: Option<Uint256>,
+    /// The collateral backs borrows and can be locked from this height
+    pub borrow_enabled_from: Option<u64>,
+    /// The collateral no longer backs borrows nor can be locked from this height
+    pub borrow_disabled_from: Option<u64>,
+    /// Liquidates the collateral instead of the global `liquidation_contract`
+    pub liquidation_contract: Option<String>,
+    /// Token decimals, amounts are scaled to 6 decimals before oracle prices apply
+    pub decimals: u8,
+    /// Converts collateral token amounts into the underlying asset
+    /// the oracle prices, the token amount is used as is when not set
+    pub exchange_rate_source: Option<String>,
+    /// Per-block rate added to the borrow rate of the debt the collateral backs
+    pub borrow_rate_premium: Option<Decimal256>,
+    pub token_type: TokenType,
 }
 
 // We define a custom struct for each query response
@@ -159,6 +843,7 @@ pub struct WhitelistResponse {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct CollateralsResponse {
     pub borrower: String,
+    pub sub_account: Option<String>,
     pub collaterals: TokensHuman, // <(Collateral Token, Amount)>
 }
 
@@ -172,4 +857,437 @@ pub struct AllCollateralsResponse {
 pub struct BorrowLimitResponse {
     pub borrower: String,
     pub borrow_limit: Uint256,
+    /// Borrow limit used by liquidations over the loan amount, the position
+    /// is liquidatable below one. `Decimal256::MAX` without a loan
+    pub health_factor: Decimal256,
+    /// Borrow rate premium of the collaterals weighted by their value,
+    /// the market adds it to the per-block borrow rate of the loan
+    pub borrow_rate_premium: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BadDebtResponse {
+    pub bad_debt: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TrustedContractsResponse {
+    pub oracle_contract: String,
+    pub market_contract: String,
+    pub liquidation_contract: String,
+    pub collector_contract: String,
+    pub custody_contracts: Vec<String>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollateralCapUtilizationResponse {
+    pub elems: Vec<CollateralCapUtilizationElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollateralCapUtilizationElem {
+    pub collateral_token: String,
+    /// `None` means the collateral is not capped
+    pub cap: Option<Uint256>,
+    pub used: Uint256,
+    pub utilization_ratio: Option<Decimal256>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationBonusResponse {
+    pub borrower: String,
+    /// Stable value of the collaterals that would be liquidated
+    pub seized_value: Uint256,
+    /// Amount repaid to the market
+    pub repay_amount: Uint256,
+    /// Bid fee charged by the liquidation contract
+    pub protocol_fee: Uint256,
+    pub bonus: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MaxLiquidatableResponse {
+    pub borrower: String,
+    /// Amount repaid to the market
+    pub repay_amount: Uint256,
+    /// Bid fee charged by the liquidation contract
+    pub bid_fee: Uint256,
+    pub collaterals: TokensHuman,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationWaterfallResponse {
+    pub borrower: String,
+    pub loan_amount: Uint256,
+    pub steps: Vec<LiquidationWaterfallElem>,
+    /// Amount repaid to the market by all the steps
+    pub repay_amount: Uint256,
+    /// The remaining loan is within the borrow limit of the remaining collaterals
+    pub cures_shortfall: bool,
+    /// Loan left unbacked once every collateral is seized
+    pub residual_bad_debt: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationWaterfallElem {
+    pub collateral_token: String,
+    pub amount: Uint256,
+    /// Stable value of the seized amount
+    pub value: Uint256,
+    /// Amount repaid to the market, net of the premium and bid fee
+    pub repay_amount: Uint256,
+    /// Loan left after this step
+    pub remaining_loan: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StressTestResponse {
+    pub price_drop: Decimal256,
+    /// Positions valued in the page
+    pub positions: u32,
+    pub liquidatable_positions: u32,
+    /// Loans of the liquidatable positions
+    pub loan_amount: Uint256,
+    /// Amount the liquidations would repay to the market
+    pub repay_amount: Uint256,
+    /// Value of the collaterals the liquidations would seize at the dropped prices
+    pub seized_value: Uint256,
+    /// Start the next chunk after this borrower
+    pub last_borrower: Option<String>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationHistoryResponse {
+    pub records: Vec<LiquidationRecordResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationRecordResponse {
+    pub id: u64,
+    pub borrower: String,
+    pub liquidator: String,
+    /// Expected repay amount at the premium rate of each collateral
+    pub repaid: Uint256,
+    pub seized_tokens: TokensHuman,
+    pub block_height: u64,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochTotalsResponse {
+    pub total_anc_purchased: Uint256,
+    pub total_buffer_distributed: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochRatePerformanceResponse {
+    pub epochs: Vec<EpochRatePerformanceElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochRatePerformanceElem {
+    pub height: u64,
+    pub realized_deposit_rate: Decimal256,
+    pub target_deposit_rate: Decimal256,
+    /// Distance between the realized and the target deposit rate
+    pub deviation: Decimal256,
+    pub below_target: bool,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BufferFlowResponse {
+    pub epochs: Vec<BufferFlowElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BufferFlowElem {
+    pub height: u64,
+    /// Interest buffer accrued since the previous epoch
+    pub inflow: Uint256,
+    /// Interest buffer sent to the market for the depositors
+    pub distributed: Uint256,
+    pub anc_purchased: Uint256,
+    /// Interest buffer left after the epoch operations
+    pub ending_balance: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NextEpochResponse {
+    pub last_epoch_height: u64,
+    pub epoch_period: u64,
+    pub blocks_until_next: u64,
+    /// Epoch operations were started by a batched call
+    /// and are not finalized yet
+    pub in_progress: bool,
+}
+
+// We define a custom struct for each query response
+/// Estimated figures, all rates are per block
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NetDepositRateResponse {
+    pub deposit_rate: Decimal256,
+    pub anc_emission_rate: Decimal256,
+    pub anc_price: Decimal256,
+    /// aTerra supply valued at the exchange rate
+    pub total_deposits: Uint256,
+    pub dilution_rate: Decimal256,
+    pub net_deposit_rate: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SubAccountResponse {
+    pub sub_account: String,
+    pub collateral_value: Uint256,
+    pub loan_amount: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SubAccountsResponse {
+    pub owner: String,
+    pub sub_accounts: Vec<SubAccountResponse>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EffectiveLtvResponse {
+    pub borrower: String,
+    pub collateral_token: String,
+    pub max_ltv: Decimal256,
+    pub effective_ltv: Decimal256,
+    /// Adjustments in the order they apply, their reductions
+    /// sum to `max_ltv - effective_ltv`
+    pub adjustments: Vec<LtvAdjustmentElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LtvAdjustmentElem {
+    pub adjustment: String,
+    pub ltv_reduction: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SafeBorrowAmountResponse {
+    pub borrower: String,
+    pub safe_borrow_limit: Uint256,
+    pub loan_amount: Uint256,
+    pub safe_borrow_amount: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionResponse {
+    pub borrower: String,
+    pub collaterals: Vec<PositionCollateralElem>,
+    pub collateral_value: Uint256,
+    pub borrow_limit: Uint256,
+    pub loan_amount: Uint256,
+    /// Loan amount over the collateral value, zero without collaterals
+    pub ltv: Decimal256,
+    /// Borrow limit used by liquidations over the loan amount, the position
+    /// is liquidatable below one. `Decimal256::MAX` without a loan
+    pub health_factor: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RiskContributionResponse {
+    pub borrower: String,
+    pub ltv: Decimal256,
+    pub contributions: Vec<RiskContributionElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RiskContributionElem {
+    pub collateral_token: String,
+    pub value: Uint256,
+    /// Value times the max LTV, what the collateral adds to the borrow limit
+    pub weighted_value: Uint256,
+    /// LTV increase caused by a 1% price drop of the collateral
+    pub sensitivity: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionCollateralElem {
+    pub collateral_token: String,
+    pub amount: Uint256,
+    pub price: Decimal256,
+    pub value: Uint256,
+    /// Price at which the position becomes liquidatable while the other
+    /// collateral prices stay unchanged. `None` without a loan or when the
+    /// other collaterals alone keep the position safe
+    pub liquidation_price: Option<Decimal256>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AncBuybackEstimateResponse {
+    pub amount: Uint256,
+    pub anc_amount: Uint256,
+    /// Stable coins paid per ANC received
+    pub average_price: Decimal256,
+    pub spot_price: Decimal256,
+    /// Ratio the average price exceeds the spot price by,
+    /// zero when it does not
+    pub slippage: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolvencyCheckResponse {
+    pub solvent: bool,
+    pub total_collateral_value: Uint256,
+    pub total_debt: Uint256,
+    /// Total collateral value over the total debt, none without debt
+    pub collateralization_ratio: Option<Decimal256>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConsistencyCheckResponse {
+    pub elems: Vec<ConsistencyCheckElem>,
+    /// Collaterals whose total locked counter is below
+    /// the amount locked by the page alone
+    pub discrepancies: Vec<String>,
+    /// Start the next page after this borrower
+    pub last_borrower: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConsistencyCheckElem {
+    pub collateral_token: String,
+    pub total_locked: Uint256,
+    pub page_locked: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CategoryExposureResponse {
+    pub categories: Vec<CategoryExposureElem>,
+    pub total_borrow_capacity: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CategoryExposureElem {
+    pub correlation_group: Option<String>,
+    /// Stable value of the collaterals locked in the group
+    pub locked_value: Uint256,
+    /// Locked value weighted by the max LTV of each collateral
+    pub borrow_capacity: Uint256,
+    /// Share of the protocol-wide borrow capacity
+    pub capacity_share: Decimal256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StaleCollateralsResponse {
+    pub elems: Vec<StaleCollateralElem>,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub config: ConfigResponse,
+    pub whitelist: Vec<WhitelistResponseElem>,
+    pub collateral_prices: Vec<(String, Decimal256)>, // <(Collateral Token, Price)>
+    pub market_state: MarketStateResponse,
+    pub interest_model_config: InterestModelConfigResponse,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GlobalBorrowableByCollateralResponse {
+    pub elems: Vec<GlobalBorrowableElem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GlobalBorrowableElem {
+    pub collateral_token: String,
+    /// Stable value of the collateral locked by all borrowers
+    pub locked_value: Uint256,
+    /// Locked value weighted by the max LTV of the collateral
+    pub borrow_capacity: Uint256,
+    /// Borrowing capacity left under the collateral cap,
+    /// `None` means the collateral is not capped
+    pub remaining_capacity: Option<Uint256>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StaleCollateralElem {
+    pub collateral_token: String,
+    /// Oldest update time of the prices crossed into the stable value
+    pub last_updated: u64,
+    /// Seconds elapsed since `last_updated`
+    pub price_age: u64,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FairnessFundResponse {
+    pub available: Uint256,
+    pub reserved: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InterestBufferDepositResponse {
+    pub deposited_amount: Uint256,
+    pub aterra_amount: Uint256,
+    pub value: Uint256,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SnapshotResponse {
+    pub config: ConfigResponse,
+    pub whitelist: Vec<WhitelistResponseElem>,
+    pub total_locked: TokensHuman, // <(Collateral Token, Amount)>
+    pub positions: Vec<SnapshotPositionElem>,
+    /// Start the next chunk after this borrower
+    pub last_borrower: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SnapshotPositionElem {
+    pub borrower: String,
+    pub sub_account: Option<String>,
+    pub collaterals: TokensHuman, // <(Collateral Token, Amount)>
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AuthorizationResponse {
+    pub address: String,
+    /// Owner or risk admin, each updating its own config fields
+    pub can_update_config: bool,
+    /// Owner or risk admin, each updating its own whitelist fields
+    pub can_update_whitelist: bool,
+    /// Disabling borrows against a collateral is up to the owner
+    pub can_pause: bool,
+    /// Liquidating positions below their borrow limit is open to anyone
+    pub can_liquidate: bool,
+    /// Epoch operations can be executed by anyone once due
+    pub can_run_epoch: bool,
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetRegistryResponse {
+    pub stable_denom: String,
+    pub collateral_tokens: Vec<String>,
+    pub aterra_contract: String,
+    pub anc_token: String,
 }
@@ -9,7 +9,7 @@ use cosmwasm_std::{
 use cw20::{Cw20QueryMsg, TokenInfoResponse};
 use terra_cosmwasm::TerraQuerier;
 
-use crate::oracle::{PriceResponse, QueryMsg as OracleQueryMsg};
+use crate::oracle::{PriceMode, PriceResponse, QueryMsg as OracleQueryMsg};
 
 pub fn query_all_balances(deps: Deps, account_addr: Addr) -> StdResult<Vec<Coin>> {
     // load price form the oracle
@@ -104,10 +104,37 @@ pub fn query_price(
     quote: String,
     time_contraints: Option<TimeConstraints>,
 ) -> StdResult<PriceResponse> {
+    query_price_by_mode(
+        deps,
+        oracle_addr,
+        base,
+        quote,
+        &PriceMode::Spot,
+        time_contraints,
+    )
+}
+
+/// Query the spot or TWAP price of the oracle
+pub fn query_price_by_mode(
+    deps: Deps,
+    oracle_addr: Addr,
+    base: String,
+    quote: String,
+    price_mode: &PriceMode,
+    time_contraints: Option<TimeConstraints>,
+) -> StdResult<PriceResponse> {
+    let msg = match price_mode {
+        PriceMode::Spot => OracleQueryMsg::Price { base, quote },
+        PriceMode::Twap { window } => OracleQueryMsg::TwapPrice {
+            base,
+            quote,
+            window: *window,
+        },
+    };
     let oracle_price: PriceResponse =
         deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
             contract_addr: oracle_addr.to_string(),
-            msg: to_binary(&OracleQueryMsg::Price { base, quote })?,
+            msg: to_binary(&msg)?,
         }))?;
 
     if let Some(time_contraints) = time_contraints {
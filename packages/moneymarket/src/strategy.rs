@@ -0,0 +1,28 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_bignumber::Uint256;
+
+/// Interface of the external yield strategies the market
+/// deploys part of its idle liquidity into
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Deposit the attached stable coins into the strategy
+    Deposit {},
+    /// Withdraw `amount` of the deposited stable coins to the sender
+    Withdraw { amount: Uint256 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Stable coins the depositor can withdraw, yield included
+    DepositorBalance { depositor: String },
+}
+
+// We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositorBalanceResponse {
+    pub balance: Uint256,
+}
@@ -1,3 +1,4 @@
+use crate::common::round_decimal;
 use crate::mock_querier::mock_dependencies;
 use crate::oracle::PriceResponse;
 use crate::querier::{compute_tax, deduct_tax, query_price, query_tax_rate, TimeConstraints};
@@ -5,6 +6,7 @@ use crate::tokens::{Tokens, TokensHuman, TokensMath, TokensToRaw};
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{Addr, Api, CanonicalAddr, Coin, Decimal, StdError, Uint128};
+use std::str::FromStr;
 
 #[test]
 fn tax_rate_querier() {
@@ -91,6 +93,7 @@ fn oracle_price_querier() {
             rate: Decimal256::from_ratio(131, 2),
             last_updated_base: 123,
             last_updated_quote: 321,
+            confidence_spread: None,
         }
     );
 
@@ -300,3 +303,25 @@ fn token_math_invalid_token_2() {
 
     let _ = tokens_1_raw.sub(tokens_2_raw);
 }
+
+#[test]
+fn decimal_rounding() {
+    let value = Decimal256::from_str("0.135714285714285714").unwrap();
+    assert_eq!(round_decimal(value, None), value);
+    assert_eq!(round_decimal(value, Some(18)), value);
+    assert_eq!(round_decimal(value, Some(24)), value);
+    assert_eq!(round_decimal(value, Some(0)), Decimal256::zero());
+    assert_eq!(round_decimal(value, Some(2)).to_string(), "0.14");
+    assert_eq!(round_decimal(value, Some(6)).to_string(), "0.135714");
+    assert_eq!(round_decimal(value, Some(9)).to_string(), "0.135714286");
+
+    // ties round to the even neighbour, in both directions
+    let tie = |value: &str, precision: u32| {
+        round_decimal(Decimal256::from_str(value).unwrap(), Some(precision)).to_string()
+    };
+    assert_eq!(tie("0.125", 2), "0.12");
+    assert_eq!(tie("0.135", 2), "0.14");
+    assert_eq!(tie("2.5", 0), "2");
+    assert_eq!(tie("3.5", 0), "4");
+    assert_eq!(tie("0.1250000001", 2), "0.13");
+}